@@ -2,7 +2,10 @@
 MEMORY
 {
   /* NOTE K = KiBi = 1024 bytes */
-  FLASH : ORIGIN = 0x00000000, LENGTH = 512K
+  /* The last 12K (0x7D000..0x80000) is reserved for data: the settings
+     page, loaded at boot and written by the console's SAVE, and the change
+     log (see src/flash.rs) */
+  FLASH : ORIGIN = 0x00000000, LENGTH = 500K
   RAM : ORIGIN = 0x20000000, LENGTH = 128K
 }
 
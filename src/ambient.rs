@@ -0,0 +1,163 @@
+//! # Ambient Light Module
+//!
+//! This module uses one LED of the 5x5 matrix as a light sensor and scales
+//! the output brightness so the calibration target looks consistent in a
+//! bright or a dark room.
+//!
+//! ## Measurement
+//!
+//! An LED is also a (poor) photodiode. Reverse-biasing the center matrix LED
+//! charges its junction capacitance; the column pin is then released and the
+//! photocurrent discharges it. The brighter the room, the sooner the column
+//! reads low:
+//!
+//! 1. **Charge**: Row (anode) low, column (cathode) high for [`AMBIENT_CHARGE_US`]
+//! 2. **Discharge**: Column switched to a floating input
+//! 3. **Time**: Wait for the column to read low, up to [`AMBIENT_DARK_US`]
+//!
+//! The LED is never forward-biased, so the measurement is invisible.
+//!
+//! ## Matrix Sharing
+//!
+//! The measurement borrows the matrix pins while holding [`MATRIX_LOCK`].
+//! Anything that drives the matrix must hold the same lock and reassert its
+//! pin configuration afterward; the pins are left disconnected.
+//!
+//! ## Brightness Mapping
+//!
+//! Decay times map linearly from [`AMBIENT_BRIGHT_US`] (full brightness) to
+//! [`AMBIENT_DARK_US`] ([`AMBIENT_MIN_SCALE`]). The published scale only moves
+//! when the target differs by more than [`AMBIENT_HYSTERESIS`], so a passing
+//! shadow or sensor noise doesn't make the output pump.
+use crate::*;
+
+/// Time the LED junction is reverse-charged before each measurement, in µs.
+pub const AMBIENT_CHARGE_US: u64 = 100;
+/// Decay time at or below which the room counts as fully bright, in µs.
+pub const AMBIENT_BRIGHT_US: u32 = 500;
+/// Decay time at or above which the room counts as fully dark, in µs.
+pub const AMBIENT_DARK_US: u32 = 20_000;
+/// Brightness scale applied in a fully dark room, in percent.
+pub const AMBIENT_MIN_SCALE: u32 = 20;
+/// Minimum change of the target scale that is passed through, in percent.
+pub const AMBIENT_HYSTERESIS: u32 = 5;
+/// Delay between ambient measurements, in milliseconds.
+pub const AMBIENT_PERIOD_MS: u64 = 500;
+
+/// Shared ambient-light state.
+#[derive(Debug, Clone, Copy)]
+pub struct Ambient {
+    /// Whether adaptive brightness is applied.
+    pub enabled: bool,
+    /// Most recent discharge time in µs (lower is brighter).
+    pub reading: u32,
+    /// Brightness scale applied by the RGB module, 0 to [`BRIGHTNESS_MAX`].
+    pub scale: u32,
+}
+
+/// Global ambient-light state, written by `ambient_task` and the UI toggle.
+pub static AMBIENT: Mutex<CriticalSectionRawMutex, Ambient> = Mutex::new(Ambient {
+    enabled: false,
+    reading: 0,
+    scale: BRIGHTNESS_MAX,
+});
+
+/// Retrieves the brightness scale currently requested by ambient light.
+///
+/// # Returns
+/// [`BRIGHTNESS_MAX`] while the feature is disabled.
+pub async fn get_ambient_scale() -> u32 {
+    let ambient = AMBIENT.lock().await;
+    ambient.scale
+}
+
+/// Enables or disables ambient-light adaptive brightness.
+///
+/// Disabling restores full scale immediately.
+pub async fn toggle_ambient() {
+    let mut ambient = AMBIENT.lock().await;
+    ambient.enabled = !ambient.enabled;
+    if !ambient.enabled {
+        ambient.scale = BRIGHTNESS_MAX;
+    }
+    rprintln!(
+        "Ambient brightness: {}",
+        if ambient.enabled { "on" } else { "off" }
+    );
+}
+
+/// Maps a discharge time to a target brightness scale.
+///
+/// # Arguments
+/// * `decay_us` - Measured discharge time in µs
+///
+/// # Returns
+/// Scale from [`AMBIENT_MIN_SCALE`] (dark) to [`BRIGHTNESS_MAX`] (bright).
+pub fn ambient_target(decay_us: u32) -> u32 {
+    let decay = decay_us.clamp(AMBIENT_BRIGHT_US, AMBIENT_DARK_US);
+    let span = AMBIENT_DARK_US - AMBIENT_BRIGHT_US;
+    let darkness = decay - AMBIENT_BRIGHT_US;
+    BRIGHTNESS_MAX - (BRIGHTNESS_MAX - AMBIENT_MIN_SCALE) * darkness / span
+}
+
+/// Applies hysteresis to a new target scale.
+///
+/// # Arguments
+/// * `current` - Currently published scale
+/// * `target` - Scale requested by the latest measurement
+///
+/// # Returns
+/// `target` if it differs from `current` by more than
+/// [`AMBIENT_HYSTERESIS`], otherwise `current`.
+pub fn ambient_hysteresis(current: u32, target: u32) -> u32 {
+    if current.abs_diff(target) > AMBIENT_HYSTERESIS {
+        target
+    } else {
+        current
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decay_maps_from_full_to_the_minimum_scale() {
+        for bright in [0, AMBIENT_BRIGHT_US] {
+            assert_eq!(ambient_target(bright), BRIGHTNESS_MAX);
+        }
+        for dark in [AMBIENT_DARK_US, AMBIENT_DARK_US + 1, u32::MAX] {
+            assert_eq!(ambient_target(dark), AMBIENT_MIN_SCALE);
+        }
+        // Halfway between the two is halfway down.
+        let middle = (AMBIENT_BRIGHT_US + AMBIENT_DARK_US) / 2;
+        assert_eq!(ambient_target(middle), 60);
+        let mut previous = BRIGHTNESS_MAX;
+        for decay in (0..=AMBIENT_DARK_US + 1000).step_by(250) {
+            let target = ambient_target(decay);
+            assert!(target <= previous, "{}", decay);
+            previous = target;
+        }
+    }
+
+    #[test]
+    fn hysteresis_holds_through_noise() {
+        assert_eq!(ambient_hysteresis(60, 65), 60);
+        assert_eq!(ambient_hysteresis(60, 55), 60);
+        assert_eq!(ambient_hysteresis(60, 66), 66);
+        assert_eq!(ambient_hysteresis(60, 54), 54);
+        // Readings jittering by a millisecond around the middle of the
+        // range never move the scale...
+        let mut scale = ambient_target(10_250);
+        for reading in [9_250, 11_250, 10_000, 10_500, 9_500, 11_000, 9_250] {
+            scale = ambient_hysteresis(scale, ambient_target(reading));
+            assert_eq!(scale, 60, "{}", reading);
+        }
+        // ...while a real change goes straight through, all the way to
+        // either end.
+        scale = ambient_hysteresis(scale, ambient_target(AMBIENT_BRIGHT_US));
+        assert_eq!(scale, BRIGHTNESS_MAX);
+        scale = ambient_hysteresis(scale, ambient_target(AMBIENT_DARK_US));
+        assert_eq!(scale, AMBIENT_MIN_SCALE);
+    }
+}
@@ -0,0 +1,95 @@
+//! # Bar Graph Module
+//!
+//! This module shows each channel's level on one of three 10-segment LED
+//! bar graphs, driven by four daisy-chained 74HC595 shift registers, as a
+//! readout that can be read from across the room.
+//!
+//! ## Wiring
+//!
+//! The registers are bit-banged on three edge pins (change them in
+//! `main`): P15 to SER of the first register, P13 to every SRCLK, and P14
+//! to every RCLK. Each register's QH' feeds the next one's SER. Red uses
+//! outputs 0–9 of the chain, green 10–19, and blue 20–29, counting from
+//! QA of the first register; the last two outputs stay unused.
+//!
+//! ## Display
+//!
+//! A level from 0 to `LEVELS - 1` lights a proportional number of
+//! segments, rounded, so 0 is dark and the top level lights all ten. In
+//! [`BarStyle::Dot`] only the top segment of that bar is lit.
+//!
+//! ## Rate Limit
+//!
+//! The task follows the [`events`](crate::events) bus, but shifts out at
+//! most one frame per [`BARGRAPH_MIN_INTERVAL`], always with the latest
+//! levels, so a fast knob sweep doesn't flood the pins.
+use crate::*;
+
+/// Segments per bar graph.
+pub const BAR_SEGMENTS: u32 = 10;
+/// Shortest time between two shifted-out frames.
+pub const BARGRAPH_MIN_INTERVAL: Duration = Duration::from_millis(50);
+
+/// How a level is drawn on a bar.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BarStyle {
+    /// Light every segment up to the level.
+    Bar,
+    /// Light only the segment at the level.
+    Dot,
+}
+
+/// Bar style, changed by the console.
+pub static BAR_STYLE: Mutex<CriticalSectionRawMutex, BarStyle> = Mutex::new(BarStyle::Bar);
+
+/// Converts a level to a bar pattern.
+///
+/// # Returns
+/// Segment bits, bit 0 the bottom segment.
+pub fn bar_pattern(level: u32, style: BarStyle) -> u16 {
+    let top = LEVELS - 1;
+    let lit = (level.min(top) * BAR_SEGMENTS + top / 2) / top;
+    match (style, lit) {
+        (_, 0) => 0,
+        (BarStyle::Bar, lit) => ((1u32 << lit) - 1) as u16,
+        (BarStyle::Dot, lit) => 1 << (lit - 1),
+    }
+}
+
+/// Builds the bytes to shift out for all three bars.
+///
+/// # Returns
+/// Bytes in shift order: the first byte ends up in the last register.
+pub fn bargraph_frame(levels: [u32; 3], style: BarStyle) -> [u8; 4] {
+    let bits = levels
+        .iter()
+        .enumerate()
+        .fold(0u32, |bits, (channel, &level)| {
+            bits | (bar_pattern(level, style) as u32) << (channel as u32 * BAR_SEGMENTS)
+        });
+    bits.to_be_bytes()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bar_patterns_round_to_the_segments() {
+        assert_eq!(bar_pattern(0, BarStyle::Bar), 0);
+        assert_eq!(bar_pattern(15, BarStyle::Bar), 0b11_1111_1111);
+        // Level 8 of 15 rounds to 5 of 10 segments.
+        assert_eq!(bar_pattern(8, BarStyle::Bar), 0b00_0001_1111);
+        assert_eq!(bar_pattern(8, BarStyle::Dot), 0b00_0001_0000);
+        assert_eq!(bar_pattern(0, BarStyle::Dot), 0);
+    }
+
+    #[test]
+    fn frame_packs_the_bars_low_bits_last() {
+        // Red full, green and blue dark: outputs 0-9 lit.
+        assert_eq!(
+            bargraph_frame([15, 0, 0], BarStyle::Bar),
+            [0x00, 0x00, 0x03, 0xFF]
+        );
+    }
+}
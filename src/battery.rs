@@ -0,0 +1,211 @@
+//! # Battery Warning Module
+//!
+//! This module watches the supply voltage on battery power and flashes a
+//! warning on the LED when it runs low, so the board gets recharged before
+//! it browns out.
+//!
+//! ## Measurement
+//!
+//! VDD is scanned as the last channel of the knob's SAADC conversion,
+//! through the internal VDD input at gain 1/6 against the 0.6V reference,
+//! so full scale is [`VDD_FULL_SCALE_MV`] whatever the supply (unlike the
+//! ratiometric knob, which can't see it). Every knob sample hands the
+//! reading to [`record_vdd_sample`], which smooths it
+//! ([`VDD_SMOOTHING`]) and updates a [`BatteryMonitor`].
+//!
+//! ## Warning
+//!
+//! The battery is low once the smoothed VDD drops below
+//! [`BatteryWarning::threshold_mv`], and stays low until it is back above
+//! the threshold by [`BatteryWarning::hysteresis_mv`], so a supply sitting
+//! on the threshold doesn't toggle the warning. While it is low, the RGB
+//! loop overlays the [`WarningBlink`] on its output (see
+//! [`BatteryWarning::overlay`]): a short pulse of the warning color every
+//! period, at the current master brightness, with the normal color in
+//! between. Changes of state are logged with the voltage.
+use crate::*;
+
+/// Supply voltage at a full-scale VDD reading, in millivolts.
+pub const VDD_FULL_SCALE_MV: u32 = 3600;
+/// Fraction of the VDD error corrected per reading.
+pub const VDD_SMOOTHING: f32 = 0.05;
+
+/// Warning pattern flashed over the output while the battery is low.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct WarningBlink {
+    /// Time from one pulse to the next.
+    pub period: Duration,
+    /// How long each pulse shows.
+    pub pulse: Duration,
+    /// RGB levels shown during a pulse.
+    pub levels: [u32; 3],
+}
+
+/// Low-battery detection and warning settings.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BatteryWarning {
+    /// VDD below which the battery is low, in millivolts.
+    pub threshold_mv: u32,
+    /// How far above the threshold VDD must recover to clear the warning,
+    /// in millivolts.
+    pub hysteresis_mv: u32,
+    /// Pattern shown while the battery is low.
+    pub blink: WarningBlink,
+}
+
+/// Low-battery settings: a 150 ms red pulse every four seconds below 2.4V.
+pub const BATTERY_WARNING: BatteryWarning = BatteryWarning {
+    threshold_mv: 2400,
+    hysteresis_mv: 100,
+    blink: WarningBlink {
+        period: Duration::from_secs(4),
+        pulse: Duration::from_millis(150),
+        levels: [LEVELS - 1, 0, 0],
+    },
+};
+
+impl BatteryWarning {
+    /// Applies the warning pattern to a frame's levels.
+    ///
+    /// # Arguments
+    /// * `now_ms` - Current time in milliseconds since boot
+    /// * `low` - Whether the battery is low
+    /// * `levels` - RGB levels the frame would show
+    ///
+    /// # Returns
+    /// The blink's levels during a pulse of a low battery, else `levels`.
+    pub fn overlay(&self, now_ms: u64, low: bool, levels: [u32; 3]) -> [u32; 3] {
+        let period_ms = self.blink.period.as_millis().max(1);
+        if low && now_ms % period_ms < self.blink.pulse.as_millis() {
+            self.blink.levels
+        } else {
+            levels
+        }
+    }
+}
+
+/// Converts a raw VDD channel reading to millivolts.
+pub fn vdd_millivolts(raw: i16) -> u32 {
+    raw.max(0) as u32 * VDD_FULL_SCALE_MV / (KNOB_RAW_MAX as u32 + 1)
+}
+
+/// Smoothed supply voltage and low-battery state with hysteresis.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct BatteryMonitor {
+    /// Smoothed VDD in millivolts, or `None` before the first reading.
+    vdd_mv: Option<f32>,
+    /// Whether the battery is low.
+    low: bool,
+}
+
+impl BatteryMonitor {
+    /// Starts with no reading and no warning.
+    pub const fn new() -> Self {
+        Self {
+            vdd_mv: None,
+            low: false,
+        }
+    }
+
+    /// Adds a VDD reading.
+    ///
+    /// # Arguments
+    /// * `mv` - VDD in millivolts
+    /// * `warning` - Threshold and hysteresis to apply
+    /// * `alpha` - Fraction of the error to correct (0.0 to 1.0); the first
+    ///   reading is taken as it is
+    ///
+    /// # Returns
+    /// Whether the battery is low now.
+    pub fn push(&mut self, mv: u32, warning: &BatteryWarning, alpha: f32) -> bool {
+        let mv = mv as f32;
+        let smoothed = match self.vdd_mv {
+            Some(current) => current + (mv - current) * alpha.clamp(0.0, 1.0),
+            None => mv,
+        };
+        self.vdd_mv = Some(smoothed);
+        let threshold = warning.threshold_mv as f32;
+        if self.low {
+            self.low = smoothed < threshold + warning.hysteresis_mv as f32;
+        } else {
+            self.low = smoothed < threshold;
+        }
+        self.low
+    }
+
+    /// Smoothed VDD in whole millivolts, or `None` before the first reading.
+    pub fn vdd_mv(&self) -> Option<u32> {
+        self.vdd_mv.map(|mv| mv as u32)
+    }
+
+    /// Whether the battery is low.
+    pub fn low(&self) -> bool {
+        self.low
+    }
+}
+
+/// Global supply monitor, fed by the knob's SAADC scans.
+pub static BATTERY: Mutex<CriticalSectionRawMutex, BatteryMonitor> =
+    Mutex::new(BatteryMonitor::new());
+
+/// Records a raw VDD reading, logging when the battery turns low or
+/// recovers.
+pub async fn record_vdd_sample(raw: i16) {
+    let mut monitor = BATTERY.lock().await;
+    let was_low = monitor.low();
+    let low = monitor.push(vdd_millivolts(raw), &BATTERY_WARNING, VDD_SMOOTHING);
+    if low != was_low {
+        rprintln!(
+            "battery: {} ({} mV)",
+            if low { "low, recharge" } else { "ok" },
+            monitor.vdd_mv().unwrap_or(0)
+        );
+    }
+}
+
+/// Returns whether the battery is low.
+pub async fn get_battery_low() -> bool {
+    BATTERY.lock().await.low()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn warning_blinks_only_while_low() {
+        let color = [3, 9, 12];
+        let red = BATTERY_WARNING.blink.levels;
+        // Pulses at the start of each period while low...
+        assert_eq!(BATTERY_WARNING.overlay(0, true, color), red);
+        assert_eq!(BATTERY_WARNING.overlay(100, true, color), red);
+        assert_eq!(BATTERY_WARNING.overlay(2000, true, color), color);
+        assert_eq!(BATTERY_WARNING.overlay(4050, true, color), red);
+        // ...and never otherwise.
+        assert_eq!(BATTERY_WARNING.overlay(0, false, color), color);
+    }
+
+    #[test]
+    fn vdd_scales_the_raw_reading() {
+        assert_eq!(vdd_millivolts(0), 0);
+        assert_eq!(vdd_millivolts(1 << 13), VDD_FULL_SCALE_MV / 2);
+        // Below-zero noise reads as 0V.
+        assert_eq!(vdd_millivolts(-4), 0);
+    }
+
+    #[test]
+    fn monitor_smooths_with_hysteresis() {
+        let warning = BATTERY_WARNING;
+        let mut monitor = BatteryMonitor::new();
+        // A healthy supply isn't low.
+        assert!(!monitor.push(3000, &warning, 1.0));
+        // Dropping below the threshold triggers the warning...
+        assert!(monitor.push(2350, &warning, 1.0));
+        // ...which holds until VDD is back above it by the hysteresis...
+        assert!(monitor.push(2450, &warning, 1.0));
+        assert!(!monitor.push(2500, &warning, 1.0));
+        // ...and the smoothing keeps a single dip from triggering it.
+        assert!(!monitor.push(2000, &warning, VDD_SMOOTHING));
+        assert_eq!(monitor.vdd_mv(), Some(2475));
+    }
+}
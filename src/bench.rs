@@ -0,0 +1,478 @@
+//! # PWM Timing Benchmark Module
+//!
+//! This module measures the software PWM's edge jitter on real hardware
+//! and grades it against fixed thresholds, as a regression gate for
+//! changes to the PWM scan.
+//!
+//! ## Wiring
+//!
+//! With the `bench` feature, the software PWM scan ([`Rgb::run`]) toggles
+//! a marker output (P13) at the start of every frame and at every LED pin change. Jumper
+//! P13 to P14: GPIOTE turns each marker edge on P14 into an event, and PPI
+//! routes it to a capture task of TIMER1, free-running at 1 MHz, so the
+//! edge is timestamped in hardware, independent of the embassy-time tick.
+//! The marker can also be watched on a scope.
+//!
+//! ## Script
+//!
+//! [`BenchRunner`] plays every [`BENCH_FRAME_RATES`] × [`BENCH_PATTERNS`]
+//! combination in turn: it sets the frame rate and levels, lets
+//! [`BENCH_SETTLE_FRAMES`] frames pass, then measures [`BENCH_FRAMES`]
+//! frames. Each edge's jitter is its captured time after the frame's start
+//! marker minus its intended deadline after the frame start, in
+//! microseconds; the frame start itself is taken on the embassy-time
+//! tick, so its rounding counts as jitter too.
+//!
+//! ## Report
+//!
+//! After each scenario one line gives the edge count and the min, mean,
+//! max and 99th percentile jitter ([`JitterStats`]), with PASS if every
+//! edge was captured and both the worst and the p99 magnitude are within
+//! [`BENCH_THRESHOLDS`]. The run ends with an overall PASS or FAIL:
+//!
+//! ```text
+//! bench:  60 fps [15, 15, 15]: 3000 edges, jitter min -2 / mean 9 / max 31 / p99 30 us: PASS
+//! bench: PASS (16/16 scenarios)
+//! ```
+//!
+//! Leave the controls alone while it runs: knob, console or idle changes
+//! to the levels or frame rate would mix into the scenario being measured.
+use crate::*;
+
+/// Frame rates the benchmark runs at.
+pub const BENCH_FRAME_RATES: [u64; 4] = [30, 60, 100, 160];
+/// Level patterns the benchmark runs at each frame rate: all full, one
+/// channel alone, uneven levels, and two channels around a dark one.
+pub const BENCH_PATTERNS: [[u32; 3]; 4] = [
+    [LEVELS - 1, LEVELS - 1, LEVELS - 1],
+    [8, 0, 0],
+    [1, 7, 14],
+    [LEVELS - 1, 0, LEVELS - 1],
+];
+/// Number of scenarios in a benchmark run.
+pub const BENCH_SCENARIOS: usize = BENCH_FRAME_RATES.len() * BENCH_PATTERNS.len();
+/// Frames to let pass after changing the scenario, before measuring.
+pub const BENCH_SETTLE_FRAMES: u32 = 10;
+/// Frames measured per scenario.
+pub const BENCH_FRAMES: u32 = 500;
+/// Histogram buckets of [`JitterStats`], 1 µs each; the last one also
+/// counts everything beyond it.
+pub const JITTER_BUCKETS: usize = 128;
+
+/// Pass limits for a scenario's jitter magnitude, in microseconds.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BenchThresholds {
+    /// Largest allowed jitter of any edge.
+    pub max_us: u32,
+    /// Largest allowed 99th percentile jitter.
+    pub p99_us: u32,
+}
+
+/// Thresholds used by the benchmark. The embassy-time tick is about 30 µs
+/// on this board, so frame-start rounding alone approaches that.
+pub const BENCH_THRESHOLDS: BenchThresholds = BenchThresholds {
+    max_us: 100,
+    p99_us: 50,
+};
+
+/// One frame rate and level pattern to measure.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BenchScenario {
+    /// Frame rate in frames per second.
+    pub frame_rate: u64,
+    /// RGB levels.
+    pub levels: [u32; 3],
+}
+
+/// Looks up a scenario, frame rate by frame rate.
+///
+/// # Returns
+/// The scenario, or `None` past the last one.
+pub fn bench_scenario(index: usize) -> Option<BenchScenario> {
+    let frame_rate = *BENCH_FRAME_RATES.get(index / BENCH_PATTERNS.len())?;
+    Some(BenchScenario {
+        frame_rate,
+        levels: BENCH_PATTERNS[index % BENCH_PATTERNS.len()],
+    })
+}
+
+/// Edge jitter statistics of one scenario.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct JitterStats {
+    /// Edges measured.
+    pub count: u32,
+    /// Edges whose capture never arrived.
+    pub missed: u32,
+    min: i32,
+    max: i32,
+    sum: i64,
+    /// Edges by jitter magnitude in µs.
+    histogram: [u32; JITTER_BUCKETS],
+}
+
+impl JitterStats {
+    /// Creates empty statistics.
+    pub const fn new() -> Self {
+        Self {
+            count: 0,
+            missed: 0,
+            min: i32::MAX,
+            max: i32::MIN,
+            sum: 0,
+            histogram: [0; JITTER_BUCKETS],
+        }
+    }
+    /// Adds one edge's jitter, positive when late.
+    pub fn push(&mut self, delta_us: i32) {
+        self.count = self.count.saturating_add(1);
+        self.min = self.min.min(delta_us);
+        self.max = self.max.max(delta_us);
+        self.sum = self.sum.saturating_add(delta_us as i64);
+        let bucket = (delta_us.unsigned_abs() as usize).min(JITTER_BUCKETS - 1);
+        self.histogram[bucket] = self.histogram[bucket].saturating_add(1);
+    }
+    /// Counts an edge whose capture never arrived.
+    pub fn miss(&mut self) {
+        self.missed = self.missed.saturating_add(1);
+    }
+    /// Earliest edge, in µs; 0 if there were none.
+    pub fn min(&self) -> i32 {
+        if self.count == 0 {
+            0
+        } else {
+            self.min
+        }
+    }
+    /// Latest edge, in µs; 0 if there were none.
+    pub fn max(&self) -> i32 {
+        if self.count == 0 {
+            0
+        } else {
+            self.max
+        }
+    }
+    /// Mean jitter, in µs; 0 if there were none.
+    pub fn mean(&self) -> i32 {
+        if self.count == 0 {
+            0
+        } else {
+            (self.sum / self.count as i64) as i32
+        }
+    }
+    /// Largest jitter magnitude, in µs.
+    pub fn max_abs(&self) -> u32 {
+        self.min().unsigned_abs().max(self.max().unsigned_abs())
+    }
+    /// 99th percentile jitter magnitude, in µs, capped at the last
+    /// histogram bucket.
+    pub fn p99(&self) -> u32 {
+        let rank = (self.count as u64 * 99).div_ceil(100);
+        let mut seen = 0;
+        for (us, &edges) in self.histogram.iter().enumerate() {
+            seen += edges as u64;
+            if seen >= rank && seen > 0 {
+                return us as u32;
+            }
+        }
+        0
+    }
+    /// Whether every edge was captured and the jitter is within
+    /// `thresholds`.
+    pub fn passes(&self, thresholds: &BenchThresholds) -> bool {
+        self.count > 0
+            && self.missed == 0
+            && self.max_abs() <= thresholds.max_us
+            && self.p99() <= thresholds.p99_us
+    }
+}
+
+impl Default for JitterStats {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Result of one scenario.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ScenarioReport {
+    /// Scenario measured.
+    pub scenario: BenchScenario,
+    /// Its jitter.
+    pub stats: JitterStats,
+    /// Limits it was graded against.
+    pub thresholds: BenchThresholds,
+}
+
+impl ScenarioReport {
+    /// Whether the scenario passed.
+    pub fn passed(&self) -> bool {
+        self.stats.passes(&self.thresholds)
+    }
+}
+
+impl core::fmt::Display for ScenarioReport {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        let stats = &self.stats;
+        write!(
+            f,
+            "{:>3} fps {:?}: {} edges, jitter min {} / mean {} / max {} / p99 {} us",
+            self.scenario.frame_rate,
+            self.scenario.levels,
+            stats.count,
+            stats.min(),
+            stats.mean(),
+            stats.max(),
+            stats.p99(),
+        )?;
+        if stats.missed > 0 {
+            write!(f, ", {} missed", stats.missed)?;
+        }
+        write!(f, ": {}", if self.passed() { "PASS" } else { "FAIL" })
+    }
+}
+
+/// Outcome of a whole benchmark run.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BenchVerdict {
+    /// Scenarios that passed.
+    pub passed: u32,
+    /// Scenarios that failed.
+    pub failed: u32,
+}
+
+impl BenchVerdict {
+    /// Whether every scenario passed.
+    pub fn passed(&self) -> bool {
+        self.failed == 0
+    }
+}
+
+impl core::fmt::Display for BenchVerdict {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        let total = self.passed + self.failed;
+        if self.passed() {
+            write!(f, "PASS ({}/{} scenarios)", self.passed, total)
+        } else {
+            write!(f, "FAIL ({} of {} scenarios failed)", self.failed, total)
+        }
+    }
+}
+
+/// What the benchmark does at the start of a frame.
+// A report is returned once per scenario, so its size costs little.
+#[allow(clippy::large_enum_variant)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BenchStep {
+    /// Switch to this scenario's frame rate and levels.
+    Start(BenchScenario),
+    /// Nothing new; keep playing.
+    Continue,
+    /// A scenario ended with this report.
+    Finished(ScenarioReport),
+    /// The run ended with this verdict. Only returned once.
+    Done(BenchVerdict),
+}
+
+/// The benchmark script, advanced once per frame.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BenchRunner {
+    thresholds: BenchThresholds,
+    /// Scenario being played.
+    index: usize,
+    /// Frames played in this scenario, including the current one.
+    frame: u32,
+    stats: JitterStats,
+    verdict: BenchVerdict,
+    done: bool,
+}
+
+impl BenchRunner {
+    /// Creates a runner at the start of the script.
+    pub const fn new(thresholds: BenchThresholds) -> Self {
+        Self {
+            thresholds,
+            index: 0,
+            frame: 0,
+            stats: JitterStats::new(),
+            verdict: BenchVerdict {
+                passed: 0,
+                failed: 0,
+            },
+            done: false,
+        }
+    }
+    /// The scenario being played, if the run hasn't ended.
+    pub fn scenario(&self) -> Option<BenchScenario> {
+        bench_scenario(self.index)
+    }
+    /// Whether edges of the current frame are measured (not while
+    /// settling, and not after the run).
+    pub fn measuring(&self) -> bool {
+        self.scenario().is_some() && self.frame > BENCH_SETTLE_FRAMES
+    }
+    /// Records one edge's jitter, or `None` for a missed capture; ignored
+    /// while not [measuring](BenchRunner::measuring).
+    pub fn record(&mut self, delta_us: Option<i32>) {
+        if !self.measuring() {
+            return;
+        }
+        match delta_us {
+            Some(delta_us) => self.stats.push(delta_us),
+            None => self.stats.miss(),
+        }
+    }
+    /// Advances the script by one frame.
+    ///
+    /// # Returns
+    /// What to do before playing the frame.
+    pub fn frame(&mut self) -> BenchStep {
+        let Some(scenario) = self.scenario() else {
+            if self.done {
+                return BenchStep::Continue;
+            }
+            self.done = true;
+            return BenchStep::Done(self.verdict);
+        };
+        if self.frame == 0 {
+            self.frame = 1;
+            return BenchStep::Start(scenario);
+        }
+        if self.frame < BENCH_SETTLE_FRAMES + BENCH_FRAMES {
+            self.frame += 1;
+            return BenchStep::Continue;
+        }
+        let report = ScenarioReport {
+            scenario,
+            stats: self.stats,
+            thresholds: self.thresholds,
+        };
+        if report.passed() {
+            self.verdict.passed += 1;
+        } else {
+            self.verdict.failed += 1;
+        }
+        self.stats = JitterStats::new();
+        self.index += 1;
+        self.frame = 0;
+        BenchStep::Finished(report)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scenarios_step_through_the_patterns_at_each_rate() {
+        assert_eq!(
+            bench_scenario(0),
+            Some(BenchScenario {
+                frame_rate: 30,
+                levels: BENCH_PATTERNS[0]
+            }),
+        );
+        assert_eq!(bench_scenario(5).unwrap().frame_rate, 60);
+        assert_eq!(bench_scenario(5).unwrap().levels, BENCH_PATTERNS[1]);
+        assert_eq!(bench_scenario(BENCH_SCENARIOS), None);
+    }
+
+    #[test]
+    fn jitter_stats_and_thresholds() {
+        let mut stats = JitterStats::new();
+        for delta in [-3, 4, 5, 6] {
+            stats.push(delta);
+        }
+        assert_eq!((stats.min(), stats.mean(), stats.max()), (-3, 3, 6));
+        assert_eq!(stats.p99(), 6);
+
+        // The p99 ignores the worst 1%, the max doesn't.
+        let mut stats = JitterStats::new();
+        for _ in 0..99 {
+            stats.push(10);
+        }
+        stats.push(-90);
+        assert_eq!((stats.p99(), stats.max_abs()), (10, 90));
+        let thresholds = BenchThresholds {
+            max_us: 100,
+            p99_us: 20,
+        };
+        assert!(stats.passes(&thresholds));
+        assert!(!stats.passes(&BenchThresholds {
+            max_us: 50,
+            ..thresholds
+        }));
+
+        // Jitter past the histogram lands in the last bucket.
+        stats.push(1_000_000);
+        stats.push(1_000_000);
+        assert_eq!(stats.p99(), JITTER_BUCKETS as u32 - 1);
+
+        // No edges, or a missed capture, is a failure.
+        assert!(!JitterStats::new().passes(&thresholds));
+        let mut missed = JitterStats::new();
+        missed.push(0);
+        missed.miss();
+        assert!(!missed.passes(&thresholds));
+    }
+
+    #[test]
+    fn runner_measures_every_scenario_and_gives_a_verdict() {
+        let thresholds = BenchThresholds {
+            max_us: 40,
+            p99_us: 20,
+        };
+        let mut runner = BenchRunner::new(thresholds);
+        let (mut started, mut reports, mut measured) = (0, 0, 0);
+        let verdict = loop {
+            match runner.frame() {
+                BenchStep::Start(_) => started += 1,
+                BenchStep::Finished(report) => {
+                    reports += 1;
+                    assert!(report.passed());
+                    assert_eq!(report.stats.count, BENCH_FRAMES * 4);
+                }
+                BenchStep::Done(verdict) => break verdict,
+                BenchStep::Continue => {}
+            }
+            if runner.measuring() {
+                measured += 1;
+                for delta in [3, -2, 10, 5] {
+                    runner.record(Some(delta));
+                }
+            }
+        };
+        assert_eq!((started, reports), (BENCH_SCENARIOS, BENCH_SCENARIOS));
+        assert_eq!(measured, BENCH_SCENARIOS as u32 * BENCH_FRAMES);
+        assert_eq!(
+            verdict,
+            BenchVerdict {
+                passed: 16,
+                failed: 0
+            }
+        );
+        // After the verdict the runner stays quiet.
+        assert_eq!(runner.frame(), BenchStep::Continue);
+        assert!(!runner.measuring());
+
+        // One late edge fails its scenario, and with it the run.
+        let mut runner = BenchRunner::new(thresholds);
+        let verdict = loop {
+            if let BenchStep::Done(verdict) = runner.frame() {
+                break verdict;
+            }
+            if runner.measuring() {
+                runner.record(Some(0));
+            }
+            if runner.scenario() == Some(bench_scenario(3).unwrap()) && runner.measuring() {
+                runner.record(Some(41));
+            }
+        };
+        assert_eq!(
+            verdict,
+            BenchVerdict {
+                passed: 15,
+                failed: 1
+            }
+        );
+    }
+}
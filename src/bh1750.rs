@@ -0,0 +1,180 @@
+//! # BH1750 Light Sensor Module
+//!
+//! This module reads a BH1750 lux sensor on the edge-connector I2C bus and
+//! scales the output brightness to the room, a more accurate alternative to
+//! the matrix-LED sensing of the `ambient` feature. (The two scales
+//! multiply, so enable only one of them.)
+//!
+//! ## Measurement
+//!
+//! Each sample runs [`BH1750_ONE_SHOT`]: power on, start a one-time
+//! high-resolution measurement, wait out its 180 ms maximum, and read the
+//! 16-bit result, which the datasheet converts as `lux = raw / 1.2`. The
+//! sensor powers down again by itself after one-time measurements.
+//!
+//! ## Compensation
+//!
+//! [`lux_target`] maps lux to a brightness scale on a log curve: at or
+//! below [`LUX_DARK`] the scale is the configured minimum, at or above
+//! [`LUX_BRIGHT`] the maximum. The published scale then follows the target
+//! through a first-order filter with a configurable time constant
+//! ([`lux_smooth`]), so a passing shadow barely moves the output.
+//!
+//! ## Missing Sensor
+//!
+//! If the sensor doesn't answer, a warning is logged once, the scale
+//! returns to full, and sampling keeps retrying; recovery is logged too.
+use crate::*;
+
+/// I2C address of the BH1750 with ADDR low.
+pub const BH1750_ADDR: u8 = 0x23;
+/// Opcode: power on.
+const OP_POWER_ON: u8 = 0x01;
+/// Opcode: one-time high-resolution measurement (1 lx resolution).
+const OP_ONE_TIME_H: u8 = 0x20;
+/// Maximum high-resolution measurement time, in milliseconds.
+const BH1750_MEASURE_MS: u64 = 180;
+/// Illuminance at or below which the minimum scale applies.
+pub const LUX_DARK: f32 = 1.0;
+/// Illuminance at or above which the maximum scale applies.
+pub const LUX_BRIGHT: f32 = 1000.0;
+/// Longest allowed time constant, in seconds.
+pub const LUX_TAU_MAX: u32 = 600;
+
+/// One step of a BH1750 transaction sequence.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Bh1750Step {
+    /// Write a one-byte opcode.
+    Write(u8),
+    /// Wait this many milliseconds.
+    Wait(u64),
+    /// Read the two-byte, big-endian result.
+    Read,
+}
+
+/// The one-shot high-resolution measurement sequence.
+pub const BH1750_ONE_SHOT: [Bh1750Step; 4] = [
+    Bh1750Step::Write(OP_POWER_ON),
+    Bh1750Step::Write(OP_ONE_TIME_H),
+    Bh1750Step::Wait(BH1750_MEASURE_MS),
+    Bh1750Step::Read,
+];
+
+/// Converts a high-resolution reading to lux.
+///
+/// # Arguments
+/// * `data` - The two result bytes as read, high byte first
+pub fn bh1750_lux(data: [u8; 2]) -> f32 {
+    u16::from_be_bytes(data) as f32 / 1.2
+}
+
+/// Maps illuminance to a target brightness scale.
+///
+/// # Arguments
+/// * `lux` - Illuminance in lux
+/// * `min` - Scale at or below [`LUX_DARK`], in percent
+/// * `max` - Scale at or above [`LUX_BRIGHT`], in percent
+///
+/// # Returns
+/// The scale, logarithmic in lux between the two ends.
+pub fn lux_target(lux: f32, min: u32, max: u32) -> f32 {
+    let span = num_traits::Float::log10(LUX_BRIGHT / LUX_DARK);
+    let position = num_traits::Float::log10(lux.max(LUX_DARK) / LUX_DARK) / span;
+    min as f32 + (max as f32 - min as f32) * position.min(1.0)
+}
+
+/// Moves the scale toward the target with a first-order filter.
+///
+/// # Arguments
+/// * `current` - Current scale
+/// * `target` - Target scale from [`lux_target`]
+/// * `dt_s` - Time since the last update, in seconds
+/// * `tau_s` - Time constant in seconds; 0 jumps straight to the target
+pub fn lux_smooth(current: f32, target: f32, dt_s: f32, tau_s: f32) -> f32 {
+    current + (target - current) * dt_s / (tau_s + dt_s)
+}
+
+/// Shared lux compensation state.
+#[derive(Debug, Clone, Copy)]
+pub struct Lux {
+    /// Whether the scale follows the sensor.
+    pub enabled: bool,
+    /// Latest reading, or `None` while the sensor is missing.
+    pub lux: Option<f32>,
+    /// Brightness scale applied to the output, 0 to [`BRIGHTNESS_MAX`].
+    pub scale: u32,
+    /// Scale in the dark, in percent.
+    pub min: u32,
+    /// Scale in bright light, in percent.
+    pub max: u32,
+    /// Filter time constant, in seconds.
+    pub tau_s: u32,
+}
+
+/// Global lux compensation state, written by `bh1750_task` and the console.
+pub static LUX: Mutex<CriticalSectionRawMutex, Lux> = Mutex::new(Lux {
+    enabled: true,
+    lux: None,
+    scale: BRIGHTNESS_MAX,
+    min: 20,
+    max: BRIGHTNESS_MAX,
+    tau_s: 5,
+});
+
+/// Retrieves the brightness scale requested by the lux sensor.
+///
+/// # Returns
+/// [`BRIGHTNESS_MAX`] while disabled or without a sensor.
+pub async fn get_lux_scale() -> u32 {
+    LUX.lock().await.scale
+}
+
+/// Prints the latest reading and compensation settings.
+pub async fn report_lux() {
+    let lux = *LUX.lock().await;
+    match lux.lux {
+        Some(reading) => rprintln!("lux: {:.1} lx, scale {}%", reading, lux.scale),
+        None => rprintln!("lux: no sensor, scale {}%", lux.scale),
+    }
+    rprintln!(
+        "lux: {}, min {}%, max {}%, tau {} s",
+        if lux.enabled { "on" } else { "off" },
+        lux.min,
+        lux.max,
+        lux.tau_s
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn one_shot_follows_the_datasheet() {
+        // Datasheet: power on (0x01), one-time H-resolution (0x20), wait, read.
+        assert_eq!(BH1750_ONE_SHOT[0], Bh1750Step::Write(0x01));
+        assert_eq!(BH1750_ONE_SHOT[1], Bh1750Step::Write(0x20));
+        assert_eq!(BH1750_ONE_SHOT[3], Bh1750Step::Read);
+    }
+
+    #[test]
+    fn raw_data_converts_to_lux() {
+        // Datasheet example: 0x83 0x90 is 28067 lx.
+        assert_eq!(bh1750_lux([0x83, 0x90]).round() as u32, 28067);
+    }
+
+    #[test]
+    fn lux_maps_to_the_scale_in_log_terms() {
+        assert_eq!(lux_target(0.0, 20, 100), 20.0);
+        assert_eq!(lux_target(5000.0, 20, 100), 100.0);
+        // 31.6 lx is half-way between 1 and 1000 lx in log terms.
+        assert!((lux_target(31.6, 20, 100) - 60.0).abs() < 0.1);
+    }
+
+    #[test]
+    fn smoothing_closes_the_gap_by_tau() {
+        // With an update interval equal to tau, half the gap closes per step.
+        assert_eq!(lux_smooth(100.0, 50.0, 5.0, 5.0), 75.0);
+        assert_eq!(lux_smooth(100.0, 50.0, 1.0, 0.0), 50.0);
+    }
+}
@@ -0,0 +1,120 @@
+//! # Board Modules
+//!
+//! The hardware half of each library module that needs it: peripheral
+//! drivers, interrupt-driven inputs and the Embassy tasks, with the pure
+//! logic they call kept in the library.
+
+#[cfg(feature = "ambient")]
+mod ambient;
+#[cfg(feature = "bargraph")]
+mod bargraph;
+#[cfg(feature = "bench")]
+mod bench;
+#[cfg(feature = "bh1750")]
+mod bh1750;
+#[cfg(feature = "camsync")]
+mod camsync;
+#[cfg(feature = "changelog")]
+mod changelog;
+#[cfg(feature = "compass")]
+mod compass;
+mod console;
+#[cfg(feature = "dmx")]
+mod dmx;
+mod flash;
+#[cfg(feature = "idle")]
+mod idle;
+#[cfg(feature = "ir")]
+mod ir;
+mod knob;
+mod logging;
+#[cfg(feature = "radio")]
+mod matrix;
+#[cfg(feature = "motion")]
+mod motion;
+#[cfg(feature = "oled")]
+mod oled;
+#[cfg(feature = "pca9685")]
+mod pca9685;
+#[cfg(feature = "protocol")]
+mod protocol;
+#[cfg(feature = "radio")]
+mod radio;
+#[cfg(feature = "remote")]
+mod remote;
+mod reset;
+mod rgb;
+#[cfg(feature = "schedule")]
+mod schedule;
+mod selftest;
+#[cfg(feature = "sleep")]
+mod sleep;
+mod stats;
+mod storage;
+#[cfg(feature = "telemetry")]
+mod telemetry;
+#[cfg(feature = "temperature")]
+mod temperature;
+#[cfg(feature = "testpattern")]
+mod testpattern;
+#[cfg(feature = "tone")]
+mod tone;
+mod touch;
+mod ui;
+#[cfg(feature = "ambient")]
+pub use ambient::*;
+#[cfg(feature = "bargraph")]
+pub use bargraph::*;
+#[cfg(feature = "bench")]
+pub use bench::*;
+#[cfg(feature = "bh1750")]
+pub use bh1750::*;
+#[cfg(feature = "camsync")]
+pub use camsync::*;
+#[cfg(feature = "changelog")]
+pub use changelog::*;
+#[cfg(feature = "compass")]
+pub use compass::*;
+pub use console::*;
+#[cfg(feature = "dmx")]
+pub use dmx::*;
+pub use flash::*;
+#[cfg(feature = "idle")]
+pub use idle::*;
+#[cfg(feature = "ir")]
+pub use ir::*;
+pub use knob::*;
+pub use logging::*;
+#[cfg(feature = "radio")]
+pub use matrix::*;
+#[cfg(feature = "motion")]
+pub use motion::*;
+#[cfg(feature = "oled")]
+pub use oled::*;
+#[cfg(feature = "pca9685")]
+pub use pca9685::*;
+#[cfg(feature = "protocol")]
+pub use protocol::*;
+#[cfg(feature = "radio")]
+pub use radio::*;
+#[cfg(feature = "remote")]
+pub use remote::*;
+pub use reset::*;
+pub use rgb::*;
+#[cfg(feature = "schedule")]
+pub use schedule::*;
+pub use selftest::*;
+#[cfg(feature = "sleep")]
+pub use sleep::*;
+pub use stats::*;
+pub use storage::*;
+#[cfg(feature = "telemetry")]
+pub use telemetry::*;
+#[cfg(feature = "temperature")]
+pub use temperature::*;
+#[cfg(feature = "testpattern")]
+pub use testpattern::*;
+#[cfg(feature = "tone")]
+pub use tone::*;
+pub use touch::*;
+pub use ui::*;
@@ -0,0 +1,57 @@
+//! # Ambient Light Sensing
+//!
+//! Measures the room light with the matrix LED for the library's adaptive
+//! brightness (see its `ambient` module).
+use crate::*;
+use microbit_bsp::embassy_nrf::{
+    gpio::{Flex, Pull},
+    peripherals,
+};
+
+/// Measures how long the reverse-charged center matrix LED takes to discharge.
+///
+/// # Returns
+/// Discharge time in µs, capped at [`AMBIENT_DARK_US`].
+async fn measure_decay() -> u32 {
+    let _matrix = MATRIX_LOCK.lock().await;
+    // SAFETY: the matrix pins are only driven while holding MATRIX_LOCK, so
+    // nothing else touches them for the duration of the measurement.
+    let mut row = Flex::new(unsafe { peripherals::P0_15::steal() });
+    let mut col = Flex::new(unsafe { peripherals::P0_31::steal() });
+    row.set_low();
+    row.set_as_output(OutputDrive::Standard);
+    col.set_high();
+    col.set_as_output(OutputDrive::Standard);
+    Timer::after_micros(AMBIENT_CHARGE_US).await;
+
+    col.set_as_input(Pull::None);
+    let start = Instant::now();
+    let limit = Duration::from_micros(AMBIENT_DARK_US as u64);
+    let _ = embassy_time::with_timeout(limit, col.wait_for_low()).await;
+    start.elapsed().as_micros().min(AMBIENT_DARK_US as u64) as u32
+}
+
+/// Periodically measures ambient light and publishes the brightness scale.
+///
+/// Measurements are only taken while the feature is enabled. Scale changes
+/// are logged together with the raw reading.
+#[embassy_executor::task]
+pub async fn ambient_task() -> ! {
+    loop {
+        Timer::after_millis(AMBIENT_PERIOD_MS).await;
+        if !AMBIENT.lock().await.enabled {
+            continue;
+        }
+        let reading = measure_decay().await;
+        let mut ambient = AMBIENT.lock().await;
+        if !ambient.enabled {
+            continue;
+        }
+        ambient.reading = reading;
+        let scale = ambient_hysteresis(ambient.scale, ambient_target(reading));
+        if scale != ambient.scale {
+            ambient.scale = scale;
+            rprintln!("ambient: {} us, scale {}%", reading, scale);
+        }
+    }
+}
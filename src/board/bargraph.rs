@@ -0,0 +1,73 @@
+//! # Bar Graph Output
+//!
+//! Shifts the library's bar graph frames (see its `bargraph` module) out
+//! to the 74HC595 chain.
+use crate::*;
+use embassy_sync::pubsub::WaitResult;
+
+/// Refresh period when no levels change, picking up style changes.
+const BARGRAPH_REFRESH: Duration = Duration::from_secs(1);
+
+/// Bit-banged pins of a 74HC595 chain.
+pub struct ShiftRegister {
+    /// Serial data, to SER of the first register.
+    data: Output<'static, AnyPin>,
+    /// Shift clock, to every SRCLK.
+    clock: Output<'static, AnyPin>,
+    /// Storage (latch) clock, to every RCLK.
+    latch: Output<'static, AnyPin>,
+}
+
+impl ShiftRegister {
+    /// Creates the driver with all pins low.
+    pub fn new(data: AnyPin, clock: AnyPin, latch: AnyPin) -> Self {
+        let pin = |p| Output::new(p, Level::Low, OutputDrive::Standard);
+        Self {
+            data: pin(data),
+            clock: pin(clock),
+            latch: pin(latch),
+        }
+    }
+
+    /// Shifts out bytes, most significant bit first, then latches them
+    /// onto the outputs together.
+    pub fn write(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            for bit in (0..8).rev() {
+                self.data.set_level((byte >> bit & 1 == 1).into());
+                self.clock.set_high();
+                self.clock.set_low();
+            }
+        }
+        self.latch.set_high();
+        self.latch.set_low();
+    }
+}
+
+/// Shows the levels on the bar graphs.
+///
+/// # Arguments
+/// * `bars` - Shift register chain driving the bars
+#[embassy_executor::task]
+pub async fn bargraph_task(mut bars: ShiftRegister) -> ! {
+    let mut events = subscribe_state();
+    let mut shown = None;
+    loop {
+        let frame = bargraph_frame(get_rgb_levels().await, *BAR_STYLE.lock().await);
+        if shown != Some(frame) {
+            bars.write(&frame);
+            shown = Some(frame);
+        }
+        Timer::after(BARGRAPH_MIN_INTERVAL).await;
+        // Wait for a level change; anything queued during the pause is
+        // covered by re-reading the levels above.
+        loop {
+            match embassy_time::with_timeout(BARGRAPH_REFRESH, events.next_message()).await {
+                Ok(WaitResult::Message(
+                    StateChanged::FrameRate(_) | StateChanged::Brightness(_),
+                )) => continue,
+                _ => break,
+            }
+        }
+    }
+}
@@ -0,0 +1,144 @@
+//! # Bench Probe
+//!
+//! Marks and captures PWM edges for the library's benchmark (see its
+//! `bench` module) with GPIOTE, PPI and TIMER1.
+use crate::*;
+use microbit_bsp::embassy_nrf::{gpio::Pin, pac};
+
+/// GPIOTE channel watching the capture input.
+const BENCH_GPIOTE_CHANNEL: usize = 7;
+/// PPI channel from the GPIOTE event to the TIMER1 capture.
+const BENCH_PPI_CHANNEL: usize = 19;
+/// Polls to wait for a marker edge's capture before counting it missed.
+const BENCH_CAPTURE_SPINS: u32 = 1_000;
+
+/// Marker output and hardware capture of its edges, driving a
+/// [`BenchRunner`].
+pub struct BenchProbe {
+    marker: Output<'static, AnyPin>,
+    _capture: AnyPin,
+    runner: BenchRunner,
+    /// Capture of the current frame's start marker, in µs.
+    frame_start_us: Option<u32>,
+}
+
+impl BenchProbe {
+    /// Returns the TIMER1 register block.
+    fn timer(&self) -> &pac::timer0::RegisterBlock {
+        // SAFETY: TIMER1 is used by nothing else in this firmware.
+        unsafe { &*pac::TIMER1::ptr() }
+    }
+
+    /// Returns the GPIOTE register block.
+    fn gpiote(&self) -> &pac::gpiote::RegisterBlock {
+        // SAFETY: only the benchmark's channel is touched, and the embassy
+        // GPIOTE driver leaves channels it hasn't handed out alone.
+        unsafe { &*pac::GPIOTE::ptr() }
+    }
+
+    /// Sets up the marker output and the GPIOTE → PPI → TIMER1 capture.
+    ///
+    /// # Arguments
+    /// * `marker` - Output toggled at every edge
+    /// * `capture` - Input jumpered to `marker`
+    pub fn new(marker: AnyPin, capture: AnyPin) -> Self {
+        let pin_port = capture.pin_port();
+        let this = Self {
+            marker: Output::new(marker, Level::Low, OutputDrive::Standard),
+            _capture: capture,
+            runner: BenchRunner::new(BENCH_THRESHOLDS),
+            frame_start_us: None,
+        };
+        let t = this.timer();
+        t.mode.write(|w| w.mode().timer());
+        t.bitmode.write(|w| w.bitmode()._32bit());
+        // 16 MHz / 2^4 = 1 MHz.
+        t.prescaler.write(|w| unsafe { w.prescaler().bits(4) });
+        t.tasks_clear.write(|w| unsafe { w.bits(1) });
+        t.tasks_start.write(|w| unsafe { w.bits(1) });
+        let g = this.gpiote();
+        g.config[BENCH_GPIOTE_CHANNEL].write(|w| unsafe {
+            w.mode()
+                .event()
+                .psel()
+                .bits(pin_port & 0x1F)
+                .port()
+                .bit(pin_port >= 32)
+                .polarity()
+                .toggle()
+        });
+        // SAFETY: the PPI channel is used by nothing else in this firmware.
+        let ppi = unsafe { &*pac::PPI::ptr() };
+        let event = &g.events_in[BENCH_GPIOTE_CHANNEL] as *const _ as u32;
+        let task = &t.tasks_capture[0] as *const _ as u32;
+        ppi.ch[BENCH_PPI_CHANNEL]
+            .eep
+            .write(|w| unsafe { w.bits(event) });
+        ppi.ch[BENCH_PPI_CHANNEL]
+            .tep
+            .write(|w| unsafe { w.bits(task) });
+        ppi.chenset
+            .write(|w| unsafe { w.bits(1 << BENCH_PPI_CHANNEL) });
+        this
+    }
+
+    /// Toggles the marker and waits for its capture.
+    ///
+    /// # Returns
+    /// The captured time in µs, or `None` if no edge arrived (jumper
+    /// missing).
+    fn mark(&mut self) -> Option<u32> {
+        let g = self.gpiote();
+        g.events_in[BENCH_GPIOTE_CHANNEL].reset();
+        self.marker.toggle();
+        let g = self.gpiote();
+        for _ in 0..BENCH_CAPTURE_SPINS {
+            if g.events_in[BENCH_GPIOTE_CHANNEL].read().bits() != 0 {
+                g.events_in[BENCH_GPIOTE_CHANNEL].reset();
+                return Some(self.timer().cc[0].read().bits());
+            }
+        }
+        None
+    }
+
+    /// Advances the script at the top of a frame, applying a new scenario
+    /// to the shared state and printing reports.
+    pub async fn step(&mut self) {
+        match self.runner.frame() {
+            BenchStep::Start(scenario) => {
+                set_frame_rate(StateSource::Bench, |rate| *rate = scenario.frame_rate).await;
+                set_rgb_levels_from(StateSource::Bench, |rgb| *rgb = scenario.levels).await;
+            }
+            BenchStep::Continue => {}
+            BenchStep::Finished(report) => rprintln!("bench: {}", report),
+            BenchStep::Done(verdict) => rprintln!("bench: {}", verdict),
+        }
+    }
+
+    /// Marks the start of a frame, which edges are measured from.
+    pub fn frame_start(&mut self) {
+        if self.runner.measuring() {
+            self.frame_start_us = self.mark();
+        }
+    }
+
+    /// Marks one LED edge and records its jitter.
+    ///
+    /// # Arguments
+    /// * `intended_us` - The edge's deadline after the frame start, in µs
+    pub fn edge(&mut self, intended_us: u64) {
+        if !self.runner.measuring() {
+            return;
+        }
+        let delta = match (self.frame_start_us, self.mark()) {
+            (Some(start), Some(at)) => {
+                let actual_us = at.wrapping_sub(start) as i64;
+                Some(
+                    (actual_us - intended_us as i64).clamp(i32::MIN as i64, i32::MAX as i64) as i32,
+                )
+            }
+            _ => None,
+        };
+        self.runner.record(delta);
+    }
+}
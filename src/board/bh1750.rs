@@ -0,0 +1,70 @@
+//! # Lux Sensor
+//!
+//! Reads the BH1750 over I2C for the library's lux compensation (see its
+//! `bh1750` module).
+use crate::*;
+use microbit_bsp::embassy_nrf::{
+    peripherals::TWISPI1,
+    twim::{self, Twim},
+};
+
+/// Delay between samples, in milliseconds.
+const LUX_PERIOD_MS: u64 = 1000;
+
+/// Runs [`BH1750_ONE_SHOT`] and converts the result.
+async fn bh1750_measure(twim: &mut Twim<'static, TWISPI1>) -> Result<f32, twim::Error> {
+    let mut data = [0; 2];
+    for step in BH1750_ONE_SHOT {
+        match step {
+            Bh1750Step::Write(opcode) => twim.write(BH1750_ADDR, &[opcode]).await?,
+            Bh1750Step::Wait(ms) => Timer::after_millis(ms).await,
+            Bh1750Step::Read => twim.read(BH1750_ADDR, &mut data).await?,
+        }
+    }
+    Ok(bh1750_lux(data))
+}
+
+/// Samples the sensor and publishes the compensated brightness scale.
+///
+/// # Arguments
+/// * `twim` - I2C bus with the sensor
+#[embassy_executor::task]
+pub async fn bh1750_task(mut twim: Twim<'static, TWISPI1>) -> ! {
+    let mut failing = false;
+    let mut scale = BRIGHTNESS_MAX as f32;
+    let mut last = Instant::now();
+    loop {
+        Timer::after_millis(LUX_PERIOD_MS).await;
+        let reading = bh1750_measure(&mut twim).await;
+        let dt_s = last.elapsed().as_millis() as f32 / 1000.0;
+        last = Instant::now();
+        let mut lux = LUX.lock().await;
+        match reading {
+            Ok(reading) => {
+                if failing {
+                    failing = false;
+                    rprintln!("bh1750: sensor found");
+                }
+                lux.lux = Some(reading);
+                if lux.enabled {
+                    let target = lux_target(reading, lux.min, lux.max);
+                    scale = lux_smooth(scale, target, dt_s, lux.tau_s as f32);
+                } else {
+                    scale = BRIGHTNESS_MAX as f32;
+                }
+            }
+            Err(e) => {
+                if !failing {
+                    failing = true;
+                    rprintln!(
+                        "bh1750: warning, no sensor ({:?}); brightness not compensated",
+                        e
+                    );
+                }
+                lux.lux = None;
+                scale = BRIGHTNESS_MAX as f32;
+            }
+        }
+        lux.scale = (scale.round() as u32).min(BRIGHTNESS_MAX);
+    }
+}
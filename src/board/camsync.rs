@@ -0,0 +1,23 @@
+//! # Camera Trigger Input
+//!
+//! Waits on the trigger pin for the library's camera sync (see its
+//! `camsync` module).
+use crate::*;
+use microbit_bsp::embassy_nrf::gpio::Input;
+
+/// Waits for the trigger if sync is enabled.
+///
+/// # Returns
+/// `None` with sync disabled; otherwise the wake-up time of the trigger,
+/// or `Some(None)` if the wait timed out.
+pub async fn wait_for_trigger(trigger: &mut Input<'static, AnyPin>) -> Option<Option<Instant>> {
+    let timeout = {
+        let sync = CAM_SYNC.lock().await;
+        if !sync.enabled {
+            return None;
+        }
+        sync.tracker.timeout()
+    };
+    let edge = embassy_time::with_timeout(timeout, trigger.wait_for_rising_edge()).await;
+    Some(edge.ok().map(|()| Instant::now()))
+}
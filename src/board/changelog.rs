@@ -0,0 +1,89 @@
+//! # Change Log Task
+//!
+//! Keeps the library's change log (see its `changelog` module) in the
+//! reserved log pages of internal flash.
+use crate::*;
+use embassy_sync::pubsub::WaitResult;
+
+/// The change log, available once [`changelog_task`] has opened it.
+pub static CHANGE_LOG: Mutex<ThreadModeRawMutex, Option<ChangeLog<NvmcFlash>>> = Mutex::new(None);
+
+/// Prints every logged change, oldest first.
+pub async fn dump_change_log() {
+    let log = CHANGE_LOG.lock().await;
+    let Some(log) = log.as_ref() else {
+        rprintln!("log: not ready");
+        return;
+    };
+    let mut count = 0;
+    log.for_each(|record| {
+        let name = LOG_PARAMETER_NAMES
+            .get(record.parameter as usize)
+            .unwrap_or(&"?");
+        rprintln!(
+            "{:>8.1} s  {}: {} -> {}",
+            record.uptime_ms as f32 / 1000.0,
+            name,
+            record.old,
+            record.new
+        );
+        count += 1;
+    });
+    rprintln!("log: {} records", count);
+}
+
+/// Logs settled changes of the levels and frame rate.
+#[embassy_executor::task]
+pub async fn changelog_task() -> ! {
+    // SAFETY: the log region is reserved in memory.x and used only here.
+    let flash = unsafe { NvmcFlash::new(LOG_FLASH_BASE, LOG_PAGES) };
+    *CHANGE_LOG.lock().await = Some(ChangeLog::open(flash));
+    let mut events = subscribe_state();
+    let read_values = || async {
+        let [red, green, blue] = get_rgb_levels().await;
+        [red, green, blue, get_frame_rate().await as u32]
+    };
+    let mut logged = read_values().await;
+    let mut current = logged;
+    let mut changed_at: Option<Instant> = None;
+    loop {
+        match embassy_time::with_timeout(LOG_SETTLE, events.next_message()).await {
+            Ok(WaitResult::Message(StateChanged::Levels(levels))) => {
+                current[..3].copy_from_slice(&levels);
+                changed_at = Some(Instant::now());
+            }
+            Ok(WaitResult::Message(StateChanged::FrameRate(frame_rate))) => {
+                current[3] = frame_rate as u32;
+                changed_at = Some(Instant::now());
+            }
+            Ok(WaitResult::Message(
+                StateChanged::OutputEnabled(_) | StateChanged::Brightness(_),
+            )) => {}
+            Ok(WaitResult::Lagged(_)) => {
+                current = read_values().await;
+                changed_at = Some(Instant::now());
+            }
+            Err(_) => {}
+        }
+        if !changed_at.is_some_and(|at| at.elapsed() >= LOG_SETTLE) {
+            continue;
+        }
+        changed_at = None;
+        let uptime_ms = Instant::now().as_millis() as u32;
+        let mut log = CHANGE_LOG.lock().await;
+        let Some(log) = log.as_mut() else {
+            continue;
+        };
+        for (parameter, (&old, &new)) in logged.iter().zip(current.iter()).enumerate() {
+            if old != new {
+                log.append(&ChangeRecord {
+                    parameter: parameter as u8,
+                    old: old as u16,
+                    new: new as u16,
+                    uptime_ms,
+                });
+            }
+        }
+        logged = current;
+    }
+}
@@ -0,0 +1,99 @@
+//! # Magnetometer
+//!
+//! Reads the LSM303AGR magnetometer for the library's compass mode (see
+//! its `compass` module).
+use crate::*;
+use microbit_bsp::embassy_nrf::{
+    peripherals::TWISPI0,
+    twim::{self, Twim},
+};
+
+/// I2C address of the LSM303AGR magnetometer.
+const MAG_ADDR: u8 = 0x1E;
+/// Configuration register A: temperature compensation, 10 Hz, continuous.
+const CFG_REG_A_M: u8 = 0x60;
+/// Configuration register C: block data update.
+const CFG_REG_C_M: u8 = 0x62;
+/// First output register; X, Y and Z follow as little-endian `i16`s.
+const OUTX_L_REG_M: u8 = 0x68;
+/// Value of [`CFG_REG_A_M`]: `COMP_TEMP_EN`, ODR 10 Hz, continuous mode.
+const CFG_A_CONTINUOUS: u8 = 0x80;
+/// Value of [`CFG_REG_C_M`]: `BDU`.
+const CFG_C_BDU: u8 = 0x10;
+
+/// Configures the magnetometer for continuous measurement.
+async fn mag_init(twim: &mut Twim<'static, TWISPI0>) -> Result<(), twim::Error> {
+    twim.write(MAG_ADDR, &[CFG_REG_A_M, CFG_A_CONTINUOUS])
+        .await?;
+    twim.write(MAG_ADDR, &[CFG_REG_C_M, CFG_C_BDU]).await
+}
+
+/// Reads the X and Y magnetometer outputs.
+async fn mag_read(twim: &mut Twim<'static, TWISPI0>) -> Result<(i32, i32), twim::Error> {
+    let mut buf = [0u8; 6];
+    twim.write_read(MAG_ADDR, &[OUTX_L_REG_M], &mut buf).await?;
+    let x = i16::from_le_bytes([buf[0], buf[1]]) as i32;
+    let y = i16::from_le_bytes([buf[2], buf[3]]) as i32;
+    Ok((x, y))
+}
+
+/// Reads the magnetometer and drives calibration and the compass hue.
+///
+/// I2C errors are logged and the sensor is re-initialized on the next pass;
+/// they never stop the task.
+#[embassy_executor::task]
+pub async fn compass_task(mut twim: Twim<'static, TWISPI0>) -> ! {
+    let mut initialized = false;
+    let mut hard_iron = HardIron::default();
+    let mut heading = None;
+    loop {
+        Timer::after_millis(COMPASS_PERIOD_MS).await;
+        let mode = *COMPASS_MODE.lock().await;
+        if !mode.enabled() && mode.calibrating_until.is_none() {
+            heading = None;
+            continue;
+        }
+        if !initialized {
+            if let Err(error) = mag_init(&mut twim).await {
+                rprintln!("compass: init failed: {:?}", error);
+                continue;
+            }
+            initialized = true;
+        }
+        let (x, y) = match mag_read(&mut twim).await {
+            Ok(reading) => reading,
+            Err(error) => {
+                rprintln!("compass: read failed: {:?}", error);
+                initialized = false;
+                continue;
+            }
+        };
+
+        let mut mode = COMPASS_MODE.lock().await;
+        if let Some(until) = mode.calibrating_until {
+            hard_iron.add(x, y);
+            if Instant::now() >= until {
+                mode.calibrating_until = None;
+                match hard_iron.offset() {
+                    Some(offset) => {
+                        mode.offset = offset;
+                        rprintln!("Compass calibration: offset {:?}", offset);
+                    }
+                    None => rprintln!("Compass calibration: no samples"),
+                }
+                hard_iron = HardIron::default();
+            }
+        }
+        if !mode.enabled() {
+            continue;
+        }
+        let reading = heading_degrees(x, y, mode.offset);
+        let smoothed = match heading {
+            Some(current) => smooth_heading(current, reading, COMPASS_SMOOTHING),
+            None => reading,
+        };
+        heading = Some(smoothed);
+        let levels = hsv_to_levels(smoothed, 1.0, 1.0);
+        set_rgb_levels(|rgb| *rgb = levels).await;
+    }
+}
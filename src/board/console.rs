@@ -0,0 +1,275 @@
+//! # Console Task
+//!
+//! Reads command lines from the RTT down channel, parses them with the
+//! library's `console` module and executes them.
+use crate::*;
+use rtt_target::DownChannel;
+
+/// Delay between polls of the RTT down channel, in milliseconds.
+const CONSOLE_POLL_MS: u64 = 20;
+
+/// Executes a parsed command.
+async fn execute(command: Command) {
+    match command {
+        Command::Focus(_) | Command::Rgb(_) | Command::Reset(_) => UI_COMMANDS.send(command).await,
+        Command::Timing => {
+            rprintln!("{}", *RGB_TIMING.lock().await);
+            #[cfg(feature = "camsync")]
+            {
+                let sync = CAM_SYNC.lock().await;
+                if sync.enabled {
+                    rprintln!("sync: {}", sync.tracker);
+                }
+            }
+        }
+        Command::ExplainTiming(_) => UI_COMMANDS.send(command).await,
+        Command::Verbosity(verbosity) => {
+            *VERBOSITY.lock().await = verbosity;
+            rprintln!("verbosity: {:?}", verbosity);
+        }
+        Command::Output(enabled) => {
+            if let Some(enabled) = enabled {
+                set_output_enabled(enabled).await;
+            }
+            let enabled = get_output_enabled().await;
+            rprintln!("output: {}", if enabled { "on" } else { "off" });
+        }
+        Command::Gamma(exponent) => {
+            let mut curve = GAMMA_CURVE.lock().await;
+            let knee = curve.map_or(GAMMA_LIVE_KNEE, |gamma| gamma.knee);
+            *curve = exponent.map(|exponent| Gamma { exponent, knee });
+            match *curve {
+                Some(gamma) => rprintln!("gamma: {:.2} (knee {})", gamma.exponent, gamma.knee),
+                None => rprintln!("gamma: off (linear)"),
+            }
+        }
+        #[cfg(not(feature = "hwpwm"))]
+        Command::Dither(mode) => {
+            *DITHER_MODE.lock().await = mode;
+            rprintln!("dither: {:?}", mode);
+        }
+        #[cfg(feature = "hwpwm")]
+        Command::PwmChannel(led, enabled) => {
+            let mut channels = PWM_CHANNELS.lock().await;
+            *channels = with_channel_enabled(*channels, led, enabled);
+            let state = |enabled| if enabled { "on" } else { "off" };
+            rprintln!(
+                "pwm: red {}, green {}, blue {}",
+                state(channels[0]),
+                state(channels[1]),
+                state(channels[2])
+            );
+        }
+        #[cfg(feature = "bh1750")]
+        Command::Lux => report_lux().await,
+        #[cfg(feature = "bh1750")]
+        Command::LuxEnabled(enabled) => {
+            LUX.lock().await.enabled = enabled;
+            report_lux().await;
+        }
+        #[cfg(feature = "bh1750")]
+        Command::LuxScale(bright, percent) => {
+            let mut lux = LUX.lock().await;
+            if bright {
+                lux.max = percent;
+            } else {
+                lux.min = percent;
+            }
+            rprintln!("lux: min {}%, max {}%", lux.min, lux.max);
+        }
+        #[cfg(feature = "bh1750")]
+        Command::LuxTau(seconds) => {
+            LUX.lock().await.tau_s = seconds;
+            rprintln!("lux: tau {} s", seconds);
+        }
+        #[cfg(feature = "bargraph")]
+        Command::BarStyle(style) => {
+            *BAR_STYLE.lock().await = style;
+            rprintln!("bars: {:?}", style);
+        }
+        Command::FpsDemo(_)
+        | Command::FpsDemoColor(_)
+        | Command::KnobStats(_)
+        | Command::KnobCalibrate
+        | Command::SelfTest
+        | Command::Dump
+        | Command::Restore(_)
+        | Command::Save => UI_COMMANDS.send(command).await,
+        Command::Latency => rprintln!("latency: {}", take_latency().await),
+        Command::Ranges => {
+            for range in value_ranges() {
+                rprintln!("{}", range);
+            }
+        }
+        Command::Snapshot(slot) => {
+            let snapshot = take_snapshot(slot).await;
+            rprintln!(
+                "snapshot {:?}: levels {:?}, {} fps",
+                slot,
+                snapshot.levels,
+                snapshot.frame_rate
+            );
+        }
+        Command::Diff => match snapshot_diff().await {
+            Some(diff) => rprintln!("{}", diff),
+            None => rprintln!("diff: take SNAP A and SNAP B first"),
+        },
+        Command::Stats(reset) => {
+            let dump = if reset {
+                STATS.take()
+            } else {
+                STATS.snapshot()
+            };
+            rprintln!("{}", StatsLine(dump));
+            rprintln!("{}", get_flash_wear().await);
+        }
+        #[cfg(feature = "camsync")]
+        Command::Sync(enabled) => set_cam_sync(enabled).await,
+        #[cfg(feature = "idle")]
+        Command::EffectLayer(effect) => {
+            if let Some(effect) = effect {
+                // Ends a running idle animation, so the layer starts from
+                // the user's color.
+                idle_input().await;
+                set_effect_layer(effect).await;
+            }
+            rprintln!("effect: {:?}", get_effect_layer().await);
+        }
+        #[cfg(feature = "tone")]
+        Command::Mute(muted) => {
+            *TONE_MUTED.lock().await = muted;
+            rprintln!("tone: {}", if muted { "muted" } else { "on" });
+        }
+        #[cfg(feature = "temperature")]
+        Command::TempMode(enabled) => set_temperature_mode(enabled).await,
+        #[cfg(feature = "temperature")]
+        Command::TempThreshold(high, celsius) => {
+            if set_temperature_threshold(high, celsius).await {
+                let mode = TEMP_MODE.lock().await;
+                rprintln!("temperature range: {}..{} C", mode.low, mode.high);
+            } else {
+                rprintln!("error: low threshold must stay below high");
+            }
+        }
+        #[cfg(feature = "compass")]
+        Command::CompassMode(enabled) => set_compass_mode(enabled).await,
+        #[cfg(feature = "compass")]
+        Command::CompassCalibrate => start_compass_calibration().await,
+        #[cfg(feature = "motion")]
+        Command::TiltMode(enabled) => set_tilt_mode(enabled).await,
+        #[cfg(feature = "sleep")]
+        Command::Sleep(delay) => set_sleep_timer(delay).await,
+        #[cfg(feature = "schedule")]
+        Command::ScheduleMode(enabled) => set_schedule_enabled(enabled).await,
+        #[cfg(feature = "schedule")]
+        Command::SchedulePeriod(seconds) => {
+            SCHEDULE.lock().await.period_s = seconds;
+            rprintln!("schedule: {} s cycle", seconds);
+        }
+        #[cfg(feature = "schedule")]
+        Command::ScheduleCurve(curve) => {
+            SCHEDULE.lock().await.curve = curve;
+            rprintln!("schedule: {:?} curve", curve);
+        }
+        #[cfg(feature = "dmx")]
+        Command::DmxAddress(address) => {
+            if set_dmx_address(address).await {
+                rprintln!("dmx: fixture at channels {}..={}", address, address + 2);
+            } else {
+                rprintln!("error: DMX address must be 1..={}", DMX_ADDRESS_MAX - 2);
+            }
+        }
+        #[cfg(feature = "radio")]
+        Command::RadioRole(role) => set_radio_role(role).await,
+        #[cfg(feature = "radio")]
+        Command::RadioGroup(group) => {
+            if set_radio_group(group).await {
+                rprintln!("radio: group {}", group);
+            } else {
+                rprintln!("error: radio group must be 0..={}", RADIO_GROUP_MAX);
+            }
+        }
+        #[cfg(feature = "radio")]
+        Command::MatrixBrightness(brightness) => {
+            if let Some(brightness) = brightness {
+                set_matrix_brightness(brightness).await;
+            }
+            rprintln!(
+                "matrix: brightness {}/{}",
+                get_matrix_brightness().await,
+                MATRIX_BRIGHTNESS_MAX
+            );
+        }
+        #[cfg(feature = "telemetry")]
+        Command::Telemetry(enabled, rate) => {
+            if set_telemetry(enabled, rate).await {
+                if enabled {
+                    rprintln!("telemetry: on at {} Hz", TELEMETRY.lock().await.rate_hz);
+                } else {
+                    rprintln!("telemetry: off");
+                }
+            } else {
+                rprintln!(
+                    "error: telemetry rate must be 1..={} Hz",
+                    TELEMETRY_RATE_MAX
+                );
+            }
+        }
+        #[cfg(feature = "changelog")]
+        Command::LogDump => dump_change_log().await,
+        #[cfg(feature = "record")]
+        Command::Record(enabled) => set_recording(enabled).await,
+        #[cfg(feature = "record")]
+        Command::RecordDump => dump_trace().await,
+        #[cfg(feature = "testpattern")]
+        Command::PatternMode(enabled) => set_pattern_mode(enabled).await,
+        #[cfg(feature = "testpattern")]
+        Command::PatternDwell(seconds) => {
+            PATTERN_MODE.lock().await.dwell_s = seconds;
+            rprintln!("pattern: {} s per color", seconds);
+        }
+        #[cfg(all(feature = "fault-inject", debug_assertions))]
+        Command::Fault(fault) => inject_fault(fault).await,
+        #[cfg(feature = "ir")]
+        Command::Adjust(_) | Command::Preset(_) | Command::Power(_) => {
+            UI_COMMANDS.send(command).await
+        }
+    }
+}
+
+/// Reads console lines from the RTT down channel and executes them.
+///
+/// Over-long lines are discarded whole rather than executed truncated.
+#[embassy_executor::task]
+pub async fn console_task(mut input: DownChannel) -> ! {
+    let mut line = [0u8; CONSOLE_LINE_MAX];
+    let mut len = 0;
+    let mut overflow = false;
+    loop {
+        let mut buf = [0u8; 16];
+        let count = input.read(&mut buf);
+        for &byte in &buf[..count] {
+            if byte != b'\n' && byte != b'\r' {
+                if len < line.len() {
+                    line[len] = byte;
+                    len += 1;
+                } else {
+                    overflow = true;
+                }
+                continue;
+            }
+            if overflow {
+                rprintln!("error: line too long");
+            } else if len > 0 {
+                match core::str::from_utf8(&line[..len]).map(parse_command) {
+                    Ok(Ok(command)) => execute(command).await,
+                    Ok(Err(error)) => rprintln!("error: {:?}", error),
+                    Err(_) => rprintln!("error: invalid UTF-8"),
+                }
+            }
+            len = 0;
+            overflow = false;
+        }
+        Timer::after_millis(CONSOLE_POLL_MS).await;
+    }
+}
@@ -0,0 +1,52 @@
+//! # DMX Transmitter
+//!
+//! Sends the library's DMX packets (see its `dmx` module) on UARTE0.
+use crate::*;
+use microbit_bsp::embassy_nrf::{
+    peripherals::UARTE0,
+    uarte::{self, UarteTx},
+};
+
+/// Sends DMX packets of the current color continuously.
+///
+/// # Arguments
+/// * `uarte` - UART peripheral used for the slots
+/// * `tx` - Pin wired to the transceiver's DI input
+#[embassy_executor::task]
+pub async fn dmx_task(mut uarte: UARTE0, mut tx: AnyPin) -> ! {
+    let mut config = uarte::Config::default();
+    config.baudrate = uarte::Baudrate::BAUD250000;
+    let mut packet = [0u8; DMX_PACKET_MAX];
+    loop {
+        let start = Instant::now();
+        let brightness = get_master_brightness().await;
+        #[cfg(feature = "ambient")]
+        let brightness = brightness * get_ambient_scale().await / BRIGHTNESS_MAX;
+        #[cfg(feature = "bh1750")]
+        let brightness = brightness * get_lux_scale().await / BRIGHTNESS_MAX;
+        let address = *DMX_ADDRESS.lock().await;
+        let len = dmx_packet(get_rgb_levels().await, brightness, address, &mut packet);
+
+        {
+            let mut line = Output::new(&mut tx, Level::Low, OutputDrive::Standard);
+            Timer::after_micros(DMX_BREAK_US).await;
+            line.set_high();
+            Timer::after_micros(DMX_MAB_US).await;
+        }
+        let mut port = UarteTx::new(&mut uarte, Irqs, &mut tx, config);
+        // DMX512 needs two stop bits, which the driver config doesn't
+        // expose; set them directly while the UARTE is idle.
+        // SAFETY: only this task uses UARTE0, and no transfer is running.
+        unsafe {
+            (*microbit_bsp::embassy_nrf::pac::UARTE0::ptr())
+                .config
+                .modify(|_, w| w.stop().two());
+        }
+        if let Err(error) = port.write(&packet[..len]).await {
+            rprintln!("dmx: UART error: {:?}", error);
+        }
+        drop(port);
+
+        Timer::at(start + Duration::from_millis(DMX_PERIOD_MS)).await;
+    }
+}
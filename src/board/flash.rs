@@ -0,0 +1,71 @@
+//! # NVMC Flash
+//!
+//! Programs the reserved flash regions (see the library's `flash` module)
+//! through the NVMC.
+use crate::*;
+use microbit_bsp::embassy_nrf::pac;
+
+/// A reserved region of internal flash, programmed through the NVMC.
+///
+/// Flash operations stall the CPU (about 41 µs per word and 85 ms per page
+/// erase), briefly freezing the scanout.
+pub struct NvmcFlash {
+    /// Flash address of the region.
+    base: u32,
+    /// Region length in pages.
+    pages: u32,
+}
+
+impl NvmcFlash {
+    /// Creates access to a flash region.
+    ///
+    /// # Safety
+    /// The region must be page-aligned, reserved in `memory.x`, and not
+    /// overlap any other [`NvmcFlash`].
+    pub const unsafe fn new(base: u32, pages: u32) -> Self {
+        Self { base, pages }
+    }
+
+    /// Returns the NVMC register block.
+    fn regs(&self) -> &pac::nvmc::RegisterBlock {
+        // SAFETY: NVMC operations never interleave (see the module docs).
+        unsafe { &*pac::NVMC::ptr() }
+    }
+
+    /// Waits for the NVMC to finish the current operation.
+    fn wait_ready(&self) {
+        while self.regs().ready.read().ready().is_busy() {}
+    }
+}
+
+impl FlashRegion for NvmcFlash {
+    fn read(&self, offset: u32, buf: &mut [u8]) {
+        assert!(offset + buf.len() as u32 <= self.pages * FLASH_PAGE_SIZE);
+        let base = (self.base + offset) as *const u8;
+        for (i, byte) in buf.iter_mut().enumerate() {
+            // SAFETY: inside the region, which is mapped flash.
+            *byte = unsafe { core::ptr::read_volatile(base.add(i)) };
+        }
+    }
+
+    fn write_word(&mut self, offset: u32, word: u32) {
+        assert!(offset % 4 == 0 && offset + 4 <= self.pages * FLASH_PAGE_SIZE);
+        self.regs().config.write(|w| w.wen().wen());
+        self.wait_ready();
+        // SAFETY: word-aligned address inside the reserved region.
+        unsafe { core::ptr::write_volatile((self.base + offset) as *mut u32, word) };
+        self.wait_ready();
+        self.regs().config.write(|w| w.wen().ren());
+    }
+
+    fn erase_page(&mut self, page: u32) {
+        assert!(page < self.pages);
+        self.regs().config.write(|w| w.wen().een());
+        self.wait_ready();
+        self.regs()
+            .erasepage()
+            .write(|w| unsafe { w.bits(self.base + page * FLASH_PAGE_SIZE) });
+        self.wait_ready();
+        self.regs().config.write(|w| w.wen().ren());
+    }
+}
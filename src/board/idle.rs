@@ -0,0 +1,32 @@
+//! # Idle Animation Task
+//!
+//! Plays the library's idle animation (see its `idle` module) on the LED.
+use crate::*;
+
+/// Delay between animation frames, in milliseconds.
+const IDLE_TICK_MS: u64 = 50;
+
+/// Plays the idle animation whenever the controls have been idle for the
+/// configured timeout.
+#[embassy_executor::task]
+pub async fn idle_task() -> ! {
+    loop {
+        Timer::after_millis(IDLE_TICK_MS).await;
+        // A layered effect already animates the LED.
+        if EFFECT_LAYER.lock().await.is_some() {
+            continue;
+        }
+        // Hold the idle lock while writing so input can't be overwritten by
+        // a stale animation frame.
+        let mut idle = IDLE.lock().await;
+        let was_active = idle.active();
+        if let Some(levels) = idle.tick(Instant::now().as_millis(), get_rgb_levels().await) {
+            if !was_active {
+                rprintln!("Idle animation: {:?}", idle.config.idle_effect);
+            }
+            if !idle.config.idle_effect.external() {
+                set_rgb_levels(|rgb| *rgb = levels).await;
+            }
+        }
+    }
+}
@@ -0,0 +1,57 @@
+//! # IR Receiver
+//!
+//! Times the IR receiver's pulses for the library's NEC decoder (see its
+//! `ir` module).
+use crate::*;
+use microbit_bsp::embassy_nrf::gpio::Input;
+
+/// Longest gap after which a repeat code still continues the last key.
+const IR_REPEAT_WINDOW: Duration = Duration::from_millis(200);
+/// Address the remote must send, or `None` to accept any remote.
+const IR_ADDRESS: Option<u16> = None;
+
+/// Receives IR remote keys and forwards them to the UI.
+///
+/// Repeat codes re-send the last key while it is held, but only for the
+/// up/down keys; a repeat long after the last code is ignored.
+///
+/// # Arguments
+/// * `input` - IR receiver output pin
+#[embassy_executor::task]
+pub async fn ir_task(mut input: Input<'static, AnyPin>) -> ! {
+    let mut decoder = NecDecoder::new();
+    let mut last_edge = Instant::now();
+    let mut held: Option<(Command, Instant)> = None;
+    loop {
+        // The level before the edge is the one that just ended.
+        let mark = input.is_low();
+        input.wait_for_any_edge().await;
+        let now = Instant::now();
+        let duration_us = (now - last_edge).as_micros();
+        last_edge = now;
+
+        let command = match decoder.push(mark, duration_us) {
+            Some(NecEvent::Frame { address, command }) => {
+                if IR_ADDRESS.is_some_and(|expected| expected != address) {
+                    continue;
+                }
+                let Some(key) = ir_key_command(command) else {
+                    rprintln!(
+                        "ir: unmapped key {:#04x} (address {:#06x})",
+                        command,
+                        address
+                    );
+                    continue;
+                };
+                key
+            }
+            Some(NecEvent::Repeat) => match held {
+                Some((key @ Command::Adjust(_), at)) if now - at <= IR_REPEAT_WINDOW => key,
+                _ => continue,
+            },
+            None => continue,
+        };
+        held = Some((command, now));
+        UI_COMMANDS.send(command).await;
+    }
+}
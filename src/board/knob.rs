@@ -0,0 +1,139 @@
+//! # Knob Sampling
+//!
+//! Drives the library's [`Knob`] (see its `knob` module) from the
+//! potentiometer through the SAADC. A burst of knob scans is one SAADC
+//! transfer, its scans triggered by TIMER0 through two PPI channels. A
+//! driver left wedged by a timed-out conversion is torn down and built
+//! again from the same configuration.
+use crate::*;
+#[cfg(feature = "mic")]
+use microbit_bsp::embassy_nrf::peripherals::P0_05;
+use microbit_bsp::embassy_nrf::{
+    peripherals::{P0_04, PPI_CH0, PPI_CH1, SAADC, TIMER0},
+    timer::Frequency,
+};
+
+/// Spacing between the scans of a knob burst, in microseconds: long enough
+/// for a scan of every channel to finish.
+const KNOB_BURST_PERIOD_US: u32 = 100;
+
+/// Sets up the SAADC: the knob first, then the microphone and the supply
+/// when those features are on.
+///
+/// # Arguments
+///
+/// * `saadc` - The SAADC peripheral
+/// * `knob` - The knob's wiper, on P2
+/// * `mic` - The microphone input (with `mic`)
+pub fn new_saadc(
+    saadc: SAADC,
+    knob: P0_04,
+    #[cfg(feature = "mic")] mic: P0_05,
+) -> saadc::Saadc<'static, ADC_CHANNELS> {
+    let mut config = saadc::Config::default();
+    config.resolution = saadc::Resolution::_14BIT;
+    // Ratiometric knob: full scale is VDD, where the pot's top end sits.
+    let mut knob_channel = saadc::ChannelConfig::single_ended(knob);
+    knob_channel.gain = saadc::Gain::GAIN1_4;
+    knob_channel.reference = saadc::Reference::VDD1_4;
+    // The microphone shares the SAADC with the knob as a second channel, so
+    // every conversion samples both inputs in one scan. The supply is
+    // scanned last through the internal VDD input, at the default gain 1/6
+    // against the 0.6V reference (3.6V full scale).
+    saadc::Saadc::new(
+        saadc,
+        Irqs,
+        config,
+        [
+            knob_channel,
+            #[cfg(feature = "mic")]
+            saadc::ChannelConfig::single_ended(mic),
+            #[cfg(feature = "battery")]
+            saadc::ChannelConfig::single_ended(saadc::VddInput),
+        ],
+    )
+}
+
+/// The SAADC configured to read [`ADC_CHANNELS`] analog input channels,
+/// with the timer and PPI channels that pace a knob burst.
+pub struct Adc {
+    /// The driver; only `None` while [`KnobAdc::reinit`] replaces it.
+    saadc: Option<saadc::Saadc<'static, ADC_CHANNELS>>,
+    timer: TIMER0,
+    ppi: (PPI_CH0, PPI_CH1),
+}
+
+impl Adc {
+    /// Wraps a configured SAADC.
+    ///
+    /// # Arguments
+    ///
+    /// * `saadc` - SAADC from [`new_saadc`]
+    /// * `timer` - Timer that triggers the scans of a burst
+    /// * `ppi` - PPI channels from the timer to the SAADC
+    pub fn new(
+        saadc: saadc::Saadc<'static, ADC_CHANNELS>,
+        timer: TIMER0,
+        ppi: (PPI_CH0, PPI_CH1),
+    ) -> Self {
+        Self {
+            saadc: Some(saadc),
+            timer,
+            ppi,
+        }
+    }
+    /// The SAADC driver.
+    fn saadc(&mut self) -> &mut saadc::Saadc<'static, ADC_CHANNELS> {
+        self.saadc
+            .as_mut()
+            .expect("the SAADC is only absent inside reinit")
+    }
+}
+
+impl KnobAdc for Adc {
+    async fn calibrate(&mut self) {
+        self.saadc().calibrate().await
+    }
+    async fn sample(&mut self, buf: &mut [i16; ADC_CHANNELS]) {
+        self.saadc().sample(buf).await
+    }
+    async fn sample_burst(&mut self, burst: &mut [[i16; ADC_CHANNELS]; KNOB_BURST]) {
+        // The sampler double-buffers; the first buffer filled is the burst.
+        let mut bufs = [[[0; ADC_CHANNELS]; KNOB_BURST]; 2];
+        self.saadc
+            .as_mut()
+            .expect("the SAADC is only absent inside reinit")
+            .run_task_sampler(
+                &mut self.timer,
+                &mut self.ppi.0,
+                &mut self.ppi.1,
+                Frequency::F1MHz,
+                KNOB_BURST_PERIOD_US,
+                &mut bufs,
+                |scans| {
+                    burst.copy_from_slice(scans);
+                    saadc::CallbackResult::Stop
+                },
+            )
+            .await
+    }
+    async fn reinit(&mut self) {
+        // Dropping the old driver disables the SAADC, so it has to go before
+        // the new one sets the peripheral up again.
+        self.saadc = None;
+        // SAFETY: the driver just dropped was the only user of the SAADC and
+        // of its input pins, which `new_saadc` was given at startup.
+        let saadc = unsafe {
+            new_saadc(
+                SAADC::steal(),
+                P0_04::steal(),
+                #[cfg(feature = "mic")]
+                P0_05::steal(),
+            )
+        };
+        self.saadc = Some(saadc);
+    }
+    fn reset(&mut self) -> ! {
+        controlled_reset()
+    }
+}
@@ -0,0 +1,148 @@
+//! # RTT Console
+//!
+//! Writes the library's console lines (see its `logging` module) to the
+//! RTT terminal channel, and prints the deferred lines and drop notices.
+use crate::*;
+use core::cell::RefCell;
+use core::sync::atomic::Ordering;
+use cortex_m::interrupt::Mutex as IrqMutex;
+use embassy_futures::select::{select, Either};
+use rtt_target::{ChannelMode, UpChannel};
+
+/// Terminal channel written by [`rprintln!`](crate::rprintln), and whether
+/// a host is reading it.
+struct LogSink {
+    channel: UpChannel,
+    host: HostMonitor,
+}
+
+/// Log sink, once [`init_logging`] has run.
+static LOG_SINK: IrqMutex<RefCell<Option<LogSink>>> = IrqMutex::new(RefCell::new(None));
+
+/// Start of the SEGGER RTT control block, as `rtt_target` lays it out.
+/// Only its size and the offsets are used.
+#[repr(C)]
+struct RttHeader {
+    _id: [u8; 16],
+    _max_up_channels: usize,
+    _max_down_channels: usize,
+}
+
+/// One channel's descriptor in the RTT control block.
+#[repr(C)]
+struct RttChannel {
+    _name: *const u8,
+    _buffer: *mut u8,
+    _size: usize,
+    write: usize,
+    read: usize,
+    _flags: usize,
+}
+
+extern "C" {
+    /// Control block defined by `rtt_init!`.
+    static _SEGGER_RTT: RttHeader;
+}
+
+/// Reads the terminal channel's ring-buffer offsets.
+///
+/// # Returns
+/// `(write, read)`: where the firmware writes next and where the host
+/// reads next.
+///
+/// # Safety
+/// `rtt_init!` must have set up the control block, with the terminal as
+/// up channel 0.
+unsafe fn terminal_offsets() -> (usize, usize) {
+    // Up channel 0 follows the header directly.
+    let header = core::ptr::addr_of!(_SEGGER_RTT);
+    let channel = header.add(1) as *const RttChannel;
+    (
+        core::ptr::addr_of!((*channel).write).read_volatile(),
+        core::ptr::addr_of!((*channel).read).read_volatile(),
+    )
+}
+
+/// Takes over the terminal channel for [`rprintln!`](crate::rprintln),
+/// registering [`write_rtt_line`] as the [`LogWriter`].
+///
+/// # Safety
+/// Channel 0 must be the terminal up channel set up by `rtt_init!`. The
+/// handle aliases the print channel kept for the panic handler, which only
+/// writes once the firmware has stopped logging.
+pub unsafe fn init_logging() {
+    let Some(mut channel) = UpChannel::conjure(0) else {
+        return;
+    };
+    // Whole lines or nothing; `log-blocking` retries in `write_rtt_line`.
+    channel.set_mode(ChannelMode::NoBlockSkip);
+    let sink = LogSink {
+        channel,
+        host: HostMonitor::new(LOG_HOST_GRACE),
+    };
+    cortex_m::interrupt::free(|cs| *LOG_SINK.borrow(cs).borrow_mut() = Some(sink));
+    set_log_writer(write_rtt_line);
+}
+
+/// Writes one line in a single piece.
+///
+/// With `log-blocking`, waits for room while a host is reading, each try
+/// in its own critical section so interrupts still run in between.
+///
+/// # Returns
+/// `false` if the line was dropped (buffer full, no host, or logging not
+/// started).
+fn write_rtt_line(line: &LogLine) -> bool {
+    let bytes = line.as_bytes();
+    loop {
+        let now_ms = Instant::now().as_millis();
+        let written = cortex_m::interrupt::free(|cs| {
+            let mut sink = LOG_SINK.borrow(cs).borrow_mut();
+            let Some(sink) = sink.as_mut() else {
+                return Some(false);
+            };
+            // SAFETY: the sink only exists once `init_logging` found the
+            // control block.
+            let (write, read) = unsafe { terminal_offsets() };
+            if !sink.host.observe(now_ms, write, read) {
+                return Some(false);
+            }
+            if sink.channel.write(bytes) == bytes.len() {
+                Some(true)
+            } else if cfg!(feature = "log-blocking") {
+                None
+            } else {
+                Some(false)
+            }
+        });
+        if let Some(written) = written {
+            return written;
+        }
+    }
+}
+
+/// Prints deferred lines as they are queued, and reports dropped log lines
+/// every [`LOG_DROP_REPORT`] that had any.
+///
+/// A notice that is itself dropped is retried next time, with the count
+/// still covering every line lost since the last notice that got through.
+#[embassy_executor::task]
+pub async fn log_task() -> ! {
+    let mut reported = 0;
+    let mut next_report = Instant::now() + LOG_DROP_REPORT;
+    loop {
+        match select(Timer::at(next_report), LOG_DEFERRED.receive()).await {
+            Either::First(()) => {
+                next_report = Instant::now() + LOG_DROP_REPORT;
+                let dropped = LOG_DROPPED.load(Ordering::Relaxed);
+                if let Some(count) = drop_notice(dropped, reported) {
+                    let notice = LogLine::format(format_args!("{} log lines dropped", count));
+                    if write_line(&notice) {
+                        reported = dropped;
+                    }
+                }
+            }
+            Either::Second(line) => write_line_counted(&line),
+        }
+    }
+}
@@ -0,0 +1,49 @@
+//! # Status LED
+//!
+//! Drives the matrix status LED (see the library's `matrix` module).
+use crate::*;
+use microbit_bsp::embassy_nrf::peripherals;
+
+/// Drives the status LED at the matrix brightness.
+#[embassy_executor::task]
+pub async fn matrix_task() -> ! {
+    // SAFETY: ROW1 and COL1 are taken only here. ROW1 is only driven high
+    // while holding MATRIX_LOCK, so it never forward-biases the LED at row
+    // 1, column 3 into the column the ambient sensor leaves floating.
+    let mut row = Output::new(
+        unsafe { peripherals::P0_21::steal() },
+        Level::Low,
+        OutputDrive::Standard,
+    );
+    let _col = Output::new(
+        unsafe { peripherals::P0_28::steal() },
+        Level::Low,
+        OutputDrive::Standard,
+    );
+    let period_us = MATRIX_PWM_PERIOD.as_micros();
+    loop {
+        #[cfg(feature = "schedule")]
+        let scale = if SCHEDULE.lock().await.enabled {
+            get_master_brightness().await
+        } else {
+            BRIGHTNESS_MAX
+        };
+        #[cfg(not(feature = "schedule"))]
+        let scale = BRIGHTNESS_MAX;
+        let duty = if LINK_LED.load(Ordering::Relaxed) {
+            matrix_duty(get_matrix_brightness().await, scale) as u64
+        } else {
+            0
+        };
+        let on_us = period_us * duty / 1000;
+        if on_us > 0 {
+            let _matrix = MATRIX_LOCK.lock().await;
+            row.set_high();
+            Timer::after_micros(on_us).await;
+            row.set_low();
+        }
+        if on_us < period_us {
+            Timer::after_micros(period_us - on_us).await;
+        }
+    }
+}
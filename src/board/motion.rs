@@ -0,0 +1,95 @@
+//! # Accelerometer
+//!
+//! Reads the LSM303AGR accelerometer for the library's tilt mode (see its
+//! `motion` module).
+use crate::*;
+use microbit_bsp::embassy_nrf::{
+    peripherals::TWISPI0,
+    twim::{self, Twim},
+};
+
+/// I2C address of the LSM303AGR accelerometer.
+const ACCEL_ADDR: u8 = 0x19;
+/// Control register 1: data rate and enabled axes.
+const CTRL_REG1_A: u8 = 0x20;
+/// Control register 4: block data update and full scale.
+const CTRL_REG4_A: u8 = 0x23;
+/// First output register; X, Y and Z follow as little-endian `i16`s. The
+/// top bit of the address asks for auto-increment.
+const OUT_X_L_A: u8 = 0x28 | 0x80;
+/// Value of [`CTRL_REG1_A`]: ODR 50 Hz, normal mode, X, Y and Z enabled.
+const CTRL1_50HZ_XYZ: u8 = 0x47;
+/// Value of [`CTRL_REG4_A`]: `BDU`, ±2 g.
+const CTRL4_BDU_2G: u8 = 0x80;
+/// Accelerometer counts per g, for the left-justified ±2 g outputs.
+const ACCEL_COUNTS_PER_G: f32 = 16384.0;
+
+/// Configures the accelerometer for continuous measurement.
+async fn accel_init(twim: &mut Twim<'static, TWISPI0>) -> Result<(), twim::Error> {
+    twim.write(ACCEL_ADDR, &[CTRL_REG1_A, CTRL1_50HZ_XYZ])
+        .await?;
+    twim.write(ACCEL_ADDR, &[CTRL_REG4_A, CTRL4_BDU_2G]).await
+}
+
+/// Reads the X and Y accelerometer outputs, in g.
+async fn accel_read(twim: &mut Twim<'static, TWISPI0>) -> Result<[f32; 2], twim::Error> {
+    let mut buf = [0u8; 6];
+    twim.write_read(ACCEL_ADDR, &[OUT_X_L_A], &mut buf).await?;
+    let x = i16::from_le_bytes([buf[0], buf[1]]) as f32 / ACCEL_COUNTS_PER_G;
+    let y = i16::from_le_bytes([buf[2], buf[3]]) as f32 / ACCEL_COUNTS_PER_G;
+    Ok([x, y])
+}
+
+/// Reads the accelerometer and drives the levels from the tilt while tilt
+/// mode or the [`Effect::Tilt`] idle animation is on.
+///
+/// I2C errors are logged and the sensor is re-initialized on the next pass;
+/// they never stop the task.
+#[embassy_executor::task]
+pub async fn motion_task(mut twim: Twim<'static, TWISPI0>) -> ! {
+    let mut initialized = false;
+    let mut tilt = None;
+    loop {
+        Timer::after_millis(MOTION_PERIOD_MS).await;
+        let enabled = MOTION_MODE.lock().await.enabled();
+        #[cfg(feature = "idle")]
+        let idle_tilt = IDLE.lock().await.tilt_active();
+        #[cfg(not(feature = "idle"))]
+        let idle_tilt = false;
+        if !enabled && !idle_tilt {
+            tilt = None;
+            continue;
+        }
+        if !initialized {
+            if let Err(error) = accel_init(&mut twim).await {
+                rprintln!("motion: init failed: {:?}", error);
+                continue;
+            }
+            initialized = true;
+        }
+        let reading = match accel_read(&mut twim).await {
+            Ok(reading) => reading,
+            Err(error) => {
+                rprintln!("motion: read failed: {:?}", error);
+                initialized = false;
+                continue;
+            }
+        };
+        let smoothed = match tilt {
+            Some(current) => smooth_tilt(current, reading, MOTION_SMOOTHING),
+            None => reading,
+        };
+        tilt = Some(smoothed);
+        let levels = tilt_levels(smoothed);
+
+        // Hold the idle lock while writing, so input that just ended the
+        // animation can't be overwritten by a stale tilt color.
+        #[cfg(feature = "idle")]
+        let idle = IDLE.lock().await;
+        #[cfg(feature = "idle")]
+        let enabled = enabled || idle.tilt_active();
+        if enabled {
+            set_rgb_levels(|rgb| *rgb = levels).await;
+        }
+    }
+}
@@ -0,0 +1,133 @@
+//! # SSD1306 Display
+//!
+//! Writes the library's status frames (see its `oled` module) to the
+//! SSD1306 over I2C.
+use crate::*;
+use embassy_sync::pubsub::WaitResult;
+use microbit_bsp::embassy_nrf::{
+    peripherals::TWISPI1,
+    twim::{self, Twim},
+};
+
+/// I2C address of the SSD1306.
+const OLED_ADDR: u8 = 0x3C;
+/// Data bytes sent per I2C write during a blit.
+const OLED_CHUNK: usize = 32;
+/// Delay between retries while the display is failing, in milliseconds.
+const OLED_RETRY_MS: u64 = 1000;
+
+/// Control byte prefixing a command stream.
+const CONTROL_COMMAND: u8 = 0x00;
+/// Control byte prefixing display data.
+const CONTROL_DATA: u8 = 0x40;
+/// Init sequence for a 128x64 panel with the internal charge pump.
+const OLED_INIT: [u8; 25] = [
+    0xAE, // display off
+    0xD5, 0x80, // clock divide
+    0xA8, 0x3F, // multiplex 64
+    0xD3, 0x00, // display offset 0
+    0x40, // start line 0
+    0x8D, 0x14, // charge pump on
+    0x20, 0x00, // horizontal addressing
+    0xA1, // segment remap
+    0xC8, // COM scan descending
+    0xDA, 0x12, // COM pins
+    0x81, 0xCF, // contrast
+    0xD9, 0xF1, // precharge
+    0xDB, 0x40, // VCOMH deselect
+    0xA4, // display follows RAM
+    0xA6, // normal (not inverted)
+    0xAF, // display on
+];
+/// Resets the write window to the whole screen before a blit.
+const OLED_WINDOW: [u8; 6] = [0x21, 0, OLED_WIDTH as u8 - 1, 0x22, 0, OLED_PAGES as u8 - 1];
+
+/// Sends a command sequence to the display.
+async fn oled_commands(
+    twim: &mut Twim<'static, TWISPI1>,
+    commands: &[u8],
+) -> Result<(), twim::Error> {
+    for &command in commands {
+        twim.write(OLED_ADDR, &[CONTROL_COMMAND, command]).await?;
+    }
+    Ok(())
+}
+
+/// Copies a framebuffer to the display.
+async fn oled_blit(
+    twim: &mut Twim<'static, TWISPI1>,
+    fb: &[u8; OLED_FB_SIZE],
+) -> Result<(), twim::Error> {
+    oled_commands(twim, &OLED_WINDOW).await?;
+    let mut buf = [0u8; OLED_CHUNK + 1];
+    buf[0] = CONTROL_DATA;
+    for chunk in fb.chunks(OLED_CHUNK) {
+        buf[1..=chunk.len()].copy_from_slice(chunk);
+        twim.write(OLED_ADDR, &buf[..=chunk.len()]).await?;
+    }
+    Ok(())
+}
+
+/// Keeps the OLED showing the current levels, frame rate, and output
+/// state.
+///
+/// Redraws only when a [`StateChanged`] event changes the values, or after
+/// an I2C error once the display responds again.
+#[embassy_executor::task]
+pub async fn oled_task(mut twim: Twim<'static, TWISPI1>) -> ! {
+    let mut events = subscribe_state();
+    let mut fb = [0u8; OLED_FB_SIZE];
+    let mut initialized = false;
+    let mut shown = None;
+    let mut failing = false;
+    let mut state = (
+        get_rgb_levels().await,
+        get_frame_rate().await,
+        get_output_enabled().await,
+    );
+    loop {
+        if !initialized || shown != Some(state) {
+            let result = async {
+                if !initialized {
+                    oled_commands(&mut twim, &OLED_INIT).await?;
+                    initialized = true;
+                }
+                render_status(state.0, state.1, state.2, &mut fb);
+                oled_blit(&mut twim, &fb).await
+            }
+            .await;
+            match result {
+                Ok(()) => {
+                    shown = Some(state);
+                    failing = false;
+                }
+                Err(error) => {
+                    // Only log the transition to failing, so an absent
+                    // display does not flood the terminal.
+                    if !failing {
+                        rprintln!("oled: I2C error: {:?}", error);
+                        failing = true;
+                    }
+                    initialized = false;
+                    shown = None;
+                }
+            }
+        }
+        // Wait for a change, waking periodically to retry a failed display.
+        let timeout = Duration::from_millis(OLED_RETRY_MS);
+        match embassy_time::with_timeout(timeout, events.next_message()).await {
+            Ok(WaitResult::Message(StateChanged::Levels(levels))) => state.0 = levels,
+            Ok(WaitResult::Message(StateChanged::FrameRate(frame_rate))) => state.1 = frame_rate,
+            Ok(WaitResult::Message(StateChanged::OutputEnabled(enabled))) => state.2 = enabled,
+            Ok(WaitResult::Message(StateChanged::Brightness(_))) => {}
+            Ok(WaitResult::Lagged(_)) => {
+                state = (
+                    get_rgb_levels().await,
+                    get_frame_rate().await,
+                    get_output_enabled().await,
+                );
+            }
+            Err(_) => {}
+        }
+    }
+}
@@ -0,0 +1,124 @@
+//! # PCA9685 Driver
+//!
+//! Writes the library's PCA9685 duties (see its `pca9685` module) over
+//! I2C.
+use crate::*;
+use microbit_bsp::embassy_nrf::{
+    peripherals::TWISPI1,
+    twim::{self, Twim},
+};
+
+/// I2C address of the PCA9685 with all address pins low.
+const PCA9685_ADDR: u8 = 0x40;
+/// First output used, for red; green and blue follow.
+const PCA9685_FIRST_OUTPUT: u8 = 0;
+/// Delay between checks for changed values, in milliseconds.
+const PCA9685_PERIOD_MS: u64 = 20;
+
+/// Mode register 1.
+const REG_MODE1: u8 = 0x00;
+/// Mode register 2.
+const REG_MODE2: u8 = 0x01;
+/// `LED0_ON_L`; each output has four registers from here.
+const REG_LED0_ON_L: u8 = 0x06;
+/// Prescaler register, writable only while sleeping.
+const REG_PRE_SCALE: u8 = 0xFE;
+/// MODE1: restart PWM after sleep.
+const MODE1_RESTART: u8 = 0x80;
+/// MODE1: register auto-increment.
+const MODE1_AI: u8 = 0x20;
+/// MODE1: oscillator off.
+const MODE1_SLEEP: u8 = 0x10;
+/// MODE2: totem-pole outputs, suited to driving MOSFET gates.
+const MODE2_OUTDRV: u8 = 0x04;
+
+/// Register-level PCA9685 driver.
+struct Pca9685 {
+    twim: Twim<'static, TWISPI1>,
+}
+
+impl Pca9685 {
+    /// Writes one register.
+    async fn write_register(&mut self, register: u8, value: u8) -> Result<(), twim::Error> {
+        self.twim.write(PCA9685_ADDR, &[register, value]).await
+    }
+
+    /// Resets the mode registers and sets the PWM frequency.
+    ///
+    /// Leaves the chip awake with auto-increment enabled.
+    async fn init(&mut self, frame_rate: u64) -> Result<(), twim::Error> {
+        self.write_register(REG_MODE2, MODE2_OUTDRV).await?;
+        self.set_frequency(frame_rate).await
+    }
+
+    /// Sets the PWM frequency, sleeping the oscillator around the write.
+    async fn set_frequency(&mut self, frame_rate: u64) -> Result<(), twim::Error> {
+        self.write_register(REG_MODE1, MODE1_AI | MODE1_SLEEP)
+            .await?;
+        self.write_register(REG_PRE_SCALE, pca9685_prescale(frame_rate))
+            .await?;
+        self.write_register(REG_MODE1, MODE1_AI).await?;
+        Timer::after_micros(500).await;
+        self.write_register(REG_MODE1, MODE1_AI | MODE1_RESTART)
+            .await
+    }
+
+    /// Writes the duties of the three outputs in one auto-incremented burst.
+    async fn set_duties(&mut self, duties: [u32; 3]) -> Result<(), twim::Error> {
+        let mut buf = [0u8; 1 + 3 * 4];
+        buf[0] = REG_LED0_ON_L + 4 * PCA9685_FIRST_OUTPUT;
+        for (registers, duty) in buf[1..].chunks_mut(4).zip(duties) {
+            registers.copy_from_slice(&pca9685_output_registers(duty));
+        }
+        self.twim.write(PCA9685_ADDR, &buf).await
+    }
+}
+
+/// Keeps the PCA9685 outputs in step with the shared levels, brightness,
+/// and frame rate.
+///
+/// Writes only when something changes.
+#[embassy_executor::task]
+pub async fn pca9685_task(twim: Twim<'static, TWISPI1>) -> ! {
+    let mut chip = Pca9685 { twim };
+    let mut frame_rate = None;
+    let mut duties = None;
+    let mut failing = false;
+    loop {
+        let brightness = get_master_brightness().await;
+        #[cfg(feature = "ambient")]
+        let brightness = brightness * get_ambient_scale().await / BRIGHTNESS_MAX;
+        #[cfg(feature = "bh1750")]
+        let brightness = brightness * get_lux_scale().await / BRIGHTNESS_MAX;
+        let new_duties = pca9685_duties(get_rgb_levels().await, brightness, BRIGHTNESS_CEILING);
+        let new_frame_rate = get_frame_rate().await;
+        let result = async {
+            match frame_rate {
+                None => chip.init(new_frame_rate).await?,
+                Some(rate) if rate != new_frame_rate => chip.set_frequency(new_frame_rate).await?,
+                _ => {}
+            }
+            frame_rate = Some(new_frame_rate);
+            if duties != Some(new_duties) {
+                chip.set_duties(new_duties).await?;
+                duties = Some(new_duties);
+            }
+            Ok::<(), twim::Error>(())
+        }
+        .await;
+        match result {
+            Ok(()) => failing = false,
+            Err(error) => {
+                // Only log the transition to failing, so an absent chip
+                // does not flood the terminal.
+                if !failing {
+                    rprintln!("pca9685: I2C error: {:?}", error);
+                    failing = true;
+                }
+                frame_rate = None;
+                duties = None;
+            }
+        }
+        Timer::after_millis(PCA9685_PERIOD_MS).await;
+    }
+}
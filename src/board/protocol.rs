@@ -0,0 +1,57 @@
+//! # Binary Protocol Task
+//!
+//! Serves the library's framed protocol (see its `protocol` module) over
+//! a pair of RTT channels.
+use crate::*;
+use rtt_target::{DownChannel, UpChannel};
+
+/// Delay between polls of the protocol channel and state, in milliseconds.
+const PROTOCOL_POLL_MS: u64 = 20;
+
+/// Sends one payload as a frame on the up channel.
+fn send(output: &mut UpChannel, payload: &[u8]) {
+    let mut frame = [0u8; FRAME_MAX];
+    if let Some(len) = encode_frame(payload, &mut frame) {
+        output.write(&frame[..len]);
+    }
+}
+
+/// Serves the binary protocol over a dedicated pair of RTT channels.
+///
+/// Dropped frames are counted and logged on the text console; they never
+/// disturb later frames.
+#[embassy_executor::task]
+pub async fn protocol_task(mut input: DownChannel, mut output: UpChannel) -> ! {
+    let mut decoder = FrameDecoder::default();
+    let mut session = Session::default();
+    let mut last_state = None;
+    loop {
+        let mut buf = [0u8; 32];
+        let count = input.read(&mut buf);
+        for &byte in &buf[..count] {
+            let mut payload = [0u8; PAYLOAD_MAX];
+            match decoder.push(byte, &mut payload) {
+                Some(Ok(len)) => {
+                    let mut reply = [0u8; PAYLOAD_MAX];
+                    let reply_len = dispatch(&mut session, &payload[..len], &mut reply).await;
+                    send(&mut output, &reply[..reply_len]);
+                }
+                Some(Err(error)) => rprintln!("protocol: dropped frame: {:?}", error),
+                None => {}
+            }
+        }
+
+        if session.subscribed {
+            let state = (get_rgb_levels().await, get_frame_rate().await);
+            if last_state != Some(state) {
+                last_state = Some(state);
+                let mut payload = [0u8; PAYLOAD_MAX];
+                let len = state_changed(state.0, state.1, &mut payload);
+                send(&mut output, &payload[..len]);
+            }
+        } else {
+            last_state = None;
+        }
+        Timer::after_millis(PROTOCOL_POLL_MS).await;
+    }
+}
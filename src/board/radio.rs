@@ -0,0 +1,227 @@
+//! # Radio Driver
+//!
+//! Sends and receives the library's radio packets (see its `radio` module)
+//! on the nRF52 RADIO.
+//!
+//! A transfer ends with the RADIO's DISABLED event, whose interrupt wakes
+//! the waiting task through [`RADIO_WAKER`], so the executor sleeps while
+//! the radio listens.
+use crate::*;
+use core::{future::poll_fn, task::Poll};
+use embassy_sync::waitqueue::AtomicWaker;
+use microbit_bsp::embassy_nrf::{
+    bind_interrupts,
+    interrupt::{self, typelevel::Interrupt as _},
+    pac, peripherals,
+};
+
+/// Delay between teacher broadcasts, in milliseconds.
+const RADIO_BROADCAST_MS: u64 = 100;
+/// Channel frequency as an offset from 2400 MHz.
+const RADIO_FREQUENCY: u8 = 7;
+
+/// Wakes the task waiting for a transfer to end.
+static RADIO_WAKER: AtomicWaker = AtomicWaker::new();
+
+/// RADIO interrupt handler: masks the DISABLED interrupt and wakes the
+/// waiting task, which checks the event itself.
+pub(crate) struct RadioInterruptHandler;
+
+impl interrupt::typelevel::Handler<interrupt::typelevel::RADIO> for RadioInterruptHandler {
+    unsafe fn on_interrupt() {
+        // SAFETY: only the interrupt mask is written, and the RADIO is
+        // otherwise used only by the `Radio` driver.
+        let r = unsafe { &*pac::RADIO::ptr() };
+        r.intenclr.write(|w| w.disabled().clear());
+        RADIO_WAKER.wake();
+    }
+}
+
+bind_interrupts!(struct RadioIrqs {
+    RADIO => RadioInterruptHandler;
+});
+
+/// Register-level driver for the nRF52 RADIO in 1 Mbit proprietary mode.
+pub(crate) struct Radio {
+    _radio: peripherals::RADIO,
+    buf: [u8; 1 + RADIO_PAYLOAD_LEN],
+}
+
+impl Radio {
+    /// Returns the RADIO register block.
+    fn regs(&self) -> &pac::radio::RegisterBlock {
+        // SAFETY: the RADIO singleton is owned by `self`.
+        unsafe { &*pac::RADIO::ptr() }
+    }
+
+    /// Powers up and configures the radio, and enables its interrupt.
+    fn new(
+        radio: peripherals::RADIO,
+        _irq: impl interrupt::typelevel::Binding<interrupt::typelevel::RADIO, RadioInterruptHandler>,
+    ) -> Self {
+        let this = Self {
+            _radio: radio,
+            buf: [0; 1 + RADIO_PAYLOAD_LEN],
+        };
+        let r = this.regs();
+        r.power.write(|w| w.power().enabled());
+        r.mode.write(|w| w.mode().nrf_1mbit());
+        r.txpower.write(|w| w.txpower()._0d_bm());
+        r.frequency
+            .write(|w| unsafe { w.frequency().bits(RADIO_FREQUENCY) });
+        r.pcnf0.write(|w| unsafe { w.lflen().bits(8) });
+        r.pcnf1.write(|w| unsafe {
+            w.maxlen()
+                .bits(RADIO_PAYLOAD_LEN as u8)
+                .balen()
+                .bits(4)
+                .whiteen()
+                .enabled()
+        });
+        r.base0.write(|w| unsafe { w.bits(0x7562_6974) });
+        r.prefix0.write(|w| unsafe { w.ap0().bits(0) });
+        r.txaddress.write(|w| unsafe { w.txaddress().bits(0) });
+        r.rxaddresses.write(|w| w.addr0().enabled());
+        r.crccnf.write(|w| w.len().two());
+        r.crcinit.write(|w| unsafe { w.crcinit().bits(0xFFFF) });
+        r.crcpoly.write(|w| unsafe { w.crcpoly().bits(0x1_1021) });
+        r.datawhiteiv
+            .write(|w| unsafe { w.datawhiteiv().bits(0x18) });
+        r.shorts
+            .write(|w| w.ready_start().enabled().end_disable().enabled());
+        r.intenclr.write(|w| w.disabled().clear());
+        interrupt::typelevel::RADIO::unpend();
+        // SAFETY: the handler touches only the RADIO, which `this` owns.
+        unsafe { interrupt::typelevel::RADIO::enable() };
+        this
+    }
+
+    /// Starts a transmit (`tx`) or receive and waits for it to end.
+    async fn run(&mut self, tx: bool) {
+        let r = self.regs();
+        r.packetptr
+            .write(|w| unsafe { w.bits(self.buf.as_ptr() as u32) });
+        r.events_disabled.reset();
+        if tx {
+            r.tasks_txen.write(|w| unsafe { w.bits(1) });
+        } else {
+            r.tasks_rxen.write(|w| unsafe { w.bits(1) });
+        }
+        poll_fn(|cx| {
+            RADIO_WAKER.register(cx.waker());
+            if r.events_disabled.read().bits() != 0 {
+                return Poll::Ready(());
+            }
+            // Unmasked after registering, so an event that has just come
+            // in still interrupts and wakes this task.
+            r.intenset.write(|w| w.disabled().set());
+            Poll::Pending
+        })
+        .await;
+    }
+
+    /// Broadcasts one packet.
+    pub(crate) async fn send(&mut self, packet: &[u8; 1 + RADIO_PAYLOAD_LEN]) {
+        self.buf = *packet;
+        self.run(true).await;
+    }
+
+    /// Listens for one packet with a good CRC.
+    ///
+    /// Cancelling the future (such as by a timeout) leaves the receiver
+    /// running until the next call stops it.
+    pub(crate) async fn receive(&mut self) -> &[u8] {
+        loop {
+            self.run(false).await;
+            if self.regs().crcstatus.read().crcstatus().is_crcok() {
+                let len = (self.buf[0] as usize + 1).min(self.buf.len());
+                return &self.buf[..len];
+            }
+        }
+    }
+
+    /// Stops any transfer in progress.
+    pub(crate) fn stop(&mut self) {
+        let r = self.regs();
+        r.intenclr.write(|w| w.disabled().clear());
+        r.tasks_disable.write(|w| unsafe { w.bits(1) });
+        while r.events_disabled.read().bits() == 0 {}
+        r.events_disabled.reset();
+    }
+}
+
+/// Broadcasts or mirrors the color according to [`RADIO_MODE`].
+///
+/// # Arguments
+/// * `radio` - RADIO peripheral
+#[embassy_executor::task]
+pub async fn radio_task(radio: peripherals::RADIO) -> ! {
+    let mut radio = Radio::new(radio, RadioIrqs);
+    let mut sequence = 0u8;
+    let mut last_packet = None;
+    #[cfg(feature = "remote")]
+    let mut last_event = None;
+    loop {
+        let (role, group) = {
+            let mode = RADIO_MODE.lock().await;
+            (mode.role, mode.group)
+        };
+        match role {
+            RadioRole::Off => {
+                set_link_led(false);
+                last_packet = None;
+                Timer::after_millis(RADIO_BROADCAST_MS).await;
+            }
+            RadioRole::Teacher => {
+                set_link_led(true);
+                last_packet = None;
+                let packet = RadioPacket {
+                    group,
+                    sequence,
+                    levels: get_rgb_levels().await,
+                    frame_rate: get_frame_rate().await,
+                };
+                radio.send(&packet.encode()).await;
+                sequence = sequence.wrapping_add(1);
+                Timer::after_millis(RADIO_BROADCAST_MS).await;
+            }
+            RadioRole::Student => {
+                let received =
+                    embassy_time::with_timeout(Duration::from_millis(RADIO_LISTEN_MS), async {
+                        RadioPacket::decode(radio.receive().await, group)
+                    })
+                    .await;
+                if received.is_err() {
+                    radio.stop();
+                }
+                let now = Instant::now().as_millis();
+                let mut mode = RADIO_MODE.lock().await;
+                // Drop packets if the role or group changed while listening.
+                if mode.role != RadioRole::Student || mode.group != group {
+                    continue;
+                }
+                if let Ok(Ok(packet)) = received {
+                    if !mode.linked {
+                        mode.saved = get_rgb_levels().await;
+                        mode.linked = true;
+                        rprintln!("Radio: linked to group {}", group);
+                    }
+                    set_rgb_levels_from(StateSource::Radio, |rgb| *rgb = packet.levels).await;
+                    set_frame_rate(StateSource::Radio, |rate| *rate = packet.frame_rate).await;
+                    last_packet = Some(now);
+                }
+                if mode.linked && !radio_link_alive(last_packet, now) {
+                    radio_unlink(&mut mode).await;
+                    rprintln!("Radio: teacher lost, local control");
+                }
+                let blink_on = (now / RADIO_BLINK_MS) % 2 == 0;
+                set_link_led(mode.linked || blink_on);
+            }
+            #[cfg(feature = "remote")]
+            RadioRole::Remote | RadioRole::Host => {
+                last_packet = None;
+                remote_pass(&mut radio, role, group, &mut sequence, &mut last_event).await;
+            }
+        }
+    }
+}
@@ -0,0 +1,123 @@
+//! # Remote Radio Pass
+//!
+//! Sends and receives the library's remote packets (see its `remote`
+//! module) for the radio task.
+use crate::*;
+
+/// Runs one pass of the remote or host role for the radio task.
+///
+/// Offers while pairing; once paired, a remote sends its snapshot and a
+/// host listens for input packets, forwarding changes to
+/// [`REMOTE_EVENTS`]. Drives the link LED as described in the module docs.
+///
+/// # Arguments
+/// * `radio` - Radio driver
+/// * `role` - [`RadioRole::Remote`] or [`RadioRole::Host`]
+/// * `group` - Radio group
+/// * `sequence` - Input packet counter
+/// * `last_event` - Last input forwarded by a host, for change detection
+pub(crate) async fn remote_pass(
+    radio: &mut Radio,
+    role: RadioRole,
+    group: u8,
+    sequence: &mut u8,
+    last_event: &mut Option<InputEvent>,
+) {
+    let now = Instant::now().as_millis();
+    let (pairing, snapshot) = {
+        let mut link = REMOTE_LINK.lock().await;
+        let pairing = link.pairing.tick(now);
+        if pairing != link.pairing {
+            match pairing.session() {
+                Some(session) => rprintln!("Remote: paired, session {:04x}", session),
+                None => rprintln!("Remote: no peer, unpaired"),
+            }
+            link.pairing = pairing;
+            link.last_packet_ms = None;
+            *last_event = None;
+        }
+        (pairing, link.snapshot)
+    };
+    let listen = Duration::from_millis(RADIO_LISTEN_MS);
+    match (pairing, role) {
+        (Pairing::Unpaired, _) => {
+            set_link_led(false);
+            Timer::after(listen).await;
+        }
+        (Pairing::Offering { nonce, .. }, _) => {
+            set_link_led((now / PAIR_BLINK_MS) % 2 == 0);
+            radio
+                .send(&RemoteMessage::PairOffer(nonce).encode(group))
+                .await;
+            let received = embassy_time::with_timeout(listen, async {
+                loop {
+                    if let Ok(RemoteMessage::PairOffer(peer)) =
+                        RemoteMessage::decode(radio.receive().await, group)
+                    {
+                        return peer;
+                    }
+                }
+            })
+            .await;
+            match received {
+                Ok(peer) => {
+                    let mut link = REMOTE_LINK.lock().await;
+                    link.pairing = link.pairing.peer_offer(peer, Instant::now().as_millis());
+                }
+                Err(_) => radio.stop(),
+            }
+        }
+        (Pairing::Paired { session }, RadioRole::Remote) => {
+            set_link_led(true);
+            if let Some(event) = snapshot {
+                let message = RemoteMessage::Input {
+                    session,
+                    sequence: *sequence,
+                    event,
+                };
+                radio.send(&message.encode(group)).await;
+                *sequence = sequence.wrapping_add(1);
+            }
+            Timer::after_millis(REMOTE_SEND_MS).await;
+        }
+        (Pairing::Paired { session }, _) => {
+            let received = embassy_time::with_timeout(listen, async {
+                loop {
+                    if let Ok(RemoteMessage::Input {
+                        session: packet_session,
+                        event,
+                        ..
+                    }) = RemoteMessage::decode(radio.receive().await, group)
+                    {
+                        if packet_session == session {
+                            return event;
+                        }
+                    }
+                }
+            })
+            .await;
+            let now = Instant::now().as_millis();
+            let mut link = REMOTE_LINK.lock().await;
+            match received {
+                Ok(event) => {
+                    if link.last_packet_ms.is_none() {
+                        rprintln!("Remote: linked");
+                    }
+                    link.last_packet_ms = Some(now);
+                    if *last_event != Some(event) {
+                        *last_event = Some(event);
+                        let _ = REMOTE_EVENTS.try_send(event);
+                    }
+                }
+                Err(_) => radio.stop(),
+            }
+            if link.last_packet_ms.is_some() && !radio_link_alive(link.last_packet_ms, now) {
+                link.last_packet_ms = None;
+                *last_event = None;
+                rprintln!("Remote: link lost, local control");
+            }
+            let linked = link.last_packet_ms.is_some();
+            set_link_led(linked || (now / RADIO_BLINK_MS) % 2 == 0);
+        }
+    }
+}
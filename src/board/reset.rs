@@ -0,0 +1,50 @@
+//! # Controlled Reset
+//!
+//! Plays the library's panic color (see its `reset` module) on the LED
+//! pins and resets the chip.
+use crate::*;
+#[cfg(feature = "hwpwm")]
+use microbit_bsp::embassy_nrf::pac;
+use microbit_bsp::embassy_nrf::peripherals;
+
+/// CPU clock, for the busy-wait delay.
+const CPU_HZ: u64 = 64_000_000;
+
+/// The RGB LED pins, taken over for the panic color.
+struct ResetPins([Output<'static, AnyPin>; 3]);
+
+impl ResetOutput for ResetPins {
+    fn set(&mut self, led: usize, high: bool) {
+        if high {
+            self.0[led].set_high();
+        } else {
+            self.0[led].set_low();
+        }
+    }
+    fn busy_wait(&mut self, duration: Duration) {
+        cortex_m::asm::delay((duration.as_micros() * CPU_HZ / 1_000_000) as u32);
+    }
+}
+
+/// Shows [`RESET_COLOR`] and resets the chip.
+///
+/// Call it once anything worth logging has been given time to drain.
+pub fn controlled_reset() -> ! {
+    // Pin changes go straight to the pins once the PWM peripheral lets go.
+    #[cfg(feature = "hwpwm")]
+    {
+        // SAFETY: nothing runs after this to use the PWM again.
+        let regs = unsafe { &*pac::PWM1::ptr() };
+        regs.enable.write(|w| w.enable().disabled());
+    }
+    // SAFETY: this never yields, so the RGB loop that owns these pins
+    // (P9, P8 and P16) never runs again before the reset.
+    let pin = |p: AnyPin| Output::new(p, Level::Low, OutputDrive::Standard);
+    let mut pins = ResetPins([
+        pin(AnyPin::from(unsafe { peripherals::P0_09::steal() })),
+        pin(AnyPin::from(unsafe { peripherals::P0_10::steal() })),
+        pin(AnyPin::from(unsafe { peripherals::P1_02::steal() })),
+    ]);
+    show_reset_color(&mut pins, RESET_COLOR);
+    cortex_m::peripheral::SCB::sys_reset();
+}
@@ -0,0 +1,558 @@
+//! # RGB Scanout
+//!
+//! Drives the RGB LED from the shared state: the software PWM frame loop
+//! on three GPIO pins, or the PWM1 peripheral with `hwpwm`. The timing,
+//! level processing and frame schedule it plays live in the library's
+//! `rgb` module.
+//!
+//! ## Usage Example
+//!
+//! ```rust,no_run
+//! let rgb_pins = [red_pin, green_pin, blue_pin];
+//! let rgb = Rgb::new(rgb_pins, 60); // 60 FPS initial rate
+//! rgb.run().await; // Start the RGB control loop
+//! ```
+use crate::*;
+use core::sync::atomic::{AtomicU32, Ordering};
+#[cfg(not(feature = "hwpwm"))]
+use embassy_sync::channel::Channel;
+#[cfg(feature = "camsync")]
+use microbit_bsp::embassy_nrf::gpio::Input;
+
+/// Type alias for the RGB LED pin array.
+///
+/// Represents the three GPIO output pins that control the RGB LED:
+/// - Index 0: Red LED pin
+/// - Index 1: Green LED pin  
+/// - Index 2: Blue LED pin
+///
+/// Each pin is configured as a standard output with low initial state,
+/// with its input buffer connected so the pin test can read the pad back
+/// (see [`toggle_led_pin`]).
+#[cfg(not(feature = "hwpwm"))]
+type RgbPins = [Flex<'static, AnyPin>; 3];
+/// Hardware PWM driving the RGB LED, channels 0/1/2 = red/green/blue.
+#[cfg(feature = "hwpwm")]
+type RgbPins = SimplePwm<'static, PWM1>;
+#[cfg(feature = "hwpwm")]
+use microbit_bsp::embassy_nrf::{
+    pac,
+    peripherals::PWM1,
+    pwm::{Prescaler, SimplePwm},
+};
+
+/// Delay between hardware PWM updates, in milliseconds.
+#[cfg(feature = "hwpwm")]
+const PWM_UPDATE_MS: u64 = 10;
+/// `PSEL.OUT` bit that disconnects a PWM channel from its pin.
+#[cfg(feature = "hwpwm")]
+const PSEL_DISCONNECT: u32 = 1 << 31;
+
+/// One software-PWM channel: its pin and everything measured on it.
+///
+/// Kept as one value, so the schedule's channel index picks the pin and
+/// its measurements together and they can't fall out of step.
+#[cfg(not(feature = "hwpwm"))]
+struct LedChannel {
+    /// Channel name for reports.
+    name: &'static str,
+    /// GPIO pin driving the LED, readable for the pin test.
+    pin: Flex<'static, AnyPin>,
+    /// The channel's rising-edge count in [`PWM_RISES`].
+    rises: &'static AtomicU32,
+    /// When the pin last went high, and the schedule tick it was due at.
+    lit: (Instant, u64),
+    /// On-time accuracy since the last report.
+    on_time_error: OnTimeError,
+}
+
+#[cfg(not(feature = "hwpwm"))]
+impl LedChannel {
+    /// Wraps a channel's pin, with nothing measured yet.
+    fn new(name: &'static str, pin: Flex<'static, AnyPin>, rises: &'static AtomicU32) -> Self {
+        Self {
+            name,
+            pin,
+            rises,
+            lit: (Instant::now(), 0),
+            on_time_error: OnTimeError::default(),
+        }
+    }
+    /// Lights the LED for a change due at schedule tick `at`.
+    fn rise(&mut self, at: u64) {
+        self.lit = (Instant::now(), at);
+        self.pin.set_high();
+        self.rises.fetch_add(1, Ordering::Relaxed);
+    }
+    /// Darkens the LED for a change due at schedule tick `at`, measuring
+    /// the on-time if asked to.
+    fn fall(&mut self, at: u64, measure: bool) {
+        self.pin.set_low();
+        if measure {
+            let (lit_at, rise_at) = self.lit;
+            self.on_time_error.push(
+                Duration::from_ticks(at - rise_at).as_micros(),
+                lit_at.elapsed().as_micros(),
+            );
+        }
+    }
+    /// Drives the pin high and low `toggles` times, reading the pad back
+    /// at the end of each half-period, and leaves it low.
+    async fn toggle(&mut self, toggles: u32, half_period: Duration) -> PinReadback {
+        let mut readback = PinReadback::default();
+        for _ in 0..toggles {
+            for high in [true, false] {
+                if high {
+                    self.pin.set_high();
+                } else {
+                    self.pin.set_low();
+                }
+                Timer::after(half_period).await;
+                readback.reads += 1;
+                if self.pin.is_high() == high {
+                    readback.matched += 1;
+                }
+            }
+        }
+        readback
+    }
+}
+
+/// Pin tests waiting for the RGB loop, by channel index.
+#[cfg(not(feature = "hwpwm"))]
+static PIN_TEST_REQUESTS: Channel<ThreadModeRawMutex, usize, 1> = Channel::new();
+/// Pin test results from the RGB loop.
+#[cfg(not(feature = "hwpwm"))]
+static PIN_TEST_RESULTS: Channel<ThreadModeRawMutex, PinReadback, 1> = Channel::new();
+
+/// Has the RGB loop drive one LED pin through the pin test between two
+/// frames: [`SELFTEST_PIN_TOGGLES`] high/low cycles of
+/// [`SELFTEST_PIN_HALF_PERIOD`] each, with the other pins low.
+///
+/// The frame loop stops meanwhile, so the blink is all the LED shows.
+///
+/// # Arguments
+/// * `led` - Channel index (0 = red, 1 = green, 2 = blue); any other
+///   index takes no reads
+///
+/// # Returns
+/// The pad reads taken.
+#[cfg(not(feature = "hwpwm"))]
+pub async fn toggle_led_pin(led: usize) -> PinReadback {
+    PIN_TEST_REQUESTS.send(led).await;
+    PIN_TEST_RESULTS.receive().await
+}
+
+/// RGB LED controller using software PWM (or hardware PWM with `hwpwm`).
+///
+/// Manages three LEDs with individual intensity control and configurable
+/// frame rate. Reads RGB levels and frame rate from shared state.
+pub struct Rgb {
+    /// Hardware PWM driving the RGB LEDs.
+    #[cfg(feature = "hwpwm")]
+    rgb: RgbPins,
+    /// Software-PWM channels [red, green, blue].
+    #[cfg(not(feature = "hwpwm"))]
+    leds: [LedChannel; 3],
+    /// Inputs, timing and schedule of the current frame.
+    scanout: Scanout,
+    /// Per-channel on-times in timer ticks.
+    #[cfg(feature = "hwpwm")]
+    on_times: [u64; 3],
+    /// Channels currently connected to their pins.
+    #[cfg(feature = "hwpwm")]
+    channels: [bool; 3],
+    /// Camera-sync trigger input, if one is wired.
+    #[cfg(feature = "camsync")]
+    trigger: Option<Input<'static, AnyPin>>,
+    /// Whether to measure on-times this frame ([`Verbosity::Debug`]).
+    #[cfg(not(feature = "hwpwm"))]
+    measure_on_times: bool,
+    /// Time of the last on-time accuracy report.
+    #[cfg(not(feature = "hwpwm"))]
+    on_time_reported: Instant,
+    /// Frames and timer waits played since the last report.
+    #[cfg(not(feature = "hwpwm"))]
+    frame_waits: (u32, u32),
+    /// Edge-jitter benchmark probe, if one is wired.
+    #[cfg(feature = "bench")]
+    bench: Option<BenchProbe>,
+    /// Timer wake-up overhead in ticks, taken off every frame deadline.
+    #[cfg(not(feature = "hwpwm"))]
+    timer_overhead: u64,
+}
+
+impl Rgb {
+    /// Creates a new RGB controller.
+    ///
+    /// # Arguments
+    /// * `rgb` - Array of GPIO pins [red, green, blue], set up as outputs
+    ///   with their input buffers connected (with
+    ///   `hwpwm`, a three-channel [`SimplePwm`] in the same order)
+    /// * `frame_rate` - Initial frame rate in FPS
+    ///
+    /// # Examples
+    /// ```rust,no_run
+    /// let rgb_pins = [red_pin, green_pin, blue_pin];
+    /// let rgb = Rgb::new(rgb_pins, 60);
+    /// ```
+    pub fn new(rgb: RgbPins, frame_rate: u64) -> Self {
+        #[cfg(not(feature = "hwpwm"))]
+        let [red, green, blue] = rgb;
+        Self {
+            #[cfg(feature = "hwpwm")]
+            rgb,
+            #[cfg(not(feature = "hwpwm"))]
+            leds: [
+                LedChannel::new("red", red, &PWM_RISES[0]),
+                LedChannel::new("green", green, &PWM_RISES[1]),
+                LedChannel::new("blue", blue, &PWM_RISES[2]),
+            ],
+            scanout: Scanout::new(frame_rate, BRIGHTNESS_CEILING),
+            #[cfg(feature = "hwpwm")]
+            on_times: [0; 3],
+            #[cfg(feature = "hwpwm")]
+            channels: [true; 3],
+            #[cfg(feature = "camsync")]
+            trigger: None,
+            #[cfg(not(feature = "hwpwm"))]
+            measure_on_times: false,
+            #[cfg(not(feature = "hwpwm"))]
+            on_time_reported: Instant::now(),
+            #[cfg(not(feature = "hwpwm"))]
+            frame_waits: (0, 0),
+            #[cfg(feature = "bench")]
+            bench: None,
+            #[cfg(not(feature = "hwpwm"))]
+            timer_overhead: 0,
+        }
+    }
+    /// Adds a camera-sync trigger input.
+    ///
+    /// # Arguments
+    /// * `trigger` - Input watched for rising edges while sync is enabled
+    #[cfg(feature = "camsync")]
+    pub fn with_trigger(mut self, trigger: Input<'static, AnyPin>) -> Self {
+        self.trigger = Some(trigger);
+        self
+    }
+    /// Adds the edge-jitter benchmark, which then takes over the levels and
+    /// frame rate; see the [`bench`](crate::bench) module.
+    ///
+    /// # Arguments
+    /// * `probe` - Marker output and capture of its edges
+    #[cfg(feature = "bench")]
+    pub fn with_bench(mut self, probe: BenchProbe) -> Self {
+        self.bench = Some(probe);
+        self
+    }
+    /// Sets the timer wake-up overhead to compensate for, which brings
+    /// every frame deadline forward by it (see [`compensated_deadline`]).
+    ///
+    /// # Arguments
+    /// * `ticks` - Overhead in timer ticks, from [`calibrate_timer_overhead`]
+    #[cfg(not(feature = "hwpwm"))]
+    pub fn with_timer_overhead(mut self, ticks: u64) -> Self {
+        self.timer_overhead = ticks;
+        self
+    }
+    /// Plays one frame of the precomputed [`FrameSchedule`].
+    ///
+    /// This is the core PWM implementation that controls LED brightness through
+    /// time-based on/off control. Each LED is turned on for a duration
+    /// proportional to the desired intensity, then turned off for the
+    /// remaining time.
+    ///
+    /// # PWM Algorithm
+    ///
+    /// 1. **Walk**: For each [`PinChange`], wait until its deadline (unless
+    ///    an earlier change at the same tick already did) and set the pin
+    /// 2. **Frame End**: Wait until [`FrameSchedule::frame_ticks`] (a tick
+    ///    later when [`FrameCarry`] says so), so dark time after the last
+    ///    change keeps the frame period
+    ///
+    /// # Arguments
+    ///
+    /// * `mode` - Dither algorithm picking this frame's extra ticks (see
+    ///   [`Scanout::frame_steps`])
+    ///
+    /// # Timing Behavior
+    ///
+    /// - **Intensity 0**: LED has no pin changes; its slot is dark time
+    /// - **Intensity 15**: LED on for 15 of its 16 ticks
+    /// - **Intensity 8**: LED on for 50% of its slot
+    ///
+    /// # Returns
+    /// The number of timer waits used.
+    ///
+    /// # Performance Notes
+    ///
+    /// - Uses async timers with deadlines measured from the frame start,
+    ///   so a late wake-up doesn't delay the changes after it
+    /// - Each deadline is brought forward by the calibrated timer overhead
+    /// - Nothing is computed beyond adding the dither tick
+    #[cfg(not(feature = "hwpwm"))]
+    async fn play_frame(&mut self, mode: DitherMode) -> usize {
+        let frame_start = Instant::now();
+        #[cfg(feature = "bench")]
+        if let Some(bench) = self.bench.as_mut() {
+            bench.frame_start();
+        }
+        for step in self.scanout.frame_steps(mode) {
+            let (at, led, high) = match step {
+                FrameStep::Wait(at) => {
+                    let at = compensated_deadline(at, self.timer_overhead);
+                    Timer::at(frame_start + Duration::from_ticks(at)).await;
+                    continue;
+                }
+                FrameStep::Pin { at, led, high } => (at, led, high),
+            };
+            let channel = &mut self.leds[led];
+            if high {
+                channel.rise(at);
+            } else {
+                channel.fall(at, self.measure_on_times);
+            }
+            #[cfg(feature = "bench")]
+            if let Some(bench) = self.bench.as_mut() {
+                bench.edge(Duration::from_ticks(at).as_micros());
+            }
+        }
+        self.scanout.schedule().waits()
+    }
+    /// Writes the frame's on-times to the hardware PWM and waits for the
+    /// next update.
+    #[cfg(feature = "hwpwm")]
+    async fn update_pwm(&mut self) {
+        let channels = *PWM_CHANNELS.lock().await;
+        if channels != self.channels {
+            self.connect_channels(channels);
+        }
+        let timing = self.scanout.timing();
+        let top = pwm_top(timing.frame_rate);
+        if self.rgb.max_duty() != top {
+            self.rgb.set_prescaler(Prescaler::Div128);
+            self.rgb.set_max_duty(top);
+        }
+        for (channel, &on_time) in self.on_times.iter().enumerate() {
+            let slot_time = timing.slot_ticks(channel);
+            self.rgb
+                .set_duty(channel, pwm_compare(on_time, slot_time, top));
+        }
+        Timer::after_millis(PWM_UPDATE_MS).await;
+    }
+    /// Connects or disconnects each channel's pin to match `channels`.
+    ///
+    /// # Arguments
+    /// * `channels` - Channel enables [red, green, blue]
+    #[cfg(feature = "hwpwm")]
+    fn connect_channels(&mut self, channels: [bool; 3]) {
+        // SAFETY: only the pin selection is changed, which `SimplePwm`
+        // sets once at creation and never reads back.
+        let regs = unsafe { &*pac::PWM1::ptr() };
+        // Pin selection may only change while the peripheral is stopped.
+        self.rgb.disable();
+        for (led, &enabled) in channels.iter().enumerate() {
+            regs.psel.out[led].modify(|r, w| {
+                let psel = if enabled {
+                    r.bits() & !PSEL_DISCONNECT
+                } else {
+                    r.bits() | PSEL_DISCONNECT
+                };
+                // SAFETY: the pin number bits are kept as they were.
+                unsafe { w.bits(psel) }
+            });
+        }
+        self.rgb.enable();
+        self.channels = channels;
+    }
+    /// Logs each lit channel's on-time accuracy once per [`ON_TIME_REPORT`]
+    /// while measuring, and starts a new interval.
+    #[cfg(not(feature = "hwpwm"))]
+    fn report_on_time_errors(&mut self) {
+        if !self.measure_on_times {
+            self.restart_on_time_errors();
+            return;
+        }
+        if self.on_time_reported.elapsed() < ON_TIME_REPORT {
+            return;
+        }
+        for channel in &self.leds {
+            if channel.on_time_error.frames > 0 {
+                rprintln_deferred!("pwm {}: {}", channel.name, channel.on_time_error);
+            }
+        }
+        let (frames, waits) = self.frame_waits;
+        if frames > 0 {
+            let elapsed_us = self.on_time_reported.elapsed().as_micros().max(1);
+            rprintln_deferred!(
+                "pwm: {:.1} timer waits per frame, {:.1} fps of {}",
+                waits as f32 / frames as f32,
+                frames as f32 * 1_000_000.0 / elapsed_us as f32,
+                self.scanout.timing().frame_rate
+            );
+        }
+        self.restart_on_time_errors();
+    }
+    /// Clears the on-time measurements and starts a new report interval.
+    #[cfg(not(feature = "hwpwm"))]
+    fn restart_on_time_errors(&mut self) {
+        for channel in &mut self.leds {
+            channel.on_time_error = OnTimeError::default();
+        }
+        self.frame_waits = (0, 0);
+        self.on_time_reported = Instant::now();
+    }
+    /// Publishes the timing in use to [`RGB_TIMING`].
+    async fn publish_timing(&self) {
+        *RGB_TIMING.lock().await = self.scanout.timing();
+    }
+    /// Main RGB control loop.
+    ///
+    /// Continuously updates RGB levels and frame rate from shared state,
+    /// then executes PWM cycles for all three LEDs.
+    ///
+    /// # Operation
+    /// 1. Run a requested pin test (see [`toggle_led_pin`])
+    /// 2. Read current RGB levels and master brightness from shared state
+    /// 3. Hand them to [`Scanout::update`], which recomputes the timing
+    ///    for a new frame rate (published here) and, if anything changed,
+    ///    rebuilds the [`FrameSchedule`] (ending with the brightness
+    ///    ceiling and the full-on limit); then pick the frame's dither ticks
+    /// 5. With camera sync enabled, wait for the trigger
+    /// 6. Play the frame's pin changes, lighting each LED in sequence
+    ///    and measuring the real on-times at [`Verbosity::Debug`]
+    /// 7. Yield, so the UI runs at least once a frame (see the [`latency`]
+    ///    module), and repeat
+    ///
+    /// This function never returns under normal operation.
+    ///
+    /// # Never Returns
+    ///
+    /// This function runs indefinitely under normal operation. It will only
+    /// exit if the hardware fails or the system panics.
+    pub async fn run(mut self) -> ! {
+        self.publish_timing().await;
+        #[cfg(feature = "telemetry")]
+        let mut frame_start = Instant::now();
+        loop {
+            #[cfg(feature = "telemetry")]
+            {
+                let now = Instant::now();
+                record_frame_time((now - frame_start).as_micros() as u32).await;
+                frame_start = now;
+            }
+            #[cfg(feature = "bench")]
+            if let Some(bench) = self.bench.as_mut() {
+                bench.step().await;
+            }
+            #[cfg(not(feature = "hwpwm"))]
+            if let Ok(led) = PIN_TEST_REQUESTS.try_receive() {
+                for channel in self.leds.iter_mut() {
+                    channel.pin.set_low();
+                }
+                let readback = match self.leds.get_mut(led) {
+                    Some(channel) => {
+                        channel
+                            .toggle(SELFTEST_PIN_TOGGLES, SELFTEST_PIN_HALF_PERIOD)
+                            .await
+                    }
+                    None => PinReadback::default(),
+                };
+                PIN_TEST_RESULTS.send(readback).await;
+            }
+            let levels = get_rgb_levels().await.map(|level| level.min(LEVELS - 1));
+            #[cfg(feature = "idle")]
+            let levels = effect_layer(levels).await;
+            #[cfg(feature = "battery")]
+            let levels = BATTERY_WARNING.overlay(
+                Instant::now().as_millis(),
+                get_battery_low().await,
+                levels,
+            );
+            let brightness = get_master_brightness().await.min(BRIGHTNESS_MAX);
+            #[cfg(feature = "ambient")]
+            let brightness = brightness.saturating_mul(get_ambient_scale().await) / BRIGHTNESS_MAX;
+            #[cfg(feature = "bh1750")]
+            let brightness = brightness.saturating_mul(get_lux_scale().await) / BRIGHTNESS_MAX;
+            let brightness = if get_output_enabled().await {
+                brightness
+            } else {
+                0
+            };
+            let update = self.scanout.update(FrameInputs {
+                levels,
+                brightness,
+                frame_rate: get_frame_rate().await,
+                gamma: *GAMMA_CURVE.lock().await,
+            });
+            if update.levels {
+                count_stat!(LevelUpdates);
+            }
+            if update.frame_rate {
+                count_stat!(FrameRateChanges);
+                rprintln_deferred!(
+                    "RGB: Frame rate updated to {} fps",
+                    self.scanout.timing().frame_rate
+                );
+                self.publish_timing().await;
+            }
+            #[cfg(not(feature = "hwpwm"))]
+            if let Some(phases) = update.phases {
+                if phases > PWM_PHASES {
+                    rprintln_deferred!(
+                        "RGB: {} PWM phases below {} fps to reduce flicker",
+                        phases,
+                        FLICKER_THRESHOLD_FPS
+                    );
+                } else {
+                    rprintln_deferred!("RGB: back to {} PWM phases", phases);
+                }
+            }
+            if update.rebuilt {
+                latency_rendered().await;
+            }
+            #[cfg(feature = "hwpwm")]
+            if update.rebuilt {
+                let scanout = &self.scanout;
+                self.on_times = frame_on_times(
+                    scanout.timing(),
+                    scanout.levels(),
+                    scanout.brightness(),
+                    scanout.ceiling(),
+                );
+            }
+            #[cfg(not(feature = "hwpwm"))]
+            let mode = {
+                self.measure_on_times = *VERBOSITY.lock().await >= Verbosity::Debug;
+                *DITHER_MODE.lock().await
+            };
+            #[cfg(feature = "camsync")]
+            let trigger = match self.trigger.as_mut() {
+                Some(input) => wait_for_trigger(input).await,
+                None => None,
+            };
+            #[cfg(feature = "camsync")]
+            let frame_start = Instant::now();
+            #[cfg(not(feature = "hwpwm"))]
+            {
+                let frame_waits = self.play_frame(mode).await;
+                if self.measure_on_times {
+                    let (frames, waits) = &mut self.frame_waits;
+                    *frames += 1;
+                    *waits += frame_waits as u32;
+                }
+                self.report_on_time_errors();
+            }
+            #[cfg(feature = "camsync")]
+            if let Some(woke) = trigger {
+                record_trigger(woke, frame_start).await;
+            }
+            #[cfg(feature = "hwpwm")]
+            self.update_pwm().await;
+            // Past-due timer waits don't yield, so an overrunning frame
+            // could otherwise keep the UI from ever running.
+            embassy_futures::yield_now().await;
+        }
+    }
+}
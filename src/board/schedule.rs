@@ -0,0 +1,35 @@
+//! # Brightness Schedule Task
+//!
+//! Writes the library's scheduled brightness (see its `schedule` module).
+use crate::*;
+
+/// Delay between brightness updates, in milliseconds.
+const SCHEDULE_UPDATE_MS: u64 = 500;
+
+/// Periodically writes the scheduled brightness while the schedule is
+/// enabled.
+#[embassy_executor::task]
+pub async fn schedule_task() -> ! {
+    let mut last = None;
+    loop {
+        Timer::after_millis(SCHEDULE_UPDATE_MS).await;
+        // Hold the schedule lock while writing so a concurrent disable
+        // can't be overwritten by a stale scheduled value.
+        let schedule = SCHEDULE.lock().await;
+        if !schedule.enabled {
+            last = None;
+            continue;
+        }
+        let brightness = brightness_at(
+            Instant::now().as_millis(),
+            schedule.period_s as u64 * 1000,
+            schedule.curve,
+            SCHEDULE_NIGHT,
+            SCHEDULE_DAY,
+        );
+        if last != Some(brightness) {
+            last = Some(brightness);
+            set_master_brightness(|b| *b = brightness).await;
+        }
+    }
+}
@@ -0,0 +1,78 @@
+//! # Board Self-Checks
+//!
+//! The `SELFTEST` checks (see the library's `selftest` module) that need
+//! the knob or the LED pins.
+use crate::*;
+
+/// Checks that the ADC calibrates within [`KNOB_RECAL`]'s timeout.
+pub async fn check_knob_calibration(knob: &mut Knob<Adc>) -> CheckResult {
+    let start = Instant::now();
+    let ok = knob.recalibrate().await;
+    let result = CheckResult::measured(
+        SelfCheck::KnobCalibration,
+        start.elapsed().as_millis(),
+        Limit::AtMost(KNOB_RECAL.timeout.as_millis()),
+        "ms",
+    );
+    if ok {
+        result
+    } else {
+        result.failed()
+    }
+}
+
+/// Checks that the raw knob reading is quiet.
+pub async fn check_knob_noise(knob: &mut Knob<Adc>) -> CheckResult {
+    let stats = knob.stats(SELFTEST_KNOB_SAMPLES).await;
+    CheckResult::measured(
+        SelfCheck::KnobNoise,
+        num_traits::Float::ceil(stats.std_dev()) as u64,
+        Limit::AtMost(SELFTEST_KNOB_SD_MAX),
+        "counts sd",
+    )
+}
+
+/// Checks that one LED pin toggles: driven high and low
+/// [`SELFTEST_PIN_TOGGLES`] times, every read back of the pad matches.
+///
+/// Announces the blink first, for whoever is watching. With `hwpwm` the
+/// channel is blinked through its level instead, leaving it dark, and
+/// the result is to be observed; the caller restores the levels.
+///
+/// # Arguments
+/// * `check` - [`SelfCheck::RedPin`], [`SelfCheck::GreenPin`] or
+///   [`SelfCheck::BluePin`]
+/// * `led` - Channel index (0 = red, 1 = green, 2 = blue)
+pub async fn check_pin(check: SelfCheck, led: usize) -> CheckResult {
+    #[cfg(feature = "hwpwm")]
+    if !get_output_enabled().await || get_master_brightness().await == 0 {
+        return CheckResult::skipped(check);
+    }
+    rprintln!(
+        "selftest: {} blinking {} times",
+        check.name(),
+        SELFTEST_PIN_TOGGLES
+    );
+    #[cfg(not(feature = "hwpwm"))]
+    {
+        let readback = toggle_led_pin(led).await;
+        CheckResult::measured(
+            check,
+            readback.matched as u64,
+            Limit::AtLeast(SELFTEST_PIN_TOGGLES as u64 * 2),
+            "reads",
+        )
+    }
+    #[cfg(feature = "hwpwm")]
+    {
+        for _ in 0..SELFTEST_PIN_TOGGLES {
+            for level in [LEVELS - 1, 0] {
+                let mut levels = [0; 3];
+                levels[led] = level;
+                set_rgb_levels(|rgb| *rgb = levels).await;
+                Timer::after(SELFTEST_PIN_HALF_PERIOD).await;
+            }
+        }
+        CheckResult::observed(check)
+    }
+}
@@ -0,0 +1,26 @@
+//! # Sleep Timer Task
+//!
+//! Runs the library's sleep timer (see its `sleep` module).
+use crate::*;
+
+/// Delay between sleep timer checks, in milliseconds.
+const SLEEP_TICK_MS: u64 = 100;
+
+/// Fades the output out and turns it off when the sleep timer runs out.
+#[embassy_executor::task]
+pub async fn sleep_task() -> ! {
+    loop {
+        Timer::after_millis(SLEEP_TICK_MS).await;
+        // Hold the lock while writing so a cancel can't be overwritten by a
+        // stale fade step.
+        let mut sleep = SLEEP.lock().await;
+        let was_asleep = sleep.phase() == SleepPhase::Asleep;
+        let brightness = get_master_brightness().await;
+        if let Some(brightness) = sleep.tick(Instant::now().as_millis(), brightness) {
+            set_master_brightness(|b| *b = brightness).await;
+            if !was_asleep && sleep.phase() == SleepPhase::Asleep {
+                rprintln!("Sleep timer: off");
+            }
+        }
+    }
+}
@@ -0,0 +1,19 @@
+//! # Statistics Task
+//!
+//! Prints the [`STATS`] counters periodically (see the library's `stats`
+//! module).
+use crate::*;
+
+/// Prints the counters every [`STATS_HEARTBEAT`] interval.
+#[embassy_executor::task]
+pub async fn stats_task(heartbeat: StatsHeartbeat) -> ! {
+    loop {
+        Timer::after(heartbeat.interval).await;
+        let dump = if heartbeat.reset {
+            STATS.take()
+        } else {
+            STATS.snapshot()
+        };
+        rprintln!("{}", StatsLine(dump));
+    }
+}
@@ -0,0 +1,16 @@
+//! # Settings Page
+//!
+//! The settings page of internal flash as a [`FlashStorage`].
+use crate::*;
+
+/// Storage on the settings page.
+// SAFETY: the settings page is reserved in memory.x and used only here.
+pub static SETTINGS_STORAGE: Mutex<ThreadModeRawMutex, FlashStorage<NvmcFlash>> =
+    Mutex::new(FlashStorage::new(unsafe {
+        NvmcFlash::new(STORAGE_FLASH_BASE, 1)
+    }));
+
+/// Returns the settings page's wear counts.
+pub async fn get_flash_wear() -> WearCount {
+    SETTINGS_STORAGE.lock().await.wear()
+}
@@ -0,0 +1,51 @@
+//! # Telemetry Output
+//!
+//! Sends the library's telemetry records (see its `telemetry` module) on
+//! an RTT channel.
+use crate::*;
+use embassy_sync::pubsub::WaitResult;
+use rtt_target::UpChannel;
+
+/// Sends telemetry records at the configured rate while enabled.
+///
+/// # Arguments
+/// * `output` - RTT up channel dedicated to telemetry
+#[embassy_executor::task]
+pub async fn telemetry_task(mut output: UpChannel) -> ! {
+    let mut events = subscribe_state();
+    let mut levels = get_rgb_levels().await;
+    let mut sequence = 0u8;
+    let mut next = Instant::now();
+    loop {
+        while let Some(event) = events.try_next_message() {
+            match event {
+                WaitResult::Message(StateChanged::Levels(new_levels)) => levels = new_levels,
+                WaitResult::Message(StateChanged::FrameRate(_))
+                | WaitResult::Message(StateChanged::OutputEnabled(_))
+                | WaitResult::Message(StateChanged::Brightness(_)) => {}
+                WaitResult::Lagged(_) => levels = get_rgb_levels().await,
+            }
+        }
+        let telemetry = *TELEMETRY.lock().await;
+        next += Duration::from_micros(1_000_000 / telemetry.rate_hz as u64);
+        if telemetry.enabled {
+            let record = TelemetryRecord {
+                sequence,
+                timestamp_ms: Instant::now().as_millis() as u32,
+                raw_knob: telemetry.raw_knob,
+                filtered_knob: telemetry.filtered_knob.unwrap_or(telemetry.raw_knob),
+                levels: levels.map(|level| level as u8),
+                frame_us: telemetry.frame_us,
+            };
+            // A full channel drops the record; the sequence gap shows it.
+            output.write(&record.encode());
+            sequence = sequence.wrapping_add(1);
+        }
+        // Don't try to catch up after a stall.
+        let now = Instant::now();
+        if next < now {
+            next = now;
+        }
+        Timer::at(next).await;
+    }
+}
@@ -0,0 +1,37 @@
+//! # Temperature Task
+//!
+//! Reads the chip temperature sensor for the library's temperature mode
+//! (see its `temperature` module).
+use crate::*;
+use microbit_bsp::embassy_nrf::temp::Temp;
+
+/// Periodically reads the chip temperature and, while temperature mode is
+/// enabled, writes the gradient color to the shared RGB levels.
+#[embassy_executor::task]
+pub async fn temperature_task(mut temp: Temp<'static>) -> ! {
+    let mut last = None;
+    loop {
+        Timer::after_millis(TEMP_PERIOD_MS).await;
+        if !TEMP_MODE.lock().await.enabled() {
+            last = None;
+            continue;
+        }
+        let quarter_degrees = temp.read().await.to_bits();
+        // Hold the mode lock while writing so a concurrent exit can't be
+        // overwritten by a stale gradient color.
+        let mode = TEMP_MODE.lock().await;
+        if !mode.enabled() {
+            continue;
+        }
+        let levels = temperature_levels(quarter_degrees, mode.low, mode.high);
+        set_rgb_levels(|rgb| *rgb = levels).await;
+        if last != Some(levels) {
+            last = Some(levels);
+            rprintln!(
+                "temperature: {:.2} C -> {:?}",
+                quarter_degrees as f32 / 4.0,
+                levels
+            );
+        }
+    }
+}
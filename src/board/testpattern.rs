@@ -0,0 +1,42 @@
+//! # Test Pattern Task
+//!
+//! Steps the LED through the library's reference colors (see its
+//! `testpattern` module).
+use crate::*;
+
+/// Delay between checks of the pattern state, in milliseconds.
+const PATTERN_POLL_MS: u64 = 100;
+
+/// Steps through [`TEST_PATTERN`] while the pattern is enabled, restarting
+/// from the first color each time it is started.
+#[embassy_executor::task]
+pub async fn testpattern_task() -> ! {
+    // Index of the next color and when the current one was shown.
+    let mut step: Option<(usize, Instant)> = None;
+    loop {
+        Timer::after_millis(PATTERN_POLL_MS).await;
+        // Hold the mode lock while writing so a concurrent stop can't be
+        // overwritten by a stale pattern color.
+        let mode = PATTERN_MODE.lock().await;
+        if !mode.enabled() {
+            step = None;
+            continue;
+        }
+        let dwell = Duration::from_secs(mode.dwell_s as u64);
+        let index = match step {
+            None => 0,
+            Some((next, shown)) if shown.elapsed() >= dwell => next,
+            Some(_) => continue,
+        };
+        let (label, levels) = TEST_PATTERN[index];
+        set_rgb_levels(|rgb| *rgb = levels).await;
+        rprintln!(
+            "pattern {}/{}: {} {:?}",
+            index + 1,
+            TEST_PATTERN.len(),
+            label,
+            levels
+        );
+        step = Some(((index + 1) % TEST_PATTERN.len(), Instant::now()));
+    }
+}
@@ -0,0 +1,47 @@
+//! # Speaker Tone Task
+//!
+//! Plays the library's queued level tones (see its `tone` module) on the
+//! speaker PWM.
+use crate::*;
+use microbit_bsp::embassy_nrf::{peripherals::PWM0, pwm::SimplePwm};
+
+/// Plays queued level changes on the speaker.
+///
+/// Waits for a level, collects whatever else was queued meanwhile, and plays
+/// the result: one [`TONE_NOTE_MS`] tone, or a sweep sharing [`TONE_SWEEP_MS`].
+#[embassy_executor::task]
+pub async fn tone_task(pwm: SimplePwm<'static, PWM0>) -> ! {
+    loop {
+        let mut pending = [0u32; TONE_QUEUE_DEPTH];
+        pending[0] = TONE_QUEUE.receive().await;
+        let mut count = 1;
+        while count < pending.len() {
+            match TONE_QUEUE.try_receive() {
+                Ok(level) => {
+                    pending[count] = level;
+                    count += 1;
+                }
+                Err(_) => break,
+            }
+        }
+        if *TONE_MUTED.lock().await {
+            continue;
+        }
+
+        let mut notes = [0u32; TONE_SWEEP_MAX];
+        let count = schedule_notes(&pending[..count], &mut notes);
+        let note_ms = if count == 1 {
+            TONE_NOTE_MS
+        } else {
+            TONE_SWEEP_MS / count as u64
+        };
+        pwm.enable();
+        for &level in &notes[..count] {
+            pwm.set_period(level_frequency(level));
+            pwm.set_duty(0, pwm.max_duty() / 2);
+            Timer::after_millis(note_ms).await;
+        }
+        pwm.set_duty(0, 0);
+        pwm.disable();
+    }
+}
@@ -0,0 +1,50 @@
+//! # Touch Logo Pin
+//!
+//! Times the touch logo's charge on P1.04 and feeds it to the library's
+//! `TouchDetector`.
+use crate::*;
+use microbit_bsp::embassy_nrf::gpio::{Flex, Pull};
+
+/// Time the pad is held low to discharge it before each measurement, in µs.
+const TOUCH_DISCHARGE_US: u64 = 10;
+
+/// The touch logo pin and its detector.
+pub struct TouchLogo {
+    pin: Flex<'static, AnyPin>,
+    detector: TouchDetector,
+}
+
+impl TouchLogo {
+    /// Wraps the touch logo pin.
+    pub fn new(pin: Flex<'static, AnyPin>) -> Self {
+        Self {
+            pin,
+            detector: TouchDetector::new(),
+        }
+    }
+
+    /// Discharges the pad and times how long it takes to charge again.
+    ///
+    /// # Returns
+    /// The charge time in µs, capped at [`TOUCH_CHARGE_MAX_US`].
+    pub async fn sample(&mut self) -> u32 {
+        self.pin.set_low();
+        self.pin.set_as_output(OutputDrive::Standard);
+        Timer::after_micros(TOUCH_DISCHARGE_US).await;
+
+        self.pin.set_as_input(Pull::None);
+        let start = Instant::now();
+        let limit = Duration::from_micros(TOUCH_CHARGE_MAX_US as u64);
+        let _ = embassy_time::with_timeout(limit, self.pin.wait_for_high()).await;
+        start.elapsed().as_micros().min(TOUCH_CHARGE_MAX_US as u64) as u32
+    }
+
+    /// Samples the logo once.
+    ///
+    /// # Returns
+    /// `true` on the sample a touch starts.
+    pub async fn touch_started(&mut self) -> bool {
+        let charge_us = self.sample().await;
+        self.detector.push(charge_us) == Some(true)
+    }
+}
@@ -0,0 +1,804 @@
+//! # UI Loop
+//!
+//! Drives the library's UI state machine (see its `ui` module) from the
+//! buttons, the knob and the touch logo.
+use crate::*;
+
+/// UI loop delay while sound-reactive mode is active, in milliseconds.
+#[cfg(feature = "mic")]
+const SOUND_LOOP_MS: u64 = 10;
+
+/// User interface controller that processes knob and button inputs.
+///
+/// Manages the mapping between button states and controllable parameters,
+/// reads knob values, and updates shared state for the RGB controller.
+pub struct Ui {
+    knob: Knob<Adc>,
+    button_a: Button,
+    button_b: Button,
+    /// Buttons [A, B] found stuck at startup and ignored since.
+    stuck: [bool; 2],
+    /// Buttons [A, B] as read last pass, for counting transitions.
+    pressed: [bool; 2],
+    state: UiState,
+    /// Whether the state came from settings saved with `SAVE`, whose blue
+    /// level the knob's power-on reading then leaves alone.
+    stored: bool,
+    /// Parameter selection from the buttons, chord, and focus.
+    core: UiCore,
+    /// Settles, rate-limits and coalesces knob levels; reset when the
+    /// parameter changes.
+    knob_path: KnobPipeline,
+    /// Levels saved by a `Power(false)` command, restored by `Power(true)`.
+    #[cfg(feature = "ir")]
+    power_saved: Option<[u32; 3]>,
+    /// Frame-rate demo color state.
+    fps_demo: FrameRateDemo,
+    /// Time of the last frame-rate change while the demo is active.
+    fps_demo_changed: Instant,
+    /// Arbitration between the local knob and a paired remote.
+    #[cfg(feature = "remote")]
+    arbiter: InputArbiter,
+    /// Whether A+B was held last pass, to start pairing once per press.
+    #[cfg(feature = "remote")]
+    pair_held: bool,
+    /// Sound-reactive mode state, `Some` while the mode is active.
+    #[cfg(feature = "mic")]
+    sound: Option<SoundMode>,
+    /// When the poll wait that ended the last loop pass was due, if it did.
+    poll_due: Option<Instant>,
+    /// Touch logo, if read (see [`TOUCH_ACTION`]).
+    touch: Option<TouchLogo>,
+}
+
+impl Ui {
+    /// User interface controller that processes knob and button inputs.
+    ///
+    /// Manages the mapping between button states and controllable parameters,
+    /// reads knob values, and updates shared state for the RGB controller.
+    ///
+    /// # Arguments
+    /// * `knob` - Knob input
+    /// * `button_a` - Button A
+    /// * `button_b` - Button B
+    /// * `chord` - What the A+B chord does
+    /// * `start` - Parameter the knob edits at startup (see
+    ///   [`UiCore::starting_in`])
+    pub fn new(
+        knob: Knob<Adc>,
+        button_a: Button,
+        button_b: Button,
+        chord: ChordAction,
+        start: ControlParameter,
+    ) -> Self {
+        Self {
+            knob,
+            button_a,
+            button_b,
+            stuck: [false; 2],
+            pressed: [false; 2],
+            state: UiState::default(),
+            stored: false,
+            core: UiCore::new(chord).starting_in(start),
+            knob_path: KnobPipeline::new(),
+            fps_demo: FrameRateDemo::DEFAULT,
+            fps_demo_changed: Instant::now(),
+            #[cfg(feature = "ir")]
+            power_saved: None,
+            #[cfg(feature = "remote")]
+            arbiter: InputArbiter::default(),
+            #[cfg(feature = "remote")]
+            pair_held: false,
+            #[cfg(feature = "mic")]
+            sound: None,
+            poll_due: None,
+            touch: None,
+        }
+    }
+    /// Starts from settings loaded from flash instead of [`DEFAULTS`].
+    ///
+    /// # Arguments
+    /// * `settings` - Power-on values, already written to the shared state
+    pub fn with_settings(mut self, settings: Defaults) -> Self {
+        self.state = UiState::from_settings(&settings);
+        self.stored = settings != DEFAULTS;
+        self
+    }
+    /// Adds the touch logo, which then runs [`TOUCH_ACTION`] on each touch.
+    ///
+    /// # Arguments
+    /// * `touch` - Touch logo input
+    pub fn with_touch(mut self, touch: TouchLogo) -> Self {
+        self.touch = Some(touch);
+        self
+    }
+    /// Waits out the loop delay, remembering when it was due to end.
+    async fn pause(&mut self) {
+        let due = Instant::now() + UI_POLL;
+        Timer::at(due).await;
+        self.poll_due = Some(due);
+    }
+    /// Reads button state.
+    ///
+    /// # Returns
+    /// Whether buttons A and B are pressed (see [`button_parameter`]).
+    fn read_buttons(&mut self) -> (bool, bool) {
+        let [a_stuck, b_stuck] = self.stuck;
+        (
+            !a_stuck && self.button_a.is_low(),
+            !b_stuck && self.button_b.is_low(),
+        )
+    }
+    /// Runs self-test checks and prints a line per result, restoring the
+    /// levels afterward.
+    ///
+    /// # Arguments
+    /// * `which` - Checks to run, in order
+    ///
+    /// # Returns
+    /// The report, for the caller to summarize.
+    async fn self_test(&mut self, which: &[SelfCheck]) -> SelfTestReport {
+        let levels = get_rgb_levels().await;
+        let report = run_self_checks(self, which).await;
+        set_rgb_levels(|rgb| *rgb = levels).await;
+        for result in report.results() {
+            rprintln!("selftest {}", result);
+        }
+        report
+    }
+    /// Checks for buttons held down from startup (see [`STUCK_BUTTON`]),
+    /// warning about each and ignoring it if so configured.
+    ///
+    /// Returns at once if no button is pressed.
+    async fn check_stuck_buttons(&mut self) {
+        let mut detectors = [StuckButtonDetector::new(STUCK_BUTTON.threshold); 2];
+        loop {
+            let now_ms = Instant::now().as_millis();
+            let verdicts = [
+                detectors[0].push(now_ms, self.button_a.is_low()),
+                detectors[1].push(now_ms, self.button_b.is_low()),
+            ];
+            if !verdicts.contains(&BootButton::Checking) {
+                for (name, (verdict, stuck)) in ["A", "B"]
+                    .iter()
+                    .zip(verdicts.iter().zip(self.stuck.iter_mut()))
+                {
+                    if *verdict == BootButton::Stuck {
+                        rprintln!(
+                            "warning: button {} held since startup, {}",
+                            name,
+                            if STUCK_BUTTON.ignore {
+                                "ignoring it until reset"
+                            } else {
+                                "check it for a stuck switch or wiring"
+                            }
+                        );
+                        *stuck = STUCK_BUTTON.ignore;
+                    }
+                }
+                return;
+            }
+            Timer::after(UI_POLL).await;
+        }
+    }
+    /// Value of a parameter in the local state.
+    fn parameter_value(&self, parameter: ControlParameter) -> u32 {
+        match parameter.channel() {
+            Some(channel) => self.state.levels[channel],
+            None if parameter == ControlParameter::Saturation => self.state.saturation,
+            None => self.state.frame_rate as u32,
+        }
+    }
+    /// Sets a parameter of the local state to a mapped knob value.
+    ///
+    /// # Returns
+    /// `true` if the value changed.
+    fn set_parameter(&mut self, parameter: ControlParameter, mapped_value: u32) -> bool {
+        match parameter.channel() {
+            Some(channel) => {
+                if mapped_value == self.state.levels[channel] {
+                    return false;
+                }
+                self.state.levels[channel] = mapped_value;
+            }
+            None if parameter == ControlParameter::Saturation => {
+                if mapped_value == self.state.saturation {
+                    return false;
+                }
+                self.state.saturation = mapped_value;
+            }
+            None => {
+                let new_frame_rate: u64 = mapped_value.into();
+                if new_frame_rate == self.state.frame_rate {
+                    return false;
+                }
+                self.state.frame_rate = new_frame_rate;
+            }
+        }
+        true
+    }
+    /// Applies an input change from the paired remote.
+    ///
+    /// Remote changes always apply, and hold off the local knob for
+    /// [`REMOTE_HOLDOFF`].
+    #[cfg(feature = "remote")]
+    async fn apply_remote(&mut self, event: InputEvent) {
+        self.arbiter.remote_change(Instant::now().as_millis());
+        let mapped_value = map_knob_value(event.level, event.parameter);
+        if self.set_parameter(event.parameter, mapped_value) {
+            self.publish_change(event.parameter).await;
+        }
+    }
+    /// Applies a command forwarded from the console or IR remote.
+    ///
+    /// Only UI-local commands are forwarded; anything else is ignored.
+    ///
+    /// # Arguments
+    /// * `command` - Command that changes UI-local state
+    async fn handle_command(&mut self, command: Command) {
+        match command {
+            Command::Focus(focus) => {
+                self.core.set_focus(focus);
+                rprintln!("{}", FocusNotice(focus));
+            }
+            Command::ExplainTiming(explain) => {
+                self.state.explain_timing = explain;
+                self.state.show().await;
+            }
+            Command::FpsDemo(enabled) => {
+                self.fps_demo.enabled = enabled;
+                if !enabled {
+                    self.end_fps_demo().await;
+                }
+                rprintln!("Frame-rate demo: {}", if enabled { "on" } else { "off" });
+            }
+            Command::FpsDemoColor(color) => {
+                self.fps_demo.color = color;
+                if self.fps_demo.active() {
+                    set_rgb_levels(|rgb| *rgb = color).await;
+                }
+            }
+            Command::Rgb(levels) => {
+                self.state.levels = levels;
+                set_rgb_levels(|rgb| *rgb = levels).await;
+                self.state.show().await;
+            }
+            Command::Reset(target) => self.reset(target).await,
+            Command::Dump => {
+                let config = self.full_config().await;
+                rprintln!("{}", ConfigLine(&config));
+            }
+            Command::Restore(config) => self.restore_config(config).await,
+            Command::Save => {
+                let settings = self.full_config().await.settings();
+                match save_settings(&*SETTINGS_STORAGE.lock().await, &settings) {
+                    Ok(()) => rprintln!("Settings saved"),
+                    Err(error) => rprintln!("Settings not saved: {:?}", error),
+                }
+            }
+            Command::KnobStats(count) => {
+                rprintln!("knob: sampling {} readings, hold still", count);
+                let stats = self.knob.stats(count).await;
+                rprintln!("knob: {}", stats);
+            }
+            Command::KnobCalibrate => {
+                if self.knob.recalibrate().await {
+                    rprintln!("knob: calibrated");
+                }
+            }
+            Command::SelfTest => {
+                rprintln!("selftest: running, hands off the knob and buttons");
+                let report = self.self_test(&SelfCheck::ALL).await;
+                rprintln!("{}", report);
+            }
+            #[cfg(feature = "ir")]
+            Command::Adjust(steps) => {
+                let parameter = self.core.current();
+                match parameter.channel() {
+                    Some(channel) => {
+                        let level =
+                            (self.state.levels[channel] as i64).saturating_add(steps as i64);
+                        self.state.levels[channel] = level.clamp(0, LEVELS as i64 - 1) as u32;
+                    }
+                    None if parameter == ControlParameter::Saturation => {
+                        let saturation =
+                            (self.state.saturation as i64).saturating_add(steps as i64);
+                        self.state.saturation = saturation.clamp(0, LEVELS as i64 - 1) as u32;
+                    }
+                    None => {
+                        let frame_rate = (self.state.frame_rate.min(i64::MAX as u64) as i64)
+                            .saturating_add((steps as i64).saturating_mul(FRAME_RATE_STEP as i64));
+                        self.state.frame_rate =
+                            frame_rate.clamp(FRAME_RATE_MIN as i64, FRAME_RATE_MAX as i64) as u64;
+                    }
+                }
+                self.publish_change(parameter).await;
+            }
+            #[cfg(feature = "ir")]
+            Command::Preset(levels) => {
+                self.state.levels = levels;
+                set_rgb_levels(|rgb| *rgb = levels).await;
+                self.state.show().await;
+            }
+            #[cfg(feature = "ir")]
+            Command::Power(on) => {
+                if on {
+                    if let Some(levels) = self.power_saved.take() {
+                        self.state.levels = levels;
+                    }
+                } else if self.power_saved.is_none() {
+                    self.power_saved = Some(self.state.levels);
+                    self.state.levels = [0; 3];
+                }
+                let levels = self.state.levels;
+                set_rgb_levels(|rgb| *rgb = levels).await;
+                self.state.show().await;
+            }
+            _ => {}
+        }
+    }
+    /// Shows and publishes a change to one parameter of the local state.
+    ///
+    /// Writes only the edited parameter, so changes made meanwhile by other
+    /// writers (console, protocol) to the rest survive. Saturation is the
+    /// exception: it sets all three levels.
+    ///
+    /// # Arguments
+    /// * `parameter` - Parameter whose local value changed
+    async fn publish_change(&mut self, parameter: ControlParameter) {
+        if parameter == ControlParameter::Saturation {
+            self.state.levels = saturation_levels(MOOD_HUE, self.state.saturation);
+        }
+        self.state.show().await;
+        match parameter.channel() {
+            Some(channel) => {
+                #[cfg(feature = "tone")]
+                play_level(self.state.levels[channel]);
+                set_rgb_levels(|rgb| rgb[channel] = self.state.levels[channel]).await;
+            }
+            None if parameter == ControlParameter::Saturation => {
+                let levels = self.state.levels;
+                set_rgb_levels(|rgb| *rgb = levels).await;
+                rprintln!("saturation: {} (hue {})", self.state.saturation, MOOD_HUE);
+            }
+            None => {
+                self.start_fps_demo().await;
+                set_frame_rate(StateSource::Ui, |rate| *rate = self.state.frame_rate).await;
+                rprintln!("Frame rate changed to : {} fps", self.state.frame_rate);
+            }
+        }
+    }
+    /// Shows the demo color on a frame-rate change, if the demo is enabled.
+    async fn start_fps_demo(&mut self) {
+        self.fps_demo_changed = Instant::now();
+        if let Some(color) = self.fps_demo.enter(get_rgb_levels().await) {
+            set_rgb_levels(|rgb| *rgb = color).await;
+        }
+    }
+    /// Restores the color saved by [`Ui::start_fps_demo`], if any.
+    async fn end_fps_demo(&mut self) {
+        if let Some(levels) = self.fps_demo.exit() {
+            set_rgb_levels(|rgb| *rgb = levels).await;
+        }
+    }
+    /// Finishes a non-editing chord action on the chord's release.
+    ///
+    /// # Arguments
+    /// * `action` - Action reported by [`UiCore::step`]
+    async fn chord_released(&mut self, action: ChordAction) {
+        match action {
+            ChordAction::EditRed => {}
+            ChordAction::Next => self.announce_focus(),
+            ChordAction::Reset => self.reset(ResetTarget::All).await,
+        }
+    }
+    /// Runs the touch logo's action.
+    ///
+    /// # Arguments
+    /// * `action` - [`TOUCH_ACTION`]
+    async fn touched(&mut self, action: TouchAction) {
+        match action {
+            TouchAction::Next => {
+                self.core.set_focus(next_focus(self.core.focus()));
+                self.announce_focus();
+            }
+            TouchAction::Lock => {
+                let focus = match self.core.focus() {
+                    Some(_) => None,
+                    None => Some(self.core.current()),
+                };
+                self.core.set_focus(focus);
+                self.announce_focus();
+            }
+            TouchAction::Output => {
+                let enabled = !get_output_enabled().await;
+                set_output_enabled(enabled).await;
+                rprintln!("output: {}", if enabled { "on" } else { "off" });
+            }
+            TouchAction::Reset => self.reset(ResetTarget::All).await,
+        }
+    }
+    /// Logs the focus after a chord or touch moved it.
+    fn announce_focus(&self) {
+        rprintln!("{}", FocusNotice(self.core.focus()));
+    }
+    /// Restores the targeted settings to their defaults, writing only the
+    /// shared state they cover.
+    ///
+    /// # Arguments
+    /// * `target` - Settings to restore
+    async fn reset(&mut self, target: ResetTarget) {
+        self.state.reset(target);
+        if target != ResetTarget::FrameRate {
+            let levels = self.state.levels;
+            set_rgb_levels(|rgb| *rgb = levels).await;
+        }
+        if target != ResetTarget::Levels {
+            let frame_rate = self.state.frame_rate;
+            set_frame_rate(StateSource::Ui, |rate| *rate = frame_rate).await;
+        }
+        match target {
+            ResetTarget::All => rprintln!("Reset to defaults"),
+            ResetTarget::FrameRate => rprintln!("Frame rate reset to default"),
+            ResetTarget::Levels => rprintln!("Levels reset to defaults"),
+        }
+        self.state.show().await;
+    }
+    /// Gathers the runtime configuration, the UI's own part included.
+    async fn full_config(&self) -> FullConfig {
+        FullConfig {
+            levels: match self.fps_demo.saved() {
+                Some(levels) => levels,
+                None => get_rgb_levels().await,
+            },
+            frame_rate: get_frame_rate().await,
+            saturation: self.state.saturation,
+            brightness: get_master_brightness().await,
+            output_enabled: get_output_enabled().await,
+            explain_timing: self.state.explain_timing,
+            verbosity: *VERBOSITY.lock().await,
+            #[cfg(not(feature = "hwpwm"))]
+            dither: *DITHER_MODE.lock().await,
+            #[cfg(feature = "hwpwm")]
+            channels: *PWM_CHANNELS.lock().await,
+            gamma: *GAMMA_CURVE.lock().await,
+            fps_demo: self.fps_demo.enabled,
+            fps_demo_color: self.fps_demo.color,
+        }
+    }
+    /// Applies a configuration from `RESTORE`, clamping anything out of
+    /// range and naming what was clamped.
+    ///
+    /// # Arguments
+    /// * `config` - Decoded configuration, as stored
+    async fn restore_config(&mut self, config: FullConfig) {
+        let (config, adjusted) = config.validated();
+        // The restored levels replace the demo color and the color it saved.
+        self.fps_demo.exit();
+        self.fps_demo.enabled = config.fps_demo;
+        self.fps_demo.color = config.fps_demo_color;
+        self.state.levels = config.levels;
+        self.state.frame_rate = config.frame_rate;
+        self.state.saturation = config.saturation;
+        self.state.output_enabled = config.output_enabled;
+        self.state.explain_timing = config.explain_timing;
+        set_rgb_levels(|rgb| *rgb = config.levels).await;
+        set_frame_rate(StateSource::Ui, |rate| *rate = config.frame_rate).await;
+        set_master_brightness(|b| *b = config.brightness).await;
+        set_output_enabled(config.output_enabled).await;
+        *VERBOSITY.lock().await = config.verbosity;
+        #[cfg(not(feature = "hwpwm"))]
+        {
+            *DITHER_MODE.lock().await = config.dither;
+        }
+        #[cfg(feature = "hwpwm")]
+        {
+            *PWM_CHANNELS.lock().await = config.channels;
+        }
+        *GAMMA_CURVE.lock().await = config.gamma;
+        if adjusted.any() {
+            rprintln!("{}", AdjustedNotice(adjusted));
+        }
+        rprintln!("Configuration restored");
+        self.state.show().await;
+    }
+    /// Runs the long-hold action for a button combination.
+    ///
+    /// # Arguments
+    /// * `parameter` - Parameter selected by the held combination
+    async fn long_hold(&mut self, parameter: ControlParameter) {
+        match parameter {
+            #[cfg(feature = "mic")]
+            ControlParameter::Red => self.toggle_sound().await,
+            #[cfg(feature = "ambient")]
+            ControlParameter::Green => toggle_ambient().await,
+            _ => {}
+        }
+    }
+    /// Enters or leaves sound-reactive mode.
+    ///
+    /// Entering saves the current master brightness; leaving restores it, so
+    /// the calibrated output is unchanged once the demo is over.
+    #[cfg(feature = "mic")]
+    async fn toggle_sound(&mut self) {
+        match self.sound.take() {
+            Some(sound) => {
+                set_master_brightness(|b| *b = sound.saved_brightness).await;
+                rprintln!("Sound mode: off");
+            }
+            None => {
+                self.sound = Some(SoundMode {
+                    envelope: Envelope::default(),
+                    sensitivity: 0,
+                    saved_brightness: get_master_brightness().await,
+                    idle_knob: None,
+                });
+                rprintln!("Sound mode: on (knob sets sensitivity)");
+            }
+        }
+    }
+    /// Starts or ends sound-reactive mode along with an
+    /// [`Effect::SoundReactive`] idle animation. A mode started by hand is
+    /// left alone.
+    #[cfg(all(feature = "idle", feature = "mic"))]
+    async fn idle_sound(&mut self) {
+        let idle = IDLE.lock().await.sound_reactive();
+        match self.sound.as_ref() {
+            None if idle => {
+                self.sound = Some(SoundMode {
+                    envelope: Envelope::default(),
+                    sensitivity: IDLE_SOUND_SENSITIVITY,
+                    saved_brightness: get_master_brightness().await,
+                    idle_knob: Some(self.knob.measure().await),
+                });
+            }
+            Some(sound) if sound.idle_knob.is_some() && !idle => {
+                let saved = sound.saved_brightness;
+                set_master_brightness(|b| *b = saved).await;
+                self.sound = None;
+            }
+            _ => {}
+        }
+    }
+    /// Runs one sound-mode pass of the UI loop.
+    ///
+    /// While the mode is active, reads the knob as sensitivity (or, for the
+    /// idle animation, as input that ends it), feeds a burst of microphone
+    /// samples through the envelope follower, and publishes the resulting
+    /// master brightness.
+    ///
+    /// # Returns
+    /// `true` if sound mode handled this pass and normal knob handling
+    /// should be skipped.
+    #[cfg(feature = "mic")]
+    async fn sound_pass(&mut self) -> bool {
+        let Some(sound) = self.sound.as_mut() else {
+            return false;
+        };
+        let knob = self.knob.measure().await;
+        if sound.idle_knob.is_none() && knob != sound.sensitivity {
+            sound.sensitivity = knob;
+            rprintln!("sensitivity: {}", knob);
+        }
+        // Turning the knob is input, which ends the idle animation.
+        #[cfg(feature = "idle")]
+        if sound
+            .idle_knob
+            .is_some_and(|start| knob.abs_diff(start) > 1)
+        {
+            idle_input().await;
+        }
+        for _ in 0..MIC_BURST {
+            sound.envelope.update(self.knob.sample_mic().await);
+        }
+        let brightness = sound.envelope.brightness(sound.sensitivity);
+        set_master_brightness(|b| *b = brightness).await;
+        Timer::after_millis(SOUND_LOOP_MS).await;
+        true
+    }
+    /// Main UI control loop that handles input processing and state management.
+    ///
+    /// This is the primary entry point for the UI system. It runs continuously,
+    /// processing button and knob inputs, managing parameter selection, and
+    /// synchronizing state with the RGB display system.
+    ///
+    /// # Startup
+    ///
+    /// Before the loop starts, any button held down is checked for being
+    /// stuck (see [`STUCK_BUTTON`]), the LED pins are checked if
+    /// [`SELFTEST_PINS_AT_BOOT`] is set, and the blue level is seeded from the
+    /// knob with [`Knob::measure_settled`] over [`KNOB_WARMUP`], so a
+    /// garbage first conversion can't flash a wrong color.
+    ///
+    /// # Value Ranges
+    ///
+    /// - **RGB Parameters**: 0-15 (mapped from knob input)
+    /// - **Frame Rate**: 10-160 FPS (mapped from knob input)
+    ///
+    /// # Performance Considerations
+    ///
+    /// - Uses change detection to minimize shared state updates
+    /// - Local state caching reduces lock contention
+    /// - 50ms loop delay balances responsiveness with CPU usage
+    /// - Knob changes are stamped for the [`latency`] module's measurement
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// let mut ui = Ui::new(knob, btn_a, btn_b, CHORD_ACTION, START_PARAMETER);
+    /// ui.run().await; // Starts the UI control loop (never returns)
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// This function never returns under normal operation. It will only
+    /// exit if the hardware fails or the system panics.
+    pub async fn run(&mut self) -> ! {
+        self.check_stuck_buttons().await;
+        if SELFTEST_PINS_AT_BOOT {
+            self.self_test(&SelfCheck::PINS).await;
+        }
+        if !self.stored {
+            self.state.levels[2] = self
+                .knob
+                .measure_settled(KNOB_CONFIG.blue, KNOB_WARMUP)
+                .await;
+            set_rgb_levels(|rgb| {
+                *rgb = self.state.levels;
+            })
+            .await;
+        }
+        self.state.show().await;
+        loop {
+            // The earliest this pass could have run, for latency stamps.
+            let pass_due = self.poll_due.take().unwrap_or_else(Instant::now);
+            self.state.output_enabled = get_output_enabled().await;
+            while let Ok(command) = UI_COMMANDS.try_receive() {
+                #[cfg(feature = "idle")]
+                idle_input().await;
+                #[cfg(feature = "sleep")]
+                sleep_input().await;
+                self.handle_command(command).await;
+            }
+            #[cfg(feature = "remote")]
+            while let Ok(event) = REMOTE_EVENTS.try_receive() {
+                #[cfg(feature = "idle")]
+                idle_input().await;
+                #[cfg(feature = "sleep")]
+                sleep_input().await;
+                self.apply_remote(event).await;
+            }
+
+            let (a_pressed, b_pressed) = self.read_buttons();
+            for (pressed, last) in [a_pressed, b_pressed].iter().zip(self.pressed.iter_mut()) {
+                if *pressed != *last {
+                    count_stat!(ButtonTransitions);
+                    *last = *pressed;
+                }
+            }
+            #[cfg(feature = "record")]
+            record_ui_pass(a_pressed, b_pressed).await;
+            let step = self
+                .core
+                .step(Instant::now().as_millis(), a_pressed, b_pressed);
+            let buttons = step.buttons;
+            #[cfg(feature = "idle")]
+            if buttons != ControlParameter::FrameRate {
+                idle_input().await;
+            }
+            #[cfg(feature = "sleep")]
+            if buttons != ControlParameter::FrameRate {
+                sleep_input().await;
+            }
+            #[cfg(feature = "remote")]
+            {
+                let pair_pressed = buttons == ControlParameter::Red;
+                if pair_pressed && !self.pair_held {
+                    remote_pair_pressed().await;
+                }
+                self.pair_held = pair_pressed;
+            }
+            if let Some(action) = step.chord {
+                self.chord_released(action).await;
+            }
+            if step.long_hold {
+                self.long_hold(buttons).await;
+            }
+            if let Some(touch) = self.touch.as_mut() {
+                if touch.touch_started().await {
+                    #[cfg(feature = "idle")]
+                    idle_input().await;
+                    #[cfg(feature = "sleep")]
+                    sleep_input().await;
+                    if let Some(action) = TOUCH_ACTION {
+                        self.touched(action).await;
+                    }
+                }
+            }
+
+            #[cfg(all(feature = "idle", feature = "mic"))]
+            self.idle_sound().await;
+            #[cfg(feature = "mic")]
+            if self.sound_pass().await {
+                continue;
+            }
+
+            let Some(parameter) = step.parameter else {
+                self.pause().await;
+                continue;
+            };
+
+            if self.fps_demo.active()
+                && (parameter != ControlParameter::FrameRate
+                    || self.fps_demo_changed.elapsed() >= FPS_DEMO_HOLD)
+            {
+                self.end_fps_demo().await;
+            }
+
+            if self.core.select(parameter) {
+                // Ramp from where the parameter is, not from the knob.
+                let current = self.parameter_value(parameter);
+                self.knob_path.reset(
+                    Instant::now().as_millis(),
+                    unmap_knob_value(current, parameter),
+                );
+                rprintln!("Now controlling: {:?}", parameter);
+                self.state.show().await;
+            }
+
+            let raw = self.knob.sample_raw().await;
+            #[cfg(feature = "record")]
+            record_knob_raw(raw).await;
+            let knob_value = knob_level(raw, KNOB_CONFIG.response(parameter));
+            #[cfg(feature = "remote")]
+            set_remote_snapshot(InputEvent {
+                parameter,
+                level: knob_value,
+            })
+            .await;
+            let Some(raw_knob_value) = self.knob_path.push(Instant::now().as_millis(), knob_value)
+            else {
+                self.pause().await;
+                continue;
+            };
+            #[cfg(feature = "idle")]
+            idle_input().await;
+            #[cfg(feature = "sleep")]
+            sleep_input().await;
+            #[cfg(feature = "remote")]
+            if !self
+                .arbiter
+                .local_allowed(Instant::now().as_millis(), remote_linked().await)
+            {
+                self.pause().await;
+                continue;
+            }
+            let mapped_value = map_knob_value(raw_knob_value, parameter);
+
+            if self.set_parameter(parameter, mapped_value) {
+                latency_input(pass_due).await;
+                self.publish_change(parameter).await;
+            }
+            self.pause().await;
+        }
+    }
+}
+
+impl SelfChecks for Ui {
+    async fn run_check(&mut self, check: SelfCheck) -> CheckResult {
+        match check {
+            SelfCheck::KnobCalibration => check_knob_calibration(&mut self.knob).await,
+            SelfCheck::KnobNoise => check_knob_noise(&mut self.knob).await,
+            SelfCheck::ButtonA => check_button(check, self.button_a.is_low()),
+            SelfCheck::ButtonB => check_button(check, self.button_b.is_low()),
+            SelfCheck::Red => check_channel(check, 0).await,
+            SelfCheck::Green => check_channel(check, 1).await,
+            SelfCheck::Blue => check_channel(check, 2).await,
+            SelfCheck::RedPin => check_pin(check, 0).await,
+            SelfCheck::GreenPin => check_pin(check, 1).await,
+            SelfCheck::BluePin => check_pin(check, 2).await,
+            SelfCheck::FrameRate => check_frame_rate().await,
+            SelfCheck::StateRoundTrip => check_state_round_trip().await,
+        }
+    }
+}
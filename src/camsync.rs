@@ -0,0 +1,246 @@
+//! # Camera Sync Module
+//!
+//! This module phase-locks the software PWM frame to an external trigger,
+//! such as a camera's exposure output, so every exposure sees the LEDs at
+//! the same point in their cycle.
+//!
+//! ## Trigger
+//!
+//! The trigger input (P12 with a pull-down; change the pin in `main`) is
+//! watched for rising edges through GPIOTE. With sync enabled (`SYNC ON`),
+//! [`Rgb::run`] computes the next frame, then waits for an edge before
+//! starting the scan, so the first LED edge follows the trigger by only
+//! the wake-up latency. A frame takes one frame period, so set the frame
+//! rate above the trigger rate: triggers that arrive mid-frame are missed,
+//! and the frame starts on the next one.
+//!
+//! ## Sync States
+//!
+//! [`SyncTracker`] follows the outcome of each wait:
+//!
+//! - [`SyncState::Timeout`]: waiting up to [`SYNC_TIMEOUT`] per frame for
+//!   the trigger, after enabling or after a missed trigger
+//! - [`SyncState::Locked`]: frames start on triggers
+//! - [`SyncState::FreeRunning`]: [`SYNC_MISS_LIMIT`] waits in a row timed
+//!   out, so sync is lost (and logged); frames run free, only listening
+//!   for [`SYNC_PROBE`] each, and lock again on the next trigger heard
+//!
+//! ## Latency
+//!
+//! The delay from the trigger wake-up to the start of the scan is
+//! measured every locked frame; its range is the jitter reported by the
+//! `TIMING` console command. It is measured with the embassy-time tick
+//! (about 30 µs on this board) and excludes the fixed interrupt latency.
+use crate::*;
+
+/// Longest wait for a trigger before the frame runs free.
+pub const SYNC_TIMEOUT: Duration = Duration::from_millis(100);
+/// How long a free-running frame listens for a trigger.
+pub const SYNC_PROBE: Duration = Duration::from_millis(1);
+/// Consecutive timeouts after which sync counts as lost.
+pub const SYNC_MISS_LIMIT: u32 = 3;
+
+/// Camera-sync lock state.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SyncState {
+    /// Frames start on triggers.
+    Locked,
+    /// Waiting for a trigger that hasn't arrived yet.
+    Timeout,
+    /// Sync lost; frames run free while probing for triggers.
+    FreeRunning,
+}
+
+/// Result of one wait for the trigger.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TriggerOutcome {
+    /// The trigger arrived; the scan started this long after the wake-up.
+    Trigger {
+        /// Wake-up to scan start, in microseconds.
+        latency_us: u32,
+    },
+    /// No trigger within the wait.
+    Timeout,
+}
+
+/// Sync state machine with trigger latency statistics.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SyncTracker {
+    /// Current state.
+    pub state: SyncState,
+    /// Consecutive timeouts.
+    misses: u32,
+    /// Latency of the last locked frame, in microseconds.
+    pub last_latency_us: Option<u32>,
+    /// Lowest latency since locking, in microseconds.
+    min_latency_us: u32,
+    /// Highest latency since locking, in microseconds.
+    max_latency_us: u32,
+}
+
+impl SyncTracker {
+    /// Creates a tracker waiting for its first trigger.
+    pub const fn new() -> Self {
+        Self {
+            state: SyncState::Timeout,
+            misses: 0,
+            last_latency_us: None,
+            min_latency_us: u32::MAX,
+            max_latency_us: 0,
+        }
+    }
+
+    /// How long the next frame should wait for the trigger.
+    pub fn timeout(&self) -> Duration {
+        match self.state {
+            SyncState::FreeRunning => SYNC_PROBE,
+            SyncState::Locked | SyncState::Timeout => SYNC_TIMEOUT,
+        }
+    }
+
+    /// Feeds the outcome of one wait.
+    ///
+    /// # Returns
+    /// The new state if it changed.
+    pub fn update(&mut self, outcome: TriggerOutcome) -> Option<SyncState> {
+        let old = self.state;
+        match outcome {
+            TriggerOutcome::Trigger { latency_us } => {
+                if old != SyncState::Locked {
+                    self.min_latency_us = u32::MAX;
+                    self.max_latency_us = 0;
+                }
+                self.state = SyncState::Locked;
+                self.misses = 0;
+                self.last_latency_us = Some(latency_us);
+                self.min_latency_us = self.min_latency_us.min(latency_us);
+                self.max_latency_us = self.max_latency_us.max(latency_us);
+            }
+            TriggerOutcome::Timeout => {
+                self.misses = self.misses.saturating_add(1);
+                self.state = if self.misses >= SYNC_MISS_LIMIT {
+                    SyncState::FreeRunning
+                } else {
+                    SyncState::Timeout
+                };
+            }
+        }
+        (self.state != old).then_some(self.state)
+    }
+
+    /// Latency range since locking (max − min), in microseconds.
+    pub fn jitter_us(&self) -> Option<u32> {
+        (self.max_latency_us >= self.min_latency_us)
+            .then(|| self.max_latency_us - self.min_latency_us)
+    }
+}
+
+impl Default for SyncTracker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl core::fmt::Display for SyncTracker {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "{:?}", self.state)?;
+        if let (Some(last), Some(jitter)) = (self.last_latency_us, self.jitter_us()) {
+            write!(
+                f,
+                ", latency {} us (min {}, max {}, jitter {} us)",
+                last, self.min_latency_us, self.max_latency_us, jitter
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Shared camera-sync state.
+#[derive(Debug, Clone, Copy)]
+pub struct CamSync {
+    /// Whether frames wait for the trigger.
+    pub enabled: bool,
+    /// Lock state and latency statistics.
+    pub tracker: SyncTracker,
+}
+
+/// Global camera-sync state, changed by the console.
+pub static CAM_SYNC: Mutex<CriticalSectionRawMutex, CamSync> = Mutex::new(CamSync {
+    enabled: false,
+    tracker: SyncTracker::new(),
+});
+
+/// Enables or disables camera sync, restarting the lock.
+pub async fn set_cam_sync(enabled: bool) {
+    *CAM_SYNC.lock().await = CamSync {
+        enabled,
+        tracker: SyncTracker::new(),
+    };
+    rprintln!(
+        "sync: {}",
+        if enabled {
+            "waiting for trigger"
+        } else {
+            "off"
+        }
+    );
+}
+
+/// Records the outcome of a wait once the frame is underway.
+///
+/// # Arguments
+/// * `woke` - Trigger wake-up time from `wait_for_trigger`, or `None`
+///   on a timeout
+/// * `frame_start` - Time the scan started
+pub async fn record_trigger(woke: Option<Instant>, frame_start: Instant) {
+    let outcome = match woke {
+        Some(woke) => TriggerOutcome::Trigger {
+            latency_us: (frame_start - woke).as_micros() as u32,
+        },
+        None => TriggerOutcome::Timeout,
+    };
+    let mut sync = CAM_SYNC.lock().await;
+    if !sync.enabled {
+        return;
+    }
+    match sync.tracker.update(outcome) {
+        Some(SyncState::Locked) => rprintln!("sync: locked"),
+        Some(SyncState::FreeRunning) => rprintln!("sync: lost, free-running"),
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tracker_locks_times_out_and_free_runs() {
+        // A mocked trigger source: two triggers, then the camera stops.
+        let outcomes = [
+            TriggerOutcome::Trigger { latency_us: 30 },
+            TriggerOutcome::Trigger { latency_us: 61 },
+            TriggerOutcome::Timeout,
+            TriggerOutcome::Timeout,
+            TriggerOutcome::Timeout,
+        ];
+        let expected = [
+            Some(SyncState::Locked),
+            None,
+            Some(SyncState::Timeout),
+            None,
+            Some(SyncState::FreeRunning),
+        ];
+        let mut tracker = SyncTracker::new();
+        for (outcome, change) in outcomes.into_iter().zip(expected) {
+            assert_eq!(tracker.update(outcome), change);
+        }
+        assert_eq!(tracker.jitter_us(), Some(31));
+        assert_eq!(tracker.timeout(), SYNC_PROBE);
+        // The camera comes back: locked again at once.
+        assert_eq!(
+            tracker.update(TriggerOutcome::Trigger { latency_us: 30 }),
+            Some(SyncState::Locked)
+        );
+    }
+}
@@ -0,0 +1,361 @@
+//! # Change Log Module
+//!
+//! This module keeps an append-only log of calibration changes in a
+//! reserved region of internal flash, so a long tuning session can be
+//! reviewed afterward with the `LOG DUMP` console command.
+//!
+//! ## Flash Layout
+//!
+//! The log takes the last [`LOG_PAGES`] pages of flash, at
+//! [`LOG_FLASH_BASE`] (see the [`flash`](crate::flash) module). Each page starts with a header slot and is
+//! followed by fixed-size record slots, all [`LOG_SLOT_SIZE`] bytes:
+//!
+//! ```text
+//! header: magic u32 | page sequence u32 | 0 u32 | crc16 u32
+//! record: 0xC7 | parameter | 0 0 | old u16 | new u16 | uptime ms u32 | crc16 u16 | 0 0
+//! ```
+//!
+//! All fields are little endian, and each CRC-16 covers the bytes before
+//! it. Slots are programmed one word at a time, CRC word last, so a slot is
+//! only valid once completely written.
+//!
+//! ## Rotation and Recovery
+//!
+//! Records are appended to the active page, the valid page with the
+//! highest sequence. When it fills, the oldest page is erased and given the
+//! next sequence, discarding the oldest records.
+//!
+//! At boot the pages are scanned: headers that fail their CRC (a torn
+//! erase or header write) are ignored, and the write head is placed after
+//! the last slot that isn't erased. A record torn by power loss mid-write
+//! fails its CRC, is skipped by the dump, and is never written over.
+//!
+//! ## What Gets Logged
+//!
+//! The log task follows the [`events`](crate::events) bus and records a
+//! change once the value has been stable for [`LOG_SETTLE`], so sweeping
+//! the knob through several levels logs only where it stopped.
+use crate::*;
+
+/// Number of pages in the log region.
+pub const LOG_PAGES: u32 = 2;
+/// Size of a header or record slot in bytes.
+pub const LOG_SLOT_SIZE: u32 = 16;
+/// Slots per page, including the header slot.
+const LOG_SLOTS: u32 = FLASH_PAGE_SIZE / LOG_SLOT_SIZE;
+/// Page header magic, "LOGP".
+const LOG_PAGE_MAGIC: u32 = 0x5047_4F4C;
+/// First byte of every record.
+const LOG_RECORD_MAGIC: u8 = 0xC7;
+/// How long a value must stay unchanged before it is logged.
+pub const LOG_SETTLE: Duration = Duration::from_secs(2);
+/// Display names of the logged parameters, by parameter number.
+pub const LOG_PARAMETER_NAMES: [&str; 4] = ["red", "green", "blue", "frame rate"];
+
+/// One logged change.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ChangeRecord {
+    /// Parameter number: 0 red, 1 green, 2 blue, 3 frame rate.
+    pub parameter: u8,
+    /// Value before the change.
+    pub old: u16,
+    /// Value after the change.
+    pub new: u16,
+    /// Uptime when the change was logged, in milliseconds.
+    pub uptime_ms: u32,
+}
+
+impl ChangeRecord {
+    /// Encodes the record as a slot.
+    fn encode(&self) -> [u8; LOG_SLOT_SIZE as usize] {
+        let mut slot = [0u8; LOG_SLOT_SIZE as usize];
+        slot[0] = LOG_RECORD_MAGIC;
+        slot[1] = self.parameter;
+        slot[4..6].copy_from_slice(&self.old.to_le_bytes());
+        slot[6..8].copy_from_slice(&self.new.to_le_bytes());
+        slot[8..12].copy_from_slice(&self.uptime_ms.to_le_bytes());
+        let crc = crc16(&slot[..12]);
+        slot[12..14].copy_from_slice(&crc.to_le_bytes());
+        slot
+    }
+}
+
+/// What a slot holds.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LogSlot {
+    /// Never written since the page was erased.
+    Erased,
+    /// A complete record.
+    Record(ChangeRecord),
+    /// Partly written, corrupted, or not a record.
+    Torn,
+}
+
+impl LogSlot {
+    /// Classifies a slot's bytes.
+    pub fn classify(slot: &[u8; LOG_SLOT_SIZE as usize]) -> Self {
+        if slot.iter().all(|&byte| byte == 0xFF) {
+            return LogSlot::Erased;
+        }
+        let crc = u16::from_le_bytes([slot[12], slot[13]]);
+        if slot[0] != LOG_RECORD_MAGIC || crc != crc16(&slot[..12]) {
+            return LogSlot::Torn;
+        }
+        LogSlot::Record(ChangeRecord {
+            parameter: slot[1],
+            old: u16::from_le_bytes([slot[4], slot[5]]),
+            new: u16::from_le_bytes([slot[6], slot[7]]),
+            uptime_ms: u32::from_le_bytes([slot[8], slot[9], slot[10], slot[11]]),
+        })
+    }
+}
+
+/// Encodes a page header slot.
+fn page_header(sequence: u32) -> [u8; LOG_SLOT_SIZE as usize] {
+    let mut slot = [0u8; LOG_SLOT_SIZE as usize];
+    slot[0..4].copy_from_slice(&LOG_PAGE_MAGIC.to_le_bytes());
+    slot[4..8].copy_from_slice(&sequence.to_le_bytes());
+    let crc = crc16(&slot[..12]) as u32;
+    slot[12..16].copy_from_slice(&crc.to_le_bytes());
+    slot
+}
+
+/// Decodes a page header slot.
+///
+/// # Returns
+/// The page sequence, or `None` for an erased, torn, or foreign header.
+fn page_sequence(slot: &[u8; LOG_SLOT_SIZE as usize]) -> Option<u32> {
+    let word = |i: usize| u32::from_le_bytes([slot[i], slot[i + 1], slot[i + 2], slot[i + 3]]);
+    (word(0) == LOG_PAGE_MAGIC && word(12) == crc16(&slot[..12]) as u32).then(|| word(4))
+}
+
+/// Append-only change log in a [`FlashRegion`].
+pub struct ChangeLog<F: FlashRegion> {
+    flash: F,
+    /// Active page index.
+    page: u32,
+    /// Sequence of the active page.
+    sequence: u32,
+    /// Next free slot in the active page; [`LOG_SLOTS`] when full.
+    head: u32,
+}
+
+impl<F: FlashRegion> ChangeLog<F> {
+    /// Reads one slot.
+    fn read_slot(&self, page: u32, slot: u32) -> [u8; LOG_SLOT_SIZE as usize] {
+        let mut buf = [0u8; LOG_SLOT_SIZE as usize];
+        self.flash
+            .read(page * FLASH_PAGE_SIZE + slot * LOG_SLOT_SIZE, &mut buf);
+        buf
+    }
+
+    /// Programs one slot word by word, last word last.
+    fn write_slot(&mut self, page: u32, slot: u32, bytes: &[u8; LOG_SLOT_SIZE as usize]) {
+        let offset = page * FLASH_PAGE_SIZE + slot * LOG_SLOT_SIZE;
+        for (i, word) in bytes.chunks_exact(4).enumerate() {
+            let word = u32::from_le_bytes([word[0], word[1], word[2], word[3]]);
+            self.flash.write_word(offset + 4 * i as u32, word);
+        }
+    }
+
+    /// Erases a page and makes it the active page with a new sequence.
+    fn start_page(&mut self, page: u32, sequence: u32) {
+        self.flash.erase_page(page);
+        self.write_slot(page, 0, &page_header(sequence));
+        self.page = page;
+        self.sequence = sequence;
+        self.head = 1;
+    }
+
+    /// Opens the log, scanning the flash for the active page and write
+    /// head. A region without a valid page is formatted.
+    pub fn open(flash: F) -> Self {
+        let mut log = Self {
+            flash,
+            page: 0,
+            sequence: 0,
+            head: LOG_SLOTS,
+        };
+        let active = (0..LOG_PAGES)
+            .filter_map(|page| Some((page, page_sequence(&log.read_slot(page, 0))?)))
+            .max_by_key(|&(_, sequence)| sequence);
+        let Some((page, sequence)) = active else {
+            log.start_page(0, 1);
+            return log;
+        };
+        log.page = page;
+        log.sequence = sequence;
+        // Torn slots count as used: they can't be programmed again.
+        log.head = (1..LOG_SLOTS)
+            .rev()
+            .find(|&slot| LogSlot::classify(&log.read_slot(page, slot)) != LogSlot::Erased)
+            .map_or(1, |slot| slot + 1);
+        log
+    }
+
+    /// Appends a record, rotating to a fresh page when the active one is full.
+    pub fn append(&mut self, record: &ChangeRecord) {
+        if self.head >= LOG_SLOTS {
+            let next = (self.page + 1) % LOG_PAGES;
+            self.start_page(next, self.sequence.wrapping_add(1));
+        }
+        self.write_slot(self.page, self.head, &record.encode());
+        self.head += 1;
+    }
+
+    /// Calls `f` with every valid record, oldest first.
+    pub fn for_each(&self, mut f: impl FnMut(ChangeRecord)) {
+        let mut pages = [(0u32, 0u32); LOG_PAGES as usize];
+        let mut count = 0;
+        for page in 0..LOG_PAGES {
+            if let Some(sequence) = page_sequence(&self.read_slot(page, 0)) {
+                pages[count] = (sequence, page);
+                count += 1;
+            }
+        }
+        let pages = &mut pages[..count];
+        pages.sort_unstable();
+        for &(_, page) in pages.iter() {
+            for slot in 1..LOG_SLOTS {
+                match LogSlot::classify(&self.read_slot(page, slot)) {
+                    LogSlot::Record(record) => f(record),
+                    LogSlot::Torn => {}
+                    LogSlot::Erased => break,
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The log region in RAM.
+    type RamLog = RamFlash<{ LOG_PAGES as usize }>;
+
+    fn record(n: u32) -> ChangeRecord {
+        ChangeRecord {
+            parameter: (n % 4) as u8,
+            old: n as u16,
+            new: n as u16 + 1,
+            uptime_ms: n * 1000,
+        }
+    }
+
+    /// The `old` field of every record the dump shows, oldest first.
+    fn dump(log: &ChangeLog<RamLog>) -> Vec<u16> {
+        let mut seen = Vec::new();
+        log.for_each(|record| seen.push(record.old));
+        seen
+    }
+
+    #[test]
+    fn record_and_header_format() {
+        let slot = ChangeRecord {
+            parameter: 3,
+            old: 0x1234,
+            new: 60,
+            uptime_ms: 0x0102_0304,
+        }
+        .encode();
+        assert_eq!(
+            slot[..12],
+            [0xC7, 3, 0, 0, 0x34, 0x12, 60, 0, 0x04, 0x03, 0x02, 0x01]
+        );
+        assert_eq!(slot[12..14], crc16(&slot[..12]).to_le_bytes());
+        assert_eq!(slot[14..], [0, 0]);
+        assert_eq!(
+            LogSlot::classify(&slot),
+            LogSlot::Record(ChangeRecord {
+                parameter: 3,
+                old: 0x1234,
+                new: 60,
+                uptime_ms: 0x0102_0304,
+            })
+        );
+        assert_eq!(LogSlot::classify(&[0xFF; 16]), LogSlot::Erased);
+        // A changed byte, or a page header, is not a record.
+        let mut corrupt = slot;
+        corrupt[6] ^= 1;
+        assert_eq!(LogSlot::classify(&corrupt), LogSlot::Torn);
+        assert_eq!(LogSlot::classify(&page_header(1)), LogSlot::Torn);
+
+        let header = page_header(0x0A0B_0C0D);
+        assert_eq!(
+            header[..8],
+            [0x4C, 0x4F, 0x47, 0x50, 0x0D, 0x0C, 0x0B, 0x0A]
+        );
+        assert_eq!(page_sequence(&header), Some(0x0A0B_0C0D));
+        assert_eq!(page_sequence(&[0xFF; 16]), None);
+        let mut torn = header;
+        torn[12..].fill(0xFF);
+        assert_eq!(page_sequence(&torn), None);
+    }
+
+    #[test]
+    fn full_pages_rotate_out_the_oldest_records() {
+        let per_page = LOG_SLOTS - 1;
+        let mut log = ChangeLog::open(RamLog::new());
+        assert_eq!((log.page, log.sequence, log.head), (0, 1, 1));
+        for n in 0..2 * per_page {
+            log.append(&record(n));
+        }
+        assert_eq!((log.page, log.sequence, log.head), (1, 2, LOG_SLOTS));
+        assert_eq!(dump(&log), (0..2 * per_page as u16).collect::<Vec<_>>());
+
+        // Both pages are full: the next record erases the first page.
+        log.append(&record(2 * per_page));
+        assert_eq!((log.page, log.sequence, log.head), (0, 3, 2));
+        let kept = (per_page as u16..=2 * per_page as u16).collect::<Vec<_>>();
+        assert_eq!(dump(&log), kept);
+
+        // Reopening finds the newest page and the slot after its record.
+        let mut log = ChangeLog::open(log.flash);
+        assert_eq!((log.page, log.sequence, log.head), (0, 3, 2));
+        assert_eq!(dump(&log), kept);
+        log.append(&record(1000));
+        assert_eq!(dump(&log).last(), Some(&1000));
+    }
+
+    #[test]
+    fn a_torn_record_is_skipped_and_not_written_over() {
+        let mut log = ChangeLog::open(RamLog::new());
+        for n in 0..3 {
+            log.append(&record(n));
+        }
+        // Power fails two words into the fourth record.
+        log.flash.writes_left = 2;
+        log.append(&record(3));
+        assert_eq!(LogSlot::classify(&log.read_slot(0, 4)), LogSlot::Torn);
+
+        let mut log = ChangeLog::open(log.flash);
+        log.flash.writes_left = u32::MAX;
+        assert_eq!(log.head, 5);
+        log.append(&record(4));
+        assert_eq!(dump(&log), [0, 1, 2, 4]);
+    }
+
+    #[test]
+    fn a_torn_rotation_keeps_the_full_page() {
+        let per_page = LOG_SLOTS - 1;
+        let mut log = ChangeLog::open(RamLog::new());
+        for n in 0..per_page {
+            log.append(&record(n));
+        }
+        // Power fails after the erase, halfway through the new header.
+        log.flash.writes_left = 2;
+        log.append(&record(per_page));
+
+        // The erased page is ignored, and the full one is still active.
+        let mut log = ChangeLog::open(log.flash);
+        log.flash.writes_left = u32::MAX;
+        assert_eq!((log.page, log.sequence, log.head), (0, 1, LOG_SLOTS));
+        assert_eq!(dump(&log).len(), per_page as usize);
+
+        // The next record rotates again, this time for good.
+        log.append(&record(per_page));
+        assert_eq!((log.page, log.sequence, log.head), (1, 2, 2));
+        assert_eq!(dump(&log), (0..=per_page as u16).collect::<Vec<_>>());
+    }
+}
@@ -0,0 +1,236 @@
+//! # Color Conversion Module
+//!
+//! This module holds the color math shared by the demo modes, so every mode
+//! that works in hue terms goes through the same conversion to RGB levels.
+//!
+//! ## HSV
+//!
+//! Hue is in degrees (0-360, wrapping), saturation and value are fractions
+//! from 0.0 to 1.0. Results are rounded to the nearest of the [`LEVELS`]
+//! steps per channel.
+//!
+//! ## Hex Colors
+//!
+//! [`set_color_hex`] takes web-style `#RRGGBB` colors (the `#` is
+//! optional, digits in either case). Each 8-bit channel is scaled to the
+//! nearest of the [`LEVELS`] steps, so `#FFFFFF` is full white and `#808080`
+//! lands on the middle level.
+//!
+//! ## Fixed Hue
+//!
+//! [`saturation_levels`] turns a single saturation level into a color of
+//! one hue (see [`MOOD_HUE`]), at full value: the knob's
+//! [`ControlParameter::Saturation`] fades it from white to the pure hue,
+//! so one control gives pleasant variations of one color.
+//!
+//! ## Luminance
+//!
+//! [`luminance`] weighs the channels by how bright each looks, with the
+//! Rec. 709 coefficients in [`LUMINANCE_WEIGHTS`], so green counts ten
+//! times as much as blue. Each level is taken as a fraction of the top
+//! level, first mapped through a [`Gamma`] curve if one is given, so the
+//! result follows the light actually emitted when [`GAMMA`] is set.
+use crate::*;
+
+/// Relative brightness of full red, green and blue (Rec. 709), summing
+/// to 1.
+pub const LUMINANCE_WEIGHTS: [f32; 3] = [0.2126, 0.7152, 0.0722];
+
+/// Reasons a hex color string was rejected.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum HexColorError {
+    /// Not exactly six digits after the optional `#`.
+    Length,
+    /// A character that isn't a hex digit.
+    Digit,
+}
+
+/// Converts an HSV color to RGB levels.
+///
+/// # Arguments
+/// * `hue` - Hue in degrees; values outside 0-360 wrap around
+/// * `saturation` - Saturation from 0.0 (grey) to 1.0 (pure hue)
+/// * `value` - Brightness from 0.0 (off) to 1.0 (full)
+///
+/// # Returns
+/// RGB levels [red, green, blue], each 0 to [`LEVELS`]-1.
+pub fn hsv_to_levels(hue: f32, saturation: f32, value: f32) -> [u32; 3] {
+    let saturation = saturation.clamp(0.0, 1.0);
+    let value = value.clamp(0.0, 1.0);
+    let mut hue = hue % 360.0;
+    if hue < 0.0 {
+        hue += 360.0;
+    }
+    let sector = hue / 60.0;
+    let chroma = value * saturation;
+    let x = chroma * (1.0 - num_traits::Float::abs((sector % 2.0) - 1.0));
+    let (r, g, b) = match sector as u32 {
+        0 => (chroma, x, 0.0),
+        1 => (x, chroma, 0.0),
+        2 => (0.0, chroma, x),
+        3 => (0.0, x, chroma),
+        4 => (x, 0.0, chroma),
+        _ => (chroma, 0.0, x),
+    };
+    let m = value - chroma;
+    [r, g, b].map(|c| ((c + m) * (LEVELS - 1) as f32 + 0.5) as u32)
+}
+
+/// Converts a saturation level at a fixed hue to RGB levels.
+///
+/// # Arguments
+/// * `hue` - Hue in degrees, as for [`hsv_to_levels`]
+/// * `saturation` - Saturation level, 0 (white) to [`LEVELS`]-1 (pure
+///   hue); higher values are clamped
+///
+/// # Returns
+/// RGB levels [red, green, blue] at full value.
+pub fn saturation_levels(hue: f32, saturation: u32) -> [u32; 3] {
+    let saturation = saturation.min(LEVELS - 1) as f32 / (LEVELS - 1) as f32;
+    hsv_to_levels(hue, saturation, 1.0)
+}
+
+/// Computes the perceived brightness of a color.
+///
+/// # Arguments
+/// * `levels` - RGB levels [red, green, blue] (0 to [`LEVELS`]-1; higher
+///   counts as the top level)
+/// * `gamma` - Curve to linearize the levels through, or `None` for linear
+///   levels
+///
+/// # Returns
+/// Relative luminance from 0.0 (black) to 1.0 (white).
+pub fn luminance(levels: [u32; 3], gamma: Option<Gamma>) -> f32 {
+    levels
+        .iter()
+        .zip(LUMINANCE_WEIGHTS.iter())
+        .map(|(&level, &weight)| {
+            let x = level.min(LEVELS - 1) as f32 / (LEVELS - 1) as f32;
+            let linear = match gamma {
+                Some(gamma) => gamma.curve(x),
+                None => x,
+            };
+            linear * weight
+        })
+        .sum::<f32>()
+        .min(1.0)
+}
+
+/// Parses a `#RRGGBB` color into RGB levels.
+///
+/// # Arguments
+/// * `s` - Six hex digits, optionally after a `#`, in either case
+///
+/// # Returns
+/// RGB levels [red, green, blue], each 8-bit channel rounded to the nearest
+/// of 0 to [`LEVELS`]-1.
+pub fn parse_color_hex(s: &str) -> Result<[u32; 3], HexColorError> {
+    let digits = s.strip_prefix('#').unwrap_or(s).as_bytes();
+    if digits.len() != 6 {
+        return Err(HexColorError::Length);
+    }
+    let mut levels = [0; 3];
+    for (level, pair) in levels.iter_mut().zip(digits.chunks(2)) {
+        let mut channel = 0;
+        for &digit in pair {
+            let value = (digit as char).to_digit(16).ok_or(HexColorError::Digit)?;
+            channel = channel * 16 + value;
+        }
+        *level = (channel * (LEVELS - 1) + 127) / 255;
+    }
+    Ok(levels)
+}
+
+/// Sets the RGB levels from a `#RRGGBB` color.
+///
+/// # Arguments
+/// * `s` - Color as accepted by [`parse_color_hex`]
+///
+/// # Returns
+/// The parse error, leaving the levels unchanged, if `s` is malformed.
+pub async fn set_color_hex(s: &str) -> Result<(), HexColorError> {
+    let levels = parse_color_hex(s)?;
+    set_rgb_levels(|rgb| *rgb = levels).await;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hsv_primary() {
+        assert_eq!(hsv_to_levels(120.0, 1.0, 1.0), [0, LEVELS - 1, 0]);
+    }
+
+    #[test]
+    fn saturation_fades_toward_the_hue() {
+        let full = LEVELS - 1;
+        // No saturation is white, full saturation the pure hue.
+        assert_eq!(saturation_levels(MOOD_HUE, 0), [full; 3]);
+        assert_eq!(saturation_levels(0.0, full), [full, 0, 0]);
+        assert_eq!(saturation_levels(240.0, full), [0, 0, full]);
+        assert_eq!(saturation_levels(30.0, full), [full, 8, 0]);
+        assert_eq!(saturation_levels(0.0, 99), saturation_levels(0.0, full));
+        // At a fixed hue, the strongest channel stays full while turning the
+        // saturation up only fades the others, so the hue never wanders.
+        for hue in [0.0, 30.0, 90.0, 200.0, 300.0] {
+            let mut previous = saturation_levels(hue, 0);
+            for saturation in 1..LEVELS {
+                let levels = saturation_levels(hue, saturation);
+                assert_eq!(levels.iter().max(), Some(&full));
+                for channel in 0..3 {
+                    assert!(levels[channel] <= previous[channel]);
+                }
+                previous = levels;
+            }
+            assert_eq!(previous, hsv_to_levels(hue, 1.0, 1.0));
+        }
+    }
+
+    #[test]
+    fn luminance_weights_and_gamma() {
+        let full = LEVELS - 1;
+        assert_eq!(luminance([0, 0, 0], None), 0.0);
+        assert!((luminance([full; 3], None) - 1.0).abs() < 1e-6);
+        // Each primary gives its own weight.
+        assert!((luminance([full, 0, 0], None) - 0.2126).abs() < 1e-6);
+        assert!((luminance([0, full, 0], None) - 0.7152).abs() < 1e-6);
+        assert!((luminance([0, 0, full], None) - 0.0722).abs() < 1e-6);
+        // Out-of-range levels are capped.
+        assert!((luminance([u32::MAX; 3], None) - 1.0).abs() < 1e-6);
+
+        // Gamma leaves black and white alone but darkens everything between.
+        let gamma = Some(Gamma {
+            exponent: 2.2,
+            knee: 0,
+        });
+        assert_eq!(luminance([0; 3], gamma), 0.0);
+        assert!((luminance([full; 3], gamma) - 1.0).abs() < 1e-6);
+        let grey = [full / 2; 3];
+        assert!(luminance(grey, gamma) < luminance(grey, None) / 2.0);
+    }
+
+    #[test]
+    fn hex_colors_parse_to_levels() {
+        assert_eq!(parse_color_hex("#FF0000"), Ok([LEVELS - 1, 0, 0]));
+        assert_eq!(parse_color_hex("00ff00"), Ok([0, LEVELS - 1, 0]));
+        assert_eq!(parse_color_hex("#ffFFff"), parse_color_hex("FFFFFF"));
+        assert_eq!(parse_color_hex("#000000"), Ok([0, 0, 0]));
+        // 0x80 = 128 is just past halfway: 128 * 15 / 255 = 7.53, so 8.
+        assert_eq!(parse_color_hex("#808080"), Ok([8, 8, 8]));
+        // Level 1 starts halfway to it, at 255 / 30 = 8.5.
+        assert_eq!(parse_color_hex("#080900"), Ok([0, 1, 0]));
+
+        assert_eq!(parse_color_hex(""), Err(HexColorError::Length));
+        assert_eq!(parse_color_hex("#"), Err(HexColorError::Length));
+        assert_eq!(parse_color_hex("#FFF"), Err(HexColorError::Length));
+        assert_eq!(parse_color_hex("#FF00000"), Err(HexColorError::Length));
+        assert_eq!(parse_color_hex("##FF0000"), Err(HexColorError::Length));
+        assert_eq!(parse_color_hex("#FF00G0"), Err(HexColorError::Digit));
+        assert_eq!(parse_color_hex("+FF000"), Err(HexColorError::Digit));
+        assert_eq!(parse_color_hex("#FF 000"), Err(HexColorError::Digit));
+        // Six bytes, but not six digits.
+        assert_eq!(parse_color_hex("#FF00\u{e9}"), Err(HexColorError::Digit));
+    }
+}
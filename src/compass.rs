@@ -0,0 +1,229 @@
+//! # Compass Hue Module
+//!
+//! This module provides a demo mode in which the LED hue follows the board's
+//! compass heading: rotating the micro:bit sweeps through the color wheel.
+//!
+//! ## Sensor
+//!
+//! The LSM303AGR magnetometer is driven directly over the internal I2C bus
+//! in continuous 10 Hz mode with temperature compensation and block data
+//! update. The heading is tilt-uncompensated, so hold the board flat.
+//!
+//! ## Hard-Iron Calibration
+//!
+//! Nearby magnets and the board itself offset the measured field. During
+//! calibration (`COMPASS CAL`) the user waves the board in a figure eight
+//! for [`COMPASS_CAL_TIME`] while the X/Y extremes are recorded; the
+//! midpoint of each axis becomes the offset subtracted from later readings.
+//!
+//! ## Heading to Color
+//!
+//! ```text
+//! heading = atan2(y - offset_y, x - offset_x), in degrees 0-360
+//! ```
+//!
+//! The heading is smoothed along the shortest arc, so a wobble between
+//! 359° and 1° never swings the long way round, and mapped to hue through
+//! [`hsv_to_levels`] at full saturation and value.
+use crate::*;
+
+/// How long the figure-eight calibration capture runs.
+pub const COMPASS_CAL_TIME: Duration = Duration::from_secs(15);
+/// Delay between magnetometer reads, in milliseconds.
+pub const COMPASS_PERIOD_MS: u64 = 100;
+/// Fraction of the heading error corrected per reading.
+pub const COMPASS_SMOOTHING: f32 = 0.25;
+
+/// Running X/Y extremes captured during hard-iron calibration.
+#[derive(Debug, Clone, Copy)]
+pub struct HardIron {
+    min: [i32; 2],
+    max: [i32; 2],
+}
+
+impl Default for HardIron {
+    fn default() -> Self {
+        Self {
+            min: [i32::MAX; 2],
+            max: [i32::MIN; 2],
+        }
+    }
+}
+
+impl HardIron {
+    /// Records one X/Y field reading.
+    pub fn add(&mut self, x: i32, y: i32) {
+        for (axis, value) in [x, y].into_iter().enumerate() {
+            self.min[axis] = self.min[axis].min(value);
+            self.max[axis] = self.max[axis].max(value);
+        }
+    }
+    /// Returns the X/Y offset to subtract, or `None` if nothing was recorded.
+    pub fn offset(&self) -> Option<[i32; 2]> {
+        if self.min[0] > self.max[0] {
+            return None;
+        }
+        Some([0, 1].map(|axis| (self.min[axis] + self.max[axis]) / 2))
+    }
+}
+
+/// Computes the compass heading from a field reading.
+///
+/// # Arguments
+/// * `x`, `y` - Raw magnetometer X and Y readings
+/// * `offset` - Hard-iron X/Y offset
+///
+/// # Returns
+/// Heading in degrees, 0.0 up to (not including) 360.0.
+pub fn heading_degrees(x: i32, y: i32, offset: [i32; 2]) -> f32 {
+    let x = (x - offset[0]) as f32;
+    let y = (y - offset[1]) as f32;
+    let heading = num_traits::Float::atan2(y, x).to_degrees();
+    if heading < 0.0 {
+        heading + 360.0
+    } else {
+        heading
+    }
+}
+
+/// Moves a smoothed heading toward a new reading along the shortest arc.
+///
+/// # Arguments
+/// * `current` - Current smoothed heading in degrees
+/// * `target` - New heading reading in degrees
+/// * `alpha` - Fraction of the angular error to correct (0.0 to 1.0)
+///
+/// # Returns
+/// The new smoothed heading, 0.0 up to (not including) 360.0.
+pub fn smooth_heading(current: f32, target: f32, alpha: f32) -> f32 {
+    let mut error = (target - current) % 360.0;
+    if error > 180.0 {
+        error -= 360.0;
+    } else if error < -180.0 {
+        error += 360.0;
+    }
+    let mut heading = current + error * alpha;
+    if heading < 0.0 {
+        heading += 360.0;
+    } else if heading >= 360.0 {
+        heading -= 360.0;
+    }
+    heading
+}
+
+/// Shared compass-mode state.
+#[derive(Debug, Clone, Copy)]
+pub struct CompassMode {
+    /// When the running calibration capture ends, if one is running.
+    pub calibrating_until: Option<Instant>,
+    /// Hard-iron X/Y offset.
+    pub offset: [i32; 2],
+    /// Hands the RGB levels to the compass hue, saving the user's color.
+    overlay: LevelsOverlay,
+}
+
+impl CompassMode {
+    /// Whether the compass hue currently owns the RGB levels.
+    pub fn enabled(&self) -> bool {
+        self.overlay.active()
+    }
+}
+
+/// Global compass-mode state, changed by the console.
+pub static COMPASS_MODE: Mutex<CriticalSectionRawMutex, CompassMode> = Mutex::new(CompassMode {
+    calibrating_until: None,
+    offset: [0; 2],
+    overlay: LevelsOverlay::OFF,
+});
+
+/// Enters or leaves compass mode.
+///
+/// Entering saves the current RGB levels; leaving restores them
+/// (see [`LevelsOverlay`]).
+pub async fn set_compass_mode(enabled: bool) {
+    let mut mode = COMPASS_MODE.lock().await;
+    if !mode.overlay.set(enabled).await {
+        return;
+    }
+    if enabled {
+        rprintln!("Compass mode: on");
+    } else {
+        rprintln!("Compass mode: off");
+    }
+}
+
+/// Starts a hard-iron calibration capture.
+pub async fn start_compass_calibration() {
+    COMPASS_MODE.lock().await.calibrating_until = Some(Instant::now() + COMPASS_CAL_TIME);
+    rprintln!(
+        "Compass calibration: wave the board in a figure eight for {} s",
+        COMPASS_CAL_TIME.as_secs()
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Hard-iron offset of the synthetic board.
+    const OFFSET: [i32; 2] = [120, -80];
+
+    /// The field reading pointing `degrees` round from +X, offset by
+    /// [`OFFSET`].
+    fn field(degrees: f32) -> (i32, i32) {
+        let radians = degrees.to_radians();
+        (
+            OFFSET[0] + num_traits::Float::round(400.0 * num_traits::Float::cos(radians)) as i32,
+            OFFSET[1] + num_traits::Float::round(400.0 * num_traits::Float::sin(radians)) as i32,
+        )
+    }
+
+    #[test]
+    fn hard_iron_offset_is_the_midpoint_of_the_extremes() {
+        let mut hard_iron = HardIron::default();
+        assert_eq!(hard_iron.offset(), None);
+        for degrees in (0..360).step_by(10) {
+            let (x, y) = field(degrees as f32);
+            hard_iron.add(x, y);
+        }
+        assert_eq!(hard_iron.offset(), Some(OFFSET));
+    }
+
+    #[test]
+    fn heading_follows_the_field_all_the_way_round() {
+        for degrees in (0..360).step_by(15) {
+            let (x, y) = field(degrees as f32);
+            let heading = heading_degrees(x, y, OFFSET);
+            assert!((0.0..360.0).contains(&heading), "{}", heading);
+            assert!(
+                (heading - degrees as f32).abs() < 0.2,
+                "{} {}",
+                degrees,
+                heading
+            );
+        }
+        // Just below +X reads just below 360, not negative.
+        let (x, y) = field(-1.0);
+        assert!((heading_degrees(x, y, OFFSET) - 359.0).abs() < 0.2);
+        // Without the offset the same readings point elsewhere.
+        let (x, y) = field(90.0);
+        assert!((heading_degrees(x, y, [0; 2]) - 90.0).abs() > 5.0);
+    }
+
+    #[test]
+    fn smoothing_takes_the_short_way_across_north() {
+        assert_eq!(smooth_heading(359.0, 1.0, 0.5), 0.0);
+        assert_eq!(smooth_heading(1.0, 359.0, 0.5), 0.0);
+        assert_eq!(smooth_heading(350.0, 10.0, 0.25), 355.0);
+        assert_eq!(smooth_heading(10.0, 350.0, 0.25), 5.0);
+        assert_eq!(smooth_heading(90.0, 120.0, 1.0), 120.0);
+        // Settling from 300 to 20 runs up through north, never through
+        // south, and stays in range.
+        let mut heading = 300.0;
+        for _ in 0..60 {
+            heading = smooth_heading(heading, 20.0, COMPASS_SMOOTHING);
+            assert!(heading >= 300.0 || heading <= 20.0, "{}", heading);
+        }
+        assert!((heading - 20.0).abs() < 0.01, "{}", heading);
+    }
+}
@@ -0,0 +1,624 @@
+//! # Full Configuration Module
+//!
+//! This module gathers the whole runtime configuration into one
+//! [`FullConfig`], so it can be snapshotted before an experiment and put
+//! back in one step: the console's `DUMP` prints it as a single hex blob,
+//! and `RESTORE <blob>` applies it. The same blob can be saved through a
+//! [`StorageBackend`] like the settings.
+//!
+//! ## Contents
+//!
+//! The levels, frame rate, saturation, master brightness, and output
+//! enable (everything a [`Defaults`] holds), then the status timing line,
+//! the verbosity, the software PWM's dither mode or, with `hwpwm`, the
+//! channel enables, the gamma curve, and the frame-rate demo's enable and
+//! color. The saturation, timing line and demo are UI state, so the UI task
+//! captures and applies the configuration.
+//!
+//! Left out, each for a reason:
+//!
+//! - **Build-time settings**: the knob responses ([`KNOB_CONFIG`]) and
+//!   windows ([`KNOB_RANGES`]), [`BRIGHTNESS_CEILING`], the reset (panic)
+//!   color [`RESET_COLOR`], [`FULL_ON_LIMIT`], [`PWM_PHASES`],
+//!   [`FLICKER_THRESHOLD_FPS`], [`CHORD_ACTION`], [`TOUCH_ACTION`] and
+//!   [`MOOD_HUE`] are constants, so there is nothing to restore: the same
+//!   firmware has the same ones.
+//! - **Modes and sessions**: the knob focus, the idle effect, the
+//!   temperature, compass, tilt and test-pattern modes, the sleep timer,
+//!   camera sync, telemetry and recording are what the board is doing, not
+//!   how it is set up, and restoring one would start it.
+//! - **Feature settings**: the lux compensation, bar graph style, tone mute,
+//!   temperature gradient, schedule, DMX address, radio role and group,
+//!   matrix brightness and test-pattern dwell only exist with their
+//!   features. Leaving them out keeps a blob the same length, and valid,
+//!   whatever features the firmware that reads it was built with, and
+//!   within a console line (below).
+//!
+//! ## Format
+//!
+//! The blob is framed like the settings (see [`frame_blob`]) with its own
+//! magic, [`FULL_CONFIG_MAGIC`], and its payload starts with the settings
+//! version 2 payload:
+//!
+//! ```text
+//! settings payload (8) | explain_timing u8 | verbosity u8 | dither u8 | channel mask u8
+//!     | gamma exponent u16 | gamma knee u8 | demo enable u8 | demo color u8 x3
+//! ```
+//!
+//! The gamma exponent is stored in hundredths, 0 for linear levels.
+//! Version 1 blobs end before the gamma curve and version 2 blobs before
+//! the demo. As hex after `RESTORE `, a current blob fits a
+//! [`CONSOLE_LINE_MAX`] console line.
+//!
+//! [`decode_full_config`] also accepts version 1 and 2 blobs and a settings
+//! blob, the older formats, taking the fields they lack from their power-on
+//! values.
+//!
+//! ## Restoring
+//!
+//! Numeric fields are checked through the same paths as every other
+//! writer ([`validate_levels`], [`validate_frame_rate`], and the saturation
+//! and brightness limits), and an out-of-range value is clamped rather than
+//! rejecting the blob; [`FullConfig::validated`] reports exactly which
+//! fields were adjusted. Flags and modes have no nearest valid value, so an
+//! unknown code rejects the blob, as does a bad CRC.
+use crate::*;
+
+/// First two bytes of every full-configuration blob, "RF".
+pub const FULL_CONFIG_MAGIC: [u8; 2] = *b"RF";
+/// Version [`encode_full_config`] writes.
+pub const FULL_CONFIG_VERSION: u8 = 3;
+/// Payload length of version 1.
+const FULL_CONFIG_V1_LEN: usize = SETTINGS_V2_LEN + 4;
+/// Payload length of version 2: version 1, then the gamma curve.
+const FULL_CONFIG_V2_LEN: usize = FULL_CONFIG_V1_LEN + 3;
+/// Payload length of version 3: version 2, then the frame-rate demo.
+const FULL_CONFIG_V3_LEN: usize = FULL_CONFIG_V2_LEN + 4;
+/// Length of a current-version blob, CRC included.
+pub const FULL_CONFIG_BLOB_LEN: usize = SETTINGS_HEADER_LEN + FULL_CONFIG_V3_LEN + 2;
+/// Units of the stored gamma exponent, per 1.0.
+const GAMMA_EXPONENT_UNITS: f32 = 100.0;
+
+// `RESTORE ` and the blob in hex fit a console line.
+const _: () = assert!("RESTORE ".len() + 2 * FULL_CONFIG_BLOB_LEN <= CONSOLE_LINE_MAX);
+
+/// Every runtime setting that `DUMP` saves and `RESTORE` applies.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FullConfig {
+    /// RGB levels.
+    pub levels: [u32; 3],
+    /// Frame rate in FPS.
+    pub frame_rate: u64,
+    /// Saturation level of [`MOOD_HUE`].
+    pub saturation: u32,
+    /// Master brightness.
+    pub brightness: u32,
+    /// Output enable.
+    pub output_enabled: bool,
+    /// Whether the status output explains the PWM timing.
+    pub explain_timing: bool,
+    /// Diagnostic verbosity.
+    pub verbosity: Verbosity,
+    /// Software PWM dither mode.
+    #[cfg(not(feature = "hwpwm"))]
+    pub dither: DitherMode,
+    /// Hardware PWM channel enables [red, green, blue].
+    #[cfg(feature = "hwpwm")]
+    pub channels: [bool; 3],
+    /// Gamma curve, `None` for linear levels.
+    pub gamma: Option<Gamma>,
+    /// Whether adjusting the frame rate shows the demo color.
+    pub fps_demo: bool,
+    /// Frame-rate demo color.
+    pub fps_demo_color: [u32; 3],
+}
+
+impl FullConfig {
+    /// Takes the settings' fields, and the power-on values for the rest.
+    pub fn from_settings(settings: &Defaults) -> Self {
+        Self {
+            levels: settings.levels,
+            frame_rate: settings.frame_rate,
+            saturation: settings.saturation,
+            brightness: settings.brightness,
+            output_enabled: settings.output_enabled,
+            explain_timing: false,
+            verbosity: Verbosity::Info,
+            #[cfg(not(feature = "hwpwm"))]
+            dither: DitherMode::Off,
+            #[cfg(feature = "hwpwm")]
+            channels: [true; 3],
+            gamma: GAMMA,
+            fps_demo: FrameRateDemo::DEFAULT.enabled,
+            fps_demo_color: FrameRateDemo::DEFAULT.color,
+        }
+    }
+
+    /// The fields a [`Defaults`] holds.
+    pub fn settings(&self) -> Defaults {
+        Defaults {
+            levels: self.levels,
+            frame_rate: self.frame_rate,
+            saturation: self.saturation,
+            brightness: self.brightness,
+            output_enabled: self.output_enabled,
+        }
+    }
+
+    /// Clamps every numeric field into its valid range.
+    ///
+    /// # Returns
+    /// The configuration to apply, and which fields had to change.
+    pub fn validated(self) -> (Self, ConfigAdjusted) {
+        let mut config = self;
+        let mut adjusted = ConfigAdjusted::default();
+        if let Err(clamped) = validate_levels(config.levels) {
+            adjusted.levels = core::array::from_fn(|led| clamped[led] != config.levels[led]);
+            config.levels = clamped;
+        }
+        if let Err(clamped) = validate_frame_rate(config.frame_rate) {
+            config.frame_rate = clamped;
+            adjusted.frame_rate = true;
+        }
+        if config.saturation >= LEVELS {
+            config.saturation = LEVELS - 1;
+            adjusted.saturation = true;
+        }
+        if config.brightness > BRIGHTNESS_MAX {
+            config.brightness = BRIGHTNESS_MAX;
+            adjusted.brightness = true;
+        }
+        if let Some(gamma) = &mut config.gamma {
+            let exponent = gamma.exponent.clamp(GAMMA_EXPONENT_MIN, GAMMA_EXPONENT_MAX);
+            let knee = gamma.knee.min(LEVELS - 1);
+            adjusted.gamma = exponent != gamma.exponent || knee != gamma.knee;
+            *gamma = Gamma { exponent, knee };
+        }
+        if let Err(clamped) = validate_levels(config.fps_demo_color) {
+            config.fps_demo_color = clamped;
+            adjusted.fps_demo_color = true;
+        }
+        (config, adjusted)
+    }
+}
+
+/// Fields [`FullConfig::validated`] had to clamp.
+///
+/// Displays the adjusted fields by their `RANGES` keys (see
+/// [`parameter_key`]), comma separated, or `none`.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct ConfigAdjusted {
+    /// Each RGB level.
+    pub levels: [bool; 3],
+    /// The frame rate.
+    pub frame_rate: bool,
+    /// The saturation.
+    pub saturation: bool,
+    /// The master brightness.
+    pub brightness: bool,
+    /// The gamma curve's exponent or knee.
+    pub gamma: bool,
+    /// The frame-rate demo color.
+    pub fps_demo_color: bool,
+}
+
+impl ConfigAdjusted {
+    /// Whether any field was adjusted.
+    pub fn any(&self) -> bool {
+        self.levels.contains(&true)
+            || self.frame_rate
+            || self.saturation
+            || self.brightness
+            || self.gamma
+            || self.fps_demo_color
+    }
+}
+
+impl core::fmt::Display for ConfigAdjusted {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        let fields = [
+            (self.levels[0], parameter_key(ControlParameter::Red)),
+            (self.levels[1], parameter_key(ControlParameter::Green)),
+            (self.levels[2], parameter_key(ControlParameter::Blue)),
+            (self.frame_rate, parameter_key(ControlParameter::FrameRate)),
+            (self.saturation, parameter_key(ControlParameter::Saturation)),
+            (self.brightness, "brightness"),
+            (self.gamma, "gamma"),
+            (self.fps_demo_color, "demo"),
+        ];
+        let mut first = true;
+        for (_, key) in fields.iter().filter(|(adjusted, _)| *adjusted) {
+            if !first {
+                write!(f, ", ")?;
+            }
+            write!(f, "{}", key)?;
+            first = false;
+        }
+        if first {
+            write!(f, "none")?;
+        }
+        Ok(())
+    }
+}
+
+/// Encodes a configuration as a current-version blob.
+///
+/// Values are stored as they are, even out of range, so they can be
+/// reported when the blob is restored.
+pub fn encode_full_config(config: &FullConfig) -> [u8; FULL_CONFIG_BLOB_LEN] {
+    let mut payload = [0; FULL_CONFIG_V3_LEN];
+    payload[..SETTINGS_V2_LEN].copy_from_slice(&encode_settings_payload(&config.settings()));
+    payload[SETTINGS_V2_LEN] = config.explain_timing as u8;
+    payload[SETTINGS_V2_LEN + 1] = match config.verbosity {
+        Verbosity::Info => 0,
+        Verbosity::Debug => 1,
+    };
+    #[cfg(not(feature = "hwpwm"))]
+    {
+        payload[SETTINGS_V2_LEN + 2] = match config.dither {
+            DitherMode::Off => 0,
+            DitherMode::Ordered => 1,
+            DitherMode::ErrorDiffusion => 2,
+        };
+        payload[SETTINGS_V2_LEN + 3] = 0b111;
+    }
+    #[cfg(feature = "hwpwm")]
+    {
+        let mask = (0..3).filter(|&led| config.channels[led]);
+        payload[SETTINGS_V2_LEN + 3] = mask.fold(0u8, |mask, led| mask | 1 << led);
+    }
+    if let Some(gamma) = config.gamma {
+        // An exponent too small to store stays a curve, not linear levels.
+        let exponent = num_traits::Float::round(gamma.exponent * GAMMA_EXPONENT_UNITS);
+        let exponent = exponent.clamp(1.0, u16::MAX as f32) as u16;
+        let [exponent0, exponent1] = exponent.to_le_bytes();
+        payload[FULL_CONFIG_V1_LEN..FULL_CONFIG_V2_LEN].copy_from_slice(&[
+            exponent0,
+            exponent1,
+            gamma.knee.min(u8::MAX as u32) as u8,
+        ]);
+    }
+    payload[FULL_CONFIG_V2_LEN] = config.fps_demo as u8;
+    for (byte, level) in payload[FULL_CONFIG_V2_LEN + 1..]
+        .iter_mut()
+        .zip(config.fps_demo_color)
+    {
+        *byte = level.min(u8::MAX as u32) as u8;
+    }
+    frame_blob(FULL_CONFIG_MAGIC, FULL_CONFIG_VERSION, &payload)
+}
+
+/// Decodes a full-configuration blob, or a settings blob as the older
+/// format.
+///
+/// Numeric fields come back as stored; pass the result through
+/// [`FullConfig::validated`] before applying it.
+///
+/// # Returns
+/// The configuration, or why the blob was rejected.
+pub fn decode_full_config(blob: &[u8]) -> Result<FullConfig, SettingsError> {
+    if blob.starts_with(&SETTINGS_MAGIC) {
+        return decode_settings(blob).map(|settings| FullConfig::from_settings(&settings));
+    }
+    let (header, payload) = SettingsHeader::parse_with(FULL_CONFIG_MAGIC, blob)?;
+    let expected_len = match header.version {
+        1 => FULL_CONFIG_V1_LEN,
+        2 => FULL_CONFIG_V2_LEN,
+        3 => FULL_CONFIG_V3_LEN,
+        version => return Err(SettingsError::UnknownVersion(version)),
+    };
+    if payload.len() != expected_len {
+        return Err(SettingsError::BadLength);
+    }
+    let (v1, later) = payload.split_at(FULL_CONFIG_V1_LEN);
+    let &[r, g, b, fps0, fps1, saturation, brightness, output, explain, verbosity, dither, mask] =
+        v1
+    else {
+        return Err(SettingsError::BadLength);
+    };
+    let flag = |byte| match byte {
+        0 => Ok(false),
+        1 => Ok(true),
+        _ => Err(SettingsError::OutOfRange),
+    };
+    let mut config = FullConfig::from_settings(&Defaults {
+        levels: [r as u32, g as u32, b as u32],
+        frame_rate: u16::from_le_bytes([fps0, fps1]) as u64,
+        saturation: saturation as u32,
+        brightness: brightness as u32,
+        output_enabled: flag(output)?,
+    });
+    config.explain_timing = flag(explain)?;
+    config.verbosity = match verbosity {
+        0 => Verbosity::Info,
+        1 => Verbosity::Debug,
+        _ => return Err(SettingsError::OutOfRange),
+    };
+    if mask > 0b111 {
+        return Err(SettingsError::OutOfRange);
+    }
+    // A blob from the other PWM backend carries that backend's field; it
+    // only has to be well formed.
+    #[cfg(not(feature = "hwpwm"))]
+    {
+        config.dither = match dither {
+            0 => DitherMode::Off,
+            1 => DitherMode::Ordered,
+            2 => DitherMode::ErrorDiffusion,
+            _ => return Err(SettingsError::OutOfRange),
+        };
+    }
+    #[cfg(feature = "hwpwm")]
+    {
+        if dither > 2 {
+            return Err(SettingsError::OutOfRange);
+        }
+        config.channels = core::array::from_fn(|led| mask & 1 << led != 0);
+    }
+    // Version 1 ends here, and version 2 before the demo; what a blob
+    // lacks keeps its power-on value.
+    if let &[exponent0, exponent1, knee, ref later @ ..] = later {
+        config.gamma = match u16::from_le_bytes([exponent0, exponent1]) {
+            0 => None,
+            exponent => Some(Gamma {
+                exponent: exponent as f32 / GAMMA_EXPONENT_UNITS,
+                knee: knee as u32,
+            }),
+        };
+        if let &[demo, r, g, b] = later {
+            config.fps_demo = flag(demo)?;
+            config.fps_demo_color = [r as u32, g as u32, b as u32];
+        }
+    }
+    Ok(config)
+}
+
+/// Formats a blob as uppercase hex digits, two per byte.
+pub struct HexBlob<'a>(pub &'a [u8]);
+
+impl core::fmt::Display for HexBlob<'_> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        for byte in self.0 {
+            write!(f, "{:02X}", byte)?;
+        }
+        Ok(())
+    }
+}
+
+/// Parses the hex digits of a blob, as printed by [`HexBlob`], in either
+/// case.
+///
+/// # Returns
+/// The bytes, in a buffer as long as the longest blob (a
+/// full-configuration one), and how many there are.
+pub fn parse_hex_blob(text: &str) -> Result<([u8; FULL_CONFIG_BLOB_LEN], usize), SettingsError> {
+    let digits = text.as_bytes();
+    if digits.len() % 2 == 1 {
+        return Err(SettingsError::BadHex);
+    }
+    let mut buffer = [0; FULL_CONFIG_BLOB_LEN];
+    let len = digits.len() / 2;
+    if len > buffer.len() {
+        return Err(SettingsError::BadLength);
+    }
+    for (byte, pair) in buffer.iter_mut().zip(digits.chunks(2)) {
+        let pair = core::str::from_utf8(pair).map_err(|_| SettingsError::BadHex)?;
+        if !pair.bytes().all(|digit| digit.is_ascii_hexdigit()) {
+            return Err(SettingsError::BadHex);
+        }
+        *byte = u8::from_str_radix(pair, 16).map_err(|_| SettingsError::BadHex)?;
+    }
+    Ok((buffer, len))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn validated_adjusts_only_the_fields_out_of_range() {
+        let config = FullConfig::from_settings(&DEFAULTS);
+        assert_eq!(config.validated(), (config, ConfigAdjusted::default()));
+
+        // Only the fields out of range are adjusted, and each is named.
+        let wild = FullConfig {
+            levels: [3, LEVELS, 200],
+            frame_rate: 5,
+            brightness: 250,
+            ..config
+        };
+        let (fixed, adjusted) = wild.validated();
+        assert_eq!(fixed.levels, [3, LEVELS - 1, LEVELS - 1]);
+        assert_eq!(fixed.frame_rate, FRAME_RATE_MIN);
+        assert_eq!(fixed.brightness, BRIGHTNESS_MAX);
+        assert_eq!(fixed.saturation, config.saturation);
+        assert_eq!(adjusted.levels, [false, true, true]);
+        assert!(adjusted.frame_rate && adjusted.brightness && !adjusted.saturation);
+    }
+
+    #[test]
+    fn full_config_round_trips_and_saves() {
+        let config = FullConfig {
+            levels: [3, 9, 15],
+            explain_timing: true,
+            verbosity: Verbosity::Debug,
+            ..FullConfig::from_settings(&DEFAULTS)
+        };
+        let blob = encode_full_config(&config);
+        assert_eq!(&blob[..3], &[b'R', b'F', FULL_CONFIG_VERSION]);
+        assert_eq!(decode_full_config(&blob), Ok(config));
+
+        // It saves like the settings.
+        let storage = RamStorage::new();
+        storage.save(&blob).unwrap();
+        assert_eq!(
+            decode_full_config(storage.load().unwrap().as_bytes()),
+            Ok(config)
+        );
+    }
+
+    #[test]
+    fn decode_rejects_corrupt_and_future_blobs() {
+        let config = FullConfig {
+            frame_rate: 90,
+            ..FullConfig::from_settings(&DEFAULTS)
+        };
+        let blob = encode_full_config(&config);
+
+        // Any flipped bit is caught by the CRC.
+        for byte in SETTINGS_HEADER_LEN..blob.len() {
+            let mut corrupt = blob;
+            corrupt[byte] ^= 0x01;
+            assert_eq!(decode_full_config(&corrupt), Err(SettingsError::BadCrc));
+        }
+        assert_eq!(
+            decode_full_config(&blob[..10]),
+            Err(SettingsError::BadLength)
+        );
+        assert_eq!(decode_full_config(b"hello"), Err(SettingsError::BadMagic));
+
+        // A settings blob restores its fields and leaves the rest at power-on.
+        let settings = Defaults {
+            levels: [1, 2, 3],
+            brightness: 40,
+            ..DEFAULTS
+        };
+        assert_eq!(
+            decode_full_config(&encode_settings(&settings)),
+            Ok(FullConfig::from_settings(&settings)),
+        );
+
+        // An out-of-range level survives decoding, to be clamped and reported.
+        let wild = FullConfig {
+            levels: [LEVELS + 4, 0, 0],
+            ..config
+        };
+        let decoded = decode_full_config(&encode_full_config(&wild)).unwrap();
+        assert_eq!(decoded.levels[0], LEVELS + 4);
+        assert_eq!(decoded.validated().1.levels, [true, false, false]);
+
+        // A newer version is rejected even with a good CRC.
+        let mut payload = [0; FULL_CONFIG_V3_LEN];
+        payload
+            .copy_from_slice(&blob[SETTINGS_HEADER_LEN..SETTINGS_HEADER_LEN + FULL_CONFIG_V3_LEN]);
+        let future: [u8; FULL_CONFIG_BLOB_LEN] =
+            frame_blob(FULL_CONFIG_MAGIC, FULL_CONFIG_VERSION + 1, &payload);
+        assert_eq!(
+            decode_full_config(&future),
+            Err(SettingsError::UnknownVersion(4))
+        );
+    }
+
+    #[test]
+    fn gamma_round_trips_and_clamps() {
+        let config = FullConfig::from_settings(&DEFAULTS);
+        let curves = [
+            None,
+            Some(Gamma {
+                exponent: 2.2,
+                knee: 3,
+            }),
+            Some(Gamma {
+                exponent: 1.0,
+                knee: 0,
+            }),
+        ];
+        for gamma in curves {
+            let config = FullConfig { gamma, ..config };
+            assert_eq!(decode_full_config(&encode_full_config(&config)), Ok(config));
+        }
+
+        // A version 1 blob, from before the gamma curve, keeps the power-on one.
+        let v1: [u8; SETTINGS_HEADER_LEN + FULL_CONFIG_V1_LEN + 2] = frame_blob(
+            FULL_CONFIG_MAGIC,
+            1,
+            &encode_full_config(&config)
+                [SETTINGS_HEADER_LEN..SETTINGS_HEADER_LEN + FULL_CONFIG_V1_LEN],
+        );
+        let decoded = decode_full_config(&v1).unwrap();
+        assert_eq!(
+            decoded,
+            FullConfig {
+                gamma: GAMMA,
+                ..config
+            }
+        );
+
+        // An out-of-range curve survives decoding, to be clamped and reported.
+        let wild = FullConfig {
+            gamma: Some(Gamma {
+                exponent: 4.5,
+                knee: 40,
+            }),
+            ..config
+        };
+        let (fixed, adjusted) = decode_full_config(&encode_full_config(&wild))
+            .unwrap()
+            .validated();
+        assert_eq!(
+            fixed.gamma,
+            Some(Gamma {
+                exponent: GAMMA_EXPONENT_MAX,
+                knee: LEVELS - 1
+            })
+        );
+        assert!(adjusted.gamma && !adjusted.brightness);
+    }
+
+    #[test]
+    fn frame_rate_demo_round_trips_and_clamps() {
+        let config = FullConfig {
+            fps_demo: true,
+            fps_demo_color: [15, 0, 4],
+            ..FullConfig::from_settings(&DEFAULTS)
+        };
+        let blob = encode_full_config(&config);
+        assert_eq!(decode_full_config(&blob), Ok(config));
+
+        // A version 2 blob, from before the demo, keeps the power-on demo.
+        let v2: [u8; SETTINGS_HEADER_LEN + FULL_CONFIG_V2_LEN + 2] = frame_blob(
+            FULL_CONFIG_MAGIC,
+            2,
+            &blob[SETTINGS_HEADER_LEN..SETTINGS_HEADER_LEN + FULL_CONFIG_V2_LEN],
+        );
+        assert_eq!(
+            decode_full_config(&v2),
+            Ok(FullConfig {
+                fps_demo: FrameRateDemo::DEFAULT.enabled,
+                fps_demo_color: FrameRateDemo::DEFAULT.color,
+                ..config
+            }),
+        );
+
+        // An out-of-range color is clamped and reported; a bad flag rejects.
+        let wild = FullConfig {
+            fps_demo_color: [0, LEVELS, 0],
+            ..config
+        };
+        let (fixed, adjusted) = decode_full_config(&encode_full_config(&wild))
+            .unwrap()
+            .validated();
+        assert_eq!(fixed.fps_demo_color, [0, LEVELS - 1, 0]);
+        assert!(adjusted.fps_demo_color && !adjusted.levels.contains(&true));
+        let mut payload = [0; FULL_CONFIG_V3_LEN];
+        payload
+            .copy_from_slice(&blob[SETTINGS_HEADER_LEN..SETTINGS_HEADER_LEN + FULL_CONFIG_V3_LEN]);
+        payload[FULL_CONFIG_V2_LEN] = 2;
+        let bad: [u8; FULL_CONFIG_BLOB_LEN] =
+            frame_blob(FULL_CONFIG_MAGIC, FULL_CONFIG_VERSION, &payload);
+        assert_eq!(decode_full_config(&bad), Err(SettingsError::OutOfRange));
+    }
+
+    #[test]
+    fn hex_blobs_parse_to_bytes() {
+        let (buffer, len) = parse_hex_blob("52460aFF").unwrap();
+        assert_eq!(&buffer[..len], &[0x52, 0x46, 0x0A, 0xFF]);
+        assert_eq!(parse_hex_blob("524"), Err(SettingsError::BadHex));
+        assert_eq!(parse_hex_blob("52G6"), Err(SettingsError::BadHex));
+        assert_eq!(parse_hex_blob("+1"), Err(SettingsError::BadHex));
+        // One byte too many for any blob.
+        let long = [b'0'; 2 * (FULL_CONFIG_BLOB_LEN + 1)];
+        let long = core::str::from_utf8(&long).unwrap();
+        assert_eq!(parse_hex_blob(long), Err(SettingsError::BadLength));
+    }
+}
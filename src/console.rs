@@ -0,0 +1,806 @@
+//! # Command Console Module
+//!
+//! This module provides a line-oriented text command interface over an RTT
+//! down channel, so the calibration can be driven from the host terminal
+//! (`cargo embed` forwards typed lines to the target).
+//!
+//! ## Commands
+//!
+//! Commands are case-insensitive, one per line:
+//!
+//! - `FOCUS R|G|B|FPS|SAT`: Pin the knob to one parameter regardless of
+//!   buttons; `SAT` varies the saturation of a fixed hue
+//! - `FOCUS` or `FOCUS OFF`: Return the knob to button-selected control
+//! - `RGB <r> <g> <b>` or `RGB #RRGGBB`: Set all three levels at once
+//! - `RESET [FPS|RGB]`: Restore the default levels and frame rate, or only one
+//! - `TIMING`: Report the PWM tick time and resulting frame period
+//! - `TIMING EXPLAIN ON|OFF`: Add that timing line to the status output
+//! - `VERBOSITY INFO|DEBUG`: Set how much diagnostic output is printed
+//! - `OUTPUT [ON|OFF]`: Report, enable, or blank the LED output
+//! - `GAMMA <exponent>|OFF`: Set the gamma curve's exponent (1.0-3.0), or go linear
+//! - `DITHER OFF|ORDERED|DIFFUSION` (not `hwpwm`): Set the PWM dither mode
+//! - `PWM R|G|B ON|OFF` (`hwpwm` feature): Connect or release one channel's pin
+//! - `SYNC ON|OFF` (`camsync` feature): Start frames on the camera trigger
+//! - `LUX` (`bh1750` feature): Report the light level and compensation
+//! - `LUX ON|OFF` (`bh1750` feature): Enable or disable lux compensation
+//! - `LUX MIN|MAX <percent>` (`bh1750` feature): Set the dark/bright scale
+//! - `LUX TAU <seconds>` (`bh1750` feature): Set the smoothing time constant
+//! - `BARS BAR|DOT` (`bargraph` feature): Set the bar graph style
+//! - `DEMO ON|OFF`: Show a demo color while the frame rate is adjusted
+//! - `DEMO COLOR <r> <g> <b>|#RRGGBB`: Set the frame-rate demo color
+//! - `KNOB STATS [<samples>]`: Report the raw knob reading's jitter statistics
+//! - `KNOB CAL`: Recalibrate the knob's ADC now
+//! - `LATENCY`: Report the knob input-to-LED latency since the last report
+//! - `RANGES`: Report the valid range of each setting as `key=low..high` lines
+//! - `SNAP A|B`: Store the current levels and frame rate in a snapshot slot
+//! - `DIFF`: Report the change from snapshot A to snapshot B
+//! - `SELFTEST`: Run the hardware self-test suite and report pass/fail per check
+//! - `STATS [RESET]`: Report the runtime event counters, optionally zeroing them, and flash wear
+//! - `DUMP`: Print the whole runtime configuration as one hex blob
+//! - `RESTORE <blob>`: Apply a blob from `DUMP`, reporting any clamped fields
+//! - `SAVE`: Store the current settings in flash as the power-on values
+//! - `EFFECT [OFF|BREATHE|DRIFT]` (`idle` feature): Report or set the effect layered over the color
+//! - `MUTE ON|OFF` (`tone` feature): Silence or restore tone feedback
+//! - `TEMP ON|OFF` (`temperature` feature): Enter or leave temperature mode
+//! - `TEMP LOW|HIGH <celsius>` (`temperature` feature): Set a gradient endpoint
+//! - `COMPASS ON|OFF` (`compass` feature): Enter or leave compass hue mode
+//! - `COMPASS CAL` (`compass` feature): Capture the hard-iron offset
+//! - `TILT ON|OFF` (`motion` feature): Enter or leave tilt color mode
+//! - `SLEEP [<minutes>]|OFF` (`sleep` feature): Fade out and turn off after a delay
+//! - `SCHEDULE ON|OFF` (`schedule` feature): Enable or disable day/night dimming
+//! - `SCHEDULE PERIOD <seconds>` (`schedule` feature): Set the cycle length
+//! - `SCHEDULE CURVE COSINE|STEP` (`schedule` feature): Set the curve shape
+//! - `DMX ADDRESS <n>` (`dmx` feature): Set the fixture's first DMX channel
+//! - `RADIO TEACHER|STUDENT|OFF` (`radio` feature): Set the radio role
+//! - `RADIO GROUP <0-15>` (`radio` feature): Set the radio group
+//! - `MATRIX [<0-9>]` (`radio` feature): Report or set the matrix status LED's brightness
+//! - `RADIO REMOTE|HOST` (`remote` feature): Act as a wireless knob or its host
+//! - `TELEMETRY ON [<hz>]|OFF` (`telemetry` feature): Start or stop telemetry records
+//! - `LOG DUMP` (`changelog` feature): Print the logged calibration changes
+//! - `RECORD ON|OFF|DUMP` (`record` feature): Record UI input traces, or print one
+//! - `PATTERN ON|OFF` (`testpattern` feature): Start or stop the test pattern
+//! - `PATTERN DWELL <seconds>` (`testpattern` feature): Set the time per color
+//! - `FAULT KNOB <raw>|OFF` (`fault-inject`, debug): Override knob readings
+//! - `FAULT FPS <fps>` (`fault-inject`, debug): Write an unchecked frame rate
+//! - `FAULT LEVEL R|G|B <level>` (`fault-inject`, debug): Write an unchecked level
+//!
+//! ## Architecture
+//!
+//! [`console_task`] polls the down channel, assembles lines, and hands each
+//! one to [`parse_command`]. Commands that touch shared state are executed
+//! directly; commands that change UI-local state are forwarded to the UI
+//! task through [`UI_COMMANDS`].
+use crate::*;
+use embassy_sync::channel::Channel;
+
+/// Maximum length of one console line, in bytes.
+pub const CONSOLE_LINE_MAX: usize = 64;
+/// Maximum number of arguments after the command word.
+const CONSOLE_ARGS_MAX: usize = 4;
+
+/// A parsed console command.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Command {
+    /// Pin the knob to a parameter, or clear the pin with `None`.
+    Focus(Option<ControlParameter>),
+    /// Set all three levels in one change.
+    Rgb([u32; 3]),
+    /// Restore defaults for the levels, the frame rate, or both.
+    Reset(ResetTarget),
+    /// Report the PWM timing in use.
+    Timing,
+    /// Include (`true`) the timing explanation in the status output.
+    ExplainTiming(bool),
+    /// Set the diagnostic verbosity.
+    Verbosity(Verbosity),
+    /// Enable (`Some(true)`) or blank the LED output, or just report it.
+    Output(Option<bool>),
+    /// Set the gamma exponent, or (`None`) go back to linear levels.
+    Gamma(Option<f32>),
+    /// Set how the software PWM dithers fractions of a timer tick.
+    #[cfg(not(feature = "hwpwm"))]
+    Dither(DitherMode),
+    /// Enable (`true`) or disable one hardware PWM channel (0 = red).
+    #[cfg(feature = "hwpwm")]
+    PwmChannel(usize, bool),
+    /// Report the lux reading and compensation settings.
+    #[cfg(feature = "bh1750")]
+    Lux,
+    /// Enable (`true`) or disable lux compensation.
+    #[cfg(feature = "bh1750")]
+    LuxEnabled(bool),
+    /// Set the scale in the dark (`false`) or bright light (`true`), in percent.
+    #[cfg(feature = "bh1750")]
+    LuxScale(bool, u32),
+    /// Set the lux smoothing time constant, in seconds.
+    #[cfg(feature = "bh1750")]
+    LuxTau(u32),
+    /// Set the bar graph style.
+    #[cfg(feature = "bargraph")]
+    BarStyle(BarStyle),
+    /// Enable (`true`) or disable the frame-rate demo color.
+    FpsDemo(bool),
+    /// Set the frame-rate demo color.
+    FpsDemoColor([u32; 3]),
+    /// Report raw knob statistics over this many samples.
+    KnobStats(u32),
+    /// Recalibrate the knob's ADC.
+    KnobCalibrate,
+    /// Report and restart the input latency distribution.
+    Latency,
+    /// Report the valid range of each setting.
+    Ranges,
+    /// Store the current state in a snapshot slot.
+    Snapshot(SnapshotSlot),
+    /// Report the change between the snapshots.
+    Diff,
+    /// Run the hardware self-test suite.
+    SelfTest,
+    /// Report the event counters, zeroing them if `true`.
+    Stats(bool),
+    /// Print the runtime configuration as a hex blob.
+    Dump,
+    /// Apply a decoded configuration blob.
+    Restore(FullConfig),
+    /// Store the current settings as the power-on values.
+    Save,
+    /// Enable (`true`) or disable camera-sync frame starts.
+    #[cfg(feature = "camsync")]
+    Sync(bool),
+    /// Set the effect layered over the color, or just report it.
+    #[cfg(feature = "idle")]
+    EffectLayer(Option<Effect>),
+    /// Mute (`true`) or unmute tone feedback.
+    #[cfg(feature = "tone")]
+    Mute(bool),
+    /// Enter (`true`) or leave temperature mode.
+    #[cfg(feature = "temperature")]
+    TempMode(bool),
+    /// Set the high (`true`) or low gradient threshold in °C.
+    #[cfg(feature = "temperature")]
+    TempThreshold(bool, i32),
+    /// Enter (`true`) or leave compass hue mode.
+    #[cfg(feature = "compass")]
+    CompassMode(bool),
+    /// Start a hard-iron calibration capture.
+    #[cfg(feature = "compass")]
+    CompassCalibrate,
+    /// Enter (`true`) or leave tilt color mode.
+    #[cfg(feature = "motion")]
+    TiltMode(bool),
+    /// Set the sleep timer to go off after a delay, or cancel it with `None`.
+    #[cfg(feature = "sleep")]
+    Sleep(Option<Duration>),
+    /// Enable (`true`) or disable the brightness schedule.
+    #[cfg(feature = "schedule")]
+    ScheduleMode(bool),
+    /// Set the schedule cycle length in seconds (nonzero).
+    #[cfg(feature = "schedule")]
+    SchedulePeriod(u32),
+    /// Set the schedule curve shape.
+    #[cfg(feature = "schedule")]
+    ScheduleCurve(ScheduleCurve),
+    /// Set the DMX address of the fixture's first channel.
+    #[cfg(feature = "dmx")]
+    DmxAddress(u16),
+    /// Set the radio role.
+    #[cfg(feature = "radio")]
+    RadioRole(RadioRole),
+    /// Set the radio group (0 to [`RADIO_GROUP_MAX`]).
+    #[cfg(feature = "radio")]
+    RadioGroup(u8),
+    /// Set the matrix brightness step, or just report it.
+    #[cfg(feature = "radio")]
+    MatrixBrightness(Option<u8>),
+    /// Start (`true`) or stop telemetry, optionally at a new rate in Hz.
+    #[cfg(feature = "telemetry")]
+    Telemetry(bool, Option<u32>),
+    /// Print the calibration change log.
+    #[cfg(feature = "changelog")]
+    LogDump,
+    /// Start (`true`) or stop recording the UI's inputs.
+    #[cfg(feature = "record")]
+    Record(bool),
+    /// Print the recorded input trace.
+    #[cfg(feature = "record")]
+    RecordDump,
+    /// Start (`true`) or stop the reference-color test pattern.
+    #[cfg(feature = "testpattern")]
+    PatternMode(bool),
+    /// Set the test pattern dwell per color in seconds (nonzero).
+    #[cfg(feature = "testpattern")]
+    PatternDwell(u32),
+    /// Step the selected parameter up (positive) or down by whole steps.
+    #[cfg(feature = "ir")]
+    Adjust(i32),
+    /// Load preset RGB levels.
+    #[cfg(feature = "ir")]
+    Preset([u32; 3]),
+    /// Restore the levels saved by the last `Power(false)` (`true`), or
+    /// save them and blank the LED.
+    #[cfg(feature = "ir")]
+    Power(bool),
+    /// Inject a fault for robustness testing.
+    #[cfg(all(feature = "fault-inject", debug_assertions))]
+    Fault(Fault),
+}
+
+/// Reasons a console line could not be parsed.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CommandError {
+    /// The line was empty.
+    Empty,
+    /// The first word is not a known command.
+    UnknownCommand,
+    /// The command's arguments are missing or malformed.
+    BadArgument,
+    /// The `RESTORE` blob was rejected.
+    BadConfig(SettingsError),
+}
+
+/// Commands forwarded to the UI task, which owns the state they change.
+pub static UI_COMMANDS: Channel<CriticalSectionRawMutex, Command, 4> = Channel::new();
+
+/// Parses a parameter name as used in console commands.
+///
+/// # Arguments
+/// * `word` - `R`, `G`, `B`, `FPS`, or `SAT` (case-insensitive)
+fn parse_parameter(word: &str) -> Result<ControlParameter, CommandError> {
+    let parameters = [
+        ("R", ControlParameter::Red),
+        ("G", ControlParameter::Green),
+        ("B", ControlParameter::Blue),
+        ("FPS", ControlParameter::FrameRate),
+        ("SAT", ControlParameter::Saturation),
+    ];
+    parameters
+        .iter()
+        .find(|(name, _)| word.eq_ignore_ascii_case(name))
+        .map(|&(_, parameter)| parameter)
+        .ok_or(CommandError::BadArgument)
+}
+
+/// Parses an `ON`/`OFF` argument (case-insensitive).
+fn parse_on_off(word: &str) -> Result<bool, CommandError> {
+    if word.eq_ignore_ascii_case("ON") {
+        Ok(true)
+    } else if word.eq_ignore_ascii_case("OFF") {
+        Ok(false)
+    } else {
+        Err(CommandError::BadArgument)
+    }
+}
+
+/// Parses a `GAMMA` argument: an exponent from [`GAMMA_EXPONENT_MIN`] to
+/// [`GAMMA_EXPONENT_MAX`], or `OFF` (case-insensitive) for `None`.
+fn parse_gamma(word: &str) -> Result<Option<f32>, CommandError> {
+    if word.eq_ignore_ascii_case("OFF") {
+        return Ok(None);
+    }
+    match word.parse::<f32>() {
+        Ok(exponent) if (GAMMA_EXPONENT_MIN..=GAMMA_EXPONENT_MAX).contains(&exponent) => {
+            Ok(Some(exponent))
+        }
+        _ => Err(CommandError::BadArgument),
+    }
+}
+
+/// Parses three level arguments, red first, or one `#RRGGBB` color (see
+/// [`parse_color_hex`]).
+///
+/// All three must be present and below [`LEVELS`]; otherwise none are
+/// used.
+///
+/// # Arguments
+/// * `words` - Red, green, and blue level arguments
+fn parse_levels(words: &[&str]) -> Result<[u32; 3], CommandError> {
+    if let [color] = words {
+        return parse_color_hex(color).map_err(|_| CommandError::BadArgument);
+    }
+    let [red, green, blue] = words else {
+        return Err(CommandError::BadArgument);
+    };
+    let mut levels = [0; 3];
+    for (level, value) in levels.iter_mut().zip([red, green, blue]) {
+        *level = match value.parse() {
+            Ok(level) if level < LEVELS => level,
+            _ => return Err(CommandError::BadArgument),
+        };
+    }
+    Ok(levels)
+}
+
+/// Parses one console line into a command.
+///
+/// # Arguments
+/// * `line` - Command text without the line terminator
+pub fn parse_command(line: &str) -> Result<Command, CommandError> {
+    let mut words = line.split_ascii_whitespace();
+    let command = words.next().ok_or(CommandError::Empty)?;
+    let mut args = [""; CONSOLE_ARGS_MAX];
+    let mut count = 0;
+    for word in words {
+        *args.get_mut(count).ok_or(CommandError::BadArgument)? = word;
+        count += 1;
+    }
+    let args = &args[..count];
+
+    if command.eq_ignore_ascii_case("FOCUS") {
+        return match args {
+            [] => Ok(Command::Focus(None)),
+            [word] if word.eq_ignore_ascii_case("OFF") => Ok(Command::Focus(None)),
+            [word] => Ok(Command::Focus(Some(parse_parameter(word)?))),
+            _ => Err(CommandError::BadArgument),
+        };
+    }
+    if command.eq_ignore_ascii_case("RGB") {
+        return parse_levels(args).map(Command::Rgb);
+    }
+    if command.eq_ignore_ascii_case("RESET") {
+        return match args {
+            [] => Ok(Command::Reset(ResetTarget::All)),
+            [word] if word.eq_ignore_ascii_case("FPS") => {
+                Ok(Command::Reset(ResetTarget::FrameRate))
+            }
+            [word] if word.eq_ignore_ascii_case("RGB") => Ok(Command::Reset(ResetTarget::Levels)),
+            _ => Err(CommandError::BadArgument),
+        };
+    }
+    if command.eq_ignore_ascii_case("TIMING") {
+        return match args {
+            [] => Ok(Command::Timing),
+            [word, flag] if word.eq_ignore_ascii_case("EXPLAIN") => {
+                Ok(Command::ExplainTiming(parse_on_off(flag)?))
+            }
+            _ => Err(CommandError::BadArgument),
+        };
+    }
+    if command.eq_ignore_ascii_case("VERBOSITY") {
+        return match args {
+            [word] if word.eq_ignore_ascii_case("INFO") => Ok(Command::Verbosity(Verbosity::Info)),
+            [word] if word.eq_ignore_ascii_case("DEBUG") => {
+                Ok(Command::Verbosity(Verbosity::Debug))
+            }
+            _ => Err(CommandError::BadArgument),
+        };
+    }
+    if command.eq_ignore_ascii_case("OUTPUT") {
+        return match args {
+            [] => Ok(Command::Output(None)),
+            [flag] => Ok(Command::Output(Some(parse_on_off(flag)?))),
+            _ => Err(CommandError::BadArgument),
+        };
+    }
+    if command.eq_ignore_ascii_case("GAMMA") {
+        return match args {
+            [word] => Ok(Command::Gamma(parse_gamma(word)?)),
+            _ => Err(CommandError::BadArgument),
+        };
+    }
+    #[cfg(not(feature = "hwpwm"))]
+    if command.eq_ignore_ascii_case("DITHER") {
+        return match args {
+            [word] if word.eq_ignore_ascii_case("OFF") => Ok(Command::Dither(DitherMode::Off)),
+            [word] if word.eq_ignore_ascii_case("ORDERED") => {
+                Ok(Command::Dither(DitherMode::Ordered))
+            }
+            [word] if word.eq_ignore_ascii_case("DIFFUSION") => {
+                Ok(Command::Dither(DitherMode::ErrorDiffusion))
+            }
+            _ => Err(CommandError::BadArgument),
+        };
+    }
+    #[cfg(feature = "hwpwm")]
+    if command.eq_ignore_ascii_case("PWM") {
+        return match args {
+            [name, flag] => {
+                let channel = parse_parameter(name)?
+                    .channel()
+                    .ok_or(CommandError::BadArgument)?;
+                Ok(Command::PwmChannel(channel, parse_on_off(flag)?))
+            }
+            _ => Err(CommandError::BadArgument),
+        };
+    }
+    #[cfg(feature = "camsync")]
+    if command.eq_ignore_ascii_case("SYNC") {
+        return match args {
+            [flag] => Ok(Command::Sync(parse_on_off(flag)?)),
+            _ => Err(CommandError::BadArgument),
+        };
+    }
+    #[cfg(feature = "bh1750")]
+    if command.eq_ignore_ascii_case("LUX") {
+        return match args {
+            [] => Ok(Command::Lux),
+            [flag] => Ok(Command::LuxEnabled(parse_on_off(flag)?)),
+            [word, value]
+                if word.eq_ignore_ascii_case("MIN") || word.eq_ignore_ascii_case("MAX") =>
+            {
+                match value.parse() {
+                    Ok(percent) if percent <= BRIGHTNESS_MAX => {
+                        Ok(Command::LuxScale(word.eq_ignore_ascii_case("MAX"), percent))
+                    }
+                    _ => Err(CommandError::BadArgument),
+                }
+            }
+            [word, value] if word.eq_ignore_ascii_case("TAU") => match value.parse() {
+                Ok(seconds) if seconds <= LUX_TAU_MAX => Ok(Command::LuxTau(seconds)),
+                _ => Err(CommandError::BadArgument),
+            },
+            _ => Err(CommandError::BadArgument),
+        };
+    }
+    #[cfg(feature = "bargraph")]
+    if command.eq_ignore_ascii_case("BARS") {
+        return match args {
+            [word] if word.eq_ignore_ascii_case("BAR") => Ok(Command::BarStyle(BarStyle::Bar)),
+            [word] if word.eq_ignore_ascii_case("DOT") => Ok(Command::BarStyle(BarStyle::Dot)),
+            _ => Err(CommandError::BadArgument),
+        };
+    }
+    if command.eq_ignore_ascii_case("DEMO") {
+        return match args {
+            [flag] => Ok(Command::FpsDemo(parse_on_off(flag)?)),
+            [word, levels @ ..] if word.eq_ignore_ascii_case("COLOR") => {
+                parse_levels(levels).map(Command::FpsDemoColor)
+            }
+            _ => Err(CommandError::BadArgument),
+        };
+    }
+    if command.eq_ignore_ascii_case("KNOB") {
+        return match args {
+            [word] if word.eq_ignore_ascii_case("STATS") => {
+                Ok(Command::KnobStats(KNOB_STATS_DEFAULT))
+            }
+            [word, value] if word.eq_ignore_ascii_case("STATS") => match value.parse() {
+                Ok(count) if (1..=KNOB_STATS_MAX).contains(&count) => Ok(Command::KnobStats(count)),
+                _ => Err(CommandError::BadArgument),
+            },
+            [word] if word.eq_ignore_ascii_case("CAL") => Ok(Command::KnobCalibrate),
+            _ => Err(CommandError::BadArgument),
+        };
+    }
+    if command.eq_ignore_ascii_case("LATENCY") {
+        return match args {
+            [] => Ok(Command::Latency),
+            _ => Err(CommandError::BadArgument),
+        };
+    }
+    if command.eq_ignore_ascii_case("RANGES") {
+        return match args {
+            [] => Ok(Command::Ranges),
+            _ => Err(CommandError::BadArgument),
+        };
+    }
+    if command.eq_ignore_ascii_case("SNAP") {
+        return match args {
+            [word] if word.eq_ignore_ascii_case("A") => Ok(Command::Snapshot(SnapshotSlot::A)),
+            [word] if word.eq_ignore_ascii_case("B") => Ok(Command::Snapshot(SnapshotSlot::B)),
+            _ => Err(CommandError::BadArgument),
+        };
+    }
+    if command.eq_ignore_ascii_case("DIFF") {
+        return match args {
+            [] => Ok(Command::Diff),
+            _ => Err(CommandError::BadArgument),
+        };
+    }
+    if command.eq_ignore_ascii_case("SELFTEST") {
+        return match args {
+            [] => Ok(Command::SelfTest),
+            _ => Err(CommandError::BadArgument),
+        };
+    }
+    if command.eq_ignore_ascii_case("STATS") {
+        return match args {
+            [] => Ok(Command::Stats(false)),
+            [word] if word.eq_ignore_ascii_case("RESET") => Ok(Command::Stats(true)),
+            _ => Err(CommandError::BadArgument),
+        };
+    }
+    if command.eq_ignore_ascii_case("DUMP") {
+        return match args {
+            [] => Ok(Command::Dump),
+            _ => Err(CommandError::BadArgument),
+        };
+    }
+    if command.eq_ignore_ascii_case("RESTORE") {
+        let [blob] = args else {
+            return Err(CommandError::BadArgument);
+        };
+        return parse_hex_blob(blob)
+            .and_then(|(buffer, len)| decode_full_config(&buffer[..len]))
+            .map(Command::Restore)
+            .map_err(CommandError::BadConfig);
+    }
+    if command.eq_ignore_ascii_case("SAVE") {
+        return match args {
+            [] => Ok(Command::Save),
+            _ => Err(CommandError::BadArgument),
+        };
+    }
+    #[cfg(feature = "idle")]
+    if command.eq_ignore_ascii_case("EFFECT") {
+        let effects = [
+            ("OFF", Effect::Off),
+            ("BREATHE", Effect::Breathe),
+            ("DRIFT", Effect::Drift),
+        ];
+        return match args {
+            [] => Ok(Command::EffectLayer(None)),
+            [word] => effects
+                .iter()
+                .find(|(name, _)| word.eq_ignore_ascii_case(name))
+                .map(|&(_, effect)| Command::EffectLayer(Some(effect)))
+                .ok_or(CommandError::BadArgument),
+            _ => Err(CommandError::BadArgument),
+        };
+    }
+    #[cfg(feature = "tone")]
+    if command.eq_ignore_ascii_case("MUTE") {
+        return match args {
+            [flag] => Ok(Command::Mute(parse_on_off(flag)?)),
+            _ => Err(CommandError::BadArgument),
+        };
+    }
+    #[cfg(feature = "temperature")]
+    if command.eq_ignore_ascii_case("TEMP") {
+        return match args {
+            [flag] => Ok(Command::TempMode(parse_on_off(flag)?)),
+            [word, value] => {
+                let high = if word.eq_ignore_ascii_case("HIGH") {
+                    true
+                } else if word.eq_ignore_ascii_case("LOW") {
+                    false
+                } else {
+                    return Err(CommandError::BadArgument);
+                };
+                let celsius = value.parse().map_err(|_| CommandError::BadArgument)?;
+                Ok(Command::TempThreshold(high, celsius))
+            }
+            _ => Err(CommandError::BadArgument),
+        };
+    }
+    #[cfg(feature = "compass")]
+    if command.eq_ignore_ascii_case("COMPASS") {
+        return match args {
+            [word] if word.eq_ignore_ascii_case("CAL") => Ok(Command::CompassCalibrate),
+            [flag] => Ok(Command::CompassMode(parse_on_off(flag)?)),
+            _ => Err(CommandError::BadArgument),
+        };
+    }
+    #[cfg(feature = "motion")]
+    if command.eq_ignore_ascii_case("TILT") {
+        return match args {
+            [flag] => Ok(Command::TiltMode(parse_on_off(flag)?)),
+            _ => Err(CommandError::BadArgument),
+        };
+    }
+    #[cfg(feature = "sleep")]
+    if command.eq_ignore_ascii_case("SLEEP") {
+        return match args {
+            [] => Ok(Command::Sleep(Some(SLEEP_CONFIG.delay))),
+            [word] if word.eq_ignore_ascii_case("OFF") => Ok(Command::Sleep(None)),
+            [value] => match value.parse() {
+                Ok(minutes) if (1..=SLEEP_MINUTES_MAX).contains(&minutes) => {
+                    Ok(Command::Sleep(Some(Duration::from_secs(minutes * 60))))
+                }
+                _ => Err(CommandError::BadArgument),
+            },
+            _ => Err(CommandError::BadArgument),
+        };
+    }
+    #[cfg(feature = "schedule")]
+    if command.eq_ignore_ascii_case("SCHEDULE") {
+        return match args {
+            [flag] => Ok(Command::ScheduleMode(parse_on_off(flag)?)),
+            [word, value] if word.eq_ignore_ascii_case("PERIOD") => match value.parse() {
+                Ok(seconds) if seconds > 0 => Ok(Command::SchedulePeriod(seconds)),
+                _ => Err(CommandError::BadArgument),
+            },
+            [word, shape] if word.eq_ignore_ascii_case("CURVE") => {
+                if shape.eq_ignore_ascii_case("COSINE") {
+                    Ok(Command::ScheduleCurve(ScheduleCurve::Cosine))
+                } else if shape.eq_ignore_ascii_case("STEP") {
+                    Ok(Command::ScheduleCurve(ScheduleCurve::Step))
+                } else {
+                    Err(CommandError::BadArgument)
+                }
+            }
+            _ => Err(CommandError::BadArgument),
+        };
+    }
+    #[cfg(feature = "dmx")]
+    if command.eq_ignore_ascii_case("DMX") {
+        return match args {
+            [word, value] if word.eq_ignore_ascii_case("ADDRESS") => {
+                let address = value.parse().map_err(|_| CommandError::BadArgument)?;
+                Ok(Command::DmxAddress(address))
+            }
+            _ => Err(CommandError::BadArgument),
+        };
+    }
+    #[cfg(feature = "radio")]
+    if command.eq_ignore_ascii_case("RADIO") {
+        let roles = [
+            ("TEACHER", RadioRole::Teacher),
+            ("STUDENT", RadioRole::Student),
+            ("OFF", RadioRole::Off),
+            #[cfg(feature = "remote")]
+            ("REMOTE", RadioRole::Remote),
+            #[cfg(feature = "remote")]
+            ("HOST", RadioRole::Host),
+        ];
+        return match args {
+            [word, value] if word.eq_ignore_ascii_case("GROUP") => {
+                let group = value.parse().map_err(|_| CommandError::BadArgument)?;
+                Ok(Command::RadioGroup(group))
+            }
+            [word] => roles
+                .iter()
+                .find(|(name, _)| word.eq_ignore_ascii_case(name))
+                .map(|&(_, role)| Command::RadioRole(role))
+                .ok_or(CommandError::BadArgument),
+            _ => Err(CommandError::BadArgument),
+        };
+    }
+    #[cfg(feature = "radio")]
+    if command.eq_ignore_ascii_case("MATRIX") {
+        return match args {
+            [] => Ok(Command::MatrixBrightness(None)),
+            [value] => match value.parse() {
+                Ok(brightness) if brightness <= MATRIX_BRIGHTNESS_MAX => {
+                    Ok(Command::MatrixBrightness(Some(brightness)))
+                }
+                _ => Err(CommandError::BadArgument),
+            },
+            _ => Err(CommandError::BadArgument),
+        };
+    }
+    #[cfg(feature = "telemetry")]
+    if command.eq_ignore_ascii_case("TELEMETRY") {
+        return match args {
+            [flag] => Ok(Command::Telemetry(parse_on_off(flag)?, None)),
+            [flag, rate] if flag.eq_ignore_ascii_case("ON") => {
+                let rate = rate.parse().map_err(|_| CommandError::BadArgument)?;
+                Ok(Command::Telemetry(true, Some(rate)))
+            }
+            _ => Err(CommandError::BadArgument),
+        };
+    }
+    #[cfg(feature = "changelog")]
+    if command.eq_ignore_ascii_case("LOG") {
+        return match args {
+            [word] if word.eq_ignore_ascii_case("DUMP") => Ok(Command::LogDump),
+            _ => Err(CommandError::BadArgument),
+        };
+    }
+    #[cfg(feature = "record")]
+    if command.eq_ignore_ascii_case("RECORD") {
+        return match args {
+            [word] if word.eq_ignore_ascii_case("DUMP") => Ok(Command::RecordDump),
+            [flag] => Ok(Command::Record(parse_on_off(flag)?)),
+            _ => Err(CommandError::BadArgument),
+        };
+    }
+    #[cfg(feature = "testpattern")]
+    if command.eq_ignore_ascii_case("PATTERN") {
+        return match args {
+            [flag] => Ok(Command::PatternMode(parse_on_off(flag)?)),
+            [word, value] if word.eq_ignore_ascii_case("DWELL") => match value.parse() {
+                Ok(seconds) if seconds > 0 => Ok(Command::PatternDwell(seconds)),
+                _ => Err(CommandError::BadArgument),
+            },
+            _ => Err(CommandError::BadArgument),
+        };
+    }
+    #[cfg(all(feature = "fault-inject", debug_assertions))]
+    if command.eq_ignore_ascii_case("FAULT") {
+        return match args {
+            [word, value] if word.eq_ignore_ascii_case("KNOB") => {
+                if value.eq_ignore_ascii_case("OFF") {
+                    return Ok(Command::Fault(Fault::Knob(None)));
+                }
+                let raw = value.parse().map_err(|_| CommandError::BadArgument)?;
+                Ok(Command::Fault(Fault::Knob(Some(raw))))
+            }
+            [word, value] if word.eq_ignore_ascii_case("FPS") => {
+                let frame_rate = value.parse().map_err(|_| CommandError::BadArgument)?;
+                Ok(Command::Fault(Fault::FrameRate(frame_rate)))
+            }
+            [word, name, value] if word.eq_ignore_ascii_case("LEVEL") => {
+                let channel = parse_parameter(name)?
+                    .channel()
+                    .ok_or(CommandError::BadArgument)?;
+                let level = value.parse().map_err(|_| CommandError::BadArgument)?;
+                Ok(Command::Fault(Fault::Level(channel, level)))
+            }
+            _ => Err(CommandError::BadArgument),
+        };
+    }
+    Err(CommandError::UnknownCommand)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn gamma_accepts_exponents_in_range_or_off() {
+        assert_eq!(parse_gamma("2.2"), Ok(Some(2.2)));
+        assert_eq!(parse_gamma("1"), Ok(Some(1.0)));
+        assert_eq!(parse_gamma("3.0"), Ok(Some(3.0)));
+        assert_eq!(parse_gamma("off"), Ok(None));
+        // Out of range, or not a number at all.
+        assert_eq!(parse_gamma("0.9"), Err(CommandError::BadArgument));
+        assert_eq!(parse_gamma("3.01"), Err(CommandError::BadArgument));
+        assert_eq!(parse_gamma("-2"), Err(CommandError::BadArgument));
+        assert_eq!(parse_gamma("NaN"), Err(CommandError::BadArgument));
+        assert_eq!(parse_gamma("inf"), Err(CommandError::BadArgument));
+        assert_eq!(parse_gamma("2,2"), Err(CommandError::BadArgument));
+    }
+
+    #[test]
+    fn levels_take_three_numbers_or_a_hex_color() {
+        assert_eq!(parse_levels(&["15", "0", "7"]), Ok([15, 0, 7]));
+        assert_eq!(parse_levels(&["15", "0"]), Err(CommandError::BadArgument));
+        assert_eq!(
+            parse_levels(&["15", "16", "7"]),
+            Err(CommandError::BadArgument)
+        );
+        assert_eq!(
+            parse_levels(&["15", "-1", "7"]),
+            Err(CommandError::BadArgument)
+        );
+        assert_eq!(parse_levels(&["#FF8000"]), Ok([15, 8, 0]));
+        assert_eq!(parse_levels(&["#FF80"]), Err(CommandError::BadArgument));
+    }
+
+    #[test]
+    fn commands_parse_with_their_arguments() {
+        assert_eq!(
+            parse_command("focus r"),
+            Ok(Command::Focus(Some(ControlParameter::Red))),
+        );
+        assert_eq!(parse_command("rgb 15 8 0"), Ok(Command::Rgb([15, 8, 0])));
+        assert_eq!(parse_command("rgb 15 8"), Err(CommandError::BadArgument));
+        assert_eq!(parse_command("rgb 15 8 16"), Err(CommandError::BadArgument));
+        assert_eq!(parse_command("reset"), Ok(Command::Reset(ResetTarget::All)));
+        assert_eq!(
+            parse_command("RESET fps"),
+            Ok(Command::Reset(ResetTarget::FrameRate))
+        );
+        assert_eq!(
+            parse_command("reset RGB"),
+            Ok(Command::Reset(ResetTarget::Levels))
+        );
+        assert_eq!(parse_command("reset blue"), Err(CommandError::BadArgument));
+        assert_eq!(parse_command("save"), Ok(Command::Save));
+        assert_eq!(parse_command("SAVE now"), Err(CommandError::BadArgument));
+
+        // RESTORE takes back what DUMP printed, and says why it won't.
+        let config = FullConfig::from_settings(&DEFAULTS);
+        let mut line = [0u8; CONSOLE_LINE_MAX];
+        line[..8].copy_from_slice(b"restore ");
+        let blob = encode_full_config(&config);
+        for (i, byte) in blob.iter().enumerate() {
+            let digits = b"0123456789ABCDEF";
+            line[8 + 2 * i] = digits[(byte >> 4) as usize];
+            line[9 + 2 * i] = digits[(byte & 0xF) as usize];
+        }
+        let len = 8 + 2 * blob.len();
+        let restore = core::str::from_utf8(&line[..len]).unwrap();
+        assert_eq!(parse_command(restore), Ok(Command::Restore(config)));
+        line[len - 1] = if line[len - 1] == b'0' { b'1' } else { b'0' };
+        let corrupt = core::str::from_utf8(&line[..len]).unwrap();
+        assert_eq!(
+            parse_command(corrupt),
+            Err(CommandError::BadConfig(SettingsError::BadCrc)),
+        );
+        assert_eq!(
+            parse_command("restore 52X6"),
+            Err(CommandError::BadConfig(SettingsError::BadHex)),
+        );
+    }
+}
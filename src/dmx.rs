@@ -0,0 +1,117 @@
+//! # DMX512 Output Module
+//!
+//! This module sends the current color as a minimal DMX512 universe on a
+//! UART pin, so an RS-485 transceiver (such as a MAX485 module) can drive a
+//! stage fixture with the calibrated color.
+//!
+//! ## Packet
+//!
+//! ```text
+//! break (≥ 88 µs low) | mark after break (≥ 8 µs high) | start code 0x00 | slots 1..=N
+//! ```
+//!
+//! Slots are 250 kbaud, 8 data bits, no parity, two stop bits. Only the
+//! slots up to the fixture's last channel are sent: `N` is the start
+//! address plus two. Packets repeat at about 40 Hz, independent of
+//! [`FRAME_RATE`].
+//!
+//! ## Timing
+//!
+//! The nRF52 UARTE cannot send a break, so the TX pin is driven low as a
+//! GPIO for [`DMX_BREAK_US`], then high for [`DMX_MAB_US`], before the
+//! UARTE is started on the same pin for the slots. The line floats for a
+//! few microseconds during the hand-over; a pull-up on the transceiver's
+//! DI input keeps it marking.
+//!
+//! ## Channels
+//!
+//! The fixture's three channels start at a configurable DMX address
+//! (`DMX ADDRESS <n>` on the console) in [`DMX_ORDER`]. Each slot is the
+//! channel's level scaled to 0–255 and by the master brightness.
+use crate::*;
+
+/// Break length, in microseconds (DMX512 minimum 88).
+pub const DMX_BREAK_US: u64 = 176;
+/// Mark-after-break length, in microseconds (DMX512 minimum 8).
+pub const DMX_MAB_US: u64 = 16;
+/// Delay between packet starts, in milliseconds (about 40 Hz).
+pub const DMX_PERIOD_MS: u64 = 25;
+/// Highest DMX address in a universe.
+pub const DMX_ADDRESS_MAX: u16 = 512;
+/// Slots written per fixture.
+const DMX_FIXTURE_SLOTS: usize = 3;
+/// Largest packet: the start code plus a full universe.
+pub const DMX_PACKET_MAX: usize = 1 + DMX_ADDRESS_MAX as usize;
+/// Color channel (0 = red, 1 = green, 2 = blue) sent in each fixture slot.
+///
+/// Change this for fixtures with another channel order, e.g. `[1, 0, 2]`
+/// for GRB.
+pub const DMX_ORDER: [usize; 3] = [0, 1, 2];
+
+/// DMX address of the fixture's first channel, changed by the console.
+pub static DMX_ADDRESS: Mutex<CriticalSectionRawMutex, u16> = Mutex::new(1);
+
+/// Converts a level to an 8-bit DMX slot value.
+///
+/// # Arguments
+/// * `level` - Level from 0 to [`LEVELS`]-1
+/// * `brightness` - Master brightness from 0 to [`BRIGHTNESS_MAX`]
+pub fn dmx_slot(level: u32, brightness: u32) -> u8 {
+    let level = level.min(LEVELS - 1);
+    let brightness = brightness.min(BRIGHTNESS_MAX);
+    (level * 255 * brightness / ((LEVELS - 1) * BRIGHTNESS_MAX)) as u8
+}
+
+/// Builds a DMX packet: the start code and every slot up to the fixture's
+/// last channel.
+///
+/// Slots before the fixture are sent as 0.
+///
+/// # Arguments
+/// * `levels` - RGB levels
+/// * `brightness` - Master brightness from 0 to [`BRIGHTNESS_MAX`]
+/// * `address` - DMX address of the first channel, 1 to
+///   [`DMX_ADDRESS_MAX`] - 2
+/// * `packet` - Buffer to fill
+///
+/// # Returns
+/// The number of bytes of `packet` to send.
+pub fn dmx_packet(
+    levels: [u32; 3],
+    brightness: u32,
+    address: u16,
+    packet: &mut [u8; DMX_PACKET_MAX],
+) -> usize {
+    let first = address.clamp(1, DMX_ADDRESS_MAX - DMX_FIXTURE_SLOTS as u16 + 1) as usize;
+    let len = first + DMX_FIXTURE_SLOTS;
+    packet[..len].fill(0);
+    for (slot, &channel) in packet[first..len].iter_mut().zip(DMX_ORDER.iter()) {
+        *slot = dmx_slot(levels[channel], brightness);
+    }
+    len
+}
+
+/// Sets a DMX start address.
+///
+/// # Returns
+/// `false` (leaving the address unchanged) if the fixture would not fit in
+/// the universe.
+pub async fn set_dmx_address(address: u16) -> bool {
+    if !(1..=DMX_ADDRESS_MAX - DMX_FIXTURE_SLOTS as u16 + 1).contains(&address) {
+        return false;
+    }
+    *DMX_ADDRESS.lock().await = address;
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn packet_starts_with_the_start_code() {
+        let mut packet = [0u8; DMX_PACKET_MAX];
+        let len = dmx_packet([15, 0, 0], BRIGHTNESS_MAX, 1, &mut packet);
+        assert_eq!(&packet[..len], &[0x00, 255, 0, 0]);
+    }
+}
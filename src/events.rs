@@ -0,0 +1,104 @@
+//! # State Event Bus Module
+//!
+//! This module provides a small publish/subscribe bus so tasks can react to
+//! state changes instead of polling the shared mutexes.
+//!
+//! ## Publishing
+//!
+//! The shared-state setters ([`set_rgb_levels`], [`set_frame_rate`],
+//! [`set_output_enabled`], [`set_master_brightness`])
+//! publish a [`StateChanged`] event whenever the stored value actually
+//! changes, so every writer (UI, console, protocol, demo modes) is covered
+//! without knowing who listens.
+//!
+//! ## Subscribing
+//!
+//! [`subscribe_state`] hands out one of [`STATE_SUBSCRIBERS_MAX`]
+//! subscriptions. Events are published without waiting: a subscriber that
+//! falls more than [`STATE_EVENTS_DEPTH`] events behind loses the oldest
+//! ones and sees a lag notice, after which it should re-read the state
+//! directly.
+use crate::*;
+use embassy_sync::pubsub::{PubSubChannel, Subscriber};
+
+/// Events buffered per subscriber before the oldest are dropped.
+pub const STATE_EVENTS_DEPTH: usize = 8;
+/// Maximum number of subscribers.
+pub const STATE_SUBSCRIBERS_MAX: usize = 4;
+
+/// A change to shared state.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StateChanged {
+    /// The RGB levels changed to the given values.
+    Levels([u32; 3]),
+    /// The frame rate changed to the given value in FPS.
+    FrameRate(u64),
+    /// The LED output was enabled (`true`) or blanked.
+    OutputEnabled(bool),
+    /// The master brightness changed to the given value, 0 to
+    /// [`BRIGHTNESS_MAX`].
+    Brightness(u32),
+}
+
+/// Subscription handle returned by [`subscribe_state`].
+pub type StateSubscriber = Subscriber<
+    'static,
+    CriticalSectionRawMutex,
+    StateChanged,
+    STATE_EVENTS_DEPTH,
+    STATE_SUBSCRIBERS_MAX,
+    0,
+>;
+
+/// The state-change bus.
+static STATE_EVENTS: PubSubChannel<
+    CriticalSectionRawMutex,
+    StateChanged,
+    STATE_EVENTS_DEPTH,
+    STATE_SUBSCRIBERS_MAX,
+    0,
+> = PubSubChannel::new();
+
+/// Publishes a state change to every subscriber without waiting.
+pub fn publish_state(event: StateChanged) {
+    STATE_EVENTS.immediate_publisher().publish_immediate(event);
+}
+
+/// Subscribes to state changes.
+///
+/// # Panics
+/// If more than [`STATE_SUBSCRIBERS_MAX`] subscriptions are taken; each
+/// subscribing task takes one at startup.
+pub fn subscribe_state() -> StateSubscriber {
+    STATE_EVENTS
+        .subscriber()
+        .expect("too many state subscribers")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use embassy_sync::pubsub::WaitResult;
+
+    #[test]
+    fn subscribers_see_published_changes() {
+        let mut events = subscribe_state();
+        publish_state(StateChanged::FrameRate(75));
+        publish_state(StateChanged::Brightness(BRIGHTNESS_MAX / 4));
+        // Other tests publish through the shared setters too; skip theirs.
+        let mut seen = Vec::new();
+        while let Some(event) = events.try_next_message() {
+            if let WaitResult::Message(event) = event {
+                seen.push(event);
+            }
+        }
+        let ours = [
+            StateChanged::FrameRate(75),
+            StateChanged::Brightness(BRIGHTNESS_MAX / 4),
+        ];
+        let mut rest = seen.iter();
+        for event in ours {
+            assert!(rest.any(|&seen| seen == event), "{:?}", seen);
+        }
+    }
+}
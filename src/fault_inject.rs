@@ -0,0 +1,82 @@
+//! # Fault Injection Module
+//!
+//! This module deliberately feeds bad inputs into the system so the
+//! robustness guards can be exercised on hardware, logging how each one
+//! responds. It only exists in debug builds with the `fault-inject`
+//! feature; release builds compile it out entirely.
+//!
+//! ## Faults
+//!
+//! - **Knob**: Replaces the raw SAADC knob reading with a fixed value, such
+//!   as a negative reading or one beyond full scale, until cleared. The UI
+//!   keeps running on the injected value.
+//! - **Frame rate**: Writes an arbitrary frame rate (including 0) through
+//!   the normal setter.
+//! - **Level**: Writes an arbitrary level to one color channel through the
+//!   normal setter.
+//!
+//! ## Guards Exercised
+//!
+//! - Knob readings are clamped into the travel by [`knob_position`] and
+//!   [`KnobResponse::level`].
+//! - Stored levels and frame rates are clamped by their shared-state
+//!   setters, so the scanout never sees an out-of-range value (a zero frame
+//!   rate would divide by zero, a level above the top would underflow the
+//!   off time).
+use crate::*;
+
+/// A fault to inject.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Fault {
+    /// Replace knob readings with a raw SAADC value, or stop with `None`.
+    Knob(Option<i16>),
+    /// Write a frame rate in FPS.
+    FrameRate(u64),
+    /// Write a level to a color channel (0 = red, 1 = green, 2 = blue).
+    Level(usize, u32),
+}
+
+/// Raw knob reading currently injected, if any.
+static KNOB_FAULT: Mutex<CriticalSectionRawMutex, Option<i16>> = Mutex::new(None);
+
+/// Returns the injected raw knob reading, if a knob fault is active.
+pub async fn injected_knob_raw() -> Option<i16> {
+    *KNOB_FAULT.lock().await
+}
+
+/// Injects a fault and logs the system's response.
+pub async fn inject_fault(fault: Fault) {
+    match fault {
+        Fault::Knob(None) => {
+            *KNOB_FAULT.lock().await = None;
+            rprintln!("fault: knob readings restored");
+        }
+        Fault::Knob(Some(raw)) => {
+            *KNOB_FAULT.lock().await = Some(raw);
+            let position = knob_position(raw);
+            rprintln!(
+                "fault: knob raw {} -> position {:.3} -> level {}",
+                raw,
+                position,
+                KnobResponse::DEFAULT.level(position)
+            );
+        }
+        Fault::FrameRate(frame_rate) => {
+            set_frame_rate(StateSource::Fault, |fps| *fps = frame_rate).await;
+            rprintln!(
+                "fault: frame rate {} -> stored {} fps",
+                frame_rate,
+                get_frame_rate().await
+            );
+        }
+        Fault::Level(channel, level) => {
+            set_rgb_levels_from(StateSource::Fault, |rgb| rgb[channel] = level).await;
+            rprintln!(
+                "fault: level {} on channel {} -> stored {}",
+                level,
+                channel,
+                get_rgb_levels().await[channel]
+            );
+        }
+    }
+}
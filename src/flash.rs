@@ -0,0 +1,106 @@
+//! # Flash Module
+//!
+//! This module gives word-level access to the regions of internal flash
+//! reserved for data, shared by the [`storage`](crate::storage) backend
+//! and the change log (`changelog` feature).
+//!
+//! ## Layout
+//!
+//! The last three 4 KiB pages are excluded from the program image in
+//! `memory.x`:
+//!
+//! ```text
+//! 0x7D000  settings page   (STORAGE_FLASH_BASE, 1 page)
+//! 0x7E000  change log      (LOG_FLASH_BASE, 2 pages)
+//! 0x80000  end of flash
+//! ```
+//!
+//! The firmware loads the settings page at boot, writes it on the
+//! console's `SAVE` (see the [`settings`](crate::settings) module), and
+//! reports its wear with `STATS`. The change log region is used only with
+//! the `changelog` feature.
+//!
+//! ## Sharing the NVMC
+//!
+//! Every operation programs the NVMC synchronously and leaves it read-only,
+//! and all callers run on the thread-mode executor, so operations never
+//! interleave and several regions can share the controller.
+
+/// Flash page size in bytes.
+pub const FLASH_PAGE_SIZE: u32 = 4096;
+/// Flash address of the settings page used by [`FlashStorage`](crate::FlashStorage).
+pub const STORAGE_FLASH_BASE: u32 = 0x0007_D000;
+/// Flash address of the change log region.
+pub const LOG_FLASH_BASE: u32 = 0x0007_E000;
+/// End of the nRF52833's 512 KiB of flash.
+const FLASH_END: u32 = 0x0008_0000;
+
+// The regions tile the 12 KiB that memory.x leaves out of the program.
+const _: () = assert!(FLASH_END - STORAGE_FLASH_BASE == 12 * 1024);
+const _: () = assert!(STORAGE_FLASH_BASE + FLASH_PAGE_SIZE == LOG_FLASH_BASE);
+const _: () = assert!(LOG_FLASH_BASE + 2 * FLASH_PAGE_SIZE == FLASH_END);
+
+/// Word-programmable flash region.
+///
+/// Offsets are relative to the start of the region. As with NOR flash,
+/// programming can only clear bits, and erasing sets a whole page to `0xFF`.
+pub trait FlashRegion {
+    /// Reads bytes starting at `offset`.
+    fn read(&self, offset: u32, buf: &mut [u8]);
+    /// Programs one word at a word-aligned `offset`.
+    fn write_word(&mut self, offset: u32, word: u32);
+    /// Erases one page of the region.
+    fn erase_page(&mut self, page: u32);
+}
+
+/// Pages of flash in RAM for tests: programming only clears bits, and the
+/// power can be made to fail partway through a write.
+#[cfg(test)]
+pub(crate) struct RamFlash<const PAGES: usize> {
+    pages: [[u8; FLASH_PAGE_SIZE as usize]; PAGES],
+    /// Word writes left before the power fails.
+    pub(crate) writes_left: u32,
+}
+
+#[cfg(test)]
+impl<const PAGES: usize> RamFlash<PAGES> {
+    /// Erased pages with the power on.
+    pub(crate) fn new() -> Self {
+        Self {
+            pages: [[0xFF; FLASH_PAGE_SIZE as usize]; PAGES],
+            writes_left: u32::MAX,
+        }
+    }
+
+    /// Splits a region offset into a page and an offset within it.
+    fn locate(offset: u32) -> (usize, usize) {
+        (
+            (offset / FLASH_PAGE_SIZE) as usize,
+            (offset % FLASH_PAGE_SIZE) as usize,
+        )
+    }
+}
+
+#[cfg(test)]
+impl<const PAGES: usize> FlashRegion for RamFlash<PAGES> {
+    fn read(&self, offset: u32, buf: &mut [u8]) {
+        let (page, start) = Self::locate(offset);
+        buf.copy_from_slice(&self.pages[page][start..start + buf.len()]);
+    }
+    fn write_word(&mut self, offset: u32, word: u32) {
+        if self.writes_left == 0 {
+            return;
+        }
+        self.writes_left -= 1;
+        let (page, start) = Self::locate(offset);
+        for (byte, bits) in self.pages[page][start..start + 4]
+            .iter_mut()
+            .zip(word.to_le_bytes())
+        {
+            *byte &= bits;
+        }
+    }
+    fn erase_page(&mut self, page: u32) {
+        self.pages[page as usize] = [0xFF; FLASH_PAGE_SIZE as usize];
+    }
+}
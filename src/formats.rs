@@ -0,0 +1,417 @@
+//! # Text Formats Module
+//!
+//! This module holds the console line formats that host scripts scrape,
+//! and pins each one byte for byte.
+//!
+//! ## Formats
+//!
+//! - Status block: [`StatusBlock`], printed after every change
+//! - Focus notice: [`FocusNotice`], when the knob is pinned or released
+//! - Delta: [`SnapshotDiff`], printed by `DIFF` (its golden lines are with it)
+//! - Heartbeat: [`StatsLine`], printed by `STATS` and [`stats_task`]
+//! - Configuration export: [`ConfigLine`], printed by `DUMP`
+//! - Clamped fields: [`AdjustedNotice`], printed by `RESTORE`
+//!
+//! ## Golden Outputs
+//!
+//! Each format's golden test lists the exact lines it prints for a matrix of
+//! representative states (all zero, all at the top, mixed, a three-digit
+//! frame rate, clamped fields, a locked focus). [`check_golden`] formats
+//! the lines through [`LogLine`], the same formatter
+//! [`rprintln!`](crate::rprintln) uses, newlines included, so the tests
+//! check exactly the bytes the firmware writes. A deliberate format change
+//! updates its golden lines in the same change; an accidental one fails
+//! with a [`GoldenDiff`] naming the first line that differs.
+use crate::*;
+use core::fmt::Display;
+
+/// First line where formatted output differs from its golden copy.
+pub struct GoldenDiff<'a> {
+    /// Line number, from 1.
+    pub line: usize,
+    /// The golden line, or `None` if the output has extra lines.
+    pub expected: Option<&'a str>,
+    /// The line printed, or `None` if the output ended early.
+    pub actual: Option<LogLine>,
+}
+
+impl core::fmt::Debug for GoldenDiff<'_> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(
+            f,
+            "line {}: expected {:?}, got {:?}",
+            self.line,
+            self.expected,
+            self.actual.as_ref().map(LogLine::as_str)
+        )
+    }
+}
+
+/// Compares formatted lines against their golden copies.
+///
+/// # Arguments
+/// * `lines` - Values printed one per [`rprintln!`](crate::rprintln)
+/// * `golden` - Expected console lines, newlines included
+///
+/// # Returns
+/// The first difference, if any.
+// Tests `unwrap` the result to print the difference, which matters more
+// there than its size.
+#[allow(clippy::result_large_err)]
+pub fn check_golden<'a, T: Display>(
+    lines: impl IntoIterator<Item = T>,
+    golden: &[&'a str],
+) -> Result<(), GoldenDiff<'a>> {
+    let mut lines = lines.into_iter();
+    let mut n = 0;
+    loop {
+        let actual = lines
+            .next()
+            .map(|line| LogLine::format(format_args!("{}", line)));
+        let expected = golden.get(n).copied();
+        n += 1;
+        match (&actual, expected) {
+            (None, None) => return Ok(()),
+            (Some(line), Some(expected)) if line.as_str() == expected => {}
+            _ => {
+                return Err(GoldenDiff {
+                    line: n,
+                    expected,
+                    actual,
+                })
+            }
+        }
+    }
+}
+
+/// The status readout printed after every change.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct StatusBlock {
+    /// RGB levels.
+    pub levels: [u32; 3],
+    /// Frame rate in FPS.
+    pub frame_rate: u64,
+    /// Whether to include the PWM timing line.
+    pub explain_timing: bool,
+    /// Whether the LED output is enabled.
+    pub output_enabled: bool,
+    /// Master brightness, 0 to [`BRIGHTNESS_MAX`].
+    pub brightness: u32,
+    /// Gamma curve, or `None` for linear levels.
+    pub gamma: Option<Gamma>,
+}
+
+/// One line of a [`StatusBlock`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StatusLine {
+    /// Blank separator before the block.
+    Blank,
+    /// A channel name and its level.
+    Level(&'static str, u32),
+    /// The frame rate.
+    FrameRate(u64),
+    /// The frame rate's PWM timing.
+    Timing(RgbTiming),
+    /// Estimated LED current in milliamps.
+    Current(f32),
+    /// Whether the output is enabled.
+    Output(bool),
+}
+
+impl StatusBlock {
+    /// The block's lines, in print order.
+    ///
+    /// The current is estimated from what the frame loop would drive (see
+    /// [`estimate_current_ma`]): nothing while the output is off.
+    pub fn lines(&self) -> impl Iterator<Item = StatusLine> {
+        let names = ["red", "green", "blue"];
+        let inputs = FrameInputs {
+            levels: self.levels,
+            brightness: if self.output_enabled {
+                self.brightness
+            } else {
+                0
+            },
+            frame_rate: self.frame_rate,
+            gamma: self.gamma,
+        };
+        [
+            Some(StatusLine::Blank),
+            Some(StatusLine::Level(names[0], self.levels[0])),
+            Some(StatusLine::Level(names[1], self.levels[1])),
+            Some(StatusLine::Level(names[2], self.levels[2])),
+            Some(StatusLine::FrameRate(self.frame_rate)),
+            self.explain_timing
+                .then(|| StatusLine::Timing(RgbTiming::for_frame_rate(self.frame_rate))),
+            Some(StatusLine::Current(estimate_current_ma(
+                inputs,
+                BRIGHTNESS_CEILING,
+            ))),
+            Some(StatusLine::Output(self.output_enabled)),
+        ]
+        .into_iter()
+        .flatten()
+    }
+}
+
+impl Display for StatusLine {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            StatusLine::Blank => Ok(()),
+            StatusLine::Level(name, level) => write!(f, "{}: {}", name, level),
+            StatusLine::FrameRate(frame_rate) => write!(f, "frame rate: {}", frame_rate),
+            StatusLine::Timing(timing) => write!(f, "timing: {}", timing),
+            StatusLine::Current(ma) => write!(f, "est. current: {:.1} mA", ma),
+            StatusLine::Output(enabled) => {
+                write!(f, "output: {}", if *enabled { "on" } else { "off" })
+            }
+        }
+    }
+}
+
+/// The notice printed when the knob's focus changes.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FocusNotice(pub Option<ControlParameter>);
+
+impl Display for FocusNotice {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self.0 {
+            Some(parameter) => write!(f, "Focus locked: {:?}", parameter),
+            None => write!(f, "Focus cleared"),
+        }
+    }
+}
+
+/// The heartbeat line of event counters.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct StatsLine(pub StatsSnapshot);
+
+impl Display for StatsLine {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "stats: {}", self.0)
+    }
+}
+
+/// The configuration export printed by `DUMP`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ConfigLine<'a>(pub &'a FullConfig);
+
+impl Display for ConfigLine<'_> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "config: {}", HexBlob(&encode_full_config(self.0)))
+    }
+}
+
+/// The clamped-field marker printed by `RESTORE`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AdjustedNotice(pub ConfigAdjusted);
+
+impl Display for AdjustedNotice {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "restore: adjusted {}", self.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn check_golden_reports_the_first_difference() {
+        assert!(check_golden([7, 8], &["7\n", "8\n"]).is_ok());
+        let diff = check_golden([7, 9], &["7\n", "8\n"]).unwrap_err();
+        assert_eq!(diff.line, 2);
+        assert_eq!(diff.actual.unwrap().as_str(), "9\n");
+        // Missing and extra lines count as differences too.
+        assert_eq!(check_golden([7], &["7\n", "8\n"]).unwrap_err().line, 2);
+        assert_eq!(check_golden([7, 8], &["7\n"]).unwrap_err().expected, None);
+    }
+
+    #[test]
+    fn status_block_golden() {
+        // All zero, at the slowest frame rate.
+        let zero = StatusBlock {
+            levels: [0; 3],
+            frame_rate: FRAME_RATE_MIN,
+            explain_timing: false,
+            output_enabled: true,
+            brightness: BRIGHTNESS_MAX,
+            gamma: None,
+        };
+        check_golden(
+            zero.lines(),
+            &[
+                "\n",
+                "red: 0\n",
+                "green: 0\n",
+                "blue: 0\n",
+                "frame rate: 10\n",
+                "est. current: 0.0 mA\n",
+                "output: on\n",
+            ],
+        )
+        .unwrap();
+
+        // All at the top, at a frame rate whose frame is a whole number of
+        // ticks at both tick rates (see below).
+        let max = StatusBlock {
+            levels: [LEVELS - 1; 3],
+            frame_rate: 64,
+            explain_timing: false,
+            output_enabled: true,
+            brightness: BRIGHTNESS_MAX,
+            gamma: None,
+        };
+        check_golden(
+            max.lines(),
+            &[
+                "\n",
+                "red: 15\n",
+                "green: 15\n",
+                "blue: 15\n",
+                "frame rate: 64\n",
+                // With `hwpwm` the three channels are lit at once.
+                if cfg!(feature = "hwpwm") {
+                    "est. current: 12.2 mA\n"
+                } else {
+                    "est. current: 4.1 mA\n"
+                },
+                "output: on\n",
+            ],
+        )
+        .unwrap();
+
+        // Mixed, with the timing line. 64 fps divides both the 1 MHz host
+        // tick rate and the firmware's 32768 Hz, so the lines are the same
+        // on either.
+        let mixed = StatusBlock {
+            levels: [10, 8, 12],
+            frame_rate: 64,
+            explain_timing: true,
+            output_enabled: true,
+            brightness: BRIGHTNESS_MAX,
+            gamma: None,
+        };
+        check_golden(mixed.lines(), &[
+            "\n",
+            "red: 10\n",
+            "green: 8\n",
+            "blue: 12\n",
+            "frame rate: 64\n",
+            "timing: 64 fps -> frame period 15625 us -> tick 325.5 us (period / 3 LEDs / 16 levels)\n",
+            if cfg!(feature = "hwpwm") {
+                "est. current: 8.1 mA\n"
+            } else {
+                "est. current: 2.7 mA\n"
+            },
+            "output: on\n",
+        ])
+        .unwrap();
+
+        // The same with the output blanked: the frame loop drives nothing.
+        let blanked = StatusBlock {
+            explain_timing: false,
+            output_enabled: false,
+            ..mixed
+        };
+        check_golden(
+            blanked.lines(),
+            &[
+                "\n",
+                "red: 10\n",
+                "green: 8\n",
+                "blue: 12\n",
+                "frame rate: 64\n",
+                "est. current: 0.0 mA\n",
+                "output: off\n",
+            ],
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn focus_notice_golden() {
+        check_golden(
+            [
+                FocusNotice(Some(ControlParameter::Red)),
+                FocusNotice(Some(ControlParameter::FrameRate)),
+                FocusNotice(None),
+            ],
+            &[
+                "Focus locked: Red\n",
+                "Focus locked: FrameRate\n",
+                "Focus cleared\n",
+            ],
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn stats_line_golden() {
+        static COUNTERS: Stats = Stats::new();
+        check_golden([StatsLine(COUNTERS.snapshot())], &[
+            "stats: levels=0 fps=0 knob=0 clamped=0 deduped=0 held=0 buttons=0 chord_aborts=0 log_dropped=0 adc_errors=0\n",
+        ])
+        .unwrap();
+
+        for _ in 0..120 {
+            COUNTERS.increment(Stat::KnobReadings);
+        }
+        COUNTERS.increment(Stat::ButtonTransitions);
+        COUNTERS.increment(Stat::AdcErrors);
+        check_golden([StatsLine(COUNTERS.snapshot())], &[
+            "stats: levels=0 fps=0 knob=120 clamped=0 deduped=0 held=0 buttons=1 chord_aborts=0 log_dropped=0 adc_errors=1\n",
+        ])
+        .unwrap();
+    }
+
+    #[test]
+    fn config_line_golden() {
+        // Power-on, all levels at the top.
+        let power_on = FullConfig::from_settings(&DEFAULTS);
+        check_golden(
+            [ConfigLine(&power_on)],
+            &["config: 524603130F0F0F64000F640100000007000000000808082697\n"],
+        )
+        .unwrap();
+
+        // Mixed, with the timing line and debug output on and a gamma curve.
+        let mixed = FullConfig {
+            levels: [3, 9, 15],
+            frame_rate: 60,
+            explain_timing: true,
+            verbosity: Verbosity::Debug,
+            gamma: Some(Gamma {
+                exponent: 2.2,
+                knee: 3,
+            }),
+            ..power_on
+        };
+        check_golden(
+            [ConfigLine(&mixed)],
+            &["config: 5246031303090F3C000F640101010007DC0003000808089FBB\n"],
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn adjusted_notice_golden() {
+        let config = FullConfig::from_settings(&DEFAULTS);
+        let (_, none) = config.validated();
+        let (_, clamped) = FullConfig {
+            levels: [3, LEVELS, 200],
+            frame_rate: 5,
+            brightness: 250,
+            ..config
+        }
+        .validated();
+        check_golden(
+            [AdjustedNotice(none), AdjustedNotice(clamped)],
+            &[
+                "restore: adjusted none\n",
+                "restore: adjusted green, blue, fps, brightness\n",
+            ],
+        )
+        .unwrap();
+    }
+}
@@ -0,0 +1,257 @@
+//! # Gamma Correction Module
+//!
+//! This module maps the [`LEVELS`] steps onto a perceptual brightness curve
+//! instead of the linear `level / LEVELS` duty, when [`GAMMA`] is set.
+//!
+//! ## Linear Knee
+//!
+//! A pure power curve `x^exponent` crushes the lowest levels: at 2.2, level
+//! 1 gets well under 1% of the slot, which an LED barely shows. Like the
+//! sRGB transfer function, [`Gamma`] runs linearly from 0 up to a knee
+//! level, so level 1 is always visibly lit, and follows an offset power
+//! curve above it:
+//!
+//! ```text
+//! x < k:   y = slope * x
+//! x >= k:  y = ((x + a) / (1 + a))^exponent,  a = k * (exponent - 1)
+//! ```
+//!
+//! where `x` is the level as a fraction of the top level. That choice of
+//! `a` makes the power curve's value and slope at the knee match the
+//! line's, so the curve has no step or corner at the join and the table
+//! steps grow smoothly across it.
+//!
+//! ## Scale
+//!
+//! [`Gamma::share`] gives a level's on-time as a share of its slot, in
+//! units of 1/[`GAMMA_SCALE`]. The top level keeps the linear curve's
+//! `(LEVELS - 1) / LEVELS`, so gamma only changes the levels in between.
+//!
+//! ## Live Adjustment
+//!
+//! [`GAMMA`] is only the power-on curve: the console's `GAMMA <exponent>`
+//! sets [`GAMMA_CURVE`], with the exponent limited to
+//! [`GAMMA_EXPONENT_MIN`] to [`GAMMA_EXPONENT_MAX`], and `GAMMA OFF` goes
+//! back to linear levels. The RGB loop picks the change up between frames,
+//! computes the new [`GammaTable`] and installs it (see
+//! [`install_gamma_table`]) before building the next frame's schedule, so
+//! no frame is ever played half on the old curve and half on the new.
+use crate::*;
+use core::cell::Cell;
+use embassy_sync::blocking_mutex::CriticalSectionMutex;
+
+/// Full-slot share in [`Gamma::share`] units.
+pub const GAMMA_SCALE: u32 = 1 << 16;
+/// Smallest exponent `GAMMA` accepts (linear above the knee).
+pub const GAMMA_EXPONENT_MIN: f32 = 1.0;
+/// Largest exponent `GAMMA` accepts.
+pub const GAMMA_EXPONENT_MAX: f32 = 3.0;
+/// Knee `GAMMA` uses when no curve is set yet; a set curve keeps its own.
+pub const GAMMA_LIVE_KNEE: u32 = 3;
+/// Share of the top level, the same as with linear levels.
+const GAMMA_TOP: u32 = GAMMA_SCALE / LEVELS * (LEVELS - 1);
+
+/// Gamma curve with a linear segment below a knee.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Gamma {
+    /// Power of the curve above the knee, 1.0 (linear) or more.
+    pub exponent: f32,
+    /// Level below which the curve is linear; 0 for a pure power curve.
+    pub knee: u32,
+}
+
+impl Gamma {
+    /// Evaluates the curve.
+    ///
+    /// # Arguments
+    /// * `x` - Input from 0.0 to 1.0, a level over [`LEVELS`]-1
+    ///
+    /// # Returns
+    /// Relative brightness from 0.0 to 1.0.
+    pub fn curve(&self, x: f32) -> f32 {
+        let x = x.clamp(0.0, 1.0);
+        let exponent = self.exponent.max(1.0);
+        let k = self.knee as f32 / (LEVELS - 1) as f32;
+        if k >= 1.0 {
+            return x;
+        }
+        let a = k * (exponent - 1.0);
+        let power = |x: f32| num_traits::Float::powf((x + a) / (1.0 + a), exponent);
+        if x < k {
+            x * power(k) / k
+        } else {
+            power(x)
+        }
+    }
+
+    /// On-time share of one level's slot.
+    ///
+    /// # Arguments
+    /// * `level` - Level from 0 to [`LEVELS`]-1
+    ///
+    /// # Returns
+    /// The share in units of 1/[`GAMMA_SCALE`] of a slot.
+    pub fn share(&self, level: u32) -> u32 {
+        let x = level.min(LEVELS - 1) as f32 / (LEVELS - 1) as f32;
+        (self.curve(x) * GAMMA_TOP as f32 + 0.5) as u32
+    }
+
+    /// Computes the share of every level.
+    ///
+    /// # Returns
+    /// [`Gamma::share`] for levels 0 to [`LEVELS`]-1.
+    pub fn table(&self) -> [u32; LEVELS as usize] {
+        let mut table = [0; LEVELS as usize];
+        for (level, share) in table.iter_mut().enumerate() {
+            *share = self.share(level as u32);
+        }
+        table
+    }
+}
+
+/// Per-level on-time shares the frame loop uses.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum GammaTable {
+    /// Linear `level / LEVELS` duty.
+    Linear,
+    /// A [`Gamma::table`].
+    Curve([u32; LEVELS as usize]),
+}
+
+impl GammaTable {
+    /// Computes the table for a curve, or `None` for linear levels.
+    pub fn new(gamma: Option<Gamma>) -> Self {
+        match gamma {
+            Some(gamma) => GammaTable::Curve(gamma.table()),
+            None => GammaTable::Linear,
+        }
+    }
+
+    /// On-time of a level at full brightness in one LED's slot.
+    ///
+    /// # Arguments
+    /// * `timing` - Timing for the current frame rate
+    /// * `led` - LED index (0=Red, 1=Green, 2=Blue)
+    /// * `level` - Level from 0 to [`LEVELS`]-1
+    ///
+    /// # Returns
+    /// The on-time in timer ticks, rounded down.
+    pub fn level_ticks(&self, timing: &RgbTiming, led: usize, level: u32) -> u64 {
+        let level = level.min(LEVELS - 1);
+        match self {
+            GammaTable::Linear => timing.level_ticks(led, level),
+            GammaTable::Curve(shares) => {
+                timing.slot_ticks(led) * shares[level as usize] as u64 / GAMMA_SCALE as u64
+            }
+        }
+    }
+}
+
+/// Gamma curve the RGB loop should use, `None` for linear levels; starts
+/// as [`GAMMA`] and is changed by the console.
+pub static GAMMA_CURVE: Mutex<CriticalSectionRawMutex, Option<Gamma>> = Mutex::new(GAMMA);
+
+/// Table installed by the RGB loop, or `None` until it first changes.
+static GAMMA_TABLE: CriticalSectionMutex<Cell<Option<GammaTable>>> =
+    CriticalSectionMutex::new(Cell::new(None));
+
+/// The table on-times are computed with: the last one installed, or
+/// [`GAMMA`]'s before any is.
+pub fn active_gamma_table() -> GammaTable {
+    GAMMA_TABLE
+        .lock(Cell::get)
+        .unwrap_or_else(|| GammaTable::new(GAMMA))
+}
+
+/// Makes `table` the one on-times are computed with.
+///
+/// Called by the RGB loop between frames, so a frame's schedule is always
+/// built from a single table.
+pub fn install_gamma_table(table: GammaTable) {
+    GAMMA_TABLE.lock(|cell| cell.set(Some(table)));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn curve_is_smooth_at_the_knee() {
+        let gamma = Gamma {
+            exponent: 2.2,
+            knee: 3,
+        };
+        let k = 3.0 / (LEVELS - 1) as f32;
+        // No step at the knee...
+        let (below, above) = (gamma.curve(k - 1e-4), gamma.curve(k + 1e-4));
+        assert!((above - below).abs() < 1e-3);
+        // ...and no corner: the slope is the same on both sides.
+        let slope_below = (gamma.curve(k) - gamma.curve(k - 0.01)) / 0.01;
+        let slope_above = (gamma.curve(k + 0.01) - gamma.curve(k)) / 0.01;
+        assert!((slope_above - slope_below).abs() < 0.05);
+        // The ends are fixed.
+        assert_eq!(gamma.curve(0.0), 0.0);
+        assert!((gamma.curve(1.0) - 1.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn table_rises_evenly_through_the_knee() {
+        for exponent in [1.0, 1.8, 2.2, 2.8] {
+            for knee in [0, 1, 3, 6, LEVELS - 1] {
+                let table = Gamma { exponent, knee }.table();
+                // Off stays off and the top level matches linear levels.
+                assert_eq!(table[0], 0);
+                assert_eq!(
+                    table[LEVELS as usize - 1],
+                    GAMMA_SCALE / LEVELS * (LEVELS - 1)
+                );
+                // Every level is brighter than the one below.
+                for pair in table.windows(2) {
+                    assert!(pair[0] < pair[1]);
+                }
+                // The steps never shrink, across the knee included (up to
+                // rounding), so there's no kink where the line meets the power curve.
+                for step in table.windows(3) {
+                    assert!(step[2] - step[1] + 1 >= step[1] - step[0]);
+                }
+                // Below the knee the steps are all the same size.
+                for level in 1..knee.min(LEVELS - 1) as usize {
+                    assert!(table[level + 1] - table[level] <= table[1] + 1);
+                }
+            }
+        }
+        // A knee keeps level 1 lit where the pure curve nearly loses it.
+        let pure = Gamma {
+            exponent: 2.2,
+            knee: 0,
+        }
+        .table();
+        let knee = Gamma {
+            exponent: 2.2,
+            knee: 3,
+        }
+        .table();
+        assert!(knee[1] > 5 * pure[1]);
+    }
+
+    #[test]
+    fn table_keeps_the_ends_and_dims_the_middle() {
+        let timing = RgbTiming::for_frame_rate(100);
+        let curve = GammaTable::new(Some(Gamma {
+            exponent: 2.2,
+            knee: 3,
+        }));
+        for led in 0..3 {
+            // Linear is the plain level duty...
+            assert_eq!(
+                GammaTable::Linear.level_ticks(&timing, led, 5),
+                timing.level_ticks(led, 5)
+            );
+            // ...and the curve keeps both ends and dims the middle.
+            assert_eq!(curve.level_ticks(&timing, led, 0), 0);
+            let top = LEVELS - 1;
+            assert!(timing.level_ticks(led, top) - curve.level_ticks(&timing, led, top) <= 1);
+            assert!(curve.level_ticks(&timing, led, 8) < timing.level_ticks(led, 8));
+        }
+    }
+}
@@ -0,0 +1,489 @@
+//! # Button Gesture Module
+//!
+//! This module turns timestamped button samples into gestures, so the UI
+//! reacts to taps, long presses, and chords rather than to raw button
+//! levels and its own timing checks.
+//!
+//! ## Gestures
+//!
+//! A [`GestureDetector`] takes one sample of both buttons per UI pass and
+//! emits [`Gesture`]s:
+//!
+//! - [`Gesture::Tap`]: one button pressed and released from all-released,
+//!   with the other never pressed and shorter than the long press. It is
+//!   emitted once the double-tap window ([`DOUBLE_TAP`]) has passed with no
+//!   second tap, or as soon as another press shows there won't be one
+//! - [`Gesture::DoubleTap`]: two taps of the same button, the second
+//!   pressed within [`DOUBLE_TAP`] of the first's release, emitted on the
+//!   second release in place of both taps
+//! - [`Gesture::LongPress`]: a button combination held for [`LONG_HOLD`],
+//!   once per hold; the press it ends is not a tap
+//! - [`Gesture::ChordEnter`] and [`Gesture::ChordExit`]: A+B going down
+//!   and coming apart (on the first button released); an exit that ends a
+//!   long press is marked aborted and counted in [`Stat::ChordAborts`]
+//!
+//! The samples are taken as debounced: the 50 ms UI loop is slower than
+//! the buttons' bounce, so a level seen on a pass is the button's.
+//!
+//! ## Use
+//!
+//! [`UiCore`] drives the chord actions and long holds from these events.
+//! Taps and double taps are detected for gestures still to be assigned.
+use crate::*;
+
+/// Longest gap from a tap's release to the next press for the two to make
+/// a double tap.
+pub const DOUBLE_TAP: Duration = Duration::from_millis(300);
+
+/// One of the two buttons.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum GestureButton {
+    /// Button A.
+    A,
+    /// Button B.
+    B,
+}
+
+/// A high-level button gesture.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Gesture {
+    /// A short press and release of one button.
+    Tap(GestureButton),
+    /// Two taps of one button in quick succession.
+    DoubleTap(GestureButton),
+    /// A button combination held for [`LONG_HOLD`], named by the parameter
+    /// it selects (see [`button_parameter`]).
+    LongPress(ControlParameter),
+    /// Both buttons went down.
+    ChordEnter,
+    /// Both buttons were down and one came up.
+    ChordExit {
+        /// Whether the chord had fired a long press, so its release should
+        /// run nothing.
+        aborted: bool,
+    },
+}
+
+/// Release detector for the A+B chord.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ChordGate {
+    held: bool,
+}
+
+impl ChordGate {
+    /// Tracks the chord for one pass of the UI loop.
+    ///
+    /// # Arguments
+    /// * `held` - Whether both buttons are down this pass
+    /// * `long_hold_fired` - Whether the hold being released already ran
+    ///   its long-hold action
+    ///
+    /// # Returns
+    /// `true` on the pass the chord is released, unless the hold was a
+    /// long hold.
+    pub fn released(&mut self, held: bool, long_hold_fired: bool) -> bool {
+        let released = self.held && !held;
+        self.held = held;
+        if released && long_hold_fired {
+            count_stat!(ChordAborts);
+        }
+        released && !long_hold_fired
+    }
+}
+
+/// Gestures completed by one sample, in the order they happened.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Gestures {
+    events: [Option<Gesture>; 4],
+    next: usize,
+}
+
+impl Gestures {
+    const fn new() -> Self {
+        Self {
+            events: [None; 4],
+            next: 0,
+        }
+    }
+
+    fn push(&mut self, gesture: Gesture) {
+        if let Some(slot) = self.events.iter_mut().find(|slot| slot.is_none()) {
+            *slot = Some(gesture);
+        }
+    }
+}
+
+impl Iterator for Gestures {
+    type Item = Gesture;
+
+    fn next(&mut self) -> Option<Gesture> {
+        let gesture = *self.events.get(self.next)?;
+        self.next += 1;
+        gesture
+    }
+}
+
+/// A single-button press in progress, a tap if it ends cleanly.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Press {
+    button: GestureButton,
+    /// Whether the other button or a long press has ruled out a tap.
+    spoiled: bool,
+    /// Whether this press follows a tap of the same button in the window.
+    second: bool,
+}
+
+/// Gesture state machine over timestamped button samples.
+///
+/// # Examples
+/// Timelines of (time in ms, A, B) samples and the gestures they produce:
+///
+/// Every sequence of up to `SEQUENCE_LEN` samples, each a UI loop delay,
+/// a little over the double-tap window, or a whole long press after the
+/// last, keeps the invariants, and every clean short press is accounted
+/// for exactly once, as a tap or half a double tap:
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GestureDetector {
+    /// Buttons A and B at the last sample.
+    last: (bool, bool),
+    /// Detects the chord's release.
+    chord: ChordGate,
+    /// Button combination currently held and when it was first seen, in
+    /// milliseconds.
+    hold: Option<(ControlParameter, u64)>,
+    /// Whether the current hold has already fired its long press.
+    hold_fired: bool,
+    /// Single-button press in progress.
+    press: Option<Press>,
+    /// Tap waiting out the double-tap window, and when it was released.
+    tap: Option<(GestureButton, u64)>,
+}
+
+impl GestureDetector {
+    /// Creates a detector with both buttons released.
+    pub const fn new() -> Self {
+        Self {
+            last: (false, false),
+            chord: ChordGate { held: false },
+            hold: None,
+            hold_fired: false,
+            press: None,
+            tap: None,
+        }
+    }
+
+    /// Takes one sample of the buttons.
+    ///
+    /// # Arguments
+    /// * `now_ms` - Current time in milliseconds since boot
+    /// * `a` - Whether button A is down
+    /// * `b` - Whether button B is down
+    ///
+    /// # Returns
+    /// The gestures this sample completed.
+    pub fn push(&mut self, now_ms: u64, a: bool, b: bool) -> Gestures {
+        let mut gestures = Gestures::new();
+        let buttons = button_parameter(a, b);
+        let double_tap_ms = DOUBLE_TAP.as_millis();
+        if let Some((button, released_ms)) = self.tap {
+            if self.press.is_none() && now_ms.saturating_sub(released_ms) > double_tap_ms {
+                gestures.push(Gesture::Tap(button));
+                self.tap = None;
+            }
+        }
+
+        let chord = a && b;
+        if chord && self.last != (true, true) {
+            gestures.push(Gesture::ChordEnter);
+        }
+        let exiting = self.chord.held && !chord;
+        let released = self.chord.released(chord, self.hold_fired);
+        if exiting {
+            gestures.push(Gesture::ChordExit { aborted: !released });
+        }
+
+        let long_press = self.long_press_elapsed(buttons, now_ms);
+        if long_press {
+            gestures.push(Gesture::LongPress(buttons));
+        }
+
+        let single = match (a, b) {
+            (true, false) => Some(GestureButton::A),
+            (false, true) => Some(GestureButton::B),
+            _ => None,
+        };
+        match self.press.as_mut() {
+            None if self.last == (false, false) && (a || b) => {
+                let second = match self.tap {
+                    Some((button, released_ms))
+                        if Some(button) == single
+                            && now_ms.saturating_sub(released_ms) <= double_tap_ms =>
+                    {
+                        true
+                    }
+                    _ => {
+                        if let Some((button, _)) = self.tap.take() {
+                            gestures.push(Gesture::Tap(button));
+                        }
+                        false
+                    }
+                };
+                self.press = single.map(|button| Press {
+                    button,
+                    spoiled: false,
+                    second,
+                });
+            }
+            None => {}
+            Some(press) => {
+                if ((a || b) && single != Some(press.button)) || long_press {
+                    press.spoiled = true;
+                }
+                if !a && !b {
+                    let press = *press;
+                    self.press = None;
+                    if press.spoiled {
+                        if let Some((button, _)) = self.tap.take() {
+                            gestures.push(Gesture::Tap(button));
+                        }
+                    } else if press.second && self.tap.is_some() {
+                        self.tap = None;
+                        gestures.push(Gesture::DoubleTap(press.button));
+                    } else {
+                        self.tap = Some((press.button, now_ms));
+                    }
+                }
+            }
+        }
+        self.last = (a, b);
+        gestures
+    }
+
+    /// Tracks how long the current button combination has been held.
+    ///
+    /// # Returns
+    /// `true` exactly once per hold, when the combination has been held for
+    /// [`LONG_HOLD`]. Releasing all buttons never counts as a hold.
+    fn long_press_elapsed(&mut self, parameter: ControlParameter, now_ms: u64) -> bool {
+        match self.hold {
+            Some((held, start_ms)) if held == parameter => {
+                if !self.hold_fired && now_ms.saturating_sub(start_ms) >= LONG_HOLD.as_millis() {
+                    self.hold_fired = true;
+                    return true;
+                }
+            }
+            _ => {
+                self.hold =
+                    (parameter != ControlParameter::FrameRate).then_some((parameter, now_ms));
+                self.hold_fired = false;
+            }
+        }
+        false
+    }
+}
+
+impl Default for GestureDetector {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn chord_fires_once_on_a_short_release() {
+        let mut chord = ChordGate::default();
+        // Pressing and holding never fires.
+        assert!(!chord.released(true, false));
+        assert!(!chord.released(true, false));
+        // Releasing fires once.
+        assert!(chord.released(false, false));
+        assert!(!chord.released(false, false));
+        // A release that ends a long hold doesn't fire.
+        assert!(!chord.released(true, false));
+        assert!(!chord.released(false, true));
+    }
+
+    #[test]
+    fn gestures_from_button_samples() {
+        fn gestures(samples: &[(u64, bool, bool)]) -> [Option<(u64, Gesture)>; 4] {
+            let mut detector = GestureDetector::new();
+            let mut seen = [None; 4];
+            let mut n = 0;
+            for &(now_ms, a, b) in samples {
+                for gesture in detector.push(now_ms, a, b) {
+                    seen[n] = Some((now_ms, gesture));
+                    n += 1;
+                }
+            }
+            seen
+        }
+        use GestureButton::{A, B};
+
+        // A tap shows once the double-tap window has passed.
+        let tap = gestures(&[
+            (0, true, false),
+            (100, false, false),
+            (400, false, false),
+            (450, false, false),
+        ]);
+        assert_eq!(tap, [Some((450, Gesture::Tap(A))), None, None, None]);
+
+        // A second tap inside the window makes a double tap instead.
+        let double = gestures(&[
+            (0, true, false),
+            (100, false, false),
+            (200, true, false),
+            (300, false, false),
+            (800, false, false),
+        ]);
+        assert_eq!(
+            double,
+            [Some((300, Gesture::DoubleTap(A))), None, None, None]
+        );
+
+        // Taps of different buttons stay separate; the second press settles
+        // the first.
+        let two = gestures(&[
+            (0, true, false),
+            (100, false, false),
+            (200, false, true),
+            (300, false, false),
+            (650, false, false),
+        ]);
+        assert_eq!(
+            two,
+            [
+                Some((200, Gesture::Tap(A))),
+                Some((650, Gesture::Tap(B))),
+                None,
+                None
+            ]
+        );
+
+        // A long press fires while held, and its release is no tap.
+        let long = gestures(&[
+            (0, true, false),
+            (1000, true, false),
+            (2000, true, false),
+            (2100, false, false),
+            (2800, false, false),
+        ]);
+        assert_eq!(
+            long,
+            [
+                Some((2000, Gesture::LongPress(ControlParameter::Blue))),
+                None,
+                None,
+                None
+            ]
+        );
+
+        // A chord rolled on from A is a chord, not a tap of A.
+        let chord = gestures(&[
+            (0, true, false),
+            (50, true, true),
+            (500, false, false),
+            (1000, false, false),
+        ]);
+        assert_eq!(
+            chord,
+            [
+                Some((50, Gesture::ChordEnter)),
+                Some((500, Gesture::ChordExit { aborted: false })),
+                None,
+                None,
+            ]
+        );
+
+        // A chord held into a long press exits aborted.
+        let aborted = gestures(&[(0, true, true), (2000, true, true), (2100, false, false)]);
+        assert_eq!(
+            aborted,
+            [
+                Some((0, Gesture::ChordEnter)),
+                Some((2000, Gesture::LongPress(ControlParameter::Red))),
+                Some((2100, Gesture::ChordExit { aborted: true })),
+                None,
+            ]
+        );
+    }
+
+    #[test]
+    fn every_short_sequence_is_consistent() {
+        const SEQUENCE_LEN: u32 = 5;
+        const SYMBOLS: u32 = 12;
+        let gaps = [50, DOUBLE_TAP.as_millis() + 50, LONG_HOLD.as_millis()];
+        for code in 0..SYMBOLS.pow(SEQUENCE_LEN) {
+            let mut samples = [(0u64, false, false); SEQUENCE_LEN as usize + 2];
+            let mut rest = code;
+            let mut now_ms = 0;
+            for sample in samples.iter_mut().take(SEQUENCE_LEN as usize) {
+                let symbol = rest % SYMBOLS;
+                rest /= SYMBOLS;
+                now_ms += gaps[(symbol / 4) as usize];
+                *sample = (now_ms, symbol & 1 != 0, symbol & 2 != 0);
+            }
+            // Two long releases at the end flush any pending tap.
+            samples[SEQUENCE_LEN as usize] = (now_ms + LONG_HOLD.as_millis(), false, false);
+            samples[SEQUENCE_LEN as usize + 1] = (now_ms + 2 * LONG_HOLD.as_millis(), false, false);
+
+            let mut detector = GestureDetector::new();
+            let mut last = (false, false);
+            let (mut held, mut hold_fires) = (ControlParameter::FrameRate, 0);
+            let (mut taps, mut doubles, mut clean) = (0, 0, 0);
+            // Single-button press from all-released: (button A?, start, clean?).
+            let mut press: Option<(bool, u64, bool)> = None;
+            for &(now_ms, a, b) in samples.iter() {
+                let (mut enters, mut exits) = (0, 0);
+                let buttons = button_parameter(a, b);
+                if buttons != held {
+                    held = buttons;
+                    hold_fires = 0;
+                }
+                for gesture in detector.push(now_ms, a, b) {
+                    match gesture {
+                        Gesture::ChordEnter => enters += 1,
+                        Gesture::ChordExit { .. } => exits += 1,
+                        Gesture::LongPress(parameter) => {
+                            // Once per hold, for the buttons down, never none.
+                            assert_eq!(parameter, buttons, "{:?}", samples);
+                            assert!(parameter != ControlParameter::FrameRate, "{:?}", samples);
+                            hold_fires += 1;
+                            assert_eq!(hold_fires, 1, "{:?}", samples);
+                        }
+                        Gesture::DoubleTap(_) => {
+                            assert!(!a && !b, "{:?}", samples);
+                            doubles += 1;
+                        }
+                        Gesture::Tap(_) => taps += 1,
+                    }
+                }
+                // Chord edges match the samples exactly.
+                let chord = a && b;
+                let was_chord = last == (true, true);
+                assert_eq!(enters, (chord && !was_chord) as u32, "{:?}", samples);
+                assert_eq!(exits, (was_chord && !chord) as u32, "{:?}", samples);
+
+                match press.as_mut() {
+                    None if last == (false, false) && a != b => press = Some((a, now_ms, true)),
+                    None => {}
+                    Some((is_a, start_ms, ok)) => {
+                        if a || b {
+                            *ok &= a == *is_a
+                                && b != *is_a
+                                && now_ms - *start_ms < LONG_HOLD.as_millis();
+                        } else {
+                            clean += *ok as u32;
+                            press = None;
+                        }
+                    }
+                }
+                last = (a, b);
+            }
+            assert_eq!(taps + 2 * doubles, clean, "{:?}", samples);
+        }
+    }
+}
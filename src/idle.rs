@@ -0,0 +1,388 @@
+//! # Idle Animation Module
+//!
+//! This module plays a gentle animation on the LED once the controls have
+//! been left alone for a while, and puts the user's color back the moment
+//! they are touched again.
+//!
+//! ## Effects
+//!
+//! [`IDLE_CONFIG`] picks the [`Effect`] and the idle timeout:
+//!
+//! - [`Effect::Breathe`]: The saved color slowly dims and brightens
+//! - [`Effect::Drift`]: The hue slowly circles the color wheel at the saved
+//!   color's brightness
+//! - [`Effect::SoundReactive`] (`mic` feature): The saved color stays, and
+//!   its brightness follows the microphone's sound level, as in the UI's
+//!   sound-reactive mode (see the [`mic`](crate::mic) module)
+//! - [`Effect::Tilt`] (`motion` feature): The color follows the board's
+//!   tilt (see the [`motion`](crate::motion) module)
+//! - [`Effect::Off`]: No animation; the color stays as set
+//!
+//! ## Transitions
+//!
+//! Entering is smooth: over [`IDLE_FADE`] the output is blended from the
+//! saved color into the animation. Leaving is immediate, so the first knob
+//! turn or button press after a pause acts on the color the user left,
+//! not on an animation frame: the UI reports input through
+//! [`idle_input`], which restores the saved color before the input itself
+//! is applied.
+//!
+//! ## Coexistence with Calibration
+//!
+//! The animation only ever writes the RGB levels while idle; the saved
+//! color is never modified, so a calibration left running overnight comes
+//! back exactly as it was.
+//!
+//! ## Layering over Manual Control
+//!
+//! An effect can also run all the time as a layer over the manual color
+//! (`EFFECT BREATHE|DRIFT|OFF` on the console, see [`set_effect_layer`]).
+//! The layer never writes the RGB levels: the RGB loop passes them through
+//! [`effect_layer`] on their way to the LED, so the knob and buttons keep
+//! editing the base color while the effect plays on top of it, with no
+//! mode to leave. [`compose_effect`] defines how each effect combines with
+//! the base color:
+//!
+//! - [`Effect::Breathe`] multiplies it: every channel dims and brightens
+//!   by the same factor, so the hue and ratios are kept and black stays
+//!   black
+//! - [`Effect::Drift`] replaces the hue and keeps the brightness: the
+//!   brightest channel of the base color sets the drifting color's value
+//! - [`Effect::Off`], and the effects another task animates, pass it
+//!   through unchanged
+//!
+//! Like the idle animation, the layer fades in over [`IDLE_FADE`] when it
+//! starts. While a layer is on the idle animation stays off, since the LED
+//! is already animated, and the battery warning still blinks over both.
+use crate::*;
+
+/// Time over which the output fades from the saved color into the animation.
+pub const IDLE_FADE: Duration = Duration::from_secs(3);
+/// Length of one breath or one trip round the color wheel, in milliseconds.
+const IDLE_PERIOD_MS: u64 = 8_000;
+/// How far [`Effect::Breathe`] dims at the bottom of a breath, as a fraction.
+const BREATHE_DEPTH: f32 = 0.75;
+
+/// Animation shown while idle.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Effect {
+    /// No animation.
+    Off,
+    /// Slowly dim and brighten the saved color.
+    Breathe,
+    /// Slowly rotate the hue at the saved color's brightness.
+    Drift,
+    /// Keep the saved color and let the UI drive the master brightness from
+    /// the microphone.
+    #[cfg(feature = "mic")]
+    SoundReactive,
+    /// Let the motion task set the color from the board's tilt.
+    #[cfg(feature = "motion")]
+    Tilt,
+}
+
+impl Effect {
+    /// Whether another task animates the output for this effect, so
+    /// `idle_task` only tracks idleness and leaves the levels alone.
+    pub fn external(self) -> bool {
+        !matches!(self, Effect::Off | Effect::Breathe | Effect::Drift)
+    }
+}
+
+/// Idle animation settings.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct IdleConfig {
+    /// Time without input before the animation starts.
+    pub timeout: Duration,
+    /// Animation shown once idle.
+    pub idle_effect: Effect,
+}
+
+/// Idle animation settings used by `idle_task`.
+pub const IDLE_CONFIG: IdleConfig = IdleConfig {
+    timeout: Duration::from_secs(60),
+    idle_effect: Effect::Breathe,
+};
+
+/// Blends two colors.
+///
+/// # Arguments
+/// * `from` - Color at `fraction` 0.0
+/// * `to` - Color at `fraction` 1.0
+/// * `fraction` - Blend position, clamped to 0.0..=1.0
+pub fn fade_levels(from: [u32; 3], to: [u32; 3], fraction: f32) -> [u32; 3] {
+    let fraction = fraction.clamp(0.0, 1.0);
+    let mut levels = [0; 3];
+    for (level, (&from, &to)) in levels.iter_mut().zip(from.iter().zip(to.iter())) {
+        let blended = from as f32 + (to as f32 - from as f32) * fraction;
+        *level = ((blended + 0.5) as u32).min(LEVELS - 1);
+    }
+    levels
+}
+
+/// Composes an effect with a base color, at full strength.
+///
+/// # Arguments
+/// * `effect` - Effect to apply
+/// * `base` - Color the effect modulates
+/// * `elapsed_ms` - Time since the effect started
+///
+/// # Returns
+/// The composed levels (see the module docs for the rules).
+pub fn compose_effect(effect: Effect, base: [u32; 3], elapsed_ms: u64) -> [u32; 3] {
+    let phase = (elapsed_ms % IDLE_PERIOD_MS) as f32 / IDLE_PERIOD_MS as f32;
+    match effect {
+        Effect::Off => base,
+        #[cfg(feature = "mic")]
+        Effect::SoundReactive => base,
+        #[cfg(feature = "motion")]
+        Effect::Tilt => base,
+        Effect::Breathe => {
+            let dip = (1.0 - num_traits::Float::cos(phase * 2.0 * core::f32::consts::PI)) / 2.0;
+            let scale = 1.0 - BREATHE_DEPTH * dip;
+            base.map(|level| (level as f32 * scale + 0.5) as u32)
+        }
+        Effect::Drift => {
+            let peak = base.iter().copied().max().unwrap_or(0);
+            hsv_to_levels(phase * 360.0, 1.0, peak as f32 / (LEVELS - 1) as f32)
+        }
+    }
+}
+
+/// Computes one animation frame.
+///
+/// # Arguments
+/// * `effect` - Animation to show
+/// * `saved` - The user's color, which the animation starts from
+/// * `elapsed_ms` - Time since the animation started
+///
+/// # Returns
+/// The levels to show: [`compose_effect`], faded in from `saved` over
+/// [`IDLE_FADE`].
+pub fn idle_frame(effect: Effect, saved: [u32; 3], elapsed_ms: u64) -> [u32; 3] {
+    if effect.external() || effect == Effect::Off {
+        return saved;
+    }
+    let target = compose_effect(effect, saved, elapsed_ms);
+    let fade = elapsed_ms as f32 / IDLE_FADE.as_millis() as f32;
+    fade_levels(saved, target, fade)
+}
+
+/// Idle detection and animation state.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct IdleAnimation {
+    /// Timeout and effect.
+    pub config: IdleConfig,
+    /// Time of the last input, in milliseconds since boot.
+    last_input_ms: u64,
+    /// The user's color and the animation's start time, while animating.
+    running: Option<([u32; 3], u64)>,
+}
+
+impl IdleAnimation {
+    /// Creates the state as if input had just happened at boot.
+    pub const fn new(config: IdleConfig) -> Self {
+        Self {
+            config,
+            last_input_ms: 0,
+            running: None,
+        }
+    }
+
+    /// Whether the animation is running.
+    pub fn active(&self) -> bool {
+        self.running.is_some()
+    }
+
+    /// Whether the UI should be running sound-reactive brightness for the
+    /// animation now.
+    #[cfg(feature = "mic")]
+    pub fn sound_reactive(&self) -> bool {
+        self.active() && self.config.idle_effect == Effect::SoundReactive
+    }
+
+    /// Whether the motion task should be setting the color from the tilt
+    /// for the animation now.
+    #[cfg(feature = "motion")]
+    pub fn tilt_active(&self) -> bool {
+        self.active() && self.config.idle_effect == Effect::Tilt
+    }
+
+    /// Advances the animation.
+    ///
+    /// # Arguments
+    /// * `now_ms` - Current time in milliseconds since boot
+    /// * `levels` - Levels currently shown, saved if the animation starts
+    ///
+    /// # Returns
+    /// The levels to show, or `None` while not idle.
+    pub fn tick(&mut self, now_ms: u64, levels: [u32; 3]) -> Option<[u32; 3]> {
+        if self.config.idle_effect == Effect::Off {
+            return None;
+        }
+        let (saved, start_ms) = match self.running {
+            Some(running) => running,
+            None => {
+                let timeout_ms = self.config.timeout.as_millis();
+                if now_ms.saturating_sub(self.last_input_ms) < timeout_ms {
+                    return None;
+                }
+                self.running = Some((levels, now_ms));
+                (levels, now_ms)
+            }
+        };
+        Some(idle_frame(
+            self.config.idle_effect,
+            saved,
+            now_ms.saturating_sub(start_ms),
+        ))
+    }
+
+    /// Records input, stopping the animation.
+    ///
+    /// # Arguments
+    /// * `now_ms` - Time of the input in milliseconds since boot
+    ///
+    /// # Returns
+    /// The user's color to restore, if the animation was running.
+    pub fn input(&mut self, now_ms: u64) -> Option<[u32; 3]> {
+        self.last_input_ms = now_ms;
+        self.running.take().map(|(saved, _)| saved)
+    }
+}
+
+/// Global idle state, fed by the UI and animated by `idle_task`.
+pub static IDLE: Mutex<CriticalSectionRawMutex, IdleAnimation> =
+    Mutex::new(IdleAnimation::new(IDLE_CONFIG));
+
+/// Reports user input, restoring the user's color if the animation was
+/// running.
+///
+/// Call before applying the input, so it acts on the restored color.
+pub async fn idle_input() {
+    let mut idle = IDLE.lock().await;
+    if let Some(saved) = idle.input(Instant::now().as_millis()) {
+        set_rgb_levels(|rgb| *rgb = saved).await;
+        rprintln!("Idle animation: off");
+    }
+}
+
+/// Effect layered over the manual color, and its start time in
+/// milliseconds since boot, if one is on.
+pub static EFFECT_LAYER: Mutex<CriticalSectionRawMutex, Option<(Effect, u64)>> = Mutex::new(None);
+
+/// Starts an effect layered over the manual color, or stops it with
+/// [`Effect::Off`].
+///
+/// Starting the effect already on leaves it running, rather than
+/// restarting its fade. Effects another task animates
+/// ([`Effect::external`]) can't be layered and stop the layer.
+pub async fn set_effect_layer(effect: Effect) {
+    let mut layer = EFFECT_LAYER.lock().await;
+    if effect == Effect::Off || effect.external() {
+        *layer = None;
+    } else if layer.map(|(running, _)| running) != Some(effect) {
+        *layer = Some((effect, Instant::now().as_millis()));
+    }
+}
+
+/// Returns the effect layered over the manual color.
+pub async fn get_effect_layer() -> Effect {
+    EFFECT_LAYER
+        .lock()
+        .await
+        .map_or(Effect::Off, |(effect, _)| effect)
+}
+
+/// Composes the layered effect, if any, over the levels on their way to
+/// the LED.
+///
+/// # Arguments
+/// * `levels` - The manual color, as set by the controls
+///
+/// # Returns
+/// The levels to show: [`idle_frame`] of the layer over `levels`, so the
+/// layer fades in as it starts, or `levels` unchanged with no layer.
+pub async fn effect_layer(levels: [u32; 3]) -> [u32; 3] {
+    match *EFFECT_LAYER.lock().await {
+        Some((effect, start_ms)) => idle_frame(
+            effect,
+            levels,
+            Instant::now().as_millis().saturating_sub(start_ms),
+        ),
+        None => levels,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fade_levels_interpolates() {
+        assert_eq!(fade_levels([15, 0, 4], [1, 8, 4], 0.0), [15, 0, 4]);
+        assert_eq!(fade_levels([15, 0, 4], [1, 8, 4], 0.5), [8, 4, 4]);
+        assert_eq!(fade_levels([15, 0, 4], [1, 8, 4], 1.0), [1, 8, 4]);
+    }
+
+    #[test]
+    fn compose_effect_layers_over_the_color() {
+        // Breathe over a fixed color: a dimming envelope on every channel,
+        // from the color itself down to a quarter of it and back.
+        let base = [12, 8, 0];
+        assert_eq!(compose_effect(Effect::Breathe, base, 0), [12, 8, 0]);
+        assert_eq!(compose_effect(Effect::Breathe, base, 2_000), [8, 5, 0]);
+        assert_eq!(compose_effect(Effect::Breathe, base, 4_000), [3, 2, 0]);
+        assert_eq!(compose_effect(Effect::Breathe, base, 6_000), [8, 5, 0]);
+        assert_eq!(compose_effect(Effect::Breathe, base, 8_000), [12, 8, 0]);
+        // Turning the base color mid-breath moves the envelope with it.
+        assert_eq!(compose_effect(Effect::Breathe, [4, 4, 4], 4_000), [1, 1, 1]);
+
+        // Drift over the same color: the hue turns, the brightness stays.
+        assert_eq!(compose_effect(Effect::Drift, base, 0), [12, 0, 0]);
+        assert_eq!(compose_effect(Effect::Drift, base, 4_000), [0, 12, 12]);
+        assert_eq!(compose_effect(Effect::Drift, [0, 0, 0], 4_000), [0, 0, 0]);
+
+        assert_eq!(compose_effect(Effect::Off, base, 4_000), base);
+    }
+
+    #[test]
+    fn idle_frame_starts_on_the_color() {
+        // Every effect starts on the user's color, so entering doesn't jump.
+        for effect in [Effect::Off, Effect::Breathe, Effect::Drift] {
+            assert_eq!(idle_frame(effect, [12, 6, 0], 0), [12, 6, 0]);
+        }
+        // Half a breath in, the color is at its dimmest.
+        assert_eq!(idle_frame(Effect::Breathe, [12, 8, 0], 4_000), [3, 2, 0]);
+    }
+
+    #[test]
+    fn idle_animation_times_out_and_hands_back_the_color() {
+        let config = IdleConfig {
+            timeout: Duration::from_secs(60),
+            idle_effect: Effect::Breathe,
+        };
+        let mut idle = IdleAnimation::new(config);
+        // Before the timeout nothing happens.
+        assert_eq!(idle.tick(59_999, [12, 8, 0]), None);
+        assert!(!idle.active());
+        // At the timeout the user's color is saved and the animation starts.
+        assert_eq!(idle.tick(60_000, [12, 8, 0]), Some([12, 8, 0]));
+        assert!(idle.active());
+        assert_eq!(idle.tick(64_000, [12, 8, 0]), Some([3, 2, 0]));
+        // Input stops it at once and hands back the color to restore...
+        assert_eq!(idle.input(64_050), Some([12, 8, 0]));
+        assert!(!idle.active());
+        // ...and the timeout starts over from that input.
+        assert_eq!(idle.tick(124_049, [12, 8, 0]), None);
+        assert!(idle.tick(124_050, [12, 8, 0]).is_some());
+
+        // With no effect configured it never starts.
+        let mut off = IdleAnimation::new(IdleConfig {
+            idle_effect: Effect::Off,
+            ..config
+        });
+        assert_eq!(off.tick(1_000_000, [12, 8, 0]), None);
+        assert_eq!(off.input(1_000_001), None);
+    }
+}
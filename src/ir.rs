@@ -0,0 +1,232 @@
+//! # IR Remote Module
+//!
+//! This module decodes a cheap NEC-protocol IR remote (the 24-key kind sold
+//! with LED strips) from a demodulating receiver such as a VS1838B, and
+//! turns its keys into UI commands for hands-free control.
+//!
+//! ## Capture
+//!
+//! The receiver output idles high and pulls low during a carrier burst
+//! ("mark"). [`ir_task`] waits for each edge and timestamps it, feeding the
+//! duration and polarity of every mark and space to a [`NecDecoder`].
+//!
+//! ## NEC Protocol
+//!
+//! ```text
+//! frame:  9 ms mark, 4.5 ms space, 32 bits, 562 µs stop mark
+//! bit:    562 µs mark, then 562 µs space (0) or 1687 µs space (1)
+//! repeat: 9 ms mark, 2.25 ms space, 562 µs mark (every 108 ms while held)
+//! ```
+//!
+//! The 32 bits are sent least significant first: address, inverted address
+//! (or the high address byte in extended NEC), command, and inverted
+//! command. Every duration is accepted within ±25% of nominal, and frames
+//! whose command fails its inverse check are rejected.
+//!
+//! ## Key Map
+//!
+//! [`ir_key_command`] maps the remote's 24 keys onto [`UI_COMMANDS`], the
+//! same queue console commands take to the UI:
+//!
+//! - **Brightness up/down**: Step the selected parameter (held keys repeat)
+//! - **Off/On**: Blank the LED, then restore the saved levels
+//! - **R/G/B/W**: Select red, green, blue, or frame rate (like `FOCUS`)
+//! - **Color keys**: Load a preset color
+//! - **Effect keys** (FLASH, STROBE, FADE, SMOOTH): Return to button control
+use crate::*;
+
+/// Nominal leader mark, in microseconds.
+const NEC_LEADER_MARK_US: u64 = 9000;
+/// Nominal leader space before a data frame, in microseconds.
+const NEC_LEADER_SPACE_US: u64 = 4500;
+/// Nominal leader space before a repeat code, in microseconds.
+const NEC_REPEAT_SPACE_US: u64 = 2250;
+/// Nominal bit mark (and zero space), in microseconds.
+const NEC_BIT_MARK_US: u64 = 562;
+/// Nominal one space, in microseconds.
+const NEC_ONE_SPACE_US: u64 = 1687;
+/// Number of data bits per frame.
+const NEC_BITS: u32 = 32;
+
+/// A decoded NEC event.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum NecEvent {
+    /// A complete, validated frame.
+    Frame {
+        /// 8-bit address, or 16-bit extended address.
+        address: u16,
+        /// Command byte.
+        command: u8,
+    },
+    /// A repeat code: the last key is still held.
+    Repeat,
+}
+
+/// Decoder position within a transmission.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum NecState {
+    /// Waiting for a leader mark.
+    Idle,
+    /// Leader mark seen; expecting its space.
+    Leader,
+    /// Repeat space seen; expecting the closing mark.
+    RepeatEnd,
+    /// Expecting the mark of bit `count` (or the stop mark after 32).
+    BitMark { value: u32, count: u32 },
+    /// Expecting the space of bit `count`.
+    BitSpace { value: u32, count: u32 },
+}
+
+/// Pulse-train decoder for the NEC IR protocol.
+///
+/// Feed it every mark and space with [`NecDecoder::push`]. Anything out of
+/// place drops it back to waiting for a leader, so noise and truncated
+/// frames are discarded rather than decoded.
+#[derive(Debug, Clone, Copy)]
+pub struct NecDecoder {
+    state: NecState,
+}
+
+/// Whether a duration is within ±25% of a nominal one.
+fn nec_within(duration_us: u64, nominal_us: u64) -> bool {
+    duration_us * 4 >= nominal_us * 3 && duration_us * 4 <= nominal_us * 5
+}
+
+impl NecDecoder {
+    /// Creates a decoder waiting for a leader.
+    pub const fn new() -> Self {
+        Self {
+            state: NecState::Idle,
+        }
+    }
+
+    /// Feeds one mark or space to the decoder.
+    ///
+    /// # Arguments
+    /// * `mark` - `true` for a carrier burst (receiver output low)
+    /// * `duration_us` - Length of the mark or space in microseconds
+    ///
+    /// # Returns
+    /// An event when this pulse completes a valid frame or repeat code.
+    pub fn push(&mut self, mark: bool, duration_us: u64) -> Option<NecEvent> {
+        let (next, event) = match (self.state, mark) {
+            (NecState::Leader, false) if nec_within(duration_us, NEC_LEADER_SPACE_US) => {
+                (NecState::BitMark { value: 0, count: 0 }, None)
+            }
+            (NecState::Leader, false) if nec_within(duration_us, NEC_REPEAT_SPACE_US) => {
+                (NecState::RepeatEnd, None)
+            }
+            (NecState::RepeatEnd, true) if nec_within(duration_us, NEC_BIT_MARK_US) => {
+                (NecState::Idle, Some(NecEvent::Repeat))
+            }
+            (NecState::BitMark { value, count }, true)
+                if nec_within(duration_us, NEC_BIT_MARK_US) =>
+            {
+                if count == NEC_BITS {
+                    (NecState::Idle, Self::frame(value))
+                } else {
+                    (NecState::BitSpace { value, count }, None)
+                }
+            }
+            (NecState::BitSpace { value, count }, false) => {
+                let bit = if nec_within(duration_us, NEC_BIT_MARK_US) {
+                    0
+                } else if nec_within(duration_us, NEC_ONE_SPACE_US) {
+                    1
+                } else {
+                    self.state = NecState::Idle;
+                    return None;
+                };
+                let value = value | bit << count;
+                (
+                    NecState::BitMark {
+                        value,
+                        count: count + 1,
+                    },
+                    None,
+                )
+            }
+            // A leader mark starts a new transmission from any state, so a
+            // frame cut short by noise doesn't swallow the next one.
+            (_, true) if nec_within(duration_us, NEC_LEADER_MARK_US) => (NecState::Leader, None),
+            _ => (NecState::Idle, None),
+        };
+        self.state = next;
+        event
+    }
+
+    /// Validates and unpacks a received 32-bit frame.
+    fn frame(value: u32) -> Option<NecEvent> {
+        let [address, address_inverse, command, command_inverse] = value.to_le_bytes();
+        if command ^ command_inverse != 0xFF {
+            return None;
+        }
+        let address = if address ^ address_inverse == 0xFF {
+            address as u16
+        } else {
+            u16::from_le_bytes([address, address_inverse])
+        };
+        Some(NecEvent::Frame { address, command })
+    }
+}
+
+impl Default for NecDecoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Preset colors on the remote's color keys, by command code.
+const IR_PRESETS: [(u8, [u32; 3]); 12] = [
+    (0x08, [15, 4, 0]),  // orange-red
+    (0x09, [4, 15, 4]),  // light green
+    (0x0A, [4, 4, 15]),  // light blue
+    (0x0C, [15, 8, 0]),  // orange
+    (0x0D, [0, 15, 15]), // cyan
+    (0x0E, [8, 0, 15]),  // purple
+    (0x10, [15, 11, 2]), // light orange
+    (0x11, [0, 10, 8]),  // teal
+    (0x12, [11, 4, 15]), // violet
+    (0x14, [15, 15, 0]), // yellow
+    (0x15, [4, 11, 15]), // sky blue
+    (0x16, [15, 6, 10]), // pink
+];
+
+/// Maps a key of the 24-key LED-strip remote to a UI command.
+///
+/// # Arguments
+/// * `command` - NEC command byte of the key
+///
+/// # Returns
+/// The UI command, or `None` for an unknown key.
+pub fn ir_key_command(command: u8) -> Option<Command> {
+    let focus = |parameter| Some(Command::Focus(Some(parameter)));
+    match command {
+        0x00 => Some(Command::Adjust(1)),
+        0x01 => Some(Command::Adjust(-1)),
+        0x02 => Some(Command::Power(false)),
+        0x03 => Some(Command::Power(true)),
+        0x04 => focus(ControlParameter::Red),
+        0x05 => focus(ControlParameter::Green),
+        0x06 => focus(ControlParameter::Blue),
+        0x07 => focus(ControlParameter::FrameRate),
+        0x0B | 0x0F | 0x13 | 0x17 => Some(Command::Focus(None)),
+        _ => IR_PRESETS
+            .iter()
+            .find(|&&(code, _)| code == command)
+            .map(|&(_, levels)| Command::Preset(levels)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn leader_and_short_space_decode_as_a_repeat() {
+        let mut decoder = NecDecoder::new();
+        assert_eq!(decoder.push(true, 9000), None);
+        assert_eq!(decoder.push(false, 2250), None);
+        assert_eq!(decoder.push(true, 560), Some(NecEvent::Repeat));
+    }
+}
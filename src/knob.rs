@@ -5,19 +5,981 @@
 //!
 //! The knob converts analog voltage readings into discrete levels suitable
 //! for controlling RGB LED intensity or other stepped parameters.`
+//!
+//! The knob channel is ratiometric: with a gain of 1/4 against a VDD/4
+//! reference, the wiper at +3.3V reads [`KNOB_RAW_MAX`] whatever the actual
+//! supply, so both ends of the travel map to the end levels and every
+//! level between the end zones (below) gets an equal share of the travel.
+//!
+//! ## Burst Sampling
+//!
+//! Each knob reading can be a burst of [`KNOB_BURST`] scans taken back to
+//! back into one buffer, which [`reduce_burst`] averages channel by
+//! channel before anything else sees the reading. The mean is in raw
+//! counts like a single scan, so calibration holds, statistics and the
+//! level scaling below work on it unchanged, while the noise shrinks with
+//! the square root of the burst. The whole burst is one transfer under one
+//! [`KnobTimeout::timeout`] (see [`KnobAdc::sample_burst`]), with its scans
+//! paced by a timer, so a burst costs [`KNOB_BURST`] conversion times but
+//! only one wait. The microphone keeps single scans.
+//!
+//! ## Response Curves
+//!
+//! A [`KnobResponse`] shapes how knob travel maps onto levels: a dead zone
+//! at the bottom of the travel that always reads level 0 and one at the top
+//! that always reads the top level, with a [`ResponseCurve`] over the travel
+//! between them, and optionally a center detent that snaps the middle of
+//! the shaped travel to the middle level, for parameters with a natural
+//! neutral setting. The UI picks a response per controlled parameter.
+//!
+//! The end zones are what make both ends of every range reachable on a real
+//! pot, whose wiper rarely gets all the way to either rail: with
+//! [`KnobResponse::DEFAULT`], any pot whose travel covers 5% to 95% of full
+//! scale reaches level 0 and the top level, whatever the curve, and so the
+//! lowest and highest value of every parameter (see the
+//! [`mapping`](crate::mapping) module).
+//!
+//! ## Change Stream
+//!
+//! Besides polling with [`Knob::measure`], [`Knob::changes`] returns a
+//! [`KnobChanges`] stream whose [`KnobChanges::next`] future resolves only
+//! when the level differs from the last one yielded, so it composes with
+//! `select` alongside other events.
+//!
+//! ## Jitter Statistics
+//!
+//! [`Knob::stats`] samples the raw ADC reading over a window and returns
+//! its [`KnobStats`] (min, max, mean, standard deviation), quantifying the
+//! knob's noise before choosing a filter. The console's `KNOB STATS`
+//! command reports them.
+//!
+//! ## Startup Warm-up
+//!
+//! The first conversions after a cold boot can read garbage while the
+//! supply and the wiper filter settle. [`Knob::measure_settled`] drops the
+//! first [`KnobWarmup::discard`] readings and averages the next
+//! [`KnobWarmup::samples`], so the level seeded at startup (see
+//! [`KNOB_WARMUP`]) is the knob's real position.
+//!
+//! ## Recalibration
+//!
+//! The SAADC offset drifts with temperature and supply, and a calibration
+//! taken during a noisy moment would otherwise stick for good, so the knob
+//! recalibrates every [`KnobRecal::interval`] and on the console's `KNOB
+//! CAL`. It runs just before a sample, when no conversion is in flight
+//! (the knob owns the ADC). A calibration that doesn't finish within
+//! [`KnobRecal::timeout`] is logged and retried after
+//! [`KnobRecal::retry`]; sampling carries on with the ADC as it is.
+//!
+//! A fresh offset can move the reading by a few counts, which near a
+//! level boundary would look like a knob turn. After each calibration a
+//! [`RawHold`] keeps reporting the last reading until the knob moves more
+//! than [`KnobRecal::hold_margin`] counts away from it.
+//!
+//! ## Conversion Timeout
+//!
+//! A wedged SAADC, or a misrouted interrupt, would leave a conversion that
+//! never completes, and with it a dead UI task behind an LED that still
+//! shows the last color. Every scan is given [`KnobTimeout::timeout`]; one
+//! that runs over counts as an ADC error, is logged, and restarts the
+//! peripheral through [`KnobAdc::reinit`] and a recalibration, while the
+//! knob keeps reporting its last good reading so the UI loop carries on. [`SampleFaults`] backs
+//! off between attempts, doubling the wait from [`KnobTimeout::backoff`] up
+//! to [`KnobTimeout::backoff_max`], and after
+//! [`KnobTimeout::escalate_after`] timeouts in a row resets the chip, the
+//! only supervisor there is, with [`controlled_reset`], which shows the
+//! panic color first.
+//!
+//! ## Commit on Settle
+//!
+//! By default every new level the UI reads is applied at once, so turning
+//! the knob from one setting to another sweeps the LED through everything
+//! in between. With [`KNOB_COMMIT`] set to [`KnobCommit::OnSettle`], a
+//! [`SettleGate`] holds a new level back until the knob has rested on it
+//! for the settle window, and only that final level is applied. A knob
+//! that keeps flickering between two levels never settles, so the window
+//! should be longer than a deliberate pause but shorter than patience.
+//!
+//! ## Rate Limit
+//!
+//! A fast sweep across the whole travel jumps the LED from one extreme to
+//! the other in a single pass. With [`KNOB_MAX_RATE`] set, a
+//! [`RateLimiter`] moves the level the UI applies toward the knob by at
+//! most that many levels per second, so a flick of the knob becomes a
+//! steady ramp that carries on until it catches up. It limits the target
+//! itself, before anything is written to the shared state.
+//!
+//! ## Write Coalescing
+//!
+//! A knob resting on the boundary between two levels reads them in turn,
+//! and every flip would be written to the shared state and re-render the
+//! LED. A [`WriteCoalescer`] sits last in the UI's knob path: it drops a
+//! level equal to the one last written, and a flip straight back to the
+//! level written before that (one level away) is held until the knob has
+//! stayed on it for [`KNOB_DITHER_HOLD`]. A sweep never returns to the
+//! previous level, so it is written step by step with no delay. The
+//! dropped writes are counted in [`Stat::WritesDeduped`] and
+//! [`Stat::WritesHeld`].
+//!
+//! [`KnobPipeline`] chains the settle gate, rate limit and coalescer in
+//! that order, as the UI and the [`replay`](crate::replay) harness run them.
 use crate::*;
+use core::future::Future;
+
+/// Number of SAADC channels scanned per conversion.
+///
+/// The knob is always channel 0. With the `mic` feature the onboard
+/// microphone is scanned as channel 1 of the same conversion, so the two
+/// inputs never compete for the peripheral. With the `battery` feature the
+/// supply voltage is scanned as the last channel.
+pub const ADC_CHANNELS: usize =
+    1 + cfg!(feature = "mic") as usize + cfg!(feature = "battery") as usize;
+/// Scans averaged into every knob reading; 1 takes single scans.
+pub const KNOB_BURST: usize = 1;
+/// SAADC channel index of the knob input.
+const KNOB_CHANNEL: usize = 0;
+/// SAADC channel index of the microphone input.
+#[cfg(feature = "mic")]
+const MIC_CHANNEL: usize = 1;
+/// SAADC channel index of the VDD input.
+#[cfg(feature = "battery")]
+const VDD_CHANNEL: usize = ADC_CHANNELS - 1;
+
+/// The SAADC as [`Knob`] drives it, configured to read [`ADC_CHANNELS`]
+/// analog input channels per scan.
+///
+/// The board implements it for the real peripheral; tests drive a [`Knob`]
+/// through a stand-in.
+pub trait KnobAdc {
+    /// Calibrates the ADC's offset.
+    fn calibrate(&mut self) -> impl Future<Output = ()>;
+    /// Converts every channel once into `buf`.
+    fn sample(&mut self, buf: &mut [i16; ADC_CHANNELS]) -> impl Future<Output = ()>;
+    /// Converts every channel [`KNOB_BURST`] times back to back into
+    /// `burst`, as one transfer.
+    fn sample_burst(
+        &mut self,
+        burst: &mut [[i16; ADC_CHANNELS]; KNOB_BURST],
+    ) -> impl Future<Output = ()>;
+    /// Tears the driver down and sets the ADC up again after a conversion
+    /// timed out.
+    fn reinit(&mut self) -> impl Future<Output = ()>;
+    /// Resets the chip once the ADC is wedged.
+    fn reset(&mut self) -> !;
+}
+
+/// Delay between samples while waiting for a level change, in milliseconds.
+const KNOB_CHANGE_POLL_MS: u64 = 20;
+
+/// Raw 14-bit SAADC reading at the top of the knob travel (wiper at VDD).
+pub const KNOB_RAW_MAX: i16 = (1 << 14) - 1;
+/// Raw SAADC reading treated as the top of the knob travel.
+const KNOB_FULL_SCALE: f32 = KNOB_RAW_MAX as f32;
+/// Delay between samples taken by [`Knob::stats`], in milliseconds.
+const KNOB_STATS_INTERVAL_MS: u64 = 1;
+/// Default window for knob statistics, in samples.
+pub const KNOB_STATS_DEFAULT: u32 = 256;
+/// Largest window for knob statistics, in samples.
+pub const KNOB_STATS_MAX: u32 = 4096;
+/// Base of the [`ResponseCurve::Log`] taper; larger is more pronounced.
+const LOG_TAPER_BASE: f32 = 10.0;
+/// Half-width of the center detent, as a fraction of the shaped travel.
+pub const CENTER_SNAP_BAND: f32 = 0.08;
+
+/// Readings taken to settle the knob at startup.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct KnobWarmup {
+    /// Readings thrown away first.
+    pub discard: u32,
+    /// Readings averaged after those, at least 1.
+    pub samples: u32,
+}
+
+/// Warm-up used to seed the UI's startup level, taken
+/// [`KNOB_STATS_INTERVAL_MS`] apart: about 12 ms in all.
+pub const KNOB_WARMUP: KnobWarmup = KnobWarmup {
+    discard: 4,
+    samples: 8,
+};
+
+/// Knob recalibration settings.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct KnobRecal {
+    /// Time between calibrations; zero for on demand only.
+    pub interval: Duration,
+    /// Time before retrying a calibration that failed.
+    pub retry: Duration,
+    /// Longest a calibration may take before it counts as failed.
+    pub timeout: Duration,
+    /// Raw counts the reading must move from the last one before a
+    /// calibration to be reported.
+    pub hold_margin: i16,
+}
+
+/// When a knob level is applied.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum KnobCommit {
+    /// Apply every new level as it is read.
+    Immediate,
+    /// Apply a level only once the knob has rested on it this long.
+    OnSettle(Duration),
+}
+
+/// When the UI applies knob levels; see [`SettleGate`].
+pub const KNOB_COMMIT: KnobCommit = KnobCommit::Immediate;
+
+/// Fastest the UI moves a parameter after the knob, in knob levels per
+/// second, or `None` to follow the knob at once; see [`RateLimiter`].
+pub const KNOB_MAX_RATE: Option<u32> = None;
+
+/// How long the knob must stay on a level it just flipped back to before
+/// the UI writes it; see [`WriteCoalescer`].
+pub const KNOB_DITHER_HOLD: Duration = Duration::from_millis(200);
+
+/// Recalibration settings used by [`Knob`].
+pub const KNOB_RECAL: KnobRecal = KnobRecal {
+    interval: Duration::from_secs(5 * 60),
+    retry: Duration::from_secs(30),
+    timeout: Duration::from_millis(50),
+    hold_margin: 64,
+};
+
+/// SAADC conversion timeout settings.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct KnobTimeout {
+    /// Longest a conversion may take before it counts as failed.
+    pub timeout: Duration,
+    /// Wait before the first retry after a failure; doubled for each
+    /// further failure in a row.
+    pub backoff: Duration,
+    /// Longest wait between retries.
+    pub backoff_max: Duration,
+    /// Failures in a row after which the chip is reset.
+    pub escalate_after: u32,
+}
+
+/// Conversion timeout settings used by [`Knob`].
+pub const KNOB_TIMEOUT: KnobTimeout = KnobTimeout {
+    timeout: Duration::from_millis(250),
+    backoff: Duration::from_millis(100),
+    backoff_max: Duration::from_secs(2),
+    escalate_after: 5,
+};
+
+/// What to do about a conversion that timed out.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SampleFault {
+    /// Try to restart the ADC and carry on.
+    Recover,
+    /// Too many failures in a row: reset the chip.
+    Escalate,
+}
+
+/// Retry and escalation policy for timed-out conversions.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SampleFaults {
+    /// Wait before the first retry, in milliseconds.
+    backoff_ms: u64,
+    /// Longest wait between retries, in milliseconds.
+    backoff_max_ms: u64,
+    /// Failures in a row that escalate.
+    escalate_after: u32,
+    /// Failures in a row so far.
+    failures: u32,
+    /// When the next conversion may be tried, in milliseconds since boot.
+    next_ms: u64,
+}
+
+impl SampleFaults {
+    /// Creates a policy with no failures, ready at once.
+    pub const fn new(timeout: KnobTimeout) -> Self {
+        Self {
+            backoff_ms: timeout.backoff.as_millis(),
+            backoff_max_ms: timeout.backoff_max.as_millis(),
+            escalate_after: timeout.escalate_after,
+            failures: 0,
+            next_ms: 0,
+        }
+    }
+    /// Whether a conversion may be tried.
+    ///
+    /// # Arguments
+    /// * `now_ms` - Current time in milliseconds since boot
+    pub fn ready(&self, now_ms: u64) -> bool {
+        now_ms >= self.next_ms
+    }
+    /// Records a conversion that completed.
+    pub fn succeeded(&mut self) {
+        self.failures = 0;
+        self.next_ms = 0;
+    }
+    /// Records a conversion that timed out and schedules the next try.
+    ///
+    /// # Arguments
+    /// * `now_ms` - Current time in milliseconds since boot
+    pub fn failed(&mut self, now_ms: u64) -> SampleFault {
+        self.failures = self.failures.saturating_add(1);
+        let doublings = (self.failures - 1).min(16);
+        let wait = (self.backoff_ms << doublings).min(self.backoff_max_ms);
+        self.next_ms = now_ms.saturating_add(wait);
+        if self.failures >= self.escalate_after {
+            SampleFault::Escalate
+        } else {
+            SampleFault::Recover
+        }
+    }
+    /// Failures in a row so far.
+    pub fn failures(&self) -> u32 {
+        self.failures
+    }
+}
+
+/// When the next knob calibration is due.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RecalSchedule {
+    /// Time between calibrations in milliseconds, or 0 for none.
+    interval_ms: u64,
+    /// Retry delay in milliseconds.
+    retry_ms: u64,
+    /// When the next calibration is due, in milliseconds since boot.
+    next_ms: u64,
+}
+
+impl RecalSchedule {
+    /// Creates a schedule that is due at once.
+    pub const fn new(recal: KnobRecal) -> Self {
+        Self {
+            interval_ms: recal.interval.as_millis(),
+            retry_ms: recal.retry.as_millis(),
+            next_ms: 0,
+        }
+    }
+    /// Whether a calibration is due.
+    ///
+    /// # Arguments
+    /// * `now_ms` - Current time in milliseconds since boot
+    pub fn due(&self, now_ms: u64) -> bool {
+        // `u64::MAX` is never: on demand only, with nothing requested.
+        self.next_ms != u64::MAX && now_ms >= self.next_ms
+    }
+    /// Makes a calibration due at once.
+    pub fn request(&mut self) {
+        self.next_ms = 0;
+    }
+    /// Schedules the next calibration after one has run.
+    ///
+    /// # Arguments
+    /// * `now_ms` - Current time in milliseconds since boot
+    /// * `ok` - Whether the calibration completed
+    pub fn finished(&mut self, now_ms: u64, ok: bool) {
+        self.next_ms = match (ok, self.interval_ms) {
+            (false, _) => now_ms.saturating_add(self.retry_ms),
+            (true, 0) => u64::MAX,
+            (true, interval_ms) => now_ms.saturating_add(interval_ms),
+        };
+    }
+}
+
+/// Holds the knob reading steady across a calibration.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RawHold {
+    /// Reading to report while held.
+    held: Option<i16>,
+    /// Raw counts the reading must move to release the hold.
+    margin: i16,
+}
+
+impl RawHold {
+    /// Creates a released hold.
+    pub const fn new(margin: i16) -> Self {
+        Self { held: None, margin }
+    }
+    /// Starts holding a reading.
+    pub fn hold(&mut self, raw: i16) {
+        self.held = Some(raw);
+    }
+    /// Filters a new reading.
+    ///
+    /// # Returns
+    /// The held reading while the new one is within the margin of it,
+    /// else the new reading, releasing the hold.
+    pub fn apply(&mut self, raw: i16) -> i16 {
+        match self.held {
+            Some(held) if (raw as i32 - held as i32).abs() <= self.margin as i32 => held,
+            _ => {
+                self.held = None;
+                raw
+            }
+        }
+    }
+}
+
+/// Averages raw readings once a [`KnobWarmup`]'s discards have passed.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct KnobWarmupFilter {
+    warmup: KnobWarmup,
+    /// Readings pushed so far.
+    seen: u32,
+    /// Statistics of the readings kept.
+    stats: KnobStats,
+}
+
+impl KnobWarmupFilter {
+    /// Starts a warm-up with no readings.
+    pub fn new(warmup: KnobWarmup) -> Self {
+        Self {
+            warmup,
+            seen: 0,
+            stats: KnobStats::default(),
+        }
+    }
+    /// Adds one raw reading.
+    ///
+    /// # Returns
+    /// The averaged reading once the warm-up is complete, else `None`.
+    pub fn push(&mut self, raw: i16) -> Option<i16> {
+        let samples = self.warmup.samples.max(1);
+        if self.seen >= self.warmup.discard && self.stats.count < samples {
+            self.stats.push(raw);
+        }
+        self.seen = self.seen.saturating_add(1);
+        (self.stats.count == samples).then(|| num_traits::Float::round(self.stats.mean()) as i16)
+    }
+}
+
+/// Shape of the mapping from knob travel to level.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ResponseCurve {
+    /// Equal knob travel per level.
+    Linear,
+    /// Logarithmic taper: fine steps at the low end, coarse at the top, so
+    /// each stretch of travel covers a similar ratio of values.
+    Log,
+}
+
+/// Knob response settings for one controlled parameter.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct KnobResponse {
+    /// Curve applied after the dead zone.
+    pub curve: ResponseCurve,
+    /// Fraction of the travel at the bottom that reads as level 0.
+    pub deadzone: f32,
+    /// Fraction of the travel at the top that reads as level [`LEVELS`]-1.
+    pub topzone: f32,
+    /// Snap travel within [`CENTER_SNAP_BAND`] of the middle (after the
+    /// dead zone and curve) to the middle level, `LEVELS / 2`.
+    pub center_snap: bool,
+}
 
-/// Type alias for a single-channel SAADC configuration.
+impl KnobResponse {
+    /// Linear response with the knob's historical bottom dead zone and a
+    /// top dead zone over the last 6% of the travel.
+    pub const DEFAULT: Self = Self {
+        curve: ResponseCurve::Linear,
+        deadzone: 2.0 / (LEVELS + 2) as f32,
+        topzone: 0.06,
+        center_snap: false,
+    };
+    /// Maps a normalized knob position to a level.
+    ///
+    /// # Arguments
+    /// * `position` - Knob travel from 0.0 (bottom) to 1.0 (top)
+    ///
+    /// # Returns
+    /// A level from 0 to [`LEVELS`]-1.
+    pub fn level(&self, position: f32) -> u32 {
+        let span = (1.0 - self.deadzone - self.topzone).max(f32::EPSILON);
+        let travel = ((position - self.deadzone) / span).clamp(0.0, 1.0);
+        let shaped = match self.curve {
+            ResponseCurve::Linear => travel,
+            ResponseCurve::Log => {
+                (num_traits::Float::powf(LOG_TAPER_BASE, travel) - 1.0) / (LOG_TAPER_BASE - 1.0)
+            }
+        };
+        if self.center_snap && num_traits::Float::abs(shaped - 0.5) <= CENTER_SNAP_BAND {
+            return LEVELS / 2;
+        }
+        num_traits::Float::floor((LEVELS as f32 * shaped).clamp(0.0, (LEVELS - 1) as f32)) as u32
+    }
+}
+
+/// Converts a raw SAADC knob reading to a normalized position.
+///
+/// Negative readings (noise around ground) count as the bottom of the
+/// travel; readings above [`KNOB_FULL_SCALE`] land past 1.0 and are clamped
+/// by [`KnobResponse::level`].
+pub fn knob_position(raw: i16) -> f32 {
+    raw.max(0) as f32 / KNOB_FULL_SCALE
+}
+
+/// Maps a raw SAADC knob reading to a level, as [`Knob::measure_with`] does.
+///
+/// # Arguments
+/// * `raw` - Raw knob reading
+/// * `response` - Dead zone and curve to apply
+pub fn knob_level(raw: i16, response: KnobResponse) -> u32 {
+    response.level(knob_position(raw))
+}
+
+/// Averages a burst of scans, channel by channel.
+///
+/// # Arguments
+/// * `burst` - Scans of every channel, in the order taken
 ///
-/// Represents the SAADC peripheral configured to read from one analog input channel.
-pub type Adc = saadc::Saadc<'static, 1>;
+/// # Returns
+/// Each channel's mean, rounded to the nearest count (halves up); all
+/// zero for an empty burst.
+pub fn reduce_burst<const N: usize>(burst: &[[i16; ADC_CHANNELS]; N]) -> [i16; ADC_CHANNELS] {
+    let count = N.max(1) as i64;
+    let mut mean = [0; ADC_CHANNELS];
+    for (channel, mean) in mean.iter_mut().enumerate() {
+        let sum: i64 = burst.iter().map(|scan| scan[channel] as i64).sum();
+        *mean = (sum + count / 2).div_euclid(count) as i16;
+    }
+    mean
+}
+
+/// Statistics of raw knob readings over a window.
+///
+/// Accumulates in integers, so the mean and standard deviation are exact
+/// up to the final float conversion.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct KnobStats {
+    /// Number of samples.
+    pub count: u32,
+    /// Lowest reading; `i16::MAX` while empty.
+    pub min: i16,
+    /// Highest reading; `i16::MIN` while empty.
+    pub max: i16,
+    /// Sum of the readings.
+    sum: i64,
+    /// Sum of the squared readings.
+    sum_squares: i64,
+}
+
+impl Default for KnobStats {
+    fn default() -> Self {
+        Self {
+            count: 0,
+            min: i16::MAX,
+            max: i16::MIN,
+            sum: 0,
+            sum_squares: 0,
+        }
+    }
+}
+
+impl KnobStats {
+    /// Adds one raw reading.
+    pub fn push(&mut self, raw: i16) {
+        self.count = self.count.saturating_add(1);
+        self.min = self.min.min(raw);
+        self.max = self.max.max(raw);
+        self.sum = self.sum.saturating_add(raw as i64);
+        self.sum_squares = self.sum_squares.saturating_add(raw as i64 * raw as i64);
+    }
+    /// Mean reading, or 0 with no samples.
+    pub fn mean(&self) -> f32 {
+        if self.count == 0 {
+            return 0.0;
+        }
+        self.sum as f32 / self.count as f32
+    }
+    /// Population standard deviation, or 0 with no samples.
+    pub fn std_dev(&self) -> f32 {
+        if self.count == 0 {
+            return 0.0;
+        }
+        // n² · variance = n · Σx² - (Σx)², exact in integers.
+        let n = self.count as i128;
+        let scaled = n * self.sum_squares as i128 - self.sum as i128 * self.sum as i128;
+        num_traits::Float::sqrt(scaled as f32) / self.count as f32
+    }
+    /// Peak-to-peak spread in knob levels at full scale, a rough guide to
+    /// how often the noise alone can flip a level.
+    pub fn spread_levels(&self) -> f32 {
+        if self.count == 0 {
+            return 0.0;
+        }
+        (self.max as f32 - self.min as f32) * LEVELS as f32 / KNOB_FULL_SCALE
+    }
+}
+
+impl core::fmt::Display for KnobStats {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(
+            f,
+            "n={} min={} max={} mean={:.1} sd={:.2} (spread {:.2} levels)",
+            self.count,
+            self.min,
+            self.max,
+            self.mean(),
+            self.std_dev(),
+            self.spread_levels()
+        )
+    }
+}
+
+/// Computes the statistics of a set of raw knob readings.
+pub fn knob_stats(samples: &[i16]) -> KnobStats {
+    let mut stats = KnobStats::default();
+    for &raw in samples {
+        stats.push(raw);
+    }
+    stats
+}
+
+/// Change-detection gate for a stream of levels.
+///
+/// Passes a level through only when it differs from the last one passed;
+/// the first level always passes.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct LevelGate {
+    last: Option<u32>,
+}
+
+impl LevelGate {
+    /// Offers a sampled level to the gate.
+    ///
+    /// # Returns
+    /// `Some(level)` if it differs from the last level passed, else `None`.
+    pub fn accept(&mut self, level: u32) -> Option<u32> {
+        if self.last == Some(level) {
+            return None;
+        }
+        self.last = Some(level);
+        Some(level)
+    }
+}
+
+/// Write coalescer for the levels the UI applies.
+///
+/// Like [`LevelGate`] it drops a level equal to the last one written.
+/// Beyond that, a level one step from the last written that returns to the
+/// one written before it is a flip back, not a move: if it comes within
+/// the hold time of the last write, or while the level is already flipping
+/// back and forth, it is held until it has been read for the whole hold
+/// time. Any other level is written at once.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct WriteCoalescer {
+    /// How long a flip back must be read before it is written, in
+    /// milliseconds.
+    hold_ms: u64,
+    /// Level last written, and when.
+    written: Option<(u32, u64)>,
+    /// Level written before that.
+    previous: Option<u32>,
+    /// Flip back being held, and when it was first read.
+    held: Option<(u32, u64)>,
+    /// Whether a flip back has been held since the last write.
+    dithering: bool,
+    /// Level last offered, to count each dropped write once.
+    last: Option<u32>,
+}
+
+impl WriteCoalescer {
+    /// Creates a coalescer that has written nothing yet.
+    ///
+    /// # Arguments
+    /// * `hold` - How long a flip back must be read before it is written
+    pub const fn new(hold: Duration) -> Self {
+        Self {
+            hold_ms: hold.as_millis(),
+            written: None,
+            previous: None,
+            held: None,
+            dithering: false,
+            last: None,
+        }
+    }
+
+    /// Offers a level to write.
+    ///
+    /// # Arguments
+    /// * `now_ms` - Current time in milliseconds since boot
+    /// * `level` - Level to write
+    ///
+    /// # Returns
+    /// `Some(level)` if it should be written now, else `None`.
+    pub fn push(&mut self, now_ms: u64, level: u32) -> Option<u32> {
+        let changed = self.last.replace(level) != Some(level);
+        let Some((written, written_ms)) = self.written else {
+            return Some(self.write(now_ms, level));
+        };
+        if level == written {
+            self.held = None;
+            if changed {
+                count_stat!(WritesDeduped);
+            }
+            return None;
+        }
+        let flip_back = self.previous == Some(level) && level.abs_diff(written) == 1;
+        if flip_back && (self.dithering || now_ms.saturating_sub(written_ms) < self.hold_ms) {
+            self.dithering = true;
+            let since = match self.held {
+                Some((held, since)) if held == level => since,
+                _ => now_ms,
+            };
+            self.held = Some((level, since));
+            if now_ms.saturating_sub(since) < self.hold_ms {
+                if changed {
+                    count_stat!(WritesHeld);
+                }
+                return None;
+            }
+        }
+        Some(self.write(now_ms, level))
+    }
+
+    fn write(&mut self, now_ms: u64, level: u32) -> u32 {
+        self.previous = self.written.map(|(written, _)| written);
+        self.written = Some((level, now_ms));
+        self.held = None;
+        self.dithering = false;
+        level
+    }
+}
+
+/// Settle detector for [`KnobCommit::OnSettle`].
+///
+/// Tracks when the sampled level last changed and passes it through once
+/// it has held for the settle window.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SettleGate {
+    /// Settle window in milliseconds.
+    window_ms: u64,
+    /// Level last sampled and when it was first seen.
+    last: Option<(u32, u64)>,
+    /// Whether the last level has been committed.
+    committed: bool,
+}
+
+impl SettleGate {
+    /// Creates a gate that has seen no level yet.
+    ///
+    /// # Arguments
+    /// * `window` - How long a level must hold before it is committed
+    pub const fn new(window: Duration) -> Self {
+        Self {
+            window_ms: window.as_millis(),
+            last: None,
+            committed: false,
+        }
+    }
+
+    /// Offers a sampled level to the gate.
+    ///
+    /// # Arguments
+    /// * `now_ms` - Time of the sample in milliseconds since boot
+    /// * `level` - Sampled level
+    ///
+    /// # Returns
+    /// `Some(level)` on the first sample at which the level has held for
+    /// the window, else `None`.
+    pub fn push(&mut self, now_ms: u64, level: u32) -> Option<u32> {
+        let since = match self.last {
+            Some((last, since)) if last == level => since,
+            _ => {
+                self.last = Some((level, now_ms));
+                self.committed = false;
+                now_ms
+            }
+        };
+        if self.committed || now_ms.saturating_sub(since) < self.window_ms {
+            return None;
+        }
+        self.committed = true;
+        Some(level)
+    }
+}
+
+/// Slew-rate limit on a stream of levels, for [`KNOB_MAX_RATE`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RateLimiter {
+    /// Levels per second.
+    max_rate: u32,
+    /// Level last passed on.
+    value: Option<u32>,
+    /// Level being ramped toward.
+    target: Option<u32>,
+    /// Time up to which the ramp has been accounted for, in milliseconds.
+    at_ms: u64,
+}
+
+impl RateLimiter {
+    /// Creates a limiter with no level yet.
+    ///
+    /// # Arguments
+    /// * `max_rate` - Most levels to move per second, at least 1
+    pub const fn new(max_rate: u32) -> Self {
+        Self {
+            max_rate: if max_rate == 0 { 1 } else { max_rate },
+            value: None,
+            target: None,
+            at_ms: 0,
+        }
+    }
+
+    /// Starts the ramp from `value`, with no target yet.
+    ///
+    /// # Arguments
+    /// * `now_ms` - Current time in milliseconds since boot
+    /// * `value` - Level the output is at now
+    pub fn reset(&mut self, now_ms: u64, value: u32) {
+        self.value = Some(value);
+        self.target = None;
+        self.at_ms = now_ms;
+    }
+
+    /// Moves toward the target as far as the rate allows.
+    ///
+    /// # Arguments
+    /// * `now_ms` - Current time in milliseconds since boot
+    /// * `target` - New target level, or `None` to keep the last one
+    ///
+    /// # Returns
+    /// The level to apply, or `None` before the first target.
+    pub fn step(&mut self, now_ms: u64, target: Option<u32>) -> Option<u32> {
+        if target.is_some() {
+            self.target = target;
+        }
+        let target = self.target?;
+        let Some(value) = self.value else {
+            self.value = Some(target);
+            self.at_ms = now_ms;
+            return Some(target);
+        };
+        let distance = value.abs_diff(target) as u64;
+        let allowed = now_ms.saturating_sub(self.at_ms) * self.max_rate as u64 / 1000;
+        let moved = allowed.min(distance);
+        if moved == distance {
+            self.at_ms = now_ms;
+        } else {
+            // Keep the fraction of a level already earned for next time.
+            self.at_ms += moved * 1000 / self.max_rate as u64;
+        }
+        let moved = moved as u32;
+        let value = if target > value {
+            value + moved
+        } else {
+            value - moved
+        };
+        self.value = Some(value);
+        Some(value)
+    }
+}
+
+/// The UI's knob path from level to write: a [`SettleGate`] with
+/// [`KnobCommit::OnSettle`], then a [`RateLimiter`] with [`KNOB_MAX_RATE`],
+/// then a [`WriteCoalescer`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct KnobPipeline {
+    /// Holds levels back until they settle, with [`KnobCommit::OnSettle`].
+    settle: Option<SettleGate>,
+    /// Limits how fast levels move, with [`KNOB_MAX_RATE`].
+    rate: Option<RateLimiter>,
+    /// Drops repeated levels and holds back dithering.
+    gate: WriteCoalescer,
+}
+
+impl KnobPipeline {
+    /// Creates the path with nothing written yet.
+    pub fn new() -> Self {
+        Self {
+            settle: match KNOB_COMMIT {
+                KnobCommit::Immediate => None,
+                KnobCommit::OnSettle(window) => Some(SettleGate::new(window)),
+            },
+            rate: KNOB_MAX_RATE.map(RateLimiter::new),
+            gate: WriteCoalescer::new(KNOB_DITHER_HOLD),
+        }
+    }
+
+    /// Starts over for a newly selected parameter.
+    ///
+    /// # Arguments
+    /// * `now_ms` - Current time in milliseconds since boot
+    /// * `current` - Level the parameter is at now, where a rate limit ramps
+    ///   from
+    pub fn reset(&mut self, now_ms: u64, current: u32) {
+        let rate = self.rate;
+        *self = Self::new();
+        if let Some(mut rate) = rate {
+            rate.reset(now_ms, current);
+            self.rate = Some(rate);
+        }
+    }
+
+    /// Offers a level read from the knob.
+    ///
+    /// # Arguments
+    /// * `now_ms` - Current time in milliseconds since boot
+    /// * `level` - Level read this pass
+    ///
+    /// # Returns
+    /// The level to write now, if any.
+    pub fn push(&mut self, now_ms: u64, level: u32) -> Option<u32> {
+        let committed = match self.settle.as_mut() {
+            Some(settle) => settle.push(now_ms, level),
+            None => Some(level),
+        };
+        let committed = match self.rate.as_mut() {
+            Some(rate) => rate.step(now_ms, committed),
+            None => committed,
+        };
+        committed.and_then(|level| self.gate.push(now_ms, level))
+    }
+}
+
+impl Default for KnobPipeline {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Stream of distinct knob levels, created by [`Knob::changes`].
+pub struct KnobChanges<'a, A: KnobAdc> {
+    knob: &'a mut Knob<A>,
+    response: KnobResponse,
+    gate: LevelGate,
+}
+
+impl<A: KnobAdc> KnobChanges<'_, A> {
+    /// Waits for the next distinct level.
+    ///
+    /// The first call resolves with the current level. Each later call
+    /// samples every [`KNOB_CHANGE_POLL_MS`] until the level changes.
+    pub async fn next(&mut self) -> u32 {
+        loop {
+            let level = self.knob.measure_with(self.response).await;
+            if let Some(level) = self.gate.accept(level) {
+                return level;
+            }
+            Timer::after_millis(KNOB_CHANGE_POLL_MS).await;
+        }
+    }
+}
 
 /// Analog knob controller that converts ADC readings to discrete levels.
 ///
-/// Wraps the SAADC peripheral to provide convenient analog input reading
-/// with automatic calibration and conversion to discrete level values.
-pub struct Knob(Adc);
-impl Knob {
+/// Wraps the SAADC peripheral (any [`KnobAdc`]) to provide convenient
+/// analog input reading with automatic calibration and conversion to
+/// discrete level values.
+pub struct Knob<A: KnobAdc> {
+    adc: A,
+    /// When the next calibration is due.
+    recal: RecalSchedule,
+    /// Steadies the reading across a calibration.
+    hold: RawHold,
+    /// Last reading from the ADC.
+    last_raw: Option<i16>,
+    /// Timed-out conversions and when to retry.
+    faults: SampleFaults,
+    /// Last microphone reading, reported while the ADC is failing.
+    #[cfg(feature = "mic")]
+    last_mic: i16,
+}
+impl<A: KnobAdc> Knob<A> {
     /// Creates a new knob controller and calibrates the ADC.
     ///
     /// # Arguments
@@ -27,22 +989,53 @@ impl Knob {
     /// # Examples
     ///
     /// ```rust,no_run
-    /// let adc = saadc::Saadc::new(
-    ///     board.saadc,
-    ///     Irqs,
-    ///     saadc_config,
-    ///     [saadc::ChannelConfig::single_ended(board.p2)],
-    /// );
+    /// # use mb2_embassy_rgb::*;
+    /// # async fn example(adc: impl KnobAdc) {
+    /// // On the board, `adc` wraps the SAADC sampling the knob on P2 (see
+    /// // `src/main.rs`).
     /// let knob = Knob::new(adc).await;
+    /// # }
     /// ```
-    pub async fn new(adc: Adc) -> Self {
-        adc.calibrate().await;
-        Self(adc)
+    pub async fn new(adc: A) -> Self {
+        let mut knob = Self {
+            adc,
+            recal: RecalSchedule::new(KNOB_RECAL),
+            hold: RawHold::new(KNOB_RECAL.hold_margin),
+            last_raw: None,
+            faults: SampleFaults::new(KNOB_TIMEOUT),
+            #[cfg(feature = "mic")]
+            last_mic: 0,
+        };
+        knob.recalibrate().await;
+        knob
+    }
+    /// Calibrates the ADC now, holding the reading steady across it.
+    ///
+    /// # Returns
+    ///
+    /// `false` if the calibration timed out; the ADC keeps its previous
+    /// calibration and another attempt is scheduled.
+    pub async fn recalibrate(&mut self) -> bool {
+        let done = embassy_time::with_timeout(KNOB_RECAL.timeout, self.adc.calibrate()).await;
+        let ok = done.is_ok();
+        if ok {
+            if let Some(raw) = self.last_raw {
+                self.hold.hold(raw);
+            }
+        } else {
+            count_stat!(AdcErrors);
+            rprintln!(
+                "knob: calibration timed out, keeping the previous one, retrying in {} s",
+                KNOB_RECAL.retry.as_secs()
+            );
+        }
+        self.recal.finished(Instant::now().as_millis(), ok);
+        ok
     }
     /// Reads the knob position and converts it to a discrete level.
     ///
-    /// Samples the ADC and maps the result to a discrete level from 0 to [`LEVELS`]-1.
-    /// The mapping includes a small offset to ensure the full range is reachable.
+    /// Samples the ADC and maps the result to a discrete level from 0 to [`LEVELS`]-1
+    /// using [`KnobResponse::DEFAULT`].
     ///
     /// # Returns
     ///
@@ -53,18 +1046,615 @@ impl Knob {
     /// # Examples
     ///
     /// ```rust,no_run
+    /// # use mb2_embassy_rgb::*;
+    /// # async fn example(mut knob: Knob<impl KnobAdc>) {
     /// let level = knob.measure().await;
     /// // With LEVELS=16, level will be 0-15
-    /// println!("Knob at level: {}", level);
+    /// rprintln!("Knob at level: {}", level);
+    /// # }
     /// ```
     pub async fn measure(&mut self) -> u32 {
-        let mut buf = [0];
-        self.0.sample(&mut buf).await;
-        let raw = buf[0].clamp(0, 0x7fff) as u16;
-        let scaled = raw as f32 / 10_000.0;
-        let result = ((LEVELS + 2) as f32 * scaled - 2.0)
-            .clamp(0.0, (LEVELS - 1) as f32)
-            .floor();
-        result as u32
+        self.measure_with(KnobResponse::DEFAULT).await
+    }
+    /// Reads the knob position and maps it through a response curve.
+    ///
+    /// # Arguments
+    ///
+    /// * `response` - Dead zone and curve to apply
+    ///
+    /// # Returns
+    ///
+    /// A value from 0 to ([`LEVELS`]-1).
+    pub async fn measure_with(&mut self, response: KnobResponse) -> u32 {
+        knob_level(self.sample_raw().await, response)
+    }
+    /// Reads the knob position after a warm-up, for a level that has to be
+    /// right first time.
+    ///
+    /// # Arguments
+    ///
+    /// * `response` - Dead zone and curve to apply
+    /// * `warmup` - Readings to discard and to average
+    ///
+    /// # Returns
+    ///
+    /// A value from 0 to ([`LEVELS`]-1).
+    pub async fn measure_settled(&mut self, response: KnobResponse, warmup: KnobWarmup) -> u32 {
+        let mut filter = KnobWarmupFilter::new(warmup);
+        loop {
+            if let Some(raw) = filter.push(self.sample_raw().await) {
+                return knob_level(raw, response);
+            }
+            Timer::after_millis(KNOB_STATS_INTERVAL_MS).await;
+        }
+    }
+    /// Takes one raw knob reading, the mean of a [`KNOB_BURST`] of scans.
+    ///
+    /// # Returns
+    ///
+    /// The raw SAADC reading (or the injected one, with `fault-inject`).
+    pub async fn sample_raw(&mut self) -> i16 {
+        if self.recal.due(Instant::now().as_millis()) {
+            self.recalibrate().await;
+        }
+        let mut burst = [[0; ADC_CHANNELS]; KNOB_BURST];
+        if !self.scan_burst(&mut burst).await {
+            // Keep the UI going on the last good reading.
+            return self.last_raw.unwrap_or(0);
+        }
+        let buf = reduce_burst(&burst);
+        let raw = self.hold.apply(buf[KNOB_CHANNEL]);
+        self.last_raw = Some(raw);
+        #[cfg(feature = "battery")]
+        record_vdd_sample(buf[VDD_CHANNEL]).await;
+        count_stat!(KnobReadings);
+        if !(0..=KNOB_RAW_MAX).contains(&raw) {
+            count_stat!(KnobClamped);
+        }
+        #[cfg(all(feature = "fault-inject", debug_assertions))]
+        let raw = injected_knob_raw().await.unwrap_or(raw);
+        #[cfg(feature = "telemetry")]
+        record_knob_sample(raw).await;
+        raw
+    }
+    /// Collects raw-reading statistics over a window of samples.
+    ///
+    /// Hold the knob still while this runs: any movement shows up as noise.
+    ///
+    /// # Arguments
+    ///
+    /// * `count` - Number of samples, taken [`KNOB_STATS_INTERVAL_MS`] apart
+    pub async fn stats(&mut self, count: u32) -> KnobStats {
+        let mut stats = KnobStats::default();
+        for _ in 0..count {
+            stats.push(self.sample_raw().await);
+            Timer::after_millis(KNOB_STATS_INTERVAL_MS).await;
+        }
+        stats
+    }
+    /// Returns a stream of distinct levels through a response curve.
+    ///
+    /// # Arguments
+    ///
+    /// * `response` - Dead zone and curve to apply
+    pub fn changes(&mut self, response: KnobResponse) -> KnobChanges<'_, A> {
+        KnobChanges {
+            knob: self,
+            response,
+            gate: LevelGate::default(),
+        }
+    }
+    /// Takes a raw microphone sample from the shared SAADC scan.
+    ///
+    /// The knob channel is converted in the same scan and discarded, which
+    /// keeps the peripheral configuration fixed for both users.
+    ///
+    /// # Returns
+    ///
+    /// The raw 14-bit microphone reading, still riding on the mic bias voltage.
+    #[cfg(feature = "mic")]
+    pub async fn sample_mic(&mut self) -> i16 {
+        let mut buf = [0; ADC_CHANNELS];
+        if self.scan(&mut buf).await {
+            self.last_mic = buf[MIC_CHANNEL];
+        }
+        self.last_mic
+    }
+    /// Takes a burst of [`KNOB_BURST`] scans in one transfer, giving up
+    /// after [`KnobTimeout::timeout`].
+    ///
+    /// # Returns
+    ///
+    /// `false` if the transfer timed out, or was skipped while backing off
+    /// after one; `burst` may then be only partly filled.
+    async fn scan_burst(&mut self, burst: &mut [[i16; ADC_CHANNELS]; KNOB_BURST]) -> bool {
+        let now_ms = Instant::now().as_millis();
+        if !self.faults.ready(now_ms) {
+            return false;
+        }
+        let done =
+            embassy_time::with_timeout(KNOB_TIMEOUT.timeout, self.adc.sample_burst(burst)).await;
+        self.conversion_finished(done.is_ok()).await
+    }
+    /// Runs one SAADC scan, giving up after [`KnobTimeout::timeout`].
+    ///
+    /// # Returns
+    ///
+    /// `false` if the scan timed out, or was skipped while backing off
+    /// after one; `buf` is then left as it was.
+    #[cfg(feature = "mic")]
+    async fn scan(&mut self, buf: &mut [i16; ADC_CHANNELS]) -> bool {
+        let now_ms = Instant::now().as_millis();
+        if !self.faults.ready(now_ms) {
+            return false;
+        }
+        let done = embassy_time::with_timeout(KNOB_TIMEOUT.timeout, self.adc.sample(buf)).await;
+        self.conversion_finished(done.is_ok()).await
+    }
+    /// Records how a conversion ended.
+    ///
+    /// The backoff after a timeout runs from when the timeout was noticed:
+    /// counted from the start of the conversion, the timeout itself would
+    /// use up the first backoff. A timeout is logged and recovered from by rebuilding the driver with
+    /// [`KnobAdc::reinit`] and recalibrating, or resets the chip through
+    /// [`KnobAdc::reset`] once [`SampleFaults`] escalates.
+    ///
+    /// # Returns
+    ///
+    /// `done`, whether the conversion completed.
+    async fn conversion_finished(&mut self, done: bool) -> bool {
+        if done {
+            self.faults.succeeded();
+            return true;
+        }
+        count_stat!(AdcErrors);
+        match self.faults.failed(Instant::now().as_millis()) {
+            SampleFault::Recover => {
+                rprintln!(
+                    "knob: conversion timed out ({} in a row), restarting the ADC",
+                    self.faults.failures()
+                );
+                self.adc.reinit().await;
+                self.recalibrate().await;
+            }
+            SampleFault::Escalate => {
+                rprintln!(
+                    "knob: ADC wedged after {} timeouts in a row, resetting",
+                    self.faults.failures()
+                );
+                // Give the console a moment to drain first.
+                Timer::after_millis(100).await;
+                self.adc.reset();
+            }
+        }
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use embassy_futures::block_on;
+
+    #[test]
+    fn sample_faults_back_off_then_escalate() {
+        let mut faults = SampleFaults::new(KnobTimeout {
+            backoff: Duration::from_millis(100),
+            backoff_max: Duration::from_millis(300),
+            escalate_after: 4,
+            ..KNOB_TIMEOUT
+        });
+        assert!(faults.ready(0));
+        // Each failure in a row waits twice as long before the next try, up to
+        // the limit...
+        assert_eq!(faults.failed(0), SampleFault::Recover);
+        assert!(!faults.ready(99));
+        assert!(faults.ready(100));
+        assert_eq!(faults.failed(100), SampleFault::Recover);
+        assert!(!faults.ready(299));
+        assert!(faults.ready(300));
+        assert_eq!(faults.failed(300), SampleFault::Recover);
+        assert!(!faults.ready(599));
+        assert!(faults.ready(600));
+        // ...until one too many escalates.
+        assert_eq!(faults.failed(600), SampleFault::Escalate);
+
+        // A conversion that completes clears the count.
+        let mut faults = SampleFaults::new(KNOB_TIMEOUT);
+        for now_ms in [0, 1000, 2000] {
+            assert_eq!(faults.failed(now_ms), SampleFault::Recover);
+        }
+        faults.succeeded();
+        assert_eq!(faults.failures(), 0);
+        assert!(faults.ready(2001));
+        assert_eq!(faults.failed(2001), SampleFault::Recover);
+    }
+
+    #[test]
+    fn recal_schedule_retries_sooner_after_a_failure() {
+        let mut schedule = RecalSchedule::new(KnobRecal {
+            interval: Duration::from_secs(300),
+            retry: Duration::from_secs(30),
+            ..KNOB_RECAL
+        });
+        schedule.finished(0, true);
+        assert!(!schedule.due(299_999));
+        assert!(schedule.due(300_000));
+        // A failure is retried sooner.
+        schedule.finished(300_000, false);
+        assert!(!schedule.due(329_999));
+        assert!(schedule.due(330_000));
+        // Success goes back to the full interval.
+        schedule.finished(330_000, true);
+        assert!(!schedule.due(629_999));
+        // A request makes it due at once.
+        schedule.request();
+        assert!(schedule.due(330_001));
+
+        // With no interval, only requests and retries make it due.
+        let mut schedule = RecalSchedule::new(KnobRecal {
+            interval: Duration::from_secs(0),
+            ..KNOB_RECAL
+        });
+        schedule.finished(0, true);
+        assert!(!schedule.due(u64::MAX));
+        schedule.finished(0, false);
+        assert!(schedule.due(KNOB_RECAL.retry.as_millis()));
+    }
+
+    #[test]
+    fn raw_hold_steadies_the_reading_across_a_calibration() {
+        let mut hold = RawHold::new(64);
+        assert_eq!(hold.apply(8000), 8000);
+        // After a calibration, small shifts report the last reading...
+        hold.hold(8000);
+        assert_eq!(hold.apply(8030), 8000);
+        assert_eq!(hold.apply(7936), 8000);
+        // ...until the knob really moves, which releases the hold for good.
+        assert_eq!(hold.apply(8065), 8065);
+        assert_eq!(hold.apply(8030), 8030);
+
+        // The held reading maps to the same level, so the UI sees no change.
+        let response = KnobResponse::DEFAULT;
+        let boundary = (KNOB_RAW_MAX as i32 / LEVELS as i32 * 8) as i16;
+        let before = boundary - 10;
+        hold.hold(before);
+        let after = hold.apply(boundary + 10);
+        assert_eq!(knob_level(after, response), knob_level(before, response));
+    }
+
+    #[test]
+    fn warmup_filter_discards_then_averages() {
+        let warmup = KnobWarmup {
+            discard: 2,
+            samples: 4,
+        };
+        let mut filter = KnobWarmupFilter::new(warmup);
+        // Power-up garbage is dropped...
+        assert_eq!(filter.push(KNOB_RAW_MAX), None);
+        assert_eq!(filter.push(-300), None);
+        // ...and the settled readings are averaged, rounding to nearest.
+        assert_eq!(filter.push(8000), None);
+        assert_eq!(filter.push(8004), None);
+        assert_eq!(filter.push(8001), None);
+        assert_eq!(filter.push(8002), Some(8002));
+        // Further readings don't change the result.
+        assert_eq!(filter.push(0), Some(8002));
+
+        // No discards and one sample is the plain first reading.
+        let mut filter = KnobWarmupFilter::new(KnobWarmup {
+            discard: 0,
+            samples: 1,
+        });
+        assert_eq!(filter.push(1234), Some(1234));
+        // Zero samples still averages one.
+        let mut filter = KnobWarmupFilter::new(KnobWarmup {
+            discard: 1,
+            samples: 0,
+        });
+        assert_eq!(filter.push(0), None);
+        assert_eq!(filter.push(42), Some(42));
+
+        // A glitch at the first reading no longer moves the startup level.
+        let response = KnobResponse::DEFAULT;
+        let mut filter = KnobWarmupFilter::new(KNOB_WARMUP);
+        let mut settled = None;
+        for n in 0..KNOB_WARMUP.discard + KNOB_WARMUP.samples {
+            settled = filter.push(if n == 0 { KNOB_RAW_MAX } else { 0 });
+        }
+        assert_eq!(settled.map(|raw| knob_level(raw, response)), Some(0));
+    }
+
+    #[test]
+    fn center_snap_and_out_of_range_positions() {
+        let response = KnobResponse {
+            deadzone: 0.0,
+            topzone: 0.0,
+            center_snap: true,
+            ..KnobResponse::DEFAULT
+        };
+        // Just either side of the middle snaps to it...
+        assert_eq!(response.level(0.45), LEVELS / 2);
+        assert_eq!(response.level(0.55), LEVELS / 2);
+        // ...which without the detent would be two different levels.
+        let plain = KnobResponse {
+            center_snap: false,
+            ..response
+        };
+        assert_eq!(plain.level(0.45), 7);
+        assert_eq!(plain.level(0.55), 8);
+        // Outside the band the knob reads as usual.
+        assert_eq!(response.level(0.25), 4);
+        assert_eq!(response.level(0.75), 12);
+        // Positions off either end (or not a number) stay in range.
+        assert_eq!(KnobResponse::DEFAULT.level(7.5), LEVELS - 1);
+        assert_eq!(KnobResponse::DEFAULT.level(-2.0), 0);
+        assert_eq!(KnobResponse::DEFAULT.level(f32::NAN), 0);
+    }
+
+    /// Stand-in SAADC that reads a fixed knob position.
+    #[derive(Default)]
+    struct MockAdc {
+        raw: i16,
+        /// Bursts transferred so far.
+        bursts: u32,
+        /// Whether bursts never complete, like a wedged SAADC.
+        hung: bool,
+        /// Times the driver was rebuilt.
+        reinits: u32,
+    }
+
+    impl KnobAdc for MockAdc {
+        async fn calibrate(&mut self) {}
+        async fn sample(&mut self, buf: &mut [i16; ADC_CHANNELS]) {
+            buf[KNOB_CHANNEL] = self.raw;
+        }
+        async fn sample_burst(&mut self, burst: &mut [[i16; ADC_CHANNELS]; KNOB_BURST]) {
+            self.bursts += 1;
+            if self.hung {
+                core::future::pending::<()>().await;
+            }
+            for scan in burst.iter_mut() {
+                scan[KNOB_CHANNEL] = self.raw;
+            }
+        }
+        async fn reinit(&mut self) {
+            self.reinits += 1;
+        }
+        fn reset(&mut self) -> ! {
+            panic!("the mock ADC is never reset");
+        }
+    }
+
+    #[test]
+    fn every_raw_reading_maps_to_an_equal_band() {
+        // Sweep every 14-bit reading through the knob: the level only ever
+        // steps up by one, so each level is a single non-empty band...
+        let mut knob = block_on(Knob::new(MockAdc::default()));
+        let mut measure = |raw| {
+            knob.adc.raw = raw;
+            block_on(knob.measure())
+        };
+        let mut first = [0; LEVELS as usize];
+        let mut last = [0; LEVELS as usize];
+        let mut previous = 0;
+        for raw in 0..=KNOB_RAW_MAX {
+            let level = measure(raw);
+            assert!(level == previous || level == previous + 1);
+            if level != previous || raw == 0 {
+                first[level as usize] = raw;
+            }
+            last[level as usize] = raw;
+            previous = level;
+        }
+        // ...both ends of the travel reach the end levels...
+        assert_eq!(measure(0), 0);
+        assert_eq!(measure(KNOB_RAW_MAX), LEVELS - 1);
+        // ...and between level 0 and the top level, which include the dead
+        // zones, the bands are equal to within one count.
+        let mut widths = (1..LEVELS as usize - 1).map(|level| last[level] - first[level] + 1);
+        let first_width = widths.next().unwrap();
+        let (narrowest, widest) = widths.fold((first_width, first_width), |(lo, hi), width| {
+            (lo.min(width), hi.max(width))
+        });
+        assert!(widest - narrowest <= 1);
+    }
+
+    #[test]
+    fn every_reading_is_one_burst() {
+        let mut knob = block_on(Knob::new(MockAdc {
+            raw: 8575,
+            ..MockAdc::default()
+        }));
+        for reading in 1..=5 {
+            assert_eq!(block_on(knob.sample_raw()), 8575);
+            assert_eq!(knob.adc.bursts, reading);
+        }
+    }
+
+    #[test]
+    fn a_hung_conversion_keeps_the_last_reading() {
+        let mut knob = block_on(Knob::new(MockAdc {
+            raw: 8575,
+            ..MockAdc::default()
+        }));
+        assert_eq!(block_on(knob.sample_raw()), 8575);
+
+        // The burst never completes: after the timeout the knob reports the
+        // last good reading and rebuilds the driver.
+        knob.adc.hung = true;
+        knob.adc.raw = 100;
+        assert_eq!(block_on(knob.sample_raw()), 8575);
+        assert_eq!((knob.adc.bursts, knob.adc.reinits), (2, 1));
+        assert_eq!(knob.faults.failures(), 1);
+
+        // Inside the backoff the ADC is left alone.
+        assert!(!knob.faults.ready(Instant::now().as_millis()));
+        assert_eq!(block_on(knob.sample_raw()), 8575);
+        assert_eq!((knob.adc.bursts, knob.adc.reinits), (2, 1));
+
+        // Once it has passed, the rebuilt ADC is read again.
+        knob.adc.hung = false;
+        block_on(Timer::after(KNOB_TIMEOUT.backoff));
+        assert_eq!(block_on(knob.sample_raw()), 100);
+        assert_eq!(knob.adc.bursts, 3);
+        assert_eq!(knob.faults.failures(), 0);
+    }
+
+    #[test]
+    fn reduce_burst_rounds_the_mean() {
+        // A noisy burst straddling the boundary between levels 7 and 8: half
+        // the scans alone would read 8, but their mean reads 7.
+        let burst = [8500, 8700, 8450, 8650].map(|raw| [raw; ADC_CHANNELS]);
+        assert_eq!(knob_level(8700, KnobResponse::DEFAULT), 8);
+        let mean = reduce_burst(&burst);
+        assert_eq!(mean, [8575; ADC_CHANNELS]);
+        assert_eq!(knob_level(mean[0], KnobResponse::DEFAULT), 7);
+        // Rounding is to the nearest count, below zero too.
+        assert_eq!(
+            reduce_burst(&[[1; ADC_CHANNELS], [2; ADC_CHANNELS]]),
+            [2; ADC_CHANNELS]
+        );
+        assert_eq!(
+            reduce_burst(&[[-3; ADC_CHANNELS], [-2; ADC_CHANNELS]]),
+            [-2; ADC_CHANNELS]
+        );
+        // A burst of one is the scan itself, even at the ends of the range.
+        assert_eq!(
+            reduce_burst(&[[i16::MIN; ADC_CHANNELS]]),
+            [i16::MIN; ADC_CHANNELS]
+        );
+        assert_eq!(
+            reduce_burst(&[[KNOB_RAW_MAX; ADC_CHANNELS]; 64]),
+            [KNOB_RAW_MAX; ADC_CHANNELS]
+        );
+    }
+
+    #[test]
+    fn knob_stats_mean_and_jitter() {
+        let stats = knob_stats(&[2, 4, 4, 4, 5, 5, 7, 9]);
+        assert_eq!((stats.min, stats.max), (2, 9));
+        assert_eq!(stats.mean(), 5.0);
+        assert_eq!(stats.std_dev(), 2.0);
+        // A steady knob has no jitter.
+        assert_eq!(knob_stats(&[5000; 16]).std_dev(), 0.0);
+    }
+
+    #[test]
+    fn level_gate_drops_repeats() {
+        let mut gate = LevelGate::default();
+        assert_eq!(gate.accept(3), Some(3));
+        assert_eq!(gate.accept(3), None);
+        assert_eq!(gate.accept(4), Some(4));
+    }
+
+    #[test]
+    fn write_coalescer_holds_flips_back() {
+        let hold = Duration::from_millis(200);
+
+        // A knob dithering between 4 and 5 every 20 ms: the move to 4 is
+        // written, and every flip after it is dropped.
+        let mut dither = WriteCoalescer::new(hold);
+        let mut writes = 0;
+        for step in 0..50u64 {
+            let level = if step % 2 == 0 { 5 } else { 4 };
+            if dither.push(step * 20, level).is_some() {
+                writes += 1;
+            }
+        }
+        assert_eq!(writes, 2);
+
+        // A sweep, even a fast one, writes every level when it is read.
+        let mut sweep = WriteCoalescer::new(hold);
+        for level in 0..16 {
+            assert_eq!(sweep.push(level as u64 * 5, level), Some(level));
+        }
+        for level in (0..16).rev().skip(2) {
+            assert_eq!(
+                sweep.push(1000 + (15 - level) as u64 * 5, level),
+                Some(level)
+            );
+        }
+
+        // A flip back that stays put is written once it has held.
+        let mut rest = WriteCoalescer::new(hold);
+        assert_eq!(rest.push(0, 7), Some(7));
+        assert_eq!(rest.push(10, 8), Some(8));
+        assert_eq!(rest.push(20, 7), None);
+        assert_eq!(rest.push(150, 7), None);
+        assert_eq!(rest.push(220, 7), Some(7));
+        // Identical readings after that write nothing.
+        assert_eq!(rest.push(230, 7), None);
+    }
+
+    #[test]
+    fn settle_gate_commits_once_the_level_holds() {
+        let mut gate = SettleGate::new(Duration::from_millis(300));
+        // A sweep from 2 to 9 commits nothing on the way...
+        for (n, level) in (2..=9).enumerate() {
+            assert_eq!(gate.push(n as u64 * 50, level), None);
+        }
+        // ...nor before the last level has held for the window...
+        assert_eq!(gate.push(600, 9), None);
+        // ...then commits it once.
+        assert_eq!(gate.push(650, 9), Some(9));
+        assert_eq!(gate.push(700, 9), None);
+
+        // A brief excursion restarts the window.
+        assert_eq!(gate.push(750, 10), None);
+        assert_eq!(gate.push(800, 9), None);
+        assert_eq!(gate.push(1050, 9), None);
+        assert_eq!(gate.push(1100, 9), Some(9));
+
+        // A zero window commits every change at once, like immediate mode.
+        let mut instant = SettleGate::new(Duration::from_millis(0));
+        assert_eq!(instant.push(0, 4), Some(4));
+        assert_eq!(instant.push(50, 5), Some(5));
+    }
+
+    #[test]
+    fn rate_limiter_ramps_toward_the_knob() {
+        // Starting from level 0, the knob is swept to the top in 100 ms, at
+        // 20 ms samples.
+        let mut rate = RateLimiter::new(30);
+        rate.reset(0, 0);
+        let mut previous = 0;
+        let mut reached = None;
+        for now_ms in (20..=1000).step_by(20) {
+            let knob = (now_ms as u32 * LEVELS / 100).min(LEVELS - 1);
+            let level = rate.step(now_ms, Some(knob)).unwrap();
+            // Never faster than 30 levels per second overall...
+            assert!(level as u64 <= 30 * now_ms / 1000);
+            // ...moving smoothly, one level at a time at this rate...
+            assert!(level - previous <= 1);
+            assert!(level <= knob);
+            if level == LEVELS - 1 && reached.is_none() {
+                reached = Some(now_ms);
+            }
+            previous = level;
+        }
+        // ...and catching up with the knob after half a second.
+        assert_eq!(reached, Some(500));
+
+        // Downward moves are limited the same way, and no new target keeps
+        // ramping toward the last one.
+        assert_eq!(rate.step(1100, Some(0)), Some(12));
+        assert_eq!(rate.step(1200, None), Some(9));
+        assert_eq!(rate.step(1600, None), Some(0));
+
+        // Without a reset, the first target is taken at once.
+        let mut fresh = RateLimiter::new(30);
+        assert_eq!(fresh.step(0, None), None);
+        assert_eq!(fresh.step(0, Some(9)), Some(9));
+    }
+
+    #[test]
+    fn knob_pipeline_starts_over_on_reset() {
+        let mut path = KnobPipeline::new();
+        assert_eq!(path.push(0, 7), Some(7));
+        assert_eq!(path.push(50, 7), None);
+        // A new parameter starts over, so the same level is written for it.
+        path.reset(100, 3);
+        assert_eq!(path.push(100, 7), Some(7));
     }
 }
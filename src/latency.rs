@@ -0,0 +1,205 @@
+//! # Input Latency Module
+//!
+//! This module measures how long a knob change takes to reach the LED, so
+//! the UI's responsiveness can be checked at every frame rate.
+//!
+//! ## Measurement
+//!
+//! The UI stamps each knob change that alters the state with
+//! [`latency_input`], using the time its poll wait was due to end rather
+//! than the time it actually ran: a UI task that wakes late because the
+//! RGB loop kept the executor busy counts that delay against the input.
+//! [`Rgb::run`] calls [`latency_rendered`] when it starts the first frame
+//! built from changed state, closing the measurement. Changes made before
+//! the previous one was rendered are folded into it, so every sample is
+//! one input-to-render delay.
+//!
+//! ## Scheduling
+//!
+//! The RGB loop and the UI share one task (they are joined in `main`), so
+//! the UI only runs while the RGB loop is waiting. A timer wait whose
+//! deadline has already passed completes without yielding, and a frame
+//! that overruns its deadlines would never wait at all; [`Rgb::run`]
+//! therefore yields once per frame, which bounds the UI's extra delay to
+//! one frame, at most 100 ms at [`FRAME_RATE_MIN`].
+//!
+//! The console's `LATENCY` command reports the distribution gathered
+//! since the last report and starts a new one. At [`FRAME_RATE_MAX`] the
+//! p99 should stay below [`LATENCY_BOUND_MS`].
+use crate::*;
+
+/// Width of one histogram bucket, in milliseconds.
+pub const LATENCY_BUCKET_MS: u64 = 4;
+/// Number of histogram buckets; the last collects everything beyond.
+const LATENCY_BUCKETS: usize = 64;
+/// Input-to-render latency the p99 should stay below, in milliseconds.
+pub const LATENCY_BOUND_MS: u64 = 100;
+
+/// Distribution of input-to-render latencies.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LatencyHistogram {
+    /// Samples per [`LATENCY_BUCKET_MS`]-wide bucket.
+    buckets: [u32; LATENCY_BUCKETS],
+    /// Number of samples.
+    count: u32,
+    /// Longest latency seen, in milliseconds.
+    max_ms: u64,
+}
+
+impl LatencyHistogram {
+    /// Creates an empty histogram.
+    pub const fn new() -> Self {
+        Self {
+            buckets: [0; LATENCY_BUCKETS],
+            count: 0,
+            max_ms: 0,
+        }
+    }
+    /// Adds one latency in milliseconds.
+    pub fn record(&mut self, ms: u64) {
+        let bucket = (ms / LATENCY_BUCKET_MS).min(LATENCY_BUCKETS as u64 - 1) as usize;
+        self.buckets[bucket] = self.buckets[bucket].saturating_add(1);
+        self.count = self.count.saturating_add(1);
+        self.max_ms = self.max_ms.max(ms);
+    }
+    /// Number of samples.
+    pub fn count(&self) -> u32 {
+        self.count
+    }
+    /// Longest latency seen, in milliseconds.
+    pub fn max_ms(&self) -> u64 {
+        self.max_ms
+    }
+    /// Latency that `percent`% of the samples are at or below.
+    ///
+    /// # Arguments
+    /// * `percent` - Percentile from 1 to 100
+    ///
+    /// # Returns
+    /// The upper edge of the bucket holding that sample, capped at the
+    /// maximum (the maximum itself past the last bucket), in milliseconds;
+    /// `None` with no samples.
+    pub fn percentile(&self, percent: u32) -> Option<u64> {
+        if self.count == 0 {
+            return None;
+        }
+        let rank = (self.count as u64 * percent.clamp(1, 100) as u64).div_ceil(100);
+        let mut seen = 0;
+        for (bucket, &count) in self.buckets.iter().enumerate() {
+            seen += count as u64;
+            if seen >= rank && bucket < LATENCY_BUCKETS - 1 {
+                let edge = (bucket as u64 + 1) * LATENCY_BUCKET_MS - 1;
+                return Some(edge.min(self.max_ms));
+            }
+        }
+        Some(self.max_ms)
+    }
+    /// Whether the p99 is below [`LATENCY_BOUND_MS`] (vacuously, with no
+    /// samples).
+    pub fn within_bound(&self) -> bool {
+        self.percentile(99).is_none_or(|p99| p99 < LATENCY_BOUND_MS)
+    }
+}
+
+impl Default for LatencyHistogram {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl core::fmt::Display for LatencyHistogram {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        let (Some(p50), Some(p90), Some(p99)) = (
+            self.percentile(50),
+            self.percentile(90),
+            self.percentile(99),
+        ) else {
+            return write!(f, "no samples");
+        };
+        write!(
+            f,
+            "n={} p50<={} p90<={} p99<={} max={} ms ({} the {} ms bound)",
+            self.count,
+            p50,
+            p90,
+            p99,
+            self.max_ms,
+            if self.within_bound() {
+                "within"
+            } else {
+                "OVER"
+            },
+            LATENCY_BOUND_MS
+        )
+    }
+}
+
+/// Input waiting to be rendered, and the latencies measured so far.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LatencyProbe {
+    /// When the oldest input not yet rendered happened.
+    pending: Option<Instant>,
+    /// Latencies since the last report.
+    histogram: LatencyHistogram,
+}
+
+/// Global input latency probe, fed by the UI and the RGB loop.
+pub static INPUT_LATENCY: Mutex<CriticalSectionRawMutex, LatencyProbe> = Mutex::new(LatencyProbe {
+    pending: None,
+    histogram: LatencyHistogram::new(),
+});
+
+/// Records a state-changing input.
+///
+/// # Arguments
+/// * `at` - When the input happened
+pub async fn latency_input(at: Instant) {
+    INPUT_LATENCY.lock().await.pending.get_or_insert(at);
+}
+
+/// Records that a frame built from changed state has started, closing the
+/// pending input's measurement if there is one.
+pub async fn latency_rendered() {
+    let mut probe = INPUT_LATENCY.lock().await;
+    if let Some(at) = probe.pending.take() {
+        probe.histogram.record(at.elapsed().as_millis());
+    }
+}
+
+/// Takes the latencies gathered since the last call.
+pub async fn take_latency() -> LatencyHistogram {
+    core::mem::take(&mut INPUT_LATENCY.lock().await.histogram)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn histogram_percentiles_and_bound() {
+        let mut histogram = LatencyHistogram::new();
+        assert_eq!(histogram.percentile(99), None);
+        for ms in 0..100 {
+            histogram.record(ms);
+        }
+        assert_eq!(histogram.count(), 100);
+        assert_eq!(histogram.max_ms(), 99);
+        // Percentiles are bucket upper edges, never beyond the maximum.
+        assert_eq!(histogram.percentile(50), Some(51));
+        assert_eq!(histogram.percentile(99), Some(99));
+        assert!(histogram.within_bound());
+
+        // One very late render in a hundred doesn't move the p99...
+        let mut histogram = LatencyHistogram::new();
+        for _ in 0..99 {
+            histogram.record(10);
+        }
+        histogram.record(5_000);
+        assert_eq!(histogram.percentile(99), Some(11));
+        assert!(histogram.within_bound());
+        // ...but two do.
+        histogram.record(5_000);
+        assert_eq!(histogram.percentile(99), Some(5_000));
+        assert!(!histogram.within_bound());
+    }
+}
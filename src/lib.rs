@@ -0,0 +1,862 @@
+//! # RGB LED Controller for micro:bit: Core Library
+//!
+//! This library holds everything in the RGB calibration firmware that
+//! doesn't touch the hardware: the shared state and its setters, knob
+//! mapping and filtering, the UI state machine, PWM timing and scheduling,
+//! the console and protocol parsers, the wire and flash formats, and the
+//! virtual-clock simulation. The firmware binary (`src/main.rs` and its
+//! [`board`](../mb2_embassy_rgb/index.html) modules) adds the peripherals,
+//! the interrupt bindings and the Embassy tasks on top.
+//!
+//! ## Host Tests
+//!
+//! The library builds for the host as well as for the micro:bit, so its
+//! unit tests run with `cargo test-host` (see `.cargo/config.toml`). The
+//! feature modules are gated as in the firmware, so their tests run with
+//! the feature on, such as `cargo test-host --features ir,remote`.
+//!
+//! Shared state lives in [`CriticalSectionRawMutex`] mutexes, which work
+//! on the host as on the board. On the single-core nRF52833 the critical
+//! section only covers the lock bookkeeping, never the time a guard is
+//! held, so it costs no more than the thread-mode mutex did.
+//!
+//! ## Architecture
+//!
+//! - [`ambient`] module (`ambient` feature): Adaptive brightness from room light
+//! - [`bargraph`] module (`bargraph` feature): Levels on 74HC595-driven LED bar graphs
+//! - [`battery`] module (`battery` feature): Low-battery warning blink from the supply voltage
+//! - [`bench`] module (`bench` feature): PWM edge-jitter benchmark with PASS/FAIL
+//! - [`bh1750`] module (`bh1750` feature): Lux-sensor brightness compensation
+//! - [`camsync`] module (`camsync` feature): Phase-locks the PWM frame to a camera trigger
+//! - [`changelog`] module (`changelog` feature): Flash log of settled calibration changes
+//! - [`color`] module: Shared color math such as HSV conversion
+//! - [`compass`] module (`compass` feature): Magnetometer heading shown as hue
+//! - [`config`] module: Dump and restore of the whole runtime configuration as one blob
+//! - [`console`] module: Parses text commands from the host
+//! - [`crc`] module: CRC-16 for framed formats
+//! - [`dmx`] module (`dmx` feature): DMX512 output of the color for stage fixtures
+//! - [`events`] module: Publish/subscribe bus for shared-state changes
+//! - [`fault_inject`] module (`fault-inject` feature, debug builds): Injects bad inputs
+//! - [`flash`] module: Reserved internal-flash regions and the flash access trait
+//! - [`formats`] module: Console line formats scraped by host scripts, with golden outputs
+//! - [`gamma`] module: Gamma curve with a linear knee for the level duty
+//! - [`gesture`] module: Tap, double-tap, long-press and chord gestures from button samples
+//! - [`idle`] module (`idle` feature): Breathe or color-drift animation when idle, or layered over the color
+//! - [`ir`] module (`ir` feature): NEC IR remote decoding into UI commands
+//! - [`knob`] module: Knob filtering and mapping of the potentiometer readings
+//! - [`latency`] module: Measures knob input-to-LED latency
+//! - [`logging`] module: Non-blocking console output with a dropped-line count
+//! - [`mapping`] module: Pure knob-level to parameter mapping and its inverse
+//! - [`matrix`] module (`radio` feature): Status LED on the 5x5 matrix at its own brightness
+//! - [`mic`] module (`mic` feature): Sound-reactive brightness from the microphone
+//! - [`motion`] module (`motion` feature): Accelerometer tilt shown as color
+//! - [`oled`] module (`oled` feature): Status readout on an external SSD1306 OLED
+//! - [`pca9685`] module (`pca9685` feature): Mirrors the output onto a PCA9685 PWM chip
+//! - [`power`] module: Estimates LED current draw from the levels
+//! - [`protocol`] module (`protocol` feature): Framed binary protocol for test rigs
+//! - [`radio`] module (`radio` feature): Teacher/student color mirroring over radio
+//! - [`remote`] module (`remote` feature): Second micro:bit as a wireless knob
+//! - [`replay`] module: Input trace recording (`record` feature) and replay on a virtual clock
+//! - [`reset`] module: Panic color shown on the LED before a controlled reset
+//! - [`rgb`] module: PWM timing, level processing and the software PWM frame schedule
+//! - [`schedule`] module (`schedule` feature): Day/night master brightness cycle
+//! - [`selftest`] module: Results and report of the `SELFTEST` suite
+//! - [`settings`] module: Versioned, CRC-checked blob format for saved settings
+//! - [`sim`] module (software PWM): Replays the frame loop on a virtual clock
+//! - [`sleep`] module (`sleep` feature): Sleep timer that fades the LED out
+//! - [`snapshot`] module: A/B snapshots of the color state and their difference
+//! - [`stats`] module: Runtime event counters, dumped on demand or periodically
+//! - [`storage`] module: Storage backends (flash, RAM) for saved state
+//! - [`telemetry`] module (`telemetry` feature): Binary knob/level/frame records for plotting
+//! - [`temperature`] module (`temperature` feature): Chip temperature shown as a color
+//! - [`testpattern`] module (`testpattern` feature): Reference colors for colorimeter checks
+//! - [`timercal`] module (software PWM): Timer overhead calibration math
+//! - [`tone`] module (`tone` feature): Speaker pitch feedback for level changes
+//! - [`touch`] module: Capacitive touch logo as a third input
+//! - [`ui`] module: Button and knob UI state machine and its settings
+//!
+//! Shared state is managed through async-safe mutexes for thread-safe access
+//! across the concurrent tasks; changes are also published on the [`events`]
+//! bus for tasks that react to them.
+
+#![cfg_attr(not(test), no_std)]
+
+#[cfg(feature = "ambient")]
+mod ambient;
+#[cfg(feature = "bargraph")]
+mod bargraph;
+#[cfg(feature = "battery")]
+mod battery;
+#[cfg(feature = "bench")]
+mod bench;
+#[cfg(feature = "bh1750")]
+mod bh1750;
+#[cfg(feature = "camsync")]
+mod camsync;
+#[cfg(feature = "changelog")]
+mod changelog;
+mod color;
+#[cfg(feature = "compass")]
+mod compass;
+mod config;
+mod console;
+mod crc;
+#[cfg(feature = "dmx")]
+mod dmx;
+mod events;
+#[cfg(all(feature = "fault-inject", debug_assertions))]
+mod fault_inject;
+mod flash;
+mod formats;
+mod gamma;
+mod gesture;
+#[cfg(feature = "idle")]
+mod idle;
+#[cfg(feature = "ir")]
+mod ir;
+mod knob;
+mod latency;
+mod logging;
+mod mapping;
+#[cfg(feature = "radio")]
+mod matrix;
+#[cfg(feature = "mic")]
+mod mic;
+#[cfg(feature = "motion")]
+mod motion;
+#[cfg(feature = "oled")]
+mod oled;
+mod overlay;
+#[cfg(feature = "pca9685")]
+mod pca9685;
+mod power;
+#[cfg(feature = "protocol")]
+mod protocol;
+#[cfg(feature = "radio")]
+mod radio;
+#[cfg(feature = "remote")]
+mod remote;
+mod replay;
+mod reset;
+mod rgb;
+#[cfg(feature = "schedule")]
+mod schedule;
+mod selftest;
+mod settings;
+#[cfg(not(feature = "hwpwm"))]
+mod sim;
+#[cfg(feature = "sleep")]
+mod sleep;
+mod snapshot;
+mod stats;
+mod storage;
+#[cfg(feature = "telemetry")]
+mod telemetry;
+#[cfg(feature = "temperature")]
+mod temperature;
+#[cfg(feature = "testpattern")]
+mod testpattern;
+#[cfg(not(feature = "hwpwm"))]
+mod timercal;
+#[cfg(feature = "tone")]
+mod tone;
+mod touch;
+mod ui;
+#[cfg(feature = "ambient")]
+pub use ambient::*;
+#[cfg(feature = "bargraph")]
+pub use bargraph::*;
+#[cfg(feature = "battery")]
+pub use battery::*;
+#[cfg(feature = "bench")]
+pub use bench::*;
+#[cfg(feature = "bh1750")]
+pub use bh1750::*;
+#[cfg(feature = "camsync")]
+pub use camsync::*;
+#[cfg(feature = "changelog")]
+pub use changelog::*;
+pub use color::*;
+#[cfg(feature = "compass")]
+pub use compass::*;
+pub use config::*;
+pub use console::*;
+pub use crc::*;
+#[cfg(feature = "dmx")]
+pub use dmx::*;
+pub use events::*;
+#[cfg(all(feature = "fault-inject", debug_assertions))]
+pub use fault_inject::*;
+pub use flash::*;
+pub use formats::*;
+pub use gamma::*;
+pub use gesture::*;
+#[cfg(feature = "idle")]
+pub use idle::*;
+#[cfg(feature = "ir")]
+pub use ir::*;
+pub use knob::*;
+pub use latency::*;
+pub use logging::*;
+pub use mapping::*;
+#[cfg(feature = "radio")]
+pub use matrix::*;
+#[cfg(feature = "mic")]
+pub use mic::*;
+#[cfg(feature = "motion")]
+pub use motion::*;
+#[cfg(feature = "oled")]
+pub use oled::*;
+pub use overlay::*;
+#[cfg(feature = "pca9685")]
+pub use pca9685::*;
+pub use power::*;
+#[cfg(feature = "protocol")]
+pub use protocol::*;
+#[cfg(feature = "radio")]
+pub use radio::*;
+#[cfg(feature = "remote")]
+pub use remote::*;
+pub use replay::*;
+pub use reset::*;
+pub use rgb::*;
+#[cfg(feature = "schedule")]
+pub use schedule::*;
+pub use selftest::*;
+pub use settings::*;
+#[cfg(not(feature = "hwpwm"))]
+pub use sim::*;
+#[cfg(feature = "sleep")]
+pub use sleep::*;
+pub use snapshot::*;
+pub use stats::*;
+pub use storage::*;
+#[cfg(feature = "telemetry")]
+pub use telemetry::*;
+#[cfg(feature = "temperature")]
+pub use temperature::*;
+#[cfg(feature = "testpattern")]
+pub use testpattern::*;
+#[cfg(not(feature = "hwpwm"))]
+pub use timercal::*;
+#[cfg(feature = "tone")]
+pub use tone::*;
+pub use touch::*;
+pub use ui::*;
+
+use embassy_sync::{blocking_mutex::raw::CriticalSectionRawMutex, mutex::Mutex};
+use embassy_time::{Duration, Instant, Timer};
+
+/// Power-on values of the shared state and the UI.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Defaults {
+    /// RGB levels.
+    pub levels: [u32; 3],
+    /// Frame rate in FPS.
+    pub frame_rate: u64,
+    /// Saturation level of [`MOOD_HUE`].
+    pub saturation: u32,
+    /// Master brightness.
+    pub brightness: u32,
+    /// Output enable.
+    pub output_enabled: bool,
+}
+/// The one definition of the power-on values.
+///
+/// The shared-state statics start from these, [`UiState::default`] copies
+/// them, and the console's `RESET` and the [`ChordAction::Reset`] chord
+/// restore them. Settings saved with the console's `SAVE` replace them at
+/// boot (see [`load_settings`]).
+///
+/// The levels are full white on purpose: a freshly flashed board lights
+/// every channel, so a dead LED or wiring fault shows at once. Unless
+/// settings were saved, the UI then replaces the blue level with the knob
+/// reading at power-on; a reset restores full white.
+pub const DEFAULTS: Defaults = Defaults {
+    levels: [LEVELS - 1; 3],
+    frame_rate: 100,
+    saturation: LEVELS - 1,
+    brightness: BRIGHTNESS_MAX,
+    output_enabled: true,
+};
+
+/// Global RGB LED intensity levels shared across all tasks.
+///
+/// This mutex-protected array contains the current intensity values for each LED channel:
+/// - Index 0: Red channel intensity (0 to [`LEVELS`]-1)
+/// - Index 1: Green channel intensity (0 to [`LEVELS`]-1)  
+/// - Index 2: Blue channel intensity (0 to [`LEVELS`]-1)
+///
+/// The values are used by the RGB module for PWM control and modified by the UI module
+/// based on user input from the knob and buttons.
+///
+/// Default value: [`DEFAULTS`]
+pub static RGB_LEVELS: Mutex<CriticalSectionRawMutex, [u32; 3]> = Mutex::new(DEFAULTS.levels);
+/// Global frame rate setting for RGB LED refresh rate.
+///
+/// This mutex-protected value controls how frequently the RGB LEDs are updated,
+/// measured in frames per second (Hz). Higher values provide smoother transitions
+/// but increase CPU usage. The frame rate can be adjusted through the UI.
+///
+/// Default value: [`DEFAULTS`] (100 Hz)
+pub static FRAME_RATE: Mutex<CriticalSectionRawMutex, u64> = Mutex::new(DEFAULTS.frame_rate);
+/// Lowest frame rate the UI and protocols will set, in FPS.
+pub const FRAME_RATE_MIN: u64 = 10;
+/// Highest frame rate the UI and protocols will set, in FPS.
+pub const FRAME_RATE_MAX: u64 = 160;
+/// Frame rate change per knob level, in FPS.
+pub const FRAME_RATE_STEP: u64 = 10;
+/// Writer of the frame rate or levels, named in out-of-range warnings.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StateSource {
+    /// Knob, IR remote, or console, through the UI task.
+    Ui,
+    /// A teacher board's radio packets.
+    Radio,
+    /// The framed binary protocol.
+    Protocol,
+    /// The edge-jitter benchmark script.
+    Bench,
+    /// Console fault injection.
+    Fault,
+}
+/// Checks a frame rate against [`FRAME_RATE_MIN`]..=[`FRAME_RATE_MAX`].
+///
+/// This is the single definition of a valid frame rate: [`set_frame_rate`]
+/// stores only what it accepts, and the RGB module and the radio and
+/// protocol decoders check against it too.
+///
+/// # Returns
+/// `Ok(frame_rate)` if it is in range, or `Err` with the nearest rate that
+/// is.
+pub fn validate_frame_rate(frame_rate: u64) -> Result<u64, u64> {
+    let clamped = frame_rate.clamp(FRAME_RATE_MIN, FRAME_RATE_MAX);
+    if clamped == frame_rate {
+        Ok(frame_rate)
+    } else {
+        Err(clamped)
+    }
+}
+/// Global master brightness applied on top of the RGB levels.
+///
+/// Expressed as a percentage from 0 (dark) to [`BRIGHTNESS_MAX`] (full
+/// calibrated output). The RGB module scales each channel's on-time by this
+/// value, so dimming preserves the ratio between the calibrated channels.
+///
+/// Default value: [`DEFAULTS`] ([`BRIGHTNESS_MAX`])
+pub static MASTER_BRIGHTNESS: Mutex<CriticalSectionRawMutex, u32> = Mutex::new(DEFAULTS.brightness);
+/// Global output enable for the LED.
+///
+/// While `false` the RGB module drives the LED dark, as if the master
+/// brightness were 0, but the levels and brightness are kept, so enabling
+/// it again brings back exactly the same output.
+///
+/// Default value: [`DEFAULTS`] (`true`)
+pub static OUTPUT_ENABLED: Mutex<CriticalSectionRawMutex, bool> =
+    Mutex::new(DEFAULTS.output_enabled);
+/// Lock that must be held by anything driving the LED matrix pins.
+///
+/// The matrix LEDs share rows and columns, so a row driven high for one LED
+/// can forward-bias another into a column the ambient measurement leaves
+/// floating (see the `ambient` and `matrix` modules). Each user holds it
+/// for as long as it drives any matrix pin away from its idle level.
+pub static MATRIX_LOCK: Mutex<CriticalSectionRawMutex, ()> = Mutex::new(());
+/// Full-scale value of [`MASTER_BRIGHTNESS`] (100%).
+pub const BRIGHTNESS_MAX: u32 = 100;
+/// Administrative cap on the output duty, as a percentage of [`BRIGHTNESS_MAX`].
+///
+/// Unlike [`MASTER_BRIGHTNESS`] this is fixed at build time: set it below
+/// [`BRIGHTNESS_MAX`] to limit a device (for example to 70% for heat
+/// reasons) whatever the user selects. The RGB module applies it after all
+/// other scaling, dimming every channel by the same factor so the color
+/// ratio is preserved.
+pub const BRIGHTNESS_CEILING: u32 = BRIGHTNESS_MAX;
+/// Maximum number of channels allowed at full on-time at the same moment.
+///
+/// Fixed at build time like [`BRIGHTNESS_CEILING`]. Where the channels are
+/// lit together (hardware PWM, the PCA9685 mirror), a frame with more
+/// full-on channels than this is throttled so the supply never sees more
+/// than this many LEDs' worth of peak current. The sequential bit-bang
+/// scanout never lights two channels at once, so there it has no effect
+/// unless set to 0. The default of 3 disables the limit.
+pub const FULL_ON_LIMIT: usize = 3;
+/// Number of PWM phases each frame is split into, fixed at build time.
+///
+/// Each lit channel gets this many shorter pulses per frame instead of one,
+/// with the same total on-time, which reduces visible flicker at dim levels
+/// (see the [`rgb`] module). 1 to [`PWM_PHASES_MAX`]; the software PWM
+/// scanout only.
+pub const PWM_PHASES: u32 = 1;
+/// Frame rate below which the software PWM adds phases to hide flicker,
+/// fixed at build time.
+///
+/// Below this rate the frame is split into enough phases that the LED
+/// still pulses at least this often (see [`anti_flicker_phases`]), so the
+/// low end of the frame-rate range stays comfortable to look at. 0 turns
+/// the mitigation off.
+pub const FLICKER_THRESHOLD_FPS: u64 = 50;
+/// Gamma curve applied to the levels at power-on.
+///
+/// `None` keeps the linear `level / LEVELS` duty. With a [`Gamma`], each
+/// level's on-time follows the curve instead, for example
+/// `Some(Gamma { exponent: 2.2, knee: 3 })` for an even perceived
+/// brightness ramp that still keeps level 1 visible (see the [`gamma`]
+/// module). The console's `GAMMA` changes it at runtime.
+pub const GAMMA: Option<Gamma> = None;
+/// What the A+B button chord does, fixed at build time.
+///
+/// [`ChordAction::EditRed`] keeps the original scheme; the other actions
+/// free the chord for navigation (see [`ChordAction`]).
+pub const CHORD_ACTION: ChordAction = ChordAction::EditRed;
+/// Parameter the knob edits at power-on, fixed at build time.
+///
+/// [`ControlParameter::FrameRate`] keeps the buttons in charge from the
+/// start; any other parameter is edited with no button held until the
+/// first button press (see [`UiCore::starting_in`]).
+pub const START_PARAMETER: ControlParameter = ControlParameter::FrameRate;
+/// What touching the micro:bit logo does, fixed at build time, or `None` to
+/// leave the logo unread (see the [`touch`] module).
+pub const TOUCH_ACTION: Option<TouchAction> = Some(TouchAction::Next);
+/// Hue in degrees that [`ControlParameter::Saturation`] varies the
+/// saturation of, fixed at build time. 30 is a warm amber.
+pub const MOOD_HUE: f32 = 30.0;
+/// Part of each parameter's range the knob covers, fixed at build time.
+///
+/// [`KnobRanges::FULL`] lets the knob reach every value. To lock a device
+/// to a window, give that parameter a [`KnobRange`], for example
+/// `frame_rate: Some(KnobRange::new(30, 60))`; the knob's whole travel
+/// then spans 30 to 60 FPS (see the [`mapping`] module).
+pub const KNOB_RANGES: KnobRanges = KnobRanges::FULL;
+const _: () = assert!(
+    KNOB_RANGES.within_bounds(),
+    "KNOB_RANGES must lie within each parameter's bounds"
+);
+/// Maximum intensity levels for each RGB channel.
+///
+/// This constant defines the number of discrete intensity steps available
+/// for each LED channel, providing 16 levels from 0 (off) to 15 (maximum brightness).
+/// The actual PWM duty cycle is calculated as `level / LEVELS`.
+pub const LEVELS: u32 = 16;
+/// Shortest time between two clamped-levels warnings, so a runaway writer
+/// can't flood the log.
+const LEVELS_WARNING_INTERVAL: Duration = Duration::from_secs(1);
+/// When the last clamped-levels warning was printed.
+static LEVELS_WARNED_AT: Mutex<CriticalSectionRawMutex, Option<Instant>> = Mutex::new(None);
+/// Checks RGB levels against 0..=[`LEVELS`]-1.
+///
+/// This is the single definition of valid levels: [`set_rgb_levels`]
+/// stores only what it accepts, [`try_set_rgb_levels`] refuses anything
+/// else, and the radio decoder checks against it too.
+///
+/// # Returns
+/// `Ok(levels)` if every channel is in range, or `Err` with each channel
+/// clamped to the nearest level that is.
+pub fn validate_levels(levels: [u32; 3]) -> Result<[u32; 3], [u32; 3]> {
+    let clamped = levels.map(|level| level.min(LEVELS - 1));
+    if clamped == levels {
+        Ok(levels)
+    } else {
+        Err(clamped)
+    }
+}
+/// Retrieves the current RGB LED intensity levels.
+///
+/// This is a convenience function that safely accesses the shared [`RGB_LEVELS`] state.
+///
+/// # Returns
+///
+/// An array of three `u32` values representing the current intensity levels
+/// for red, green, and blue channels respectively.
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// # use mb2_embassy_rgb::*;
+/// # async fn example() {
+/// let [red, green, blue] = get_rgb_levels().await;
+/// rprintln!("Current RGB: R={}, G={}, B={}", red, green, blue);
+/// # }
+/// ```
+pub async fn get_rgb_levels() -> [u32; 3] {
+    let rgb_levels = RGB_LEVELS.lock().await;
+    *rgb_levels
+}
+
+/// Updates the RGB LED intensity levels using a closure.
+///
+/// This function provides safe, atomic access to modify the shared [`RGB_LEVELS`] state.
+/// The provided closure receives a mutable reference to the RGB levels array.
+/// Levels that [`validate_levels`] rejects are clamped to [`LEVELS`]-1 after
+/// the closure runs, with a warning at most once per
+/// [`LEVELS_WARNING_INTERVAL`], and a [`StateChanged::Levels`] event is
+/// published if the levels changed. Use [`set_rgb_levels_from`] to name the
+/// writer in the warning.
+///
+/// # Parameters
+///
+/// * `setter` - A closure that receives `&mut [u32; 3]` to modify the RGB levels
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// # use mb2_embassy_rgb::*;
+/// # async fn example() {
+/// // Set red to maximum, others to zero
+/// set_rgb_levels(|levels| {
+///     levels[0] = LEVELS - 1; // Red
+///     levels[1] = 0; // Green
+///     levels[2] = 0; // Blue
+/// })
+/// .await;
+///
+/// // Increment blue channel (with bounds checking)
+/// set_rgb_levels(|levels| {
+///     if levels[2] < LEVELS - 1 {
+///         levels[2] += 1;
+///     }
+/// })
+/// .await;
+/// # }
+/// ```
+pub async fn set_rgb_levels<F>(setter: F)
+where
+    F: FnOnce(&mut [u32; 3]),
+{
+    store_rgb_levels(None, setter).await;
+}
+/// Updates the RGB levels like [`set_rgb_levels`], naming the writer if the
+/// levels have to be clamped.
+///
+/// # Parameters
+///
+/// * `source` - Who is writing, for the warning
+/// * `setter` - A closure that receives `&mut [u32; 3]` to modify the RGB levels
+pub async fn set_rgb_levels_from<F>(source: StateSource, setter: F)
+where
+    F: FnOnce(&mut [u32; 3]),
+{
+    store_rgb_levels(Some(source), setter).await;
+}
+/// Stores new RGB levels only if [`validate_levels`] accepts them.
+///
+/// For protocol handlers, which should refuse a bad request rather than
+/// act on a clamped one.
+///
+/// # Parameters
+///
+/// * `levels` - New RGB levels
+///
+/// # Returns
+///
+/// `Err` with the clamped levels, leaving the stored levels alone, if any
+/// channel is out of range.
+pub async fn try_set_rgb_levels(levels: [u32; 3]) -> Result<(), [u32; 3]> {
+    let levels = validate_levels(levels)?;
+    store_rgb_levels(None, |rgb| *rgb = levels).await;
+    Ok(())
+}
+/// Runs a levels update, clamping and publishing as described on
+/// [`set_rgb_levels`].
+async fn store_rgb_levels<F>(source: Option<StateSource>, setter: F)
+where
+    F: FnOnce(&mut [u32; 3]),
+{
+    let mut rgb_levels = RGB_LEVELS.lock().await;
+    let old = *rgb_levels;
+    setter(&mut rgb_levels);
+    if let Err(clamped) = validate_levels(*rgb_levels) {
+        let mut warned_at = LEVELS_WARNED_AT.lock().await;
+        let now = Instant::now();
+        if warned_at.is_none_or(|at| now.saturating_duration_since(at) >= LEVELS_WARNING_INTERVAL) {
+            *warned_at = Some(now);
+            match source {
+                Some(source) => rprintln!(
+                    "warning: levels {:?} from {:?} out of range, using {:?}",
+                    *rgb_levels,
+                    source,
+                    clamped
+                ),
+                None => rprintln!(
+                    "warning: levels {:?} out of range, using {:?}",
+                    *rgb_levels,
+                    clamped
+                ),
+            }
+        }
+        *rgb_levels = clamped;
+    }
+    if *rgb_levels != old {
+        publish_state(StateChanged::Levels(*rgb_levels));
+    }
+}
+///
+/// This is a convenience function that safely accesses the shared [`FRAME_RATE`] state.
+///
+/// # Returns
+///
+/// The current frame rate in Hz as a `u64` value.
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// # use mb2_embassy_rgb::*;
+/// # async fn example() {
+/// let current_fps = get_frame_rate().await;
+/// rprintln!("Running at {} FPS", current_fps);
+/// # }
+/// ```
+pub async fn get_frame_rate() -> u64 {
+    let frame_rate = FRAME_RATE.lock().await;
+    *frame_rate
+}
+/// Updates the frame rate setting using a closure.
+///
+/// This function provides safe, atomic access to modify the shared [`FRAME_RATE`] state.
+/// The provided closure receives a mutable reference to the frame rate value.
+/// A value that [`validate_frame_rate`] rejects is clamped into range, with
+/// a warning naming the writer. With the software PWM, a rate too fast for
+/// the timer (see [`fit_frame_rate`]) is then lowered to the fastest
+/// feasible one, again with a warning. A [`StateChanged::FrameRate`] event
+/// is published if the stored value changed.
+///
+/// # Parameters
+///
+/// * `source` - Who is writing, for the warning
+/// * `setter` - A closure that receives `&mut u64` to modify the frame rate
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// # use mb2_embassy_rgb::*;
+/// # async fn example() {
+/// // Set frame rate to 60 Hz
+/// set_frame_rate(StateSource::Ui, |fps| *fps = 60).await;
+///
+/// // Double the current frame rate
+/// set_frame_rate(StateSource::Ui, |fps| *fps *= 2).await;
+/// # }
+/// ```
+pub async fn set_frame_rate<F>(source: StateSource, setter: F)
+where
+    F: FnOnce(&mut u64),
+{
+    let mut frame_rate = FRAME_RATE.lock().await;
+    let old = *frame_rate;
+    setter(&mut frame_rate);
+    if let Err(clamped) = validate_frame_rate(*frame_rate) {
+        rprintln!(
+            "warning: frame rate {} from {:?} out of range, using {}",
+            *frame_rate,
+            source,
+            clamped
+        );
+        *frame_rate = clamped;
+    }
+    #[cfg(not(feature = "hwpwm"))]
+    match fit_frame_rate(*frame_rate, LEVELS, PWM_PHASES, TIMER_FLOOR_US) {
+        FrameRateFit::Feasible => {}
+        FrameRateFit::Clamped(feasible) => {
+            rprintln!(
+                "warning: frame rate {} from {:?} needs {} us PWM slices, below the {} us timer floor, using {}",
+                *frame_rate,
+                source,
+                min_slice_us(*frame_rate, LEVELS, PWM_PHASES),
+                TIMER_FLOOR_US,
+                feasible
+            );
+            *frame_rate = feasible;
+        }
+        FrameRateFit::Degraded => rprintln!(
+            "warning: {} levels x {} phases is too fine for the {} us timer floor at any frame rate, low levels will be inaccurate",
+            LEVELS,
+            PWM_PHASES,
+            TIMER_FLOOR_US
+        ),
+    }
+    if *frame_rate != old {
+        publish_state(StateChanged::FrameRate(*frame_rate));
+    }
+}
+/// Retrieves the current master brightness percentage.
+///
+/// This is a convenience function that safely accesses the shared [`MASTER_BRIGHTNESS`] state.
+///
+/// # Returns
+///
+/// The master brightness from 0 to [`BRIGHTNESS_MAX`].
+pub async fn get_master_brightness() -> u32 {
+    let brightness = MASTER_BRIGHTNESS.lock().await;
+    *brightness
+}
+/// Updates the master brightness using a closure.
+///
+/// The stored value is clamped to [`BRIGHTNESS_MAX`] after the closure runs.
+/// A [`StateChanged::Brightness`] event is published if it changed.
+///
+/// # Parameters
+///
+/// * `setter` - A closure that receives `&mut u32` to modify the brightness
+pub async fn set_master_brightness<F>(setter: F)
+where
+    F: FnOnce(&mut u32),
+{
+    let mut brightness = MASTER_BRIGHTNESS.lock().await;
+    let old = *brightness;
+    setter(&mut brightness);
+    *brightness = (*brightness).min(BRIGHTNESS_MAX);
+    if *brightness != old {
+        publish_state(StateChanged::Brightness(*brightness));
+    }
+}
+/// Retrieves whether the LED output is enabled.
+///
+/// This is a convenience function that safely accesses the shared [`OUTPUT_ENABLED`] state.
+///
+/// # Returns
+///
+/// `true` while the LED shows the levels, `false` while it is blanked.
+pub async fn get_output_enabled() -> bool {
+    let enabled = OUTPUT_ENABLED.lock().await;
+    *enabled
+}
+/// Enables or blanks the LED output.
+///
+/// A [`StateChanged::OutputEnabled`] event is published if the flag changed.
+///
+/// # Parameters
+///
+/// * `enabled` - `true` to show the levels, `false` to blank the LED
+pub async fn set_output_enabled(enabled: bool) {
+    let mut output_enabled = OUTPUT_ENABLED.lock().await;
+    if *output_enabled != enabled {
+        *output_enabled = enabled;
+        publish_state(StateChanged::OutputEnabled(enabled));
+    }
+}
+/// How much diagnostic output the firmware prints.
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
+pub enum Verbosity {
+    /// Status and changes only.
+    Info,
+    /// Also periodic measurements, such as PWM on-time accuracy.
+    Debug,
+}
+/// Global diagnostic verbosity, set from the console.
+///
+/// Default value: [`Verbosity::Info`]
+pub static VERBOSITY: Mutex<CriticalSectionRawMutex, Verbosity> = Mutex::new(Verbosity::Info);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use embassy_futures::block_on;
+
+    #[test]
+    fn frame_rate_range() {
+        assert_eq!(validate_frame_rate(FRAME_RATE_MIN), Ok(FRAME_RATE_MIN));
+        assert_eq!(validate_frame_rate(FRAME_RATE_MAX), Ok(FRAME_RATE_MAX));
+        assert_eq!(validate_frame_rate(60), Ok(60));
+        assert_eq!(validate_frame_rate(FRAME_RATE_MIN - 1), Err(FRAME_RATE_MIN));
+        assert_eq!(validate_frame_rate(FRAME_RATE_MAX + 1), Err(FRAME_RATE_MAX));
+        assert_eq!(validate_frame_rate(0), Err(FRAME_RATE_MIN));
+        assert_eq!(validate_frame_rate(100_000), Err(FRAME_RATE_MAX));
+        assert_eq!(validate_frame_rate(u64::MAX), Err(FRAME_RATE_MAX));
+    }
+
+    #[test]
+    fn levels_range() {
+        assert_eq!(validate_levels([0, 8, LEVELS - 1]), Ok([0, 8, LEVELS - 1]));
+        assert_eq!(validate_levels([16, 0, 0]), Err([LEVELS - 1, 0, 0]));
+        assert_eq!(validate_levels([0, 255, 3]), Err([0, LEVELS - 1, 3]));
+        assert_eq!(validate_levels([u32::MAX; 3]), Err([LEVELS - 1; 3]));
+    }
+
+    #[test]
+    fn clamped_levels_fit_their_slots() {
+        // The clamped levels drive the PWM math like any valid ones: no
+        // on-time longer than its slot, at any frame rate.
+        for wild in [[16, 0, 0], [0, 255, 0], [u32::MAX; 3]] {
+            let clamped = validate_levels(wild).unwrap_err();
+            for frame_rate in [FRAME_RATE_MIN, 60, FRAME_RATE_MAX] {
+                let timing = RgbTiming::for_frame_rate(frame_rate);
+                let full = BRIGHTNESS_MAX;
+                let on_times = frame_on_times(timing, clamped, full, full);
+                assert_eq!(on_times, frame_on_times(timing, wild, full, full));
+                let schedule = FrameSchedule::new(&timing, clamped, full, full, PWM_PHASES);
+                for (led, &on_time) in on_times.iter().enumerate() {
+                    assert!(on_time <= timing.slot_ticks(led));
+                    assert!(schedule.on_ticks(led) <= timing.slot_ticks(led));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn defaults_are_valid() {
+        assert_eq!(validate_levels(DEFAULTS.levels), Ok(DEFAULTS.levels));
+        assert_eq!(
+            validate_frame_rate(DEFAULTS.frame_rate),
+            Ok(DEFAULTS.frame_rate)
+        );
+        assert_eq!(
+            fit_frame_rate(DEFAULTS.frame_rate, LEVELS, PWM_PHASES, TIMER_FLOOR_US),
+            FrameRateFit::Feasible,
+        );
+    }
+
+    /// The shared-state statics, in one test so that no other test runs
+    /// against them at the same time.
+    #[test]
+    fn shared_state() {
+        block_on(async {
+            // Before any task has written them, the statics hold the
+            // defaults.
+            assert_eq!(get_rgb_levels().await, DEFAULTS.levels);
+            assert_eq!(get_frame_rate().await, DEFAULTS.frame_rate);
+            assert_eq!(get_master_brightness().await, DEFAULTS.brightness);
+            assert_eq!(get_output_enabled().await, DEFAULTS.output_enabled);
+
+            set_rgb_levels(|levels| *levels = [LEVELS - 1, 0, 0]).await;
+            set_rgb_levels(|levels| levels[2] += 1).await;
+            assert_eq!(get_rgb_levels().await, [LEVELS - 1, 0, 1]);
+            // Out-of-range writes are stored as the top level.
+            for wild in [16, 255, u32::MAX] {
+                set_rgb_levels(|levels| *levels = [wild, 0, wild]).await;
+                assert_eq!(get_rgb_levels().await, [LEVELS - 1, 0, LEVELS - 1]);
+            }
+            set_rgb_levels_from(StateSource::Radio, |levels| levels[1] = 99).await;
+            assert_eq!(get_rgb_levels().await, [LEVELS - 1; 3]);
+
+            // try_set_rgb_levels refuses rather than clamps.
+            set_rgb_levels(|levels| *levels = [1, 2, 3]).await;
+            for wild in [[16, 0, 0], [0, 255, 0], [0, 0, u32::MAX]] {
+                assert!(try_set_rgb_levels(wild).await.is_err());
+                assert_eq!(get_rgb_levels().await, [1, 2, 3]);
+            }
+            assert_eq!(try_set_rgb_levels([LEVELS - 1, 0, 4]).await, Ok(()));
+            assert_eq!(get_rgb_levels().await, [LEVELS - 1, 0, 4]);
+
+            set_frame_rate(StateSource::Ui, |fps| *fps = 60).await;
+            assert_eq!(get_frame_rate().await, 60);
+            // Doubling stops at FRAME_RATE_MAX.
+            set_frame_rate(StateSource::Ui, |fps| *fps *= 2).await;
+            set_frame_rate(StateSource::Ui, |fps| *fps *= 2).await;
+            assert_eq!(get_frame_rate().await, FRAME_RATE_MAX);
+            set_frame_rate(StateSource::Protocol, |fps| *fps = 0).await;
+            assert_eq!(get_frame_rate().await, FRAME_RATE_MIN);
+
+            set_master_brightness(|b| *b = BRIGHTNESS_MAX / 2).await;
+            assert_eq!(get_master_brightness().await, BRIGHTNESS_MAX / 2);
+            set_master_brightness(|b| *b = BRIGHTNESS_MAX + 1).await;
+            assert_eq!(get_master_brightness().await, BRIGHTNESS_MAX);
+
+            // The output flag round-trips through the setter, in either
+            // order.
+            for enabled in [false, true, true, false] {
+                set_output_enabled(enabled).await;
+                assert_eq!(get_output_enabled().await, enabled);
+            }
+            set_output_enabled(true).await;
+        });
+    }
+}
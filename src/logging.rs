@@ -0,0 +1,355 @@
+//! # Logging Module
+//!
+//! This module routes all console output through a non-blocking wrapper, so
+//! a full RTT buffer (heavy logging with no host reading fast enough) costs
+//! lost lines rather than stalled tasks or a glitching PWM.
+//!
+//! ## Output
+//!
+//! The crate's [`rprintln!`](crate::rprintln) replaces the `rtt_target`
+//! macro of the same name with the same syntax. Each line is formatted into
+//! a [`LOG_LINE_MAX`]-byte [`LogLine`] (longer lines are cut short and end
+//! in [`LOG_TRUNCATED`]) and handed in one piece to the [`LogWriter`] the
+//! firmware registers with [`set_log_writer`], which writes it to the RTT
+//! terminal channel. In the default skip mode a line that doesn't fit in
+//! the buffer is dropped whole and counted; the firmware's `log_task` then
+//! prints `N log lines dropped` at most every [`LOG_DROP_REPORT`], once the
+//! host has caught up.
+//!
+//! With the `log-blocking` feature a line that doesn't fit waits for the
+//! host to read enough instead, which loses nothing while a host is
+//! reading.
+//!
+//! ## Host Detection
+//!
+//! Waiting on a host that isn't there would freeze the firmware the first
+//! time the buffer filled, and a standalone board has nobody reading. A
+//! [`HostMonitor`] therefore watches the host's read offset in the RTT
+//! control block on every write: while bytes are pending and the offset
+//! hasn't moved for [`LOG_HOST_GRACE`], the host counts as absent and every
+//! line is dropped (and counted) without touching the channel, in either
+//! mode; a blocked write gives up at the same point. As soon as the offset
+//! moves again, a host has attached and is draining, and full logging
+//! resumes, with the next drop notice covering the gap.
+//!
+//! ## Whole Lines
+//!
+//! Lines from different tasks never interleave, by construction: a line is
+//! complete before anything is written, and the single channel write that
+//! sends it happens inside a critical section, so no other task or
+//! interrupt handler can write between its bytes. Consecutive lines from
+//! one task with no `.await` between them (such as the UI's status readout)
+//! also stay together, since the executor only switches tasks at an await.
+//!
+//! ## Hot Path
+//!
+//! Code that must not wait on the console, chiefly the RGB frame loop,
+//! logs with [`rprintln_deferred!`](crate::rprintln_deferred) instead. It
+//! formats the line the same way but only queues it, up to
+//! [`LOG_DEFERRED_DEPTH`] lines in [`LOG_DEFERRED`], for `log_task` to
+//! write; a line that finds the queue full is dropped and counted like any
+//! other. This keeps the loop from stalling even with the `log-blocking`
+//! feature.
+//!
+//! ## Channel Sharing
+//!
+//! The terminal channel also stays registered as the `rtt_target` print
+//! channel, so `panic-rtt-target` can still report panics on it.
+use crate::*;
+use core::cell::Cell;
+use core::fmt::Write;
+use core::sync::atomic::{AtomicU32, Ordering};
+use embassy_sync::blocking_mutex::CriticalSectionMutex;
+use embassy_sync::channel::Channel;
+
+/// Longest console line, in bytes including the newline.
+pub const LOG_LINE_MAX: usize = 160;
+/// Shortest interval between dropped-line notices.
+pub const LOG_DROP_REPORT: Duration = Duration::from_secs(5);
+/// Marker ending a line cut short at [`LOG_LINE_MAX`].
+pub const LOG_TRUNCATED: &str = "[...]";
+/// Number of deferred lines that can wait in [`LOG_DEFERRED`].
+pub const LOG_DEFERRED_DEPTH: usize = 4;
+/// How long pending output may go unread before the host counts as absent.
+pub const LOG_HOST_GRACE: Duration = Duration::from_secs(1);
+
+/// Writes one whole line to the console.
+///
+/// Returns `false` if the line was dropped (buffer full or no host).
+pub type LogWriter = fn(&LogLine) -> bool;
+
+/// Console writer, once the firmware has registered one.
+static LOG_WRITER: CriticalSectionMutex<Cell<Option<LogWriter>>> =
+    CriticalSectionMutex::new(Cell::new(None));
+/// Number of lines dropped since boot, wrapping.
+pub static LOG_DROPPED: AtomicU32 = AtomicU32::new(0);
+/// Lines queued by [`rprintln_deferred!`](crate::rprintln_deferred).
+pub static LOG_DEFERRED: Channel<CriticalSectionRawMutex, LogLine, LOG_DEFERRED_DEPTH> =
+    Channel::new();
+
+/// Prints one line to the console without blocking.
+///
+/// Drop-in replacement for `rtt_target::rprintln!`.
+#[macro_export]
+macro_rules! rprintln {
+    () => {
+        $crate::log_line(format_args!(""))
+    };
+    ($($arg:tt)*) => {
+        $crate::log_line(format_args!($($arg)*))
+    };
+}
+
+/// Queues one line in [`LOG_DEFERRED`] to print later, never waiting on the
+/// console.
+///
+/// Same syntax as [`rprintln!`](crate::rprintln); for the RGB hot path.
+#[macro_export]
+macro_rules! rprintln_deferred {
+    ($($arg:tt)*) => {
+        $crate::log_deferred(format_args!($($arg)*))
+    };
+}
+
+/// One formatted console line, cut short rather than failing when too long.
+#[derive(Clone)]
+pub struct LogLine {
+    buf: [u8; LOG_LINE_MAX],
+    len: usize,
+    truncated: bool,
+}
+
+impl Write for LogLine {
+    fn write_str(&mut self, s: &str) -> core::fmt::Result {
+        // Keep room for the newline, and cut on a character boundary.
+        let room = LOG_LINE_MAX - 1 - self.len;
+        let mut count = s.len().min(room);
+        while !s.is_char_boundary(count) {
+            count -= 1;
+        }
+        self.buf[self.len..self.len + count].copy_from_slice(&s.as_bytes()[..count]);
+        self.len += count;
+        self.truncated |= count < s.len();
+        Ok(())
+    }
+}
+
+impl LogLine {
+    /// Formats one line, newline included.
+    ///
+    /// # Arguments
+    /// * `args` - Line contents, as from `format_args!`
+    ///
+    /// # Returns
+    /// The line, ending in [`LOG_TRUNCATED`] before the newline if it
+    /// didn't fit in [`LOG_LINE_MAX`] bytes.
+    pub fn format(args: core::fmt::Arguments) -> Self {
+        let mut line = Self {
+            buf: [0; LOG_LINE_MAX],
+            len: 0,
+            truncated: false,
+        };
+        let _ = line.write_fmt(args);
+        if line.truncated {
+            let mut start = LOG_LINE_MAX - 1 - LOG_TRUNCATED.len();
+            while !line.as_str().is_char_boundary(start.min(line.len)) {
+                start -= 1;
+            }
+            line.len = start.min(line.len);
+            let end = line.len + LOG_TRUNCATED.len();
+            line.buf[line.len..end].copy_from_slice(LOG_TRUNCATED.as_bytes());
+            line.len = end;
+        }
+        line.buf[line.len] = b'\n';
+        line.len += 1;
+        line
+    }
+    /// The line's bytes as written to the channel.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.buf[..self.len]
+    }
+    /// The line as text.
+    pub fn as_str(&self) -> &str {
+        // Only whole characters are ever copied in.
+        core::str::from_utf8(self.as_bytes()).unwrap_or_default()
+    }
+    /// Whether the line was cut short.
+    pub fn is_truncated(&self) -> bool {
+        self.truncated
+    }
+}
+
+/// Tracks whether a host is draining the terminal channel, from the
+/// progress of its read offset.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HostMonitor {
+    /// Grace period, in milliseconds.
+    grace_ms: u64,
+    /// Read offset at the last observation.
+    last_read: Option<usize>,
+    /// When the host last made progress or had nothing to read, in
+    /// milliseconds.
+    progress_at: u64,
+    /// Whether the host counted as present at the last observation.
+    present: bool,
+}
+
+impl HostMonitor {
+    /// Starts monitoring, assuming a host until shown otherwise.
+    ///
+    /// # Arguments
+    /// * `grace` - How long pending output may go unread
+    pub const fn new(grace: Duration) -> Self {
+        Self {
+            grace_ms: grace.as_millis(),
+            last_read: None,
+            progress_at: 0,
+            present: true,
+        }
+    }
+    /// Takes one look at the channel offsets.
+    ///
+    /// # Arguments
+    /// * `now_ms` - Time of the look, in milliseconds
+    /// * `write` - Firmware's write offset in the ring buffer
+    /// * `read` - Host's read offset in the ring buffer
+    ///
+    /// # Returns
+    /// Whether a host counts as present.
+    pub fn observe(&mut self, now_ms: u64, write: usize, read: usize) -> bool {
+        let first = self.last_read.is_none();
+        if first || self.last_read != Some(read) || read == write {
+            self.progress_at = now_ms;
+        }
+        self.last_read = Some(read);
+        self.present = now_ms.saturating_sub(self.progress_at) < self.grace_ms;
+        self.present
+    }
+    /// Whether a host counted as present at the last observation.
+    pub fn present(&self) -> bool {
+        self.present
+    }
+}
+
+/// Registers the console writer every line goes through.
+///
+/// # Arguments
+/// * `writer` - Writes one whole line, as the RTT terminal channel does
+pub fn set_log_writer(writer: LogWriter) {
+    LOG_WRITER.lock(|cell| cell.set(Some(writer)));
+}
+
+/// Writes one line in a single piece through the registered [`LogWriter`].
+///
+/// # Returns
+/// `false` if the line was dropped (buffer full, no host, or no writer
+/// registered yet).
+pub fn write_line(line: &LogLine) -> bool {
+    match LOG_WRITER.lock(Cell::get) {
+        Some(writer) => writer(line),
+        None => false,
+    }
+}
+
+/// Writes one line, counting it if it was dropped.
+pub fn write_line_counted(line: &LogLine) {
+    if !write_line(line) {
+        LOG_DROPPED.fetch_add(1, Ordering::Relaxed);
+        count_stat!(LogDropped);
+    }
+}
+
+/// Writes one line, counting it if it was dropped. Used by
+/// [`rprintln!`](crate::rprintln).
+pub fn log_line(args: core::fmt::Arguments) {
+    write_line_counted(&LogLine::format(args));
+}
+
+/// Queues one line in [`LOG_DEFERRED`], counting it as dropped if the
+/// queue is full. Used by [`rprintln_deferred!`](crate::rprintln_deferred).
+pub fn log_deferred(args: core::fmt::Arguments) {
+    if LOG_DEFERRED.try_send(LogLine::format(args)).is_err() {
+        LOG_DROPPED.fetch_add(1, Ordering::Relaxed);
+        count_stat!(LogDropped);
+    }
+}
+
+/// Number of dropped lines a notice should report.
+///
+/// The counters wrap, so the difference is taken modulo 2³².
+///
+/// # Arguments
+/// * `dropped` - Lines dropped since boot
+/// * `reported` - Value of `dropped` covered by the last notice
+///
+/// # Returns
+/// The lines dropped since the last notice, or `None` if there were none.
+pub fn drop_notice(dropped: u32, reported: u32) -> Option<u32> {
+    let count = dropped.wrapping_sub(reported);
+    (count != 0).then_some(count)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn long_lines_are_cut_on_a_character_boundary() {
+        let line = LogLine::format(format_args!("red: {}", 7));
+        assert_eq!(line.as_str(), "red: 7\n");
+        assert!(!line.is_truncated());
+
+        // A line that just fits is kept whole.
+        let fits = LogLine::format(format_args!("{:x<1$}", "", LOG_LINE_MAX - 1));
+        assert_eq!(fits.as_str().len(), LOG_LINE_MAX);
+        assert!(!fits.is_truncated());
+
+        // One byte more and the end is replaced by the marker.
+        let long = LogLine::format(format_args!("{:x<1$}", "", LOG_LINE_MAX));
+        assert!(long.is_truncated());
+        assert_eq!(long.as_str().len(), LOG_LINE_MAX);
+        assert!(long.as_str().ends_with("xx[...]\n"));
+
+        // Multi-byte characters are never split.
+        let wide = LogLine::format(format_args!("{:\u{b5}<1$}", "", LOG_LINE_MAX));
+        assert!(wide.is_truncated());
+        assert!(wide.as_str().ends_with("\u{b5}[...]\n"));
+        assert!(wide.as_str().len() <= LOG_LINE_MAX);
+    }
+
+    #[test]
+    fn host_is_absent_only_while_output_goes_unread() {
+        let mut host = HostMonitor::new(Duration::from_millis(1_000));
+        // Nothing pending: nothing to read, so no sign of absence.
+        assert!(host.observe(0, 0, 0));
+        // Bytes pending and the read offset stuck: present until the grace
+        // period since the last progress runs out.
+        assert!(host.observe(10, 100, 0));
+        assert!(host.observe(999, 160, 0));
+        assert!(!host.observe(1_000, 160, 0));
+        assert!(!host.present());
+        // Still stuck much later.
+        assert!(!host.observe(60_000, 160, 0));
+        // A host attaches and starts reading: present again at once.
+        assert!(host.observe(60_010, 160, 64));
+        // Progress restarts the grace period.
+        assert!(host.observe(61_009, 200, 64));
+        assert!(!host.observe(61_010, 200, 64));
+        // The read offset wrapping around the ring counts as progress too.
+        assert!(host.observe(62_000, 8, 1_000));
+        assert!(host.observe(62_500, 40, 8));
+        // A host that catches up completely is present, whatever the time.
+        assert!(host.observe(99_000, 40, 40));
+    }
+
+    #[test]
+    fn drop_notice_counts_across_the_wrap() {
+        // Nothing dropped: no notice.
+        assert_eq!(drop_notice(0, 0), None);
+        assert_eq!(drop_notice(17, 17), None);
+        assert_eq!(drop_notice(12, 5), Some(7));
+        // Across the wrap of the counter.
+        assert_eq!(drop_notice(3, u32::MAX - 1), Some(5));
+        assert_eq!(drop_notice(u32::MAX, u32::MAX), None);
+    }
+}
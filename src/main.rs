@@ -21,166 +21,55 @@
 //!
 //! ## Architecture
 //!
-//! The application uses a modular design with three main components:
-//! - [`knob`] module: Handles analog input from potentiometer
-//! - [`rgb`] module: Manages RGB LED PWM control
-//! - [`ui`] module: Processes button inputs and user interface logic
-//!
-//! Shared state is managed through async-safe mutexes for thread-safe access
-//! across the concurrent tasks.
+//! Everything that doesn't touch the hardware is in the `mb2_embassy_rgb`
+//! library (`src/lib.rs`), which also builds and tests on the host: the
+//! shared state, knob mapping, the UI state machine, PWM timing, the
+//! console and protocol parsers and the wire and flash formats. This
+//! binary adds the [`board`] modules, which hold the peripheral drivers and
+//! the Embassy tasks, and `main`, which sets up the peripherals and spawns
+//! the tasks the enabled features ask for.
 
 #![no_std]
 #![no_main]
 
-mod knob;
-mod rgb;
-mod ui;
-pub use knob::*;
-pub use rgb::*;
-pub use ui::*;
+mod board;
+pub use board::*;
+pub use mb2_embassy_rgb::*;
 
 use panic_rtt_target as _;
-use rtt_target::{rprintln, rtt_init_print};
+use rtt_target::{rtt_init, set_print_channel};
 
 use embassy_executor::Spawner;
 use embassy_futures::join;
 use embassy_sync::{blocking_mutex::raw::ThreadModeRawMutex, mutex::Mutex};
-use embassy_time::Timer;
+use embassy_time::{Duration, Instant, Timer};
 use microbit_bsp::{
     embassy_nrf::{
         bind_interrupts,
-        gpio::{AnyPin, Level, Output, OutputDrive},
-        saadc,
+        gpio::{AnyPin, Flex, Level, Output, OutputDrive},
+        peripherals::{TWISPI0, TWISPI1, UARTE0},
+        saadc, temp, twim, uarte,
     },
     Button, Microbit,
 };
 use num_traits::float::FloatCore;
 
-/// Global RGB LED intensity levels shared across all tasks.
-///
-/// This mutex-protected array contains the current intensity values for each LED channel:
-/// - Index 0: Red channel intensity (0 to [`LEVELS`]-1)
-/// - Index 1: Green channel intensity (0 to [`LEVELS`]-1)  
-/// - Index 2: Blue channel intensity (0 to [`LEVELS`]-1)
-///
-/// The values are used by the RGB module for PWM control and modified by the UI module
-/// based on user input from the knob and buttons.
-pub static RGB_LEVELS: Mutex<ThreadModeRawMutex, [u32; 3]> = Mutex::new([0; 3]);
-/// Global frame rate setting for RGB LED refresh rate.
-///
-/// This mutex-protected value controls how frequently the RGB LEDs are updated,
-/// measured in frames per second (Hz). Higher values provide smoother transitions
-/// but increase CPU usage. The frame rate can be adjusted through the UI.
-///
-/// Default value: 100 Hz
-pub static FRAME_RATE: Mutex<ThreadModeRawMutex, u64> = Mutex::new(100);
-/// Maximum intensity levels for each RGB channel.
-///
-/// This constant defines the number of discrete intensity steps available
-/// for each LED channel, providing 16 levels from 0 (off) to 15 (maximum brightness).
-/// The actual PWM duty cycle is calculated as `level / LEVELS`.
-pub const LEVELS: u32 = 16;
-/// Retrieves the current RGB LED intensity levels.
-///
-/// This is a convenience function that safely accesses the shared [`RGB_LEVELS`] state.
-///
-/// # Returns
-///
-/// An array of three `u32` values representing the current intensity levels
-/// for red, green, and blue channels respectively.
-///
-/// # Examples
-///
-/// ```rust,no_run
-/// let [red, green, blue] = get_rgb_levels().await;
-/// println!("Current RGB: R={}, G={}, B={}", red, green, blue);
-/// ```
-async fn get_rgb_levels() -> [u32; 3] {
-    let rgb_levels = RGB_LEVELS.lock().await;
-    *rgb_levels
-}
-
-/// Updates the RGB LED intensity levels using a closure.
-///
-/// This function provides safe, atomic access to modify the shared [`RGB_LEVELS`] state.
-/// The provided closure receives a mutable reference to the RGB levels array.
-///
-/// # Parameters
-///
-/// * `setter` - A closure that receives `&mut [u32; 3]` to modify the RGB levels
-///
-/// # Examples
-///
-/// ```rust,no_run
-/// // Set red to maximum, others to zero
-/// set_rgb_levels(|levels| {
-///     levels[0] = LEVELS - 1;  // Red
-///     levels[1] = 0;           // Green  
-///     levels[2] = 0;           // Blue
-/// }).await;
-///
-/// // Increment blue channel (with bounds checking)
-/// set_rgb_levels(|levels| {
-///     if levels[2] < LEVELS - 1 {
-///         levels[2] += 1;
-///     }
-/// }).await;
-/// ```
-async fn set_rgb_levels<F>(setter: F)
-where
-    F: FnOnce(&mut [u32; 3]),
-{
-    let mut rgb_levels = RGB_LEVELS.lock().await;
-    setter(&mut rgb_levels);
-}
-///
-/// This is a convenience function that safely accesses the shared [`FRAME_RATE`] state.
-///
-/// # Returns
-///
-/// The current frame rate in Hz as a `u64` value.
-///
-/// # Examples
-///
-/// ```rust,no_run
-/// let current_fps = get_frame_rate().await;
-/// println!("Running at {} FPS", current_fps);
-/// ```
-async fn get_frame_rate() -> u64 {
-    let frame_rate = FRAME_RATE.lock().await;
-    *frame_rate
-}
-/// Updates the frame rate setting using a closure.
-///
-/// This function provides safe, atomic access to modify the shared [`FRAME_RATE`] state.
-/// The provided closure receives a mutable reference to the frame rate value.
-///
-/// # Parameters
-///
-/// * `setter` - A closure that receives `&mut u64` to modify the frame rate
-///
-/// # Examples
-///
-/// ```rust,no_run
-/// // Set frame rate to 60 Hz
-/// set_frame_rate(|fps| *fps = 60).await;
-///
-/// // Double the current frame rate
-/// set_frame_rate(|fps| *fps *= 2).await;
-/// ```
-async fn set_frame_rate<F>(setter: F)
-where
-    F: FnOnce(&mut u64),
-{
-    let mut frame_rate = FRAME_RATE.lock().await;
-    setter(&mut frame_rate);
-}
+// Interrupt bindings for the peripherals used by the application. Bound at
+// crate level so tasks that re-create a driver (such as the DMX output) can
+// name them.
+bind_interrupts!(pub struct Irqs {
+    SAADC => saadc::InterruptHandler;
+    TEMP => temp::InterruptHandler;
+    SPIM0_SPIS0_TWIM0_TWIS0_SPI0_TWI0 => twim::InterruptHandler<TWISPI0>;
+    SPIM1_SPIS1_TWIM1_TWIS1_SPI1_TWI1 => twim::InterruptHandler<TWISPI1>;
+    UARTE0_UART0 => uarte::InterruptHandler<UARTE0>;
+});
 /// Main application entry point.
 ///
 /// Initializes all hardware peripherals and spawns the main application tasks:
 ///
 /// 1. **Hardware Initialization**:
-///    - Sets up RTT for debug printing
+///    - Sets up RTT for debug printing and console input
 ///    - Configures GPIO pins for RGB LEDs (P9=Red, P8=Green, P16=Blue)
 ///    - Initializes 14-bit SAADC for analog input on P2
 ///    - Configures buttons A and B for user input
@@ -189,13 +78,15 @@ where
 ///    - Creates and runs the RGB LED control task
 ///    - Creates and runs the UI input processing task
 ///    - Both tasks run concurrently using `embassy_futures::join`
+///    - The console and feature-gated background tasks (such as ambient
+///      sensing) are spawned
 ///
 /// The function runs indefinitely, and if both tasks somehow complete,
 /// it will panic with an error message.
 ///
 /// # Parameters
 ///
-/// * `_spawner` - Embassy spawner for creating additional tasks (unused in this implementation)
+/// * `spawner` - Embassy spawner for the console and feature-gated background tasks
 ///
 /// # Panics
 ///
@@ -209,31 +100,231 @@ where
 /// - Potentiometer connected to analog pin P2
 /// - Built-in buttons A and B functional
 #[embassy_executor::main]
-async fn main(_spawner: Spawner) -> ! {
-    rtt_init_print!();
+async fn main(spawner: Spawner) -> ! {
+    let channels = rtt_init! {
+        up: {
+            0: { size: 1024, name: "Terminal" }
+            1: { size: 256, name: "Protocol" }
+            2: { size: 1024, name: "Telemetry" }
+        }
+        down: {
+            0: { size: 64, name: "Terminal" }
+            1: { size: 64, name: "Protocol" }
+        }
+    };
+    set_print_channel(channels.up.0);
+    // SAFETY: channel 0 is the terminal set up just above.
+    unsafe { init_logging() };
     let board = Microbit::default();
 
-    bind_interrupts!(struct Irqs {
-        SAADC => saadc::InterruptHandler;
-    });
-
-    let led_pin = |p| Output::new(p, Level::Low, OutputDrive::Standard);
-    let red = led_pin(AnyPin::from(board.p9));
-    let green = led_pin(AnyPin::from(board.p8));
-    let blue = led_pin(AnyPin::from(board.p16));
+    // Settings saved with `SAVE` replace the power-on values before
+    // anything reads them.
+    let settings = load_settings(&*SETTINGS_STORAGE.lock().await);
+    set_rgb_levels(|rgb| *rgb = settings.levels).await;
+    set_frame_rate(StateSource::Ui, |rate| *rate = settings.frame_rate).await;
+    set_master_brightness(|b| *b = settings.brightness).await;
+    set_output_enabled(settings.output_enabled).await;
     let initial_frame_rate = get_frame_rate().await;
-    let rgb: Rgb = Rgb::new([red, green, blue], initial_frame_rate);
+    #[cfg(not(feature = "hwpwm"))]
+    let rgb: Rgb = {
+        use microbit_bsp::embassy_nrf::gpio::Pull;
+        // Outputs with the input buffer on, so the self-test can read them.
+        let led_pin = |p| {
+            let mut pin = Flex::new(p);
+            pin.set_low();
+            pin.set_as_input_output(Pull::None, OutputDrive::Standard);
+            pin
+        };
+        let red = led_pin(AnyPin::from(board.p9));
+        let green = led_pin(AnyPin::from(board.p8));
+        let blue = led_pin(AnyPin::from(board.p16));
+        Rgb::new([red, green, blue], initial_frame_rate)
+    };
+    // The camera trigger goes to P12; change the pin here to move it.
+    #[cfg(all(feature = "camsync", feature = "hwpwm"))]
+    compile_error!("`camsync` needs the software PWM scan, not `hwpwm`");
+    #[cfg(feature = "camsync")]
+    let rgb = {
+        use microbit_bsp::embassy_nrf::gpio::{Input, Pull};
+        rgb.with_trigger(Input::new(AnyPin::from(board.p12), Pull::Down))
+    };
+    // The benchmark marker is P13, jumpered to the capture input on P14.
+    #[cfg(all(feature = "bench", feature = "hwpwm"))]
+    compile_error!("`bench` measures the software PWM scan, not `hwpwm`");
+    #[cfg(all(feature = "bench", feature = "bargraph"))]
+    compile_error!("the `bench` feature needs P13 and P14, like `bargraph`");
+    #[cfg(feature = "bench")]
+    let rgb = rgb.with_bench(BenchProbe::new(
+        AnyPin::from(board.p13),
+        AnyPin::from(board.p14),
+    ));
+    // Measured before any other task runs, so nothing disturbs the samples.
+    #[cfg(not(feature = "hwpwm"))]
+    let rgb = rgb.with_timer_overhead(calibrate_timer_overhead().await);
+    // The analog LED uses the same pins, driven by hardware PWM.
+    #[cfg(feature = "hwpwm")]
+    let rgb: Rgb = {
+        let pwm = microbit_bsp::embassy_nrf::pwm::SimplePwm::new_3ch(
+            board.pwm1, board.p9, board.p8, board.p16,
+        );
+        Rgb::new(pwm, initial_frame_rate)
+    };
 
-    let mut saadc_config = saadc::Config::default();
-    saadc_config.resolution = saadc::Resolution::_14BIT;
-    let saadc = saadc::Saadc::new(
+    // The microphone's supply pin must stay driven high for as long as the
+    // firmware runs.
+    #[cfg(feature = "mic")]
+    let _mic_power = Output::new(board.micen, Level::High, OutputDrive::HighDrive);
+    let saadc = new_saadc(
         board.saadc,
-        Irqs,
-        saadc_config,
-        [saadc::ChannelConfig::single_ended(board.p2)],
+        board.p2,
+        #[cfg(feature = "mic")]
+        board.microphone,
     );
-    let knob = Knob::new(saadc).await;
-    let mut ui = Ui::new(knob, board.btn_a, board.btn_b);
+    // TIMER0 paces the scans of a knob burst through two PPI channels.
+    let adc = Adc::new(saadc, board.timer0, (board.ppi_ch0, board.ppi_ch1));
+    let knob = Knob::new(adc).await;
+    let mut ui = Ui::new(
+        knob,
+        board.btn_a,
+        board.btn_b,
+        CHORD_ACTION,
+        START_PARAMETER,
+    )
+    .with_settings(settings);
+    if TOUCH_ACTION.is_some() {
+        // SAFETY: the BSP leaves P1.04, the touch logo, unclaimed, and
+        // nothing else uses it.
+        let pin = unsafe { microbit_bsp::embassy_nrf::peripherals::P1_04::steal() };
+        ui = ui.with_touch(TouchLogo::new(Flex::new(AnyPin::from(pin))));
+    }
+
+    rprintln!("{}", get_flash_wear().await);
+    spawner.spawn(console_task(channels.down.0)).unwrap();
+    spawner.spawn(log_task()).unwrap();
+    if let Some(heartbeat) = STATS_HEARTBEAT {
+        spawner.spawn(stats_task(heartbeat)).unwrap();
+    }
+    #[cfg(feature = "protocol")]
+    spawner
+        .spawn(protocol_task(channels.down.1, channels.up.1))
+        .unwrap();
+    #[cfg(feature = "telemetry")]
+    spawner.spawn(telemetry_task(channels.up.2)).unwrap();
+    #[cfg(feature = "changelog")]
+    spawner.spawn(changelog_task()).unwrap();
+    // Bar graph shift registers: data on P15, shift clock on P13, latch on
+    // P14; change the pins here to wire them elsewhere.
+    #[cfg(feature = "bargraph")]
+    {
+        let bars = ShiftRegister::new(
+            AnyPin::from(board.p15),
+            AnyPin::from(board.p13),
+            AnyPin::from(board.p14),
+        );
+        spawner.spawn(bargraph_task(bars)).unwrap();
+    }
+    #[cfg(feature = "ambient")]
+    spawner.spawn(ambient_task()).unwrap();
+    #[cfg(feature = "schedule")]
+    spawner.spawn(schedule_task()).unwrap();
+    #[cfg(feature = "testpattern")]
+    spawner.spawn(testpattern_task()).unwrap();
+    #[cfg(feature = "idle")]
+    spawner.spawn(idle_task()).unwrap();
+    #[cfg(feature = "sleep")]
+    spawner.spawn(sleep_task()).unwrap();
+    #[cfg(feature = "radio")]
+    spawner.spawn(radio_task(board.radio)).unwrap();
+    #[cfg(feature = "radio")]
+    spawner.spawn(matrix_task()).unwrap();
+    // DMX goes out on edge-connector P0 to the RS-485 transceiver's DI.
+    #[cfg(feature = "dmx")]
+    spawner
+        .spawn(dmx_task(board.uarte0, AnyPin::from(board.p0)))
+        .unwrap();
+    // The IR receiver output goes to edge-connector P1; change the pin here
+    // to wire it elsewhere.
+    #[cfg(feature = "ir")]
+    {
+        use microbit_bsp::embassy_nrf::gpio::{Input, Pull};
+        let ir = Input::new(AnyPin::from(board.p1), Pull::Up);
+        spawner.spawn(ir_task(ir)).unwrap();
+    }
+    #[cfg(feature = "temperature")]
+    spawner
+        .spawn(temperature_task(temp::Temp::new(board.temp, Irqs)))
+        .unwrap();
+    #[cfg(feature = "compass")]
+    {
+        let i2c = twim::Twim::new(
+            board.twispi0,
+            Irqs,
+            board.i2c_int_sda,
+            board.i2c_int_scl,
+            twim::Config::default(),
+        );
+        spawner.spawn(compass_task(i2c)).unwrap();
+    }
+    // The accelerometer shares the internal I2C bus, and its TWIM instance,
+    // with the compass.
+    #[cfg(all(feature = "compass", feature = "motion"))]
+    compile_error!("the `compass` and `motion` features both need TWISPI0");
+    #[cfg(feature = "motion")]
+    {
+        let i2c = twim::Twim::new(
+            board.twispi0,
+            Irqs,
+            board.i2c_int_sda,
+            board.i2c_int_scl,
+            twim::Config::default(),
+        );
+        spawner.spawn(motion_task(i2c)).unwrap();
+    }
+    // The OLED and PCA9685 sit on the edge-connector I2C bus (P19 = SCL,
+    // P20 = SDA); change the pins here to wire them elsewhere. They share
+    // one TWIM instance, so only one of them can be enabled.
+    #[cfg(all(feature = "oled", feature = "pca9685"))]
+    compile_error!("the `oled` and `pca9685` features both need TWISPI1");
+    #[cfg(feature = "oled")]
+    {
+        let i2c = twim::Twim::new(
+            board.twispi1,
+            Irqs,
+            board.p20,
+            board.p19,
+            twim::Config::default(),
+        );
+        spawner.spawn(oled_task(i2c)).unwrap();
+    }
+    #[cfg(feature = "pca9685")]
+    {
+        let i2c = twim::Twim::new(
+            board.twispi1,
+            Irqs,
+            board.p20,
+            board.p19,
+            twim::Config::default(),
+        );
+        spawner.spawn(pca9685_task(i2c)).unwrap();
+    }
+    #[cfg(all(feature = "bh1750", any(feature = "oled", feature = "pca9685")))]
+    compile_error!("the `bh1750` feature needs TWISPI1, like `oled` and `pca9685`");
+    #[cfg(feature = "bh1750")]
+    {
+        let i2c = twim::Twim::new(
+            board.twispi1,
+            Irqs,
+            board.p20,
+            board.p19,
+            twim::Config::default(),
+        );
+        spawner.spawn(bh1750_task(i2c)).unwrap();
+    }
+    #[cfg(feature = "tone")]
+    {
+        let speaker = microbit_bsp::embassy_nrf::pwm::SimplePwm::new_1ch(board.pwm0, board.speaker);
+        spawner.spawn(tone_task(speaker)).unwrap();
+    }
 
     join::join(rgb.run(), ui.run()).await;
 
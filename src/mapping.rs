@@ -0,0 +1,457 @@
+//! # Knob Mapping Module
+//!
+//! This module maps knob levels (0 to [`LEVELS`]-1, after the dead zone and
+//! curve of the parameter's [`KnobResponse`]) onto the range of each
+//! [`ControlParameter`], and back again. It is pure, with no state or
+//! hardware, so the properties every mapping must keep can be checked by
+//! sweeping it:
+//!
+//! - **Bounds**: Every output lies within [`knob_bounds`]
+//! - **Monotonic**: Turning the knob up never lowers the value
+//! - **Endpoints**: The bottom and top of the travel reach both bounds,
+//!   even on a pot covering only 5% to 95% of full scale
+//! - **Round trip**: [`unmap_knob_value`] takes a value back to the knob
+//!   level that produces it, so knob → value → knob is stable (for the
+//!   full ranges; a narrow window maps several levels to one value)
+//!
+//! The knob sweep in this module's tests runs the whole raw ADC
+//! range through [`KNOB_CONFIG`] and [`KNOB_RANGES`] for every parameter,
+//! so a change to the config or to a mapping is covered without touching
+//! the checks.
+//!
+//! ## Knob Windows
+//!
+//! An installer can lock a parameter to a safe window with a
+//! [`KnobRange`] in [`KNOB_RANGES`], such as 30 to 60 FPS: the knob's
+//! whole travel then spans just that window, evenly and end to end, for
+//! finer control within it. Other writers (the console, remote, or
+//! protocol) are not limited by it.
+//!
+//! ## Range Report
+//!
+//! [`value_ranges`] lists the same bounds, plus the master brightness, for
+//! host tools; the console's `RANGES` command prints them one per line as
+//! `key=low..high`.
+use crate::*;
+
+/// Valid values of one setting, as reported to host tools.
+///
+/// Displays as `key=low..high`, both ends inclusive.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ValueRange {
+    /// Name of the setting.
+    pub key: &'static str,
+    /// Smallest valid value.
+    pub low: u32,
+    /// Largest valid value.
+    pub high: u32,
+}
+
+impl core::fmt::Display for ValueRange {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "{}={}..{}", self.key, self.low, self.high)
+    }
+}
+
+/// Window of a parameter's range that the knob's full travel covers.
+///
+/// Only exists with `min < max`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct KnobRange {
+    min: u32,
+    max: u32,
+}
+
+impl KnobRange {
+    /// Creates a window.
+    ///
+    /// # Returns
+    /// `None` unless `min < max`.
+    pub const fn try_new(min: u32, max: u32) -> Option<Self> {
+        if min < max {
+            Some(Self { min, max })
+        } else {
+            None
+        }
+    }
+    /// Creates a window, failing the build (in a `const`) unless
+    /// `min < max`.
+    pub const fn new(min: u32, max: u32) -> Self {
+        match Self::try_new(min, max) {
+            Some(range) => range,
+            None => panic!("a knob range needs min < max"),
+        }
+    }
+    /// Value at the bottom of the knob's travel.
+    pub const fn min(&self) -> u32 {
+        self.min
+    }
+    /// Value at the top of the knob's travel.
+    pub const fn max(&self) -> u32 {
+        self.max
+    }
+}
+
+/// Knob window of each parameter; `None` covers its whole range.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct KnobRanges {
+    /// Red level window.
+    pub red: Option<KnobRange>,
+    /// Green level window.
+    pub green: Option<KnobRange>,
+    /// Blue level window.
+    pub blue: Option<KnobRange>,
+    /// Frame rate window, in FPS.
+    pub frame_rate: Option<KnobRange>,
+    /// Saturation window.
+    pub saturation: Option<KnobRange>,
+}
+
+impl KnobRanges {
+    /// Every parameter over its whole range.
+    pub const FULL: Self = Self {
+        red: None,
+        green: None,
+        blue: None,
+        frame_rate: None,
+        saturation: None,
+    };
+
+    /// Window of one parameter.
+    pub const fn range(&self, parameter: ControlParameter) -> Option<KnobRange> {
+        match parameter {
+            ControlParameter::Red => self.red,
+            ControlParameter::Green => self.green,
+            ControlParameter::Blue => self.blue,
+            ControlParameter::FrameRate => self.frame_rate,
+            ControlParameter::Saturation => self.saturation,
+        }
+    }
+
+    /// Whether every window lies within its parameter's
+    /// [`parameter_bounds`].
+    pub const fn within_bounds(&self) -> bool {
+        let mut i = 0;
+        while i < ControlParameter::ALL.len() {
+            let parameter = ControlParameter::ALL[i];
+            if let Some(range) = self.range(parameter) {
+                let (low, high) = parameter_bounds(parameter);
+                if range.min < low || range.max > high {
+                    return false;
+                }
+            }
+            i += 1;
+        }
+        true
+    }
+}
+
+/// Smallest and largest value a parameter can take.
+///
+/// # Returns
+/// `(low, high)`: [`FRAME_RATE_MIN`] to [`FRAME_RATE_MAX`] for the frame
+/// rate, 0 to [`LEVELS`]-1 for the colors and saturation.
+pub const fn parameter_bounds(parameter: ControlParameter) -> (u32, u32) {
+    match parameter {
+        ControlParameter::FrameRate => (FRAME_RATE_MIN as u32, FRAME_RATE_MAX as u32),
+        ControlParameter::Blue
+        | ControlParameter::Green
+        | ControlParameter::Red
+        | ControlParameter::Saturation => (0, LEVELS - 1),
+    }
+}
+
+/// Smallest and largest value the knob reaches for a parameter.
+///
+/// # Returns
+/// The parameter's [`KNOB_RANGES`] window, or its [`parameter_bounds`]
+/// without one.
+pub fn knob_bounds(parameter: ControlParameter) -> (u32, u32) {
+    match KNOB_RANGES.range(parameter) {
+        Some(range) => (range.min, range.max),
+        None => parameter_bounds(parameter),
+    }
+}
+
+/// Name of a parameter in the range report.
+pub fn parameter_key(parameter: ControlParameter) -> &'static str {
+    match parameter {
+        ControlParameter::Red => "red",
+        ControlParameter::Green => "green",
+        ControlParameter::Blue => "blue",
+        ControlParameter::FrameRate => "fps",
+        ControlParameter::Saturation => "saturation",
+    }
+}
+
+/// Lists the valid range of every setting a host can change.
+///
+/// # Returns
+/// One [`ValueRange`] per [`ControlParameter`], from [`parameter_bounds`],
+/// then the master brightness, 0 to [`BRIGHTNESS_MAX`].
+pub fn value_ranges() -> [ValueRange; ControlParameter::ALL.len() + 1] {
+    let mut ranges = [ValueRange {
+        key: "brightness",
+        low: 0,
+        high: BRIGHTNESS_MAX,
+    }; ControlParameter::ALL.len() + 1];
+    for (range, parameter) in ranges.iter_mut().zip(ControlParameter::ALL) {
+        let (low, high) = parameter_bounds(parameter);
+        *range = ValueRange {
+            key: parameter_key(parameter),
+            low,
+            high,
+        };
+    }
+    ranges
+}
+
+/// Maps a knob level to a parameter's range, or to its [`KNOB_RANGES`]
+/// window if it has one (see [`map_knob_value_in`]).
+///
+/// # Arguments
+/// * `knob_value` - Knob level (0 to [`LEVELS`]-1)
+/// * `parameter` - Target parameter to map to
+///
+/// # Returns
+/// Mapped value in the appropriate range:
+/// - Frame rate: 10-160 FPS in steps of [`FRAME_RATE_STEP`]
+/// - RGB and saturation: 0-15 (unchanged)
+///
+/// A knob value past [`LEVELS`]-1 (e.g. from a remote) is clamped first.
+pub fn map_knob_value(knob_value: u32, parameter: ControlParameter) -> u32 {
+    map_knob_value_in(knob_value, parameter, &KNOB_RANGES)
+}
+
+/// Maps a knob level to a parameter's value with the given knob windows.
+///
+/// # Arguments
+/// * `knob_value` - Knob level (0 to [`LEVELS`]-1)
+/// * `parameter` - Target parameter to map to
+/// * `ranges` - Knob windows; a parameter without one gets its full range
+///
+/// # Returns
+/// With a window, the travel spread evenly from its `min` to its `max`
+/// (rounded, and kept within [`parameter_bounds`]); otherwise as
+/// [`map_knob_value`] describes.
+pub fn map_knob_value_in(knob_value: u32, parameter: ControlParameter, ranges: &KnobRanges) -> u32 {
+    let knob_value = knob_value.min(LEVELS - 1);
+    if let Some(range) = ranges.range(parameter) {
+        let (low, high) = parameter_bounds(parameter);
+        let (min, max) = (range.min.clamp(low, high), range.max.clamp(low, high));
+        let span = max.saturating_sub(min);
+        return min + (span * knob_value + (LEVELS - 1) / 2) / (LEVELS - 1);
+    }
+    match parameter {
+        ControlParameter::FrameRate => knob_value
+            .saturating_mul(FRAME_RATE_STEP as u32)
+            .saturating_add(FRAME_RATE_MIN as u32)
+            .min(FRAME_RATE_MAX as u32),
+        ControlParameter::Blue
+        | ControlParameter::Green
+        | ControlParameter::Red
+        | ControlParameter::Saturation => knob_value,
+    }
+}
+
+/// Finds the knob level for a parameter value, the inverse of
+/// [`map_knob_value`].
+///
+/// # Arguments
+/// * `value` - Parameter value; clamped to [`parameter_bounds`]
+/// * `parameter` - Parameter the value belongs to
+///
+/// # Returns
+/// The highest knob level whose mapped value doesn't exceed `value` (0
+/// below a [`KNOB_RANGES`] window).
+pub fn unmap_knob_value(value: u32, parameter: ControlParameter) -> u32 {
+    if KNOB_RANGES.range(parameter).is_some() {
+        return (0..LEVELS)
+            .rev()
+            .find(|&knob_value| map_knob_value(knob_value, parameter) <= value)
+            .unwrap_or(0);
+    }
+    let (low, high) = parameter_bounds(parameter);
+    let value = value.clamp(low, high);
+    let knob_value = match parameter {
+        ControlParameter::FrameRate => (value - low) / FRAME_RATE_STEP as u32,
+        ControlParameter::Blue
+        | ControlParameter::Green
+        | ControlParameter::Red
+        | ControlParameter::Saturation => value,
+    };
+    knob_value.min(LEVELS - 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn knob_range_rejects_empty_windows() {
+        let fps = KnobRange::try_new(30, 60).unwrap();
+        assert_eq!((fps.min(), fps.max()), (30, 60));
+        // An empty or reversed window is rejected.
+        assert_eq!(KnobRange::try_new(30, 30), None);
+        assert_eq!(KnobRange::try_new(60, 30), None);
+    }
+
+    #[test]
+    fn ranges_within_bounds() {
+        let window = KnobRanges {
+            frame_rate: Some(KnobRange::new(30, 60)),
+            ..KnobRanges::FULL
+        };
+        assert!(window.within_bounds());
+        let too_fast = KnobRanges {
+            frame_rate: Some(KnobRange::new(30, FRAME_RATE_MAX as u32 + 1)),
+            ..KnobRanges::FULL
+        };
+        assert!(!too_fast.within_bounds());
+        let too_bright = KnobRanges {
+            red: Some(KnobRange::new(0, LEVELS)),
+            ..KnobRanges::FULL
+        };
+        assert!(!too_bright.within_bounds());
+    }
+
+    #[test]
+    fn value_ranges_match_the_mapping() {
+        let ranges = value_ranges();
+        // Each parameter's range is exactly what the full knob mapping reaches.
+        for (range, parameter) in ranges.iter().zip(ControlParameter::ALL) {
+            assert_eq!(range.key, parameter_key(parameter));
+            assert_eq!(
+                range.low,
+                map_knob_value_in(0, parameter, &KnobRanges::FULL)
+            );
+            assert_eq!(
+                range.high,
+                map_knob_value_in(LEVELS - 1, parameter, &KnobRanges::FULL)
+            );
+        }
+        // And it matches the constants behind the mapping.
+        let fps = ranges.iter().find(|range| range.key == "fps").unwrap();
+        assert_eq!(
+            (fps.low, fps.high),
+            (FRAME_RATE_MIN as u32, FRAME_RATE_MAX as u32)
+        );
+        let red = ranges.iter().find(|range| range.key == "red").unwrap();
+        assert_eq!((red.low, red.high), (0, LEVELS - 1));
+        let brightness = ranges.last().unwrap();
+        assert_eq!(
+            *brightness,
+            ValueRange {
+                key: "brightness",
+                low: 0,
+                high: BRIGHTNESS_MAX
+            }
+        );
+        // Keys are unique.
+        for (i, a) in ranges.iter().enumerate() {
+            assert!(ranges[i + 1..].iter().all(|b| b.key != a.key));
+        }
+    }
+
+    #[test]
+    fn knob_sweep_covers_each_parameter() {
+        for parameter in ControlParameter::ALL {
+            let response = KNOB_CONFIG.response(parameter);
+            let (low, high) = knob_bounds(parameter);
+            // Sweep the whole travel: in bounds, monotonic, and from end to end.
+            let mut previous = map_knob_value(knob_level(0, response), parameter);
+            assert_eq!(previous, low);
+            for raw in 0..=KNOB_RAW_MAX {
+                let value = map_knob_value(knob_level(raw, response), parameter);
+                assert!((low..=high).contains(&value));
+                assert!(value >= previous);
+                previous = value;
+            }
+            assert_eq!(previous, high);
+            // A real pot stops short of both rails, yet still reaches both
+            // bounds: sweep pots covering as little as 5% to 95% of full scale.
+            for percent in [0, 2, 5] {
+                let bottom = (KNOB_RAW_MAX as i32 * percent / 100) as i16;
+                let top = (KNOB_RAW_MAX as i32 * (100 - percent) / 100) as i16;
+                let sweep =
+                    (bottom..=top).map(|raw| map_knob_value(knob_level(raw, response), parameter));
+                let (min, max) = sweep.fold((u32::MAX, 0), |(lo, hi), value| {
+                    (lo.min(value), hi.max(value))
+                });
+                assert_eq!(
+                    (min, max),
+                    (low, high),
+                    "{:?} over {}%..{}%",
+                    parameter,
+                    percent,
+                    100 - percent
+                );
+            }
+            // Every knob level round-trips over a full range; levels past the
+            // end stay in bounds.
+            if KNOB_RANGES.range(parameter).is_none() {
+                for knob_value in 0..LEVELS {
+                    let value = map_knob_value(knob_value, parameter);
+                    assert_eq!(unmap_knob_value(value, parameter), knob_value);
+                }
+            }
+            for knob_value in [LEVELS, u32::MAX] {
+                assert_eq!(map_knob_value(knob_value, parameter), high);
+            }
+            // Any value, even one set elsewhere, goes back to a knob level whose
+            // value is at or just below it, and that level is stable.
+            for value in low..=high {
+                let knob_value = unmap_knob_value(value, parameter);
+                let back = map_knob_value(knob_value, parameter);
+                assert!(back <= value);
+                assert_eq!(unmap_knob_value(back, parameter), knob_value);
+            }
+        }
+    }
+
+    #[test]
+    fn windows_map_the_whole_travel() {
+        let ranges = KnobRanges {
+            frame_rate: Some(KnobRange::new(30, 60)),
+            blue: Some(KnobRange::new(4, 8)),
+            ..KnobRanges::FULL
+        };
+        for (parameter, min, max) in [
+            (ControlParameter::FrameRate, 30, 60),
+            (ControlParameter::Blue, 4, 8),
+        ] {
+            // The knob's endpoints land exactly on the window's bounds...
+            assert_eq!(map_knob_value_in(0, parameter, &ranges), min);
+            assert_eq!(map_knob_value_in(LEVELS - 1, parameter, &ranges), max);
+            assert_eq!(map_knob_value_in(u32::MAX, parameter, &ranges), max);
+            // ...and everything between stays inside, in order.
+            let mut previous = min;
+            for knob_value in 0..LEVELS {
+                let value = map_knob_value_in(knob_value, parameter, &ranges);
+                assert!((min..=max).contains(&value));
+                assert!(value >= previous);
+                previous = value;
+            }
+        }
+        // 30 FPS spread over the 15 knob steps: 2 FPS a step.
+        assert_eq!(
+            map_knob_value_in(1, ControlParameter::FrameRate, &ranges),
+            32
+        );
+        // Parameters without a window keep their full range.
+        assert_eq!(
+            map_knob_value_in(LEVELS - 1, ControlParameter::Red, &ranges),
+            LEVELS - 1
+        );
+    }
+
+    #[test]
+    fn unmap_picks_the_step_below() {
+        assert_eq!(unmap_knob_value(60, ControlParameter::FrameRate), 5);
+        // Between steps, the step below.
+        assert_eq!(unmap_knob_value(65, ControlParameter::FrameRate), 5);
+        assert_eq!(unmap_knob_value(0, ControlParameter::FrameRate), 0);
+        assert_eq!(unmap_knob_value(9, ControlParameter::Red), 9);
+        assert_eq!(unmap_knob_value(99, ControlParameter::Red), LEVELS - 1);
+    }
+}
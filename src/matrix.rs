@@ -0,0 +1,95 @@
+//! # Matrix Brightness Module
+//!
+//! This module drives the 5x5 matrix's status LED at its own brightness,
+//! so feedback on the matrix can be dimmed for a dark room without
+//! touching the RGB LED.
+//!
+//! ## Status LED
+//!
+//! The top-left LED (row 1, column 1) shows the radio link (see the
+//! [`radio`](crate::radio) module). The radio code only decides whether it
+//! is lit, with [`set_link_led`]; [`matrix_task`] owns the pins and
+//! pulse-width modulates the row at [`MATRIX_PWM_PERIOD`], fast enough not
+//! to flicker. The row is only high while the task holds [`MATRIX_LOCK`],
+//! so a pulse waits out an ambient light measurement on the same matrix.
+//!
+//! ## Brightness
+//!
+//! [`MATRIX_BRIGHTNESS`] runs from 0 (off) to [`MATRIX_BRIGHTNESS_MAX`], as
+//! on the micro:bit's own display, and [`matrix_duty`] maps each step to a
+//! duty that doubles from one step to the next, so the steps look evenly
+//! spaced to the eye. `MATRIX <0-9>` changes it at runtime.
+//!
+//! With the `schedule` feature the scheduled master brightness also scales
+//! the matrix while the schedule is enabled, so it dims at night with the
+//! RGB LED; otherwise the two are independent.
+use crate::*;
+use core::sync::atomic::{AtomicBool, Ordering};
+
+/// Highest matrix brightness step.
+pub const MATRIX_BRIGHTNESS_MAX: u8 = 9;
+/// One on/off cycle of the status LED's PWM.
+pub const MATRIX_PWM_PERIOD: Duration = Duration::from_millis(5);
+/// Duty of each brightness step, in parts per thousand.
+const MATRIX_DUTY_PERMILLE: [u32; MATRIX_BRIGHTNESS_MAX as usize + 1] =
+    [0, 4, 8, 16, 31, 63, 125, 250, 500, 1000];
+
+/// Matrix brightness step, changed by the console.
+pub static MATRIX_BRIGHTNESS: Mutex<CriticalSectionRawMutex, u8> =
+    Mutex::new(MATRIX_BRIGHTNESS_MAX);
+/// Whether the status LED should be lit.
+pub static LINK_LED: AtomicBool = AtomicBool::new(false);
+
+/// Maps a brightness step to the status LED's duty.
+///
+/// # Arguments
+/// * `brightness` - Step from 0 to [`MATRIX_BRIGHTNESS_MAX`]; higher is
+///   taken as the maximum
+/// * `scale` - Further scaling, from 0 to [`BRIGHTNESS_MAX`] (full)
+///
+/// # Returns
+/// The duty in parts per thousand.
+pub fn matrix_duty(brightness: u8, scale: u32) -> u32 {
+    let step = brightness.min(MATRIX_BRIGHTNESS_MAX) as usize;
+    MATRIX_DUTY_PERMILLE[step] * scale.min(BRIGHTNESS_MAX) / BRIGHTNESS_MAX
+}
+
+/// Sets the matrix brightness step.
+///
+/// # Returns
+/// `false` (changing nothing) above [`MATRIX_BRIGHTNESS_MAX`].
+pub async fn set_matrix_brightness(brightness: u8) -> bool {
+    if brightness > MATRIX_BRIGHTNESS_MAX {
+        return false;
+    }
+    *MATRIX_BRIGHTNESS.lock().await = brightness;
+    true
+}
+
+/// Returns the matrix brightness step.
+pub async fn get_matrix_brightness() -> u8 {
+    *MATRIX_BRIGHTNESS.lock().await
+}
+
+/// Lights or clears the status LED.
+pub fn set_link_led(lit: bool) {
+    LINK_LED.store(lit, Ordering::Relaxed);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn duty_doubles_each_step_and_scales() {
+        // Each step doubles the duty, from off to fully on.
+        let duties = [0, 4, 8, 16, 31, 63, 125, 250, 500, 1000];
+        for (step, duty) in duties.into_iter().enumerate() {
+            assert_eq!(matrix_duty(step as u8, BRIGHTNESS_MAX), duty);
+        }
+        assert_eq!(matrix_duty(200, BRIGHTNESS_MAX), 1000);
+        // A scheduled night at 10% scales it down.
+        assert_eq!(matrix_duty(MATRIX_BRIGHTNESS_MAX, 10), 100);
+        assert_eq!(matrix_duty(5, 0), 0);
+    }
+}
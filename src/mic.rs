@@ -0,0 +1,147 @@
+//! # Microphone Sound-Reactive Module
+//!
+//! This module turns the micro:bit v2 onboard microphone into a brightness
+//! modulator: while sound mode is active the master brightness follows the
+//! ambient sound level around the calibrated color.
+//!
+//! ## Signal Path
+//!
+//! - **Sampling**: The microphone is scanned as a second SAADC channel
+//!   alongside the knob (see [`Knob::sample_mic`]), so mic sampling never
+//!   reconfigures the peripheral underneath the knob
+//! - **DC Removal**: A slow running average tracks the mic bias voltage
+//! - **Envelope**: A peak follower with a fast exponential attack
+//!   ([`MIC_ATTACK_SHIFT`]) and a slow exponential decay
+//!   ([`MIC_DECAY_SHIFT`]), all integer math
+//! - **Output**: The envelope is scaled by the knob-selected sensitivity into
+//!   a [`MASTER_BRIGHTNESS`] percentage
+//!
+//! ## Modes
+//!
+//! Sound-reactive brightness runs either by hand, toggled with a long A+B
+//! hold, or as the idle animation with [`Effect::SoundReactive`] (the
+//! `idle` feature), in which case it starts at [`IDLE_SOUND_SENSITIVITY`]
+//! and any input ends it.
+use crate::*;
+
+/// Number of microphone samples taken back-to-back per UI loop pass.
+pub const MIC_BURST: usize = 32;
+/// Right shift applied per sample when the envelope rises.
+///
+/// Each sample above the current level closes `1 / 2^MIC_ATTACK_SHIFT` of
+/// the gap (rounded up, so it always arrives): a clap reaches full level
+/// within a few samples, but a single-sample spike only gets part way.
+pub const MIC_ATTACK_SHIFT: u32 = 1;
+/// Right shift applied per sample when the envelope decays.
+///
+/// Each sample below the current peak removes `1 / 2^MIC_DECAY_SHIFT` of the
+/// gap, giving a release time of a few hundred milliseconds at the burst rate.
+pub const MIC_DECAY_SHIFT: u32 = 4;
+/// Right shift of the DC-tracking average.
+const MIC_BIAS_SHIFT: u32 = 8;
+/// Envelope amplitude that yields full brightness at the lowest sensitivity.
+const MIC_FULL_SCALE: u32 = 2048;
+/// Sensitivity the idle animation starts sound-reactive mode at.
+#[cfg(feature = "idle")]
+pub const IDLE_SOUND_SENSITIVITY: u32 = LEVELS / 2;
+
+/// Peak-following envelope detector for raw microphone samples.
+///
+/// Tracks the mic bias with a slow average, rectifies the remaining AC
+/// signal, and follows its peaks: rises quickly, decays slowly.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Envelope {
+    /// Running estimate of the mic bias, in SAADC counts scaled by `2^MIC_BIAS_SHIFT`.
+    bias: i32,
+    /// Whether `bias` has been seeded from a real sample yet.
+    seeded: bool,
+    /// Current envelope amplitude in SAADC counts.
+    level: u32,
+}
+
+impl Envelope {
+    /// Feeds one raw microphone sample through the follower.
+    ///
+    /// # Arguments
+    /// * `sample` - Raw SAADC reading from the microphone channel
+    ///
+    /// # Returns
+    /// The updated envelope amplitude in SAADC counts.
+    pub fn update(&mut self, sample: i16) -> u32 {
+        let sample = sample as i32;
+        if !self.seeded {
+            self.bias = sample << MIC_BIAS_SHIFT;
+            self.seeded = true;
+        }
+        self.bias += sample - (self.bias >> MIC_BIAS_SHIFT);
+        let magnitude = (sample - (self.bias >> MIC_BIAS_SHIFT)).unsigned_abs();
+        if magnitude > self.level {
+            let rise = magnitude - self.level;
+            self.level += (rise + (1 << MIC_ATTACK_SHIFT) - 1) >> MIC_ATTACK_SHIFT;
+        } else {
+            self.level -= (self.level - magnitude) >> MIC_DECAY_SHIFT;
+        }
+        self.level
+    }
+    /// Current envelope amplitude in SAADC counts.
+    pub fn level(&self) -> u32 {
+        self.level
+    }
+    /// Maps the current envelope to a master brightness percentage.
+    ///
+    /// # Arguments
+    /// * `sensitivity` - Knob level from 0 (least sensitive) to [`LEVELS`]-1
+    ///
+    /// # Returns
+    /// Brightness from 0 to [`BRIGHTNESS_MAX`]. Each sensitivity step adds
+    /// one more unit of gain, so the top step reaches full brightness at
+    /// `1 / LEVELS` of the envelope the bottom step needs.
+    pub fn brightness(&self, sensitivity: u32) -> u32 {
+        let gain = sensitivity.min(LEVELS - 1) + 1;
+        let scaled =
+            self.level as u64 * gain as u64 * BRIGHTNESS_MAX as u64 / MIC_FULL_SCALE as u64;
+        scaled.min(BRIGHTNESS_MAX as u64) as u32
+    }
+}
+
+/// Sound-reactive mode state held by the UI while the mode is active.
+pub struct SoundMode {
+    /// Envelope follower fed from the microphone.
+    pub envelope: Envelope,
+    /// Knob-selected sensitivity (0 to [`LEVELS`]-1).
+    pub sensitivity: u32,
+    /// Master brightness to restore when leaving the mode.
+    pub saved_brightness: u32,
+    /// Knob level when the idle animation started the mode, or `None` when
+    /// it was started by hand; turning the knob away from it ends idle.
+    pub idle_knob: Option<u32>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn envelope_rises_fast_and_decays_slowly() {
+        // Silence at the bias voltage is no envelope at all.
+        let mut envelope = Envelope::default();
+        for _ in 0..4 {
+            assert_eq!(envelope.update(2000), 0);
+        }
+        // A loud tone of amplitude 1000: the first sample only gets half way...
+        assert_eq!(envelope.update(3000), 499);
+        // ...and a few more reach the peak.
+        for i in 1..16 {
+            envelope.update(if i % 2 == 0 { 3000 } else { 1000 });
+        }
+        assert!(envelope.level() >= 995);
+        // Back to silence it decays far more slowly than it rose.
+        for _ in 0..16 {
+            envelope.update(2000);
+        }
+        assert!((300..400).contains(&envelope.level()));
+        // Which a higher sensitivity turns into more brightness.
+        assert!(envelope.brightness(LEVELS - 1) > envelope.brightness(0));
+        assert_eq!(envelope.brightness(LEVELS - 1), BRIGHTNESS_MAX);
+    }
+}
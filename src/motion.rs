@@ -0,0 +1,153 @@
+//! # Motion Color Module
+//!
+//! This module turns the board into a motion-controlled light: tilting it
+//! left and right sweeps the hue, and tilting it forward and back sets the
+//! brightness.
+//!
+//! ## Sensor
+//!
+//! The LSM303AGR accelerometer, on the same chip and internal I2C bus as
+//! the compass magnetometer, is driven directly in 50 Hz normal mode with
+//! block data update at ±2 g. Each axis reads as a left-justified `i16`, so
+//! one g is 16384 counts whatever the resolution.
+//!
+//! ## Tilt to Color
+//!
+//! With the board flat, gravity is all on Z and X and Y read zero; tilting
+//! moves it onto X or Y. Each is smoothed ([`MOTION_SMOOTHING`]) to steady
+//! a hand-held board, then [`tilt_levels`] maps it, with a tilt of
+//! [`TILT_FULL_SCALE`] g (about 45°) reaching the end of each range:
+//!
+//! ```text
+//! hue   = 0°..300° as X goes from -full scale to +full scale (red to magenta)
+//! value = 0..1     as Y goes from -full scale to +full scale
+//! ```
+//!
+//! The hue stops short of 360° so the two extremes are different colors.
+//!
+//! ## Modes
+//!
+//! `TILT ON` hands the levels to the tilt until `TILT OFF`, which restores
+//! the levels from before. With the `idle` feature, [`Effect::Tilt`] makes
+//! it the idle animation instead: the board follows the tilt once the
+//! controls have been left alone, until the next input.
+use crate::*;
+
+/// Delay between accelerometer reads, in milliseconds.
+pub const MOTION_PERIOD_MS: u64 = 50;
+/// Fraction of the tilt error corrected per reading.
+pub const MOTION_SMOOTHING: f32 = 0.2;
+/// Tilt, in g, at which the hue or brightness reaches the end of its range.
+pub const TILT_FULL_SCALE: f32 = 0.7;
+/// Hue at full tilt in +X, in degrees; full tilt in -X is 0°.
+const TILT_HUE_SPAN: f32 = 300.0;
+
+/// Maps a tilt to RGB levels.
+///
+/// # Arguments
+/// * `tilt` - X and Y acceleration in g; beyond ±[`TILT_FULL_SCALE`] is
+///   clamped
+///
+/// # Returns
+/// RGB levels at full saturation, hue from X and value from Y.
+pub fn tilt_levels(tilt: [f32; 2]) -> [u32; 3] {
+    let [x, y] = tilt.map(|g| (g / TILT_FULL_SCALE).clamp(-1.0, 1.0));
+    let hue = (x + 1.0) / 2.0 * TILT_HUE_SPAN;
+    let value = (y + 1.0) / 2.0;
+    hsv_to_levels(hue, 1.0, value)
+}
+
+/// Moves a smoothed tilt toward a new reading.
+///
+/// # Arguments
+/// * `current` - Current smoothed X/Y tilt in g
+/// * `reading` - New X/Y reading in g
+/// * `alpha` - Fraction of the error to correct (0.0 to 1.0)
+pub fn smooth_tilt(current: [f32; 2], reading: [f32; 2], alpha: f32) -> [f32; 2] {
+    let alpha = alpha.clamp(0.0, 1.0);
+    [0, 1].map(|axis| current[axis] + (reading[axis] - current[axis]) * alpha)
+}
+
+/// Shared tilt-mode state.
+#[derive(Debug, Clone, Copy)]
+pub struct MotionMode {
+    /// Hands the RGB levels to the tilt, saving the user's color.
+    overlay: LevelsOverlay,
+}
+
+impl MotionMode {
+    /// Whether the tilt currently owns the RGB levels.
+    pub fn enabled(&self) -> bool {
+        self.overlay.active()
+    }
+}
+
+/// Global tilt-mode state, changed by the console.
+pub static MOTION_MODE: Mutex<CriticalSectionRawMutex, MotionMode> = Mutex::new(MotionMode {
+    overlay: LevelsOverlay::OFF,
+});
+
+/// Enters or leaves tilt mode.
+///
+/// Entering saves the current RGB levels; leaving restores them
+/// (see [`LevelsOverlay`]).
+pub async fn set_tilt_mode(enabled: bool) {
+    let mut mode = MOTION_MODE.lock().await;
+    if !mode.overlay.set(enabled).await {
+        return;
+    }
+    if enabled {
+        rprintln!("Tilt mode: on");
+    } else {
+        rprintln!("Tilt mode: off");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tilt_maps_to_hue_and_brightness() {
+        // Flat: green-cyan at half brightness.
+        assert_eq!(tilt_levels([0.0, 0.0]), [0, 8, 4]);
+        // Full tilt left and forward is bright red, and tilting further
+        // changes nothing.
+        assert_eq!(
+            tilt_levels([-TILT_FULL_SCALE, TILT_FULL_SCALE]),
+            [LEVELS - 1, 0, 0]
+        );
+        assert_eq!(tilt_levels([-1.0, 1.0]), [LEVELS - 1, 0, 0]);
+        // Full tilt right is magenta, not red again.
+        assert_eq!(
+            tilt_levels([TILT_FULL_SCALE, TILT_FULL_SCALE]),
+            [LEVELS - 1, 0, LEVELS - 1]
+        );
+        // Full tilt back is off, whatever the hue.
+        assert_eq!(tilt_levels([0.3, -TILT_FULL_SCALE]), [0; 3]);
+        // Brightness rises steadily with forward tilt.
+        let mut last = 0;
+        for step in -7..=7 {
+            let peak = tilt_levels([0.0, step as f32 * 0.1])
+                .into_iter()
+                .max()
+                .unwrap();
+            assert!(peak >= last);
+            last = peak;
+        }
+    }
+
+    #[test]
+    fn held_tilt_is_reached_and_jolts_are_damped() {
+        let mut tilt = [0.0, 0.0];
+        // A one-reading jolt only moves the tilt a fifth of the way...
+        tilt = smooth_tilt(tilt, [0.5, -0.5], MOTION_SMOOTHING);
+        assert!((tilt[0] - 0.1).abs() < 1e-6 && (tilt[1] + 0.1).abs() < 1e-6);
+        tilt = smooth_tilt(tilt, [0.0, 0.0], MOTION_SMOOTHING);
+        // ...while a held tilt is reached within two seconds.
+        for _ in 0..(2000 / MOTION_PERIOD_MS) {
+            tilt = smooth_tilt(tilt, [0.5, -0.5], MOTION_SMOOTHING);
+        }
+        assert_eq!(tilt_levels(tilt), tilt_levels([0.5, -0.5]));
+    }
+}
@@ -0,0 +1,199 @@
+//! # OLED Status Display Module
+//!
+//! This module shows the current R/G/B levels, frame rate, and whether the
+//! output is blanked on a 128x64 SSD1306 OLED on the edge-connector I2C
+//! bus, so no host is needed to read the calibration.
+//!
+//! ## Driver
+//!
+//! A minimal built-in driver: the init sequence, horizontal addressing, and
+//! a full framebuffer blit in small I2C writes. The framebuffer uses the
+//! SSD1306 page layout: byte `page * 128 + x` holds the 8 vertical pixels
+//! `page * 8 .. page * 8 + 8` of column `x`, least significant bit on top.
+//!
+//! ## Rendering
+//!
+//! [`render_status`] is a pure function drawing the status text into a
+//! framebuffer with a compact 5x7 font; one text row occupies one page.
+//! The display task subscribes to the [`events`](crate::events) bus and
+//! only redraws and blits when the values change, keeping the I2C bus
+//! quiet.
+//!
+//! ## Errors
+//!
+//! I2C errors (for example, the display unplugged) are logged and the
+//! display is re-initialized on the next change; they never panic.
+
+/// Display width in pixels.
+pub const OLED_WIDTH: usize = 128;
+/// Number of 8-pixel pages (64 pixel rows).
+pub const OLED_PAGES: usize = 8;
+/// Framebuffer size in bytes.
+pub const OLED_FB_SIZE: usize = OLED_WIDTH * OLED_PAGES;
+/// Characters that fit on one text row.
+const LINE_CHARS: usize = OLED_WIDTH / (GLYPH_WIDTH + 1);
+
+/// Glyph width in columns; one blank column separates glyphs.
+const GLYPH_WIDTH: usize = 5;
+
+/// Returns the 5x7 glyph for a character, column bytes left to right.
+///
+/// Only the characters used by the status display are included; anything
+/// else renders blank.
+fn glyph(c: char) -> [u8; GLYPH_WIDTH] {
+    match c {
+        '0' => [0x3E, 0x51, 0x49, 0x45, 0x3E],
+        '1' => [0x00, 0x42, 0x7F, 0x40, 0x00],
+        '2' => [0x42, 0x61, 0x51, 0x49, 0x46],
+        '3' => [0x21, 0x41, 0x45, 0x4B, 0x31],
+        '4' => [0x18, 0x14, 0x12, 0x7F, 0x10],
+        '5' => [0x27, 0x45, 0x45, 0x45, 0x39],
+        '6' => [0x3C, 0x4A, 0x49, 0x49, 0x30],
+        '7' => [0x01, 0x71, 0x09, 0x05, 0x03],
+        '8' => [0x36, 0x49, 0x49, 0x49, 0x36],
+        '9' => [0x06, 0x49, 0x49, 0x29, 0x1E],
+        'B' => [0x7F, 0x49, 0x49, 0x49, 0x36],
+        'F' => [0x7F, 0x09, 0x09, 0x09, 0x01],
+        'G' => [0x3E, 0x41, 0x49, 0x49, 0x7A],
+        'O' => [0x3E, 0x41, 0x41, 0x41, 0x3E],
+        'P' => [0x7F, 0x09, 0x09, 0x09, 0x06],
+        'R' => [0x7F, 0x09, 0x19, 0x29, 0x46],
+        'S' => [0x46, 0x49, 0x49, 0x49, 0x31],
+        ':' => [0x00, 0x36, 0x36, 0x00, 0x00],
+        _ => [0x00; GLYPH_WIDTH],
+    }
+}
+
+/// Draws a line of text into one framebuffer page.
+///
+/// Text past the right edge is clipped.
+fn draw_text(fb: &mut [u8; OLED_FB_SIZE], page: usize, text: &str) {
+    let row = &mut fb[page * OLED_WIDTH..(page + 1) * OLED_WIDTH];
+    let columns = text
+        .chars()
+        .flat_map(|c| glyph(c).into_iter().chain(core::iter::once(0)));
+    for (pixel, column) in row.iter_mut().zip(columns) {
+        *pixel = column;
+    }
+}
+
+/// Small fixed-capacity text buffer for composing display lines.
+struct Line {
+    buf: [u8; LINE_CHARS],
+    len: usize,
+}
+
+impl Line {
+    /// Creates an empty line.
+    fn new() -> Self {
+        Self {
+            buf: [0; LINE_CHARS],
+            len: 0,
+        }
+    }
+
+    /// Returns the text written so far.
+    fn as_str(&self) -> &str {
+        core::str::from_utf8(&self.buf[..self.len]).unwrap_or("")
+    }
+}
+
+impl core::fmt::Write for Line {
+    fn write_str(&mut self, s: &str) -> core::fmt::Result {
+        let end = self.len + s.len();
+        self.buf
+            .get_mut(self.len..end)
+            .ok_or(core::fmt::Error)?
+            .copy_from_slice(s.as_bytes());
+        self.len = end;
+        Ok(())
+    }
+}
+
+/// Renders the status screen into a framebuffer.
+///
+/// Layout, one channel per text row with a blank page between rows, and
+/// `OFF` after the frame rate while the output is blanked:
+///
+/// ```text
+/// R:15
+/// G:8
+/// B:12
+/// FPS:100 OFF
+/// ```
+pub fn render_status(
+    levels: [u32; 3],
+    frame_rate: u64,
+    output_enabled: bool,
+    fb: &mut [u8; OLED_FB_SIZE],
+) {
+    use core::fmt::Write;
+    fb.fill(0);
+    for (page, (name, level)) in ["R", "G", "B"].iter().zip(levels).enumerate() {
+        let mut line = Line::new();
+        let _ = write!(line, "{}:{}", name, level);
+        draw_text(fb, page * 2, line.as_str());
+    }
+    let mut line = Line::new();
+    let _ = write!(line, "FPS:{}", frame_rate);
+    if !output_enabled {
+        let _ = write!(line, " OFF");
+    }
+    draw_text(fb, 6, line.as_str());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The columns of `text` as [`draw_text`] lays them out.
+    fn text_columns(text: &str) -> Vec<u8> {
+        text.chars()
+            .flat_map(|c| glyph(c).into_iter().chain([0]))
+            .collect()
+    }
+
+    /// One page of the framebuffer.
+    fn page(fb: &[u8; OLED_FB_SIZE], page: usize) -> &[u8] {
+        &fb[page * OLED_WIDTH..(page + 1) * OLED_WIDTH]
+    }
+
+    #[test]
+    fn status_screen_framebuffer() {
+        let mut fb = [0xAA; OLED_FB_SIZE];
+        render_status([15, 8, 12], 100, true, &mut fb);
+        // "R:15", column by column.
+        #[rustfmt::skip]
+        let red = [
+            0x7F, 0x09, 0x19, 0x29, 0x46, 0x00,
+            0x00, 0x36, 0x36, 0x00, 0x00, 0x00,
+            0x00, 0x42, 0x7F, 0x40, 0x00, 0x00,
+            0x27, 0x45, 0x45, 0x45, 0x39, 0x00,
+        ];
+        assert_eq!(page(&fb, 0)[..red.len()], red);
+        assert!(page(&fb, 0)[red.len()..].iter().all(|&column| column == 0));
+        for (index, text) in [(2, "G:8"), (4, "B:12"), (6, "FPS:100")] {
+            let columns = text_columns(text);
+            assert_eq!(page(&fb, index)[..columns.len()], columns, "{}", text);
+            assert!(page(&fb, index)[columns.len()..]
+                .iter()
+                .all(|&column| column == 0));
+        }
+        // The pages between the rows, and the bottom one, are blank.
+        for index in [1, 3, 5, 7] {
+            assert!(page(&fb, index).iter().all(|&column| column == 0));
+        }
+    }
+
+    #[test]
+    fn blanked_output_is_marked() {
+        let mut fb = [0; OLED_FB_SIZE];
+        render_status([0; 3], 60, false, &mut fb);
+        let columns = text_columns("FPS:60 OFF");
+        assert_eq!(page(&fb, 6)[..columns.len()], columns);
+        // Every letter of the marker is drawn.
+        for c in "OFF".chars() {
+            assert_ne!(glyph(c), [0; GLYPH_WIDTH], "{}", c);
+        }
+    }
+}
@@ -0,0 +1,90 @@
+//! # Levels Overlay Module
+//!
+//! The modes that take over the RGB levels for a while (temperature,
+//! compass, tilt, and the test pattern) save the user's color on entry and
+//! put it back on exit. They all do it through a [`LevelsOverlay`], as does
+//! the UI's [`FrameRateDemo`] around frame-rate adjustments.
+use crate::*;
+
+/// Save/restore of the user's color around a mode that owns the levels.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LevelsOverlay {
+    /// The user's color, saved while the mode owns the levels.
+    saved: Option<[u32; 3]>,
+}
+
+impl LevelsOverlay {
+    /// Not active, with nothing saved.
+    pub const OFF: Self = Self { saved: None };
+
+    /// Whether the mode currently owns the levels.
+    pub fn active(&self) -> bool {
+        self.saved.is_some()
+    }
+
+    /// The user's color, while the mode owns the levels.
+    pub fn saved(&self) -> Option<[u32; 3]> {
+        self.saved
+    }
+
+    /// Enters the mode.
+    ///
+    /// # Arguments
+    /// * `levels` - Levels currently shown, saved on entry
+    ///
+    /// # Returns
+    /// Whether the mode just started; `false` if it was already active.
+    pub fn enter(&mut self, levels: [u32; 3]) -> bool {
+        if self.saved.is_some() {
+            return false;
+        }
+        self.saved = Some(levels);
+        true
+    }
+
+    /// Leaves the mode.
+    ///
+    /// # Returns
+    /// The saved levels to restore, if the mode was active.
+    pub fn exit(&mut self) -> Option<[u32; 3]> {
+        self.saved.take()
+    }
+
+    /// Enters or leaves the mode, saving or restoring the shared RGB
+    /// levels.
+    ///
+    /// # Arguments
+    /// * `enabled` - `true` to enter, `false` to leave
+    ///
+    /// # Returns
+    /// Whether the mode changed.
+    pub async fn set(&mut self, enabled: bool) -> bool {
+        if enabled {
+            self.enter(get_rgb_levels().await)
+        } else if let Some(saved) = self.exit() {
+            set_rgb_levels(|rgb| *rgb = saved).await;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn overlay_restores_the_color_once() {
+        let mut overlay = LevelsOverlay::OFF;
+        assert_eq!(overlay.exit(), None);
+        assert!(overlay.enter([15, 3, 0]));
+        assert!(overlay.active());
+        // Entering again keeps the first color, not the mode's own.
+        assert!(!overlay.enter([0, 0, 15]));
+        assert_eq!(overlay.saved(), Some([15, 3, 0]));
+        assert_eq!(overlay.exit(), Some([15, 3, 0]));
+        assert!(!overlay.active());
+        assert_eq!(overlay.exit(), None);
+    }
+}
@@ -0,0 +1,103 @@
+//! # PCA9685 Output Backend Module
+//!
+//! This module mirrors the RGB output onto a PCA9685 16-channel I2C PWM
+//! controller, for high-power fixtures (such as a 12V LED bar behind
+//! MOSFETs) that can't hang off a GPIO pin. The GPIO software PWM keeps
+//! running alongside it, so the onboard LED still shows the same color.
+//!
+//! ## Wiring
+//!
+//! The chip sits on the edge-connector I2C bus (P19 = SCL, P20 = SDA) at
+//! address 0x40, with red, green, and blue on outputs 0, 1, and 2.
+//!
+//! ## Duty and Frequency
+//!
+//! From the PCA9685 datasheet (25 MHz internal oscillator):
+//!
+//! ```text
+//! prescale = round(25_000_000 / (4096 * frame_rate)) - 1   (3..=255)
+//! duty     = level * 4095 / (LEVELS - 1) * brightness / 100
+//! ```
+//!
+//! The PWM frequency follows [`FRAME_RATE`], limited to the chip's
+//! roughly 24–1526 Hz range. Duties go through the same master brightness
+//! and [`BRIGHTNESS_CEILING`] stages as the GPIO output, and the same
+//! [`FULL_ON_LIMIT`] on channels at full scale.
+//!
+//! ## Register Driver
+//!
+//! The prescaler can only be written while the oscillator sleeps, so a
+//! frequency change puts the chip to sleep, writes `PRE_SCALE`, wakes it,
+//! and waits the 500 µs oscillator start-up before restarting the PWM.
+//! I2C errors are logged and the chip is re-initialized on the next update.
+use crate::*;
+
+/// Internal oscillator frequency in Hz.
+const PCA9685_OSC_HZ: u64 = 25_000_000;
+/// PWM counter resolution (12 bits).
+pub const PCA9685_STEPS: u32 = 4096;
+/// Largest duty value that is not "full on".
+pub const PCA9685_DUTY_MAX: u32 = PCA9685_STEPS - 1;
+/// Smallest prescaler value allowed by the datasheet.
+const PRESCALE_MIN: u64 = 3;
+/// Largest prescaler value.
+const PRESCALE_MAX: u64 = 255;
+/// Full-on/full-off bit in the high byte of `ON` and `OFF`.
+const LED_FULL: u8 = 0x10;
+
+/// Computes the prescaler for a PWM frequency.
+///
+/// # Arguments
+/// * `frame_rate` - Desired PWM frequency in Hz
+///
+/// # Returns
+/// The datasheet prescale value, clamped to `3..=255`.
+pub fn pca9685_prescale(frame_rate: u64) -> u8 {
+    let divisor = PCA9685_STEPS as u64 * frame_rate.max(1);
+    // Rounded division, then the datasheet's "- 1".
+    let prescale = (PCA9685_OSC_HZ + divisor / 2) / divisor;
+    prescale.saturating_sub(1).clamp(PRESCALE_MIN, PRESCALE_MAX) as u8
+}
+
+/// Computes the 12-bit duty for every channel.
+///
+/// # Arguments
+/// * `levels` - RGB levels from 0 to `LEVELS - 1`
+/// * `brightness` - Master brightness from 0 to [`BRIGHTNESS_MAX`]
+/// * `ceiling` - Output cap as a percentage of [`BRIGHTNESS_MAX`]
+///
+/// # Returns
+/// Duties from 0 (off) to [`PCA9685_STEPS`] (full on), with no more than
+/// [`FULL_ON_LIMIT`] channels at full scale.
+pub fn pca9685_duties(levels: [u32; 3], brightness: u32, ceiling: u32) -> [u32; 3] {
+    let duties = levels.map(|level| {
+        let level = level.min(LEVELS - 1) as u64;
+        level * PCA9685_STEPS as u64 * brightness.min(BRIGHTNESS_MAX) as u64
+            / ((LEVELS - 1) as u64 * BRIGHTNESS_MAX as u64)
+    });
+    let duties = apply_ceiling(duties, PCA9685_STEPS as u64, ceiling);
+    apply_full_on_limit(duties, PCA9685_STEPS as u64, FULL_ON_LIMIT).map(|duty| duty as u32)
+}
+
+/// Encodes one output's `ON_L, ON_H, OFF_L, OFF_H` registers for a duty.
+///
+/// Every output turns on at count 0. Duty 0 and full scale use the chip's
+/// full-off and full-on bits, avoiding a one-count glitch at either end.
+pub fn pca9685_output_registers(duty: u32) -> [u8; 4] {
+    match duty {
+        0 => [0, 0, 0, LED_FULL],
+        d if d >= PCA9685_STEPS => [0, LED_FULL, 0, 0],
+        d => [0, 0, (d & 0xFF) as u8, (d >> 8) as u8],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn prescale_matches_the_datasheet() {
+        // Datasheet example: 200 Hz gives 0x1E.
+        assert_eq!(pca9685_prescale(200), 30);
+    }
+}
@@ -0,0 +1,183 @@
+//! # Power Estimation Module
+//!
+//! This module estimates the average LED supply current from what the frame
+//! loop actually drives, for rough battery planning.
+//!
+//! ## Estimation Model
+//!
+//! Each channel draws a fixed current while its LED is on, so the average
+//! current is each channel's on-current times its share of the frame:
+//!
+//! ```text
+//! current = sum(on_time / frame_time * CHANNEL_CURRENT_MA)
+//! ```
+//!
+//! The on-times are the frame loop's own (see [`fine_on_times_with`]), so
+//! the gamma curve, master brightness, brightness ceiling, and full-on
+//! limit all count, and a blanked output (brightness 0) draws nothing. The
+//! scanout in the [`rgb`] module lights the channels one after another,
+//! each in its own third of the frame; with `hwpwm` every channel's slot
+//! stretches over the whole PWM period, and the channels overlap.
+//!
+//! The per-channel constants are approximations for an LED driven straight
+//! from a standard-drive GPIO; adjust them to match your LED and resistors.
+use crate::*;
+
+/// Approximate on-current of each channel in milliamps [red, green, blue].
+pub const CHANNEL_CURRENT_MA: [f32; 3] = [5.0, 4.0, 4.0];
+
+/// Computes the share of the frame each channel is lit.
+///
+/// # Arguments
+/// * `inputs` - The frame loop's inputs; brightness 0 when the output is
+///   off
+/// * `ceiling` - Brightness ceiling, 0 to [`BRIGHTNESS_MAX`]
+///
+/// # Returns
+/// Duty of each channel [red, green, blue], from 0.0 to 1.0.
+pub fn frame_duty(inputs: FrameInputs, ceiling: u32) -> [f32; 3] {
+    let timing = RgbTiming::for_frame_rate(scanout_frame_rate(inputs.frame_rate));
+    let on_times = fine_on_times_with(
+        &GammaTable::new(inputs.gamma),
+        timing,
+        inputs.levels,
+        inputs.brightness,
+        ceiling,
+    );
+    let mut duty = [0.0; 3];
+    for (led, duty) in duty.iter_mut().enumerate() {
+        let period = if cfg!(feature = "hwpwm") {
+            timing.slot_ticks(led)
+        } else {
+            timing.frame_ticks
+        };
+        *duty = on_times[led] as f32 / (period.max(1) * DITHER_STEPS) as f32;
+    }
+    duty
+}
+
+/// Estimates the average LED current the frame loop draws.
+///
+/// # Arguments
+/// * `inputs` - The frame loop's inputs; brightness 0 when the output is
+///   off
+/// * `ceiling` - Brightness ceiling, 0 to [`BRIGHTNESS_MAX`]
+///
+/// # Returns
+/// Estimated average current in milliamps.
+pub fn estimate_current_ma(inputs: FrameInputs, ceiling: u32) -> f32 {
+    frame_duty(inputs, ceiling)
+        .iter()
+        .zip(CHANNEL_CURRENT_MA.iter())
+        .map(|(&duty, &current)| duty * current)
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn inputs(levels: [u32; 3]) -> FrameInputs {
+        FrameInputs {
+            levels,
+            brightness: BRIGHTNESS_MAX,
+            frame_rate: 64,
+            gamma: None,
+        }
+    }
+
+    fn close(actual: f32, expected: f32) -> bool {
+        (actual - expected).abs() < 0.05
+    }
+
+    #[test]
+    fn linear_levels_draw_their_share() {
+        assert_eq!(estimate_current_ma(inputs([0; 3]), BRIGHTNESS_MAX), 0.0);
+        // Red alone at half scale: half its slot, a sixth of the frame
+        // (half the period with `hwpwm`).
+        let half = LEVELS / 2;
+        let share = if cfg!(feature = "hwpwm") {
+            0.5
+        } else {
+            0.5 / 3.0
+        };
+        let ma = estimate_current_ma(inputs([half, 0, 0]), BRIGHTNESS_MAX);
+        assert!(close(ma, share * CHANNEL_CURRENT_MA[0]), "{}", ma);
+    }
+
+    #[test]
+    fn all_on_and_mixed_colors_draw_known_currents() {
+        // A level lights its channel for level / LEVELS of its slot, a third
+        // of the frame (the whole period with `hwpwm`).
+        let slot = if cfg!(feature = "hwpwm") {
+            1.0
+        } else {
+            1.0 / 3.0
+        };
+        let top = (LEVELS - 1) as f32 / LEVELS as f32;
+        let expected: f32 = CHANNEL_CURRENT_MA.iter().map(|&ma| ma * top * slot).sum();
+        let all_on = estimate_current_ma(inputs([LEVELS - 1; 3]), BRIGHTNESS_MAX);
+        assert!(close(all_on, expected), "{} {}", all_on, expected);
+        let (all_on_ma, mixed_ma) = if cfg!(feature = "hwpwm") {
+            (12.19, 8.12)
+        } else {
+            (4.06, 2.71)
+        };
+        assert!(close(all_on, all_on_ma), "{}", all_on);
+        let mixed = estimate_current_ma(inputs([10, 8, 12]), BRIGHTNESS_MAX);
+        assert!(close(mixed, mixed_ma), "{}", mixed);
+    }
+
+    #[test]
+    fn brightness_ceiling_and_output_scale_the_current() {
+        let levels = [LEVELS - 1, LEVELS / 2, 0];
+        let full = estimate_current_ma(inputs(levels), BRIGHTNESS_MAX);
+        let full_ma = if cfg!(feature = "hwpwm") { 6.69 } else { 2.23 };
+        assert!(close(full, full_ma), "{}", full);
+        let dimmed = FrameInputs {
+            brightness: BRIGHTNESS_MAX / 2,
+            ..inputs(levels)
+        };
+        assert!(close(
+            estimate_current_ma(dimmed, BRIGHTNESS_MAX),
+            full / 2.0
+        ));
+
+        // The ceiling caps the brightest channel and keeps the ratio.
+        let duty = frame_duty(inputs(levels), BRIGHTNESS_MAX / 4);
+        let full_duty = frame_duty(inputs(levels), BRIGHTNESS_MAX);
+        assert!(duty[0] < full_duty[0] / 2.0);
+        assert!((duty[1] / duty[0] - full_duty[1] / full_duty[0]).abs() < 0.01);
+
+        // A blanked output runs the frame loop at brightness 0.
+        let off = FrameInputs {
+            brightness: 0,
+            ..inputs(levels)
+        };
+        assert_eq!(estimate_current_ma(off, BRIGHTNESS_MAX), 0.0);
+    }
+
+    #[test]
+    fn gamma_lowers_the_mid_levels() {
+        let levels = [LEVELS / 2; 3];
+        let linear = estimate_current_ma(inputs(levels), BRIGHTNESS_MAX);
+        let curved = FrameInputs {
+            gamma: Some(Gamma {
+                exponent: 2.2,
+                knee: 0,
+            }),
+            ..inputs(levels)
+        };
+        assert!(estimate_current_ma(curved, BRIGHTNESS_MAX) < linear / 2.0);
+        // The top level is full on either way.
+        let top = [LEVELS - 1; 3];
+        let curved_top = FrameInputs {
+            levels: top,
+            ..curved
+        };
+        assert!(close(
+            estimate_current_ma(curved_top, BRIGHTNESS_MAX),
+            estimate_current_ma(inputs(top), BRIGHTNESS_MAX),
+        ));
+    }
+}
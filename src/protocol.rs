@@ -0,0 +1,494 @@
+//! # Binary Host Protocol Module
+//!
+//! This module implements a framed binary protocol for automated
+//! calibration rigs, alongside the human-readable [`console`].
+//!
+//! ## Framing
+//!
+//! Each frame is a COBS-encoded block followed by a single `0x00`
+//! delimiter. The decoded block is the message payload followed by a
+//! CRC-16/CCITT-FALSE of the payload, little-endian:
+//!
+//! ```text
+//! COBS(id, body..., crc_lo, crc_hi) 0x00
+//! ```
+//!
+//! A corrupted or truncated frame is dropped whole; since `0x00` only ever
+//! appears as a delimiter, the decoder resynchronizes at the next frame.
+//! The framer knows nothing about the transport: [`FrameDecoder`] consumes
+//! bytes and [`encode_frame`] produces them.
+//!
+//! ## Messages
+//!
+//! | ID     | Direction   | Body                 | Meaning                         |
+//! |--------|-------------|----------------------|---------------------------------|
+//! | `0x01` | host→device | `version`            | Hello; must be sent first       |
+//! | `0x10` | host→device |                      | Get levels                      |
+//! | `0x11` | host→device | `r g b`              | Set levels                      |
+//! | `0x12` | host→device |                      | Get frame rate                  |
+//! | `0x13` | host→device | `fps_lo fps_hi`      | Set frame rate                  |
+//! | `0x20` | host→device | `0|1`                | Unsubscribe/subscribe to state  |
+//! | `0x81` | device→host | `version`            | Hello reply: negotiated version |
+//! | `0x90` | device→host | `r g b`              | Levels                          |
+//! | `0x92` | device→host | `fps_lo fps_hi`      | Frame rate                      |
+//! | `0xA0` | device→host | `id`                 | Request `id` applied            |
+//! | `0xA1` | device→host | `id reason`          | Request `id` rejected           |
+//! | `0xB0` | device→host | `r g b fps_lo fps_hi`| State changed notification      |
+//!
+//! ## Version Negotiation
+//!
+//! The host sends the highest version it speaks; the device replies with
+//! the lower of that and [`PROTOCOL_VERSION`]. Until a Hello succeeds every
+//! other request is rejected with [`NakReason::NotNegotiated`]. Unknown
+//! message IDs are rejected with [`NakReason::UnknownMessage`] and the
+//! stream carries on.
+use crate::*;
+
+/// Highest protocol version this firmware speaks.
+pub const PROTOCOL_VERSION: u8 = 1;
+/// Maximum payload length (message ID plus body), in bytes.
+pub const PAYLOAD_MAX: usize = 16;
+/// Maximum encoded frame length, including the delimiter.
+pub const FRAME_MAX: usize = PAYLOAD_MAX + 2 + 2 + 1;
+
+/// COBS-encodes `input` into `output`.
+///
+/// # Returns
+/// The encoded length, or `None` if `output` is too small.
+pub fn cobs_encode(input: &[u8], output: &mut [u8]) -> Option<usize> {
+    let mut code_index = 0;
+    let mut out = 1;
+    let mut code = 1u8;
+    for &byte in input {
+        if byte != 0 {
+            *output.get_mut(out)? = byte;
+            out += 1;
+            code += 1;
+        }
+        if byte == 0 || code == 0xFF {
+            *output.get_mut(code_index)? = code;
+            code_index = out;
+            out += 1;
+            code = 1;
+        }
+    }
+    *output.get_mut(code_index)? = code;
+    Some(out)
+}
+
+/// Decodes a COBS block (without its delimiter) from `input` into `output`.
+///
+/// # Returns
+/// The decoded length, or `None` if the block is malformed or `output` is
+/// too small.
+pub fn cobs_decode(input: &[u8], output: &mut [u8]) -> Option<usize> {
+    let mut i = 0;
+    let mut out = 0;
+    while i < input.len() {
+        let code = input[i];
+        if code == 0 {
+            return None;
+        }
+        i += 1;
+        for _ in 1..code {
+            let byte = *input.get(i)?;
+            if byte == 0 {
+                return None;
+            }
+            *output.get_mut(out)? = byte;
+            out += 1;
+            i += 1;
+        }
+        if code != 0xFF && i < input.len() {
+            *output.get_mut(out)? = 0;
+            out += 1;
+        }
+    }
+    Some(out)
+}
+
+/// Encodes a payload as a complete frame, delimiter included.
+///
+/// # Returns
+/// The frame length, or `None` if the payload exceeds [`PAYLOAD_MAX`].
+pub fn encode_frame(payload: &[u8], frame: &mut [u8; FRAME_MAX]) -> Option<usize> {
+    let len = payload.len();
+    if len > PAYLOAD_MAX {
+        return None;
+    }
+    let mut block = [0u8; PAYLOAD_MAX + 2];
+    block[..len].copy_from_slice(payload);
+    block[len..len + 2].copy_from_slice(&crc16(payload).to_le_bytes());
+    let encoded = cobs_encode(&block[..len + 2], &mut frame[..FRAME_MAX - 1])?;
+    frame[encoded] = 0;
+    Some(encoded + 1)
+}
+
+/// Reasons an incoming frame was dropped.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FrameError {
+    /// The frame was longer than [`FRAME_MAX`].
+    TooLong,
+    /// The COBS encoding was invalid.
+    Malformed,
+    /// The frame was too short to hold an ID and a CRC.
+    TooShort,
+    /// The CRC did not match.
+    BadCrc,
+}
+
+/// Incremental frame decoder fed one byte at a time.
+pub struct FrameDecoder {
+    buf: [u8; FRAME_MAX],
+    len: usize,
+    overflow: bool,
+}
+
+impl Default for FrameDecoder {
+    fn default() -> Self {
+        Self {
+            buf: [0; FRAME_MAX],
+            len: 0,
+            overflow: false,
+        }
+    }
+}
+
+impl FrameDecoder {
+    /// Feeds one received byte.
+    ///
+    /// # Arguments
+    /// * `byte` - Next byte from the transport
+    /// * `payload` - Receives the payload when a frame completes
+    ///
+    /// # Returns
+    /// `None` until a delimiter arrives, then the payload length or the
+    /// reason the frame was dropped. Empty frames are ignored.
+    pub fn push(
+        &mut self,
+        byte: u8,
+        payload: &mut [u8; PAYLOAD_MAX],
+    ) -> Option<Result<usize, FrameError>> {
+        if byte != 0 {
+            match self.buf.get_mut(self.len) {
+                Some(slot) => {
+                    *slot = byte;
+                    self.len += 1;
+                }
+                None => self.overflow = true,
+            }
+            return None;
+        }
+        let (len, overflow) = (self.len, self.overflow);
+        self.len = 0;
+        self.overflow = false;
+        if overflow {
+            return Some(Err(FrameError::TooLong));
+        }
+        if len == 0 {
+            return None;
+        }
+        let mut block = [0u8; FRAME_MAX];
+        let Some(decoded) = cobs_decode(&self.buf[..len], &mut block) else {
+            return Some(Err(FrameError::Malformed));
+        };
+        if decoded < 3 {
+            return Some(Err(FrameError::TooShort));
+        }
+        let body = decoded - 2;
+        if body > PAYLOAD_MAX {
+            return Some(Err(FrameError::TooLong));
+        }
+        let crc = u16::from_le_bytes([block[body], block[body + 1]]);
+        if crc != crc16(&block[..body]) {
+            return Some(Err(FrameError::BadCrc));
+        }
+        payload[..body].copy_from_slice(&block[..body]);
+        Some(Ok(body))
+    }
+}
+
+/// Reasons a request was rejected.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[repr(u8)]
+pub enum NakReason {
+    /// The message ID is not known to this version.
+    UnknownMessage = 1,
+    /// The body length is wrong for the message.
+    BadLength = 2,
+    /// A value is outside its valid range.
+    OutOfRange = 3,
+    /// No Hello has succeeded yet.
+    NotNegotiated = 4,
+}
+
+/// A decoded host request.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Request {
+    /// Version negotiation with the host's highest version.
+    Hello(u8),
+    /// Read the RGB levels.
+    GetLevels,
+    /// Write all three RGB levels.
+    SetLevels([u8; 3]),
+    /// Read the frame rate.
+    GetFrameRate,
+    /// Write the frame rate.
+    SetFrameRate(u16),
+    /// Turn state-changed notifications on or off.
+    Subscribe(bool),
+}
+
+impl Request {
+    /// Decodes a request from a frame payload.
+    pub fn decode(payload: &[u8]) -> Result<Self, NakReason> {
+        let (&id, body) = payload.split_first().ok_or(NakReason::BadLength)?;
+        let request = match (id, body) {
+            (0x01, &[version]) => Request::Hello(version),
+            (0x10, &[]) => Request::GetLevels,
+            (0x11, &[r, g, b]) => Request::SetLevels([r, g, b]),
+            (0x12, &[]) => Request::GetFrameRate,
+            (0x13, &[lo, hi]) => Request::SetFrameRate(u16::from_le_bytes([lo, hi])),
+            (0x20, &[flag]) => Request::Subscribe(flag != 0),
+            (0x01 | 0x10..=0x13 | 0x20, _) => return Err(NakReason::BadLength),
+            _ => return Err(NakReason::UnknownMessage),
+        };
+        Ok(request)
+    }
+}
+
+/// Per-connection protocol state.
+#[derive(Debug, Default)]
+pub struct Session {
+    /// Negotiated version, once a Hello has succeeded.
+    pub version: Option<u8>,
+    /// Whether state-changed notifications are wanted.
+    pub subscribed: bool,
+}
+
+/// Handles one frame payload and writes the reply payload.
+///
+/// # Returns
+/// The length of the reply written to `reply`.
+pub async fn dispatch(
+    session: &mut Session,
+    payload: &[u8],
+    reply: &mut [u8; PAYLOAD_MAX],
+) -> usize {
+    let id = payload.first().copied().unwrap_or(0);
+    let nak = |reply: &mut [u8; PAYLOAD_MAX], reason: NakReason| {
+        reply[..3].copy_from_slice(&[0xA1, id, reason as u8]);
+        3
+    };
+    let request = match Request::decode(payload) {
+        Ok(request) => request,
+        Err(reason) => return nak(reply, reason),
+    };
+    if session.version.is_none() && !matches!(request, Request::Hello(_)) {
+        return nak(reply, NakReason::NotNegotiated);
+    }
+    let ack = |reply: &mut [u8; PAYLOAD_MAX]| {
+        reply[..2].copy_from_slice(&[0xA0, id]);
+        2
+    };
+    match request {
+        Request::Hello(0) => nak(reply, NakReason::OutOfRange),
+        Request::Hello(version) => {
+            let version = version.min(PROTOCOL_VERSION);
+            session.version = Some(version);
+            reply[..2].copy_from_slice(&[0x81, version]);
+            2
+        }
+        Request::GetLevels => {
+            let levels = get_rgb_levels().await;
+            reply[0] = 0x90;
+            for (out, level) in reply[1..4].iter_mut().zip(levels) {
+                *out = level as u8;
+            }
+            4
+        }
+        Request::SetLevels(levels) => {
+            if try_set_rgb_levels(levels.map(u32::from)).await.is_err() {
+                return nak(reply, NakReason::OutOfRange);
+            }
+            ack(reply)
+        }
+        Request::GetFrameRate => {
+            let frame_rate = get_frame_rate().await as u16;
+            reply[0] = 0x92;
+            reply[1..3].copy_from_slice(&frame_rate.to_le_bytes());
+            3
+        }
+        Request::SetFrameRate(frame_rate) => {
+            if validate_frame_rate(frame_rate as u64).is_err() {
+                return nak(reply, NakReason::OutOfRange);
+            }
+            set_frame_rate(StateSource::Protocol, |rate| *rate = frame_rate as u64).await;
+            ack(reply)
+        }
+        Request::Subscribe(subscribed) => {
+            session.subscribed = subscribed;
+            ack(reply)
+        }
+    }
+}
+
+/// Builds a state-changed notification payload.
+pub fn state_changed(levels: [u32; 3], frame_rate: u64, payload: &mut [u8; PAYLOAD_MAX]) -> usize {
+    payload[0] = 0xB0;
+    for (out, level) in payload[1..4].iter_mut().zip(levels) {
+        *out = level as u8;
+    }
+    payload[4..6].copy_from_slice(&(frame_rate as u16).to_le_bytes());
+    6
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use embassy_futures::block_on;
+
+    fn round_trip(input: &[u8]) {
+        let mut encoded = [0u8; 320];
+        let len = cobs_encode(input, &mut encoded).unwrap();
+        assert!(!encoded[..len].contains(&0), "{:?}", input);
+        let mut decoded = [0u8; 320];
+        let decoded_len = cobs_decode(&encoded[..len], &mut decoded).unwrap();
+        assert_eq!(&decoded[..decoded_len], input);
+    }
+
+    #[test]
+    fn cobs_round_trips() {
+        round_trip(&[]);
+        round_trip(&[0]);
+        round_trip(&[0, 0]);
+        round_trip(&[1, 0, 2]);
+        round_trip(&[1, 2, 0]);
+        // Runs around the 254-byte block limit, where the code byte is 0xFF
+        // and no zero follows.
+        for run in [253, 254, 255, 300] {
+            let mut input = [0x5A; 301];
+            round_trip(&input[..run]);
+            input[run] = 0;
+            round_trip(&input[..=run]);
+        }
+        let mut encoded = [0u8; 320];
+        assert_eq!(cobs_encode(&[7; 254], &mut encoded), Some(256));
+        assert_eq!(encoded[0], 0xFF);
+        // Too small an output is refused, not overrun.
+        assert_eq!(cobs_encode(&[1, 2, 3], &mut encoded[..3]), None);
+        assert_eq!(cobs_decode(&[4, 1, 2, 3], &mut [0; 2]), None);
+    }
+
+    fn feed(decoder: &mut FrameDecoder, bytes: &[u8]) -> Option<Result<usize, FrameError>> {
+        let mut payload = [0u8; PAYLOAD_MAX];
+        let mut last = None;
+        for &byte in bytes {
+            if let Some(result) = decoder.push(byte, &mut payload) {
+                last = Some(result);
+            }
+        }
+        last
+    }
+
+    #[test]
+    fn encode_frame_bounds_the_payload() {
+        let mut frame = [0u8; FRAME_MAX];
+        let len = encode_frame(&[0xFF; PAYLOAD_MAX], &mut frame).unwrap();
+        assert_eq!(frame[len - 1], 0);
+        for long in [PAYLOAD_MAX + 1, PAYLOAD_MAX + 2, 40] {
+            assert_eq!(encode_frame(&[1; 40][..long], &mut frame), None);
+        }
+    }
+
+    #[test]
+    fn frames_round_trip_through_the_decoder() {
+        let mut decoder = FrameDecoder::default();
+        let mut frame = [0u8; FRAME_MAX];
+        for payload in [&[0x10][..], &[0x11, 0, 7, 0], &[0x00; PAYLOAD_MAX]] {
+            let len = encode_frame(payload, &mut frame).unwrap();
+            let mut out = [0u8; PAYLOAD_MAX];
+            let mut result = None;
+            for &byte in &frame[..len] {
+                result = decoder.push(byte, &mut out);
+            }
+            assert_eq!(result, Some(Ok(payload.len())));
+            assert_eq!(&out[..payload.len()], payload);
+        }
+        // Back-to-back delimiters are empty frames, and ignored.
+        assert_eq!(feed(&mut decoder, &[0, 0]), None);
+    }
+
+    #[test]
+    fn bad_frames_are_dropped_and_the_decoder_resyncs() {
+        let mut decoder = FrameDecoder::default();
+        let mut good = [0u8; FRAME_MAX];
+        let good_len = encode_frame(&[0x12], &mut good).unwrap();
+        let good = &good[..good_len];
+
+        // A corrupted byte fails the CRC.
+        let mut corrupt = [0u8; FRAME_MAX];
+        corrupt[..good_len].copy_from_slice(good);
+        corrupt[1] ^= 0x40;
+        assert_eq!(
+            feed(&mut decoder, &corrupt[..good_len]),
+            Some(Err(FrameError::BadCrc))
+        );
+        assert_eq!(feed(&mut decoder, good), Some(Ok(1)));
+
+        // Truncated: a block too short for an ID and a CRC, and a code byte
+        // pointing past the end of the block.
+        assert_eq!(
+            feed(&mut decoder, &[0x02, 0x12, 0x00]),
+            Some(Err(FrameError::TooShort))
+        );
+        assert_eq!(
+            feed(&mut decoder, &[0x05, 0x12, 0x00]),
+            Some(Err(FrameError::Malformed))
+        );
+        assert_eq!(feed(&mut decoder, good), Some(Ok(1)));
+
+        // Overlong: more bytes than a frame holds before the delimiter.
+        assert_eq!(
+            feed(&mut decoder, &[0x01; FRAME_MAX + 8]),
+            None,
+            "no delimiter yet"
+        );
+        assert_eq!(feed(&mut decoder, &[0]), Some(Err(FrameError::TooLong)));
+        assert_eq!(feed(&mut decoder, good), Some(Ok(1)));
+
+        // Line noise ahead of a good frame costs only the noise.
+        let mut stream = [0x33u8; FRAME_MAX + 1];
+        stream[4] = 0;
+        stream[5..5 + good_len].copy_from_slice(good);
+        assert_eq!(feed(&mut decoder, &stream[..5 + good_len]), Some(Ok(1)));
+    }
+
+    #[test]
+    fn requests_need_a_hello_and_a_known_id() {
+        let mut session = Session::default();
+        let mut reply = [0u8; PAYLOAD_MAX];
+        let mut send = |session: &mut Session, payload: &[u8]| {
+            let len = block_on(dispatch(session, payload, &mut reply));
+            reply[..len].to_vec()
+        };
+
+        // Nothing but a Hello gets through before negotiation.
+        assert_eq!(send(&mut session, &[0x10]), [0xA1, 0x10, 4]);
+        assert_eq!(send(&mut session, &[0x20, 1]), [0xA1, 0x20, 4]);
+        assert!(!session.subscribed);
+        assert_eq!(send(&mut session, &[0x01, 0]), [0xA1, 0x01, 3]);
+        assert_eq!(session.version, None);
+
+        // The device answers with the lower version.
+        assert_eq!(send(&mut session, &[0x01, 9]), [0x81, PROTOCOL_VERSION]);
+        assert_eq!(session.version, Some(PROTOCOL_VERSION));
+
+        // Unknown IDs and wrong lengths are rejected, and the session
+        // carries on.
+        assert_eq!(send(&mut session, &[0x55, 1, 2]), [0xA1, 0x55, 1]);
+        assert_eq!(send(&mut session, &[0x12, 0]), [0xA1, 0x12, 2]);
+        assert_eq!(send(&mut session, &[0x20, 1]), [0xA0, 0x20]);
+        assert!(session.subscribed);
+    }
+}
@@ -0,0 +1,311 @@
+//! # Radio Mirroring Module
+//!
+//! This module lets one "teacher" board broadcast its color and frame rate
+//! over the 2.4 GHz radio while any number of "student" boards mirror it,
+//! for example a classroom of micro:bits following one controller.
+//!
+//! ## Groups
+//!
+//! Every packet carries a group ID (0 to [`RADIO_GROUP_MAX`]). Students
+//! ignore packets from other groups, so several classes can share the air.
+//! Teachers only broadcast: there are no acknowledgements, and any number
+//! of students can listen.
+//!
+//! ## Packet
+//!
+//! Sent with the nRF 1 Mbit mode, a 16-bit CRC, and whitening:
+//!
+//! ```text
+//! [length] ['R'] [version] [group] [sequence] [red] [green] [blue] [fps]
+//! ```
+//!
+//! Every field is one byte:
+//!
+//! - **length**: Payload bytes after it, always [`RADIO_PAYLOAD_LEN`]
+//! - **'R'**, **version**: Mark the packet as ours, in this layout; anything
+//!   else is foreign and ignored, so a layout change bumps the version
+//! - **group**: 0 to [`RADIO_GROUP_MAX`]
+//! - **sequence**: Counts the teacher's broadcasts, wrapping at 255
+//! - **red**, **green**, **blue**: Levels, 0 to [`LEVELS`]-1
+//! - **fps**: Frame rate, [`FRAME_RATE_MIN`] to [`FRAME_RATE_MAX`]
+//!
+//! The radio's CRC rejects corrupted packets before they are decoded. A
+//! student also rejects a packet with a level or frame rate out of range,
+//! rather than clamping it, and keeps its last good state.
+//! [`RadioPacket::encode`] and [`RadioPacket::decode`] are pure, so the
+//! format can be checked without a radio.
+//!
+//! ## Link Status
+//!
+//! A student is linked while the last accepted packet is younger than
+//! [`RADIO_STALE`]. Linking saves the local levels; losing the teacher
+//! restores them and hands control back to the knob. The top-left matrix
+//! LED shows the link: steady while linked (or broadcasting as teacher),
+//! blinking while a student waits for its teacher, off when the radio is
+//! off. It uses a row and column the ambient sensor never touches, and
+//! shines at the matrix brightness (see the [`matrix`](crate::matrix)
+//! module).
+//!
+//! ## Remote Input
+//!
+//! With the `remote` feature, two more roles turn a second board into a
+//! wireless knob; see the [`remote`](crate::remote) module.
+use crate::*;
+
+/// Highest radio group ID.
+pub const RADIO_GROUP_MAX: u8 = 15;
+/// Age of the last packet after which a student counts as unlinked.
+pub const RADIO_STALE: Duration = Duration::from_millis(1000);
+/// How long a student listens before re-checking its state, in milliseconds.
+pub const RADIO_LISTEN_MS: u64 = 100;
+/// Blink half-period of the link LED while unlinked, in milliseconds.
+pub const RADIO_BLINK_MS: u64 = 250;
+/// First payload byte of every packet.
+const RADIO_MAGIC: u8 = b'R';
+/// Packet layout version.
+const RADIO_VERSION: u8 = 1;
+/// Payload length, excluding the length byte.
+pub const RADIO_PAYLOAD_LEN: usize = 8;
+
+/// Role of this board on the radio.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RadioRole {
+    /// Radio unused.
+    Off,
+    /// Broadcast the local color.
+    Teacher,
+    /// Mirror a teacher's color.
+    Student,
+    /// Send this board's knob and buttons to a paired host.
+    #[cfg(feature = "remote")]
+    Remote,
+    /// Apply a paired remote's knob and buttons.
+    #[cfg(feature = "remote")]
+    Host,
+}
+
+/// Mirrored state carried by a packet.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RadioPacket {
+    /// Group the packet belongs to.
+    pub group: u8,
+    /// Broadcast counter, wrapping.
+    pub sequence: u8,
+    /// RGB levels.
+    pub levels: [u32; 3],
+    /// Frame rate in FPS.
+    pub frame_rate: u64,
+}
+
+/// Reasons a received packet was not accepted.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RadioError {
+    /// Wrong length, magic, or version: not one of ours.
+    Foreign,
+    /// A valid packet for another group.
+    OtherGroup,
+    /// A level or frame rate out of range.
+    BadValue,
+}
+
+impl RadioPacket {
+    /// Encodes the packet, length byte first.
+    ///
+    /// Levels and frame rate beyond what a byte field holds are clamped;
+    /// a frame rate out of range still reaches the student, which rejects
+    /// it.
+    pub fn encode(&self) -> [u8; 1 + RADIO_PAYLOAD_LEN] {
+        let [red, green, blue] = self.levels.map(|level| level.min(LEVELS - 1) as u8);
+        [
+            RADIO_PAYLOAD_LEN as u8,
+            RADIO_MAGIC,
+            RADIO_VERSION,
+            self.group,
+            self.sequence,
+            red,
+            green,
+            blue,
+            self.frame_rate.min(u8::MAX as u64) as u8,
+        ]
+    }
+
+    /// Decodes a received packet, accepting only the given group.
+    ///
+    /// # Arguments
+    /// * `buf` - Received bytes, length byte first
+    /// * `group` - Group this board listens to
+    pub fn decode(buf: &[u8], group: u8) -> Result<Self, RadioError> {
+        let [length, magic, version, packet_group, sequence, red, green, blue, fps] = buf else {
+            return Err(RadioError::Foreign);
+        };
+        if *length as usize != RADIO_PAYLOAD_LEN
+            || *magic != RADIO_MAGIC
+            || *version != RADIO_VERSION
+        {
+            return Err(RadioError::Foreign);
+        }
+        if *packet_group != group {
+            return Err(RadioError::OtherGroup);
+        }
+        let levels = [*red, *green, *blue].map(u32::from);
+        let frame_rate = *fps as u64;
+        if validate_levels(levels).is_err() || validate_frame_rate(frame_rate).is_err() {
+            return Err(RadioError::BadValue);
+        }
+        Ok(Self {
+            group,
+            sequence: *sequence,
+            levels,
+            frame_rate,
+        })
+    }
+}
+
+/// Whether a student link is still alive.
+///
+/// # Arguments
+/// * `last_packet_ms` - Uptime of the last accepted packet, if any
+/// * `now_ms` - Current uptime
+pub fn radio_link_alive(last_packet_ms: Option<u64>, now_ms: u64) -> bool {
+    last_packet_ms.is_some_and(|last| now_ms.saturating_sub(last) < RADIO_STALE.as_millis())
+}
+
+/// Shared radio state.
+#[derive(Debug, Clone, Copy)]
+pub struct RadioMode {
+    /// Current role.
+    pub role: RadioRole,
+    /// Group sent or listened to.
+    pub group: u8,
+    /// Whether a student is currently mirroring a teacher.
+    pub linked: bool,
+    /// Local levels saved when a student links, restored when it unlinks.
+    pub saved: [u32; 3],
+}
+
+/// Global radio state, changed by the console.
+pub static RADIO_MODE: Mutex<CriticalSectionRawMutex, RadioMode> = Mutex::new(RadioMode {
+    role: RadioRole::Off,
+    group: 0,
+    linked: false,
+    saved: [0; 3],
+});
+
+/// Hands control back to the local inputs if a student is linked.
+pub async fn radio_unlink(mode: &mut RadioMode) {
+    if mode.linked {
+        mode.linked = false;
+        let saved = mode.saved;
+        set_rgb_levels(|rgb| *rgb = saved).await;
+    }
+}
+
+/// Changes the radio role.
+pub async fn set_radio_role(role: RadioRole) {
+    let mut mode = RADIO_MODE.lock().await;
+    if role != RadioRole::Student {
+        radio_unlink(&mut mode).await;
+    }
+    mode.role = role;
+    rprintln!("Radio: {:?} (group {})", role, mode.group);
+}
+
+/// Changes the radio group.
+///
+/// # Returns
+/// `false` (leaving the group unchanged) if `group` exceeds
+/// [`RADIO_GROUP_MAX`].
+pub async fn set_radio_group(group: u8) -> bool {
+    if group > RADIO_GROUP_MAX {
+        return false;
+    }
+    let mut mode = RADIO_MODE.lock().await;
+    radio_unlink(&mut mode).await;
+    mode.group = group;
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encode_clamps_to_byte_fields() {
+        let packet = RadioPacket {
+            group: 3,
+            sequence: 7,
+            levels: [15, 0, 9],
+            frame_rate: 100,
+        };
+        assert_eq!(packet.encode(), [8, b'R', 1, 3, 7, 15, 0, 9, 100]);
+        // Fields past what their byte holds are clamped.
+        let loud = RadioPacket {
+            levels: [40, 0, 0],
+            frame_rate: 1_000,
+            ..packet
+        };
+        assert_eq!(loud.encode(), [8, b'R', 1, 3, 7, 15, 0, 0, 255]);
+    }
+
+    #[test]
+    fn packets_round_trip_and_reject_the_rest() {
+        let packet = RadioPacket {
+            group: 3,
+            sequence: 0,
+            levels: [15, 0, 0],
+            frame_rate: 100,
+        };
+        assert_eq!(RadioPacket::decode(&packet.encode(), 3), Ok(packet));
+        assert_eq!(
+            RadioPacket::decode(&packet.encode(), 4),
+            Err(RadioError::OtherGroup)
+        );
+
+        // Every group and sequence survives the round trip.
+        for group in 0..=RADIO_GROUP_MAX {
+            for sequence in [0, 1, 128, 255] {
+                let packet = RadioPacket {
+                    group,
+                    sequence,
+                    ..packet
+                };
+                assert_eq!(RadioPacket::decode(&packet.encode(), group), Ok(packet));
+            }
+        }
+
+        // Not ours: wrong length byte, magic or version, or a short or long
+        // buffer.
+        let good = packet.encode();
+        for (index, byte) in [(0, 9), (1, b'S'), (2, 2)] {
+            let mut bad = good;
+            bad[index] = byte;
+            assert_eq!(RadioPacket::decode(&bad, 3), Err(RadioError::Foreign));
+        }
+        assert_eq!(RadioPacket::decode(&good[..8], 3), Err(RadioError::Foreign));
+        assert_eq!(RadioPacket::decode(&[], 3), Err(RadioError::Foreign));
+        let mut long = [0; 10];
+        long[..9].copy_from_slice(&good);
+        assert_eq!(RadioPacket::decode(&long, 3), Err(RadioError::Foreign));
+
+        // Ours, but out of range: a level of 16, or a frame rate that was
+        // clamped on the way out.
+        let bright = [8, b'R', 1, 3, 0, 16, 0, 0, 100];
+        assert_eq!(RadioPacket::decode(&bright, 3), Err(RadioError::BadValue));
+        let slow = RadioPacket {
+            frame_rate: FRAME_RATE_MIN - 1,
+            ..packet
+        };
+        assert_eq!(
+            RadioPacket::decode(&slow.encode(), 3),
+            Err(RadioError::BadValue)
+        );
+        let fast = RadioPacket {
+            frame_rate: 1_000,
+            ..packet
+        };
+        assert_eq!(
+            RadioPacket::decode(&fast.encode(), 3),
+            Err(RadioError::BadValue)
+        );
+    }
+}
@@ -0,0 +1,409 @@
+//! # Remote Input Module
+//!
+//! This module turns a second micro:bit into a wireless knob: the hand-held
+//! "remote" board sends its knob and button inputs over the radio, and the
+//! "host" board driving the LED applies them as if they were its own.
+//!
+//! ## Messages
+//!
+//! Remote packets share the [`radio`](crate::radio) module's channel,
+//! framing, and payload length, told apart by their first byte:
+//!
+//! ```text
+//! input: [length] ['I'] [version] [group] [session lo] [session hi] [sequence] [parameter] [level]
+//! pair:  [length] ['P'] [version] [group] [nonce lo] [nonce hi] [0] [0] [0]
+//! ```
+//!
+//! An input packet is a snapshot of the remote's [`InputEvent`]: which
+//! parameter its buttons select and its knob level. The remote sends one
+//! every [`REMOTE_SEND_MS`]; the host passes it to the UI only when it
+//! differs from the previous one, so a remote left alone doesn't fight the
+//! local knob.
+//!
+//! ## Pairing
+//!
+//! Input packets carry a session ID, and the host ignores other sessions.
+//! To pair, press A+B on both boards within [`PAIR_WINDOW`] of each
+//! other. Each board then offers a random nonce for the whole window;
+//! a board that heard the other's offer ends the window paired, with the
+//! session derived from both nonces (see [`Pairing`]). Pressing A+B again
+//! starts over, and a window with no peer leaves the board unpaired.
+//!
+//! ## Arbitration
+//!
+//! The remote has priority: its changes always apply, and local knob
+//! changes are ignored until [`REMOTE_HOLDOFF`] after the last one, or
+//! immediately once the link is lost (see [`InputArbiter`]).
+//!
+//! ## Link Status
+//!
+//! The host is linked while the last input packet is younger than
+//! [`RADIO_STALE`]. The radio link LED shows the state on both boards:
+//! fast blinking while offering, steady when paired (and, on the host,
+//! linked), slow blinking while the host waits for its remote, off when
+//! unpaired.
+use crate::*;
+use embassy_sync::channel::Channel;
+
+/// Time both boards have to press A+B to pair.
+pub const PAIR_WINDOW: Duration = Duration::from_secs(5);
+/// How long local knob changes are ignored after a remote change.
+pub const REMOTE_HOLDOFF: Duration = Duration::from_secs(2);
+/// Delay between remote input packets, in milliseconds.
+pub const REMOTE_SEND_MS: u64 = 50;
+/// Blink half-period of the link LED while offering, in milliseconds.
+pub const PAIR_BLINK_MS: u64 = 100;
+/// First payload byte of an input packet.
+const REMOTE_INPUT_MAGIC: u8 = b'I';
+/// First payload byte of a pairing offer.
+const REMOTE_PAIR_MAGIC: u8 = b'P';
+/// Remote packet layout version.
+const REMOTE_VERSION: u8 = 1;
+
+/// One input from the remote: its knob level for the parameter its buttons
+/// select.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct InputEvent {
+    /// Parameter selected by the remote's buttons.
+    pub parameter: ControlParameter,
+    /// Knob level from 0 to `LEVELS - 1`, before mapping to the parameter.
+    pub level: u32,
+}
+
+/// Remote message carried by a packet.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RemoteMessage {
+    /// Input snapshot from a paired remote.
+    Input {
+        /// Session the remote was paired into.
+        session: u16,
+        /// Packet counter, wrapping.
+        sequence: u8,
+        /// The remote's inputs.
+        event: InputEvent,
+    },
+    /// Pairing offer carrying the sender's nonce.
+    PairOffer(u16),
+}
+
+/// Wire code of a parameter: 0–2 for the channels, 3 for frame rate, 4
+/// for saturation.
+fn parameter_code(parameter: ControlParameter) -> u8 {
+    match parameter {
+        ControlParameter::FrameRate => 3,
+        ControlParameter::Saturation => 4,
+        _ => parameter.channel().map_or(3, |channel| channel as u8),
+    }
+}
+
+impl RemoteMessage {
+    /// Encodes the message for a group, length byte first.
+    pub fn encode(&self, group: u8) -> [u8; 1 + RADIO_PAYLOAD_LEN] {
+        match *self {
+            RemoteMessage::Input {
+                session,
+                sequence,
+                event,
+            } => {
+                let [session_lo, session_hi] = session.to_le_bytes();
+                [
+                    RADIO_PAYLOAD_LEN as u8,
+                    REMOTE_INPUT_MAGIC,
+                    REMOTE_VERSION,
+                    group,
+                    session_lo,
+                    session_hi,
+                    sequence,
+                    parameter_code(event.parameter),
+                    event.level.min(LEVELS - 1) as u8,
+                ]
+            }
+            RemoteMessage::PairOffer(nonce) => {
+                let [nonce_lo, nonce_hi] = nonce.to_le_bytes();
+                [
+                    RADIO_PAYLOAD_LEN as u8,
+                    REMOTE_PAIR_MAGIC,
+                    REMOTE_VERSION,
+                    group,
+                    nonce_lo,
+                    nonce_hi,
+                    0,
+                    0,
+                    0,
+                ]
+            }
+        }
+    }
+
+    /// Decodes a received packet, accepting only the given group.
+    ///
+    /// Mirroring packets and anything else that isn't a remote message
+    /// decode as [`RadioError::Foreign`].
+    pub fn decode(buf: &[u8], group: u8) -> Result<Self, RadioError> {
+        let [length, magic, version, packet_group, a, b, c, d, e] = buf else {
+            return Err(RadioError::Foreign);
+        };
+        if *length as usize != RADIO_PAYLOAD_LEN
+            || ![REMOTE_INPUT_MAGIC, REMOTE_PAIR_MAGIC].contains(magic)
+            || *version != REMOTE_VERSION
+        {
+            return Err(RadioError::Foreign);
+        }
+        if *packet_group != group {
+            return Err(RadioError::OtherGroup);
+        }
+        if *magic == REMOTE_PAIR_MAGIC {
+            return Ok(RemoteMessage::PairOffer(u16::from_le_bytes([*a, *b])));
+        }
+        let parameter = match d {
+            0 => ControlParameter::Red,
+            1 => ControlParameter::Green,
+            2 => ControlParameter::Blue,
+            3 => ControlParameter::FrameRate,
+            4 => ControlParameter::Saturation,
+            _ => return Err(RadioError::BadValue),
+        };
+        if *e as u32 >= LEVELS {
+            return Err(RadioError::BadValue);
+        }
+        Ok(RemoteMessage::Input {
+            session: u16::from_le_bytes([*a, *b]),
+            sequence: *c,
+            event: InputEvent {
+                parameter,
+                level: *e as u32,
+            },
+        })
+    }
+}
+
+/// Derives the session ID from the two boards' nonces.
+///
+/// The result doesn't depend on the order, so both boards agree, and is
+/// never 0.
+pub fn pair_session(nonce: u16, peer: u16) -> u16 {
+    match nonce ^ peer {
+        0 => 1,
+        session => session,
+    }
+}
+
+/// Pairing state machine, driven by A+B presses, peer offers, and time.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Pairing {
+    /// No session: the host ignores input packets, the remote sends none.
+    Unpaired,
+    /// Offering a nonce for [`PAIR_WINDOW`] after an A+B press.
+    Offering {
+        /// This board's nonce.
+        nonce: u16,
+        /// Uptime of the press, in milliseconds.
+        since_ms: u64,
+        /// Nonce of the peer, once its offer has been heard.
+        peer: Option<u16>,
+    },
+    /// Bound to a session.
+    Paired {
+        /// Session ID shared with the peer.
+        session: u16,
+    },
+}
+
+impl Pairing {
+    /// Starts offering `nonce`, abandoning any previous session.
+    pub fn press(self, nonce: u16, now_ms: u64) -> Self {
+        Pairing::Offering {
+            nonce,
+            since_ms: now_ms,
+            peer: None,
+        }
+    }
+
+    /// Records a peer's offer heard while this board's window is open.
+    ///
+    /// Offers outside the window, or echoing this board's own nonce, are
+    /// ignored.
+    pub fn peer_offer(self, peer_nonce: u16, now_ms: u64) -> Self {
+        match self {
+            Pairing::Offering {
+                nonce, since_ms, ..
+            } if peer_nonce != nonce
+                && now_ms.saturating_sub(since_ms) < PAIR_WINDOW.as_millis() =>
+            {
+                Pairing::Offering {
+                    nonce,
+                    since_ms,
+                    peer: Some(peer_nonce),
+                }
+            }
+            other => other,
+        }
+    }
+
+    /// Closes the window once [`PAIR_WINDOW`] has passed: paired if a peer
+    /// was heard, unpaired otherwise.
+    pub fn tick(self, now_ms: u64) -> Self {
+        match self {
+            Pairing::Offering {
+                nonce,
+                since_ms,
+                peer,
+            } if now_ms.saturating_sub(since_ms) >= PAIR_WINDOW.as_millis() => match peer {
+                Some(peer) => Pairing::Paired {
+                    session: pair_session(nonce, peer),
+                },
+                None => Pairing::Unpaired,
+            },
+            other => other,
+        }
+    }
+
+    /// Returns the session ID once paired.
+    pub fn session(self) -> Option<u16> {
+        match self {
+            Pairing::Paired { session } => Some(session),
+            _ => None,
+        }
+    }
+}
+
+/// Source currently allowed to change the controls.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum InputSource {
+    /// The host's own knob and buttons.
+    Local,
+    /// The paired remote.
+    Remote,
+}
+
+/// Remote/local arbitration for the UI event path.
+///
+/// Remote changes always apply and claim control for [`REMOTE_HOLDOFF`];
+/// local changes apply when the remote hasn't changed anything for that
+/// long, or its link is down.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct InputArbiter {
+    /// Uptime of the last remote change, in milliseconds.
+    last_remote_ms: Option<u64>,
+}
+
+impl InputArbiter {
+    /// Records a remote change, which always applies.
+    pub fn remote_change(&mut self, now_ms: u64) {
+        self.last_remote_ms = Some(now_ms);
+    }
+
+    /// Returns which source owns the controls.
+    ///
+    /// # Arguments
+    /// * `now_ms` - Current uptime
+    /// * `remote_linked` - Whether the remote's link is alive
+    pub fn owner(&self, now_ms: u64, remote_linked: bool) -> InputSource {
+        match self.last_remote_ms {
+            Some(last)
+                if remote_linked && now_ms.saturating_sub(last) < REMOTE_HOLDOFF.as_millis() =>
+            {
+                InputSource::Remote
+            }
+            _ => InputSource::Local,
+        }
+    }
+
+    /// Whether a local change may apply now.
+    pub fn local_allowed(&self, now_ms: u64, remote_linked: bool) -> bool {
+        self.owner(now_ms, remote_linked) == InputSource::Local
+    }
+}
+
+/// Shared remote state.
+#[derive(Debug, Clone, Copy)]
+pub struct RemoteLink {
+    /// Pairing state of this board.
+    pub pairing: Pairing,
+    /// Uptime of the host's last accepted input packet, in milliseconds.
+    pub last_packet_ms: Option<u64>,
+    /// Latest local inputs, sent by a remote board.
+    pub snapshot: Option<InputEvent>,
+}
+
+/// Global remote state.
+pub static REMOTE_LINK: Mutex<CriticalSectionRawMutex, RemoteLink> = Mutex::new(RemoteLink {
+    pairing: Pairing::Unpaired,
+    last_packet_ms: None,
+    snapshot: None,
+});
+
+/// Remote input changes received by a host, consumed by the UI.
+pub static REMOTE_EVENTS: Channel<CriticalSectionRawMutex, InputEvent, 4> = Channel::new();
+
+/// Starts pairing after an A+B press, if this board is a remote or host.
+pub async fn remote_pair_pressed() {
+    let role = RADIO_MODE.lock().await.role;
+    if !matches!(role, RadioRole::Remote | RadioRole::Host) {
+        return;
+    }
+    let now = Instant::now();
+    // The press time in microseconds is unpredictable enough for a nonce.
+    let nonce = (now.as_micros() as u16) | 1;
+    let mut link = REMOTE_LINK.lock().await;
+    link.pairing = link.pairing.press(nonce, now.as_millis());
+    rprintln!("Remote: pairing, press A+B on the other board");
+}
+
+/// Records this board's inputs for a remote to send.
+pub async fn set_remote_snapshot(event: InputEvent) {
+    REMOTE_LINK.lock().await.snapshot = Some(event);
+}
+
+/// Whether a host currently hears its paired remote.
+pub async fn remote_linked() -> bool {
+    let link = REMOTE_LINK.lock().await;
+    link.pairing.session().is_some()
+        && radio_link_alive(link.last_packet_ms, Instant::now().as_millis())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn remote_messages_round_trip_within_their_group() {
+        let event = InputEvent {
+            parameter: ControlParameter::Green,
+            level: 9,
+        };
+        let message = RemoteMessage::Input {
+            session: 0x1234,
+            sequence: 7,
+            event,
+        };
+        assert_eq!(RemoteMessage::decode(&message.encode(2), 2), Ok(message));
+        assert_eq!(
+            RemoteMessage::decode(&message.encode(2), 3),
+            Err(RadioError::OtherGroup)
+        );
+    }
+
+    #[test]
+    fn pairing_settles_when_the_window_closes() {
+        // Board pressed at 0 s hears the peer (pressed at 3 s) and pairs when
+        // its own window closes at 5 s.
+        let pairing = Pairing::Unpaired.press(0x00F0, 0);
+        let pairing = pairing.peer_offer(0x0F00, 3_000);
+        assert_eq!(pairing.tick(4_999).session(), None);
+        assert_eq!(pairing.tick(5_000), Pairing::Paired { session: 0x0FF0 });
+        // A window with no peer ends unpaired.
+        assert_eq!(Pairing::Unpaired.press(1, 0).tick(5_000), Pairing::Unpaired);
+    }
+
+    #[test]
+    fn arbiter_holds_local_input_after_remote_changes() {
+        let mut arbiter = InputArbiter::default();
+        assert_eq!(arbiter.owner(0, true), InputSource::Local);
+        arbiter.remote_change(1_000);
+        assert!(!arbiter.local_allowed(2_999, true));
+        assert!(arbiter.local_allowed(3_000, true));
+        // Losing the link hands control back at once.
+        assert!(arbiter.local_allowed(1_500, false));
+    }
+}
@@ -0,0 +1,527 @@
+//! # Input Replay Module
+//!
+//! This module records the UI's raw inputs on the board and plays them back
+//! through the same selection and knob logic on a virtual clock, so a
+//! glitch that needs a particular human timing to show up can be captured
+//! once and then reproduced at will.
+//!
+//! ## Recording
+//!
+//! With the `record` feature, `RECORD ON` starts capturing UI passes into a
+//! RAM buffer of [`TRACE_CAPACITY`] passes: for each pass, the time since
+//! recording started, the two buttons, and the raw knob reading if the pass
+//! sampled the knob. A pass whose inputs are the same as the last one
+//! stored is skipped, so a board left alone costs nothing and a busy one
+//! fills the buffer in about [`TRACE_CAPACITY`] × [`UI_POLL`] at worst.
+//! Recording stops on `RECORD OFF` or when the buffer is full, and
+//! `RECORD DUMP` prints the trace, one pass per `trace:` line.
+//!
+//! ## Trace Format
+//!
+//! A trace is text, one pass per line, in time order:
+//!
+//! ```text
+//! <ms> <ab> <raw>
+//! ```
+//!
+//! - `<ms>`: milliseconds since recording started
+//! - `<ab>`: buttons A and B, `1` if pressed, so `10` is A alone
+//! - `<raw>`: the raw knob reading (see [`KNOB_RAW_MAX`]), or `-` if the
+//!   pass didn't sample the knob
+//!
+//! Blank lines and lines starting with `#` are ignored, and so is a
+//! leading `trace:`, so a dump can be pasted from the terminal as it is,
+//! into a bug report or a test.
+//!
+//! ## Replay
+//!
+//! [`replay`] runs a trace through a fresh [`UiCore`] and
+//! [`KnobPipeline`], with the knob responses of [`KNOB_CONFIG`], and hands
+//! back what the UI would do as [`ReplayEvent`]s. Each line is a pass at its
+//! own time; since identical passes weren't stored, the passes between two
+//! lines are run every [`UI_POLL`] with the earlier line's inputs, as the
+//! loop would have. The clock is the trace's, so the result is the same on
+//! every run. Hardware-only inputs (touch, stuck buttons, the console) are
+//! not part of a trace.
+//!
+//! ## Regression Traces
+//!
+//! The tests below replay traces of the reported glitches: a knob
+//! flapping on a level boundary, a chord released one button at a time,
+//! and the knob across a switch of parameter. Each pins the events the UI
+//! produces for it.
+use crate::*;
+
+/// Most passes a recording holds.
+pub const TRACE_CAPACITY: usize = 512;
+
+/// One UI pass of a trace.
+///
+/// Prints as a trace line.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TracePass {
+    /// Time since recording started, in milliseconds.
+    pub at_ms: u32,
+    /// Whether button A was pressed.
+    pub a: bool,
+    /// Whether button B was pressed.
+    pub b: bool,
+    /// Raw knob reading, or `None` if the pass didn't sample the knob.
+    pub raw: Option<i16>,
+}
+
+impl TracePass {
+    /// Whether two passes saw the same inputs, whatever their times.
+    fn same_inputs(&self, other: &TracePass) -> bool {
+        (self.a, self.b, self.raw) == (other.a, other.b, other.raw)
+    }
+}
+
+impl core::fmt::Display for TracePass {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "{} {}{} ", self.at_ms, self.a as u8, self.b as u8)?;
+        match self.raw {
+            Some(raw) => write!(f, "{}", raw),
+            None => write!(f, "-"),
+        }
+    }
+}
+
+/// A malformed trace line.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BadTraceLine;
+
+/// Where a trace could not be replayed.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TraceError {
+    /// Line number, from 1.
+    pub line: usize,
+}
+
+/// Parses one trace line.
+///
+/// # Returns
+/// The pass, or `None` for a blank or comment line.
+pub fn parse_trace_line(line: &str) -> Result<Option<TracePass>, BadTraceLine> {
+    let line = line.trim();
+    let line = line.strip_prefix("trace:").unwrap_or(line).trim();
+    if line.is_empty() || line.starts_with('#') {
+        return Ok(None);
+    }
+    let mut words = line.split_ascii_whitespace();
+    let (Some(at_ms), Some(buttons), Some(raw), None) =
+        (words.next(), words.next(), words.next(), words.next())
+    else {
+        return Err(BadTraceLine);
+    };
+    let at_ms = at_ms.parse().map_err(|_| BadTraceLine)?;
+    let (a, b) = match buttons.as_bytes() {
+        [a @ (b'0' | b'1'), b @ (b'0' | b'1')] => (*a == b'1', *b == b'1'),
+        _ => return Err(BadTraceLine),
+    };
+    let raw = match raw {
+        "-" => None,
+        raw => Some(raw.parse().map_err(|_| BadTraceLine)?),
+    };
+    Ok(Some(TracePass { at_ms, a, b, raw }))
+}
+
+/// What the UI did during a replay.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ReplayEvent {
+    /// The knob now controls a parameter ("Now controlling").
+    Select(ControlParameter),
+    /// A non-editing chord action ran on release.
+    Chord(ChordAction),
+    /// A button combination was held for [`LONG_HOLD`].
+    LongHold(ControlParameter),
+    /// A knob level was written to a parameter.
+    Write(ControlParameter, u32),
+}
+
+/// Plays a trace through the UI's selection and knob logic.
+///
+/// # Arguments
+/// * `trace` - Trace text (see the [module docs](self))
+/// * `chord` - What the A+B chord does
+/// * `on_event` - Called with each event and its trace time in milliseconds
+///
+/// # Returns
+/// The first malformed line, or one earlier than the line before it, as
+/// an error; events up to it have been reported.
+pub fn replay(
+    trace: &str,
+    chord: ChordAction,
+    mut on_event: impl FnMut(u64, ReplayEvent),
+) -> Result<(), TraceError> {
+    let mut run = Replay {
+        core: UiCore::new(chord),
+        knob_path: KnobPipeline::new(),
+        written: [None; ControlParameter::ALL.len()],
+    };
+    let mut last: Option<TracePass> = None;
+    for (index, line) in trace.lines().enumerate() {
+        let error = TraceError { line: index + 1 };
+        let Some(pass) = parse_trace_line(line).map_err(|_| error)? else {
+            continue;
+        };
+        if let Some(last) = last {
+            if pass.at_ms < last.at_ms {
+                return Err(error);
+            }
+            let mut now_ms = last.at_ms as u64 + UI_POLL.as_millis();
+            while now_ms < pass.at_ms as u64 {
+                run.pass(now_ms, &last, &mut on_event);
+                now_ms += UI_POLL.as_millis();
+            }
+        }
+        run.pass(pass.at_ms as u64, &pass, &mut on_event);
+        last = Some(pass);
+    }
+    Ok(())
+}
+
+/// State carried between the passes of a [`replay`].
+struct Replay {
+    core: UiCore,
+    knob_path: KnobPipeline,
+    /// Level last written to each parameter, by [`ControlParameter`] index.
+    written: [Option<u32>; 5],
+}
+
+impl Replay {
+    /// Runs one UI pass on a trace pass's inputs, as [`Ui::run`] does.
+    fn pass(&mut self, now_ms: u64, pass: &TracePass, on_event: &mut impl FnMut(u64, ReplayEvent)) {
+        let step = self.core.step(now_ms, pass.a, pass.b);
+        if let Some(action) = step.chord {
+            on_event(now_ms, ReplayEvent::Chord(action));
+        }
+        if step.long_hold {
+            on_event(now_ms, ReplayEvent::LongHold(step.buttons));
+        }
+        let Some(parameter) = step.parameter else {
+            return;
+        };
+        let level = pass
+            .raw
+            .map(|raw| knob_level(raw, KNOB_CONFIG.response(parameter)));
+        let slot = &mut self.written[parameter as usize];
+        if self.core.select(parameter) {
+            // Without the parameter's value, ramp from its last write.
+            let current = slot.or(level).unwrap_or(0);
+            self.knob_path.reset(now_ms, current);
+            on_event(now_ms, ReplayEvent::Select(parameter));
+        }
+        let Some(level) = level else {
+            return;
+        };
+        if let Some(level) = self.knob_path.push(now_ms, level) {
+            *slot = Some(level);
+            on_event(now_ms, ReplayEvent::Write(parameter, level));
+        }
+    }
+}
+
+/// Passes captured by `RECORD ON`.
+pub struct TraceRecorder {
+    passes: [TracePass; TRACE_CAPACITY],
+    len: usize,
+    /// When recording started, in milliseconds since boot, while recording.
+    start_ms: Option<u64>,
+    /// The pass in progress, stored once it is complete.
+    pending: Option<TracePass>,
+}
+
+impl TraceRecorder {
+    /// Creates an empty recorder, not recording.
+    pub const fn new() -> Self {
+        Self {
+            passes: [TracePass {
+                at_ms: 0,
+                a: false,
+                b: false,
+                raw: None,
+            }; TRACE_CAPACITY],
+            len: 0,
+            start_ms: None,
+            pending: None,
+        }
+    }
+
+    /// Clears the trace and starts recording.
+    pub fn start(&mut self, now_ms: u64) {
+        self.len = 0;
+        self.pending = None;
+        self.start_ms = Some(now_ms);
+    }
+
+    /// Stores the pass in progress and stops recording.
+    pub fn stop(&mut self) {
+        self.commit();
+        self.start_ms = None;
+    }
+
+    /// Whether passes are being recorded.
+    pub fn recording(&self) -> bool {
+        self.start_ms.is_some()
+    }
+
+    /// Starts a pass, storing the one before.
+    ///
+    /// # Arguments
+    /// * `now_ms` - Current time in milliseconds since boot
+    /// * `a` - Whether button A is down
+    /// * `b` - Whether button B is down
+    ///
+    /// # Returns
+    /// `false` if the buffer just filled up and recording stopped.
+    pub fn pass(&mut self, now_ms: u64, a: bool, b: bool) -> bool {
+        if !self.commit() {
+            return false;
+        }
+        if let Some(start_ms) = self.start_ms {
+            let at_ms = now_ms.saturating_sub(start_ms).min(u32::MAX as u64) as u32;
+            self.pending = Some(TracePass {
+                at_ms,
+                a,
+                b,
+                raw: None,
+            });
+        }
+        true
+    }
+
+    /// Adds the knob reading to the pass in progress.
+    pub fn knob(&mut self, raw: i16) {
+        if let Some(pending) = self.pending.as_mut() {
+            pending.raw = Some(raw);
+        }
+    }
+
+    /// Passes recorded so far.
+    pub fn passes(&self) -> &[TracePass] {
+        &self.passes[..self.len]
+    }
+
+    /// Stores the pass in progress unless its inputs repeat the last one.
+    ///
+    /// # Returns
+    /// `false` if there was no room for it, which stops recording.
+    fn commit(&mut self) -> bool {
+        let Some(pending) = self.pending.take() else {
+            return true;
+        };
+        if self
+            .passes()
+            .last()
+            .is_some_and(|last| last.same_inputs(&pending))
+        {
+            return true;
+        }
+        if self.len == TRACE_CAPACITY {
+            self.start_ms = None;
+            return false;
+        }
+        self.passes[self.len] = pending;
+        self.len += 1;
+        true
+    }
+}
+
+impl Default for TraceRecorder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Global input recording, fed by the UI loop.
+#[cfg(feature = "record")]
+pub static INPUT_TRACE: Mutex<CriticalSectionRawMutex, TraceRecorder> =
+    Mutex::new(TraceRecorder::new());
+
+/// Records the start of a UI pass, while recording.
+#[cfg(feature = "record")]
+pub async fn record_ui_pass(a: bool, b: bool) {
+    let mut trace = INPUT_TRACE.lock().await;
+    if trace.recording() && !trace.pass(Instant::now().as_millis(), a, b) {
+        rprintln!("record: buffer full, stopped");
+    }
+}
+
+/// Records the knob reading of the current UI pass, while recording.
+#[cfg(feature = "record")]
+pub async fn record_knob_raw(raw: i16) {
+    INPUT_TRACE.lock().await.knob(raw);
+}
+
+/// Starts (clearing the last trace) or stops recording.
+#[cfg(feature = "record")]
+pub async fn set_recording(enabled: bool) {
+    let mut trace = INPUT_TRACE.lock().await;
+    if enabled {
+        trace.start(Instant::now().as_millis());
+        rprintln!("record: on");
+    } else {
+        trace.stop();
+        rprintln!("record: off, {} passes", trace.passes().len());
+    }
+}
+
+/// Prints the recorded trace, one pass per `trace:` line.
+#[cfg(feature = "record")]
+pub async fn dump_trace() {
+    let trace = INPUT_TRACE.lock().await;
+    rprintln!("trace: # {} passes", trace.passes().len());
+    for pass in trace.passes() {
+        rprintln!("trace: {}", pass);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn trace_lines_round_trip() {
+        let pass = TracePass {
+            at_ms: 1250,
+            a: true,
+            b: false,
+            raw: Some(8123),
+        };
+        check_golden([pass], &["1250 10 8123\n"]).unwrap();
+        assert_eq!(parse_trace_line("trace: 1250 10 8123"), Ok(Some(pass)));
+        let unsampled = TracePass { raw: None, ..pass };
+        check_golden([unsampled], &["1250 10 -\n"]).unwrap();
+        assert_eq!(parse_trace_line("1250 10 -"), Ok(Some(unsampled)));
+        // Comments and blank lines hold no pass.
+        assert_eq!(parse_trace_line("# knob flapping"), Ok(None));
+        assert_eq!(parse_trace_line("   "), Ok(None));
+        // Anything else is malformed.
+        assert!(parse_trace_line("1250 12 8123").is_err());
+        assert!(parse_trace_line("1250 10").is_err());
+    }
+
+    #[test]
+    fn flapping_knob_writes_once_per_settle() {
+        // A knob resting on the frame rate's boundary between levels 3 and 4
+        // is written once each way, then nothing while it flaps, until it has
+        // stayed on 3 for KNOB_DITHER_HOLD.
+        const FLAPPING: &str = "
+        # Knob on the 3/4 boundary, let go on 3.
+        0 00 8760
+        50 00 8790
+        100 00 8762
+        150 00 8788
+        200 00 8765
+        250 00 8791
+        300 00 8760
+        600 00 8758
+        ";
+        let mut events = [None; 8];
+        let mut n = 0;
+        replay(FLAPPING, ChordAction::EditRed, |at_ms, event| {
+            events[n] = Some((at_ms, event));
+            n += 1;
+        })
+        .unwrap();
+        assert_eq!(
+            &events[..n],
+            &[
+                Some((0, ReplayEvent::Write(ControlParameter::FrameRate, 3))),
+                Some((50, ReplayEvent::Write(ControlParameter::FrameRate, 4))),
+                Some((500, ReplayEvent::Write(ControlParameter::FrameRate, 3))),
+            ]
+        );
+    }
+
+    #[test]
+    fn chord_release_writes_once() {
+        // With ChordAction::Next, a chord let go of A first passes through B
+        // alone on the way out, which must neither select nor write green.
+        const CHORD_RELEASE: &str = "
+        0 00 10000
+        500 10 10000
+        530 11 -
+        930 01 10003
+        960 00 10003
+        ";
+        let mut events = [None; 8];
+        let mut n = 0;
+        replay(CHORD_RELEASE, ChordAction::Next, |at_ms, event| {
+            events[n] = Some((at_ms, event));
+            n += 1;
+        })
+        .unwrap();
+        assert_eq!(
+            &events[..n],
+            &[
+                Some((0, ReplayEvent::Write(ControlParameter::FrameRate, 5))),
+                Some((500, ReplayEvent::Select(ControlParameter::Blue))),
+                Some((500, ReplayEvent::Write(ControlParameter::Blue, 9))),
+                Some((930, ReplayEvent::Chord(ChordAction::Next))),
+                Some((930, ReplayEvent::Select(ControlParameter::Red))),
+                Some((930, ReplayEvent::Write(ControlParameter::Red, 9))),
+            ]
+        );
+    }
+
+    #[test]
+    fn mode_switch_follows_the_buttons() {
+        // A knob parked at the top writes the top level to each parameter it
+        // is switched to, even though the level is the one last written.
+        const MODE_SWITCH: &str = "
+        0 00 16000
+        400 10 16010
+        800 00 15990
+        ";
+        let mut events = [None; 8];
+        let mut n = 0;
+        replay(MODE_SWITCH, ChordAction::EditRed, |at_ms, event| {
+            events[n] = Some((at_ms, event));
+            n += 1;
+        })
+        .unwrap();
+        assert_eq!(
+            &events[..n],
+            &[
+                Some((0, ReplayEvent::Write(ControlParameter::FrameRate, 15))),
+                Some((400, ReplayEvent::Select(ControlParameter::Blue))),
+                Some((400, ReplayEvent::Write(ControlParameter::Blue, 15))),
+                Some((800, ReplayEvent::Select(ControlParameter::FrameRate))),
+                Some((800, ReplayEvent::Write(ControlParameter::FrameRate, 15))),
+            ]
+        );
+    }
+
+    #[test]
+    fn bad_traces_report_the_line() {
+        // Malformed lines and time running backward are reported by line.
+        assert_eq!(
+            replay("0 00 1\n5 00", ChordAction::EditRed, |_, _| {}),
+            Err(TraceError { line: 2 })
+        );
+        assert_eq!(
+            replay("9 00 1\n5 00 1", ChordAction::EditRed, |_, _| {}),
+            Err(TraceError { line: 2 })
+        );
+    }
+
+    #[test]
+    fn recorder_keeps_only_changes() {
+        let mut trace = TraceRecorder::new();
+        // Nothing is kept before recording starts.
+        trace.pass(50, false, false);
+        trace.start(1000);
+        trace.pass(1000, false, false);
+        trace.knob(8123);
+        // Passes with the same inputs are skipped...
+        trace.pass(1050, false, false);
+        trace.knob(8123);
+        // ...and the chord held with no knob sample is kept.
+        trace.pass(1100, true, true);
+        trace.stop();
+        let lines = trace.passes();
+        check_golden(lines, &["0 00 8123\n", "100 11 -\n"]).unwrap();
+    }
+}
@@ -0,0 +1,117 @@
+//! # Controlled Reset Module
+//!
+//! This module resets the chip on purpose, as the knob does once the ADC
+//! is wedged, and first shows a solid "panic color" on the RGB LED for a
+//! moment, so a reset is visible to someone watching without a debugger.
+//!
+//! ## Panic Color
+//!
+//! [`RESET_COLOR`] sets the color (dim red by default) and how long it is
+//! held. `controlled_reset` never returns to the executor, so the RGB
+//! loop can't run again: it takes the LED pins over, stops the hardware
+//! PWM if `hwpwm` owns them, and plays the color itself with busy-waits,
+//! lighting one channel at a time in [`RESET_FRAME`] frames like the
+//! software PWM scan.
+//!
+//! ## Testing
+//!
+//! [`show_reset_color`] drives any [`ResetOutput`]. On the board that is
+//! the stolen pins and a cycle-counted delay; in the simulation build
+//! [`PwmTrace`] implements it on a virtual clock, so the waveform the
+//! reset path would drive can be checked without a board.
+use crate::*;
+
+/// One frame of the panic color: a slot for each channel in turn.
+pub const RESET_FRAME: Duration = Duration::from_millis(3);
+
+/// Color shown before a controlled reset.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ResetColor {
+    /// RGB levels, 0 to [`LEVELS`]-1, shown at full brightness.
+    pub levels: [u32; 3],
+    /// How long the color is held before the reset.
+    pub duration: Duration,
+}
+
+/// Panic color shown by `controlled_reset`.
+pub const RESET_COLOR: ResetColor = ResetColor {
+    levels: [4, 0, 0],
+    duration: Duration::from_millis(500),
+};
+
+/// LED output the panic color is played on.
+pub trait ResetOutput {
+    /// Drives one LED's pin (0 = red, 1 = green, 2 = blue).
+    fn set(&mut self, led: usize, high: bool);
+    /// Waits without yielding to the executor.
+    fn busy_wait(&mut self, duration: Duration);
+}
+
+/// Plays a panic color on `output`, leaving every LED off.
+///
+/// Each [`RESET_FRAME`] is split into a slot per channel, and a channel is
+/// lit for `level / LEVELS` of its slot, as in the software PWM scan, so
+/// only one LED is ever lit. The color is held for whole frames, at least
+/// one.
+///
+/// # Arguments
+/// * `output` - Pins and delay to play it with
+/// * `color` - Color and duration
+pub fn show_reset_color(output: &mut impl ResetOutput, color: ResetColor) {
+    let slot = RESET_FRAME.as_ticks() / 3;
+    let frames = (color.duration.as_ticks() / RESET_FRAME.as_ticks()).max(1);
+    let on_ticks = color
+        .levels
+        .map(|level| slot * level.min(LEVELS - 1) as u64 / LEVELS as u64);
+    for led in 0..3 {
+        output.set(led, false);
+    }
+    for _ in 0..frames {
+        for (led, &on) in on_ticks.iter().enumerate() {
+            if on > 0 {
+                output.set(led, true);
+                output.busy_wait(Duration::from_ticks(on));
+                output.set(led, false);
+            }
+            output.busy_wait(Duration::from_ticks(slot - on));
+        }
+        // The slots' rounding, so every frame lasts exactly RESET_FRAME.
+        output.busy_wait(Duration::from_ticks(RESET_FRAME.as_ticks() - slot * 3));
+    }
+}
+
+#[cfg(all(test, not(feature = "hwpwm")))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reset_color_holds_its_share_of_every_frame() {
+        // The reset path in the simulation build: only red is lit, for its
+        // share of every frame, and the color is held for the whole duration.
+        let color = ResetColor {
+            levels: [4, 0, 0],
+            duration: Duration::from_millis(300),
+        };
+        let mut trace = PwmTrace::new();
+        show_reset_color(&mut trace, color);
+        let frames = color.duration.as_ticks() / RESET_FRAME.as_ticks();
+        let slot = RESET_FRAME.as_ticks() / 3;
+        assert_eq!(trace.now(), frames * RESET_FRAME.as_ticks());
+        assert_eq!(trace.on_ticks(0), frames * (slot * 4 / LEVELS as u64));
+        assert_eq!(trace.on_ticks(1), 0);
+        assert_eq!(trace.on_ticks(2), 0);
+        assert_eq!(trace.max_lit(), 1);
+
+        // A full white is three full slots, one LED at a time.
+        let white = ResetColor {
+            levels: [LEVELS - 1; 3],
+            ..color
+        };
+        let mut trace = PwmTrace::new();
+        show_reset_color(&mut trace, white);
+        for led in 0..3 {
+            assert_eq!(trace.on_ticks(led), frames * (slot * 15 / LEVELS as u64));
+        }
+        assert_eq!(trace.max_lit(), 1);
+    }
+}
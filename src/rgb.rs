@@ -20,15 +20,41 @@
 //!
 //! ## Timing Calculation
 //!
-//! The PWM timing is calculated as:
+//! The frame period is computed first, in [`embassy_time`] ticks:
 //! ```text
-//! tick_time = 1_000_000 / (3 * frame_rate * LEVELS)
+//! frame_ticks = TICK_HZ / frame_rate
 //! ```
-//! Where:
-//! - `1_000_000`: Microseconds per second
-//! - `3`: Number of LEDs (Red, Green, Blue)
-//! - `frame_rate`: Target FPS (10-160)
-//! - `LEVELS`: Intensity levels (16, giving 0-15 range)
+//! The frame is then cut into `3 * LEVELS` PWM ticks (one slot of
+//! `LEVELS` ticks per LED), where PWM tick `k` ends at
+//! ```text
+//! frame_ticks * k / (3 * LEVELS)
+//! ```
+//! Rounding each boundary, rather than each tick, spreads the remainder
+//! over the frame Bresenham-style: ticks differ by at most one timer tick,
+//! and the slots always add up to exactly `frame_ticks`, so truncation
+//! can't make frames short (or long) by a rate-dependent amount.
+//!
+//! `frame_ticks` itself is rounded down, and the remainder
+//! `TICK_HZ % frame_rate` is carried from frame to frame the same way
+//! (see [`FrameCarry`]): whenever the carried remainders add up to a whole
+//! timer tick, that frame ends one tick later. Frame `n` then lasts
+//! ```text
+//! TICK_HZ * (n + 1) / frame_rate - TICK_HZ * n / frame_rate
+//! ```
+//! ticks, so any run of frames is within a tick of the requested rate,
+//! rather than short by the remainder every frame.
+//!
+//! ## Feasibility
+//!
+//! The shortest pulse the software PWM ever times is one PWM tick split
+//! over the [phases](#pwm-phases), `1 / (frame_rate * 3 * LEVELS *
+//! PWM_PHASES)`. Below [`TIMER_FLOOR_US`] the timer's wake-up latency
+//! swamps it and the low levels come out wrong, so [`fit_frame_rate`]
+//! checks every frame rate before it is used: [`set_frame_rate`] lowers
+//! an infeasible rate to the fastest feasible one and logs why, and if
+//! even [`FRAME_RATE_MIN`] is infeasible (too many levels or phases for
+//! the timer) the scanout runs degraded at that rate, with a warning,
+//! rather than quietly.
 //!
 //! ## Hardware Integration
 //!
@@ -36,153 +62,1402 @@
 //! - **Timing**: Microsecond-precision delays using Embassy timers
 //! - **Shared State**: Reads RGB levels and frame rate from shared memory
 //!
-//! ## Usage Example
+//! ## Hardware PWM Backend
 //!
-//! ```rust,no_run
-//! let rgb_pins = [red_pin, green_pin, blue_pin];
-//! let rgb = Rgb::new(rgb_pins, 60); // 60 FPS initial rate
-//! rgb.run().await; // Start the RGB control loop
-//! ```
+//! With the `hwpwm` feature the firmware's `Rgb` drives a common-ground analog
+//! RGB LED from three channels of the PWM1 peripheral instead, so all three
+//! colors are lit at once and the CPU only updates compare values. The PWM
+//! period follows the frame rate (a 125 kHz counter with a top of
+//! `125_000 / frame_rate`), and each channel's duty is its software-PWM
+//! on-time as a fraction of its slot, so brightness and ceiling scaling
+//! are identical in both backends.
+//!
+//! Each channel can also be switched off entirely through [`PWM_CHANNELS`]
+//! (the console's `PWM` command), which disconnects its pin from the
+//! peripheral rather than setting a zero duty. The pin then falls back to
+//! plain GPIO, an output driven low as `SimplePwm` left it, so that
+//! color stays dark and the pin is free to be reconfigured for another
+//! use. The peripheral is stopped for the moment of the change, which can
+//! blank the other channels for up to one PWM period.
+//!
+//! ## Frame Schedule
+//!
+//! The software PWM plays each frame from a [`FrameSchedule`]: the sorted
+//! pin changes of one frame, each with its deadline in timer ticks from
+//! the frame start. [`FrameSchedule::new`] is the one place the level
+//! processing stages compose (level to ticks, master brightness, ceiling,
+//! full-on limit), and it only runs when the levels, brightness or frame
+//! rate change; the frame loop just waits for each deadline and sets the
+//! pin. Changes due at the same tick share one wait, with the pin going
+//! low first, and a dark channel has no changes at all, so a frame with a
+//! single lit channel takes two timer waits instead of six. Deadlines are
+//! absolute within the frame, so wake-up latency on one change doesn't
+//! push back the rest of the frame. Given a measured overhead through
+//! `Rgb::with_timer_overhead`, every deadline is also brought forward by
+//! it, so the edges and the frame end land on time; see the
+//! [`timercal`](crate::timercal) module.
+//!
+//! ## PWM Phases
+//!
+//! With [`PWM_PHASES`] above 1, the frame is cut into that many phases,
+//! each holding a share of every channel's slot, and each channel's
+//! on-time is split into one shorter pulse per phase. The pulses add up to
+//! exactly the single-pulse on-time, so brightness is unchanged, but a dim
+//! channel flickers at a multiple of the frame rate, which is much harder
+//! to see. The cost is more timer waits per frame (up to six per phase),
+//! and more of each pulse lost to wake-up latency.
+//!
+//! At the bottom of the frame-rate range a whole frame is long enough to
+//! flicker visibly. Below [`FLICKER_THRESHOLD_FPS`], `Rgb::run` raises
+//! the phase count so the pulses still come at least that often, as far
+//! as [`PWM_PHASES_MAX`] and the timer floor allow (see
+//! [`anti_flicker_phases`]), and logs the change.
+//!
+//! ## Dithering
+//!
+//! The timer only waits whole ticks, so brightness scaling usually leaves a
+//! fraction of a tick over. The software PWM can spread that fraction over
+//! frames ([`DitherMode`], set with the console's `DITHER` command),
+//! delaying a channel's falling edge by one tick in some frames so the
+//! long-run average on-time is exact:
+//!
+//! - [`DitherMode::Ordered`]: A fixed 16-frame Bayer-style sequence. The
+//!   pattern repeats exactly, so any flicker it adds sits at a steady
+//!   frame rate / 16 and is easy to judge, but a fraction is only reached
+//!   after a full cycle.
+//! - [`DitherMode::ErrorDiffusion`]: Carries each frame's rounding error
+//!   into the next. The extra ticks are spread as evenly as possible,
+//!   which is smoother on average, but as the fraction drifts (say, with
+//!   lux compensation) the spacing keeps changing and can shimmer.
+//! - [`DitherMode::Off`]: Rounds down every frame, as without dithering.
+//!
+//! How much it matters depends on the timer tick: at 1 MHz a tick is a
+//! microsecond and only the dimmest levels benefit; with a 32 kHz tick it
+//! is ~30 µs and the difference is visible.
+//!
+//! ## On-Time Accuracy
+//!
+//! At [`Verbosity::Debug`] the software PWM timestamps each channel's
+//! `set_high`/`set_low` and compares the real on-time with the requested
+//! one, logging the mean and worst error per channel every
+//! [`ON_TIME_REPORT`] (see [`OnTimeError`]), along with the average number
+//! of timer waits per frame and the frame rate achieved against the one
+//! requested. With several [PWM phases](#pwm-phases), each
+//! pulse is measured on its own. Timer resolution and wake-up
+//! latency make short on-times relatively long, which shows up as
+//! brightness nonlinearity at the low levels.
+//!
+//! ## Benchmark
+//!
+//! With the `bench` feature and a probe given to `Rgb::with_bench`, a
+//! marker pin follows every pin change so the edge jitter can be captured
+//! and graded; see the [`bench`](crate::bench) module.
+//!
+//! ## Camera Sync
+//!
+//! With the `camsync` feature and a trigger input given to
+//! `Rgb::with_trigger`, each frame can be held until an external
+//! trigger edge; see the [`camsync`](crate::camsync) module.
 use crate::*;
+use core::sync::atomic::AtomicU32;
+use embassy_time::TICK_HZ;
+
+/// PWM counter clock with the `Div128` prescaler, in Hz.
+const PWM_CLOCK_HZ: u64 = 16_000_000 / 128;
+/// Largest counter top the PWM peripheral accepts.
+const PWM_TOP_MAX: u64 = 0x7FFF;
+/// Interval between on-time accuracy reports at [`Verbosity::Debug`].
+pub const ON_TIME_REPORT: Duration = Duration::from_secs(1);
 
-/// Type alias for the RGB LED pin array.
+/// Computes the hardware PWM counter top for a frame rate.
 ///
-/// Represents the three GPIO output pins that control the RGB LED:
-/// - Index 0: Red LED pin
-/// - Index 1: Green LED pin  
-/// - Index 2: Blue LED pin
+/// # Returns
+/// `125_000 / frame_rate`, so one PWM period is one frame, limited to the
+/// peripheral's 15-bit counter.
+pub fn pwm_top(frame_rate: u64) -> u16 {
+    (PWM_CLOCK_HZ / frame_rate.max(1)).clamp(1, PWM_TOP_MAX) as u16
+}
+
+/// Maps a channel's on-time to a hardware PWM compare value.
 ///
-/// Each pin is configured as a standard output with low initial state.
-type RgbPins = [Output<'static, AnyPin>; 3];
-/// RGB LED controller using software PWM.
+/// The nRF PWM output starts each period low and rises at the compare
+/// value, so the compare value is the *off* count: `top` is dark and 0 is
+/// fully on.
 ///
-/// Manages three LEDs with individual intensity control and configurable
-/// frame rate. Reads RGB levels and frame rate from shared state.
-pub struct Rgb {
-    /// GPIO pins for RGB LEDs [red, green, blue].
-    rgb: RgbPins,
-    /// Cached RGB intensity levels (0 to [`LEVELS`]-1).
-    levels: [u32; 3],
-    /// PWM timing interval in microseconds.
-    tick_time: u64,
-    /// Current frame rate for change detection.
-    current_frame_rate: u64,
+/// # Arguments
+/// * `on_time` - On-time within the slot, in timer ticks
+/// * `slot_time` - Slot length, in timer ticks
+/// * `top` - Counter top from [`pwm_top`]
+pub fn pwm_compare(on_time: u64, slot_time: u64, top: u16) -> u16 {
+    let on = on_time.min(slot_time) * top as u64 / slot_time.max(1);
+    top - on as u16
+}
+
+/// Hardware PWM channel enables [red, green, blue], set from the console.
+///
+/// A disabled channel's pin is disconnected from the PWM peripheral and
+/// driven low as plain GPIO (see the [module docs](self)).
+///
+/// Default value: all enabled
+#[cfg(feature = "hwpwm")]
+pub static PWM_CHANNELS: Mutex<CriticalSectionRawMutex, [bool; 3]> = Mutex::new([true; 3]);
+
+/// Sets one channel's enable flag, leaving the others as they are.
+///
+/// # Arguments
+/// * `channels` - Current enables [red, green, blue]
+/// * `led` - Channel to change (0 = red, 1 = green, 2 = blue)
+/// * `enabled` - New enable for that channel
+///
+/// # Returns
+/// The updated enables; unchanged if `led` is not a channel.
+pub fn with_channel_enabled(channels: [bool; 3], led: usize, enabled: bool) -> [bool; 3] {
+    let mut channels = channels;
+    if let Some(channel) = channels.get_mut(led) {
+        *channel = enabled;
+    }
+    channels
+}
+/// Shortest PWM slice the timer can honor, in microseconds.
+///
+/// Declared from the timer's wake-up latency; the on-time error report at
+/// [`Verbosity::Debug`] shows whether it holds on a given board.
+pub const TIMER_FLOOR_US: u64 = 20;
+
+/// Whether a frame rate's PWM slices are long enough for the timer.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FrameRateFit {
+    /// Every slice is at least the floor.
+    Feasible,
+    /// Too fast; this is the fastest feasible rate.
+    Clamped(u64),
+    /// Even [`FRAME_RATE_MIN`] is too fast; output at the low levels will
+    /// be inaccurate.
+    Degraded,
+}
+
+/// Length of the shortest PWM slice, in whole microseconds.
+///
+/// # Arguments
+/// * `frame_rate` - Frame rate in FPS
+/// * `levels` - Levels per channel
+/// * `phases` - PWM phases per frame
+pub fn min_slice_us(frame_rate: u64, levels: u32, phases: u32) -> u64 {
+    let slices = frame_rate.max(1) * 3 * levels.max(1) as u64 * phases.max(1) as u64;
+    1_000_000 / slices
 }
 
-impl Rgb {
-    /// Calculates PWM timing for the given frame rate.
+/// Checks a frame rate against the timer floor.
+///
+/// # Arguments
+/// * `frame_rate` - Frame rate in FPS, already in range
+/// * `levels` - Levels per channel
+/// * `phases` - PWM phases per frame
+/// * `floor_us` - Shortest slice the timer can honor, in microseconds
+pub fn fit_frame_rate(frame_rate: u64, levels: u32, phases: u32, floor_us: u64) -> FrameRateFit {
+    if min_slice_us(frame_rate, levels, phases) >= floor_us {
+        return FrameRateFit::Feasible;
+    }
+    // floor(1e6 / (rate * d)) >= floor_us exactly when rate <= 1e6 / (d * floor_us).
+    let divisions = 3 * levels.max(1) as u64 * phases.max(1) as u64;
+    let fastest = 1_000_000 / (divisions * floor_us.max(1));
+    if fastest >= FRAME_RATE_MIN {
+        FrameRateFit::Clamped(fastest)
+    } else {
+        FrameRateFit::Degraded
+    }
+}
+
+/// Frame rate the scanout runs at for a requested one.
+///
+/// Clamps to [`FRAME_RATE_MIN`]..=[`FRAME_RATE_MAX`] and, with software
+/// PWM, lowers an infeasible rate as [`fit_frame_rate`] says. This is the
+/// read path of `Rgb::run`, which also covers the power-on default that
+/// never went through [`set_frame_rate`].
+///
+/// # Arguments
+/// * `requested` - Frame rate from the shared state, in FPS
+pub fn scanout_frame_rate(requested: u64) -> u64 {
+    let frame_rate = validate_frame_rate(requested).unwrap_or_else(|clamped| clamped);
+    #[cfg(not(feature = "hwpwm"))]
+    let frame_rate = match fit_frame_rate(frame_rate, LEVELS, PWM_PHASES, TIMER_FLOOR_US) {
+        FrameRateFit::Clamped(feasible) => feasible,
+        FrameRateFit::Feasible | FrameRateFit::Degraded => frame_rate,
+    };
+    frame_rate
+}
+
+/// PWM timing used by the scanout, published for diagnostics.
+///
+/// All durations are in [`embassy_time`] ticks ([`TICK_HZ`] per second).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RgbTiming {
+    /// Frame rate the timing was computed for, in FPS.
+    pub frame_rate: u64,
+    /// Length of one full frame (all three channels) in timer ticks,
+    /// rounded down; [`FrameCarry`] adds the remainder back.
+    pub frame_ticks: u64,
+}
+
+impl RgbTiming {
+    /// Computes the timing the scanout uses for a frame rate.
+    pub fn for_frame_rate(frame_rate: u64) -> Self {
+        Self {
+            frame_rate,
+            frame_ticks: TICK_HZ / frame_rate.max(1),
+        }
+    }
+    /// Time from the frame start to the end of PWM tick `tick`, in timer
+    /// ticks, rounded down.
+    fn boundary(&self, tick: u64) -> u64 {
+        debug_assert!(tick <= 3 * LEVELS as u64);
+        self.frame_ticks.saturating_mul(tick) / (3 * LEVELS as u64)
+    }
+    /// Length of one LED's slot in timer ticks.
     ///
-    /// # Formula
-    /// ```rust no_run
-    /// tick_time = 1_000_000 / (3 * frame_rate * LEVELS)
-    /// ```
+    /// Slots differ by at most one tick and sum to [`Self::frame_ticks`].
     ///
     /// # Arguments
-    /// * `frame_rate` - Target refresh rate in FPS
+    /// * `led` - LED index (0=Red, 1=Green, 2=Blue)
+    pub fn slot_ticks(&self, led: usize) -> u64 {
+        self.level_ticks(led, LEVELS)
+    }
+    /// Shortest slot length in timer ticks, `frame_ticks / 3`.
+    pub fn min_slot_ticks(&self) -> u64 {
+        self.frame_ticks / 3
+    }
+    /// On-time of `level` at full brightness in one LED's slot, in timer
+    /// ticks.
     ///
-    /// # Returns
-    /// PWM tick time in microseconds
-    fn frame_tick_time(frame_rate: u64) -> u64 {
-        1_000_000 / (3 * frame_rate * LEVELS as u64)
+    /// # Arguments
+    /// * `led` - LED index (0=Red, 1=Green, 2=Blue)
+    /// * `level` - Level from 0 to [`LEVELS`] (a whole slot)
+    pub fn level_ticks(&self, led: usize, level: u32) -> u64 {
+        debug_assert!(led < 3);
+        let start = (led.min(2) as u64) * LEVELS as u64;
+        self.boundary(start + level.min(LEVELS) as u64)
+            .saturating_sub(self.boundary(start))
+    }
+    /// Timer ticks per second left over by rounding down
+    /// [`Self::frame_ticks`], `TICK_HZ % frame_rate`.
+    pub fn frame_remainder(&self) -> u64 {
+        TICK_HZ - self.frame_ticks * self.frame_rate.max(1)
+    }
+    /// Length of one full frame in microseconds.
+    pub fn frame_period(&self) -> u64 {
+        self.frame_ticks * 1_000_000 / TICK_HZ
     }
-    /// Creates a new RGB controller.
+}
+
+/// Explains the timing as one line, from frame rate down to tick time:
+///
+/// ```text
+/// 60 fps -> frame period 16666 us -> tick 347.2 us (period / 3 LEDs / 16 levels)
+/// ```
+///
+/// A higher frame rate means a shorter period and a shorter tick. The tick
+/// shown is the average; individual ticks are rounded to the timer.
+impl core::fmt::Display for RgbTiming {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        let tick_tenths = self.frame_ticks * 10_000_000 / TICK_HZ / (3 * LEVELS as u64);
+        write!(
+            f,
+            "{} fps -> frame period {} us -> tick {}.{} us (period / 3 LEDs / {} levels)",
+            self.frame_rate,
+            self.frame_period(),
+            tick_tenths / 10,
+            tick_tenths % 10,
+            LEVELS
+        )
+    }
+}
+
+/// Remainder of the frame length carried from frame to frame,
+/// Bresenham-style, so frames average exactly `TICK_HZ / frame_rate`.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct FrameCarry {
+    /// Remainders carried so far, in 1/frame_rate timer ticks.
+    carried: u64,
+}
+
+impl FrameCarry {
+    /// Carries one more frame's remainder.
     ///
     /// # Arguments
-    /// * `rgb` - Array of GPIO output pins [red, green, blue]
-    /// * `frame_rate` - Initial frame rate in FPS
+    /// * `timing` - Timing of the frame about to be played
     ///
-    /// # Examples
-    /// ```rust,no_run
-    /// let rgb_pins = [red_pin, green_pin, blue_pin];
-    /// let rgb = Rgb::new(rgb_pins, 60);
-    /// ```
-    pub fn new(rgb: RgbPins, frame_rate: u64) -> Self {
-        let tick_time = Self::frame_tick_time(frame_rate);
-        Self {
-            rgb,
-            levels: [0; 3],
-            tick_time,
-            current_frame_rate: frame_rate,
+    /// # Returns
+    /// Ticks to add to this frame's end: 1 once the remainders carried add
+    /// up to a whole tick, otherwise 0.
+    pub fn stretch(&mut self, timing: &RgbTiming) -> u64 {
+        let frame_rate = timing.frame_rate.max(1);
+        self.carried += timing.frame_remainder();
+        if self.carried >= frame_rate {
+            self.carried -= frame_rate;
+            1
+        } else {
+            0
         }
     }
-    /// Executes one PWM cycle for a single LED.
-    ///
-    /// This is the core PWM implementation that controls LED brightness through
-    /// time-based on/off control. The LED is turned on for a duration proportional
-    /// to the desired intensity, then turned off for the remaining time.
+}
+
+/// Requested versus measured on-time of one channel, accumulated over a
+/// report interval.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct OnTimeError {
+    /// Number of frames measured.
+    pub frames: u32,
+    /// Largest error by magnitude, in microseconds (measured - requested).
+    pub worst_us: i64,
+    /// Sum of the requested on-times, in microseconds.
+    requested_us: u64,
+    /// Sum of the measured on-times, in microseconds.
+    measured_us: u64,
+}
+
+impl OnTimeError {
+    /// Adds one frame's measurement.
     ///
-    /// # PWM Algorithm
+    /// # Arguments
+    /// * `requested_us` - On-time asked of the timer
+    /// * `measured_us` - Time from `set_high` to `set_low`
+    pub fn push(&mut self, requested_us: u64, measured_us: u64) {
+        let error = measured_us as i64 - requested_us as i64;
+        if error.abs() > self.worst_us.abs() {
+            self.worst_us = error;
+        }
+        self.frames = self.frames.saturating_add(1);
+        self.requested_us = self.requested_us.saturating_add(requested_us);
+        self.measured_us = self.measured_us.saturating_add(measured_us);
+    }
+    /// Mean requested on-time in microseconds, or 0 with no frames.
+    pub fn mean_requested_us(&self) -> u64 {
+        self.requested_us / self.frames.max(1) as u64
+    }
+    /// Mean measured on-time in microseconds, or 0 with no frames.
+    pub fn mean_measured_us(&self) -> u64 {
+        self.measured_us / self.frames.max(1) as u64
+    }
+    /// Mean error (measured - requested) in microseconds.
+    pub fn mean_error_us(&self) -> i64 {
+        (self.measured_us as i64 - self.requested_us as i64) / self.frames.max(1) as i64
+    }
+}
+
+/// Formats the report line, e.g.
+/// `on 312 us, requested 320 us, error -8 us (worst -15 us over 60 frames)`.
+impl core::fmt::Display for OnTimeError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(
+            f,
+            "on {} us, requested {} us, error {} us (worst {} us over {} frames)",
+            self.mean_measured_us(),
+            self.mean_requested_us(),
+            self.mean_error_us(),
+            self.worst_us,
+            self.frames
+        )
+    }
+}
+
+/// Sub-tick resolution of [`fine_on_times`], and the length of the
+/// [`DitherMode::Ordered`] cycle.
+pub const DITHER_STEPS: u64 = 16;
+/// Thresholds of the ordered dither, one per frame: the bit-reversed frame
+/// number, so the extra ticks of any fraction are spread across the cycle.
+const BAYER_SEQUENCE: [u64; DITHER_STEPS as usize] =
+    [0, 8, 4, 12, 2, 10, 6, 14, 1, 9, 5, 13, 3, 11, 7, 15];
+
+/// How the software PWM spreads fractions of a timer tick over frames.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DitherMode {
+    /// Round down every frame.
+    Off,
+    /// Fixed Bayer-style sequence over [`DITHER_STEPS`] frames.
+    Ordered,
+    /// Carry each frame's rounding error into the next.
+    ErrorDiffusion,
+}
+
+/// Dither mode used by the software PWM, set from the console.
+///
+/// Default value: [`DitherMode::Off`]
+#[cfg(not(feature = "hwpwm"))]
+pub static DITHER_MODE: Mutex<CriticalSectionRawMutex, DitherMode> = Mutex::new(DitherMode::Off);
+
+/// Temporal dither state for the three channels.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Dither {
+    /// Frame number within the ordered cycle.
+    frame: u64,
+    /// Accumulated fractions carried by error diffusion.
+    error: [u64; 3],
+}
+
+impl Dither {
+    /// Decides which channels get an extra tick this frame.
     ///
-    /// 1. **On Phase**: Turn LED on for `(intensity * tick_time)` microseconds
-    /// 2. **Off Phase**: Turn LED off for `((LEVELS - intensity) * tick_time)` microseconds
+    /// A channel with no fraction never gets one, so the extra tick always
+    /// fits in its slot.
     ///
     /// # Arguments
+    /// * `mode` - Dither algorithm
+    /// * `fine` - On-times from [`fine_on_times`]
     ///
-    /// * `led` - LED index (0=Red, 1=Green, 2=Blue)
-    ///
-    /// # Timing Behavior
+    /// # Returns
+    /// Per channel, 1 to lengthen this frame's on-time by a tick, or 0.
+    pub fn extra(&mut self, mode: DitherMode, fine: [u64; 3]) -> [u64; 3] {
+        let threshold = BAYER_SEQUENCE[(self.frame % DITHER_STEPS) as usize];
+        self.frame = self.frame.wrapping_add(1);
+        let mut extra = [0; 3];
+        for (led, extra) in extra.iter_mut().enumerate() {
+            let fraction = fine[led] % DITHER_STEPS;
+            *extra = match mode {
+                DitherMode::Off => 0,
+                DitherMode::Ordered => u64::from(fraction > threshold),
+                DitherMode::ErrorDiffusion => {
+                    self.error[led] += fraction;
+                    if self.error[led] >= DITHER_STEPS {
+                        self.error[led] -= DITHER_STEPS;
+                        1
+                    } else {
+                        0
+                    }
+                }
+            };
+        }
+        extra
+    }
+}
+
+/// Most PWM phases a frame can be split into (see [`PWM_PHASES`]).
+pub const PWM_PHASES_MAX: u32 = 8;
+
+/// Chooses the PWM phases for a frame rate, adding phases below a flicker
+/// threshold.
+///
+/// # Arguments
+/// * `frame_rate` - Frame rate in FPS
+/// * `phases` - Phases used at or above the threshold
+/// * `threshold_fps` - Pulse rate to keep up below it; 0 for none
+/// * `levels` - Levels per channel
+/// * `floor_us` - Shortest slice the timer can honor, in microseconds
+///
+/// # Returns
+/// `phases` at or above the threshold. Below it, enough phases for
+/// `frame_rate * phases` to reach the threshold, limited to
+/// [`PWM_PHASES_MAX`] and to what keeps every slice at the floor.
+pub fn anti_flicker_phases(
+    frame_rate: u64,
+    phases: u32,
+    threshold_fps: u64,
+    levels: u32,
+    floor_us: u64,
+) -> u32 {
+    if frame_rate >= threshold_fps {
+        return phases;
+    }
+    let needed = threshold_fps
+        .div_ceil(frame_rate.max(1))
+        .min(PWM_PHASES_MAX as u64) as u32;
+    let mut chosen = needed.max(phases);
+    while chosen > phases && min_slice_us(frame_rate, levels, chosen) < floor_us {
+        chosen -= 1;
+    }
+    chosen
+}
+
+/// One pin change of a software PWM frame.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PinChange {
+    /// Deadline in timer ticks from the frame start.
+    pub at: u64,
+    /// LED to change (0=Red, 1=Green, 2=Blue).
+    pub led: usize,
+    /// `true` to light the LED, `false` to turn it off.
+    pub high: bool,
+    /// Whether this falling edge takes its channel's dither tick; at most
+    /// one per channel, on a pulse with room for it.
+    pub dither: bool,
+}
+
+impl PinChange {
+    /// Deadline of this change with the frame's dither applied: the
+    /// channel's dither edge moves one tick later for an extra tick.
+    pub fn deadline(&self, extra: [u64; 3]) -> u64 {
+        if self.dither {
+            self.at + extra[self.led]
+        } else {
+            self.at
+        }
+    }
+}
+
+/// One step of playing a [`FrameSchedule`], from [`FrameSchedule::steps`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FrameStep {
+    /// Wait until this many timer ticks after the frame start.
+    Wait(u64),
+    /// Set a pin, due this many timer ticks after the frame start.
+    Pin {
+        /// Deadline of the change, with dither applied.
+        at: u64,
+        /// LED to change (0=Red, 1=Green, 2=Blue).
+        led: usize,
+        /// `true` to light the LED, `false` to turn it off.
+        high: bool,
+    },
+}
+
+/// Iterator over the steps of one frame; see [`FrameSchedule::steps`].
+pub struct FrameSteps<'a> {
+    schedule: &'a FrameSchedule,
+    extra: [u64; 3],
+    /// Next change to play.
+    index: usize,
+    /// Latest deadline already waited for.
+    armed: u64,
+    /// A change held back behind the wait for its deadline.
+    pending: Option<FrameStep>,
+    /// Whether the frame-end wait has been returned.
+    ended: bool,
+    /// Ticks added to the frame-end wait.
+    stretch: u64,
+}
+
+impl FrameSteps<'_> {
+    /// Moves the frame-end wait `ticks` later, lengthening the dark time
+    /// after the last change (see [`FrameCarry::stretch`]).
+    pub fn stretched(mut self, ticks: u64) -> Self {
+        self.stretch = ticks;
+        self
+    }
+}
+
+impl Iterator for FrameSteps<'_> {
+    type Item = FrameStep;
+
+    fn next(&mut self) -> Option<FrameStep> {
+        if let Some(step) = self.pending.take() {
+            return Some(step);
+        }
+        let Some(change) = self.schedule.changes().get(self.index) else {
+            if self.ended {
+                return None;
+            }
+            self.ended = true;
+            return Some(FrameStep::Wait(self.schedule.frame_ticks + self.stretch));
+        };
+        self.index += 1;
+        let at = change.deadline(self.extra);
+        let pin = FrameStep::Pin {
+            at,
+            led: change.led,
+            high: change.high,
+        };
+        if at > self.armed {
+            self.armed = at;
+            self.pending = Some(pin);
+            return Some(FrameStep::Wait(at));
+        }
+        Some(pin)
+    }
+}
+
+/// The precomputed pin changes of one software PWM frame, in deadline
+/// order.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FrameSchedule {
+    changes: [PinChange; 6 * PWM_PHASES_MAX as usize],
+    len: usize,
+    frame_ticks: u64,
+    /// On-times in 1/[`DITHER_STEPS`] ticks, for [`Dither::extra`].
+    fine: [u64; 3],
+}
+
+impl FrameSchedule {
+    /// Builds the schedule for a set of levels, composing every level
+    /// processing stage of [`fine_on_times`].
     ///
-    /// - **Intensity 0**: LED stays off for full cycle
-    /// - **Intensity 15**: LED stays on for full cycle  
-    /// - **Intensity 8**: LED on for 50% of cycle time
+    /// # Arguments
+    /// * `timing` - Timing for the current frame rate
+    /// * `levels` - RGB levels, 0 to [`LEVELS`]-1
+    /// * `brightness` - Master brightness, 0 to [`BRIGHTNESS_MAX`]
+    /// * `ceiling` - Brightness ceiling, 0 to [`BRIGHTNESS_MAX`]
+    /// * `phases` - PWM phases per frame, 1 to [`PWM_PHASES_MAX`]
+    pub fn new(
+        timing: &RgbTiming,
+        levels: [u32; 3],
+        brightness: u32,
+        ceiling: u32,
+        phases: u32,
+    ) -> Self {
+        let fine = fine_on_times(*timing, levels, brightness, ceiling);
+        let on_times = fine.map(|on_time| on_time / DITHER_STEPS);
+        Self::from_on_times(timing, on_times, fine, phases)
+    }
+    /// Builds the schedule for on-times already in timer ticks.
     ///
-    /// # Examples
+    /// The frame is cut into `phases` equal phases, each holding a
+    /// sub-slot of every channel in turn. Each channel's on-time is spread
+    /// over its sub-slots Bresenham-style, and a pulse that doesn't fit
+    /// its sub-slot carries the rest into the next, so the pulses always
+    /// add up to the on-time.
     ///
-    /// ```rust,no_run
-    /// // For intensity level 10 out of 15:
-    /// // ON time:  10 * tick_time microseconds
-    /// // OFF time: 5 * tick_time microseconds  
-    /// self.step(0).await; // Execute PWM cycle for red LED
-    /// ```
+    /// # Arguments
+    /// * `timing` - Timing for the current frame rate
+    /// * `on_times` - Per-channel on-times in timer ticks, cut to the slot
+    /// * `fine` - The same on-times in 1/[`DITHER_STEPS`] ticks, kept for
+    ///   dithering
+    /// * `phases` - PWM phases per frame, clamped to 1 to
+    ///   [`PWM_PHASES_MAX`]
+    pub fn from_on_times(
+        timing: &RgbTiming,
+        on_times: [u64; 3],
+        fine: [u64; 3],
+        phases: u32,
+    ) -> Self {
+        let mut schedule = Self {
+            changes: [PinChange {
+                at: 0,
+                led: 0,
+                high: false,
+                dither: false,
+            }; 6 * PWM_PHASES_MAX as usize],
+            len: 0,
+            frame_ticks: timing.frame_ticks,
+            fine,
+        };
+        let phases = phases.clamp(1, PWM_PHASES_MAX) as u64;
+        let mut done = [0; 3];
+        let mut dither_placed = [false; 3];
+        let mut start = 0;
+        for phase in 0..phases {
+            for led in 0..3 {
+                let slot_time = timing.slot_ticks(led);
+                let on_time = on_times[led].min(slot_time);
+                let sub_slot = slot_time * (phase + 1) / phases - slot_time * phase / phases;
+                let target = on_time * (phase + 1) / phases;
+                let sub_on = (target - done[led]).min(sub_slot);
+                done[led] += sub_on;
+                // The dither tick goes on the first pulse with room for it.
+                let dither = !dither_placed[led]
+                    && !fine[led].is_multiple_of(DITHER_STEPS)
+                    && sub_on < sub_slot;
+                dither_placed[led] |= dither;
+                if sub_on > 0 || dither {
+                    schedule.push(start, led, true, false);
+                    schedule.push(start + sub_on, led, false, dither);
+                }
+                start += sub_slot;
+            }
+        }
+        schedule
+    }
+    /// Appends a change.
+    fn push(&mut self, at: u64, led: usize, high: bool, dither: bool) {
+        self.changes[self.len] = PinChange {
+            at,
+            led,
+            high,
+            dither,
+        };
+        self.len += 1;
+    }
+    /// The pin changes in deadline order.
+    pub fn changes(&self) -> &[PinChange] {
+        &self.changes[..self.len]
+    }
+    /// Frame length in timer ticks; the frame ends at this deadline.
+    pub fn frame_ticks(&self) -> u64 {
+        self.frame_ticks
+    }
+    /// On-times in 1/[`DITHER_STEPS`] ticks.
+    pub fn fine_on_times(&self) -> [u64; 3] {
+        self.fine
+    }
+    /// On-time of one LED in timer ticks, without dither.
+    pub fn on_ticks(&self, led: usize) -> u64 {
+        let mut on_ticks = 0;
+        for change in self.changes().iter().filter(|change| change.led == led) {
+            if change.high {
+                on_ticks -= change.at as i64;
+            } else {
+                on_ticks += change.at as i64;
+            }
+        }
+        on_ticks as u64
+    }
+    /// The steps that play the frame: a wait before each distinct
+    /// deadline after the start, the pin changes, and a final wait for the
+    /// frame end.
     ///
-    /// # Performance Notes
+    /// `Rgb::run` plays these against the timer and the pins; the
+    /// [`sim`](crate::sim) module plays them on a virtual clock.
     ///
-    /// - Uses async timers for precise microsecond timing
-    /// - Skips timing delays when intensity is 0 or max for efficiency
-    /// - Each call completes one full PWM cycle for the specified LED
-    async fn step(&mut self, led: usize) {
-        let level = self.levels[led];
-        if level > 0 {
-            self.rgb[led].set_high();
-            let on_time = level as u64 * self.tick_time;
-            Timer::after_micros(on_time).await;
-            self.rgb[led].set_low();
+    /// # Arguments
+    /// * `extra` - This frame's dither ticks, from [`Dither::extra`]
+    pub fn steps(&self, extra: [u64; 3]) -> FrameSteps<'_> {
+        FrameSteps {
+            schedule: self,
+            extra,
+            index: 0,
+            armed: 0,
+            pending: None,
+            ended: false,
+            stretch: 0,
         }
-        let level = LEVELS - level;
-        if level > 0 {
-            let off_time = level as u64 * self.tick_time;
-            Timer::after_micros(off_time).await;
+    }
+    /// Timer waits needed to play the frame: one per distinct deadline
+    /// after the start, plus the frame end.
+    pub fn waits(&self) -> usize {
+        let mut waits = 0;
+        let mut armed = 0;
+        for change in self.changes() {
+            if change.at > armed {
+                waits += 1;
+                armed = change.at;
+            }
         }
+        if self.frame_ticks > armed {
+            waits += 1;
+        }
+        waits
     }
-    /// Main RGB control loop.
+}
+
+/// Inputs the scanout reads from the shared state at the start of every
+/// frame.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FrameInputs {
+    /// RGB levels, with any effects and overlays applied.
+    pub levels: [u32; 3],
+    /// Master brightness, with the ambient and lux scaling applied; 0 when
+    /// the output is off.
+    pub brightness: u32,
+    /// Requested frame rate in FPS.
+    pub frame_rate: u64,
+    /// Gamma curve to install, or `None` for a linear ramp.
+    pub gamma: Option<Gamma>,
+}
+
+/// What one [`Scanout::update`] changed.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct FrameUpdate {
+    /// The levels differ from the last frame's.
+    pub levels: bool,
+    /// The frame rate differs, so the timing was recomputed.
+    pub frame_rate: bool,
+    /// The new phase count, if the frame rate changed it.
+    pub phases: Option<u32>,
+    /// Anything the schedule depends on changed, so it was rebuilt.
+    pub rebuilt: bool,
+}
+
+/// PWM phases the scanout uses at a frame rate: [`PWM_PHASES`], raised
+/// below [`FLICKER_THRESHOLD_FPS`] (see [`anti_flicker_phases`]).
+fn scanout_phases(frame_rate: u64) -> u32 {
+    anti_flicker_phases(
+        frame_rate,
+        PWM_PHASES,
+        FLICKER_THRESHOLD_FPS,
+        LEVELS,
+        TIMER_FLOOR_US,
+    )
+}
+
+/// Per-frame state of the scanout: the inputs a frame was built from,
+/// its timing and phases, the schedule, and the dither carried between
+/// frames.
+///
+/// This is the part of the frame loop that doesn't touch the hardware,
+/// so `Rgb::run` and the software-PWM simulation's `FrameLoop` both
+/// step it: each frame, [`Scanout::update`] with the inputs just read,
+/// then play [`Scanout::frame_steps`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Scanout {
+    /// Levels the schedule was built from (0 to [`LEVELS`]-1).
+    levels: [u32; 3],
+    /// Master brightness the schedule was built from (0 to
+    /// [`BRIGHTNESS_MAX`]).
+    brightness: u32,
+    /// Administrative output cap (0 to [`BRIGHTNESS_MAX`]), applied last.
+    ceiling: u32,
+    /// Gamma curve of the installed [`GammaTable`].
+    gamma: Option<Gamma>,
+    /// PWM timing for the current frame rate, also used for change
+    /// detection.
+    timing: RgbTiming,
+    /// PWM phases for the current frame rate (see [`anti_flicker_phases`]).
+    phases: u32,
+    /// Pin changes of every frame until the inputs change.
+    schedule: FrameSchedule,
+    /// Temporal dither of the sub-tick on-time fractions.
+    dither: Dither,
+    /// Frame length remainder carried between frames.
+    carry: FrameCarry,
+}
+
+impl Scanout {
+    /// Starts dark, at full brightness, with the build-time gamma curve.
     ///
-    /// Continuously updates RGB levels and frame rate from shared state,
-    /// then executes PWM cycles for all three LEDs.
+    /// # Arguments
+    /// * `frame_rate` - Initial frame rate in FPS
+    /// * `ceiling` - Brightness ceiling, 0 to [`BRIGHTNESS_MAX`]
+    pub fn new(frame_rate: u64, ceiling: u32) -> Self {
+        let timing = RgbTiming::for_frame_rate(frame_rate);
+        let phases = scanout_phases(frame_rate);
+        Self {
+            levels: [0; 3],
+            brightness: BRIGHTNESS_MAX,
+            ceiling: ceiling.min(BRIGHTNESS_MAX),
+            gamma: GAMMA,
+            timing,
+            phases,
+            schedule: FrameSchedule::from_on_times(&timing, [0; 3], [0; 3], phases),
+            dither: Dither::default(),
+            carry: FrameCarry::default(),
+        }
+    }
+    /// Takes a frame's inputs, rebuilding whatever they change.
     ///
-    /// # Operation
-    /// 1. Read current RGB levels from shared state
-    /// 2. Check for frame rate changes and update timing if needed
-    /// 3. Execute PWM cycle for each LED in sequence
-    /// 4. Repeat
+    /// Clamps the levels and brightness, installs a new gamma curve (see
+    /// [`install_gamma_table`]), recomputes the timing for a new frame
+    /// rate (through [`scanout_frame_rate`]) along with its phases, and
+    /// rebuilds the [`FrameSchedule`] if any of them changed.
     ///
-    /// This function never returns under normal operation.
+    /// # Arguments
+    /// * `inputs` - Inputs read at the start of the frame
     ///
-    /// # Never Returns
+    /// # Returns
+    /// What changed, for the caller to count and log.
+    pub fn update(&mut self, inputs: FrameInputs) -> FrameUpdate {
+        let levels = inputs.levels.map(|level| level.min(LEVELS - 1));
+        let brightness = inputs.brightness.min(BRIGHTNESS_MAX);
+        let mut update = FrameUpdate {
+            levels: levels != self.levels,
+            ..FrameUpdate::default()
+        };
+        let mut changed = update.levels || brightness != self.brightness;
+        self.levels = levels;
+        self.brightness = brightness;
+
+        if inputs.gamma != self.gamma {
+            install_gamma_table(GammaTable::new(inputs.gamma));
+            self.gamma = inputs.gamma;
+            changed = true;
+        }
+
+        let frame_rate = scanout_frame_rate(inputs.frame_rate);
+        if frame_rate != self.timing.frame_rate {
+            self.timing = RgbTiming::for_frame_rate(frame_rate);
+            self.carry = FrameCarry::default();
+            let phases = scanout_phases(frame_rate);
+            if phases != self.phases {
+                self.phases = phases;
+                update.phases = Some(phases);
+            }
+            update.frame_rate = true;
+            changed = true;
+        }
+
+        if changed {
+            self.schedule = FrameSchedule::new(
+                &self.timing,
+                self.levels,
+                self.brightness,
+                self.ceiling,
+                self.phases,
+            );
+        }
+        update.rebuilt = changed;
+        update
+    }
+    /// The steps that play this frame: the schedule with this frame's
+    /// dither ticks (see [`Dither::extra`]), ending a tick late whenever
+    /// the carried frame remainder says so (see [`FrameCarry`]).
     ///
-    /// This function runs indefinitely under normal operation. It will only
-    /// exit if the hardware fails or the system panics.
-    pub async fn run(mut self) -> ! {
-        loop {
-            self.levels = get_rgb_levels().await;
+    /// # Arguments
+    /// * `mode` - Dither algorithm
+    pub fn frame_steps(&mut self, mode: DitherMode) -> FrameSteps<'_> {
+        let extra = self.dither.extra(mode, self.schedule.fine_on_times());
+        let stretch = self.carry.stretch(&self.timing);
+        self.schedule.steps(extra).stretched(stretch)
+    }
+    /// Levels the schedule was built from.
+    pub fn levels(&self) -> [u32; 3] {
+        self.levels
+    }
+    /// Master brightness the schedule was built from.
+    pub fn brightness(&self) -> u32 {
+        self.brightness
+    }
+    /// Brightness ceiling.
+    pub fn ceiling(&self) -> u32 {
+        self.ceiling
+    }
+    /// Timing for the current frame rate.
+    pub fn timing(&self) -> RgbTiming {
+        self.timing
+    }
+    /// PWM phases for the current frame rate.
+    pub fn phases(&self) -> u32 {
+        self.phases
+    }
+    /// Pin changes of the current frame.
+    pub fn schedule(&self) -> &FrameSchedule {
+        &self.schedule
+    }
+}
+
+/// Rising edges the software PWM has driven on each pin [red, green,
+/// blue] since boot, wrapping; the self-test counts them.
+pub static PWM_RISES: [AtomicU32; 3] = [AtomicU32::new(0), AtomicU32::new(0), AtomicU32::new(0)];
 
-            let new_frame_rate = get_frame_rate().await;
-            if new_frame_rate != self.current_frame_rate {
-                self.current_frame_rate = new_frame_rate;
-                self.tick_time = Self::frame_tick_time(new_frame_rate);
-                rprintln!("RGB: Frame rate updated to {} fps", new_frame_rate);
+/// Timing published by the scanout whenever it (re)computes it.
+pub static RGB_TIMING: Mutex<CriticalSectionRawMutex, RgbTiming> = Mutex::new(RgbTiming {
+    frame_rate: 0,
+    frame_ticks: 0,
+});
+/// Pad reads taken by a pin test, in which the scanout toggles one LED pin
+/// and reads the pad back.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct PinReadback {
+    /// Reads taken, one per half-period.
+    pub reads: u32,
+    /// Reads matching the level the pin was driven to.
+    pub matched: u32,
+}
+
+/// Caps channel on-times at the brightness ceiling.
+///
+/// If the brightest channel would be on for more than `ceiling` percent
+/// of its slot, all channels are scaled down by the same factor, so the
+/// brightest lands exactly on the ceiling and the color ratio is kept.
+///
+/// # Arguments
+/// * `on_times` - Per-channel on-times in timer ticks (or any unit)
+/// * `slot_time` - Length of one channel's slot in the same unit
+/// * `ceiling` - Maximum duty as a percentage of [`BRIGHTNESS_MAX`]
+///
+/// # Returns
+/// The capped on-times; unchanged if already under the ceiling.
+pub fn apply_ceiling(on_times: [u64; 3], slot_time: u64, ceiling: u32) -> [u64; 3] {
+    let cap = slot_time.saturating_mul(ceiling.min(BRIGHTNESS_MAX) as u64) / BRIGHTNESS_MAX as u64;
+    let peak = on_times.iter().copied().max().unwrap_or(0);
+    if peak <= cap {
+        return on_times;
+    }
+    on_times.map(|on_time| on_time.saturating_mul(cap) / peak)
+}
+/// Throttles channels that would be full on together.
+///
+/// A channel counts as full on when its on-time reaches `full_time`. If
+/// more than `limit` channels are full on, each of them is scaled to
+/// `limit / count` of `full_time`, so together they draw no more than
+/// `limit` full channels. Channels below `full_time` are left alone:
+/// this caps instantaneous concurrency, not the overall duty.
+///
+/// # Arguments
+/// * `on_times` - Per-channel on-times in timer ticks (or any unit)
+/// * `full_time` - On-time at which a channel counts as full on
+/// * `limit` - Maximum number of channels allowed full on together
+///
+/// # Returns
+/// The limited on-times; unchanged if at most `limit` are full on.
+pub fn apply_full_on_limit(on_times: [u64; 3], full_time: u64, limit: usize) -> [u64; 3] {
+    let full = on_times
+        .iter()
+        .filter(|&&on_time| on_time >= full_time)
+        .count();
+    if full <= limit {
+        return on_times;
+    }
+    let throttled = full_time.saturating_mul(limit as u64) / full as u64;
+    on_times.map(|on_time| {
+        if on_time >= full_time {
+            throttled
+        } else {
+            on_time
+        }
+    })
+}
+/// Computes the on-time of every channel for the next frame.
+///
+/// Maps each level through the active gamma curve if one is set (see
+/// [`active_gamma_table`]), scales it
+/// by the master brightness, applies the brightness ceiling, and finally
+/// the [`FULL_ON_LIMIT`] concurrency cap, where full on is the top level
+/// at full brightness.
+///
+/// Slots may differ by one tick, so the ceiling and the full-on
+/// threshold are taken from the shortest slot. Levels above
+/// [`LEVELS`]-1 and brightness or ceiling above [`BRIGHTNESS_MAX`] are
+/// clamped, so no on-time can exceed its slot.
+///
+/// # Arguments
+/// * `timing` - Timing for the current frame rate
+/// * `levels` - RGB levels, normally 0 to [`LEVELS`]-1
+/// * `brightness` - Master brightness, normally 0 to [`BRIGHTNESS_MAX`]
+/// * `ceiling` - Brightness ceiling, normally 0 to [`BRIGHTNESS_MAX`]
+///
+/// # Returns
+/// On-times in whole timer ticks, rounded down; see
+/// [`fine_on_times`] for the fraction.
+pub fn frame_on_times(
+    timing: RgbTiming,
+    levels: [u32; 3],
+    brightness: u32,
+    ceiling: u32,
+) -> [u64; 3] {
+    fine_on_times(timing, levels, brightness, ceiling).map(|on_time| on_time / DITHER_STEPS)
+}
+/// Computes the on-times like [`frame_on_times`], in units of
+/// 1/[`DITHER_STEPS`] of a timer tick, keeping the fraction of a tick
+/// that the timer can't wait for so [`Dither`] can spread it over frames.
+pub fn fine_on_times(
+    timing: RgbTiming,
+    levels: [u32; 3],
+    brightness: u32,
+    ceiling: u32,
+) -> [u64; 3] {
+    fine_on_times_with(&active_gamma_table(), timing, levels, brightness, ceiling)
+}
+/// Computes the on-times like [`fine_on_times`], with `gamma` in place of
+/// the installed table.
+pub fn fine_on_times_with(
+    gamma: &GammaTable,
+    timing: RgbTiming,
+    levels: [u32; 3],
+    brightness: u32,
+    ceiling: u32,
+) -> [u64; 3] {
+    let brightness = brightness.min(BRIGHTNESS_MAX) as u64;
+    let mut on_times = [0; 3];
+    for (led, on_time) in on_times.iter_mut().enumerate() {
+        let level_ticks = gamma.level_ticks(&timing, led, levels[led]);
+        *on_time = level_ticks
+            .saturating_mul(DITHER_STEPS)
+            .saturating_mul(brightness)
+            / BRIGHTNESS_MAX as u64;
+    }
+    let min_slot = timing.min_slot_ticks().saturating_mul(DITHER_STEPS);
+    let on_times = apply_ceiling(on_times, min_slot, ceiling);
+    let full_time = min_slot.saturating_mul((LEVELS - 1) as u64) / LEVELS as u64;
+    let on_times = apply_full_on_limit(on_times, full_time, FULL_ON_LIMIT);
+    debug_assert!((0..3).all(|led| on_times[led] / DITHER_STEPS <= timing.slot_ticks(led)));
+    on_times
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pwm_compare_is_the_off_count() {
+        // Level 15 of 16 at full brightness: on for 15/16 of the period.
+        assert_eq!(pwm_compare(15 * 100, 16 * 100, 1600), 100);
+    }
+
+    #[test]
+    fn channel_enables_change_one_channel() {
+        let all = [true; 3];
+        assert_eq!(with_channel_enabled(all, 0, false), [false, true, true]);
+        assert_eq!(with_channel_enabled(all, 1, false), [true, false, true]);
+        assert_eq!(with_channel_enabled(all, 2, false), [true, true, false]);
+        // Enabling one channel back doesn't touch the other disabled one.
+        let two_off = [false, true, false];
+        assert_eq!(with_channel_enabled(two_off, 2, true), [false, true, true]);
+        // Setting a flag to its current value changes nothing.
+        assert_eq!(with_channel_enabled(two_off, 1, true), two_off);
+        assert_eq!(with_channel_enabled(two_off, 3, true), two_off);
+    }
+
+    #[test]
+    fn frame_rate_fit_is_exact_across_the_configuration_space() {
+        // The shipped configuration is feasible at every rate.
+        for frame_rate in FRAME_RATE_MIN..=FRAME_RATE_MAX {
+            let fit = fit_frame_rate(frame_rate, LEVELS, PWM_PHASES, TIMER_FLOOR_US);
+            assert_eq!(fit, FrameRateFit::Feasible);
+        }
+        // 8 phases of 16 levels at 160 fps is a 16 us slice: clamped to 130 fps.
+        assert_eq!(fit_frame_rate(160, 16, 8, 20), FrameRateFit::Clamped(130));
+        assert_eq!(min_slice_us(130, 16, 8), 20);
+        // 256 levels with 8 phases can't reach 20 us even at 10 fps.
+        assert_eq!(fit_frame_rate(10, 256, 8, 20), FrameRateFit::Degraded);
+
+        // Across the configuration space, every decision is right.
+        for levels in [2, 4, 8, 16, 32, 64, 128, 256] {
+            for phases in 1..=PWM_PHASES_MAX {
+                for floor_us in [1, 5, 10, 20, 50, 100] {
+                    for frame_rate in FRAME_RATE_MIN..=FRAME_RATE_MAX {
+                        let ok = |rate| min_slice_us(rate, levels, phases) >= floor_us;
+                        match fit_frame_rate(frame_rate, levels, phases, floor_us) {
+                            FrameRateFit::Feasible => assert!(ok(frame_rate)),
+                            // The fastest feasible rate below the one asked for.
+                            FrameRateFit::Clamped(rate) => {
+                                assert!(!ok(frame_rate));
+                                assert!((FRAME_RATE_MIN..frame_rate).contains(&rate));
+                                assert!(ok(rate) && !ok(rate + 1));
+                            }
+                            FrameRateFit::Degraded => assert!(!ok(FRAME_RATE_MIN)),
+                        }
+                    }
+                }
             }
-            for led in 0..3 {
-                self.step(led).await;
+        }
+    }
+
+    #[test]
+    fn slots_add_up_to_the_frame() {
+        // Every frame's slots add up to the frame period, at every rate.
+        for frame_rate in FRAME_RATE_MIN..=FRAME_RATE_MAX {
+            let timing = RgbTiming::for_frame_rate(frame_rate);
+            let total: u64 = (0..3).map(|led| timing.slot_ticks(led)).sum();
+            assert_eq!(total, timing.frame_ticks);
+            assert_eq!(timing.frame_ticks, TICK_HZ / frame_rate);
+        }
+    }
+
+    #[test]
+    fn frame_carry_keeps_every_rate_exact() {
+        // Frame n ends at TICK_HZ * (n + 1) / frame_rate, so a second of
+        // frames is exactly a second, at every rate.
+        for frame_rate in FRAME_RATE_MIN..=FRAME_RATE_MAX {
+            let timing = RgbTiming::for_frame_rate(frame_rate);
+            assert_eq!(timing.frame_remainder(), TICK_HZ % frame_rate);
+            let mut carry = FrameCarry::default();
+            let mut elapsed = 0;
+            for frame in 1..=frame_rate {
+                elapsed += timing.frame_ticks + carry.stretch(&timing);
+                assert_eq!(elapsed, TICK_HZ * frame / frame_rate);
+            }
+            assert_eq!(elapsed, TICK_HZ);
+        }
+    }
+
+    #[test]
+    fn stretched_steps_end_the_frame_later() {
+        let timing = RgbTiming::for_frame_rate(60);
+        let schedule = FrameSchedule::new(&timing, [8, 0, 0], BRIGHTNESS_MAX, BRIGHTNESS_MAX, 1);
+        let end = |steps: FrameSteps<'_>| steps.last();
+        assert_eq!(
+            end(schedule.steps([0; 3])),
+            Some(FrameStep::Wait(timing.frame_ticks))
+        );
+        let stretched = schedule.steps([0; 3]).stretched(1);
+        assert_eq!(
+            end(stretched),
+            Some(FrameStep::Wait(timing.frame_ticks + 1))
+        );
+    }
+
+    #[test]
+    fn on_time_error_mean_and_worst() {
+        let mut error = OnTimeError::default();
+        error.push(320, 312);
+        error.push(320, 305);
+        // The mean error truncates toward zero.
+        assert_eq!(error.mean_error_us(), -11);
+        assert_eq!(error.worst_us, -15);
+    }
+
+    #[test]
+    fn dither_averages_to_the_fine_on_time() {
+        // 10 and 5/16, 3 and 11/16, and 7 whole ticks.
+        let fine = [10 * 16 + 5, 3 * 16 + 11, 7 * 16];
+        for mode in [DitherMode::Ordered, DitherMode::ErrorDiffusion] {
+            let mut dither = Dither::default();
+            let mut total = [0; 3];
+            for _ in 0..16 * 10 {
+                let extra = dither.extra(mode, fine);
+                for led in 0..3 {
+                    // Each frame is the whole ticks, or one more.
+                    assert!(extra[led] <= 1);
+                    total[led] += fine[led] / 16 + extra[led];
+                }
+            }
+            // Over whole cycles the average is exactly the fine on-time.
+            assert_eq!(total.map(|ticks| ticks * 16 / 160), fine);
+        }
+        // Without dithering the fraction is lost.
+        let mut dither = Dither::default();
+        assert_eq!(dither.extra(DitherMode::Off, fine), [0; 3]);
+    }
+
+    #[test]
+    fn anti_flicker_phases_reach_the_threshold_within_the_floor() {
+        // At the minimum rate, five phases pulse at 50 Hz.
+        assert_eq!(
+            anti_flicker_phases(FRAME_RATE_MIN, 1, 50, LEVELS, TIMER_FLOOR_US),
+            5
+        );
+        assert_eq!(anti_flicker_phases(40, 1, 50, LEVELS, TIMER_FLOOR_US), 2);
+        assert_eq!(anti_flicker_phases(50, 1, 50, LEVELS, TIMER_FLOOR_US), 1);
+        assert_eq!(
+            anti_flicker_phases(FRAME_RATE_MIN, 1, 0, LEVELS, TIMER_FLOOR_US),
+            1
+        );
+        // The timer floor wins over the threshold.
+        assert_eq!(anti_flicker_phases(10, 1, 50, 256, 50), 2);
+
+        for threshold_fps in [0, 20, 50, 100, 200] {
+            for base in 1..=PWM_PHASES_MAX {
+                for frame_rate in FRAME_RATE_MIN..=FRAME_RATE_MAX {
+                    let phases = anti_flicker_phases(
+                        frame_rate,
+                        base,
+                        threshold_fps,
+                        LEVELS,
+                        TIMER_FLOOR_US,
+                    );
+                    if frame_rate >= threshold_fps {
+                        // Only engages below the threshold.
+                        assert_eq!(phases, base);
+                    } else {
+                        assert!((base..=PWM_PHASES_MAX).contains(&phases));
+                        if phases > base {
+                            // Added phases stay feasible...
+                            assert!(min_slice_us(frame_rate, LEVELS, phases) >= TIMER_FLOOR_US);
+                            // ...and reach the threshold unless capped, and no further.
+                            if phases < PWM_PHASES_MAX {
+                                assert!(frame_rate * phases as u64 >= threshold_fps);
+                            }
+                            assert!(frame_rate * (phases as u64 - 1) < threshold_fps);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn frame_schedule_changes_are_sorted_and_exclusive() {
+        let timing = RgbTiming::for_frame_rate(60);
+        let full = BRIGHTNESS_MAX;
+        // Changes are sorted, and at most one LED is lit at any time.
+        for levels in [[0; 3], [LEVELS - 1; 3], [8, 0, 15], [0, 1, 0]] {
+            for phases in 1..=PWM_PHASES_MAX {
+                let schedule = FrameSchedule::new(&timing, levels, full, full, phases);
+                let changes = schedule.changes();
+                assert!(changes.windows(2).all(|pair| pair[0].at <= pair[1].at));
+                let mut lit = 0;
+                for change in changes {
+                    lit = if change.high { lit + 1 } else { lit - 1 };
+                    assert!(lit <= 1);
+                }
+                assert_eq!(lit, 0);
+                assert_eq!(schedule.frame_ticks(), timing.frame_ticks);
+                // The stages' result, rounded down to whole ticks.
+                let on_times = frame_on_times(timing, levels, full, full);
+                for (led, &on_time) in on_times.iter().enumerate() {
+                    assert_eq!(schedule.on_ticks(led), on_time);
+                }
+            }
+        }
+        // All dark: no changes, and one wait for the frame end.
+        let dark = FrameSchedule::new(&timing, [0; 3], full, full, 1);
+        assert!(dark.changes().is_empty());
+        assert_eq!(dark.waits(), 1);
+        // Solo red: light at once, one wait to turn it off, one for the end.
+        let solo = FrameSchedule::new(&timing, [8, 0, 0], full, full, 1);
+        assert!(solo.changes()[0].high && solo.changes()[0].at == 0);
+        assert_eq!(solo.waits(), 2);
+        // All lit: six waits, as with one on and one off phase per channel.
+        let all = FrameSchedule::new(&timing, [8; 3], full, full, 1);
+        assert_eq!(all.waits(), 6);
+
+        // A channel on for its whole slot ties with the next one's start: one
+        // wait, and red goes low before green goes high.
+        let slot = timing.slot_ticks(0);
+        let tied = FrameSchedule::from_on_times(&timing, [slot, slot, 0], [0; 3], 1);
+        assert!(!tied.changes()[1].high && tied.changes()[1].at == slot);
+        assert!(tied.changes()[2].high && tied.changes()[2].at == slot);
+        assert_eq!(tied.waits(), 3);
+        // On-times past the slot are cut to it.
+        let long = FrameSchedule::from_on_times(&timing, [u64::MAX, 0, 0], [0; 3], 1);
+        assert_eq!(long.on_ticks(0), slot);
+
+        // With phases, each channel's on-time is split into that many pulses,
+        // which add up to the single pulse, whatever the on-time.
+        for phases in 1..=PWM_PHASES_MAX {
+            for on_time in 0..=slot {
+                let on_times = [on_time, slot - on_time, on_time / 2];
+                let split = FrameSchedule::from_on_times(&timing, on_times, [0; 3], phases);
+                let single = FrameSchedule::from_on_times(&timing, on_times, [0; 3], 1);
+                for (led, &on_time) in on_times.iter().enumerate() {
+                    assert_eq!(split.on_ticks(led), on_time);
+                    assert_eq!(split.on_ticks(led), single.on_ticks(led));
+                    let pulses = split.changes().iter().filter(|c| c.led == led && c.high);
+                    assert!(pulses.count() <= phases as usize);
+                }
+            }
+        }
+        // Four phases of a half-lit red: four pulses, a quarter frame apart.
+        let quarters = FrameSchedule::from_on_times(&timing, [slot / 2, 0, 0], [0; 3], 4);
+        let starts = quarters.changes().iter().filter(|c| c.high).map(|c| c.at);
+        let mut pulses = 0;
+        for (phase, at) in starts.enumerate() {
+            assert!(at.abs_diff(timing.frame_ticks * phase as u64 / 4) <= 3);
+            pulses += 1;
+        }
+        assert_eq!(pulses, 4);
+        // A fraction of a tick gets a dither edge even on a dark whole tick.
+        let faint = FrameSchedule::from_on_times(&timing, [0; 3], [3, 0, 0], 4);
+        assert_eq!(faint.changes().iter().filter(|c| c.dither).count(), 1);
+        assert_eq!(faint.on_ticks(0), 0);
+    }
+
+    #[test]
+    fn frame_steps_wait_before_each_deadline() {
+        let timing = RgbTiming::for_frame_rate(100);
+        let full = BRIGHTNESS_MAX;
+        let schedule = FrameSchedule::new(&timing, [8, 0, 3], full, full, 1);
+        let mut steps = schedule.steps([0; 3]);
+        // Red lights at once, without a wait.
+        assert!(matches!(
+            steps.next(),
+            Some(FrameStep::Pin {
+                at: 0,
+                led: 0,
+                high: true
+            })
+        ));
+        assert!(matches!(steps.next(), Some(FrameStep::Wait(_))));
+        assert!(matches!(
+            steps.next(),
+            Some(FrameStep::Pin {
+                led: 0,
+                high: false,
+                ..
+            })
+        ));
+        // Every frame ends on its frame-end wait.
+        assert_eq!(steps.last(), Some(FrameStep::Wait(timing.frame_ticks)));
+    }
+
+    #[test]
+    fn full_on_limit_throttles_concurrent_channels() {
+        // All three full on with a limit of 2: each throttled to 2/3.
+        assert_eq!(apply_full_on_limit([300; 3], 300, 2), [200; 3]);
+        // Only two full on: nothing to do.
+        assert_eq!(
+            apply_full_on_limit([300, 300, 100], 300, 2),
+            [300, 300, 100]
+        );
+    }
+
+    #[test]
+    fn wild_inputs_clamp_to_the_largest_valid_on_times() {
+        // Stale or corrupt values come out as the largest valid ones.
+        for frame_rate in [0, FRAME_RATE_MIN, FRAME_RATE_MAX, u64::MAX] {
+            let timing = RgbTiming::for_frame_rate(frame_rate);
+            let wild = frame_on_times(timing, [LEVELS, u32::MAX, 0], u32::MAX, u32::MAX);
+            let top = [LEVELS - 1, LEVELS - 1, 0];
+            let top = frame_on_times(timing, top, BRIGHTNESS_MAX, BRIGHTNESS_MAX);
+            assert_eq!(wild, top);
+            for (led, &on_time) in wild.iter().enumerate() {
+                assert!(on_time <= timing.slot_ticks(led));
             }
         }
     }
@@ -0,0 +1,131 @@
+//! # Brightness Schedule Module
+//!
+//! This module dims the LEDs during "night" and brightens them during "day"
+//! by periodically rewriting [`MASTER_BRIGHTNESS`], simulating circadian
+//! lighting.
+//!
+//! ## Clock
+//!
+//! There is no real-time clock, so the schedule runs on uptime: one "day"
+//! is a configurable cycle length (two minutes by default, for demos), and
+//! uptime 0 is midnight. Set the cycle to 86 400 seconds for a real day
+//! that starts at boot.
+//!
+//! ## Curves
+//!
+//! With `phase` the fraction of the cycle elapsed (0 = midnight, 0.5 = noon):
+//!
+//! ```text
+//! Cosine: night + (day - night) * (1 - cos(2π · phase)) / 2
+//! Step:   day from 06:00 to 18:00 (phase 0.25..0.75), night otherwise
+//! ```
+//!
+//! ## Coexistence with Manual Brightness
+//!
+//! Enabling the schedule saves the master brightness and disabling it
+//! restores it. While enabled the schedule owns the master brightness.
+use crate::*;
+
+/// Default cycle length ("one day"), in seconds.
+pub const SCHEDULE_PERIOD_DEFAULT_S: u32 = 120;
+/// Master brightness at midnight, as a percentage of [`BRIGHTNESS_MAX`].
+pub const SCHEDULE_NIGHT: u32 = 10;
+/// Master brightness at noon, as a percentage of [`BRIGHTNESS_MAX`].
+pub const SCHEDULE_DAY: u32 = BRIGHTNESS_MAX;
+
+/// Shape of the brightness curve over one cycle.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ScheduleCurve {
+    /// Smooth raised-cosine transition between night and day.
+    Cosine,
+    /// Hard switch at 06:00 and 18:00.
+    Step,
+}
+
+/// Shared schedule state.
+#[derive(Debug, Clone, Copy)]
+pub struct Schedule {
+    /// Whether the schedule currently owns the master brightness.
+    pub enabled: bool,
+    /// Cycle length in seconds.
+    pub period_s: u32,
+    /// Curve shape.
+    pub curve: ScheduleCurve,
+    /// Master brightness saved on entry, restored on exit.
+    saved: u32,
+}
+
+/// Global schedule state, changed by the console.
+pub static SCHEDULE: Mutex<CriticalSectionRawMutex, Schedule> = Mutex::new(Schedule {
+    enabled: false,
+    period_s: SCHEDULE_PERIOD_DEFAULT_S,
+    curve: ScheduleCurve::Cosine,
+    saved: BRIGHTNESS_MAX,
+});
+
+/// Computes the scheduled master brightness at a point in time.
+///
+/// # Arguments
+/// * `elapsed_ms` - Uptime in milliseconds (0 is midnight)
+/// * `period_ms` - Cycle length in milliseconds; must be nonzero
+/// * `curve` - Curve shape
+/// * `night` - Brightness at midnight
+/// * `day` - Brightness at noon
+///
+/// # Returns
+/// A brightness between `night` and `day` inclusive.
+pub fn brightness_at(
+    elapsed_ms: u64,
+    period_ms: u64,
+    curve: ScheduleCurve,
+    night: u32,
+    day: u32,
+) -> u32 {
+    let phase = (elapsed_ms % period_ms) as f32 / period_ms as f32;
+    let daylight = match curve {
+        ScheduleCurve::Cosine => {
+            (1.0 - num_traits::Float::cos(2.0 * core::f32::consts::PI * phase)) / 2.0
+        }
+        ScheduleCurve::Step if (0.25..0.75).contains(&phase) => 1.0,
+        ScheduleCurve::Step => 0.0,
+    };
+    let span = day as f32 - night as f32;
+    num_traits::Float::round(night as f32 + span * daylight) as u32
+}
+
+/// Enables or disables the schedule.
+///
+/// Enabling saves the current master brightness; disabling restores it.
+pub async fn set_schedule_enabled(enabled: bool) {
+    let mut schedule = SCHEDULE.lock().await;
+    if enabled == schedule.enabled {
+        return;
+    }
+    schedule.enabled = enabled;
+    if enabled {
+        schedule.saved = get_master_brightness().await;
+        rprintln!(
+            "Schedule: on ({:?}, {} s cycle)",
+            schedule.curve,
+            schedule.period_s
+        );
+    } else {
+        let saved = schedule.saved;
+        set_master_brightness(|b| *b = saved).await;
+        rprintln!("Schedule: off");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn schedule_peaks_at_noon() {
+        // Noon of a 1000 ms cycle.
+        assert_eq!(
+            brightness_at(500, 1000, ScheduleCurve::Cosine, 10, 100),
+            100
+        );
+    }
+}
@@ -0,0 +1,559 @@
+//! # Self-Test Module
+//!
+//! This module runs the console's `SELFTEST` suite: a hardware-in-the-loop
+//! check of the whole board for bench regression testing, printed as a
+//! uniform pass/fail report.
+//!
+//! ## Checks
+//!
+//! Each [`SelfCheck`] is an independent function returning a
+//! [`CheckResult`], usually with the [`Measurement`] it was judged on:
+//!
+//! - **knob-cal**: The ADC calibration finishes within
+//!   [`KNOB_RECAL`]'s timeout
+//! - **knob-noise**: The raw knob reading's standard deviation over
+//!   [`SELFTEST_KNOB_SAMPLES`] readings is at most [`SELFTEST_KNOB_SD_MAX`]
+//!   (hold the knob still)
+//! - **button-a**, **button-b**: Each button reads released (hands off)
+//! - **led-red**, **led-green**, **led-blue**: With only that channel at
+//!   full level, the frame loop produces at least half the expected rising
+//!   edges on its pin over [`SELFTEST_DRIVE`] (skipped with `hwpwm`, whose
+//!   edges the CPU never sees, and while the output is blanked)
+//! - **pin-red**, **pin-green**, **pin-blue**: Each LED pin is driven high
+//!   and low [`SELFTEST_PIN_TOGGLES`] times, a visible blink, and the pad
+//!   is read back at the end of every half-period: all reads must match
+//!   the driven level, so a pin shorted to a rail or a neighbour fails.
+//!   The test drives the pin itself, so it runs even while the output is
+//!   blanked. With `hwpwm` the pins belong to the PWM peripheral and can't
+//!   be read back, so the channel is blinked through its level instead and
+//!   reported as `OBSERVE`: watch that it blinked
+//! - **frame-rate**: A new frame rate is in use within one frame of the
+//!   old rate, plus [`SELFTEST_FRAME_SLACK`]
+//! - **state**: Levels and a frame rate written through the console's
+//!   setters read back the same
+//!
+//! The levels and frame rate are restored after the checks that change
+//! them. A line announces each pin check before it blinks, so someone
+//! watching can tell which channel should be blinking.
+//!
+//! ## Report
+//!
+//! [`run_self_test`] runs every check in [`SelfCheck::ALL`] order through
+//! a [`SelfChecks`] implementation (the [`Ui`], which owns the knob and
+//! buttons) and collects the results in a [`SelfTestReport`]; a failing
+//! check never stops the ones after it. [`run_self_checks`] runs any
+//! subset, such as [`SelfCheck::PINS`], which the UI runs at boot when
+//! [`SELFTEST_PINS_AT_BOOT`] is set. The orchestration and report don't
+//! touch hardware, so they can be exercised with stubbed checks.
+use crate::*;
+use core::future::Future;
+use core::sync::atomic::Ordering;
+
+/// Knob readings sampled by the knob-noise check.
+pub const SELFTEST_KNOB_SAMPLES: u32 = 64;
+/// Largest knob-noise standard deviation that passes, in raw counts.
+pub const SELFTEST_KNOB_SD_MAX: u64 = 16;
+/// How long each LED channel is driven while its edges are counted.
+pub const SELFTEST_DRIVE: Duration = Duration::from_millis(200);
+/// Scheduling allowance on top of one frame for the frame-rate check.
+pub const SELFTEST_FRAME_SLACK: Duration = Duration::from_millis(2);
+/// High/low cycles each LED pin is driven through by its pin check.
+pub const SELFTEST_PIN_TOGGLES: u32 = 4;
+/// How long each level of a pin check is held before it's read back.
+pub const SELFTEST_PIN_HALF_PERIOD: Duration = Duration::from_millis(150);
+/// Whether the UI runs the pin checks at boot, before it starts.
+pub const SELFTEST_PINS_AT_BOOT: bool = false;
+/// Poll interval while waiting for the RGB loop to pick up a change.
+const SELFTEST_POLL: Duration = Duration::from_millis(1);
+
+/// One check of the self-test suite.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SelfCheck {
+    /// ADC calibration completes in time.
+    KnobCalibration,
+    /// Raw knob reading is quiet.
+    KnobNoise,
+    /// Button A reads released.
+    ButtonA,
+    /// Button B reads released.
+    ButtonB,
+    /// Red channel produces edges.
+    Red,
+    /// Green channel produces edges.
+    Green,
+    /// Blue channel produces edges.
+    Blue,
+    /// Red pin toggles.
+    RedPin,
+    /// Green pin toggles.
+    GreenPin,
+    /// Blue pin toggles.
+    BluePin,
+    /// Frame rate changes take effect within a frame.
+    FrameRate,
+    /// Shared state reads back what was written.
+    StateRoundTrip,
+}
+
+impl SelfCheck {
+    /// Every check, in the order the suite runs them.
+    pub const ALL: [SelfCheck; 12] = [
+        SelfCheck::KnobCalibration,
+        SelfCheck::KnobNoise,
+        SelfCheck::ButtonA,
+        SelfCheck::ButtonB,
+        SelfCheck::Red,
+        SelfCheck::Green,
+        SelfCheck::Blue,
+        SelfCheck::RedPin,
+        SelfCheck::GreenPin,
+        SelfCheck::BluePin,
+        SelfCheck::FrameRate,
+        SelfCheck::StateRoundTrip,
+    ];
+    /// The LED pin checks, red to blue.
+    pub const PINS: [SelfCheck; 3] = [SelfCheck::RedPin, SelfCheck::GreenPin, SelfCheck::BluePin];
+
+    /// Short name used in the report.
+    pub fn name(self) -> &'static str {
+        match self {
+            SelfCheck::KnobCalibration => "knob-cal",
+            SelfCheck::KnobNoise => "knob-noise",
+            SelfCheck::ButtonA => "button-a",
+            SelfCheck::ButtonB => "button-b",
+            SelfCheck::Red => "led-red",
+            SelfCheck::Green => "led-green",
+            SelfCheck::Blue => "led-blue",
+            SelfCheck::RedPin => "pin-red",
+            SelfCheck::GreenPin => "pin-green",
+            SelfCheck::BluePin => "pin-blue",
+            SelfCheck::FrameRate => "frame-rate",
+            SelfCheck::StateRoundTrip => "state",
+        }
+    }
+}
+
+/// Outcome of one check.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CheckStatus {
+    /// The check passed.
+    Pass,
+    /// The check failed.
+    Fail,
+    /// The check doesn't apply to this build or state.
+    Skipped,
+    /// The check showed a pattern it can't verify; whoever is watching
+    /// judges it.
+    Observe,
+}
+
+/// Limit a measurement is judged against.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Limit {
+    /// Passes at or below this value.
+    AtMost(u64),
+    /// Passes at or above this value.
+    AtLeast(u64),
+}
+
+/// Value a check measured, with its limit.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Measurement {
+    /// Measured value.
+    pub value: u64,
+    /// Limit for a pass.
+    pub limit: Limit,
+    /// Unit of the value and limit, such as `ms`.
+    pub unit: &'static str,
+}
+
+impl Measurement {
+    /// Whether the value is within the limit.
+    pub fn within(&self) -> bool {
+        match self.limit {
+            Limit::AtMost(max) => self.value <= max,
+            Limit::AtLeast(min) => self.value >= min,
+        }
+    }
+}
+
+/// Result of one check.
+///
+/// Displays as one report line, such as `knob-cal: PASS (3 ms, max 50 ms)`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CheckResult {
+    /// Check this is the result of.
+    pub check: SelfCheck,
+    /// Outcome.
+    pub status: CheckStatus,
+    /// What the outcome was judged on, if it was a measurement.
+    pub measurement: Option<Measurement>,
+}
+
+impl CheckResult {
+    /// Result judged on a measurement.
+    ///
+    /// # Arguments
+    /// * `check` - Check that measured it
+    /// * `value` - Measured value
+    /// * `limit` - Limit for a pass
+    /// * `unit` - Unit of the value and limit
+    pub fn measured(check: SelfCheck, value: u64, limit: Limit, unit: &'static str) -> Self {
+        let measurement = Measurement { value, limit, unit };
+        Self {
+            check,
+            status: if measurement.within() {
+                CheckStatus::Pass
+            } else {
+                CheckStatus::Fail
+            },
+            measurement: Some(measurement),
+        }
+    }
+    /// Result of a yes/no check.
+    pub fn pass_if(check: SelfCheck, ok: bool) -> Self {
+        Self {
+            check,
+            status: if ok {
+                CheckStatus::Pass
+            } else {
+                CheckStatus::Fail
+            },
+            measurement: None,
+        }
+    }
+    /// Result of a check that doesn't apply.
+    pub fn skipped(check: SelfCheck) -> Self {
+        Self {
+            check,
+            status: CheckStatus::Skipped,
+            measurement: None,
+        }
+    }
+    /// Result of a check whose pattern someone has to watch.
+    pub fn observed(check: SelfCheck) -> Self {
+        Self {
+            check,
+            status: CheckStatus::Observe,
+            measurement: None,
+        }
+    }
+    /// The same result, failed regardless of its measurement.
+    pub fn failed(self) -> Self {
+        Self {
+            status: CheckStatus::Fail,
+            ..self
+        }
+    }
+}
+
+impl core::fmt::Display for CheckResult {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        let status = match self.status {
+            CheckStatus::Pass => "PASS",
+            CheckStatus::Fail => "FAIL",
+            CheckStatus::Skipped => "SKIP",
+            CheckStatus::Observe => "OBSERVE",
+        };
+        write!(f, "{}: {}", self.check.name(), status)?;
+        if let Some(measurement) = self.measurement {
+            let (bound, limit) = match measurement.limit {
+                Limit::AtMost(max) => ("max", max),
+                Limit::AtLeast(min) => ("min", min),
+            };
+            write!(
+                f,
+                " ({} {}, {} {} {})",
+                measurement.value, measurement.unit, bound, limit, measurement.unit
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Results of a self-test run, one slot per [`SelfCheck`].
+///
+/// Displays as the summary line, such as
+/// `selftest: FAIL (7 passed, 1 failed, 1 skipped)`, with a count such
+/// as `, 3 to observe` before the parenthesis closes if any check needs
+/// watching.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SelfTestReport {
+    /// Result of each check in [`SelfCheck::ALL`] order, once run.
+    results: [Option<CheckResult>; SelfCheck::ALL.len()],
+}
+
+impl SelfTestReport {
+    /// Creates a report with no results.
+    pub const fn new() -> Self {
+        Self {
+            results: [None; SelfCheck::ALL.len()],
+        }
+    }
+    /// Records a check's result, replacing any earlier one for it.
+    pub fn record(&mut self, result: CheckResult) {
+        if let Some(slot) = SelfCheck::ALL.iter().position(|&c| c == result.check) {
+            self.results[slot] = Some(result);
+        }
+    }
+    /// The recorded results, in [`SelfCheck::ALL`] order.
+    pub fn results(&self) -> impl Iterator<Item = &CheckResult> {
+        self.results.iter().flatten()
+    }
+    /// Number of recorded results with a status.
+    pub fn count(&self, status: CheckStatus) -> usize {
+        self.results()
+            .filter(|result| result.status == status)
+            .count()
+    }
+    /// Whether every check ran and none failed; checks to observe don't
+    /// count against it.
+    pub fn passed(&self) -> bool {
+        self.results.iter().all(|result| result.is_some()) && self.count(CheckStatus::Fail) == 0
+    }
+}
+
+impl Default for SelfTestReport {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl core::fmt::Display for SelfTestReport {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(
+            f,
+            "selftest: {} ({} passed, {} failed, {} skipped",
+            if self.passed() { "PASS" } else { "FAIL" },
+            self.count(CheckStatus::Pass),
+            self.count(CheckStatus::Fail),
+            self.count(CheckStatus::Skipped)
+        )?;
+        match self.count(CheckStatus::Observe) {
+            0 => write!(f, ")"),
+            observe => write!(f, ", {} to observe)", observe),
+        }
+    }
+}
+
+/// Something that can run the individual checks.
+pub trait SelfChecks {
+    /// Runs one check.
+    fn run_check(&mut self, check: SelfCheck) -> impl Future<Output = CheckResult>;
+}
+
+/// Runs every check in [`SelfCheck::ALL`] order.
+///
+/// # Arguments
+/// * `checks` - Runs each check
+///
+/// # Returns
+/// The report, with a result for every check: a failure doesn't stop the
+/// checks after it.
+pub async fn run_self_test(checks: &mut impl SelfChecks) -> SelfTestReport {
+    run_self_checks(checks, &SelfCheck::ALL).await
+}
+
+/// Runs some of the checks, in the order given.
+///
+/// # Arguments
+/// * `checks` - Runs each check
+/// * `which` - Checks to run
+///
+/// # Returns
+/// The report, with a result for each check run. It only
+/// [`passes`](SelfTestReport::passed) if every check was run.
+pub async fn run_self_checks(checks: &mut impl SelfChecks, which: &[SelfCheck]) -> SelfTestReport {
+    let mut report = SelfTestReport::new();
+    for &check in which {
+        report.record(checks.run_check(check).await);
+    }
+    report
+}
+
+/// Checks that a button reads released.
+///
+/// # Arguments
+/// * `check` - [`SelfCheck::ButtonA`] or [`SelfCheck::ButtonB`]
+/// * `pressed` - Whether the button reads pressed
+pub fn check_button(check: SelfCheck, pressed: bool) -> CheckResult {
+    CheckResult::pass_if(check, !pressed)
+}
+
+/// Checks that one LED channel can be driven: lit alone at full level,
+/// its pin rises at least once every other frame.
+///
+/// Leaves that channel lit; the caller restores the levels.
+///
+/// # Arguments
+/// * `check` - [`SelfCheck::Red`], [`SelfCheck::Green`] or [`SelfCheck::Blue`]
+/// * `led` - Channel index (0 = red, 1 = green, 2 = blue)
+pub async fn check_channel(check: SelfCheck, led: usize) -> CheckResult {
+    if cfg!(feature = "hwpwm") || !get_output_enabled().await || get_master_brightness().await == 0
+    {
+        return CheckResult::skipped(check);
+    }
+    let mut levels = [0; 3];
+    levels[led] = LEVELS - 1;
+    set_rgb_levels(|rgb| *rgb = levels).await;
+    let frame = Duration::from_ticks(RGB_TIMING.lock().await.frame_ticks);
+    // Let the loop pick up the new levels first.
+    Timer::after(frame + frame).await;
+    let before = PWM_RISES[led].load(Ordering::Relaxed);
+    Timer::after(SELFTEST_DRIVE).await;
+    let rises = PWM_RISES[led].load(Ordering::Relaxed).wrapping_sub(before);
+    let frames = SELFTEST_DRIVE.as_ticks() / frame.as_ticks().max(1);
+    CheckResult::measured(
+        check,
+        rises as u64,
+        Limit::AtLeast((frames / 2).max(1)),
+        "edges",
+    )
+}
+
+/// Checks that a frame rate change is in use within one frame.
+///
+/// Restores the frame rate afterward.
+pub async fn check_frame_rate() -> CheckResult {
+    let check = SelfCheck::FrameRate;
+    let original = get_frame_rate().await;
+    let old = *RGB_TIMING.lock().await;
+    let target = if old.frame_rate > FRAME_RATE_MIN + FRAME_RATE_STEP {
+        FRAME_RATE_MIN + FRAME_RATE_STEP
+    } else {
+        FRAME_RATE_MAX
+    };
+    let expected = scanout_frame_rate(target);
+    let limit = Duration::from_ticks(old.frame_ticks) + SELFTEST_FRAME_SLACK;
+    let start = Instant::now();
+    set_frame_rate(StateSource::Ui, |fps| *fps = target).await;
+    let mut took = start.elapsed();
+    while RGB_TIMING.lock().await.frame_rate != expected {
+        took = start.elapsed();
+        // Give up well past the limit; the result is a failure anyway.
+        if took > limit * 4 {
+            break;
+        }
+        Timer::after(SELFTEST_POLL).await;
+    }
+    set_frame_rate(StateSource::Ui, |fps| *fps = original).await;
+    CheckResult::measured(
+        check,
+        took.as_micros(),
+        Limit::AtMost(limit.as_micros()),
+        "us",
+    )
+}
+
+/// Checks that levels and a frame rate written through the console's
+/// setters read back the same.
+///
+/// Restores both afterward.
+pub async fn check_state_round_trip() -> CheckResult {
+    let levels = get_rgb_levels().await;
+    let frame_rate = get_frame_rate().await;
+    let pattern = [1, LEVELS / 2, LEVELS - 1];
+    let test_rate = if frame_rate == FRAME_RATE_MIN {
+        FRAME_RATE_MIN + FRAME_RATE_STEP
+    } else {
+        FRAME_RATE_MIN
+    };
+    let stored = try_set_rgb_levels(pattern).await.is_ok();
+    set_frame_rate(StateSource::Ui, |fps| *fps = test_rate).await;
+    let ok = stored && get_rgb_levels().await == pattern && get_frame_rate().await == test_rate;
+    set_rgb_levels(|rgb| *rgb = levels).await;
+    set_frame_rate(StateSource::Ui, |fps| *fps = frame_rate).await;
+    CheckResult::pass_if(SelfCheck::StateRoundTrip, ok)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use embassy_futures::block_on;
+
+    #[test]
+    fn measurements_pass_within_inclusive_limits() {
+        let fast = CheckResult::measured(SelfCheck::FrameRate, 900, Limit::AtMost(12_000), "us");
+        assert_eq!(fast.status, CheckStatus::Pass);
+        let few = CheckResult::measured(SelfCheck::Red, 3, Limit::AtLeast(10), "edges");
+        assert_eq!(few.status, CheckStatus::Fail);
+        // Limits are inclusive.
+        let edge = CheckResult::measured(SelfCheck::Red, 10, Limit::AtLeast(10), "edges");
+        assert_eq!(edge.status, CheckStatus::Pass);
+        assert_eq!(
+            CheckResult::pass_if(SelfCheck::ButtonA, false).status,
+            CheckStatus::Fail
+        );
+        assert_eq!(CheckResult::skipped(SelfCheck::Blue).measurement, None);
+        assert_eq!(
+            CheckResult::observed(SelfCheck::RedPin).status,
+            CheckStatus::Observe
+        );
+    }
+
+    #[test]
+    fn self_test_runs_every_check_in_order() {
+        block_on(async {
+            // Stubbed checks: the knob is noisy, and the hardware PWM skips the
+            // LEDs and leaves the pins to be watched.
+            struct Stub(usize);
+            impl SelfChecks for Stub {
+                async fn run_check(&mut self, check: SelfCheck) -> CheckResult {
+                    self.0 += 1;
+                    match check {
+                        SelfCheck::KnobNoise => CheckResult::measured(
+                            check,
+                            40,
+                            Limit::AtMost(SELFTEST_KNOB_SD_MAX),
+                            "counts",
+                        ),
+                        SelfCheck::Red | SelfCheck::Green | SelfCheck::Blue => {
+                            CheckResult::skipped(check)
+                        }
+                        SelfCheck::RedPin | SelfCheck::GreenPin | SelfCheck::BluePin => {
+                            CheckResult::observed(check)
+                        }
+                        _ => CheckResult::pass_if(check, true),
+                    }
+                }
+            }
+            let mut stub = Stub(0);
+            let report = run_self_test(&mut stub).await;
+            // Every check ran, in order, despite the early failure.
+            assert_eq!(stub.0, SelfCheck::ALL.len());
+            assert_eq!(report.results().count(), SelfCheck::ALL.len());
+            let mut order = SelfCheck::ALL.iter().zip(report.results());
+            assert!(order.all(|(&check, result)| result.check == check));
+            assert_eq!(report.count(CheckStatus::Pass), 5);
+            assert_eq!(report.count(CheckStatus::Fail), 1);
+            assert_eq!(report.count(CheckStatus::Skipped), 3);
+            assert_eq!(report.count(CheckStatus::Observe), 3);
+            assert!(!report.passed());
+
+            // Skipped checks don't fail the suite, but missing ones do.
+            let mut report = SelfTestReport::new();
+            for check in SelfCheck::ALL {
+                report.record(CheckResult::skipped(check));
+            }
+            assert!(report.passed());
+            let mut partial = SelfTestReport::new();
+            partial.record(CheckResult::pass_if(SelfCheck::ButtonA, true));
+            assert!(!partial.passed());
+        });
+    }
+
+    #[test]
+    fn self_checks_run_only_the_chosen_ones() {
+        block_on(async {
+            struct Stub;
+            impl SelfChecks for Stub {
+                async fn run_check(&mut self, check: SelfCheck) -> CheckResult {
+                    CheckResult::pass_if(check, check != SelfCheck::GreenPin)
+                }
+            }
+            let report = run_self_checks(&mut Stub, &SelfCheck::PINS).await;
+            assert_eq!(report.results().count(), 3);
+            assert_eq!(report.count(CheckStatus::Fail), 1);
+        });
+    }
+}
@@ -0,0 +1,308 @@
+//! # Settings Module
+//!
+//! This module defines the blob that persisted settings are saved as
+//! through a [`StorageBackend`], and checks it on the way back in, so a
+//! format change or corruption can't load garbage into the LED state.
+//!
+//! ## Format
+//!
+//! The settings are the power-on values, a [`Defaults`], behind a header
+//! and followed by a CRC:
+//!
+//! ```text
+//! 'R' 'C' | version u8 | length u8 | payload | crc16 u16 (little-endian)
+//! ```
+//!
+//! The CRC ([`crc16`]) covers the header and payload. Version 2, the
+//! current one, stores every field; version 1 stored only the levels and
+//! frame rate.
+//!
+//! ## Loading
+//!
+//! [`decode_settings`] checks the magic, length, and CRC, then the version:
+//! a version 1 blob is migrated by taking the missing fields from
+//! [`DEFAULTS`], and an unknown version is rejected. Every value must also
+//! be in range. [`load_settings`] falls back to [`DEFAULTS`] on any
+//! rejection, logging why.
+//!
+//! The firmware loads the settings page at boot and saves to it on the
+//! console's `SAVE`.
+use crate::*;
+
+/// First two bytes of every settings blob, "RC".
+pub const SETTINGS_MAGIC: [u8; 2] = *b"RC";
+/// Version [`encode_settings`] writes.
+pub const SETTINGS_VERSION: u8 = 2;
+/// Bytes before the payload: magic, version, and length.
+pub(crate) const SETTINGS_HEADER_LEN: usize = 4;
+/// Payload length of version 1: levels and frame rate.
+const SETTINGS_V1_LEN: usize = 5;
+/// Payload length of version 2: every [`Defaults`] field.
+pub(crate) const SETTINGS_V2_LEN: usize = 8;
+/// Length of a current-version blob, CRC included.
+pub const SETTINGS_BLOB_LEN: usize = SETTINGS_HEADER_LEN + SETTINGS_V2_LEN + 2;
+
+/// Reasons a settings blob was rejected.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SettingsError {
+    /// Nothing was stored.
+    Missing,
+    /// The blob doesn't start with [`SETTINGS_MAGIC`].
+    BadMagic,
+    /// The blob's length doesn't match its header or its version.
+    BadLength,
+    /// The CRC doesn't match: the blob is corrupt.
+    BadCrc,
+    /// The blob is from a version this firmware doesn't know.
+    UnknownVersion(u8),
+    /// A stored value is outside its valid range.
+    OutOfRange,
+    /// The text isn't an even number of hex digits (see [`parse_hex_blob`]).
+    BadHex,
+}
+
+/// Header of a settings blob.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SettingsHeader {
+    /// Format version.
+    pub version: u8,
+    /// Payload length in bytes.
+    pub len: u8,
+}
+
+impl SettingsHeader {
+    /// Checks a settings blob's magic, length, and CRC.
+    ///
+    /// # Returns
+    /// The header and the payload, or why the blob was rejected.
+    pub fn parse(blob: &[u8]) -> Result<(Self, &[u8]), SettingsError> {
+        Self::parse_with(SETTINGS_MAGIC, blob)
+    }
+
+    /// Checks the magic, length, and CRC of a blob framed like the
+    /// settings but with its own magic (see [`frame_blob`]).
+    ///
+    /// # Returns
+    /// The header and the payload, or why the blob was rejected.
+    pub fn parse_with(magic: [u8; 2], blob: &[u8]) -> Result<(Self, &[u8]), SettingsError> {
+        let [m0, m1, version, len, ..] = *blob else {
+            return Err(SettingsError::BadLength);
+        };
+        if [m0, m1] != magic {
+            return Err(SettingsError::BadMagic);
+        }
+        let end = SETTINGS_HEADER_LEN + len as usize;
+        if blob.len() != end + 2 {
+            return Err(SettingsError::BadLength);
+        }
+        let crc = u16::from_le_bytes([blob[end], blob[end + 1]]);
+        if crc != crc16(&blob[..end]) {
+            return Err(SettingsError::BadCrc);
+        }
+        Ok((Self { version, len }, &blob[SETTINGS_HEADER_LEN..end]))
+    }
+}
+
+/// Frames a payload with a header and CRC, as in the settings format.
+///
+/// # Arguments
+/// * `magic` - First two bytes, telling the kinds of blob apart
+/// * `version` - Format version of the payload
+/// * `payload` - The encoded fields; `N` must be its length plus 6
+pub fn frame_blob<const N: usize>(magic: [u8; 2], version: u8, payload: &[u8]) -> [u8; N] {
+    debug_assert_eq!(N, SETTINGS_HEADER_LEN + payload.len() + 2);
+    let mut blob = [0; N];
+    blob[..2].copy_from_slice(&magic);
+    blob[2] = version;
+    blob[3] = payload.len() as u8;
+    let end = SETTINGS_HEADER_LEN + payload.len();
+    blob[SETTINGS_HEADER_LEN..end].copy_from_slice(payload);
+    let crc = crc16(&blob[..end]);
+    blob[end..].copy_from_slice(&crc.to_le_bytes());
+    blob
+}
+
+/// Writes the fields every settings version starts with, the version 2
+/// payload, which [`FullConfig`] also begins with.
+pub(crate) fn encode_settings_payload(settings: &Defaults) -> [u8; SETTINGS_V2_LEN] {
+    let mut payload = [0; SETTINGS_V2_LEN];
+    for (byte, &level) in payload.iter_mut().zip(settings.levels.iter()) {
+        *byte = level.min(u8::MAX as u32) as u8;
+    }
+    payload[3..5].copy_from_slice(&(settings.frame_rate.min(u16::MAX as u64) as u16).to_le_bytes());
+    payload[5] = settings.saturation.min(u8::MAX as u32) as u8;
+    payload[6] = settings.brightness.min(u8::MAX as u32) as u8;
+    payload[7] = settings.output_enabled as u8;
+    payload
+}
+
+/// Encodes settings as a current-version blob.
+pub fn encode_settings(settings: &Defaults) -> [u8; SETTINGS_BLOB_LEN] {
+    frame_blob(
+        SETTINGS_MAGIC,
+        SETTINGS_VERSION,
+        &encode_settings_payload(settings),
+    )
+}
+
+/// Decodes and checks a settings blob, migrating older versions.
+///
+/// # Returns
+/// The settings, or why the blob was rejected.
+pub fn decode_settings(blob: &[u8]) -> Result<Defaults, SettingsError> {
+    let (header, payload) = SettingsHeader::parse(blob)?;
+    let expected_len = match header.version {
+        1 => SETTINGS_V1_LEN,
+        2 => SETTINGS_V2_LEN,
+        version => return Err(SettingsError::UnknownVersion(version)),
+    };
+    if payload.len() != expected_len {
+        return Err(SettingsError::BadLength);
+    }
+    let mut settings = DEFAULTS;
+    settings.levels = [payload[0] as u32, payload[1] as u32, payload[2] as u32];
+    settings.frame_rate = u16::from_le_bytes([payload[3], payload[4]]) as u64;
+    // Version 1 ends here; the rest keep their defaults.
+    if header.version >= 2 {
+        settings.saturation = payload[5] as u32;
+        settings.brightness = payload[6] as u32;
+        settings.output_enabled = match payload[7] {
+            0 => false,
+            1 => true,
+            _ => return Err(SettingsError::OutOfRange),
+        };
+    }
+    let in_range = validate_levels(settings.levels).is_ok()
+        && validate_frame_rate(settings.frame_rate).is_ok()
+        && settings.saturation < LEVELS
+        && settings.brightness <= BRIGHTNESS_MAX;
+    if !in_range {
+        return Err(SettingsError::OutOfRange);
+    }
+    Ok(settings)
+}
+
+/// Saves settings as a current-version blob.
+pub fn save_settings(
+    storage: &impl StorageBackend,
+    settings: &Defaults,
+) -> Result<(), StorageError> {
+    storage.save(&encode_settings(settings))
+}
+
+/// Loads settings, falling back to [`DEFAULTS`] if the stored blob is
+/// missing or rejected (see [`decode_settings`]), and logging why.
+pub fn load_settings(storage: &impl StorageBackend) -> Defaults {
+    let decoded = match storage.load() {
+        Some(blob) => decode_settings(blob.as_bytes()),
+        None => Err(SettingsError::Missing),
+    };
+    decoded.unwrap_or_else(|error| {
+        if error != SettingsError::Missing {
+            rprintln!(
+                "settings: stored blob rejected ({:?}), using defaults",
+                error
+            );
+        }
+        DEFAULTS
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn frame_blob_layout() {
+        let blob: [u8; 9] = frame_blob(*b"XY", 1, &[1, 2, 3]);
+        assert_eq!(&blob[..7], &[b'X', b'Y', 1, 3, 1, 2, 3]);
+        assert_eq!(
+            SettingsHeader::parse_with(*b"XY", &blob).unwrap().1,
+            &[1, 2, 3]
+        );
+    }
+
+    #[test]
+    fn settings_round_trip() {
+        let settings = Defaults {
+            levels: [3, 9, 15],
+            frame_rate: 60,
+            ..DEFAULTS
+        };
+        let blob = encode_settings(&settings);
+        assert_eq!(&blob[..3], &[b'R', b'C', SETTINGS_VERSION]);
+        assert_eq!(decode_settings(&blob), Ok(settings));
+    }
+
+    #[test]
+    fn decode_rejects_bad_blobs_and_migrates_v1() {
+        let settings = Defaults {
+            levels: [1, 2, 3],
+            brightness: 40,
+            ..DEFAULTS
+        };
+        let blob = encode_settings(&settings);
+        assert_eq!(decode_settings(&blob), Ok(settings));
+
+        // Any flipped bit is caught by the CRC.
+        for byte in 4..blob.len() {
+            let mut corrupt = blob;
+            corrupt[byte] ^= 0x10;
+            assert_eq!(decode_settings(&corrupt), Err(SettingsError::BadCrc));
+        }
+        let mut wrong = blob;
+        wrong[0] = b'X';
+        assert_eq!(decode_settings(&wrong), Err(SettingsError::BadMagic));
+        assert_eq!(
+            decode_settings(&blob[..blob.len() - 1]),
+            Err(SettingsError::BadLength)
+        );
+        assert_eq!(decode_settings(&[]), Err(SettingsError::BadLength));
+
+        // A version 1 blob (levels 3, 4, 5 at 60 fps) is migrated, with the
+        // fields it lacked from DEFAULTS.
+        let mut v1 = [b'R', b'C', 1, 5, 3, 4, 5, 60, 0, 0, 0];
+        let crc = crc16(&v1[..9]);
+        v1[9..].copy_from_slice(&crc.to_le_bytes());
+        let migrated = decode_settings(&v1).unwrap();
+        assert_eq!((migrated.levels, migrated.frame_rate), ([3, 4, 5], 60));
+        assert_eq!(migrated.brightness, DEFAULTS.brightness);
+        assert_eq!(migrated.saturation, DEFAULTS.saturation);
+
+        // A version from newer firmware is rejected, even with a good CRC.
+        let mut future = blob;
+        future[2] = SETTINGS_VERSION + 1;
+        let crc = crc16(&future[..blob.len() - 2]);
+        future[blob.len() - 2..].copy_from_slice(&crc.to_le_bytes());
+        assert_eq!(
+            decode_settings(&future),
+            Err(SettingsError::UnknownVersion(3))
+        );
+
+        // So is a well-formed blob holding an invalid value.
+        let bad = encode_settings(&Defaults {
+            levels: [LEVELS, 0, 0],
+            ..DEFAULTS
+        });
+        assert_eq!(decode_settings(&bad), Err(SettingsError::OutOfRange));
+    }
+
+    #[test]
+    fn load_falls_back_to_the_defaults() {
+        let storage = RamStorage::new();
+        // Nothing saved yet.
+        assert_eq!(load_settings(&storage), DEFAULTS);
+        let settings = Defaults {
+            frame_rate: 50,
+            output_enabled: false,
+            ..DEFAULTS
+        };
+        save_settings(&storage, &settings).unwrap();
+        assert_eq!(load_settings(&storage), settings);
+        // A corrupt blob loads the defaults instead of garbage.
+        let mut corrupt = encode_settings(&settings);
+        corrupt[5] ^= 0xFF;
+        storage.save(&corrupt).unwrap();
+        assert_eq!(load_settings(&storage), DEFAULTS);
+    }
+}
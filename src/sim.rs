@@ -0,0 +1,484 @@
+//! # Frame Loop Simulation Module
+//!
+//! This module replays the software PWM frame loop on a virtual clock, so
+//! timing changes to the scanout can be checked without a board and a
+//! scope.
+//!
+//! ## Shared Steps
+//!
+//! `Rgb::run` plays each frame by walking [`FrameSchedule::steps`]:
+//! waits for deadlines measured from the frame start, and pin writes.
+//! [`PwmTrace`] walks the very same steps, but a wait just moves its tick
+//! counter forward and a pin write is recorded as a transition, so the
+//! waveform it reconstructs is the one the firmware would drive with a
+//! perfect timer.
+//!
+//! ## Guarded Properties
+//!
+//! The [`simulate_frames`] tests run a few hundred virtual frames at
+//! several frame rates, level patterns, dither modes, and phase counts,
+//! and check:
+//!
+//! - **Duty**: Each channel's average on-time is its level's share of the
+//!   slot, `level / LEVELS`, within a tick (with [`GAMMA`] unset)
+//! - **Period**: Every frame lasts `TICK_HZ / frame_rate` ticks, rounded
+//!   down or up, and the frames add up to exactly the requested rate
+//!   (see [`FrameCarry`])
+//! - **One at a time**: No two LEDs are ever lit together
+//!
+//! [`FrameLoop`] goes one step further and runs the scanout's own
+//! per-frame step, [`Scanout`], on inputs fed frame by frame in place of
+//! the shared-state accessors. Its tests check that the rendered frame
+//! period tracks the requested frame rate (every frame within a tick of
+//! the ideal), that a new frame rate takes effect on the very next frame,
+//! that brightness, the ceiling and the output switch reach the pins, and
+//! that a compensated timer overhead keeps the frame period.
+//!
+//! [`PwmTrace`] also stands in for the LED pins of the reset path's
+//! panic color (see the [`reset`](crate::reset) module).
+//!
+//! Any change to the frame loop (concurrent channels, bit-angle
+//! modulation, a free-running ticker) has to keep these passing.
+use crate::*;
+
+/// Pin waveform recorded from frames played on a virtual clock.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PwmTrace {
+    /// Virtual time in timer ticks.
+    now: u64,
+    /// Pin state per LED.
+    high: [bool; 3],
+    /// When each lit LED went high.
+    rose_at: [u64; 3],
+    /// Total lit time per LED in timer ticks.
+    on_ticks: [u64; 3],
+    /// Start of the last frame played.
+    frame_start: Option<u64>,
+    /// Shortest and longest frame period seen, in timer ticks.
+    frame_periods: Option<(u64, u64)>,
+    /// Frames played.
+    frames: u64,
+    /// Most LEDs lit at once.
+    max_lit: usize,
+}
+
+impl PwmTrace {
+    /// Starts an empty trace at tick 0 with all LEDs off.
+    pub const fn new() -> Self {
+        Self {
+            now: 0,
+            high: [false; 3],
+            rose_at: [0; 3],
+            on_ticks: [0; 3],
+            frame_start: None,
+            frame_periods: None,
+            frames: 0,
+            max_lit: 0,
+        }
+    }
+
+    /// Plays one frame, starting now.
+    ///
+    /// # Arguments
+    /// * `schedule` - Frame to play
+    /// * `extra` - This frame's dither ticks, from [`Dither::extra`]
+    pub fn play(&mut self, schedule: &FrameSchedule, extra: [u64; 3]) {
+        self.play_late(schedule.steps(extra), 0);
+    }
+
+    /// Plays one frame's steps, starting now, on a timer that wakes
+    /// `overhead` ticks after each deadline it is armed for.
+    ///
+    /// Deadlines are brought forward by the overhead first (see
+    /// [`compensated_deadline`]), as the frame loop does, so only those
+    /// closer to the frame start than the overhead land late.
+    ///
+    /// # Arguments
+    /// * `steps` - Frame to play, from [`FrameSchedule::steps`]
+    /// * `overhead` - Timer wake-up overhead in ticks
+    pub fn play_late(&mut self, steps: FrameSteps<'_>, overhead: u64) {
+        let frame_start = self.now;
+        if let Some(previous) = self.frame_start {
+            let period = frame_start - previous;
+            self.frame_periods = Some(match self.frame_periods {
+                Some((shortest, longest)) => (shortest.min(period), longest.max(period)),
+                None => (period, period),
+            });
+        }
+        self.frame_start = Some(frame_start);
+        for step in steps {
+            match step {
+                FrameStep::Wait(at) => {
+                    let woke = frame_start + compensated_deadline(at, overhead) + overhead;
+                    self.now = self.now.max(woke);
+                }
+                FrameStep::Pin { led, high, .. } => self.set(led, high),
+            }
+        }
+        self.frames += 1;
+    }
+
+    /// Records a pin write at the current virtual time.
+    fn set(&mut self, led: usize, high: bool) {
+        if high && !self.high[led] {
+            self.rose_at[led] = self.now;
+        } else if !high && self.high[led] {
+            self.on_ticks[led] += self.now - self.rose_at[led];
+        }
+        self.high[led] = high;
+        let lit = self.high.iter().filter(|&&high| high).count();
+        self.max_lit = self.max_lit.max(lit);
+    }
+
+    /// Virtual time in timer ticks.
+    pub fn now(&self) -> u64 {
+        self.now
+    }
+
+    /// Frames played.
+    pub fn frames(&self) -> u64 {
+        self.frames
+    }
+
+    /// Total lit time of one LED in timer ticks, over all frames.
+    pub fn on_ticks(&self, led: usize) -> u64 {
+        self.on_ticks[led]
+    }
+
+    /// Shortest and longest period between frame starts, in timer ticks,
+    /// once two frames have started.
+    pub fn frame_periods(&self) -> Option<(u64, u64)> {
+        self.frame_periods
+    }
+
+    /// Most LEDs that were lit at the same time.
+    pub fn max_lit(&self) -> usize {
+        self.max_lit
+    }
+}
+
+impl Default for PwmTrace {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Records the reset path's panic color (see [`show_reset_color`]); a
+/// busy-wait just moves the virtual clock forward.
+impl ResetOutput for PwmTrace {
+    fn set(&mut self, led: usize, high: bool) {
+        PwmTrace::set(self, led, high);
+    }
+    fn busy_wait(&mut self, duration: Duration) {
+        self.now += duration.as_ticks();
+    }
+}
+
+/// The whole frame loop of `Rgb::run` on a virtual clock, inputs
+/// included.
+///
+/// Each [`FrameLoop::step`] runs the scanout's own per-frame step,
+/// [`Scanout::update`] and [`Scanout::frame_steps`], on the inputs `Rgb::run`
+/// would have read from the shared state, then plays the frame on a timer
+/// that wakes [`FrameLoop::with_timer_overhead`] ticks late, with the
+/// deadlines brought forward to match. Only reading the shared state and
+/// driving the pins are left out.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FrameLoop {
+    /// Waveform played so far.
+    trace: PwmTrace,
+    /// Dither algorithm.
+    mode: DitherMode,
+    /// Inputs, timing and schedule of the current frame.
+    scanout: Scanout,
+    /// Timer wake-up overhead in ticks, both simulated and compensated.
+    timer_overhead: u64,
+}
+
+impl FrameLoop {
+    /// Starts a loop at tick 0 that has played no frames, with the
+    /// build-time [`BRIGHTNESS_CEILING`] and an exact timer.
+    ///
+    /// # Arguments
+    /// * `mode` - Dither algorithm
+    pub fn new(mode: DitherMode) -> Self {
+        Self {
+            trace: PwmTrace::new(),
+            mode,
+            scanout: Scanout::new(FRAME_RATE_MIN, BRIGHTNESS_CEILING),
+            timer_overhead: 0,
+        }
+    }
+    /// Sets the brightness ceiling, in place of [`BRIGHTNESS_CEILING`].
+    ///
+    /// # Arguments
+    /// * `ceiling` - Brightness ceiling, 0 to [`BRIGHTNESS_MAX`]
+    pub fn with_ceiling(mut self, ceiling: u32) -> Self {
+        self.scanout = Scanout::new(FRAME_RATE_MIN, ceiling);
+        self
+    }
+    /// Makes every timer wait wake up late by `ticks`, and compensates for
+    /// it as `Rgb::with_timer_overhead` does.
+    ///
+    /// # Arguments
+    /// * `ticks` - Overhead in timer ticks
+    pub fn with_timer_overhead(mut self, ticks: u64) -> Self {
+        self.timer_overhead = ticks;
+        self
+    }
+
+    /// Plays one frame from the inputs read at its start.
+    ///
+    /// # Arguments
+    /// * `inputs` - Inputs as `Rgb::run` reads them from the shared state
+    ///
+    /// # Returns
+    /// The length of the frame played, in timer ticks.
+    pub fn step(&mut self, inputs: FrameInputs) -> u64 {
+        self.scanout.update(inputs);
+        let frame_start = self.trace.now();
+        let steps = self.scanout.frame_steps(self.mode);
+        self.trace.play_late(steps, self.timer_overhead);
+        self.trace.now() - frame_start
+    }
+
+    /// Timing of the last frame played.
+    pub fn timing(&self) -> RgbTiming {
+        self.scanout.timing()
+    }
+
+    /// Scanout state after the last frame played.
+    pub fn scanout(&self) -> &Scanout {
+        &self.scanout
+    }
+
+    /// Waveform played so far.
+    pub fn trace(&self) -> &PwmTrace {
+        &self.trace
+    }
+}
+
+/// Runs the frame loop for fixed inputs on a virtual clock.
+///
+/// Builds the schedule, dithers each frame and carries the frame length
+/// remainder as `Rgb::run` does, at full brightness with no ceiling.
+///
+/// # Arguments
+/// * `frame_rate` - Frame rate in FPS
+/// * `levels` - RGB levels, 0 to [`LEVELS`]-1
+/// * `mode` - Dither algorithm
+/// * `phases` - PWM phases per frame, 1 to [`PWM_PHASES_MAX`]
+/// * `frames` - Number of frames to play
+///
+/// # Returns
+/// The recorded waveform.
+pub fn simulate_frames(
+    frame_rate: u64,
+    levels: [u32; 3],
+    mode: DitherMode,
+    phases: u32,
+    frames: u64,
+) -> PwmTrace {
+    let timing = RgbTiming::for_frame_rate(frame_rate);
+    let schedule = FrameSchedule::new(&timing, levels, BRIGHTNESS_MAX, BRIGHTNESS_MAX, phases);
+    let mut dither = Dither::default();
+    let mut carry = FrameCarry::default();
+    let mut trace = PwmTrace::new();
+    for _ in 0..frames {
+        let extra = dither.extra(mode, schedule.fine_on_times());
+        let steps = schedule.steps(extra).stretched(carry.stretch(&timing));
+        trace.play_late(steps, 0);
+    }
+    trace
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use embassy_time::TICK_HZ;
+
+    /// Frames played per run.
+    const FRAMES: u64 = 100;
+
+    /// Inputs at full brightness and the build-time gamma curve.
+    fn inputs(levels: [u32; 3], frame_rate: u64) -> FrameInputs {
+        FrameInputs {
+            levels,
+            brightness: BRIGHTNESS_MAX,
+            frame_rate,
+            gamma: GAMMA,
+        }
+    }
+
+    #[test]
+    fn pwm_trace_records_frames() {
+        let timing = RgbTiming::for_frame_rate(50);
+        let full = BRIGHTNESS_MAX;
+        let schedule = FrameSchedule::new(&timing, [LEVELS - 1, 0, 0], full, full, 1);
+        let mut trace = PwmTrace::new();
+        for _ in 0..3 {
+            trace.play(&schedule, [0; 3]);
+        }
+        assert_eq!(trace.frames(), 3);
+        assert_eq!(trace.now(), 3 * timing.frame_ticks);
+        assert_eq!(trace.on_ticks(0), 3 * schedule.on_ticks(0));
+        assert_eq!(trace.on_ticks(1), 0);
+        assert_eq!(
+            trace.frame_periods(),
+            Some((timing.frame_ticks, timing.frame_ticks))
+        );
+        assert_eq!(trace.max_lit(), 1);
+    }
+
+    #[test]
+    fn simulated_frames_keep_duty_period_and_exclusion() {
+        const FRAMES: u64 = 320; // A whole number of dither cycles.
+        let patterns = [[0; 3], [LEVELS - 1; 3], [1, 0, 0], [8, 4, 15], [3, 12, 7]];
+        let modes = [
+            DitherMode::Off,
+            DitherMode::Ordered,
+            DitherMode::ErrorDiffusion,
+        ];
+        for frame_rate in [FRAME_RATE_MIN, 30, 60, 100, FRAME_RATE_MAX] {
+            let timing = RgbTiming::for_frame_rate(frame_rate);
+            for levels in patterns {
+                for mode in modes {
+                    for phases in [1, 2, PWM_PHASES_MAX] {
+                        let trace = simulate_frames(frame_rate, levels, mode, phases, FRAMES);
+                        assert_eq!(trace.frames(), FRAMES);
+                        // Every period is the frame length, rounded down or up,
+                        // and together they are exact.
+                        let (shortest, longest) = trace.frame_periods().unwrap();
+                        assert!(
+                            shortest >= timing.frame_ticks && longest <= timing.frame_ticks + 1
+                        );
+                        assert_eq!(trace.now(), FRAMES * TICK_HZ / frame_rate);
+                        // One LED at a time.
+                        assert!(trace.max_lit() <= 1);
+                        for (led, &level) in levels.iter().enumerate() {
+                            // Average on-time is the level's share of the slot,
+                            // within a tick: compared in 1/(FRAMES * LEVELS) ticks.
+                            let slot = timing.slot_ticks(led);
+                            let ideal = FRAMES * slot * level as u64;
+                            let on = trace.on_ticks(led) * LEVELS as u64;
+                            assert!(on.abs_diff(ideal) < FRAMES * LEVELS as u64);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn frame_loop_tracks_the_frame_rate() {
+        let levels = [8, 4, 15];
+        for frame_rate in [10, 60, 100, 160] {
+            let mut frames = FrameLoop::new(DitherMode::Off);
+            let mut total = 0;
+            for _ in 0..FRAMES {
+                let period = frames.step(inputs(levels, frame_rate));
+                // Every frame is within one tick of the ideal TICK_HZ / frame_rate:
+                // compared in 1/frame_rate ticks.
+                assert!((period * frame_rate).abs_diff(TICK_HZ) < frame_rate);
+                total += period;
+            }
+            let timing = frames.timing();
+            assert_eq!(timing.frame_rate, frame_rate);
+            // The carried remainder makes the average period exact.
+            assert_eq!(total, FRAMES * TICK_HZ / frame_rate);
+            assert_eq!(frames.trace().now(), total);
+            assert_eq!(frames.trace().frames(), FRAMES);
+        }
+    }
+
+    /// Whether a frame period is the frame length at a rate, rounded down
+    /// or up.
+    fn is_frame_length(period: u64, frame_rate: u64) -> bool {
+        let frame_ticks = RgbTiming::for_frame_rate(frame_rate).frame_ticks;
+        period == frame_ticks || period == frame_ticks + 1
+    }
+
+    #[test]
+    fn frame_loop_changes_rate_on_the_next_frame() {
+        let levels = [8, 4, 15];
+        let mut frames = FrameLoop::new(DitherMode::Ordered);
+        for (from, to) in [(60, 100), (100, 10), (10, 160)] {
+            for _ in 0..FRAMES / 2 {
+                let period = frames.step(inputs(levels, from));
+                assert!(is_frame_length(period, from));
+            }
+            let period = frames.step(inputs(levels, to));
+            assert!(is_frame_length(period, to));
+        }
+
+        // Requests outside the valid range render at the clamped rate.
+        let mut frames = FrameLoop::new(DitherMode::Off);
+        let period = frames.step(inputs(levels, 0));
+        assert!(is_frame_length(period, FRAME_RATE_MIN));
+        let period = frames.step(inputs(levels, u64::MAX));
+        assert!(is_frame_length(period, scanout_frame_rate(FRAME_RATE_MAX)));
+    }
+
+    #[test]
+    fn frame_loop_applies_brightness_and_ceiling() {
+        let levels = [LEVELS - 1, 8, 0];
+        let full = {
+            let mut frames = FrameLoop::new(DitherMode::Off);
+            frames.step(inputs(levels, 60));
+            *frames.trace()
+        };
+        assert!(full.on_ticks(0) > 0);
+
+        // Half brightness halves every on-time, within a tick.
+        let mut frames = FrameLoop::new(DitherMode::Off);
+        frames.step(FrameInputs {
+            brightness: BRIGHTNESS_MAX / 2,
+            ..inputs(levels, 60)
+        });
+        for led in 0..3 {
+            assert!((2 * frames.trace().on_ticks(led)).abs_diff(full.on_ticks(led)) <= 2);
+        }
+
+        // The ceiling caps the brightest channel and keeps the ratio.
+        let ceiling = BRIGHTNESS_MAX / 4;
+        let mut frames = FrameLoop::new(DitherMode::Off).with_ceiling(ceiling);
+        frames.step(inputs(levels, 60));
+        let slot = frames.timing().min_slot_ticks();
+        let red = frames.trace().on_ticks(0);
+        assert!(red * BRIGHTNESS_MAX as u64 <= slot * ceiling as u64);
+        assert!(red < full.on_ticks(0));
+        assert_eq!(frames.trace().on_ticks(2), 0);
+
+        // With the output off the LED stays dark, but frames keep their
+        // length.
+        let mut frames = FrameLoop::new(DitherMode::ErrorDiffusion);
+        for _ in 0..FRAMES {
+            let period = frames.step(FrameInputs {
+                brightness: 0,
+                ..inputs(levels, 60)
+            });
+            assert!(is_frame_length(period, 60));
+        }
+        assert_eq!(frames.trace().max_lit(), 0);
+    }
+
+    #[test]
+    fn frame_loop_compensates_timer_overhead() {
+        let levels = [8, 4, 15];
+        let exact = {
+            let mut frames = FrameLoop::new(DitherMode::Off);
+            for _ in 0..FRAMES {
+                frames.step(inputs(levels, 60));
+            }
+            *frames.trace()
+        };
+        let overhead = 2;
+        let mut frames = FrameLoop::new(DitherMode::Off).with_timer_overhead(overhead);
+        for _ in 0..FRAMES {
+            frames.step(inputs(levels, 60));
+        }
+        // Each deadline past the overhead lands on time, so the waveform
+        // is the exact timer's.
+        assert_eq!(frames.trace(), &exact);
+    }
+}
@@ -0,0 +1,246 @@
+//! # Sleep Timer Module
+//!
+//! This module turns the LED off a set time after the console's `SLEEP`
+//! command, for bedside use: the light can be left on to fall asleep by.
+//!
+//! ## Timing
+//!
+//! [`SLEEP_CONFIG`] sets the default delay and the fade. Over the last
+//! [`SleepConfig::fade`] before the deadline the master brightness ramps
+//! down to zero, and at the deadline the output stays off. The levels are
+//! never touched, so the stored color is exactly as it was.
+//!
+//! ## Cancelling
+//!
+//! `SLEEP OFF` cancels the timer, and so does any input the UI reports
+//! through [`sleep_input`] (a button press, knob change, forwarded command,
+//! or remote input). Cancelling during the fade or after the LED went off
+//! restores the master brightness from when the fade began.
+//!
+//! The fade owns the master brightness while it runs, so other writers of
+//! it (the `schedule` feature, sound-reactive mode) should be left off
+//! while the timer is set.
+use crate::*;
+
+/// Longest delay the console accepts, in minutes.
+pub const SLEEP_MINUTES_MAX: u64 = 24 * 60;
+
+/// Sleep timer settings.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SleepConfig {
+    /// Delay used by `SLEEP` with no argument.
+    pub delay: Duration,
+    /// Time over which the brightness fades out before the deadline.
+    pub fade: Duration,
+}
+
+/// Sleep timer settings used by `sleep_task`.
+pub const SLEEP_CONFIG: SleepConfig = SleepConfig {
+    delay: Duration::from_secs(30 * 60),
+    fade: Duration::from_secs(60),
+};
+
+/// Where the sleep timer is in its countdown.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SleepPhase {
+    /// No timer set.
+    Off,
+    /// Counting down at full output.
+    Armed,
+    /// Fading out ahead of the deadline.
+    Fading,
+    /// Past the deadline, with the output off.
+    Asleep,
+}
+
+/// Sleep timer countdown and fade state.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SleepTimer {
+    /// Fade length in milliseconds.
+    fade_ms: u64,
+    /// When the fade starts and the deadline, in milliseconds since boot,
+    /// while set.
+    window: Option<(u64, u64)>,
+    /// Master brightness when the fade began, to restore on cancel.
+    saved: Option<u32>,
+    /// Whether the output has been turned off at the deadline.
+    asleep: bool,
+}
+
+impl SleepTimer {
+    /// Creates an unset timer.
+    ///
+    /// # Arguments
+    /// * `fade` - Time over which the brightness fades out before the
+    ///   deadline
+    pub const fn new(fade: Duration) -> Self {
+        Self {
+            fade_ms: fade.as_millis(),
+            window: None,
+            saved: None,
+            asleep: false,
+        }
+    }
+
+    /// Where the countdown is.
+    pub fn phase(&self) -> SleepPhase {
+        match (self.window, self.saved, self.asleep) {
+            (_, _, true) => SleepPhase::Asleep,
+            (Some(_), Some(_), false) => SleepPhase::Fading,
+            (Some(_), None, false) => SleepPhase::Armed,
+            (None, _, false) => SleepPhase::Off,
+        }
+    }
+
+    /// Time left until the output goes off.
+    ///
+    /// # Arguments
+    /// * `now_ms` - Current time in milliseconds since boot
+    ///
+    /// # Returns
+    /// `None` unless counting down.
+    pub fn remaining(&self, now_ms: u64) -> Option<Duration> {
+        let (_, deadline_ms) = self.window?;
+        (!self.asleep).then(|| Duration::from_millis(deadline_ms.saturating_sub(now_ms)))
+    }
+
+    /// Sets the timer, replacing any countdown already running.
+    ///
+    /// # Arguments
+    /// * `now_ms` - Current time in milliseconds since boot
+    /// * `delay` - Time until the output goes off
+    ///
+    /// # Returns
+    /// The master brightness to restore, if a replaced countdown had begun
+    /// to fade.
+    pub fn start(&mut self, now_ms: u64, delay: Duration) -> Option<u32> {
+        let restore = self.cancel();
+        let deadline_ms = now_ms.saturating_add(delay.as_millis());
+        let fade_ms = self.fade_ms.min(delay.as_millis());
+        self.window = Some((deadline_ms - fade_ms, deadline_ms));
+        restore
+    }
+
+    /// Advances the countdown.
+    ///
+    /// # Arguments
+    /// * `now_ms` - Current time in milliseconds since boot
+    /// * `brightness` - Master brightness now, saved as the fade starts
+    ///
+    /// # Returns
+    /// The master brightness to write, or `None` to leave it alone.
+    pub fn tick(&mut self, now_ms: u64, brightness: u32) -> Option<u32> {
+        let (fade_start_ms, deadline_ms) = self.window?;
+        if self.asleep || now_ms < fade_start_ms {
+            return None;
+        }
+        let saved = *self.saved.get_or_insert(brightness);
+        if now_ms >= deadline_ms {
+            self.asleep = true;
+            return Some(0);
+        }
+        let left = (deadline_ms - now_ms) as u128;
+        let span = (deadline_ms - fade_start_ms) as u128;
+        Some((saved as u128 * left / span) as u32)
+    }
+
+    /// Cancels the timer.
+    ///
+    /// # Returns
+    /// The master brightness to restore, if the fade had begun.
+    pub fn cancel(&mut self) -> Option<u32> {
+        self.window = None;
+        self.asleep = false;
+        self.saved.take()
+    }
+}
+
+/// Global sleep timer, set from the console and cancelled by input.
+pub static SLEEP: Mutex<CriticalSectionRawMutex, SleepTimer> =
+    Mutex::new(SleepTimer::new(SLEEP_CONFIG.fade));
+
+/// Sets the sleep timer, or cancels it with `None`.
+///
+/// # Arguments
+/// * `delay` - Time until the output goes off
+pub async fn set_sleep_timer(delay: Option<Duration>) {
+    let mut sleep = SLEEP.lock().await;
+    match delay {
+        Some(delay) => {
+            if let Some(brightness) = sleep.start(Instant::now().as_millis(), delay) {
+                set_master_brightness(|b| *b = brightness).await;
+            }
+            rprintln!("Sleep timer: off in {} min", delay.as_secs().div_ceil(60));
+        }
+        None => {
+            if let Some(brightness) = sleep.cancel() {
+                set_master_brightness(|b| *b = brightness).await;
+            }
+            rprintln!("Sleep timer: cancelled");
+        }
+    }
+}
+
+/// Reports user input, cancelling the sleep timer and restoring the
+/// brightness if it had begun to fade.
+pub async fn sleep_input() {
+    let mut sleep = SLEEP.lock().await;
+    if sleep.phase() == SleepPhase::Off {
+        return;
+    }
+    if let Some(brightness) = sleep.cancel() {
+        set_master_brightness(|b| *b = brightness).await;
+    }
+    rprintln!("Sleep timer: cancelled by input");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sleep_timer_fades_then_wakes() {
+        let config = SleepConfig {
+            delay: Duration::from_secs(600),
+            fade: Duration::from_secs(60),
+        };
+        let mut timer = SleepTimer::new(config.fade);
+        // Nothing happens until the timer is set.
+        assert_eq!(timer.tick(1_000_000, 80), None);
+        assert_eq!(timer.phase(), SleepPhase::Off);
+
+        assert_eq!(timer.start(0, config.delay), None);
+        assert_eq!(timer.remaining(0), Some(config.delay));
+        assert_eq!(timer.tick(539_999, 80), None);
+        assert_eq!(timer.phase(), SleepPhase::Armed);
+        // The fade starts a minute early, from the brightness at that moment...
+        assert_eq!(timer.tick(540_000, 80), Some(80));
+        assert_eq!(timer.phase(), SleepPhase::Fading);
+        assert_eq!(timer.tick(570_000, 80), Some(40));
+        // ...and ends dark at the deadline, once.
+        assert_eq!(timer.tick(600_000, 40), Some(0));
+        assert_eq!(timer.phase(), SleepPhase::Asleep);
+        assert_eq!(timer.tick(700_000, 0), None);
+        // Input wakes it, handing back the brightness from before the fade.
+        assert_eq!(timer.cancel(), Some(80));
+        assert_eq!(timer.phase(), SleepPhase::Off);
+        assert_eq!(timer.cancel(), None);
+
+        // Cancelling before the fade has nothing to restore.
+        assert_eq!(timer.start(0, config.delay), None);
+        assert_eq!(timer.tick(100_000, 80), None);
+        assert_eq!(timer.cancel(), None);
+        assert_eq!(timer.tick(600_000, 80), None);
+        // Restarting mid-fade hands back the brightness from before the fade.
+        assert_eq!(timer.start(0, config.delay), None);
+        assert_eq!(timer.tick(570_000, 80), Some(40));
+        assert_eq!(timer.start(570_000, config.delay), Some(80));
+        assert_eq!(timer.phase(), SleepPhase::Armed);
+        assert_eq!(timer.tick(570_100, 80), None);
+        assert_eq!(timer.cancel(), None);
+        // A delay shorter than the fade fades over the whole delay.
+        assert_eq!(timer.start(0, Duration::from_secs(10)), None);
+        assert_eq!(timer.tick(0, 60), Some(60));
+        assert_eq!(timer.tick(5_000, 60), Some(30));
+    }
+}
@@ -0,0 +1,176 @@
+//! # Snapshot Module
+//!
+//! This module keeps two snapshots of the color state for A/B comparison
+//! while calibrating: `SNAP A` stores the current levels and frame rate,
+//! the tuner makes changes, `SNAP B` stores the result, and `DIFF`
+//! reports what changed from A to B (see [`SnapshotDiff`]).
+//!
+//! The snapshots live in RAM only and are lost on reset.
+use crate::*;
+
+/// One of the two snapshot slots.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SnapshotSlot {
+    /// The "before" state.
+    A,
+    /// The "after" state.
+    B,
+}
+
+/// Color state captured by `SNAP`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct StateSnapshot {
+    /// RGB levels.
+    pub levels: [u32; 3],
+    /// Frame rate in FPS.
+    pub frame_rate: u64,
+}
+
+/// Change from one snapshot to another.
+///
+/// Displays one line per channel, then the frame rate, each as the signed
+/// change followed by the two values, for example:
+///
+/// ```text
+/// red: +3 (5 -> 8)
+/// green: +0 (8 -> 8)
+/// blue: -2 (12 -> 10)
+/// fps: +20 (60 -> 80)
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SnapshotDiff {
+    /// Snapshot the change starts from.
+    pub from: StateSnapshot,
+    /// Snapshot the change ends at.
+    pub to: StateSnapshot,
+    /// Change in each RGB level.
+    pub levels: [i64; 3],
+    /// Change in frame rate, in FPS.
+    pub frame_rate: i64,
+}
+
+/// Computes the change from snapshot `from` to snapshot `to`.
+///
+/// # Returns
+/// Each difference as `to - from`: positive where `to` is higher.
+pub fn diff_snapshots(from: StateSnapshot, to: StateSnapshot) -> SnapshotDiff {
+    let signed = |value: u64| value.min(i64::MAX as u64) as i64;
+    SnapshotDiff {
+        from,
+        to,
+        levels: core::array::from_fn(|led| to.levels[led] as i64 - from.levels[led] as i64),
+        frame_rate: signed(to.frame_rate).saturating_sub(signed(from.frame_rate)),
+    }
+}
+
+impl core::fmt::Display for SnapshotDiff {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        let names = ["red", "green", "blue"];
+        for (led, name) in names.iter().enumerate() {
+            writeln!(
+                f,
+                "{}: {:+} ({} -> {})",
+                name, self.levels[led], self.from.levels[led], self.to.levels[led]
+            )?;
+        }
+        write!(
+            f,
+            "fps: {:+} ({} -> {})",
+            self.frame_rate, self.from.frame_rate, self.to.frame_rate
+        )
+    }
+}
+
+/// Snapshot slots A and B, empty until `SNAP` fills them.
+pub static SNAPSHOTS: Mutex<CriticalSectionRawMutex, [Option<StateSnapshot>; 2]> =
+    Mutex::new([None; 2]);
+
+/// Captures the current levels and frame rate into a slot.
+///
+/// # Returns
+/// The snapshot taken.
+pub async fn take_snapshot(slot: SnapshotSlot) -> StateSnapshot {
+    let snapshot = StateSnapshot {
+        levels: get_rgb_levels().await,
+        frame_rate: get_frame_rate().await,
+    };
+    SNAPSHOTS.lock().await[slot as usize] = Some(snapshot);
+    snapshot
+}
+
+/// Compares the two slots.
+///
+/// # Returns
+/// The change from A to B, or `None` until both have been taken.
+pub async fn snapshot_diff() -> Option<SnapshotDiff> {
+    let [a, b] = *SNAPSHOTS.lock().await;
+    Some(diff_snapshots(a?, b?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn snapshot_diff_golden() {
+        // Golden output (see the formats module).
+        let a = StateSnapshot {
+            levels: [5, 8, 12],
+            frame_rate: 60,
+        };
+        let b = StateSnapshot {
+            levels: [8, 8, 10],
+            frame_rate: 120,
+        };
+        check_golden(
+            [diff_snapshots(a, b)],
+            &["red: +3 (5 -> 8)\ngreen: +0 (8 -> 8)\nblue: -2 (12 -> 10)\nfps: +60 (60 -> 120)\n"],
+        )
+        .unwrap();
+        let zero = StateSnapshot {
+            levels: [0; 3],
+            frame_rate: FRAME_RATE_MIN,
+        };
+        let max = StateSnapshot {
+            levels: [LEVELS - 1; 3],
+            frame_rate: FRAME_RATE_MAX,
+        };
+        check_golden([diff_snapshots(max, zero)], &[
+            "red: -15 (15 -> 0)\ngreen: -15 (15 -> 0)\nblue: -15 (15 -> 0)\nfps: -150 (160 -> 10)\n",
+        ])
+        .unwrap();
+    }
+
+    #[test]
+    fn diff_is_signed_and_never_overflows() {
+        let a = StateSnapshot {
+            levels: [5, 8, 12],
+            frame_rate: 60,
+        };
+        let b = StateSnapshot {
+            levels: [8, 8, 10],
+            frame_rate: 80,
+        };
+        let diff = diff_snapshots(a, b);
+        assert_eq!(diff.levels, [3, 0, -2]);
+        assert_eq!(diff.frame_rate, 20);
+        // Reversed, every sign flips and the magnitudes stay.
+        let back = diff_snapshots(b, a);
+        assert_eq!(back.levels, [-3, 0, 2]);
+        assert_eq!(back.frame_rate, -20);
+        // No change is all zeros.
+        assert_eq!(diff_snapshots(a, a).levels, [0; 3]);
+        // Even out-of-range levels don't overflow.
+        let low = StateSnapshot {
+            levels: [0; 3],
+            frame_rate: FRAME_RATE_MIN,
+        };
+        let high = StateSnapshot {
+            levels: [u32::MAX; 3],
+            frame_rate: FRAME_RATE_MAX,
+        };
+        let diff = diff_snapshots(high, low);
+        assert_eq!(diff.levels, [-(u32::MAX as i64); 3]);
+        assert_eq!(diff.frame_rate, -150);
+    }
+}
@@ -0,0 +1,236 @@
+//! # Statistics Module
+//!
+//! This module keeps runtime event counters for chasing intermittent
+//! problems after the fact, without printing anything at the time: how
+//! often the levels and frame rate actually changed, how many knob
+//! readings were taken or clamped, how many knob writes were dropped as
+//! duplicates or dithering, how many button edges and aborted
+//! chords there were, and how many log lines, ADC calibrations and ADC
+//! conversions failed.
+//!
+//! ## Counting
+//!
+//! Each [`Stat`] is an [`AtomicU32`] in [`STATS`], bumped with
+//! [`count_stat!`](crate::count_stat), a single relaxed atomic add that
+//! never waits on a lock, so it is safe in the RGB loop and interrupt
+//! handlers alike. The counters wrap from `u32::MAX` back to 0; at one
+//! event per millisecond that is 49 days, so a reader comparing two dumps
+//! should take the difference modulo 2³², as the dropped-line notice does.
+//!
+//! ## Reading and Resetting
+//!
+//! [`Stats::snapshot`] reads every counter, and [`Stats::take`] reads and
+//! zeroes them. Each counter is read and zeroed in one atomic swap, so an
+//! event counted while a `take` runs lands in exactly one dump, this one or
+//! the next, and none is lost. The snapshot is not atomic across counters,
+//! though: two events counted together may straddle a dump.
+//!
+//! The console's `STATS` prints a snapshot and `STATS RESET` a snapshot
+//! taken with a reset. With [`STATS_HEARTBEAT`] set, the firmware's
+//! `stats_task` also prints them periodically.
+use crate::*;
+use core::sync::atomic::{AtomicU32, Ordering};
+
+/// Periodic statistics dump.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct StatsHeartbeat {
+    /// Time between dumps.
+    pub interval: Duration,
+    /// Whether each dump resets the counters, so it covers one interval.
+    pub reset: bool,
+}
+
+/// Periodic statistics dump printed by the firmware's `stats_task`, or
+/// `None` for none.
+pub const STATS_HEARTBEAT: Option<StatsHeartbeat> = Some(StatsHeartbeat {
+    interval: Duration::from_secs(60),
+    reset: false,
+});
+
+/// A counted runtime event.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Stat {
+    /// The RGB loop picked up new levels.
+    LevelUpdates,
+    /// The RGB loop picked up a new frame rate.
+    FrameRateChanges,
+    /// Raw knob readings taken.
+    KnobReadings,
+    /// Knob readings outside the ADC's expected range, clamped.
+    KnobClamped,
+    /// Knob levels not written because they equal the last one written.
+    WritesDeduped,
+    /// Knob levels held back as dithering (see
+    /// [`WriteCoalescer`](crate::WriteCoalescer)).
+    WritesHeld,
+    /// Button presses and releases, both buttons.
+    ButtonTransitions,
+    /// A+B chords released after a long hold, so their action didn't run.
+    ChordAborts,
+    /// Console lines dropped (see the [`logging`](crate::logging) module).
+    LogDropped,
+    /// ADC calibrations and conversions that timed out.
+    AdcErrors,
+}
+
+/// Number of [`Stat`] counters.
+pub const STAT_COUNT: usize = Stat::ALL.len();
+
+impl Stat {
+    /// Every counter, in dump order.
+    pub const ALL: [Stat; 10] = [
+        Stat::LevelUpdates,
+        Stat::FrameRateChanges,
+        Stat::KnobReadings,
+        Stat::KnobClamped,
+        Stat::WritesDeduped,
+        Stat::WritesHeld,
+        Stat::ButtonTransitions,
+        Stat::ChordAborts,
+        Stat::LogDropped,
+        Stat::AdcErrors,
+    ];
+
+    /// Key the counter is dumped under.
+    pub fn name(self) -> &'static str {
+        match self {
+            Stat::LevelUpdates => "levels",
+            Stat::FrameRateChanges => "fps",
+            Stat::KnobReadings => "knob",
+            Stat::KnobClamped => "clamped",
+            Stat::WritesDeduped => "deduped",
+            Stat::WritesHeld => "held",
+            Stat::ButtonTransitions => "buttons",
+            Stat::ChordAborts => "chord_aborts",
+            Stat::LogDropped => "log_dropped",
+            Stat::AdcErrors => "adc_errors",
+        }
+    }
+}
+
+/// Counts one event.
+///
+/// `count_stat!(KnobReadings)` adds one to [`Stat::KnobReadings`] in
+/// [`STATS`].
+#[macro_export]
+macro_rules! count_stat {
+    ($stat:ident) => {
+        $crate::STATS.increment($crate::Stat::$stat)
+    };
+}
+
+/// A set of wrapping event counters.
+pub struct Stats {
+    counters: [AtomicU32; STAT_COUNT],
+}
+
+impl Stats {
+    /// Counters all at zero.
+    pub const fn new() -> Self {
+        Self {
+            counters: [const { AtomicU32::new(0) }; STAT_COUNT],
+        }
+    }
+
+    /// Counts one event.
+    pub fn increment(&self, stat: Stat) {
+        self.add(stat, 1);
+    }
+
+    /// Counts `count` events, wrapping.
+    pub fn add(&self, stat: Stat, count: u32) {
+        self.counters[stat as usize].fetch_add(count, Ordering::Relaxed);
+    }
+
+    /// Reads every counter.
+    pub fn snapshot(&self) -> StatsSnapshot {
+        StatsSnapshot {
+            counts: core::array::from_fn(|n| self.counters[n].load(Ordering::Relaxed)),
+        }
+    }
+
+    /// Reads and zeroes every counter, each in one atomic swap.
+    pub fn take(&self) -> StatsSnapshot {
+        StatsSnapshot {
+            counts: core::array::from_fn(|n| self.counters[n].swap(0, Ordering::Relaxed)),
+        }
+    }
+}
+
+impl Default for Stats {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Counters bumped by [`count_stat!`](crate::count_stat).
+pub static STATS: Stats = Stats::new();
+
+/// Counter values read by [`Stats::snapshot`] or [`Stats::take`].
+///
+/// Displays as `key=value` pairs in [`Stat::ALL`] order.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct StatsSnapshot {
+    counts: [u32; STAT_COUNT],
+}
+
+impl StatsSnapshot {
+    /// Value of one counter.
+    pub fn get(&self, stat: Stat) -> u32 {
+        self.counts[stat as usize]
+    }
+}
+
+impl core::fmt::Display for StatsSnapshot {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        for (n, stat) in Stat::ALL.iter().enumerate() {
+            if n > 0 {
+                f.write_str(" ")?;
+            }
+            write!(f, "{}={}", stat.name(), self.get(*stat))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counters_count_wrap_and_reset() {
+        static COUNTERS: Stats = Stats::new();
+        COUNTERS.increment(Stat::KnobReadings);
+        COUNTERS.increment(Stat::KnobReadings);
+        COUNTERS.increment(Stat::AdcErrors);
+        let dump = COUNTERS.snapshot();
+        assert_eq!(dump.get(Stat::KnobReadings), 2);
+        assert_eq!(dump.get(Stat::AdcErrors), 1);
+        assert_eq!(dump.get(Stat::ChordAborts), 0);
+
+        // Counters wrap rather than saturate.
+        COUNTERS.add(Stat::LogDropped, u32::MAX);
+        COUNTERS.increment(Stat::LogDropped);
+        assert_eq!(COUNTERS.snapshot().get(Stat::LogDropped), 0);
+
+        // take() resets: events before it are in its dump, events after it
+        // in the next, and none in both or neither.
+        assert_eq!(COUNTERS.take().get(Stat::KnobReadings), 2);
+        COUNTERS.increment(Stat::KnobReadings);
+        assert_eq!(COUNTERS.take().get(Stat::KnobReadings), 1);
+        assert_eq!(COUNTERS.snapshot(), StatsSnapshot::default());
+    }
+
+    #[test]
+    fn snapshot_displays_every_counter_in_order() {
+        let counters = Stats::new();
+        counters.add(Stat::KnobReadings, 120);
+        counters.increment(Stat::ButtonTransitions);
+        let line = LogLine::format(format_args!("{}", counters.snapshot()));
+        assert_eq!(
+            line.as_str(),
+            "levels=0 fps=0 knob=120 clamped=0 deduped=0 held=0 buttons=1 \
+             chord_aborts=0 log_dropped=0 adc_errors=0\n"
+        );
+    }
+}
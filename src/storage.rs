@@ -0,0 +1,428 @@
+//! # Storage Module
+//!
+//! This module defines [`StorageBackend`], the interface persistence
+//! features (settings, presets, calibration) save through, so they don't
+//! depend on where the bytes end up.
+//!
+//! ## Backends
+//!
+//! - [`FlashStorage`]: a page of internal flash that survives resets and
+//!   power loss
+//! - [`RamStorage`]: plain RAM, lost on reset; for builds without flash
+//!   (such as a host simulation) and for exercising persistence logic
+//!
+//! ## Flash Format
+//!
+//! [`FlashStorage`] starts its page with a header, and appends each save
+//! after it as a record, so most saves don't need an erase:
+//!
+//! ```text
+//! header: 'S' 'W' 0 0 | saves u32 | erases u32 | crc16 u32
+//! record: 'S' 'T' | length u16 | saves u32 | data, padded with 0xFF to whole words | crc16 u32
+//! ```
+//!
+//! Each CRC covers the fields between the magic and itself, and is written
+//! last, so a save torn by power loss is skipped and
+//! [`StorageBackend::load`] returns the last complete one. When the page is
+//! full, or has no valid header (as when new), it is erased and given a new
+//! header before writing; power loss during that erase loses the stored
+//! blob.
+//!
+//! ## Wear
+//!
+//! Each backend counts its saves and page erases in a [`WearCount`], to
+//! watch against the flash's rated endurance ([`FLASH_ENDURANCE`] erases
+//! per page). [`FlashStorage`] writes the counts into the page header
+//! right after each erase, the erase included, so they survive it even if
+//! no record is ever written after it; each record then carries the save
+//! count, one more than the record before. Only power loss during an erase
+//! or before its header is written, a few microseconds later, restarts
+//! the counts from zero.
+//! [`SETTINGS_STORAGE`] is the settings page; its counts are logged at
+//! boot and printed by the console's `STATS`.
+use crate::*;
+use core::cell::{Cell, RefCell};
+
+/// Largest blob a backend stores, in bytes.
+pub const STORAGE_BLOB_MAX: usize = 64;
+/// First half-word of every flash record, "ST".
+const STORAGE_MAGIC: u16 = 0x5453;
+/// First word of the flash page header, "SW" and two zero bytes.
+const STORAGE_PAGE_MAGIC: u32 = 0x0000_5753;
+/// Bytes of the flash page header, before the first record.
+const PAGE_HEADER_SIZE: u32 = 16;
+/// Rated erase cycles of a page of internal flash.
+pub const FLASH_ENDURANCE: u32 = 10_000;
+/// Bytes of a flash record covered by its CRC besides the data: the
+/// length and the save count.
+const RECORD_COVERED: usize = 2 + 4;
+
+/// Reasons a save failed.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StorageError {
+    /// The blob is longer than [`STORAGE_BLOB_MAX`].
+    TooLarge,
+}
+
+/// A stored blob of up to [`STORAGE_BLOB_MAX`] bytes.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct StorageBlob {
+    len: usize,
+    data: [u8; STORAGE_BLOB_MAX],
+}
+
+impl StorageBlob {
+    /// Copies bytes into a blob.
+    ///
+    /// # Returns
+    /// `None` if `bytes` is longer than [`STORAGE_BLOB_MAX`].
+    pub fn new(bytes: &[u8]) -> Option<Self> {
+        let mut data = [0; STORAGE_BLOB_MAX];
+        data.get_mut(..bytes.len())?.copy_from_slice(bytes);
+        Some(Self {
+            len: bytes.len(),
+            data,
+        })
+    }
+
+    /// Returns the stored bytes.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.data[..self.len]
+    }
+}
+
+/// Saves and erases a backend has performed, for watching flash wear.
+///
+/// Displays as `flash: 12 saves, 1 page erases (rated 10000)`.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct WearCount {
+    /// Successful saves.
+    pub saves: u32,
+    /// Page erases.
+    pub erases: u32,
+}
+
+impl core::fmt::Display for WearCount {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(
+            f,
+            "flash: {} saves, {} page erases (rated {})",
+            self.saves, self.erases, FLASH_ENDURANCE
+        )
+    }
+}
+
+/// Somewhere to keep one blob of saved state.
+///
+/// A save replaces the previous blob; a load returns the most recent
+/// complete save, or `None` if nothing was saved.
+pub trait StorageBackend {
+    /// Saves a blob, replacing the previous one.
+    fn save(&self, blob: &[u8]) -> Result<(), StorageError>;
+    /// Loads the last saved blob.
+    fn load(&self) -> Option<StorageBlob>;
+    /// Returns the saves and erases performed so far.
+    fn wear(&self) -> WearCount;
+}
+
+/// Storage in RAM, lost on reset.
+pub struct RamStorage {
+    blob: Cell<Option<StorageBlob>>,
+    wear: Cell<WearCount>,
+}
+
+impl RamStorage {
+    /// Creates empty storage.
+    pub const fn new() -> Self {
+        Self {
+            blob: Cell::new(None),
+            wear: Cell::new(WearCount {
+                saves: 0,
+                erases: 0,
+            }),
+        }
+    }
+}
+
+impl Default for RamStorage {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl StorageBackend for RamStorage {
+    fn save(&self, blob: &[u8]) -> Result<(), StorageError> {
+        self.blob
+            .set(Some(StorageBlob::new(blob).ok_or(StorageError::TooLarge)?));
+        let mut wear = self.wear.get();
+        wear.saves = wear.saves.wrapping_add(1);
+        self.wear.set(wear);
+        Ok(())
+    }
+
+    fn load(&self) -> Option<StorageBlob> {
+        self.blob.get()
+    }
+
+    fn wear(&self) -> WearCount {
+        self.wear.get()
+    }
+}
+
+/// Storage in one page of a [`FlashRegion`].
+///
+/// The wear counts go up by one per save, and are kept in the page header
+/// across an erase.
+pub struct FlashStorage<F: FlashRegion> {
+    flash: RefCell<F>,
+}
+
+/// Result of scanning the page.
+struct FlashScan {
+    /// Last complete record.
+    blob: Option<StorageBlob>,
+    /// Counts in the page header, with the saves of the last complete
+    /// record.
+    wear: WearCount,
+    /// Offset of the first free word, or `None` if the page holds data
+    /// that can't be parsed and must be erased before writing.
+    head: Option<u32>,
+}
+
+/// Flash bytes taken by a record with `len` data bytes.
+fn record_size(len: usize) -> u32 {
+    4 + 4 + (len as u32).next_multiple_of(4) + 4
+}
+
+/// CRC of a record's length, save count and data.
+fn record_crc(saves: u32, data: &[u8]) -> u32 {
+    let mut covered = [0; RECORD_COVERED + STORAGE_BLOB_MAX];
+    covered[..2].copy_from_slice(&(data.len() as u16).to_le_bytes());
+    covered[2..6].copy_from_slice(&saves.to_le_bytes());
+    covered[RECORD_COVERED..RECORD_COVERED + data.len()].copy_from_slice(data);
+    crc16(&covered[..RECORD_COVERED + data.len()]) as u32
+}
+
+/// CRC of the page header's counters.
+fn header_crc(wear: WearCount) -> u32 {
+    let mut covered = [0; 8];
+    covered[..4].copy_from_slice(&wear.saves.to_le_bytes());
+    covered[4..].copy_from_slice(&wear.erases.to_le_bytes());
+    crc16(&covered) as u32
+}
+
+impl<F: FlashRegion> FlashStorage<F> {
+    /// Creates storage on page 0 of `flash`.
+    pub const fn new(flash: F) -> Self {
+        Self {
+            flash: RefCell::new(flash),
+        }
+    }
+
+    /// Reads one word.
+    fn read_word(&self, offset: u32) -> u32 {
+        let mut buf = [0; 4];
+        self.flash.borrow().read(offset, &mut buf);
+        u32::from_le_bytes(buf)
+    }
+
+    /// Walks the records on the page.
+    fn scan(&self) -> FlashScan {
+        let mut scan = FlashScan {
+            blob: None,
+            wear: WearCount::default(),
+            head: None,
+        };
+        let header = WearCount {
+            saves: self.read_word(4),
+            erases: self.read_word(8),
+        };
+        if self.read_word(0) != STORAGE_PAGE_MAGIC || self.read_word(12) != header_crc(header) {
+            // Erased, torn, or foreign: erase it before writing.
+            return scan;
+        }
+        scan.wear = header;
+        let mut offset = PAGE_HEADER_SIZE;
+        while offset + 4 <= FLASH_PAGE_SIZE {
+            let header = self.read_word(offset);
+            if header == u32::MAX {
+                scan.head = Some(offset);
+                return scan;
+            }
+            let len = (header >> 16) as usize;
+            if header as u16 != STORAGE_MAGIC
+                || len > STORAGE_BLOB_MAX
+                || offset + record_size(len) > FLASH_PAGE_SIZE
+            {
+                return scan;
+            }
+            let saves = self.read_word(offset + 4);
+            let mut data = [0; STORAGE_BLOB_MAX];
+            self.flash.borrow().read(offset + 8, &mut data[..len]);
+            let crc = self.read_word(offset + record_size(len) - 4);
+            if crc == record_crc(saves, &data[..len]) {
+                scan.blob = StorageBlob::new(&data[..len]);
+                scan.wear.saves = saves;
+            }
+            offset += record_size(len);
+        }
+        scan.head = Some(FLASH_PAGE_SIZE);
+        scan
+    }
+}
+
+impl<F: FlashRegion> StorageBackend for FlashStorage<F> {
+    fn save(&self, blob: &[u8]) -> Result<(), StorageError> {
+        if blob.len() > STORAGE_BLOB_MAX {
+            return Err(StorageError::TooLarge);
+        }
+        let size = record_size(blob.len());
+        let scan = self.scan();
+        let mut wear = scan.wear;
+        let mut flash = self.flash.borrow_mut();
+        let mut offset = match scan.head {
+            Some(head) if head + size <= FLASH_PAGE_SIZE => head,
+            _ => {
+                flash.erase_page(0);
+                wear.erases = wear.erases.wrapping_add(1);
+                // The counts go in first, so the erase is never lost.
+                flash.write_word(0, STORAGE_PAGE_MAGIC);
+                flash.write_word(4, wear.saves);
+                flash.write_word(8, wear.erases);
+                flash.write_word(12, header_crc(wear));
+                PAGE_HEADER_SIZE
+            }
+        };
+        wear.saves = wear.saves.wrapping_add(1);
+        let len = blob.len() as u16;
+        flash.write_word(offset, STORAGE_MAGIC as u32 | (len as u32) << 16);
+        flash.write_word(offset + 4, wear.saves);
+        offset += 8;
+        for chunk in blob.chunks(4) {
+            let mut word = [0xFF; 4];
+            word[..chunk.len()].copy_from_slice(chunk);
+            flash.write_word(offset, u32::from_le_bytes(word));
+            offset += 4;
+        }
+        flash.write_word(offset, record_crc(wear.saves, blob));
+        Ok(())
+    }
+
+    fn load(&self) -> Option<StorageBlob> {
+        self.scan().blob
+    }
+
+    fn wear(&self) -> WearCount {
+        self.scan().wear
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ram_storage_keeps_the_last_blob() {
+        let storage = RamStorage::new();
+        assert_eq!(storage.load(), None);
+        storage.save(&[1, 2, 3]).unwrap();
+        assert_eq!(storage.load().unwrap().as_bytes(), &[1, 2, 3]);
+        // A save replaces the previous blob, even with a shorter one.
+        storage.save(&[9]).unwrap();
+        assert_eq!(storage.load().unwrap().as_bytes(), &[9]);
+        // A failed save leaves it alone.
+        assert_eq!(
+            storage.save(&[0; STORAGE_BLOB_MAX + 1]),
+            Err(StorageError::TooLarge)
+        );
+        assert_eq!(storage.load().unwrap().as_bytes(), &[9]);
+        // Every save counts once, a failed one not at all; RAM has no pages
+        // to erase.
+        assert_eq!(
+            storage.wear(),
+            WearCount {
+                saves: 2,
+                erases: 0
+            }
+        );
+    }
+
+    /// The settings page in RAM.
+    type RamPage = RamFlash<1>;
+
+    #[test]
+    fn flash_storage_counts_saves_and_erases() {
+        let storage = FlashStorage::new(RamPage::new());
+        assert_eq!(storage.wear(), WearCount::default());
+        // A new page has no header, so the first save erases it.
+        for saves in 1..=3 {
+            storage.save(&[saves as u8; 8]).unwrap();
+            assert_eq!(storage.wear(), WearCount { saves, erases: 1 });
+        }
+        // 20-byte records: 204 fit after the header, and the 205th erases
+        // the page first.
+        for saves in 4..=204 {
+            storage.save(&[saves as u8; 8]).unwrap();
+        }
+        assert_eq!(
+            storage.wear(),
+            WearCount {
+                saves: 204,
+                erases: 1
+            }
+        );
+        storage.save(&[205; 8]).unwrap();
+        assert_eq!(
+            storage.wear(),
+            WearCount {
+                saves: 205,
+                erases: 2
+            }
+        );
+        assert_eq!(storage.load().unwrap().as_bytes(), &[205; 8]);
+        // Rejected saves don't count.
+        assert_eq!(
+            storage.save(&[0; STORAGE_BLOB_MAX + 1]),
+            Err(StorageError::TooLarge)
+        );
+        assert_eq!(storage.wear().saves, 205);
+    }
+
+    #[test]
+    fn flash_wear_survives_a_save_torn_after_the_erase() {
+        let storage = FlashStorage::new(RamPage::new());
+        for saves in 1..=204 {
+            storage.save(&[saves as u8; 8]).unwrap();
+        }
+        assert_eq!(
+            storage.wear(),
+            WearCount {
+                saves: 204,
+                erases: 1
+            }
+        );
+        // Power fails right after the next erase's header: the blob is
+        // gone, but the counts, that erase included, are not.
+        storage.flash.borrow_mut().writes_left = 4;
+        storage.save(&[205; 8]).unwrap();
+        assert_eq!(storage.load(), None);
+        assert_eq!(
+            storage.wear(),
+            WearCount {
+                saves: 204,
+                erases: 2
+            }
+        );
+        // The next save goes on from them, with no further erase.
+        storage.flash.borrow_mut().writes_left = u32::MAX;
+        storage.save(&[206; 8]).unwrap();
+        assert_eq!(
+            storage.wear(),
+            WearCount {
+                saves: 205,
+                erases: 2
+            }
+        );
+        assert_eq!(storage.load().unwrap().as_bytes(), &[206; 8]);
+    }
+}
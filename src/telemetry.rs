@@ -0,0 +1,236 @@
+//! # Telemetry Module
+//!
+//! This module streams compact binary records on RTT up channel 2
+//! ("Telemetry") for live plotting on the host while tuning: the raw and
+//! filtered knob readings, the levels, and the last frame's duration.
+//!
+//! ## Record Format
+//!
+//! Each record is [`TELEMETRY_RECORD_LEN`] bytes, multi-byte fields little
+//! endian:
+//!
+//! | Offset | Size | Field                                            |
+//! |--------|------|--------------------------------------------------|
+//! | 0      | 1    | Sync byte [`TELEMETRY_SYNC`] (`0xA5`)            |
+//! | 1      | 1    | Sequence number, wrapping; gaps mean lost records |
+//! | 2      | 4    | Timestamp, milliseconds since boot               |
+//! | 6      | 2    | Raw knob SAADC reading (i16)                     |
+//! | 8      | 2    | Filtered knob reading (i16)                      |
+//! | 10     | 3    | Red, green, blue levels                          |
+//! | 13     | 4    | Last frame duration in microseconds              |
+//! | 17     | 1    | Checksum: wrapping sum of bytes 1 through 16     |
+//!
+//! ## Decoding
+//!
+//! [`TelemetryDecoder`] is the reference decoder: feed it the byte stream
+//! and it yields records. After lost or corrupted bytes it slides forward
+//! one byte at a time to the next sync byte whose record checksums, so it
+//! resynchronizes within one record of clean data.
+//!
+//! The levels come from the [`events`](crate::events) bus rather than a
+//! mutex read per record.
+//!
+//! ## Filtering
+//!
+//! The filtered knob value is an exponential moving average of the raw
+//! readings, `filtered += (raw - filtered) / 2^TELEMETRY_FILTER_SHIFT`,
+//! updated every time the knob is sampled.
+use crate::*;
+
+/// First byte of every record.
+pub const TELEMETRY_SYNC: u8 = 0xA5;
+/// Length of one record in bytes.
+pub const TELEMETRY_RECORD_LEN: usize = 18;
+/// Highest record rate, in Hz.
+pub const TELEMETRY_RATE_MAX: u32 = 200;
+/// Record rate used until changed, in Hz.
+const TELEMETRY_RATE_DEFAULT: u32 = 50;
+/// Smoothing of the filtered knob reading; larger is smoother.
+const TELEMETRY_FILTER_SHIFT: u32 = 3;
+
+/// One telemetry sample.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TelemetryRecord {
+    /// Wrapping record counter.
+    pub sequence: u8,
+    /// Milliseconds since boot, wrapping.
+    pub timestamp_ms: u32,
+    /// Last raw knob reading.
+    pub raw_knob: i16,
+    /// Filtered knob reading.
+    pub filtered_knob: i16,
+    /// RGB levels.
+    pub levels: [u8; 3],
+    /// Duration of the last completed frame, in microseconds.
+    pub frame_us: u32,
+}
+
+/// Computes a record's checksum over bytes 1 through 16.
+fn telemetry_checksum(bytes: &[u8]) -> u8 {
+    bytes[1..TELEMETRY_RECORD_LEN - 1]
+        .iter()
+        .fold(0u8, |sum, &byte| sum.wrapping_add(byte))
+}
+
+impl TelemetryRecord {
+    /// Encodes the record in the wire format.
+    pub fn encode(&self) -> [u8; TELEMETRY_RECORD_LEN] {
+        let mut bytes = [0u8; TELEMETRY_RECORD_LEN];
+        bytes[0] = TELEMETRY_SYNC;
+        bytes[1] = self.sequence;
+        bytes[2..6].copy_from_slice(&self.timestamp_ms.to_le_bytes());
+        bytes[6..8].copy_from_slice(&self.raw_knob.to_le_bytes());
+        bytes[8..10].copy_from_slice(&self.filtered_knob.to_le_bytes());
+        bytes[10..13].copy_from_slice(&self.levels);
+        bytes[13..17].copy_from_slice(&self.frame_us.to_le_bytes());
+        bytes[17] = telemetry_checksum(&bytes);
+        bytes
+    }
+
+    /// Decodes a record, checking the sync byte and checksum.
+    pub fn decode(bytes: &[u8; TELEMETRY_RECORD_LEN]) -> Option<Self> {
+        if bytes[0] != TELEMETRY_SYNC || bytes[17] != telemetry_checksum(bytes) {
+            return None;
+        }
+        let u32_at =
+            |i: usize| u32::from_le_bytes([bytes[i], bytes[i + 1], bytes[i + 2], bytes[i + 3]]);
+        let i16_at = |i: usize| i16::from_le_bytes([bytes[i], bytes[i + 1]]);
+        Some(Self {
+            sequence: bytes[1],
+            timestamp_ms: u32_at(2),
+            raw_knob: i16_at(6),
+            filtered_knob: i16_at(8),
+            levels: [bytes[10], bytes[11], bytes[12]],
+            frame_us: u32_at(13),
+        })
+    }
+}
+
+/// Streaming decoder for telemetry records with resynchronization.
+#[derive(Debug, Clone)]
+pub struct TelemetryDecoder {
+    buf: [u8; TELEMETRY_RECORD_LEN],
+    len: usize,
+}
+
+impl TelemetryDecoder {
+    /// Creates a decoder waiting for a sync byte.
+    pub const fn new() -> Self {
+        Self {
+            buf: [0; TELEMETRY_RECORD_LEN],
+            len: 0,
+        }
+    }
+
+    /// Feeds one byte from the stream.
+    ///
+    /// # Returns
+    /// A record when this byte completes one that checksums.
+    pub fn push(&mut self, byte: u8) -> Option<TelemetryRecord> {
+        if self.len == 0 && byte != TELEMETRY_SYNC {
+            return None;
+        }
+        self.buf[self.len] = byte;
+        self.len += 1;
+        if self.len < TELEMETRY_RECORD_LEN {
+            return None;
+        }
+        if let Some(record) = TelemetryRecord::decode(&self.buf) {
+            self.len = 0;
+            return Some(record);
+        }
+        // Bad record: slide to the next candidate sync byte and keep the
+        // bytes after it, which may be the start of a good record.
+        let next = self.buf[1..]
+            .iter()
+            .position(|&b| b == TELEMETRY_SYNC)
+            .map_or(TELEMETRY_RECORD_LEN, |i| i + 1);
+        self.buf.copy_within(next.., 0);
+        self.len = TELEMETRY_RECORD_LEN - next;
+        None
+    }
+}
+
+impl Default for TelemetryDecoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Shared telemetry settings and the inputs sampled into records.
+#[derive(Debug, Clone, Copy)]
+pub struct Telemetry {
+    /// Whether records are being sent.
+    pub enabled: bool,
+    /// Record rate in Hz, 1 to [`TELEMETRY_RATE_MAX`].
+    pub rate_hz: u32,
+    /// Last raw knob reading.
+    pub raw_knob: i16,
+    /// Filtered knob reading, `None` until the first sample.
+    pub filtered_knob: Option<i16>,
+    /// Duration of the last completed frame, in microseconds.
+    pub frame_us: u32,
+}
+
+/// Global telemetry state.
+pub static TELEMETRY: Mutex<CriticalSectionRawMutex, Telemetry> = Mutex::new(Telemetry {
+    enabled: false,
+    rate_hz: TELEMETRY_RATE_DEFAULT,
+    raw_knob: 0,
+    filtered_knob: None,
+    frame_us: 0,
+});
+
+/// Records a raw knob reading and updates the filtered value.
+pub async fn record_knob_sample(raw: i16) {
+    let mut telemetry = TELEMETRY.lock().await;
+    telemetry.raw_knob = raw;
+    let filtered = telemetry.filtered_knob.unwrap_or(raw) as i32;
+    let filtered = filtered + ((raw as i32 - filtered) >> TELEMETRY_FILTER_SHIFT);
+    telemetry.filtered_knob = Some(filtered as i16);
+}
+
+/// Records the duration of the frame just completed.
+pub async fn record_frame_time(frame_us: u32) {
+    TELEMETRY.lock().await.frame_us = frame_us;
+}
+
+/// Enables or disables the stream, optionally changing the rate.
+///
+/// # Returns
+/// `false` (changing nothing) if `rate_hz` is outside
+/// 1..=[`TELEMETRY_RATE_MAX`].
+pub async fn set_telemetry(enabled: bool, rate_hz: Option<u32>) -> bool {
+    let mut telemetry = TELEMETRY.lock().await;
+    if let Some(rate_hz) = rate_hz {
+        if !(1..=TELEMETRY_RATE_MAX).contains(&rate_hz) {
+            return false;
+        }
+        telemetry.rate_hz = rate_hz;
+    }
+    telemetry.enabled = enabled;
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn telemetry_decoder_skips_stray_bytes() {
+        let record = TelemetryRecord {
+            sequence: 7,
+            timestamp_ms: 123_456,
+            raw_knob: 2048,
+            filtered_knob: -3,
+            levels: [15, 0, 8],
+            frame_us: 16_667,
+        };
+        let mut decoder = TelemetryDecoder::new();
+        let bytes = record.encode();
+        // A stray byte before the record is skipped.
+        assert_eq!(decoder.push(0x00), None);
+        let decoded = bytes.iter().filter_map(|&b| decoder.push(b)).next();
+        assert_eq!(decoded, Some(record));
+    }
+}
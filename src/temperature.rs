@@ -0,0 +1,168 @@
+//! # Temperature Display Module
+//!
+//! This module provides a standalone demo mode that shows the chip
+//! temperature as a color: fully blue at or below a low threshold, fully red
+//! at or above a high threshold, and a linear blend in between.
+//!
+//! ## Coexistence with Calibration
+//!
+//! Entering the mode saves the current RGB levels and leaving restores them,
+//! so a calibration in progress survives the demo. While the mode is active
+//! the gradient owns the RGB levels; knob color edits are overwritten at the
+//! next temperature update.
+//!
+//! ## Gradient
+//!
+//! The nRF52 TEMP peripheral reports quarter degrees Celsius, and the
+//! gradient is interpolated at that resolution in integer math:
+//!
+//! ```text
+//! red  = (LEVELS - 1) * (t - low) / (high - low)
+//! blue = (LEVELS - 1) - red
+//! ```
+//!
+//! With no band between the thresholds the gradient is a step at the low
+//! one.
+use crate::*;
+
+/// Default low threshold in °C (fully blue at or below).
+pub const TEMP_LOW_DEFAULT: i32 = 20;
+/// Default high threshold in °C (fully red at or above).
+pub const TEMP_HIGH_DEFAULT: i32 = 30;
+/// Delay between temperature readings, in milliseconds.
+pub const TEMP_PERIOD_MS: u64 = 1000;
+
+/// Shared temperature-mode state.
+#[derive(Debug, Clone, Copy)]
+pub struct TempMode {
+    /// Low threshold in °C.
+    pub low: i32,
+    /// High threshold in °C.
+    pub high: i32,
+    /// Hands the RGB levels to the temperature gradient, saving the user's color.
+    overlay: LevelsOverlay,
+}
+
+impl TempMode {
+    /// Whether the temperature gradient currently owns the RGB levels.
+    pub fn enabled(&self) -> bool {
+        self.overlay.active()
+    }
+}
+
+/// Global temperature-mode state, changed by the console.
+pub static TEMP_MODE: Mutex<CriticalSectionRawMutex, TempMode> = Mutex::new(TempMode {
+    low: TEMP_LOW_DEFAULT,
+    high: TEMP_HIGH_DEFAULT,
+    overlay: LevelsOverlay::OFF,
+});
+
+/// Maps a temperature to RGB levels on the blue-to-red gradient.
+///
+/// # Arguments
+/// * `quarter_degrees` - Temperature in units of 0.25 °C
+/// * `low` - Low threshold in °C
+/// * `high` - High threshold in °C; at or below `low`, the gradient is a
+///   step from blue to red at `low`
+///
+/// # Returns
+/// RGB levels [red, green, blue] with green always off.
+pub fn temperature_levels(quarter_degrees: i32, low: i32, high: i32) -> [u32; 3] {
+    let low = low * 4;
+    let high = high * 4;
+    let red = if high <= low {
+        if quarter_degrees < low {
+            0
+        } else {
+            LEVELS - 1
+        }
+    } else {
+        let t = quarter_degrees.clamp(low, high);
+        ((LEVELS - 1) as i32 * (t - low) / (high - low)) as u32
+    };
+    [red, 0, LEVELS - 1 - red]
+}
+
+/// Enters or leaves temperature mode.
+///
+/// Entering saves the current RGB levels; leaving restores them
+/// (see [`LevelsOverlay`]).
+pub async fn set_temperature_mode(enabled: bool) {
+    let mut mode = TEMP_MODE.lock().await;
+    if !mode.overlay.set(enabled).await {
+        return;
+    }
+    if enabled {
+        rprintln!("Temperature mode: on ({}..{} C)", mode.low, mode.high);
+    } else {
+        rprintln!("Temperature mode: off");
+    }
+}
+
+/// Updates one gradient threshold.
+///
+/// # Arguments
+/// * `high` - `true` to set the high threshold, `false` for the low one
+/// * `celsius` - New threshold in °C
+///
+/// # Returns
+/// `false` (leaving the thresholds unchanged) if the result would not keep
+/// the low threshold strictly below the high one.
+pub async fn set_temperature_threshold(high: bool, celsius: i32) -> bool {
+    let mut mode = TEMP_MODE.lock().await;
+    let (low, high_value) = if high {
+        (mode.low, celsius)
+    } else {
+        (celsius, mode.high)
+    };
+    if low >= high_value {
+        return false;
+    }
+    mode.low = low;
+    mode.high = high_value;
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BLUE: [u32; 3] = [0, 0, LEVELS - 1];
+    const RED: [u32; 3] = [LEVELS - 1, 0, 0];
+
+    #[test]
+    fn gradient_ends_at_the_thresholds() {
+        // 20 °C is 80 quarter degrees, 30 °C is 120.
+        for quarter_degrees in [-400, 0, 79, 80] {
+            assert_eq!(temperature_levels(quarter_degrees, 20, 30), BLUE);
+        }
+        for quarter_degrees in [120, 121, 400] {
+            assert_eq!(temperature_levels(quarter_degrees, 20, 30), RED);
+        }
+    }
+
+    #[test]
+    fn gradient_interpolates_between_the_thresholds() {
+        // A level every 40/15 quarter degrees, rounding down.
+        assert_eq!(temperature_levels(82, 20, 30), BLUE);
+        assert_eq!(temperature_levels(83, 20, 30), [1, 0, 14]);
+        assert_eq!(temperature_levels(100, 20, 30), [7, 0, 8]);
+        assert_eq!(temperature_levels(119, 20, 30), [14, 0, 1]);
+        let mut previous = BLUE;
+        for quarter_degrees in 80..=120 {
+            let levels = temperature_levels(quarter_degrees, 20, 30);
+            assert_eq!(levels[0] + levels[2], LEVELS - 1);
+            assert!(levels[0] >= previous[0], "{}", quarter_degrees);
+            previous = levels;
+        }
+    }
+
+    #[test]
+    fn equal_thresholds_step_at_the_low_one() {
+        assert_eq!(temperature_levels(99, 25, 25), BLUE);
+        assert_eq!(temperature_levels(100, 25, 25), RED);
+        // Reversed thresholds step at the low one as well.
+        assert_eq!(temperature_levels(119, 30, 20), BLUE);
+        assert_eq!(temperature_levels(120, 30, 20), RED);
+    }
+}
@@ -0,0 +1,121 @@
+//! # Test Pattern Module
+//!
+//! This module cycles the LED through a fixed list of reference colors,
+//! logging each one's label over RTT, so the full output pipeline can be
+//! checked with a colorimeter.
+//!
+//! ## Pattern
+//!
+//! [`TEST_PATTERN`] holds the primaries, the secondaries, and greys at
+//! several levels from full white down to black, each shown for a
+//! configurable dwell (default [`PATTERN_DWELL_DEFAULT_S`] seconds). A
+//! build-time check keeps the list covering that whole reference set.
+//!
+//! ## Coexistence with Calibration
+//!
+//! Starting the pattern saves the current RGB levels and stopping restores
+//! them. While running, the pattern owns the RGB levels.
+use crate::*;
+
+/// Default time each reference color is shown, in seconds.
+pub const PATTERN_DWELL_DEFAULT_S: u32 = 5;
+
+/// Top level, used for the primaries and secondaries.
+const TOP: u32 = LEVELS - 1;
+
+/// Reference colors in display order, as `(label, [red, green, blue])`.
+pub const TEST_PATTERN: [(&str, [u32; 3]); 11] = [
+    ("red", [TOP, 0, 0]),
+    ("green", [0, TOP, 0]),
+    ("blue", [0, 0, TOP]),
+    ("cyan", [0, TOP, TOP]),
+    ("magenta", [TOP, 0, TOP]),
+    ("yellow", [TOP, TOP, 0]),
+    ("white", [TOP, TOP, TOP]),
+    ("grey 75%", [11, 11, 11]),
+    ("grey 50%", [8, 8, 8]),
+    ("grey 25%", [4, 4, 4]),
+    ("black", [0, 0, 0]),
+];
+
+/// Whether [`TEST_PATTERN`] contains a color.
+const fn pattern_contains(levels: [u32; 3]) -> bool {
+    let mut i = 0;
+    while i < TEST_PATTERN.len() {
+        let entry = TEST_PATTERN[i].1;
+        if entry[0] == levels[0] && entry[1] == levels[1] && entry[2] == levels[2] {
+            return true;
+        }
+        i += 1;
+    }
+    false
+}
+
+/// Whether [`TEST_PATTERN`] covers the reference set: every primary and
+/// secondary at full level, white, black, and at least three intermediate
+/// greys.
+const fn pattern_covers_reference_set() -> bool {
+    let mut mask = 1;
+    while mask < 8 {
+        let red = if mask & 1 != 0 { TOP } else { 0 };
+        let green = if mask & 2 != 0 { TOP } else { 0 };
+        let blue = if mask & 4 != 0 { TOP } else { 0 };
+        if !pattern_contains([red, green, blue]) {
+            return false;
+        }
+        mask += 1;
+    }
+    let mut greys = 0;
+    let mut i = 0;
+    while i < TEST_PATTERN.len() {
+        let [r, g, b] = TEST_PATTERN[i].1;
+        if r == g && g == b && r > 0 && r < TOP {
+            greys += 1;
+        }
+        i += 1;
+    }
+    pattern_contains([0, 0, 0]) && greys >= 3
+}
+
+const _: () = assert!(
+    pattern_covers_reference_set(),
+    "TEST_PATTERN must cover the reference set"
+);
+
+/// Shared test-pattern state.
+#[derive(Debug, Clone, Copy)]
+pub struct PatternMode {
+    /// Time each color is shown, in seconds.
+    pub dwell_s: u32,
+    /// Hands the RGB levels to the pattern, saving the user's color.
+    overlay: LevelsOverlay,
+}
+
+impl PatternMode {
+    /// Whether the pattern currently owns the RGB levels.
+    pub fn enabled(&self) -> bool {
+        self.overlay.active()
+    }
+}
+
+/// Global test-pattern state, changed by the console.
+pub static PATTERN_MODE: Mutex<CriticalSectionRawMutex, PatternMode> = Mutex::new(PatternMode {
+    dwell_s: PATTERN_DWELL_DEFAULT_S,
+    overlay: LevelsOverlay::OFF,
+});
+
+/// Starts or stops the test pattern.
+///
+/// Starting saves the current RGB levels; stopping restores them
+/// (see [`LevelsOverlay`]).
+pub async fn set_pattern_mode(enabled: bool) {
+    let mut mode = PATTERN_MODE.lock().await;
+    if !mode.overlay.set(enabled).await {
+        return;
+    }
+    if enabled {
+        rprintln!("Test pattern: on ({} s per color)", mode.dwell_s);
+    } else {
+        rprintln!("Test pattern: off");
+    }
+}
@@ -0,0 +1,185 @@
+//! # Timer Calibration Module
+//!
+//! This module measures the software PWM's timer overhead at boot, so the
+//! frame loop can wake early by that much and land its edges on time.
+//!
+//! ## Overhead
+//!
+//! A timer wait resolves some time after its deadline: the alarm
+//! interrupt, the executor waking the RGB task, and the task polling its
+//! future again all take time. The frame loop measures deadlines from the
+//! frame start, so this doesn't pile up within a frame, but every edge is
+//! late by it and so is the frame end, which is where the next frame
+//! starts: each frame runs long by one wake-up, a share of the period that
+//! grows with the frame rate.
+//!
+//! ## Calibration
+//!
+//! [`calibrate_timer_overhead`] arms [`TIMER_CAL_SAMPLES`] waits of
+//! [`TIMER_CAL_WAIT_US`] and times how late each one wakes against
+//! [`Instant`]. [`TimerCalibration`] takes the median, so an interrupt
+//! landing on one sample doesn't skew it, and caps it at
+//! [`TIMER_CAL_MAX_US`], beyond which the measurement is more likely broken
+//! than the timer. [`Rgb::with_timer_overhead`] then has every frame
+//! deadline brought forward by it (see [`compensated_deadline`]). The
+//! on-time report at [`Verbosity::Debug`] includes the achieved frame
+//! rate, to check the result.
+use crate::*;
+
+/// Number of waits timed by [`calibrate_timer_overhead`].
+pub const TIMER_CAL_SAMPLES: usize = 16;
+/// Length of each calibration wait, in µs.
+pub const TIMER_CAL_WAIT_US: u64 = 200;
+/// Largest overhead compensated, in µs; a longer measured one is capped.
+pub const TIMER_CAL_MAX_US: u64 = 100;
+
+/// Median of timer wake-up latencies.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TimerCalibration {
+    /// Latencies measured so far, in timer ticks.
+    late: [u64; TIMER_CAL_SAMPLES],
+    /// Number of latencies measured.
+    count: usize,
+}
+
+impl TimerCalibration {
+    /// Starts with no measurements.
+    pub const fn new() -> Self {
+        Self {
+            late: [0; TIMER_CAL_SAMPLES],
+            count: 0,
+        }
+    }
+
+    /// Adds one wait's latency; past [`TIMER_CAL_SAMPLES`] it is ignored.
+    ///
+    /// # Arguments
+    /// * `late` - Ticks from the deadline to the wake-up
+    pub fn push(&mut self, late: u64) {
+        if let Some(slot) = self.late.get_mut(self.count) {
+            *slot = late;
+            self.count += 1;
+        }
+    }
+
+    /// Per-wait overhead to compensate for.
+    ///
+    /// # Returns
+    /// The median latency in timer ticks (the upper one of an even count),
+    /// at most [`TIMER_CAL_MAX_US`]; 0 with nothing measured.
+    pub fn overhead(&self) -> u64 {
+        let mut late = self.late;
+        let late = &mut late[..self.count];
+        late.sort_unstable();
+        let median = late.get(late.len() / 2).copied().unwrap_or(0);
+        median.min(Duration::from_micros(TIMER_CAL_MAX_US).as_ticks())
+    }
+}
+
+impl Default for TimerCalibration {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Brings a frame deadline forward by the timer overhead, never before the
+/// frame start.
+///
+/// Every deadline moves by the same amount (or to 0), so the order of a
+/// frame's waits is kept.
+///
+/// # Arguments
+/// * `at` - Deadline in ticks from the frame start
+/// * `overhead` - Per-wait overhead in ticks, from [`TimerCalibration`]
+///
+/// # Returns
+/// The deadline to arm the timer for.
+pub fn compensated_deadline(at: u64, overhead: u64) -> u64 {
+    at.saturating_sub(overhead)
+}
+
+/// Measures the timer's wake-up overhead.
+///
+/// Takes about [`TIMER_CAL_SAMPLES`] × [`TIMER_CAL_WAIT_US`] (3 ms), and
+/// should run before the frame loop starts, with little else going on.
+///
+/// # Returns
+/// The per-wait overhead in timer ticks, as [`TimerCalibration::overhead`].
+pub async fn calibrate_timer_overhead() -> u64 {
+    let mut calibration = TimerCalibration::new();
+    for _ in 0..TIMER_CAL_SAMPLES {
+        let deadline = Instant::now() + Duration::from_micros(TIMER_CAL_WAIT_US);
+        Timer::at(deadline).await;
+        calibration.push(
+            Instant::now()
+                .saturating_duration_since(deadline)
+                .as_ticks(),
+        );
+    }
+    let overhead = calibration.overhead();
+    rprintln!(
+        "timer: {} us wake-up overhead, compensated",
+        Duration::from_ticks(overhead).as_micros()
+    );
+    overhead
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn timer_calibration_takes_the_capped_median() {
+        // Wake-ups mostly a tick late, with two disturbed by interrupts.
+        let mut calibration = TimerCalibration::new();
+        for late in [1, 1, 2, 1, 40, 1, 0, 1, 1, 2, 1, 90, 1, 1, 0, 1] {
+            calibration.push(late);
+        }
+        assert_eq!(calibration.overhead(), 1);
+
+        // Nothing measured is no overhead, and a wild measurement is capped.
+        assert_eq!(TimerCalibration::new().overhead(), 0);
+        let mut broken = TimerCalibration::new();
+        broken.push(1_000_000);
+        assert_eq!(
+            broken.overhead(),
+            Duration::from_micros(TIMER_CAL_MAX_US).as_ticks()
+        );
+    }
+
+    #[test]
+    fn compensated_deadline_lands_edges_on_time() {
+        assert_eq!(compensated_deadline(1000, 30), 970);
+        assert_eq!(compensated_deadline(20, 30), 0);
+        assert_eq!(compensated_deadline(1000, 0), 1000);
+
+        // Model a timer that wakes about 30 us late (a past-due wait returns
+        // at once) and play one 160 fps frame with and without compensation.
+        let late = Duration::from_micros(30).as_ticks().max(1);
+        let timing = RgbTiming::for_frame_rate(FRAME_RATE_MAX);
+        let full = BRIGHTNESS_MAX;
+        let schedule = FrameSchedule::new(&timing, [8, 4, 15], full, full, 1);
+        let play = |overhead: u64| {
+            let mut now = 0;
+            let mut edges_late = 0;
+            for step in schedule.steps([0; 3]) {
+                match step {
+                    FrameStep::Wait(at) => {
+                        let deadline = compensated_deadline(at, overhead);
+                        if deadline > now {
+                            now = deadline + late;
+                        }
+                    }
+                    FrameStep::Pin { at, .. } => {
+                        edges_late = edges_late.max(now.saturating_sub(at))
+                    }
+                }
+            }
+            (now, edges_late)
+        };
+        // Uncompensated, every frame runs one wake-up long...
+        assert_eq!(play(0), (timing.frame_ticks + late, late));
+        // ...and compensated it is exact, with no edge late.
+        assert_eq!(play(late), (timing.frame_ticks, 0));
+    }
+}
@@ -0,0 +1,147 @@
+//! # Tone Feedback Module
+//!
+//! This module plays a short tone on the micro:bit v2 speaker whenever a
+//! color level changes, with a pitch that rises with the level, so a channel
+//! can be calibrated by ear.
+//!
+//! ## Pitch Mapping
+//!
+//! Each level maps to one step of a major pentatonic scale starting at
+//! middle C ([`TONE_TABLE`]), so every level is distinct and any sequence
+//! of levels sounds consonant.
+//!
+//! ## Scheduling
+//!
+//! The UI never waits for audio: [`play_level`] drops the level into a small
+//! bounded queue and returns, discarding it if the queue is full. The tone
+//! task drains everything queued while the previous tone played and plays
+//! it as one quick sweep (see [`schedule_notes`]), so fast knob turns come
+//! out as a glissando instead of a backlog of notes.
+use crate::*;
+use embassy_sync::channel::Channel;
+
+/// Maximum number of level changes waiting to be played.
+pub const TONE_QUEUE_DEPTH: usize = 8;
+/// Maximum number of notes in one coalesced sweep.
+pub const TONE_SWEEP_MAX: usize = 4;
+/// Duration of a single, uncoalesced tone in milliseconds.
+pub const TONE_NOTE_MS: u64 = 80;
+/// Total duration of a coalesced sweep in milliseconds.
+pub const TONE_SWEEP_MS: u64 = 120;
+
+/// Tone frequency in Hz for each level, a major pentatonic scale from C4.
+pub const TONE_TABLE: [u32; LEVELS as usize] = [
+    262, 294, 330, 392, 440, 523, 587, 659, 784, 880, 1047, 1175, 1319, 1568, 1760, 2093,
+];
+
+/// Level changes waiting to be played by the tone task.
+pub static TONE_QUEUE: Channel<CriticalSectionRawMutex, u32, TONE_QUEUE_DEPTH> = Channel::new();
+/// Whether tone feedback is muted.
+pub static TONE_MUTED: Mutex<CriticalSectionRawMutex, bool> = Mutex::new(false);
+
+/// Returns the tone frequency for a level.
+///
+/// # Arguments
+/// * `level` - Color level; values above [`LEVELS`]-1 use the top note
+pub fn level_frequency(level: u32) -> u32 {
+    TONE_TABLE[level.min(LEVELS - 1) as usize]
+}
+
+/// Queues a tone for a level without waiting.
+///
+/// If [`TONE_QUEUE_DEPTH`] tones are already waiting the level is dropped.
+pub fn play_level(level: u32) {
+    let _ = TONE_QUEUE.try_send(level);
+}
+
+/// Coalesces pending level changes into the notes of one sweep.
+///
+/// Consecutive repeats are merged. If more than `notes.len()` distinct steps
+/// remain, they are thinned evenly, always keeping the first and the last so
+/// the sweep starts where the change started and ends on the final level
+/// (a single-note buffer keeps just the final level).
+///
+/// # Arguments
+/// * `pending` - Levels in the order they were queued
+/// * `notes` - Output buffer for the levels to play
+///
+/// # Returns
+/// Number of notes written to `notes`.
+pub fn schedule_notes(pending: &[u32], notes: &mut [u32]) -> usize {
+    let mut distinct = [0u32; TONE_QUEUE_DEPTH];
+    let mut count = 0;
+    for &level in pending.iter().take(TONE_QUEUE_DEPTH) {
+        if count == 0 || distinct[count - 1] != level {
+            distinct[count] = level;
+            count += 1;
+        }
+    }
+    if count <= notes.len() {
+        notes[..count].copy_from_slice(&distinct[..count]);
+        return count;
+    }
+    match notes.len() {
+        0 => 0,
+        1 => {
+            notes[0] = distinct[count - 1];
+            1
+        }
+        len => {
+            for (i, note) in notes.iter_mut().enumerate() {
+                *note = distinct[i * (count - 1) / (len - 1)];
+            }
+            len
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn levels_map_to_rising_pentatonic_notes() {
+        assert_eq!(level_frequency(0), 262);
+        assert_eq!(level_frequency(LEVELS - 1), 2093);
+        for level in 1..LEVELS {
+            assert!(level_frequency(level) > level_frequency(level - 1));
+        }
+        // Five notes to the octave: each step is an octave above the one
+        // five below, to within rounding.
+        for level in 5..LEVELS {
+            let octave = 2 * level_frequency(level - 5);
+            assert!(level_frequency(level).abs_diff(octave) <= 1, "{}", level);
+        }
+        // Out-of-range levels play the top note.
+        assert_eq!(level_frequency(LEVELS), 2093);
+        assert_eq!(level_frequency(u32::MAX), 2093);
+    }
+
+    #[test]
+    fn rapid_changes_coalesce_into_one_sweep() {
+        let mut notes = [0; TONE_SWEEP_MAX];
+        let mut schedule = |pending: &[u32]| {
+            let count = schedule_notes(pending, &mut notes);
+            notes[..count].to_vec()
+        };
+        assert_eq!(schedule(&[]), []);
+        assert_eq!(schedule(&[5]), [5]);
+        // Repeats merge.
+        assert_eq!(schedule(&[3, 3, 3, 4, 4]), [3, 4]);
+        assert_eq!(schedule(&[1, 2, 3, 4]), [1, 2, 3, 4]);
+        // A fast turn is thinned evenly, keeping where it started and
+        // where it stopped.
+        assert_eq!(schedule(&[0, 1, 2, 3, 4, 5, 6, 7]), [0, 2, 4, 7]);
+        assert_eq!(schedule(&[9, 8, 8, 7, 6, 5, 4]), [9, 8, 6, 4]);
+        // Only a full queue's worth is considered.
+        assert_eq!(schedule(&[0, 1, 2, 3, 4, 5, 6, 7, 8, 9]), [0, 2, 4, 7]);
+    }
+
+    #[test]
+    fn short_buffers_keep_the_final_level() {
+        let mut one = [0; 1];
+        assert_eq!(schedule_notes(&[2, 6, 4], &mut one), 1);
+        assert_eq!(one, [4]);
+        assert_eq!(schedule_notes(&[2, 6, 4], &mut []), 0);
+    }
+}
@@ -0,0 +1,155 @@
+//! # Touch Logo Module
+//!
+//! This module reads the micro:bit v2's capacitive touch logo, the gold
+//! face above the LED matrix, as a third input beside buttons A and B.
+//! What a touch does is set by [`TOUCH_ACTION`](crate::TOUCH_ACTION).
+//!
+//! ## Pin
+//!
+//! The logo is a copper pad on P1.04, held up by an external pull-up
+//! resistor of several megohms. The BSP doesn't hand the pin out, and
+//! nothing else uses it, so `main` takes it directly. A finger adds
+//! capacitance to the pad, so it charges more slowly through the pull-up.
+//! There is no touch peripheral on the nRF52833; `TouchLogo::sample`
+//! drives the pin low to discharge the pad, releases it as an input, and
+//! times how long it takes to read high again.
+//!
+//! ## Detection
+//!
+//! [`TouchDetector`] turns those charge times into touches. The first
+//! [`TOUCH_CALIBRATION`] samples, taken at startup with nobody touching,
+//! set the untouched baseline, which then slowly follows drift while the
+//! logo is untouched. A charge time more than a quarter above the baseline
+//! (at least [`TOUCH_MIN_DELTA_US`] more) is a touch, and the state only
+//! changes after [`TOUCH_DEBOUNCE`] samples in a row agree.
+
+/// Samples averaged into the untouched baseline at startup.
+pub const TOUCH_CALIBRATION: u32 = 8;
+/// Smallest charge-time rise over the baseline that counts as a touch, in µs.
+pub const TOUCH_MIN_DELTA_US: u32 = 8;
+/// Samples in a row that must agree before the touch state changes.
+pub const TOUCH_DEBOUNCE: u32 = 2;
+/// Longest charge time measured, in µs; a pad still low by then reads as this.
+pub const TOUCH_CHARGE_MAX_US: u32 = 5000;
+
+/// What touching the logo does.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TouchAction {
+    /// Focus the next parameter, like [`ChordAction::Next`](crate::ChordAction::Next).
+    Next,
+    /// Lock the knob to the parameter it controls now, or unlock it.
+    Lock,
+    /// Blank or restore the LED output.
+    Output,
+    /// Restore the power-on levels and frame rate.
+    Reset,
+}
+
+/// Touch debouncing and baseline tracking for charge times.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TouchDetector {
+    /// Untouched charge time in µs, once calibrated.
+    baseline: Option<u32>,
+    /// Sum and number of calibration samples so far.
+    calibration: (u32, u32),
+    /// Debounced touch state.
+    touched: bool,
+    /// Samples in a row that disagree with `touched`.
+    run: u32,
+}
+
+impl TouchDetector {
+    /// Creates an uncalibrated detector.
+    pub const fn new() -> Self {
+        Self {
+            baseline: None,
+            calibration: (0, 0),
+            touched: false,
+            run: 0,
+        }
+    }
+
+    /// Untouched charge time in µs, once calibrated.
+    pub fn baseline(&self) -> Option<u32> {
+        self.baseline
+    }
+
+    /// Whether the logo is touched, debounced.
+    pub fn touched(&self) -> bool {
+        self.touched
+    }
+
+    /// Adds one charge time.
+    ///
+    /// # Arguments
+    /// * `charge_us` - Time the pad took to charge, from `TouchLogo::sample`
+    ///
+    /// # Returns
+    /// `Some(true)` when a touch starts, `Some(false)` when it ends, else
+    /// `None` (always while calibrating).
+    pub fn push(&mut self, charge_us: u32) -> Option<bool> {
+        let Some(baseline) = self.baseline else {
+            let (sum, n) = self.calibration;
+            self.calibration = (sum.saturating_add(charge_us), n + 1);
+            if n + 1 == TOUCH_CALIBRATION {
+                self.baseline = Some(self.calibration.0 / TOUCH_CALIBRATION);
+            }
+            return None;
+        };
+        let threshold = baseline + (baseline / 4).max(TOUCH_MIN_DELTA_US);
+        let high = charge_us > threshold;
+        if !self.touched && !high {
+            // Follow slow drift (temperature, humidity) while untouched.
+            self.baseline = Some(baseline - baseline / 16 + charge_us / 16);
+        }
+        if high == self.touched {
+            self.run = 0;
+            return None;
+        }
+        self.run += 1;
+        if self.run < TOUCH_DEBOUNCE {
+            return None;
+        }
+        self.run = 0;
+        self.touched = high;
+        Some(high)
+    }
+}
+
+impl Default for TouchDetector {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn touch_detector_calibrates_and_debounces() {
+        let mut touch = TouchDetector::new();
+        // Startup samples, untouched, set the baseline of 100.
+        for _ in 0..TOUCH_CALIBRATION {
+            assert_eq!(touch.push(100), None);
+        }
+        assert_eq!(touch.baseline(), Some(100));
+        // Noise below the threshold (125) is ignored.
+        assert_eq!(touch.push(110), None);
+        // A touch takes TOUCH_DEBOUNCE samples to register...
+        assert_eq!(touch.push(180), None);
+        assert_eq!(touch.push(180), Some(true));
+        // ...and is reported once.
+        assert_eq!(touch.push(180), None);
+        assert!(touch.touched());
+        // A single low sample doesn't end it; two do.
+        assert_eq!(touch.push(100), None);
+        assert_eq!(touch.push(180), None);
+        assert_eq!(touch.push(100), None);
+        assert_eq!(touch.push(100), Some(false));
+        // A single high sample is a glitch, not a touch.
+        assert_eq!(touch.push(180), None);
+        assert_eq!(touch.push(100), None);
+        assert!(!touch.touched());
+    }
+}
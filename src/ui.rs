@@ -9,11 +9,124 @@
 //! - **Button A**: Knob controls blue LED intensity (0-15)
 //! - **Button B**: Knob controls green LED intensity (0-15)  
 //! - **Both buttons**: Knob controls red LED intensity (0-15)
+//!
+//! A console `FOCUS` command pins the knob to one parameter, overriding the
+//! buttons until the focus is cleared.
+//!
+//! ## Saturation
+//!
+//! Focusing [`ControlParameter::Saturation`] (`FOCUS SAT`) gives a single
+//! control for a mood lamp: the knob sets the saturation of [`MOOD_HUE`]
+//! from white to the pure hue, and all three levels follow it (see
+//! [`saturation_levels`]).
+//!
+//! ## Chord Action
+//!
+//! The A+B chord's job is set by the [`ChordAction`] given to [`Ui::new`]
+//! (see [`CHORD_ACTION`]). By default it edits red as above. Otherwise the
+//! knob is ignored while the chord is held and releasing it runs the
+//! action instead: [`ChordAction::Next`] steps the focus through red,
+//! green, blue, and frame rate and back to button control, so all four
+//! stay reachable, and [`ChordAction::Reset`] restores the power-on levels
+//! and frame rate. A release that ends a long hold runs no action.
+//!
+//! The button, chord, long-hold, and focus logic lives in the
+//! hardware-free [`UiCore`], which acts on the [`gesture`] module's events
+//! and whose tests sweep every short button sequence against the
+//! selection invariants.
+//!
+//! The knob starts out editing the frame rate, or the parameter given to
+//! [`Ui::new`] (see [`START_PARAMETER`]), which then stands in for the
+//! frame rate with no button held until the first button press; from then
+//! on the buttons work as above.
+//!
+//! The knob's dead zone and response curve follow the controlled parameter
+//! (see [`KNOB_CONFIG`]): frame rate uses a log taper, colors are linear.
+//!
+//! ## Long Holds
+//!
+//! Holding a button combination for [`LONG_HOLD`] triggers an extra action
+//! once per hold:
+//!
+//! - **Both buttons** (`mic` feature): Toggle sound-reactive mode, in which
+//!   the knob sets microphone sensitivity. The idle animation can also run
+//!   this mode ([`Effect::SoundReactive`]); then the sensitivity is fixed
+//!   and turning the knob ends it like any other input
+//! - **Button B** (`ambient` feature): Toggle ambient-light adaptive brightness
+//!
+//! With the `tone` feature, every color level change also plays a speaker
+//! tone whose pitch rises with the level.
+//!
+//! ## Stuck Buttons
+//!
+//! A button that is stuck down (or wired active-high) would otherwise hold
+//! the UI on one parameter for good. At startup `Ui::run` watches any
+//! button that is already pressed, and one still held after
+//! [`STUCK_BUTTON`]'s threshold, without ever being released, is reported
+//! and (by default) ignored until reset, so the other button and the
+//! frame rate stay reachable. A button released in time is used normally;
+//! the check only delays startup while a button is held.
+//!
+//! ## Knob Changes
+//!
+//! The knob only writes when its own reading changes (or the controlled
+//! parameter changes), so values set another way, such as from the IR
+//! remote (`ir` feature), stay put until the knob is turned.
+//!
+//! ## Frame-Rate Demo
+//!
+//! The frame rate is hard to see on a steady color. With the console's
+//! `DEMO ON`, adjusting the frame rate temporarily shows a demo color
+//! (half-brightness white by default, set with `DEMO COLOR`) where flicker
+//! is easy to spot, and restores the previous color [`FPS_DEMO_HOLD`]
+//! after the last adjustment, or as soon as the buttons select a color.
+//!
+//! ## Idle Animation
+//!
+//! With the `idle` feature, every button press, knob change, forwarded
+//! command, and remote input is reported to [`idle_input`] before it is
+//! applied, which stops a running idle animation and restores the user's
+//! color first.
+//!
+//! With the `sleep` feature, the same input is also reported to
+//! [`sleep_input`], which cancels a running sleep timer.
+//!
+//! ## Wireless Knob
+//!
+//! With the `remote` feature, input changes from a paired remote board are
+//! applied like local ones, arbitrated by an [`InputArbiter`]: the remote
+//! wins, and the local knob is ignored until it has been idle for
+//! [`REMOTE_HOLDOFF`]. Pressing A+B starts pairing, and every board
+//! records its own inputs for sending in case it is the remote.
 use crate::*;
 
+/// How long a button combination must be held to trigger its long-hold action.
+pub const LONG_HOLD: Duration = Duration::from_secs(2);
+/// How long the frame-rate demo color stays after the last adjustment.
+pub const FPS_DEMO_HOLD: Duration = Duration::from_secs(2);
+/// UI loop delay between input polls.
+pub const UI_POLL: Duration = Duration::from_millis(50);
+
+/// Boot-time stuck-button check settings.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct StuckButtonCheck {
+    /// How long a button must be held from startup, without a release,
+    /// to count as stuck.
+    pub threshold: Duration,
+    /// Whether to ignore a stuck button until reset, rather than only
+    /// warning.
+    pub ignore: bool,
+}
+
+/// Stuck-button check used by `Ui::run` at startup.
+pub const STUCK_BUTTON: StuckButtonCheck = StuckButtonCheck {
+    threshold: Duration::from_secs(3),
+    ignore: true,
+};
+
 /// Represents which parameter the knob is currently controlling.
 #[derive(Debug, Clone, Copy, PartialEq)]
-enum ControlParameter {
+pub enum ControlParameter {
     /// Frame rate control (no buttons pressed)
     FrameRate,
     /// Blue LED intensity (button A pressed)
@@ -22,6 +135,324 @@ enum ControlParameter {
     Green,
     /// Red LED intensity (both buttons pressed)
     Red,
+    /// Saturation of [`MOOD_HUE`], setting all three levels (focus only)
+    Saturation,
+}
+
+impl ControlParameter {
+    /// Every parameter, frame rate first.
+    pub const ALL: [ControlParameter; 5] = [
+        ControlParameter::FrameRate,
+        ControlParameter::Blue,
+        ControlParameter::Green,
+        ControlParameter::Red,
+        ControlParameter::Saturation,
+    ];
+    /// Index of the parameter's channel in the RGB levels array.
+    ///
+    /// # Returns
+    /// `Some(0..=2)` for the color parameters, `None` for frame rate and
+    /// saturation.
+    pub fn channel(self) -> Option<usize> {
+        match self {
+            ControlParameter::Red => Some(0),
+            ControlParameter::Green => Some(1),
+            ControlParameter::Blue => Some(2),
+            ControlParameter::FrameRate | ControlParameter::Saturation => None,
+        }
+    }
+}
+
+/// What the A+B button chord does.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ChordAction {
+    /// Holding the chord lets the knob edit red (the original scheme).
+    EditRed,
+    /// Releasing the chord focuses the next parameter (see [`next_focus`]).
+    Next,
+    /// Releasing the chord restores the power-on levels and frame rate.
+    Reset,
+}
+
+impl ChordAction {
+    /// Whether holding the chord selects red for the knob, rather than
+    /// running an action on release.
+    pub fn edits(self) -> bool {
+        self == ChordAction::EditRed
+    }
+}
+
+/// Which settings a reset restores to their power-on defaults.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ResetTarget {
+    /// Levels and frame rate.
+    All,
+    /// Frame rate only.
+    FrameRate,
+    /// RGB levels only.
+    Levels,
+}
+
+/// Focus that [`ChordAction::Next`] moves to from `focus`.
+///
+/// Steps red, green, blue, frame rate, then back to button control. The
+/// saturation focus, only set from the console, also goes back to button
+/// control.
+pub fn next_focus(focus: Option<ControlParameter>) -> Option<ControlParameter> {
+    match focus {
+        None => Some(ControlParameter::Red),
+        Some(ControlParameter::Red) => Some(ControlParameter::Green),
+        Some(ControlParameter::Green) => Some(ControlParameter::Blue),
+        Some(ControlParameter::Blue) => Some(ControlParameter::FrameRate),
+        Some(ControlParameter::FrameRate) => None,
+        Some(ControlParameter::Saturation) => None,
+    }
+}
+
+/// Parameter selected by a button combination.
+///
+/// # Arguments
+/// * `a_pressed` - Whether button A is down
+/// * `b_pressed` - Whether button B is down
+///
+/// # Returns
+/// - No buttons: Frame rate
+/// - A only: Blue LED
+/// - B only: Green LED
+/// - A + B: Red LED
+pub fn button_parameter(a_pressed: bool, b_pressed: bool) -> ControlParameter {
+    match (a_pressed, b_pressed) {
+        (false, false) => ControlParameter::FrameRate, // No buttons
+        (true, false) => ControlParameter::Blue,       // A button
+        (false, true) => ControlParameter::Green,      // B button
+        (true, true) => ControlParameter::Red,         // Both A+B buttons
+    }
+}
+
+/// What one pass of [`UiCore::step`] decided.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct UiStep {
+    /// Parameter selected by the buttons alone.
+    pub buttons: ControlParameter,
+    /// Non-editing chord action to run, on the pass the chord is released.
+    /// [`ChordAction::Next`] has already moved the focus.
+    pub chord: Option<ChordAction>,
+    /// Whether the held combination reached [`LONG_HOLD`] this pass.
+    pub long_hold: bool,
+    /// Parameter the knob controls this pass, or `None` while knob input
+    /// is ignored (the chord held for a non-editing action).
+    pub parameter: Option<ControlParameter>,
+}
+
+/// Hardware-free parameter selection: buttons, chord, long holds, and
+/// focus.
+///
+/// `Ui::run` feeds it the buttons once per pass and acts on the
+/// resulting [`UiStep`], so the whole selection state machine can be
+/// checked without a board.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct UiCore {
+    /// What the A+B chord does.
+    chord: ChordAction,
+    /// Parameter pinned by the console or chord, overriding the buttons
+    /// while `Some`.
+    focus: Option<ControlParameter>,
+    /// Parameter edited with no button held until the first press, in
+    /// place of the frame rate.
+    start: Option<ControlParameter>,
+    /// Chord releases and long holds from the button samples.
+    gestures: GestureDetector,
+    /// Parameter the knob was last announced as controlling.
+    current: ControlParameter,
+}
+
+impl UiCore {
+    /// Creates the selection state with no buttons held and no focus.
+    ///
+    /// # Arguments
+    /// * `chord` - What the A+B chord does
+    pub const fn new(chord: ChordAction) -> Self {
+        Self {
+            chord,
+            focus: None,
+            start: None,
+            gestures: GestureDetector::new(),
+            current: ControlParameter::FrameRate,
+        }
+    }
+
+    /// Starts with the knob editing a parameter instead of the frame rate.
+    ///
+    /// Anything but [`ControlParameter::FrameRate`] (the default) is what
+    /// the knob edits with no button held, until the first button press
+    /// hands it back to the buttons for good. Unlike a focus, it never
+    /// overrides a held button.
+    pub const fn starting_in(mut self, parameter: ControlParameter) -> Self {
+        self.current = parameter;
+        self.start = match parameter {
+            ControlParameter::FrameRate => None,
+            parameter => Some(parameter),
+        };
+        self
+    }
+
+    /// Parameter pinned by the console or chord, if any.
+    pub fn focus(&self) -> Option<ControlParameter> {
+        self.focus
+    }
+
+    /// Pins the knob to a parameter, or returns it to the buttons.
+    pub fn set_focus(&mut self, focus: Option<ControlParameter>) {
+        self.focus = focus;
+    }
+
+    /// Parameter the knob was last announced as controlling.
+    pub fn current(&self) -> ControlParameter {
+        self.current
+    }
+
+    /// Runs one pass of parameter selection.
+    ///
+    /// # Arguments
+    /// * `now_ms` - Current time in milliseconds since boot
+    /// * `a_pressed` - Whether button A is down
+    /// * `b_pressed` - Whether button B is down
+    pub fn step(&mut self, now_ms: u64, a_pressed: bool, b_pressed: bool) -> UiStep {
+        let buttons = button_parameter(a_pressed, b_pressed);
+        if a_pressed || b_pressed {
+            self.start = None;
+        }
+        let chord_held = buttons == ControlParameter::Red;
+        let mut chord = None;
+        let mut long_hold = false;
+        for gesture in self.gestures.push(now_ms, a_pressed, b_pressed) {
+            match gesture {
+                Gesture::ChordExit { aborted: false } if !self.chord.edits() => {
+                    if self.chord == ChordAction::Next {
+                        self.focus = next_focus(self.focus);
+                    }
+                    chord = Some(self.chord);
+                }
+                Gesture::LongPress(_) => long_hold = true,
+                _ => {}
+            }
+        }
+        let ignored = chord_held && !self.chord.edits() && self.focus.is_none();
+        UiStep {
+            buttons,
+            chord,
+            long_hold,
+            parameter: (!ignored).then(|| self.focus.or(self.start).unwrap_or(buttons)),
+        }
+    }
+
+    /// Records the parameter the knob controls this pass.
+    ///
+    /// # Returns
+    /// `true` if it differs from the last one, and should be announced.
+    pub fn select(&mut self, parameter: ControlParameter) -> bool {
+        let changed = parameter != self.current;
+        self.current = parameter;
+        changed
+    }
+}
+
+/// Per-parameter knob response table.
+///
+/// Each controlled parameter gets its own dead zone and curve, selected by
+/// whichever parameter the knob is currently editing.
+#[derive(Debug, Clone, Copy)]
+pub struct KnobConfig {
+    /// Response while editing the frame rate.
+    pub frame_rate: KnobResponse,
+    /// Response while editing red.
+    pub red: KnobResponse,
+    /// Response while editing green.
+    pub green: KnobResponse,
+    /// Response while editing blue.
+    pub blue: KnobResponse,
+    /// Response while editing saturation.
+    pub saturation: KnobResponse,
+}
+
+impl KnobConfig {
+    /// Returns the knob response for a parameter.
+    pub fn response(&self, parameter: ControlParameter) -> KnobResponse {
+        match parameter {
+            ControlParameter::FrameRate => self.frame_rate,
+            ControlParameter::Red => self.red,
+            ControlParameter::Green => self.green,
+            ControlParameter::Blue => self.blue,
+            ControlParameter::Saturation => self.saturation,
+        }
+    }
+}
+
+/// Knob responses used by the UI.
+///
+/// Frame rate uses a log taper for finer steps at the low rates where
+/// flicker appears; the color channels stay linear.
+pub const KNOB_CONFIG: KnobConfig = KnobConfig {
+    frame_rate: KnobResponse {
+        curve: ResponseCurve::Log,
+        ..KnobResponse::DEFAULT
+    },
+    red: KnobResponse::DEFAULT,
+    green: KnobResponse::DEFAULT,
+    blue: KnobResponse::DEFAULT,
+    saturation: KnobResponse::DEFAULT,
+};
+
+/// Save/restore of the color around frame-rate demo mode.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FrameRateDemo {
+    /// Whether adjusting the frame rate shows the demo color.
+    pub enabled: bool,
+    /// Color shown while adjusting.
+    pub color: [u32; 3],
+    /// The user's color, saved while the demo color is shown.
+    overlay: LevelsOverlay,
+}
+
+impl FrameRateDemo {
+    /// Disabled, with half-brightness white as the demo color.
+    pub const DEFAULT: Self = Self {
+        enabled: false,
+        color: [LEVELS / 2; 3],
+        overlay: LevelsOverlay::OFF,
+    };
+
+    /// Whether the demo color is currently shown.
+    pub fn active(&self) -> bool {
+        self.overlay.active()
+    }
+
+    /// The user's color, while the demo color is shown over it.
+    pub fn saved(&self) -> Option<[u32; 3]> {
+        self.overlay.saved()
+    }
+
+    /// Enters the demo on a frame-rate adjustment.
+    ///
+    /// # Arguments
+    /// * `levels` - Levels currently shown, saved on entry
+    ///
+    /// # Returns
+    /// The demo color to show if the demo just started.
+    pub fn enter(&mut self, levels: [u32; 3]) -> Option<[u32; 3]> {
+        if !self.enabled || !self.overlay.enter(levels) {
+            return None;
+        }
+        Some(self.color)
+    }
+
+    /// Leaves the demo.
+    ///
+    /// # Returns
+    /// The saved levels to restore, if the demo was active.
+    pub fn exit(&mut self) -> Option<[u32; 3]> {
+        self.overlay.exit()
+    }
 }
 
 /// Internal state for th e UI control system.
@@ -38,218 +469,432 @@ enum ControlParameter {
 /// # Examples
 ///
 /// ```rust,no_run
+/// # use mb2_embassy_rgb::*;
 /// let state = UiState {
-///     levels: [10, 8, 12],    // Red=10, Green=8, Blue=12
-///     frame_rate: 60,         // 60 FPS
+///     levels: [10, 8, 12], // Red=10, Green=8, Blue=12
+///     frame_rate: 60,      // 60 FPS
+///     ..UiState::default()
 /// };
 /// ```
-struct UiState {
+#[derive(Clone, Copy)]
+pub struct UiState {
     /// RGB intensity levels [red, green, blue] with values from 0-15.
     ///
     /// Each element corresponds to the intensity of the repsective color channel:
     /// - Index 0: Red intensity (0 = off, 15 = maximum)
     /// - Index 1: Green intensity (0 = off, 15 = maximum)
     /// - Index 2: Blue intensity (0 = off, 15 = maximum)
-    levels: [u32; 3],
+    pub levels: [u32; 3],
     /// Display refresh rate in frames per second (10-160 FPS).
     ///
     /// Controls how frequently the RGB LEDs are update. Higher values
     /// provide smoother visual transitions but increase power consumption.
-    frame_rate: u64,
+    pub frame_rate: u64,
+    /// Saturation level of [`MOOD_HUE`] (0-15), applied to the levels
+    /// while the knob controls [`ControlParameter::Saturation`].
+    pub saturation: u32,
+    /// Last seen [`OUTPUT_ENABLED`] flag, shown in the status.
+    pub output_enabled: bool,
+    /// Whether [`UiState::show`] explains the frame rate's PWM timing.
+    pub explain_timing: bool,
 }
 
 impl UiState {
+    /// Starts from a set of power-on values, such as settings loaded from
+    /// flash.
+    pub fn from_settings(settings: &Defaults) -> Self {
+        Self {
+            levels: settings.levels,
+            frame_rate: settings.frame_rate,
+            saturation: settings.saturation,
+            output_enabled: settings.output_enabled,
+            explain_timing: false,
+        }
+    }
     /// Displays the current UI state to the debug console.
     ///
     /// Outputs a formatted display of all current parameter values including
-    /// RGB levels and frame rate. This provides real-time feedback about
+    /// RGB levels, frame rate, and the estimated LED current. This provides real-time feedback about
     /// the system state for debugging and user confirmation.
     ///
     /// # Output Format
     ///
     /// ```text
-    /// === RGB Calibration ===
+    ///
     /// red: 10
-    /// green: 8  
+    /// green: 8
     /// blue: 12
     /// frame rate: 60
+    /// est. current: 2.7 mA
+    /// output: on
     /// ```
     ///
+    /// The lines are a [`StatusBlock`]'s, whose golden test pins them
+    /// exactly. The current estimate also takes the shared master brightness
+    /// and gamma curve.
+    ///
     /// # Examples
     ///
     /// ```rust,no_run
-    /// let state = UiState { levels: [10, 8, 12], frame_rate: 60 };
-    /// state.show(); // Prints current values to console
+    /// # use mb2_embassy_rgb::*;
+    /// # async fn example() {
+    /// let state = UiState {
+    ///     levels: [10, 8, 12],
+    ///     frame_rate: 60,
+    ///     ..UiState::default()
+    /// };
+    /// state.show().await; // Prints current values to console
+    /// # }
     /// ```
-    fn show(&self) {
-        let names = ["red", "green", "blue"];
-        rprintln!();
-        for (name, level) in names.iter().zip(self.levels.iter()) {
-            rprintln!("{}: {}", name, level);
+    pub async fn show(&self) {
+        let status = StatusBlock {
+            levels: self.levels,
+            frame_rate: self.frame_rate,
+            explain_timing: self.explain_timing,
+            output_enabled: self.output_enabled,
+            brightness: get_master_brightness().await,
+            gamma: *GAMMA_CURVE.lock().await,
+        };
+        for line in status.lines() {
+            rprintln!("{}", line);
+        }
+    }
+    /// Restores the targeted settings to their defaults, leaving the rest.
+    pub fn reset(&mut self, target: ResetTarget) {
+        let defaults = UiState::default();
+        if target != ResetTarget::FrameRate {
+            self.levels = defaults.levels;
+            self.saturation = defaults.saturation;
+        }
+        if target != ResetTarget::Levels {
+            self.frame_rate = defaults.frame_rate;
         }
-        rprintln!("frame rate: {}", self.frame_rate);
     }
 }
 
 impl Default for UiState {
     fn default() -> Self {
-        Self {
-            levels: [LEVELS - 1, LEVELS - 1, LEVELS - 1],
-            frame_rate: 100,
-        }
+        Self::from_settings(&DEFAULTS)
     }
 }
-/// User interface controller that processes knob and button inputs.
-///
-/// Manages the mapping between button states and controllable parameters,
-/// reads knob values, and updates shared state for the RGB controller.
-pub struct Ui {
-    knob: Knob,
-    button_a: Button,
-    button_b: Button,
-    state: UiState,
-    current_parameter: ControlParameter,
-}
-
-impl Ui {
-    /// User interface controller that processes knob and button inputs.
-    ///
-    /// Manages the mapping between button states and controllable parameters,
-    /// reads knob values, and updates shared state for the RGB controller.
-    pub fn new(knob: Knob, button_a: Button, button_b: Button) -> Self {
+/// Outcome of the boot-time check of one button.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BootButton {
+    /// Held since startup, but not yet for the threshold.
+    Checking,
+    /// Not pressed at startup, or released in time.
+    Free,
+    /// Held from startup for the whole threshold.
+    Stuck,
+}
+
+/// Detects a button held down from startup (see [`STUCK_BUTTON`]).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct StuckButtonDetector {
+    /// Hold time that makes a button stuck, in milliseconds.
+    threshold_ms: u64,
+    /// When the button was first seen pressed, in milliseconds.
+    pressed_since: Option<u64>,
+    /// Outcome so far.
+    verdict: BootButton,
+}
+
+impl StuckButtonDetector {
+    /// Starts checking a button.
+    ///
+    /// # Arguments
+    /// * `threshold` - How long a hold from startup makes it stuck
+    pub const fn new(threshold: Duration) -> Self {
         Self {
-            knob,
-            button_a,
-            button_b,
-            state: UiState::default(),
-            current_parameter: ControlParameter::FrameRate,
+            threshold_ms: threshold.as_millis(),
+            pressed_since: None,
+            verdict: BootButton::Checking,
         }
     }
-    /// Reads button state and determines which parameter to control.
+    /// Takes one reading of the button.
+    ///
+    /// # Arguments
+    /// * `now_ms` - Time of the reading, in milliseconds
+    /// * `pressed` - Whether the button is down
     ///
     /// # Returns
-    /// The active control parameter based on button combination:
-    /// - No buttons: Frame rate
-    /// - A only: Blue LED
-    /// - B only: Green LED  
-    /// - A + B: Red LED
-    fn read_button_state(&mut self) -> ControlParameter {
-        let a_pressed = self.button_a.is_low();
-        let b_pressed = self.button_b.is_low();
+    /// The outcome so far; once [`BootButton::Free`] or
+    /// [`BootButton::Stuck`], it no longer changes.
+    pub fn push(&mut self, now_ms: u64, pressed: bool) -> BootButton {
+        if self.verdict != BootButton::Checking {
+            return self.verdict;
+        }
+        if !pressed {
+            self.verdict = BootButton::Free;
+        } else {
+            let since = *self.pressed_since.get_or_insert(now_ms);
+            if now_ms.saturating_sub(since) >= self.threshold_ms {
+                self.verdict = BootButton::Stuck;
+            }
+        }
+        self.verdict
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-        match (a_pressed, b_pressed) {
-            (false, false) => ControlParameter::FrameRate, // No buttons
-            (true, false) => ControlParameter::Blue,       // A button
-            (false, true) => ControlParameter::Green,      // B button
-            (true, true) => ControlParameter::Red,         // Both A+B buttons
+    #[test]
+    fn only_edit_chords_edit() {
+        assert!(ChordAction::EditRed.edits());
+        assert!(!ChordAction::Next.edits());
+        assert!(!ChordAction::Reset.edits());
+    }
+
+    #[test]
+    fn focus_cycles_through_the_colors_then_frees_the_knob() {
+        let mut focus = None;
+        let mut seen = [None; 5];
+        for step in seen.iter_mut() {
+            focus = next_focus(focus);
+            *step = focus;
         }
+        assert_eq!(
+            seen,
+            [
+                Some(ControlParameter::Red),
+                Some(ControlParameter::Green),
+                Some(ControlParameter::Blue),
+                Some(ControlParameter::FrameRate),
+                None,
+            ],
+        );
     }
-    /// Maps knob value (0-15) to appropriate parameter range.
-    ///
-    /// # Arguments
-    /// * `knob_value` - Raw knob reading (0-15)
-    /// * `parameter` - Target parameter to map to
-    ///
-    /// # Returns
-    /// Mapped value in the appropriate range:
-    /// - Frame rate: 10-160 FPS
-    /// - RGB: 0-15 (unchanged)
-    fn map_knob_value(&self, knob_value: u32, parameter: ControlParameter) -> u32 {
-        match parameter {
-            ControlParameter::FrameRate => 10 + (knob_value * 10),
-            ControlParameter::Blue | ControlParameter::Green | ControlParameter::Red => knob_value,
+
+    #[test]
+    fn starting_in_lasts_until_the_first_press() {
+        let mut core = UiCore::new(ChordAction::EditRed).starting_in(ControlParameter::Blue);
+        assert_eq!(core.current(), ControlParameter::Blue);
+        assert_eq!(core.focus(), None);
+        // The first passes edit blue with no button held, with no change to
+        // announce.
+        for now_ms in [0, 50, 100] {
+            let step = core.step(now_ms, false, false);
+            assert_eq!(step.parameter, Some(ControlParameter::Blue));
         }
+        assert!(!core.select(ControlParameter::Blue));
+        // A held button wins at once.
+        let step = core.step(150, false, true);
+        assert_eq!(step.parameter, Some(ControlParameter::Green));
+        // After the first press the buttons are in charge for good.
+        let step = core.step(200, false, false);
+        assert_eq!(step.parameter, Some(ControlParameter::FrameRate));
+        // A focus still overrides the start parameter.
+        let mut core = UiCore::new(ChordAction::EditRed).starting_in(ControlParameter::Blue);
+        core.set_focus(Some(ControlParameter::Red));
+        let step = core.step(0, false, false);
+        assert_eq!(step.parameter, Some(ControlParameter::Red));
+        // Starting in frame rate is the default.
+        let default = UiCore::new(ChordAction::EditRed);
+        assert_eq!(default.starting_in(ControlParameter::FrameRate), default);
     }
-    /// Main UI control loop that handles input processing and state management.
-    ///
-    /// This is the primary entry point for the UI system. It runs continuously,
-    /// processing button and knob inputs, managing parameter selection, and
-    /// synchronizing state with the RGB display system.
 
-    /// # Value Ranges
-    ///
-    /// - **RGB Parameters**: 0-15 (mapped from knob input)
-    /// - **Frame Rate**: 10-160 FPS (mapped from knob input)
-    ///
-    /// # Performance Considerations
-    ///
-    /// - Uses change detection to minimize shared state updates
-    /// - Local state caching reduces lock contention
-    /// - 50ms loop delay balances responsiveness with CPU usage
-    ///
-    /// # Examples
-    ///
-    /// ```rust,no_run
-    /// let mut ui = Ui::new(knob, btn_a, btn_b);
-    /// ui.run().await; // Starts the UI control loop (never returns)
-    /// ```
-    ///
-    /// # Panics
-    ///
-    /// This function never returns under normal operation. It will only
-    /// exit if the hardware fails or the system panics.
-    pub async fn run(&mut self) -> ! {
-        self.state.levels[2] = self.knob.measure().await;
-        set_rgb_levels(|rgb| {
-            *rgb = self.state.levels;
-        })
-        .await;
-        self.state.show();
-        loop {
-            let parameter = self.read_button_state();
-
-            if parameter != self.current_parameter {
-                self.current_parameter = parameter;
-                rprintln!("Now controlling: {:?}", parameter);
-                self.state.show();
-            }
+    #[test]
+    fn frame_rate_demo_saves_and_restores_the_color() {
+        let mut demo = FrameRateDemo {
+            enabled: true,
+            ..FrameRateDemo::DEFAULT
+        };
+        // The first adjustment saves the user's color and shows the demo color.
+        assert_eq!(demo.enter([15, 3, 0]), Some([8, 8, 8]));
+        // Further adjustments keep it.
+        assert_eq!(demo.enter([8, 8, 8]), None);
+        // Leaving restores the user's color exactly once.
+        assert_eq!(demo.exit(), Some([15, 3, 0]));
+        assert_eq!(demo.exit(), None);
+    }
 
-            let raw_knob_value = self.knob.measure().await;
-            let mapped_value = self.map_knob_value(raw_knob_value, parameter);
-            let mut changed = false;
+    #[test]
+    fn ui_state_starts_at_the_defaults() {
+        let state = UiState::default();
+        assert_eq!(state.levels, DEFAULTS.levels);
+        assert_eq!(state.frame_rate, DEFAULTS.frame_rate);
+        assert_eq!(state.saturation, DEFAULTS.saturation);
+        assert_eq!(state.output_enabled, DEFAULTS.output_enabled);
 
-            match parameter {
-                ControlParameter::FrameRate => {
-                    let new_frame_rate: u64 = mapped_value.into();
-                    if new_frame_rate != self.state.frame_rate {
-                        self.state.frame_rate = new_frame_rate;
-                        changed = true;
-                    }
+        // Settings loaded from flash replace them, but RESET still goes
+        // back to the build-time values.
+        let stored = Defaults {
+            levels: [3, 9, 0],
+            frame_rate: 40,
+            saturation: 7,
+            ..DEFAULTS
+        };
+        let mut state = UiState::from_settings(&stored);
+        assert_eq!(
+            (state.levels, state.frame_rate, state.saturation),
+            ([3, 9, 0], 40, 7)
+        );
+        state.reset(ResetTarget::All);
+        assert_eq!(state.levels, DEFAULTS.levels);
+        assert_eq!(state.frame_rate, DEFAULTS.frame_rate);
+    }
+
+    #[test]
+    fn reset_restores_only_the_target() {
+        let defaults = UiState::default();
+        let tuned = UiState {
+            levels: [3, 9, 0],
+            frame_rate: 40,
+            ..defaults
+        };
+
+        let mut state = tuned;
+        state.reset(ResetTarget::FrameRate);
+        assert_eq!(
+            (state.levels, state.frame_rate),
+            ([3, 9, 0], defaults.frame_rate)
+        );
+
+        let mut state = tuned;
+        state.reset(ResetTarget::Levels);
+        assert_eq!((state.levels, state.frame_rate), (defaults.levels, 40));
+
+        let mut state = tuned;
+        state.reset(ResetTarget::All);
+        assert_eq!(
+            (state.levels, state.frame_rate),
+            (defaults.levels, defaults.frame_rate)
+        );
+    }
+
+    #[test]
+    fn stuck_button_verdicts() {
+        let threshold = Duration::from_millis(3_000);
+        // A simulated always-pressed button, polled every 50 ms.
+        let mut detector = StuckButtonDetector::new(threshold);
+        for now_ms in (0..3_000).step_by(50) {
+            assert_eq!(detector.push(1_000 + now_ms, true), BootButton::Checking);
+        }
+        assert_eq!(detector.push(4_000, true), BootButton::Stuck);
+        // The verdict is final, even if the button comes free later.
+        assert_eq!(detector.push(4_050, false), BootButton::Stuck);
+
+        // Released just before the threshold: not stuck, and stays that way.
+        let mut detector = StuckButtonDetector::new(threshold);
+        assert_eq!(detector.push(0, true), BootButton::Checking);
+        assert_eq!(detector.push(2_950, true), BootButton::Checking);
+        assert_eq!(detector.push(3_000, false), BootButton::Free);
+        assert_eq!(detector.push(9_000, true), BootButton::Free);
+
+        // Not pressed at startup at all.
+        let mut detector = StuckButtonDetector::new(threshold);
+        assert_eq!(detector.push(0, false), BootButton::Free);
+    }
+
+    #[test]
+    fn every_button_sequence_keeps_the_selection_invariants() {
+        // Every button sequence up to SEQUENCE_LEN passes, each pass one UI
+        // loop delay or one whole long hold after the last, keeps the
+        // invariants. A failure names the chord action and the sequence so far
+        // as (A, B, time in ms) passes.
+        const SEQUENCE_LEN: u32 = 6;
+        const SYMBOLS: u32 = 8;
+        for chord in [ChordAction::EditRed, ChordAction::Next, ChordAction::Reset] {
+            for code in 0..SYMBOLS.pow(SEQUENCE_LEN) {
+                let mut sequence = [(false, false, 0u64); SEQUENCE_LEN as usize];
+                let mut rest = code;
+                let mut now_ms = 0;
+                for pass in sequence.iter_mut() {
+                    let symbol = rest % SYMBOLS;
+                    rest /= SYMBOLS;
+                    now_ms += if symbol >= 4 {
+                        LONG_HOLD.as_millis()
+                    } else {
+                        50
+                    };
+                    *pass = (symbol & 1 != 0, symbol & 2 != 0, now_ms);
                 }
-                ControlParameter::Red => {
-                    if mapped_value != self.state.levels[0] {
-                        self.state.levels[0] = mapped_value;
-                        changed = true;
+
+                let mut core = UiCore::new(chord);
+                let mut held = ControlParameter::FrameRate;
+                let mut hold_fires = 0;
+                for (index, &(a, b, now_ms)) in sequence.iter().enumerate() {
+                    let passes = &sequence[..=index];
+                    let focus = core.focus();
+                    let step = core.step(now_ms, a, b);
+                    // The buttons select one of the defined parameters.
+                    assert_eq!(
+                        step.buttons,
+                        button_parameter(a, b),
+                        "{:?} {:?}",
+                        chord,
+                        passes
+                    );
+                    // Only a non-editing chord fires, and only as it is released.
+                    if let Some(action) = step.chord {
+                        assert!(
+                            action == chord && !chord.edits(),
+                            "{:?} {:?}",
+                            chord,
+                            passes
+                        );
+                        assert!(
+                            held == ControlParameter::Red && !(a && b),
+                            "{:?} {:?}",
+                            chord,
+                            passes
+                        );
                     }
-                }
-                ControlParameter::Green => {
-                    if mapped_value != self.state.levels[1] {
-                        self.state.levels[1] = mapped_value;
-                        changed = true;
+                    // Only the Next chord moves the focus.
+                    if step.chord != Some(ChordAction::Next) {
+                        assert_eq!(core.focus(), focus, "{:?} {:?}", chord, passes);
                     }
-                }
-                ControlParameter::Blue => {
-                    if mapped_value != self.state.levels[2] {
-                        self.state.levels[2] = mapped_value;
-                        changed = true;
+                    // A long hold fires at most once per held combination, and
+                    // never for no buttons.
+                    if step.buttons != held {
+                        held = step.buttons;
+                        hold_fires = 0;
+                    }
+                    if step.long_hold {
+                        hold_fires += 1;
+                        assert!(
+                            held != ControlParameter::FrameRate,
+                            "{:?} {:?}",
+                            chord,
+                            passes
+                        );
+                        assert_eq!(hold_fires, 1, "{:?} {:?}", chord, passes);
+                    }
+                    // A full release always gives the knob back, and without a
+                    // focus it controls the frame rate.
+                    if !a && !b {
+                        let expected = core.focus().unwrap_or(ControlParameter::FrameRate);
+                        assert_eq!(step.parameter, Some(expected), "{:?} {:?}", chord, passes);
+                    }
+                    // Knob input is only ignored under a non-editing chord.
+                    if step.parameter.is_none() {
+                        assert!(a && b && !chord.edits(), "{:?} {:?}", chord, passes);
+                    }
+                    // Announcements match real changes of the controlled parameter.
+                    if let Some(parameter) = step.parameter {
+                        assert!(
+                            ControlParameter::ALL.contains(&parameter),
+                            "{:?} {:?}",
+                            chord,
+                            passes
+                        );
+                        let before = core.current();
+                        assert_eq!(
+                            core.select(parameter),
+                            parameter != before,
+                            "{:?} {:?}",
+                            chord,
+                            passes
+                        );
+                        assert_eq!(core.current(), parameter, "{:?} {:?}", chord, passes);
                     }
                 }
+                // Whatever came before, releasing everything frees the knob.
+                let step = core.step(now_ms + 50, false, false);
+                assert!(step.parameter.is_some(), "{:?} {:?}", chord, sequence);
             }
-
-            if changed {
-                self.state.show();
-
-                set_rgb_levels(|rgb| {
-                    *rgb = self.state.levels;
-                })
-                .await;
-
-                if matches!(parameter, ControlParameter::FrameRate) {
-                    set_frame_rate(|rate| *rate = self.state.frame_rate).await;
-                    rprintln!("Frame rate changed to : {} fps", self.state.frame_rate);
-                }
-            }
-            Timer::after_millis(50).await;
         }
     }
 }